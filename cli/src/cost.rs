@@ -19,23 +19,18 @@ fn handle_t(
     params: &Parameters,
     options: DisplayOptions,
 ) -> CliResult<()> {
-    let mut total: HashMap<Cost, TDim> = HashMap::default();
+    let cost = model.cost()?;
     let mut display_graph =
         DisplayGraph::from_model_and_options(model, options)?.with_graph_def(&params.graph)?;
-    for i in ::tract_core::model::eval_order(&model)? {
-        let inputs = model.node_input_facts(i)?;
-        let cost = model.nodes()[i].op().cost(&*inputs)?;
-        if !cost.is_empty() {
-            let rows = cost
-                .iter()
-                .inspect(|(c, i)| *total.entry(*c).or_insert(0.to_dim()) += *i)
-                .map(|(c, i)| Row::Double(format!("{:?}", c), format!("{:?}", i)))
-                .collect();
-            display_graph.add_node_section(i, rows)?;
-        }
+    for (i, node_cost) in cost.nodes {
+        let rows = node_cost
+            .iter()
+            .map(|(c, i)| Row::Double(format!("{:?}", c), format!("{:?}", i)))
+            .collect();
+        display_graph.add_node_section(i, rows)?;
     }
     display_graph.render()?;
-    for (c, i) in total {
+    for (c, i) in cost.total {
         println!("{:?}: {:?}", c, i);
     }
     Ok(())
@@ -0,0 +1,55 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::{Criterion, ParameterizedBenchmark};
+
+use tract_core::internal::*;
+use tract_core::ops::cnn::{Conv, ConvUnary, KernelFormat, PaddingSpec};
+use tract_core::ops::nn::DataFormat;
+
+/// A ShuffleNet pointwise convolution: 240 channels at 28x28 in `group`
+/// groups, with products spread across groups unless `deterministic`.
+fn conv(group: usize, deterministic: bool) -> (ConvUnary, Arc<Tensor>) {
+    let image = ndarray::Array4::from_shape_fn((1, 240, 28, 28), |(_, c, y, x)| (c + y + x) as f32);
+    let image = image.into_arc_tensor();
+    let kernel = ndarray::Array4::<f32>::zeros((240, 240 / group, 1, 1));
+    let facts =
+        [TypedTensorInfo::from(image.clone()), TypedTensorInfo::from(kernel.into_arc_tensor())];
+    let conv = Conv::new(
+        DataFormat::NCHW,
+        KernelFormat::OIHW,
+        None,
+        None,
+        PaddingSpec::Valid,
+        None,
+        group,
+    )
+    .with_deterministic(deterministic);
+    (conv.to_unary(&facts).unwrap().unwrap(), image)
+}
+
+/// * `serial`: one product after the other,
+/// * `across_groups`: the products of the groups on the available cores.
+///
+/// Group 8 is the ShuffleNet case: 30 output channels per group.
+fn conv_groups(c: &mut Criterion) {
+    c.bench(
+        "conv_groups",
+        ParameterizedBenchmark::new(
+            "serial",
+            |b, &group| {
+                let (op, image) = conv(group, true);
+                b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+            },
+            vec![1, 8],
+        )
+        .with_function("across_groups", |b, &group| {
+            let (op, image) = conv(group, false);
+            b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+        }),
+    );
+}
+
+criterion_group!(benches, conv_groups);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::{Criterion, ParameterizedBenchmark};
+
+use tract_core::internal::*;
+use tract_core::ops::cnn::conv::{KernelProvider, MatMat};
+use tract_core::ops::cnn::Conv;
+
+/// A 3x3 convolution of a 28x28 image with `c` input and output channels:
+/// the im2col output, the product, and the kernel as a (c, k) matrix.
+fn gemm(c: usize) -> (Tensor, MatMat<f32>, ndarray::Array2<f32>) {
+    let image = ndarray::Array4::from_shape_fn((1, c, 28, 28), |(_, c, y, x)| (c + y + x) as f32);
+    let image = image.into_arc_tensor();
+    let kernel = ndarray::Array4::from_shape_fn((c, c, 3, 3), |(o, i, _, _)| (o + i) as f32);
+    let facts = [
+        TypedTensorInfo::from(image.clone()),
+        TypedTensorInfo::from(kernel.clone().into_arc_tensor()),
+    ];
+    let conv = Conv::default().to_unary(&facts).unwrap().unwrap();
+    let (im2col, _, gemm) = conv.to_boxed_im2col_pair::<f32>(image.shape()).unwrap();
+    let packed = im2col.as_stateless().unwrap().eval(tvec!(image)).unwrap().remove(0);
+    let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap().clone();
+    (packed.into_tensor(), gemm, kernel.into_shape((c, c * 9)).unwrap())
+}
+
+fn fact(shape: &[usize]) -> TypedTensorInfo {
+    TypedTensorInfo { shape: ShapeInfo::from(shape), datum_type: DatumType::F32, konst: None }
+}
+
+fn plan(op: MatMat<f32>, input: &Tensor) -> SimplePlan<TypedTensorInfo, TypedModel> {
+    let mut model = TypedModel::default();
+    model.add_source("packed", fact(input.shape())).unwrap();
+    let output = fact(&op.output_shape.shape);
+    model.chain("gemm", op, tvec!(output)).unwrap();
+    SimplePlan::new(model).unwrap()
+}
+
+/// * `packed`: the product, the kernel packed once when the op was built,
+/// * `unpacked`: the product, the kernel packed at each evaluation,
+/// * `pack`: packing the kernel alone, the one-time cost of `packed`.
+///
+/// `packed` pays for itself after pack / (unpacked - packed) evaluations.
+fn packing(c: &mut Criterion) {
+    c.bench(
+        "conv_kernel_packing",
+        ParameterizedBenchmark::new(
+            "packed",
+            |b, &c| {
+                let (input, gemm, _) = gemm(c);
+                let plan = plan(gemm, &input);
+                b.iter(|| plan.run(tvec!(input.clone())).unwrap())
+            },
+            vec![16, 64, 256],
+        )
+        .with_function("unpacked", |b, &c| {
+            let (input, gemm, kernel) = gemm(c);
+            let provider: KernelProvider<f32> = Arc::new(move || kernel.clone());
+            let plan = plan(gemm.with_kernel_provider(provider, None), &input);
+            b.iter(|| plan.run(tvec!(input.clone())).unwrap())
+        })
+        .with_function("pack", |b, &c| {
+            let (_, gemm, kernel) = gemm(c);
+            let kernel = kernel.into_shape((1, c, c * 9)).unwrap();
+            b.iter(|| MatMat::pack_kernels(&*gemm.mm, gemm.kernel_as_b, kernel.view()).unwrap())
+        }),
+    );
+}
+
+criterion_group!(benches, packing);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::Criterion;
+
+use tract_core::internal::*;
+
+fn fact(shape: &[usize]) -> TypedTensorInfo {
+    TypedTensorInfo { shape: ShapeInfo::from(shape), datum_type: DatumType::F32, konst: None }
+}
+
+/// im2col, gemm and a residual Add: the gemm writes its output, then the Add
+/// reads it back along with the skip.
+fn separate(h: usize, w: usize, c: usize) -> TypedModel {
+    let image_shape = [1, h, w, c];
+    let kernel = Tensor::from(ndarray::Array4::<f32>::zeros((3, 3, c, c)));
+    let conv = tract_core::ops::cnn::Conv::new(
+        tract_core::ops::nn::DataFormat::NHWC,
+        tract_core::ops::cnn::KernelFormat::HWIO,
+        None,
+        None,
+        tract_core::ops::cnn::PaddingSpec::SameUpper,
+        None,
+        1,
+    );
+    let unary = conv.to_unary(&[fact(&image_shape), kernel.into()]).unwrap().unwrap();
+    let (im2col, packed_shape, gemm) = unary.to_boxed_im2col_pair::<f32>(&image_shape).unwrap();
+    let mut model = TypedModel::default();
+    model.add_source("image", fact(&image_shape)).unwrap();
+    model.chain("im2col", im2col, tvec!(fact(&packed_shape))).unwrap();
+    let gemm = model.chain("gemm", gemm, tvec!(fact(&image_shape))).unwrap();
+    let skip = model.add_source("skip", fact(&image_shape)).unwrap();
+    let add = model
+        .add_node("add", tract_core::ops::math::Add::default(), tvec!(fact(&image_shape)))
+        .unwrap();
+    model.add_edge(OutletId::new(gemm, 0), InletId::new(add, 0)).unwrap();
+    model.add_edge(OutletId::new(skip, 0), InletId::new(add, 1)).unwrap();
+    model.set_output_outlets(&[OutletId::new(add, 0)]).unwrap();
+    model
+}
+
+fn run(c: &mut Criterion, name: &str, model: TypedModel, h: usize, w: usize, ch: usize) {
+    let image = Tensor::from(ndarray::Array4::<f32>::zeros((1, h, w, ch)));
+    let plan = SimplePlan::new(model).unwrap();
+    c.bench(
+        "conv_residual",
+        criterion::Benchmark::new(name, move |b| {
+            b.iter(|| plan.run(tvec!(image.clone(), image.clone())).unwrap())
+        })
+        // conv output written and read back, skip read
+        .throughput(criterion::Throughput::Bytes((3 * h * w * ch * 4) as u32)),
+    );
+}
+
+fn residual(c: &mut Criterion) {
+    let (h, w, ch) = (56, 56, 64);
+    run(c, "separate", separate(h, w, ch), h, w, ch);
+    // codegen folds the Add into the gemm
+    run(c, "fused", separate(h, w, ch).codegen().unwrap(), h, w, ch);
+}
+
+criterion_group!(benches, residual);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::Criterion;
+
+use tract_core::internal::*;
+use tract_core::ops::cnn::{Conv, ConvStrategy, KernelFormat, PaddingSpec};
+use tract_core::ops::nn::DataFormat;
+
+fn fact(shape: &[usize]) -> TypedTensorInfo {
+    TypedTensorInfo { shape: ShapeInfo::from(shape), datum_type: DatumType::F32, konst: None }
+}
+
+/// MobileNet block: 3x3 depthwise conv, then a 1x1 conv. Forcing the
+/// depthwise strategy keeps codegen from fusing the pair.
+fn block(h: usize, w: usize, c: usize, co: usize, strategy: ConvStrategy) -> TypedModel {
+    let image_shape = [1, h, w, c];
+    let output_shape = [1, h, w, co];
+    let dw_kernel = Tensor::from(ndarray::Array4::<f32>::zeros((3, 3, c, 1)));
+    let dw = Conv::new(
+        DataFormat::NHWC,
+        KernelFormat::HWIO,
+        None,
+        None,
+        PaddingSpec::SameUpper,
+        None,
+        c,
+    )
+    .with_strategy(strategy);
+    let dw = dw.to_unary(&[fact(&image_shape), dw_kernel.into()]).unwrap().unwrap();
+    let pw_kernel = Tensor::from(ndarray::Array4::<f32>::zeros((1, 1, c, co)));
+    let pw =
+        Conv::new(DataFormat::NHWC, KernelFormat::HWIO, None, None, PaddingSpec::Valid, None, 1);
+    let pw = pw.to_unary(&[fact(&image_shape), pw_kernel.into()]).unwrap().unwrap();
+    let mut model = TypedModel::default();
+    model.add_source("image", fact(&image_shape)).unwrap();
+    model.chain("depthwise", dw, tvec!(fact(&image_shape))).unwrap();
+    model.chain("pointwise", pw, tvec!(fact(&output_shape))).unwrap();
+    model
+}
+
+fn run(c: &mut Criterion, name: &str, model: TypedModel, h: usize, w: usize, ch: usize) {
+    let image = Tensor::from(ndarray::Array4::<f32>::zeros((1, h, w, ch)));
+    let plan = SimplePlan::new(model).unwrap();
+    c.bench(
+        "conv_separable",
+        criterion::Benchmark::new(name, move |b| {
+            b.iter(|| plan.run(tvec!(image.clone())).unwrap())
+        }),
+    );
+}
+
+fn separable(c: &mut Criterion) {
+    let (h, w, ch, co) = (56, 56, 128, 128);
+    let separate = block(h, w, ch, co, ConvStrategy::ForceDepthwise).into_optimized().unwrap();
+    run(c, "separate", separate, h, w, ch);
+    let fused = block(h, w, ch, co, ConvStrategy::Auto).into_optimized().unwrap();
+    assert!(fused.nodes().iter().any(|n| n.op().name() == "SeparableConv"));
+    run(c, "fused", fused, h, w, ch);
+}
+
+criterion_group!(benches, separable);
+criterion_main!(benches);
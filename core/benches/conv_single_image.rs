@@ -0,0 +1,59 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::{Criterion, ParameterizedBenchmark};
+
+use tract_core::internal::*;
+use tract_core::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+use tract_core::ops::nn::DataFormat;
+
+/// The im2col half of a 3x3 convolution of `n` 14x14 images with 64
+/// channels, split in `group` groups.
+fn im2col(n: usize, group: usize) -> (Box<Op>, Arc<Tensor>) {
+    let image = ndarray::Array4::from_shape_fn((n, 64, 14, 14), |(_, c, y, x)| (c + y + x) as f32);
+    let image = image.into_arc_tensor();
+    let kernel = ndarray::Array4::<f32>::zeros((64, 64 / group, 3, 3));
+    let facts =
+        [TypedTensorInfo::from(image.clone()), TypedTensorInfo::from(kernel.into_arc_tensor())];
+    let conv = Conv::new(
+        DataFormat::NCHW,
+        KernelFormat::OIHW,
+        None,
+        None,
+        PaddingSpec::SameUpper,
+        None,
+        group,
+    );
+    let conv = conv.to_unary(&facts).unwrap().unwrap();
+    let (im2col, _, _) = conv.to_boxed_im2col_pair::<f32>(image.shape()).unwrap();
+    (im2col, image)
+}
+
+/// * `single`: one image, packed without batch subviews,
+/// * `batch_of_2`: two images, through the generic batch loop.
+///
+/// Half of `batch_of_2` is the cost of one image without the single image
+/// path.
+fn single_image(c: &mut Criterion) {
+    c.bench(
+        "conv_single_image",
+        ParameterizedBenchmark::new(
+            "single",
+            |b, &group| {
+                let (op, image) = im2col(1, group);
+                let op = op.as_stateless().unwrap();
+                b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+            },
+            vec![1, 8, 32],
+        )
+        .with_function("batch_of_2", |b, &group| {
+            let (op, image) = im2col(2, group);
+            let op = op.as_stateless().unwrap();
+            b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+        }),
+    );
+}
+
+criterion_group!(benches, single_image);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::{Criterion, ParameterizedBenchmark};
+
+use tract_core::internal::*;
+use tract_core::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+use tract_core::ops::nn::DataFormat;
+
+/// The im2col half of a padded 3x3 convolution of a `size` by `size` RGB
+/// image, gathered serially when `deterministic`.
+fn im2col(size: usize, deterministic: bool) -> (Box<Op>, Arc<Tensor>) {
+    let image =
+        ndarray::Array4::from_shape_fn((1, 3, size, size), |(_, c, y, x)| (c + y + x) as f32);
+    let image = image.into_arc_tensor();
+    let kernel = ndarray::Array4::<f32>::zeros((16, 3, 3, 3));
+    let facts =
+        [TypedTensorInfo::from(image.clone()), TypedTensorInfo::from(kernel.into_arc_tensor())];
+    let conv = Conv::new(
+        DataFormat::NCHW,
+        KernelFormat::OIHW,
+        None,
+        None,
+        PaddingSpec::SameUpper,
+        None,
+        1,
+    )
+    .with_deterministic(deterministic);
+    let conv = conv.to_unary(&facts).unwrap().unwrap();
+    let (im2col, _, _) = conv.to_boxed_im2col_pair::<f32>(image.shape()).unwrap();
+    (im2col, image)
+}
+
+/// * `serial`: one thread gathers the whole matrix,
+/// * `threaded`: the columns are split across the available cores.
+fn im2col_threads(c: &mut Criterion) {
+    c.bench(
+        "im2col_threads",
+        ParameterizedBenchmark::new(
+            "serial",
+            |b, &size| {
+                let (op, image) = im2col(size, true);
+                let op = op.as_stateless().unwrap();
+                b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+            },
+            vec![256, 512, 1024],
+        )
+        .with_function("threaded", |b, &size| {
+            let (op, image) = im2col(size, false);
+            let op = op.as_stateless().unwrap();
+            b.iter(|| op.eval(tvec!(image.clone())).unwrap())
+        }),
+    );
+}
+
+criterion_group!(benches, im2col_threads);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+#[macro_use]
+extern crate criterion;
+extern crate ndarray;
+extern crate tract_core;
+use criterion::Criterion;
+
+use tract_core::internal::*;
+use tract_core::ops::math::Clip;
+use tract_core::ops::nn::Relu6;
+
+fn run(c: &mut Criterion, name: &str, op: Box<StatelessOp>, len: usize) {
+    let input = Tensor::from(ndarray::Array1::from_shape_fn(len, |i| (i % 17) as f32 - 8.0));
+    let input = input.into_arc_tensor();
+    c.bench(
+        "relu6",
+        criterion::Benchmark::new(name, move |b| b.iter(|| op.eval(tvec!(input.clone())).unwrap()))
+            .throughput(criterion::Throughput::Bytes((len * 4) as u32)),
+    );
+}
+
+fn relu6(c: &mut Criterion) {
+    let len = 112 * 112 * 32;
+    run(c, "clip", Box::new(Clip::new(0.0, 6.0)), len);
+    run(c, "relu6", Box::new(Relu6), len);
+}
+
+criterion_group!(benches, relu6);
+criterion_main!(benches);
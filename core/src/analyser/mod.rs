@@ -92,10 +92,10 @@ impl<M: BorrowMut<InferenceModel>> Analyser<M> {
                 }
             }
 
-            let inferred = node
-                .op
-                .infer(inputs, outputs)
-                .map_err(|e| format!("while running inference on {} : {}", node, e))?;
+            let inferred = node.op.infer(inputs.clone(), outputs.clone()).map_err(|e| {
+                output_conflict(node, inputs.clone(), &outputs)
+                    .unwrap_or_else(|| format!("while running inference on {} : {}", node, e))
+            })?;
 
             for (ix, &outlet) in node.inputs.iter().enumerate() {
                 let inferred_fact = &inferred.0[ix];
@@ -128,6 +128,29 @@ impl<M: BorrowMut<InferenceModel>> Analyser<M> {
     }
 }
 
+/// Names the output of `node` whose fact, set before the analysis like the
+/// shapes some formats declare for intermediate tensors, contradicts what
+/// the op infers from its inputs alone.
+fn output_conflict(
+    node: &InferenceNode,
+    inputs: TVec<&TensorFact>,
+    outputs: &[&TensorFact],
+) -> Option<String> {
+    let any = TensorFact::default();
+    let (_, inferred) = node.op.infer(inputs, outputs.iter().map(|_| &any).collect()).ok()?;
+    outputs.iter().zip(inferred.iter()).enumerate().find_map(|(ix, (declared, inferred))| {
+        declared.unify(inferred).err().map(|_| {
+            format!(
+                "{} infers {} for output #{}, conflicting with its declared fact {}",
+                node,
+                inferred.format_dt_shape(),
+                ix,
+                declared.format_dt_shape()
+            )
+        })
+    })
+}
+
 #[cfg(tests)]
 mod tests {
     #[test]
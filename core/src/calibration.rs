@@ -0,0 +1,210 @@
+//! Activation ranges for post-training quantization.
+//!
+//! A `Calibration` runs a float model on calibration inputs and records, for
+//! every output of a set of nodes, the running min and max of its values
+//! over all the runs. It can also record a histogram of their magnitudes,
+//! to pick a quantization range on a percentile instead of on outliers.
+use std::borrow::Borrow;
+
+use crate::internal::*;
+use crate::model::{Model, OutletId, TensorInfo};
+use crate::plan::SimplePlan;
+
+/// Histogram of the magnitudes of the values of a tensor.
+///
+/// Bins are of equal width, from 0. The first values set the width, which
+/// doubles, merging bins pairwise, when a larger value comes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub width: f32,
+    pub bins: Vec<u64>,
+}
+
+impl Histogram {
+    fn new(bins: usize) -> Histogram {
+        Histogram { width: 0.0, bins: vec![0; bins.max(1)] }
+    }
+
+    fn add(&mut self, values: &[f32]) {
+        let bound = values.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        let bins = self.bins.len();
+        if self.width == 0.0 && bound > 0.0 {
+            self.width = bound / bins as f32;
+        }
+        while bound > self.width * bins as f32 {
+            self.double_width();
+        }
+        for x in values {
+            let bin = if self.width == 0.0 { 0 } else { (x.abs() / self.width) as usize };
+            self.bins[bin.min(bins - 1)] += 1;
+        }
+    }
+
+    fn double_width(&mut self) {
+        for ix in 0..self.bins.len() {
+            let merged = self.bins.get(2 * ix).cloned().unwrap_or(0)
+                + self.bins.get(2 * ix + 1).cloned().unwrap_or(0);
+            self.bins[ix] = merged;
+        }
+        self.width *= 2.0;
+    }
+
+    /// Magnitude bounding the `p` fraction of the values, at the bin upper
+    /// bound, `p` being in [0, 1].
+    pub fn percentile(&self, p: f32) -> f32 {
+        let total = self.bins.iter().sum::<u64>();
+        let target = (total as f64 * p as f64).ceil() as u64;
+        let mut seen = 0;
+        for (ix, count) in self.bins.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return (ix + 1) as f32 * self.width;
+            }
+        }
+        self.bins.len() as f32 * self.width
+    }
+}
+
+/// Values taken by a tensor over the calibration runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivationRange {
+    pub min: f32,
+    pub max: f32,
+    /// See `Calibration::with_histograms`.
+    pub histogram: Option<Histogram>,
+}
+
+/// Records the ranges of the outputs of some nodes over several runs.
+#[derive(Debug)]
+pub struct Calibration<TI: TensorInfo, M: Borrow<Model<TI>>> {
+    plan: SimplePlan<TI, M>,
+    histogram_bins: Option<usize>,
+    ranges: Vec<Option<ActivationRange>>,
+}
+
+impl<TI: TensorInfo, M: Borrow<Model<TI>>> Calibration<TI, M> {
+    /// Calibration of all the outputs of the nodes named `nodes`.
+    ///
+    /// Only these nodes and their precursors run, the model outputs are not
+    /// computed unless they are among them.
+    pub fn new(
+        model: M,
+        nodes: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> TractResult<Calibration<TI, M>> {
+        let mut outlets = vec![];
+        for name in nodes {
+            let node = model.borrow().node_by_name(name.as_ref())?;
+            outlets.extend((0..node.outputs.len()).map(|slot| OutletId::new(node.id, slot)));
+        }
+        let plan = SimplePlan::new_for_outputs(model, &outlets)?;
+        Ok(Calibration { plan, histogram_bins: None, ranges: vec![None; outlets.len()] })
+    }
+
+    /// Also record histograms of `bins` bins, see `Histogram::percentile`.
+    pub fn with_histograms(mut self, bins: usize) -> Calibration<TI, M> {
+        self.histogram_bins = Some(bins);
+        self
+    }
+
+    /// Run the model on a calibration input, updating the ranges.
+    pub fn run(&mut self, inputs: TVec<Tensor>) -> TractResult<()> {
+        let Calibration { plan, histogram_bins, ranges } = self;
+        let outputs = plan.run(inputs)?;
+        for ((range, output), outlet) in ranges.iter_mut().zip(outputs).zip(&plan.outputs) {
+            let values = output
+                .cast_to::<f32>()
+                .map_err(|e| format!("Calibrating {}: {}", plan.model().outlet_name(*outlet), e))?;
+            let values = values.as_slice::<f32>()?;
+            if values.is_empty() {
+                continue;
+            }
+            let (min, max) = values
+                .iter()
+                .fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |(min, max), &x| {
+                    (min.min(x), max.max(x))
+                });
+            let range = range.get_or_insert_with(|| ActivationRange {
+                min,
+                max,
+                histogram: histogram_bins.map(Histogram::new),
+            });
+            range.min = range.min.min(min);
+            range.max = range.max.max(max);
+            if let Some(histogram) = range.histogram.as_mut() {
+                histogram.add(values);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ranges by outlet name, as `Model::outlet_name` gives it.
+    ///
+    /// Outputs that took no value yet, because they were empty in all the
+    /// runs or because nothing ran, have no range.
+    pub fn ranges(&self) -> HashMap<String, Option<ActivationRange>> {
+        self.plan
+            .outputs
+            .iter()
+            .zip(self.ranges.iter())
+            .map(|(&outlet, range)| (self.plan.model().outlet_name(outlet), range.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::math::Neg;
+    use crate::ops::nn::Relu;
+
+    fn model() -> InferenceModel {
+        let mut model = InferenceModel::default();
+        model.add_source("x", TensorFact::dt(f32::datum_type())).unwrap();
+        model.chain_default("neg", Neg::default()).unwrap();
+        model.chain_default("relu", Relu::default()).unwrap();
+        model
+    }
+
+    #[test]
+    fn ranges_bound_observed_values() {
+        let model = model();
+        let mut calib = Calibration::new(&model, &["x", "relu"]).unwrap().with_histograms(16);
+        let inputs = vec![
+            tensor1(&[1.0f32, -2.0, 0.5]),
+            tensor1(&[3.0f32, 0.25]),
+            tensor1(&[-0.5f32, -7.5, 2.0, 1.0]),
+        ];
+        for input in &inputs {
+            calib.run(tvec!(input.clone())).unwrap();
+        }
+        let ranges = calib.ranges();
+        assert_eq!(ranges.len(), 2);
+        let x = ranges["x"].as_ref().unwrap();
+        let relu = ranges["relu"].as_ref().unwrap();
+        assert_eq!((x.min, x.max), (-7.5, 3.0));
+        assert_eq!((relu.min, relu.max), (0.0, 7.5));
+        for input in &inputs {
+            for &v in input.as_slice::<f32>().unwrap() {
+                assert!(x.min <= v && v <= x.max);
+                assert!(relu.min <= (-v).max(0.0) && (-v).max(0.0) <= relu.max);
+            }
+        }
+        let histogram = x.histogram.as_ref().unwrap();
+        assert_eq!(histogram.bins.iter().sum::<u64>(), 9);
+        assert!(histogram.percentile(1.0) >= 7.5);
+        assert!(histogram.percentile(0.5) < 7.5);
+    }
+
+    #[test]
+    fn unobserved_outputs_have_no_range() {
+        let model = model();
+        let mut calib = Calibration::new(&model, &["neg"]).unwrap();
+        assert_eq!(calib.ranges()["neg"], None);
+        calib.run(tvec!(tensor1(&[] as &[f32]))).unwrap();
+        assert_eq!(calib.ranges()["neg"], None);
+        calib.run(tvec!(tensor1(&[2.0f32]))).unwrap();
+        let neg = calib.ranges()["neg"].clone().unwrap();
+        assert_eq!((neg.min, neg.max, neg.histogram), (-2.0, -2.0, None));
+        assert!(Calibration::new(&model, &["nope"]).is_err());
+    }
+}
@@ -4,6 +4,7 @@ use crate::tensor::litteral::*;
 use crate::tensor::Tensor;
 use crate::TractResult;
 use std::fmt;
+use std::sync::Arc;
 
 use tract_linalg::f16::f16;
 
@@ -25,6 +26,7 @@ pub enum DatumType {
     F64,
     TDim,
     String,
+    SequenceItem,
 }
 
 impl DatumType {
@@ -46,6 +48,7 @@ impl DatumType {
             DatumType::F64 => &[DatumType::F64],
             DatumType::String => &[DatumType::String],
             DatumType::TDim => &[DatumType::TDim],
+            DatumType::SequenceItem => &[DatumType::SequenceItem],
         }
     }
 
@@ -89,6 +92,7 @@ impl DatumType {
             DatumType::F64 => std::mem::size_of::<f64>(),
             DatumType::TDim => std::mem::size_of::<TDim>(),
             DatumType::String => std::mem::size_of::<String>(),
+            DatumType::SequenceItem => std::mem::size_of::<SequenceItem>(),
         }
     }
 
@@ -96,6 +100,7 @@ impl DatumType {
         match self {
             DatumType::TDim => std::mem::size_of::<usize>(),
             DatumType::String => std::mem::size_of::<usize>(),
+            DatumType::SequenceItem => std::mem::size_of::<usize>(),
             _ => self.size_of(),
         }
     }
@@ -163,6 +168,15 @@ try_into!(i8, f32);
 try_into!(i16, f32);
 try_into!(i32, f32);
 try_into!(i64, f32);
+try_into!(u8, f32);
+try_into!(u16, f32);
+
+try_into!(u8, f64);
+try_into!(u16, f64);
+try_into!(i8, f64);
+try_into!(i16, f64);
+try_into!(i32, f64);
+try_into!(i64, f64);
 
 impl TryInto<TDim> for i32 {
     fn try_into(&self) -> TractResult<TDim> {
@@ -198,6 +212,19 @@ impl TryInto<f32> for bool {
     }
 }
 
+impl TryInto<u8> for bool {
+    fn try_into(&self) -> TractResult<u8> {
+        Ok(*self as u8)
+    }
+}
+
+/// Masks stored as u8 hold any non zero value for true.
+impl TryInto<bool> for u8 {
+    fn try_into(&self) -> TractResult<bool> {
+        Ok(*self != 0)
+    }
+}
+
 impl TryInto<f32> for f16 {
     fn try_into(&self) -> TractResult<f32> {
         Ok(self.0.to_f32())
@@ -253,6 +280,20 @@ datum!(u8, U8);
 datum!(u16, U16);
 datum!(TDim, TDim);
 datum!(String, String);
+datum!(SequenceItem, SequenceItem);
+
+/// One tensor of a sequence.
+///
+/// Sequences are rank 1 tensors of these, so their items can have different
+/// shapes and are shared, not copied, when the sequence is edited.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SequenceItem(pub Arc<Tensor>);
+
+impl fmt::Display for SequenceItem {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "({})", self.0)
+    }
+}
 
 pub trait FloatLike: Datum {
     fn packed_direct_conv(
@@ -289,7 +330,7 @@ impl FloatLike for f32 {
         (tract_linalg::ops().sconv)(m, kernel_offsets, data_offsets)
     }
     fn packed_mat_mul(m: usize, k: usize, n: usize) -> Box<tract_linalg::MatMul<Self>> {
-        (tract_linalg::ops().smm)(m, k, n)
+        tract_linalg::tune::smm(m, k, n)
     }
     fn packed_vec_mat_mul(k: usize, n: usize) -> Box<tract_linalg::VecMatMul<Self>> {
         (tract_linalg::ops().svmm)(k, n)
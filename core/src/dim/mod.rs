@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::ops;
 use std::str::FromStr;
@@ -106,6 +107,12 @@ impl TDim {
         self.0.eval(&hashmap!())
     }
 
+    /// The value of the dimension once its symbols get these values, or the
+    /// dimension itself if some are missing.
+    pub fn substitute(&self, values: &HashMap<char, i32>) -> TDim {
+        self.0.eval(values).map(TDim::from).unwrap_or(*self)
+    }
+
     pub fn div_ceil(&self, other: TDim) -> TDim {
         TDim(self.0.div_ceil(&other.0))
     }
@@ -12,6 +12,7 @@ error_chain! {
         StrUtf8(::std::str::Utf8Error);
         NumParseInt(::std::num::ParseIntError);
         Infallible(std::convert::Infallible);
+        Conv(crate::ops::cnn::ConvError);
     }
     errors {
         TFString {}
@@ -0,0 +1,376 @@
+//! Flatbuffers, as TFLite models and saved tract models are stored in.
+//!
+//! Only what these schemas use is covered: tables, scalars, strings and
+//! vectors of scalars or tables. Unions are read as a type field and a table.
+//! Every offset is checked against the buffer: a corrupt model is an error,
+//! not a panic.
+
+use std::fmt;
+
+use crate::internal::*;
+
+/// A little-endian scalar stored in a flatbuffer.
+pub trait Scalar: Sized + Copy {
+    const SIZE: usize;
+    fn from_le(bytes: &[u8]) -> Self;
+    fn to_le(self, buf: &mut Vec<u8>);
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty),*) => {
+        $(impl Scalar for $t {
+            const SIZE: usize = std::mem::size_of::<$t>();
+            fn from_le(bytes: &[u8]) -> $t {
+                let mut le = [0u8; std::mem::size_of::<$t>()];
+                le.copy_from_slice(&bytes[..Self::SIZE]);
+                <$t>::from_le_bytes(le)
+            }
+            fn to_le(self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes())
+            }
+        })*
+    };
+}
+
+impl_scalar!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl Scalar for f32 {
+    const SIZE: usize = 4;
+    fn from_le(bytes: &[u8]) -> f32 {
+        f32::from_bits(<u32 as Scalar>::from_le(bytes))
+    }
+    fn to_le(self, buf: &mut Vec<u8>) {
+        Scalar::to_le(self.to_bits(), buf)
+    }
+}
+
+impl Scalar for f64 {
+    const SIZE: usize = 8;
+    fn from_le(bytes: &[u8]) -> f64 {
+        f64::from_bits(<u64 as Scalar>::from_le(bytes))
+    }
+    fn to_le(self, buf: &mut Vec<u8>) {
+        Scalar::to_le(self.to_bits(), buf)
+    }
+}
+
+fn read<T: Scalar>(buf: &[u8], pos: usize) -> TractResult<T> {
+    if pos + T::SIZE > buf.len() {
+        bail!("Truncated flatbuffer: reading {} bytes at {} of {}", T::SIZE, pos, buf.len());
+    }
+    Ok(T::from_le(&buf[pos..]))
+}
+
+/// A table, its fields looked up by id in its vtable.
+#[derive(Clone, Copy)]
+pub struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> fmt::Debug for Table<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Table@{}", self.pos)
+    }
+}
+
+impl<'a> Table<'a> {
+    /// The file identifier of the buffer, the four bytes following the
+    /// offset of the root table.
+    pub fn identifier(buf: &'a [u8]) -> TractResult<&'a [u8]> {
+        if buf.len() < 8 {
+            bail!("Truncated flatbuffer: no file identifier in {} bytes", buf.len());
+        }
+        Ok(&buf[4..8])
+    }
+
+    /// The root table of the buffer.
+    pub fn root(buf: &'a [u8]) -> TractResult<Table<'a>> {
+        let pos = read::<u32>(buf, 0)? as usize;
+        Table::at(buf, pos)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> TractResult<Table<'a>> {
+        read::<i32>(buf, pos)?;
+        Ok(Table { buf, pos })
+    }
+
+    /// Position of a field, None if it is absent: it then has its default.
+    fn field(&self, id: usize) -> TractResult<Option<usize>> {
+        let vtable = self.pos as i64 - read::<i32>(self.buf, self.pos)? as i64;
+        if vtable < 0 {
+            bail!("Flatbuffer vtable of table at {} out of the buffer", self.pos);
+        }
+        let vtable = vtable as usize;
+        let vtable_len = read::<u16>(self.buf, vtable)? as usize;
+        let entry = 4 + 2 * id;
+        if entry + 2 > vtable_len {
+            return Ok(None);
+        }
+        match read::<u16>(self.buf, vtable + entry)? {
+            0 => Ok(None),
+            offset => Ok(Some(self.pos + offset as usize)),
+        }
+    }
+
+    /// The object an offset field points to.
+    fn indirect(&self, id: usize) -> TractResult<Option<usize>> {
+        match self.field(id)? {
+            Some(pos) => Ok(Some(pos + read::<u32>(self.buf, pos)? as usize)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn scalar<T: Scalar>(&self, id: usize, default: T) -> TractResult<T> {
+        match self.field(id)? {
+            Some(pos) => read(self.buf, pos),
+            None => Ok(default),
+        }
+    }
+
+    pub fn table(&self, id: usize) -> TractResult<Option<Table<'a>>> {
+        match self.indirect(id)? {
+            Some(pos) => Ok(Some(Table::at(self.buf, pos)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Position of the first item and length of a vector field, of items of
+    /// `size` bytes.
+    fn vector_items(&self, id: usize, size: usize) -> TractResult<(usize, usize)> {
+        let pos = match self.indirect(id)? {
+            Some(pos) => pos,
+            None => return Ok((0, 0)),
+        };
+        let len = read::<u32>(self.buf, pos)? as usize;
+        if pos + 4 + len * size > self.buf.len() {
+            bail!("Truncated flatbuffer: vector of {} items at {}", len, pos);
+        }
+        Ok((pos + 4, len))
+    }
+
+    /// A vector of scalars, empty if absent.
+    pub fn vector<T: Scalar>(&self, id: usize) -> TractResult<Vec<T>> {
+        let (start, len) = self.vector_items(id, T::SIZE)?;
+        (0..len).map(|ix| read(self.buf, start + ix * T::SIZE)).collect()
+    }
+
+    /// A vector of bytes, in place.
+    pub fn bytes(&self, id: usize) -> TractResult<&'a [u8]> {
+        let (start, len) = self.vector_items(id, 1)?;
+        Ok(&self.buf[start..start + len])
+    }
+
+    /// A vector of tables, empty if absent.
+    pub fn tables(&self, id: usize) -> TractResult<Vec<Table<'a>>> {
+        let (start, len) = self.vector_items(id, 4)?;
+        (0..len)
+            .map(|ix| {
+                let pos = start + ix * 4;
+                Table::at(self.buf, pos + read::<u32>(self.buf, pos)? as usize)
+            })
+            .collect()
+    }
+
+    pub fn string(&self, id: usize) -> TractResult<Option<&'a str>> {
+        if self.indirect(id)?.is_none() {
+            return Ok(None);
+        }
+        let bytes = self.bytes(id)?;
+        Ok(Some(std::str::from_utf8(bytes).map_err(|e| format!("Flatbuffer string: {}", e))?))
+    }
+}
+
+/// Writing of flatbuffers.
+///
+/// Children are laid out after their parents, so the offsets all point
+/// forward, as the format requires. Nothing is aligned: the reader does not
+/// need it.
+pub mod builder {
+    use super::Scalar;
+
+    /// A value to write. Tables hold their fields by id, None for absent ones.
+    #[derive(Clone, Debug)]
+    pub enum Value {
+        U8(u8),
+        I32(i32),
+        U32(u32),
+        I64(i64),
+        Bytes(Vec<u8>),
+        I32s(Vec<i32>),
+        I64s(Vec<i64>),
+        F32s(Vec<f32>),
+        Str(String),
+        Table(Vec<Option<Value>>),
+        Tables(Vec<Value>),
+    }
+
+    impl Value {
+        /// Bytes of the value inside its table: offsets take 4.
+        fn inline_size(&self) -> usize {
+            match self {
+                Value::U8(_) => 1,
+                Value::I64(_) => 8,
+                _ => 4,
+            }
+        }
+    }
+
+    /// The buffer holding `root`, a table.
+    pub fn finish(root: &Value) -> Vec<u8> {
+        finish_buffer(root, &[])
+    }
+
+    /// The buffer holding `root`, a table, marked with a file identifier.
+    pub fn finish_with_identifier(root: &Value, identifier: &[u8; 4]) -> Vec<u8> {
+        finish_buffer(root, identifier)
+    }
+
+    fn finish_buffer(root: &Value, identifier: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; 4];
+        buf.extend_from_slice(identifier);
+        let pos = write_object(&mut buf, root);
+        patch(&mut buf, 0, pos);
+        buf
+    }
+
+    fn patch(buf: &mut [u8], at: usize, target: usize) {
+        let offset = (target - at) as u32;
+        buf[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    /// Writes an object pointed to by an offset, returning its position.
+    fn write_object(buf: &mut Vec<u8>, value: &Value) -> usize {
+        match value {
+            Value::Table(fields) => write_table(buf, fields),
+            Value::Tables(tables) => {
+                let pos = buf.len();
+                buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                let slots = buf.len();
+                buf.resize(slots + 4 * tables.len(), 0);
+                for (ix, table) in tables.iter().enumerate() {
+                    let table = write_object(buf, table);
+                    patch(buf, slots + 4 * ix, table);
+                }
+                pos
+            }
+            Value::Str(s) => {
+                let pos = write_vector(buf, s.len(), s.as_bytes());
+                buf.push(0);
+                pos
+            }
+            Value::Bytes(v) => write_vector(buf, v.len(), v),
+            Value::I32s(v) => write_scalars(buf, v),
+            Value::I64s(v) => write_scalars(buf, v),
+            Value::F32s(v) => write_scalars(buf, v),
+            scalar => panic!("{:?} is not an object", scalar),
+        }
+    }
+
+    fn write_scalars<T: Scalar>(buf: &mut Vec<u8>, v: &[T]) -> usize {
+        let mut bytes = Vec::with_capacity(v.len() * T::SIZE);
+        for x in v {
+            x.to_le(&mut bytes);
+        }
+        write_vector(buf, v.len(), &bytes)
+    }
+
+    fn write_vector(buf: &mut Vec<u8>, len: usize, bytes: &[u8]) -> usize {
+        let pos = buf.len();
+        buf.extend_from_slice(&(len as u32).to_le_bytes());
+        buf.extend_from_slice(bytes);
+        pos
+    }
+
+    fn write_table(buf: &mut Vec<u8>, fields: &[Option<Value>]) -> usize {
+        let mut offsets = vec![];
+        let mut size = 4;
+        for field in fields {
+            match field {
+                Some(value) => {
+                    offsets.push(size as u16);
+                    size += value.inline_size();
+                }
+                None => offsets.push(0),
+            }
+        }
+        let vtable = buf.len();
+        buf.extend_from_slice(&(4 + 2 * offsets.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&(size as u16).to_le_bytes());
+        for offset in &offsets {
+            buf.extend_from_slice(&offset.to_le_bytes());
+        }
+        let table = buf.len();
+        buf.extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+        let mut children = vec![];
+        for value in fields.iter().filter_map(|f| f.as_ref()) {
+            match value {
+                Value::U8(x) => buf.push(*x),
+                Value::I32(x) => buf.extend_from_slice(&x.to_le_bytes()),
+                Value::U32(x) => buf.extend_from_slice(&x.to_le_bytes()),
+                Value::I64(x) => buf.extend_from_slice(&x.to_le_bytes()),
+                object => {
+                    children.push((buf.len(), object));
+                    buf.extend_from_slice(&[0; 4]);
+                }
+            }
+        }
+        for (slot, object) in children {
+            let pos = write_object(buf, object);
+            patch(buf, slot, pos);
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::builder::*;
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let child = Value::Table(vec![Some(Value::I32(-7))]);
+        let root = Value::Table(vec![
+            Some(Value::U8(3)),
+            None,
+            Some(Value::Str("conv".to_string())),
+            Some(Value::I32s(vec![1, -2, 3])),
+            Some(Value::Tables(vec![child.clone(), child])),
+            Some(Value::F32s(vec![0.5])),
+            Some(Value::I64(-1 << 40)),
+        ]);
+        let buf = finish(&root);
+        let table = Table::root(&buf).unwrap();
+        assert_eq!(table.scalar::<u8>(0, 0).unwrap(), 3);
+        assert_eq!(table.scalar::<i32>(1, 42).unwrap(), 42);
+        assert_eq!(table.string(2).unwrap(), Some("conv"));
+        assert_eq!(table.vector::<i32>(3).unwrap(), vec![1, -2, 3]);
+        let children = table.tables(4).unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[1].scalar::<i32>(0, 0).unwrap(), -7);
+        assert_eq!(table.vector::<f32>(5).unwrap(), vec![0.5]);
+        assert_eq!(table.scalar::<i64>(6, 0).unwrap(), -1 << 40);
+        // past the end of the vtable
+        assert!(table.table(9).unwrap().is_none());
+        assert!(table.vector::<i64>(9).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let root = Value::Table(vec![Some(Value::I32s(vec![1, 2, 3]))]);
+        let buf = finish(&root);
+        let table = Table::root(&buf[..buf.len() - 1]).unwrap();
+        assert!(table.vector::<i32>(0).is_err());
+        assert!(Table::root(&buf[..2]).is_err());
+    }
+
+    #[test]
+    fn identifier() {
+        let root = Value::Table(vec![Some(Value::U32(7))]);
+        let buf = finish_with_identifier(&root, b"TEST");
+        assert_eq!(Table::identifier(&buf).unwrap(), b"TEST");
+        assert_eq!(Table::root(&buf).unwrap().scalar::<u32>(0, 0).unwrap(), 7);
+        assert!(Table::identifier(&buf[..6]).is_err());
+    }
+}
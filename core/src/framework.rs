@@ -7,15 +7,37 @@ use std::path::Path;
 
 pub type OpBuilder<ProtoOp> = fn(&ProtoOp) -> TractResult<Box<Op>>;
 
-#[derive(Default)]
-pub struct OpRegister<ProtoOp>(HashMap<String, OpBuilder<ProtoOp>>);
+/// Op builders by op name, and by the version of the operator set they
+/// build the op for.
+///
+/// Each builder covers the versions from the one it is registered at up to
+/// the next one registered for the same name.
+pub struct OpRegister<ProtoOp>(HashMap<String, Vec<(i64, OpBuilder<ProtoOp>)>>);
+
+impl<ProtoOp> Default for OpRegister<ProtoOp> {
+    fn default() -> OpRegister<ProtoOp> {
+        OpRegister(HashMap::new())
+    }
+}
 
 impl<ProtoOp> OpRegister<ProtoOp> {
+    /// The builder for the latest version of the op.
     pub fn get(&self, name: &str) -> Option<&OpBuilder<ProtoOp>> {
-        self.0.get(name)
+        self.0.get(name).and_then(|builders| builders.last()).map(|(_, b)| b)
+    }
+    /// The builder for the op as of version `version` of its operator set.
+    pub fn get_for_version(&self, name: &str, version: i64) -> Option<&OpBuilder<ProtoOp>> {
+        self.0.get(name)?.iter().rev().find(|(since, _)| *since <= version).map(|(_, b)| b)
     }
     pub fn insert(&mut self, name: impl AsRef<str>, b: OpBuilder<ProtoOp>) {
-        self.0.insert(name.as_ref().to_string(), b);
+        self.insert_since(name, 0, b)
+    }
+    /// Register `b` for the versions of the op from `since` on.
+    pub fn insert_since(&mut self, name: impl AsRef<str>, since: i64, b: OpBuilder<ProtoOp>) {
+        let builders = self.0.entry(name.as_ref().to_string()).or_insert_with(Vec::new);
+        builders.retain(|(v, _)| *v != since);
+        builders.push((since, b));
+        builders.sort_by_key(|(v, _)| *v);
     }
     pub fn names(&self) -> impl Iterator<Item = &str> {
         self.0.keys().map(|s| &**s)
@@ -51,3 +73,24 @@ pub trait Framework<ProtoOp: Debug, ProtoModel: Debug> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::identity::Identity;
+    use crate::ops::math::Neg;
+
+    #[test]
+    fn builder_by_version() {
+        let mut reg = OpRegister::<()>::default();
+        reg.insert("Op", |_| Ok(Box::new(Identity::default())));
+        reg.insert_since("Op", 13, |_| Ok(Box::new(Neg::default())));
+        reg.insert_since("Late", 11, |_| Ok(Box::new(Neg::default())));
+        let name = |b: Option<&OpBuilder<()>>| b.map(|b| b(&()).unwrap().name().into_owned());
+        assert_eq!(name(reg.get_for_version("Op", 1)), Some("Identity".to_string()));
+        assert_eq!(name(reg.get_for_version("Op", 12)), Some("Identity".to_string()));
+        assert_eq!(name(reg.get_for_version("Op", 13)), name(reg.get("Op")));
+        assert_eq!(name(reg.get_for_version("Late", 10)), None);
+        assert_eq!(name(reg.get_for_version("Late", 17)), name(reg.get("Late")));
+    }
+}
@@ -95,9 +95,11 @@ pub mod analyser;
 pub mod ops;
 
 pub mod broadcast;
+pub mod calibration;
 pub mod datum;
 pub mod dim;
 pub mod errors;
+pub mod flat;
 pub mod framework;
 pub mod model;
 mod optim;
@@ -110,7 +112,7 @@ pub use crate::errors::*;
 /// This prelude is meant for code using tract.
 pub mod prelude {
     pub use crate::analyser::types::TensorFact;
-    pub use crate::datum::{Datum, DatumType};
+    pub use crate::datum::{Datum, DatumType, SequenceItem};
     pub use crate::dim::TDim;
     pub use crate::errors::*;
     pub use crate::framework::Framework;
@@ -134,8 +136,8 @@ pub mod internal {
     pub use crate::framework::*;
     pub use crate::model::*;
     pub use crate::ops::{
-        check_input_arity, check_output_arity, Cost, InferenceOp, Op, OpState, StatefullOp,
-        StatelessOp,
+        check_input_arity, check_output_arity, normalize_axis, normalize_insertion_axis, Cost,
+        InferenceOp, Op, OpState, StatefullOp, StatelessOp,
     };
     pub use crate::plan::SessionState;
     pub use crate::prelude::*;
@@ -153,4 +155,3 @@ fn setup_test_logger() {
     let _ =
         env_logger::Builder::from_default_env().filter_level(log::LevelFilter::Trace).try_init();
 }
-
@@ -37,5 +37,6 @@ where
         .collect();
     model.outputs =
         old.output_outlets()?.iter().map(|o| OutletId::new(map[&o.node], o.slot)).collect();
+    model.output_labels = old.output_labels.clone();
     Ok(model)
 }
@@ -0,0 +1,668 @@
+//! Versioned flatbuffer format typed models are saved in.
+//!
+//! Models are saved decluttered: the ops codegen lowers convolutions to hold
+//! kernels packed for the host they were optimized on, so a loaded model goes
+//! through `into_optimized` again, on the host that runs it.
+//!
+//! The schema, fields in id order:
+//!
+//! ```text
+//! table Model { major: u32; minor: u32; nodes: [Node]; inputs: [Outlet];
+//!               outputs: [Outlet]; output_labels: [Label]; }
+//! table Node { name: string; op: string; inputs: [Outlet]; outputs: [Fact];
+//!              params: Conv; }
+//! table Outlet { node: u32; slot: u32; }
+//! table Label { label: string; }
+//! table Fact { datum_type: u8; shape: [Dim]; konst: Tensor; }
+//! table Dim { value: i32; stream: i32; }    // value + stream * S
+//! table Tensor { datum_type: u8; shape: [i64]; data: [u8]; }
+//! table Conv { data_format: u8; kernel_fmt: u8; padding: u8;
+//!              padding_before: [i64]; padding_after: [i64];
+//!              dilations: [i64]; strides: [i64]; kernel: Tensor;
+//!              bias: Tensor; full_input_shape: [Dim];
+//!              full_output_shape: [Dim]; group: u32; pad_mode: u8;
+//!              f64_output: u8; token_output: u8;
+//!              independent_shape: [i64]; options: ConvOptions; }
+//! table ConvOptions { strategy: u8; strategy_channel_block: u32;
+//!                     deterministic: u8; kernel_packing: u8;
+//!                     kernel_packing_block: u32;
+//!                     max_scratch_bytes: i64 = -1; packed_lowering: u8;
+//!                     weight_clamp: [f32]; }
+//! ```
+//!
+//! Tensor data is little-endian. Nodes have `params` only for `ConvUnary`.
+//! The other ops covered are `Source`, `Const`, its value being the konst of
+//! its output fact, and the element-wise ops without parameters of
+//! `unit_ops!`.
+//!
+//! Loading refuses models of another major version. Minor versions only add
+//! fields, which older loaders skip and newer ones read with their default.
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use ndarray::ArrayD;
+
+use crate::flat::builder::{finish_with_identifier, Value};
+use crate::flat::{Scalar, Table};
+use crate::internal::*;
+use crate::ops::cnn::conv::{
+    ConvOptions, ConvStrategy, ConvUnary, KernelCache, KernelFormat, KernelPacking, PhaseTimer,
+};
+use crate::ops::cnn::{PaddingSpec, PatchPadMode};
+use crate::ops::konst::Const;
+use crate::ops::nn::DataFormat;
+use crate::ops::source::Source;
+
+/// File identifier of saved models.
+pub const FORMAT_IDENTIFIER: &[u8; 4] = b"TRCT";
+
+/// Version of the format models are saved in, as (major, minor).
+pub const FORMAT_VERSION: (u32, u32) = (1, 0);
+
+/// Datum types, saved as their position in this list plus one. Only append.
+const DATUM_TYPES: [DatumType; 12] = [
+    DatumType::Bool,
+    DatumType::U8,
+    DatumType::U16,
+    DatumType::I8,
+    DatumType::I16,
+    DatumType::I32,
+    DatumType::I64,
+    DatumType::F16,
+    DatumType::F32,
+    DatumType::F64,
+    DatumType::TDim,
+    DatumType::String,
+];
+
+const DATA_FORMATS: [DataFormat; 2] = [DataFormat::NCHW, DataFormat::NHWC];
+const KERNEL_FORMATS: [KernelFormat; 2] = [KernelFormat::OIHW, KernelFormat::HWIO];
+const PAD_MODES: [PatchPadMode; 3] =
+    [PatchPadMode::Zero, PatchPadMode::Reflect, PatchPadMode::Edge];
+
+macro_rules! unit_ops {
+    ($($key:expr => $op:ty),*) => {
+        fn unit_op_key(op: &Op) -> Option<&'static str> {
+            $(if op.downcast_ref::<$op>().is_some() {
+                return Some($key);
+            })*
+            None
+        }
+
+        fn unit_op(key: &str) -> Option<Box<Op>> {
+            match key {
+                $($key => Some(Box::new(<$op>::default())),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+// Keyed by module: nn::Tanh and math::Tanh are both named Tanh.
+unit_ops!(
+    "math::Abs" => crate::ops::math::Abs,
+    "math::Ceil" => crate::ops::math::Ceil,
+    "math::Exp" => crate::ops::math::Exp,
+    "math::Floor" => crate::ops::math::Floor,
+    "math::Ln" => crate::ops::math::Ln,
+    "math::Neg" => crate::ops::math::Neg,
+    "math::Recip" => crate::ops::math::Recip,
+    "math::Round" => crate::ops::math::Round,
+    "math::Rsqrt" => crate::ops::math::Rsqrt,
+    "math::Sqrt" => crate::ops::math::Sqrt,
+    "math::Tanh" => crate::ops::math::Tanh,
+    "nn::Relu" => crate::ops::nn::Relu,
+    "nn::Sigmoid" => crate::ops::nn::Sigmoid,
+    "nn::Softplus" => crate::ops::nn::Softplus,
+    "nn::Softsign" => crate::ops::nn::Softsign,
+    "nn::Tanh" => crate::ops::nn::Tanh
+);
+
+impl TypedModel {
+    /// Writes the model in the versioned flatbuffer format.
+    ///
+    /// The model must be decluttered, not optimized, and hold only the ops
+    /// the format covers.
+    pub fn save(&self, w: &mut impl Write) -> TractResult<()> {
+        w.write_all(&self.to_flatbuffer()?)?;
+        Ok(())
+    }
+
+    /// Reads a model written by `save`. It comes back decluttered, to be
+    /// optimized.
+    pub fn load(r: &mut impl Read) -> TractResult<TypedModel> {
+        let mut buf = vec![];
+        r.read_to_end(&mut buf)?;
+        TypedModel::from_flatbuffer(&buf)
+    }
+
+    /// The model in the versioned flatbuffer format, see `save`.
+    pub fn to_flatbuffer(&self) -> TractResult<Vec<u8>> {
+        let nodes = self.nodes().iter().map(save_node).collect::<TractResult<_>>()?;
+        let labels = self
+            .output_labels
+            .iter()
+            .map(|label| Value::Table(vec![Some(Value::Str(label.clone()))]))
+            .collect();
+        let root = Value::Table(vec![
+            Some(Value::U32(FORMAT_VERSION.0)),
+            Some(Value::U32(FORMAT_VERSION.1)),
+            Some(Value::Tables(nodes)),
+            Some(save_outlets(self.input_outlets()?)),
+            Some(save_outlets(self.output_outlets()?)),
+            Some(Value::Tables(labels)),
+        ]);
+        Ok(finish_with_identifier(&root, FORMAT_IDENTIFIER))
+    }
+
+    /// The model in a buffer written by `to_flatbuffer`, see `load`.
+    pub fn from_flatbuffer(buf: &[u8]) -> TractResult<TypedModel> {
+        if Table::identifier(buf)? != FORMAT_IDENTIFIER {
+            bail!("Not a saved tract model");
+        }
+        let root = Table::root(buf)?;
+        let (major, minor) = (root.scalar::<u32>(0, 0)?, root.scalar::<u32>(1, 0)?);
+        if major != FORMAT_VERSION.0 {
+            bail!(
+                "Model saved in format version {}.{}, this tract reads version {}.x",
+                major,
+                minor,
+                FORMAT_VERSION.0
+            );
+        }
+        let mut model = TypedModel::default();
+        let nodes = root.tables(2)?;
+        for node in &nodes {
+            load_node(&mut model, node)?;
+        }
+        for (id, node) in nodes.iter().enumerate() {
+            for (slot, outlet) in node.tables(2)?.iter().enumerate() {
+                model.add_edge(load_outlet(&model, outlet)?, InletId::new(id, slot))?;
+            }
+        }
+        let inputs = root
+            .tables(3)?
+            .iter()
+            .map(|o| load_outlet(&model, o))
+            .collect::<TractResult<Vec<_>>>()?;
+        model.set_input_outlets(&inputs)?;
+        let outputs = root
+            .tables(4)?
+            .iter()
+            .map(|o| load_outlet(&model, o))
+            .collect::<TractResult<Vec<_>>>()?;
+        model.set_output_outlets(&outputs)?;
+        let labels = root.tables(5)?;
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|l| Ok(l.string(0)?.unwrap_or("").to_string()))
+                .collect::<TractResult<Vec<_>>>()?;
+            model.set_output_labels(labels)?;
+        }
+        Ok(model)
+    }
+}
+
+fn save_node(node: &TypedNode) -> TractResult<Value> {
+    let (op, params) = if node.op_is::<Source>() {
+        ("Source", None)
+    } else if node.op_is::<Const>() {
+        if node.outputs[0].fact.konst.is_none() {
+            bail!("Const {} without a constant output fact", node.name);
+        }
+        ("Const", None)
+    } else if let Some(conv) = node.op_as::<ConvUnary>() {
+        ("ConvUnary", Some(save_conv(conv)?))
+    } else if let Some(key) = unit_op_key(node.op()) {
+        (key, None)
+    } else {
+        bail!("{} ({}) is not covered by the model format", node.name, node.op().name())
+    };
+    let facts = node.outputs.iter().map(|o| save_fact(&o.fact)).collect::<TractResult<_>>()?;
+    Ok(Value::Table(vec![
+        Some(Value::Str(node.name.clone())),
+        Some(Value::Str(op.to_string())),
+        Some(save_outlets(&node.inputs)),
+        Some(Value::Tables(facts)),
+        params,
+    ]))
+}
+
+/// Adds the node, without its inputs: they may come from nodes after it.
+fn load_node(model: &mut TypedModel, node: &Table) -> TractResult<()> {
+    let name = node.string(0)?.unwrap_or("");
+    let facts = node.tables(3)?.iter().map(load_fact).collect::<TractResult<TVec<_>>>()?;
+    let op: Box<Op> = match node.string(1)?.ok_or("Node without op")? {
+        "Source" => Box::new(Source::new()),
+        "Const" => {
+            let value = facts.first().and_then(|f| f.konst.clone());
+            Box::new(Const::new(value.ok_or("Const without a constant output fact")?))
+        }
+        "ConvUnary" => Box::new(load_conv(&node.table(4)?.ok_or("ConvUnary without params")?)?),
+        key => match unit_op(key) {
+            Some(op) => op,
+            None => bail!("Unknown op {} in saved model", key),
+        },
+    };
+    model.add_node(name, op, facts)?;
+    Ok(())
+}
+
+fn save_outlets(outlets: &[OutletId]) -> Value {
+    Value::Tables(
+        outlets
+            .iter()
+            .map(|o| {
+                Value::Table(vec![Some(Value::U32(o.node as u32)), Some(Value::U32(o.slot as u32))])
+            })
+            .collect(),
+    )
+}
+
+fn load_outlet(model: &TypedModel, outlet: &Table) -> TractResult<OutletId> {
+    let node = outlet.scalar::<u32>(0, 0)? as usize;
+    let slot = outlet.scalar::<u32>(1, 0)? as usize;
+    if node >= model.nodes().len() || slot >= model.node(node).outputs.len() {
+        bail!("Saved model refers to missing outlet {}/{}", node, slot);
+    }
+    Ok(OutletId::new(node, slot))
+}
+
+fn save_datum_type(dt: DatumType) -> TractResult<Value> {
+    match DATUM_TYPES.iter().position(|&d| d == dt) {
+        Some(ix) => Ok(Value::U8(ix as u8 + 1)),
+        None => bail!("{:?} is not covered by the model format", dt),
+    }
+}
+
+fn load_datum_type(table: &Table, id: usize) -> TractResult<DatumType> {
+    let code = table.scalar::<u8>(id, 0)?;
+    match (code as usize).checked_sub(1).and_then(|ix| DATUM_TYPES.get(ix)) {
+        Some(&dt) => Ok(dt),
+        None => bail!("Unknown datum type {} in saved model", code),
+    }
+}
+
+/// Saves an enum as its position in `values`.
+fn save_enum<T: PartialEq>(values: &[T], value: &T) -> Value {
+    Value::U8(values.iter().position(|v| v == value).unwrap() as u8)
+}
+
+fn load_enum<T: Copy + fmt::Debug>(values: &[T], table: &Table, id: usize) -> TractResult<T> {
+    let code = table.scalar::<u8>(id, 0)?;
+    match values.get(code as usize) {
+        Some(&value) => Ok(value),
+        None => bail!("Unknown value {} in saved model, among {:?}", code, values),
+    }
+}
+
+fn save_fact(fact: &TypedTensorInfo) -> TractResult<Value> {
+    Ok(Value::Table(vec![
+        Some(save_datum_type(fact.datum_type)?),
+        Some(save_dims(&fact.shape.to_tvec())?),
+        fact.konst.as_ref().map(|k| save_tensor(k)).transpose()?,
+    ]))
+}
+
+fn load_fact(fact: &Table) -> TractResult<TypedTensorInfo> {
+    Ok(TypedTensorInfo {
+        datum_type: load_datum_type(fact, 0)?,
+        shape: load_dims(fact, 1)?.into_iter().collect(),
+        konst: fact.table(2)?.map(|k| load_tensor(&k)).transpose()?.map(Arc::new),
+    })
+}
+
+/// Dims are saved as `value + stream * S`, the only form shapes of
+/// streaming models take.
+fn save_dims(dims: &[TDim]) -> TractResult<Value> {
+    let dims = dims
+        .iter()
+        .map(|dim| {
+            let value = dim.eval(0);
+            let stream = dim.eval(1).and_then(|one| Some(one - value?));
+            match (value, stream) {
+                (Some(value), Some(stream)) if dim.eval(1000) == Some(value + 1000 * stream) => {
+                    Ok(Value::Table(vec![Some(Value::I32(value)), Some(Value::I32(stream))]))
+                }
+                _ => bail!("Dimension {:?} is not covered by the model format", dim),
+            }
+        })
+        .collect::<TractResult<_>>()?;
+    Ok(Value::Tables(dims))
+}
+
+fn load_dims(table: &Table, id: usize) -> TractResult<TVec<TDim>> {
+    table
+        .tables(id)?
+        .iter()
+        .map(|dim| {
+            let value = TDim::from(dim.scalar::<i32>(0, 0)?);
+            Ok(match dim.scalar::<i32>(1, 0)? {
+                0 => value,
+                stream => TDim::s() * TDim::from(stream) + value,
+            })
+        })
+        .collect()
+}
+
+fn save_usizes(values: &[usize]) -> Value {
+    Value::I64s(values.iter().map(|&v| v as i64).collect())
+}
+
+fn load_usizes(table: &Table, id: usize) -> TractResult<TVec<usize>> {
+    table
+        .vector::<i64>(id)?
+        .into_iter()
+        .map(|v| if v < 0 { bail!("Negative size {} in saved model", v) } else { Ok(v as usize) })
+        .collect()
+}
+
+/// Calls `$f::<T>` for the datum types of the tensors the format covers.
+macro_rules! dispatch_saved {
+    ($f:ident($dt:expr)($($args:expr),*)) => {
+        match $dt {
+            DatumType::U8 => $f::<u8>($($args),*),
+            DatumType::U16 => $f::<u16>($($args),*),
+            DatumType::I8 => $f::<i8>($($args),*),
+            DatumType::I16 => $f::<i16>($($args),*),
+            DatumType::I32 => $f::<i32>($($args),*),
+            DatumType::I64 => $f::<i64>($($args),*),
+            DatumType::F32 => $f::<f32>($($args),*),
+            DatumType::F64 => $f::<f64>($($args),*),
+            dt => bail!("{:?} tensors are not covered by the model format", dt),
+        }
+    };
+}
+
+fn tensor_data<T: Datum + Scalar>(tensor: &Tensor) -> TractResult<Vec<u8>> {
+    let mut data = Vec::with_capacity(tensor.shape().iter().product::<usize>() * T::SIZE);
+    for &x in tensor.as_slice::<T>()? {
+        x.to_le(&mut data);
+    }
+    Ok(data)
+}
+
+fn tensor_from_data<T: Datum + Scalar>(shape: &[usize], data: &[u8]) -> TractResult<Tensor> {
+    let len = shape.iter().try_fold(1usize, |len, &d| len.checked_mul(d));
+    if len.and_then(|len| len.checked_mul(T::SIZE)) != Some(data.len()) {
+        bail!("Saved tensor of shape {:?} with {} bytes of data", shape, data.len());
+    }
+    let items: Vec<T> = data.chunks(T::SIZE).map(T::from_le).collect();
+    Ok(ArrayD::from_shape_vec(shape, items)?.into())
+}
+
+fn save_tensor(tensor: &Tensor) -> TractResult<Value> {
+    let data = dispatch_saved!(tensor_data(tensor.datum_type())(tensor))?;
+    Ok(Value::Table(vec![
+        Some(save_datum_type(tensor.datum_type())?),
+        Some(save_usizes(tensor.shape())),
+        Some(Value::Bytes(data)),
+    ]))
+}
+
+fn load_tensor(tensor: &Table) -> TractResult<Tensor> {
+    let shape = load_usizes(tensor, 1)?;
+    let data = tensor.bytes(2)?;
+    dispatch_saved!(tensor_from_data(load_datum_type(tensor, 0)?)(&shape, data))
+}
+
+fn save_conv(conv: &ConvUnary) -> TractResult<Value> {
+    if conv.summary.is_some() || conv.golden.is_some() {
+        bail!("Channel summaries and golden outputs are not covered by the model format");
+    }
+    let (padding, before, after) = match &conv.padding {
+        PaddingSpec::Explicit(before, after) => {
+            (0, Some(save_usizes(before)), Some(save_usizes(after)))
+        }
+        PaddingSpec::Valid => (1, None, None),
+        PaddingSpec::SameUpper => (2, None, None),
+        PaddingSpec::SameLower => (3, None, None),
+    };
+    Ok(Value::Table(vec![
+        Some(save_enum(&DATA_FORMATS, &conv.data_format)),
+        Some(save_enum(&KERNEL_FORMATS, &conv.kernel_fmt)),
+        Some(Value::U8(padding)),
+        before,
+        after,
+        Some(save_usizes(&conv.dilations)),
+        Some(save_usizes(&conv.strides)),
+        Some(save_tensor(&conv.kernel)?),
+        conv.bias.as_ref().map(save_tensor).transpose()?,
+        Some(save_dims(&conv.full_input_shape)?),
+        Some(save_dims(&conv.full_output_shape)?),
+        Some(Value::U32(conv.group as u32)),
+        Some(save_enum(&PAD_MODES, &conv.pad_mode)),
+        Some(Value::U8(conv.f64_output as u8)),
+        Some(Value::U8(conv.token_output as u8)),
+        Some(save_usizes(&conv.independent_shape)),
+        Some(save_conv_options(&conv.options)),
+    ]))
+}
+
+fn load_conv(conv: &Table) -> TractResult<ConvUnary> {
+    let padding = match conv.scalar::<u8>(2, 0)? {
+        0 => PaddingSpec::Explicit(load_usizes(conv, 3)?, load_usizes(conv, 4)?),
+        1 => PaddingSpec::Valid,
+        2 => PaddingSpec::SameUpper,
+        3 => PaddingSpec::SameLower,
+        code => bail!("Unknown padding {} in saved model", code),
+    };
+    let options = match conv.table(16)? {
+        Some(options) => load_conv_options(&options)?,
+        None => ConvOptions::default(),
+    };
+    Ok(ConvUnary {
+        data_format: load_enum(&DATA_FORMATS, conv, 0)?,
+        kernel_fmt: load_enum(&KERNEL_FORMATS, conv, 1)?,
+        padding,
+        dilations: load_usizes(conv, 5)?,
+        strides: load_usizes(conv, 6)?,
+        kernel: load_tensor(&conv.table(7)?.ok_or("ConvUnary without kernel")?)?,
+        bias: conv.table(8)?.map(|b| load_tensor(&b)).transpose()?,
+        full_input_shape: load_dims(conv, 9)?,
+        full_output_shape: load_dims(conv, 10)?,
+        group: conv.scalar::<u32>(11, 1)? as usize,
+        summary: None,
+        pad_mode: load_enum(&PAD_MODES, conv, 12)?,
+        options,
+        f64_output: conv.scalar::<u8>(13, 0)? != 0,
+        token_output: conv.scalar::<u8>(14, 0)? != 0,
+        independent_shape: load_usizes(conv, 15)?,
+        golden: None,
+        kernel_cache: KernelCache::default(),
+        timer: PhaseTimer::default(),
+    })
+}
+
+fn save_conv_options(options: &ConvOptions) -> Value {
+    let (strategy, channel_block) = match options.strategy {
+        ConvStrategy::Auto => (0, 0),
+        ConvStrategy::ForceGemm => (1, 0),
+        ConvStrategy::ForceWinograd => (2, 0),
+        ConvStrategy::ForceDepthwise => (3, 0),
+        ConvStrategy::ForceDirect => (4, 0),
+        ConvStrategy::ForceChannelBlocked(block) => (5, block),
+    };
+    let (packing, packing_block) = match options.kernel_packing {
+        KernelPacking::Product => (0, 0),
+        KernelPacking::ChannelBlocks(block) => (1, block),
+    };
+    Value::Table(vec![
+        Some(Value::U8(strategy)),
+        Some(Value::U32(channel_block as u32)),
+        Some(Value::U8(options.deterministic as u8)),
+        Some(Value::U8(packing)),
+        Some(Value::U32(packing_block as u32)),
+        options.max_scratch_bytes.map(|bytes| Value::I64(bytes as i64)),
+        Some(Value::U8(options.packed_lowering as u8)),
+        options.weight_clamp.map(|(min, max)| Value::F32s(vec![min, max])),
+    ])
+}
+
+fn load_conv_options(options: &Table) -> TractResult<ConvOptions> {
+    let channel_block = options.scalar::<u32>(1, 0)? as usize;
+    let strategy = match options.scalar::<u8>(0, 0)? {
+        0 => ConvStrategy::Auto,
+        1 => ConvStrategy::ForceGemm,
+        2 => ConvStrategy::ForceWinograd,
+        3 => ConvStrategy::ForceDepthwise,
+        4 => ConvStrategy::ForceDirect,
+        5 => ConvStrategy::ForceChannelBlocked(channel_block),
+        code => bail!("Unknown conv strategy {} in saved model", code),
+    };
+    let kernel_packing = match options.scalar::<u8>(3, 0)? {
+        0 => KernelPacking::Product,
+        1 => KernelPacking::ChannelBlocks(options.scalar::<u32>(4, 0)? as usize),
+        code => bail!("Unknown kernel packing {} in saved model", code),
+    };
+    let weight_clamp = match &*options.vector::<f32>(7)? {
+        [] => None,
+        &[min, max] => Some((min, max)),
+        other => bail!("Weight clamp of {} values in saved model", other.len()),
+    };
+    Ok(ConvOptions {
+        strategy,
+        deterministic: options.scalar::<u8>(2, 0)? != 0,
+        kernel_packing,
+        max_scratch_bytes: match options.scalar::<i64>(5, -1)? {
+            -1 => None,
+            bytes => Some(bytes as usize),
+        },
+        packed_lowering: options.scalar::<u8>(6, 0)? != 0,
+        weight_clamp,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flat::builder::finish;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use crate::ops::nn::Relu;
+    use ndarray::*;
+
+    fn f32_fact(shape: &[usize]) -> TypedTensorInfo {
+        TypedTensorInfo { datum_type: DatumType::F32, shape: shape.into(), konst: None }
+    }
+
+    fn conv_relu() -> (TypedModel, Tensor) {
+        let input = Array4::from_shape_fn((1, 3, 6, 5), |(_, c, y, x)| {
+            ((c * 7 + y * 5 + x * 3) % 11) as f32 - 5.0
+        });
+        let kernel = Array4::from_shape_fn((4, 3, 3, 3), |(o, c, y, x)| {
+            ((o * 13 + c * 5 + y * 3 + x) % 7) as f32 * 0.25 - 0.75
+        });
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            1,
+        )
+        .with_strategy(ConvStrategy::ForceGemm);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(arr1(&[0.5f32, -1.0, 0.0, 2.0]).into());
+        let input = Tensor::from(input);
+        let output = op.eval(tvec!(input.clone().into())).unwrap().remove(0);
+        let fact = f32_fact(output.shape());
+        let mut model = TypedModel::default();
+        model.add_source("input", f32_fact(input.shape())).unwrap();
+        model.chain("conv", op, tvec!(fact.clone())).unwrap();
+        model.chain("relu", Relu::default(), tvec!(fact)).unwrap();
+        model.set_output_labels(vec!["probs"]).unwrap();
+        (model, input)
+    }
+
+    fn run(model: TypedModel, input: &Tensor) -> Arc<Tensor> {
+        SimplePlan::new(model).unwrap().run(tvec!(input.clone())).unwrap().remove(0)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (model, input) = conv_relu();
+        let mut buf = vec![];
+        model.save(&mut buf).unwrap();
+        let loaded = TypedModel::load(&mut &*buf).unwrap();
+        assert_eq!(loaded.nodes().len(), 3);
+        assert_eq!(loaded.output_names(), vec!["probs"]);
+        let (conv, saved) = (loaded.node(1), model.node(1).op_as::<ConvUnary>().unwrap());
+        let conv = conv.op_as::<ConvUnary>().unwrap();
+        assert_eq!(conv.kernel, saved.kernel);
+        assert_eq!(conv.bias, saved.bias);
+        assert_eq!(conv.padding, saved.padding);
+        assert_eq!(conv.options, saved.options);
+        let expected = run(model.clone(), &input);
+        assert_eq!(run(loaded.clone(), &input), expected);
+        assert_eq!(
+            run(loaded.into_optimized().unwrap(), &input),
+            run(model.into_optimized().unwrap(), &input)
+        );
+    }
+
+    #[test]
+    fn streaming_shapes() {
+        let mut model = TypedModel::default();
+        let shape = tvec!(2.to_dim(), TDim::s() + 3, 4.to_dim());
+        let fact = TypedTensorInfo {
+            datum_type: DatumType::F32,
+            shape: shape.iter().cloned().collect(),
+            konst: None,
+        };
+        model.add_source("input", fact.clone()).unwrap();
+        model.chain("tanh", crate::ops::math::Tanh::default(), tvec!(fact)).unwrap();
+        let loaded = TypedModel::from_flatbuffer(&model.to_flatbuffer().unwrap()).unwrap();
+        assert_eq!(loaded.output_fact(0).unwrap().shape.to_tvec(), shape);
+        assert!(loaded.node(1).op_is::<crate::ops::math::Tanh>());
+    }
+
+    fn empty_model(major: u32, minor: u32) -> Vec<u8> {
+        let root = Value::Table(vec![
+            Some(Value::U32(major)),
+            Some(Value::U32(minor)),
+            Some(Value::Tables(vec![])),
+            None,
+            None,
+            None,
+            Some(Value::Str("a field from the future".to_string())),
+        ]);
+        finish_with_identifier(&root, FORMAT_IDENTIFIER)
+    }
+
+    #[test]
+    fn refuses_other_major_versions() {
+        let err = TypedModel::from_flatbuffer(&empty_model(FORMAT_VERSION.0 + 1, 0)).unwrap_err();
+        let version = format!("format version {}.0", FORMAT_VERSION.0 + 1);
+        assert!(err.to_string().contains(&version), "{}", err);
+    }
+
+    #[test]
+    fn accepts_other_minor_versions() {
+        let buf = empty_model(FORMAT_VERSION.0, FORMAT_VERSION.1 + 1);
+        assert_eq!(TypedModel::from_flatbuffer(&buf).unwrap().nodes().len(), 0);
+    }
+
+    #[test]
+    fn refuses_other_buffers() {
+        let (model, _) = conv_relu();
+        let buf = model.to_flatbuffer().unwrap();
+        assert!(TypedModel::from_flatbuffer(&buf[..buf.len() / 2]).is_err());
+        let root = Value::Table(vec![Some(Value::U32(FORMAT_VERSION.0))]);
+        assert!(TypedModel::from_flatbuffer(&finish(&root)).is_err());
+    }
+
+    #[test]
+    fn refuses_uncovered_ops() {
+        let mut model = TypedModel::default();
+        let fact = f32_fact(&[2]);
+        model.add_source("input", fact.clone()).unwrap();
+        model.chain("clip", crate::ops::math::Clip::new(0.0, 1.0), tvec!(fact)).unwrap();
+        let err = model.to_flatbuffer().unwrap_err();
+        assert!(err.to_string().contains("clip (Clip) is not covered"), "{}", err);
+    }
+}
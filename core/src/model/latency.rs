@@ -0,0 +1,180 @@
+//! Latency estimates, to compare model variants without running them.
+//!
+//! Nodes doing multiply-accumulates, as reported by `Op::cost`, take their
+//! count over the throughput of their operator. Other nodes, element-wise
+//! ops and data movement, are bound by memory: they take the bytes of their
+//! inputs and outputs over the memory bandwidth.
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::internal::*;
+use crate::plan::SimplePlan;
+
+/// Timed runs per calibration benchmark, the fastest one counts.
+const RUNS: usize = 3;
+
+/// Throughputs of a CPU, for `TypedModel::estimated_millis`.
+#[derive(Debug, Clone)]
+pub struct Throughput {
+    /// Multiply-accumulates per second of the operators not in `op_fmas`.
+    pub fmas_per_sec: f64,
+    /// Multiply-accumulates per second of operators, by name.
+    pub op_fmas: HashMap<String, f64>,
+    /// Bytes read and written per second by the memory bound operators.
+    pub bytes_per_sec: f64,
+}
+
+impl Throughput {
+    /// Measures this CPU throughputs on a convolution and on a relu, both a
+    /// few milliseconds long.
+    ///
+    /// The convolution throughput is kept for all the operators: override
+    /// the ones known to be faster or slower in `op_fmas`.
+    pub fn calibrate() -> TractResult<Throughput> {
+        let conv = conv_model(16, 32)?;
+        let fmas = node_fmas(&conv)?.iter().sum::<u64>();
+        let conv_secs = best_run(&conv)?.as_secs_f64();
+        let relu = relu_model(1024 * 1024)?;
+        let bytes = node_bytes(&relu)?.iter().sum::<u64>();
+        let relu_secs = best_run(&relu)?.as_secs_f64();
+        Ok(Throughput {
+            fmas_per_sec: fmas as f64 / conv_secs,
+            op_fmas: HashMap::new(),
+            bytes_per_sec: bytes as f64 / relu_secs,
+        })
+    }
+
+    fn fmas_per_sec(&self, op: &str) -> f64 {
+        self.op_fmas.get(op).cloned().unwrap_or(self.fmas_per_sec)
+    }
+}
+
+/// Multiply-accumulate count of each node, failing on symbolic counts.
+fn node_fmas(model: &TypedModel) -> TractResult<Vec<u64>> {
+    let mut fmas = vec![0; model.nodes().len()];
+    for (ix, node) in model.nodes().iter().enumerate() {
+        let inputs = model.node_input_facts(ix)?;
+        for (cost, n) in node.op().cost(&*inputs)? {
+            if let Cost::FMA(_) = cost {
+                fmas[ix] += n.to_integer().map_err(|_| {
+                    format!("Can not estimate the latency of {}, costing {:?}", node, n)
+                })? as u64;
+            }
+        }
+    }
+    Ok(fmas)
+}
+
+/// Bytes each node reads and writes. Sources and constants, without
+/// inputs, move nothing.
+fn node_bytes(model: &TypedModel) -> TractResult<Vec<u64>> {
+    let mut bytes = vec![0; model.nodes().len()];
+    for (ix, node) in model.nodes().iter().enumerate() {
+        if node.inputs.is_empty() {
+            continue;
+        }
+        let inputs = model.node_input_facts(ix)?;
+        let outputs = model.node_output_facts(ix)?;
+        for fact in inputs.iter().chain(outputs.iter()) {
+            let shape = fact.shape.as_finite().ok_or_else(|| {
+                format!("Can not estimate the latency of {}, shaped {:?}", node, fact.shape)
+            })?;
+            bytes[ix] += (shape.iter().product::<usize>() * fact.datum_type.size_of()) as u64;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Estimated milliseconds each node takes on a CPU of this throughput.
+pub fn node_millis(model: &TypedModel, throughput: &Throughput) -> TractResult<Vec<f64>> {
+    let fmas = node_fmas(model)?;
+    let bytes = node_bytes(model)?;
+    Ok(model
+        .nodes()
+        .iter()
+        .map(|node| {
+            let secs = if fmas[node.id] > 0 {
+                fmas[node.id] as f64 / throughput.fmas_per_sec(&node.op().name())
+            } else {
+                bytes[node.id] as f64 / throughput.bytes_per_sec
+            };
+            secs * 1000.0
+        })
+        .collect())
+}
+
+fn best_run(model: &TypedModel) -> TractResult<Duration> {
+    let plan = SimplePlan::new(model)?;
+    let shape = model.input_fact(0)?.shape.as_finite().unwrap().to_vec();
+    let input = Tensor::from(ndarray::ArrayD::<f32>::zeros(shape));
+    plan.run(tvec!(input.clone()))?;
+    let mut best = None;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        plan.run(tvec!(input.clone()))?;
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |b: Duration| b.min(elapsed)));
+    }
+    Ok(best.unwrap())
+}
+
+fn f32_fact(shape: &[usize]) -> TypedTensorInfo {
+    TypedTensorInfo { datum_type: f32::datum_type(), shape: ShapeInfo::from(shape), konst: None }
+}
+
+/// A 3x3 convolution of `channels` in and out, on a square image of `side`.
+fn conv_model(channels: usize, side: usize) -> TractResult<TypedModel> {
+    use crate::ops::cnn::Conv;
+    let input = f32_fact(&[1, channels, side, side]);
+    let kernel = ndarray::Array4::from_elem((channels, channels, 3, 3), 0.5f32).into_tensor();
+    let conv = Conv::default().to_unary(&[input.clone(), kernel.into()])?.unwrap();
+    let output = TypedTensorInfo {
+        datum_type: f32::datum_type(),
+        shape: conv.output_shape().into_iter().collect(),
+        konst: None,
+    };
+    let mut model = TypedModel::default();
+    model.add_source("input", input)?;
+    model.chain("conv", conv, tvec!(output))?;
+    Ok(model)
+}
+
+fn relu_model(len: usize) -> TractResult<TypedModel> {
+    let mut model = TypedModel::default();
+    model.add_source("input", f32_fact(&[len]))?;
+    model.chain("relu", crate::ops::nn::Relu::default(), tvec!(f32_fact(&[len])))?;
+    Ok(model)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relu_is_memory_bound() {
+        let model = relu_model(1000).unwrap();
+        let throughput =
+            Throughput { fmas_per_sec: 1e9, op_fmas: HashMap::new(), bytes_per_sec: 1e9 };
+        // 4000 bytes read, 4000 written
+        let millis = model.estimated_millis(&throughput).unwrap();
+        assert!((millis - 8e-3).abs() < 1e-9, "{}", millis);
+    }
+
+    #[test]
+    fn estimates_follow_measured_latencies() {
+        let throughput = Throughput::calibrate().unwrap();
+        let models = vec![
+            relu_model(2048).unwrap(),
+            conv_model(8, 16).unwrap(),
+            conv_model(32, 16).unwrap(),
+            conv_model(32, 32).unwrap(),
+        ];
+        for pair in models.windows(2) {
+            let estimated: Vec<f64> =
+                pair.iter().map(|m| m.estimated_millis(&throughput).unwrap()).collect();
+            let measured: Vec<Duration> = pair.iter().map(|m| best_run(m).unwrap()).collect();
+            assert!(estimated[0] < estimated[1], "{:?}", estimated);
+            assert!(measured[0] < measured[1], "{:?}", measured);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::internal::*;
+
+/// A dimension of a model input or output, as declared.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IoDim {
+    /// Known dimension.
+    Fixed(usize),
+    /// Expression of the streaming dimension `S`.
+    Symbolic(TDim),
+    /// Not determined yet: inference or the caller must resolve it.
+    Unknown,
+}
+
+/// Name, element type and shape of a model input or output, as far as they
+/// are known without running the model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IoMetadata {
+    pub name: String,
+    /// `None` if the element type is not determined.
+    pub datum_type: Option<DatumType>,
+    /// `None` if even the rank is not determined.
+    pub shape: Option<TVec<IoDim>>,
+}
+
+impl IoMetadata {
+    fn new(name: String, fact: TensorFact) -> IoMetadata {
+        let shape = if fact.shape.is_open() {
+            None
+        } else {
+            Some(
+                fact.shape
+                    .dims()
+                    .map(|d| match d.concretize() {
+                        Some(d) => match d.as_const() {
+                            Some(d) => IoDim::Fixed(d as usize),
+                            None => IoDim::Symbolic(d),
+                        },
+                        None => IoDim::Unknown,
+                    })
+                    .collect(),
+            )
+        };
+        IoMetadata { name, datum_type: fact.datum_type.concretize(), shape }
+    }
+
+    /// The shape, if every dimension is fixed.
+    pub fn concrete_shape(&self) -> Option<TVec<usize>> {
+        self.shape
+            .as_ref()?
+            .iter()
+            .map(|d| if let IoDim::Fixed(d) = d { Some(*d) } else { None })
+            .collect()
+    }
+}
+
+impl<TI: TensorInfo> Model<TI> {
+    /// Metadata of the model inputs, in inputs order.
+    pub fn input_metadata(&self) -> TractResult<Vec<IoMetadata>> {
+        self.input_outlets()?
+            .iter()
+            .zip(self.input_names())
+            .map(|(&outlet, name)| {
+                Ok(IoMetadata::new(name.to_string(), self.outlet_fact(outlet)?.to_tensor_fact()))
+            })
+            .collect()
+    }
+
+    /// Metadata of the model outputs, in outputs order, named like
+    /// `output_names`.
+    pub fn output_metadata(&self) -> TractResult<Vec<IoMetadata>> {
+        self.output_outlets()?
+            .iter()
+            .zip(self.output_names())
+            .map(|(&outlet, name)| {
+                Ok(IoMetadata::new(name, self.outlet_fact(outlet)?.to_tensor_fact()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inference_model_metadata() {
+        let mut model = InferenceModel::default();
+        let fact = TensorFact::dt_shape(DatumType::F32, shapefact!(1, S, _));
+        model.add_source("input", fact).unwrap();
+        model.chain_default("relu", crate::ops::nn::Relu::default()).unwrap();
+        let input = &model.input_metadata().unwrap()[0];
+        assert_eq!(input.name, "input");
+        assert_eq!(input.datum_type, Some(DatumType::F32));
+        assert_eq!(
+            input.shape,
+            Some(tvec!(IoDim::Fixed(1), IoDim::Symbolic(TDim::s()), IoDim::Unknown))
+        );
+        assert_eq!(input.concrete_shape(), None);
+        let output = &model.output_metadata().unwrap()[0];
+        assert_eq!(output.name, "relu");
+        assert_eq!(output.datum_type, None);
+        assert_eq!(output.shape, None);
+    }
+
+    #[test]
+    fn typed_model_metadata() {
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::I32, tvec!(2usize, 3))).unwrap();
+        model.chain_default("relu", crate::ops::nn::Relu::default()).unwrap();
+        let model = model.into_typed().unwrap();
+        let output = &model.output_metadata().unwrap()[0];
+        assert_eq!(output.datum_type, Some(DatumType::I32));
+        assert_eq!(output.concrete_shape(), Some(tvec!(2, 3)));
+    }
+}
@@ -44,20 +44,33 @@ use std::str;
 
 pub(crate) mod compact;
 mod dsl;
+mod format;
+pub mod latency;
+mod metadata;
 mod model;
 mod node;
 pub mod order;
 mod patch;
+pub mod schedule;
 mod tensor_info;
 
 pub use self::dsl::*;
+pub use self::format::{FORMAT_IDENTIFIER, FORMAT_VERSION};
+pub use self::latency::Throughput;
+pub use self::metadata::{IoDim, IoMetadata};
 pub use self::model::*;
 pub use self::node::*;
 pub use self::order::eval_order;
 pub use self::patch::ModelPatch;
+pub use self::schedule::ReadyQueue;
 pub use self::tensor_info::*;
 pub use crate::analyser::types::TensorFact;
+pub use crate::optim::FusionEvent;
 
+use crate::analyser::types::ShapeFact;
+use crate::datum::DatumType;
+use crate::dim::{TDim, ToDim};
+use crate::ops::Cost;
 use crate::TractResult;
 
 /// A model with partially types and shapes, as produced by parsing ONNX or
@@ -82,6 +95,37 @@ pub type NormalizedNode = Node<NormalizedTensorInfo>;
 pub type NormalizedModelPatch = ModelPatch<NormalizedTensorInfo>;
 
 impl InferenceModel {
+    /// Constrain the `ix`-th input with an additional fact, like a shape or
+    /// a datum type.
+    ///
+    /// Unlike `set_input_fact`, what the model already declares is kept and
+    /// must be compatible with the new fact.
+    pub fn constrain_input_fact(&mut self, ix: usize, fact: TensorFact) -> TractResult<()> {
+        use crate::analyser::types::Fact;
+        let current = self.input_fact(ix)?;
+        let unified = current.unify(&fact).map_err(|e| {
+            format!(
+                "Input {} declared as {:?} can not be constrained to {:?}: {}",
+                ix, current, fact, e
+            )
+        })?;
+        self.set_input_fact(ix, unified)
+    }
+
+    /// Constrain the shape of the `ix`-th input.
+    pub fn constrain_input_shape<S: Into<ShapeFact>>(
+        &mut self,
+        ix: usize,
+        shape: S,
+    ) -> TractResult<()> {
+        self.constrain_input_fact(ix, TensorFact::shape(shape))
+    }
+
+    /// Constrain the datum type of the `ix`-th input.
+    pub fn constrain_input_datum_type(&mut self, ix: usize, dt: DatumType) -> TractResult<()> {
+        self.constrain_input_fact(ix, TensorFact::dt(dt))
+    }
+
     /// Analyse one node of the graph.
     pub fn analyse_one(&mut self, id: usize) -> TractResult<()> {
         crate::analyser::Analyser::new(self)?.analyse_one(id)?;
@@ -115,6 +159,56 @@ impl InferenceModel {
             .collect())
     }
 
+    /// Substitute values for the symbols of the shapes, then analyse and
+    /// optimize again.
+    ///
+    /// `S`, the streaming dimension, is the symbol shapes can hold. Once its
+    /// value is known, ops get concrete shapes and codegen can pick the
+    /// implementations needing them, like direct or depthwise convolutions.
+    /// Fails if a value makes a dimension of the graph negative.
+    pub fn concretize(&self, values: &HashMap<char, i32>) -> TractResult<TypedModel> {
+        use crate::analyser::types::GenericFact;
+        use crate::tensor::IntoArcTensor;
+        let mut model = self.clone();
+        // the dimensions derived from the symbols are checked too
+        model.analyse(false)?;
+        for id in 0..model.nodes().len() {
+            for slot in 0..model.nodes()[id].outputs.len() {
+                let outlet = OutletId::new(id, slot);
+                let mut fact = model.outlet_fact(outlet)?.clone();
+                let dims = fact
+                    .shape
+                    .dims()
+                    .map(|d| match d {
+                        GenericFact::Only(d) => match d.substitute(values) {
+                            d if d.to_integer().map(|d| d < 0).unwrap_or(false) => bail!(
+                                "Concretizing {:?} makes a dimension of {} negative: {:?}",
+                                values,
+                                model.nodes()[id],
+                                d
+                            ),
+                            d => Ok(GenericFact::Only(d)),
+                        },
+                        GenericFact::Any => Ok(GenericFact::Any),
+                    })
+                    .collect::<TractResult<_>>()?;
+                fact.shape = if fact.shape.is_open() {
+                    ShapeFact::open(dims)
+                } else {
+                    ShapeFact::closed(dims)
+                };
+                if let GenericFact::Only(value) = fact.value.clone() {
+                    if value.datum_type() == DatumType::TDim {
+                        let value = value.to_array_view::<TDim>()?.mapv(|d| d.substitute(values));
+                        fact.value = GenericFact::Only(value.into_arc_tensor());
+                    }
+                }
+                model.set_outlet_fact(outlet, fact)?;
+            }
+        }
+        model.into_optimized()
+    }
+
     /// Attempt full analyse and conversion to TypedModel.
     pub fn into_typed(mut self) -> TractResult<TypedModel> {
         self.analyse(false)?;
@@ -134,14 +228,56 @@ impl InferenceModel {
     }
 }
 
+/// Costs of a model, as reported by its operators.
+#[derive(Debug, Clone, Default)]
+pub struct ModelCost {
+    /// Costs of each node reporting some, in evaluation order.
+    pub nodes: Vec<(usize, TVec<(Cost, TDim)>)>,
+    /// Costs summed over the whole model.
+    pub total: HashMap<Cost, TDim>,
+}
+
 impl TypedModel {
+    /// Compute multiply-accumulate and parameter counts, per node and total.
+    ///
+    /// This is a static analysis of the model: nothing is evaluated.
+    pub fn cost(&self) -> TractResult<ModelCost> {
+        let mut cost = ModelCost::default();
+        for i in eval_order(self)? {
+            let inputs = self.node_input_facts(i)?;
+            let node_cost = self.nodes()[i].op().cost(&*inputs)?;
+            if !node_cost.is_empty() {
+                for (c, n) in &node_cost {
+                    *cost.total.entry(*c).or_insert(0.to_dim()) += *n;
+                }
+                cost.nodes.push((i, node_cost));
+            }
+        }
+        Ok(cost)
+    }
+
+    /// Estimated milliseconds a run takes on a CPU of this throughput,
+    /// see `latency`.
+    ///
+    /// This is a static analysis of the model: nothing is evaluated. It is
+    /// meant to rank model variants, not to predict an actual latency.
+    pub fn estimated_millis(&self, throughput: &Throughput) -> TractResult<f64> {
+        Ok(latency::node_millis(self, throughput)?.iter().sum())
+    }
+
     /// Perform declutter pass on the network.
     pub fn declutter(self) -> TractResult<TypedModel> {
+        Ok(self.declutter_reporting()?.0)
+    }
+
+    /// Same as `declutter`, also returning the rewrites done.
+    pub fn declutter_reporting(self) -> TractResult<(TypedModel, Vec<FusionEvent>)> {
         let mut model = self;
+        let mut events = vec![];
         loop {
             let mut done_something = false;
             for p in crate::optim::declutter() {
-                done_something = done_something || p.pass(&mut model)?;
+                done_something = done_something || p.pass_reporting(&mut model, &mut events)?;
                 if cfg!(debug_assertions) {
                     model.check_edges()?;
                 }
@@ -151,16 +287,22 @@ impl TypedModel {
             }
             model = compact::compact(&model)?;
         }
-        Ok(model)
+        Ok((model, events))
     }
 
     /// Translate the graph to optimized operators.
     pub fn codegen(self) -> TractResult<TypedModel> {
+        Ok(self.codegen_reporting()?.0)
+    }
+
+    /// Same as `codegen`, also returning the rewrites done.
+    pub fn codegen_reporting(self) -> TractResult<(TypedModel, Vec<FusionEvent>)> {
         let mut model = self;
+        let mut events = vec![];
         loop {
             let mut done_something = false;
             for p in crate::optim::codegen() {
-                done_something = done_something || p.pass(&mut model)?;
+                done_something = done_something || p.pass_reporting(&mut model, &mut events)?;
                 if cfg!(debug_assertions) {
                     model.check_edges()?;
                 }
@@ -170,7 +312,7 @@ impl TypedModel {
             }
             model = compact::compact(&model)?;
         }
-        Ok(model)
+        Ok((model, events))
     }
 
     /// Attempt to convert the network to a NormalizedModel.
@@ -178,10 +320,34 @@ impl TypedModel {
         compact::compact(&self)
     }
 
+    /// Run the NCHW convolutions in NHWC, the layout the products read and
+    /// write without striding over channels, keeping the model inputs and
+    /// outputs in NCHW.
+    ///
+    /// Each convolution gets a transpose of its input to NHWC and of its
+    /// output back to NCHW. Decluttering then folds the transposes between
+    /// consecutive convolutions, leaving one at each end of a chain. Other
+    /// ops between two convolutions keep their transposes: this pays off on
+    /// models chaining convolutions, so it is not part of `declutter`.
+    pub fn into_nhwc_convs(self) -> TractResult<TypedModel> {
+        use crate::optim::DeclutterPass;
+        let mut model = self.declutter()?;
+        crate::optim::NhwcConvs.pass(&mut model)?;
+        compact::compact(&model)?.declutter()
+    }
+
     /// Declutter as much as possible, then translate to optimized operators.
     pub fn into_optimized(self) -> TractResult<TypedModel> {
-        let model = self.declutter()?.codegen()?;
-        compact::compact(&model)
+        Ok(self.into_optimized_reporting()?.0)
+    }
+
+    /// Same as `into_optimized`, also returning the rewrites done, declutter
+    /// ones first.
+    pub fn into_optimized_reporting(self) -> TractResult<(TypedModel, Vec<FusionEvent>)> {
+        let (model, mut events) = self.declutter_reporting()?;
+        let (model, codegen) = model.codegen_reporting()?;
+        events.extend(codegen);
+        Ok((compact::compact(&model)?, events))
     }
 }
 
@@ -205,4 +371,172 @@ mod test {
         is_sync::<TypedModel>();
         is_sync::<NormalizedModel>();
     }
+
+    fn dynamic_model() -> InferenceModel {
+        use crate::internal::*;
+        let mut model = InferenceModel::default();
+        model.add_source_default("a").unwrap();
+        model.chain_default("add", crate::ops::math::Add::default()).unwrap();
+        model.add_const("b", tensor1(&[1.0f32, 2.0, 3.0])).unwrap();
+        model.add_edge(OutletId::new(2, 0), InletId::new(1, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(1, 0)]).unwrap();
+        model
+    }
+
+    #[test]
+    fn constrained_input_resolves_shapes() {
+        let mut model = dynamic_model();
+        model.constrain_input_shape(0, tvec!(2usize, 3)).unwrap();
+        model.constrain_input_datum_type(0, DatumType::F32).unwrap();
+        let typed = model.into_typed().unwrap();
+        let output = typed.output_fact(0).unwrap();
+        assert_eq!(output.datum_type, DatumType::F32);
+        assert_eq!(output.shape.as_finite().unwrap(), &[2, 3]);
+    }
+
+    #[test]
+    fn conv_cost() {
+        use crate::internal::*;
+        use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+        use crate::ops::nn::DataFormat;
+        let mut model = InferenceModel::default();
+        model
+            .add_source("input", TensorFact::dt_shape(f32::datum_type(), tvec!(1usize, 3, 8, 8)))
+            .unwrap();
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::Valid,
+            None,
+            1,
+        );
+        model.chain_default("conv", conv).unwrap();
+        model
+            .add_const("kernel", Tensor::from(ndarray::Array4::<f32>::zeros((4, 3, 3, 3))))
+            .unwrap();
+        model.add_edge(OutletId::new(2, 0), InletId::new(1, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(1, 0)]).unwrap();
+        // 4 output channels, 6x6 output points, 3x3x3 kernel
+        let macs = (4 * 6 * 6 * 3 * 3 * 3).to_dim();
+        let params = (4 * 3 * 3 * 3).to_dim();
+        for typed in
+            &[model.clone().into_typed().unwrap(), model.into_typed().unwrap().declutter().unwrap()]
+        {
+            let cost = typed.cost().unwrap();
+            assert_eq!(cost.nodes.len(), 1);
+            assert_eq!(cost.total[&Cost::FMA(DatumType::F32)], macs);
+            assert_eq!(cost.total[&Cost::Params(DatumType::F32)], params);
+        }
+    }
+
+    fn streaming_depthwise_model() -> InferenceModel {
+        use crate::internal::*;
+        use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+        use crate::ops::nn::DataFormat;
+        let mut model = InferenceModel::default();
+        let shape = vec![1.to_dim(), 3.to_dim(), TDim::s(), 16.to_dim()];
+        model.add_source("input", TensorFact::dt_shape(f32::datum_type(), shape)).unwrap();
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::Valid,
+            None,
+            3,
+        );
+        model.chain_default("conv", conv).unwrap();
+        let kernel = ndarray::Array4::from_shape_fn((3, 1, 3, 3), |(c, _, y, x)| {
+            (c + y * 3 + x * x) as f32 / 4.0
+        });
+        model.add_const("kernel", kernel).unwrap();
+        model.add_edge(OutletId::new(2, 0), InletId::new(1, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(1, 0)]).unwrap();
+        model
+    }
+
+    #[test]
+    fn concretized_conv_lowers_to_depthwise() {
+        let model = streaming_depthwise_model();
+        let is_depthwise =
+            |model: &TypedModel| model.nodes().iter().any(|n| n.op().name().contains("DepthWise"));
+        assert!(!is_depthwise(&model.clone().into_optimized().unwrap()));
+        let concrete = model.concretize(&hashmap!('S' => 16)).unwrap();
+        assert!(is_depthwise(&concrete));
+        assert_eq!(concrete.output_fact(0).unwrap().shape.as_finite().unwrap(), &[1, 3, 14, 14]);
+    }
+
+    #[test]
+    fn chained_convs_run_in_nhwc() {
+        use crate::internal::*;
+        use crate::ops::array::PermuteAxes;
+        use crate::ops::cnn::{Conv, ConvUnary, KernelFormat, PaddingSpec};
+        use crate::ops::nn::DataFormat;
+        let mut model = InferenceModel::default();
+        let fact = TensorFact::dt_shape(f32::datum_type(), tvec!(1usize, 3, 8, 8));
+        model.add_source("input", fact).unwrap();
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            1,
+        );
+        let first = model.chain_default("first", conv).unwrap();
+        let kernel = ndarray::Array4::from_shape_fn((4, 3, 3, 3), |(o, c, y, x)| {
+            ((o * 27 + c * 9 + y * 3 + x) % 7) as f32 - 3.0
+        });
+        model.plug_const(InletId::new(first, 1), "first-kernel", kernel).unwrap();
+        let second = model
+            .chain_after(
+                OutletId::new(first, 0),
+                "second",
+                Conv::default(),
+                tvec!(TensorFact::default()),
+            )
+            .unwrap();
+        let kernel = ndarray::Array4::from_shape_fn((2, 4, 3, 3), |(o, c, y, x)| {
+            ((o * 36 + c * 9 + y * 3 + x) % 5) as f32 / 2.0
+        });
+        model.plug_const(InletId::new(second, 1), "second-kernel", kernel).unwrap();
+        model.set_output_outlets(&[OutletId::new(second, 0)]).unwrap();
+        let typed = model.into_typed().unwrap();
+
+        let nhwc = typed.clone().into_nhwc_convs().unwrap();
+        let convs: Vec<&ConvUnary> = nhwc.nodes().iter().filter_map(|n| n.op_as()).collect();
+        assert_eq!(convs.len(), 2);
+        assert!(convs.iter().all(|c| c.data_format == DataFormat::NHWC));
+        // one transpose in, one out: the ones between the convs cancel out
+        assert_eq!(nhwc.nodes().iter().filter(|n| n.op_is::<PermuteAxes>()).count(), 2);
+        assert_eq!(nhwc.output_fact(0).unwrap().shape.as_finite().unwrap(), &[1, 2, 6, 6]);
+
+        let input = ndarray::Array4::from_shape_fn((1, 3, 8, 8), |(_, c, y, x)| {
+            ((c * 64 + y * 8 + x) % 11) as f32 - 5.0
+        });
+        let run = |model: &TypedModel| {
+            SimplePlan::new(model).unwrap().run(tvec!(input.clone().into())).unwrap().remove(0)
+        };
+        let expected = run(&typed);
+        assert_close!(*run(&nhwc), *expected);
+        assert_close!(*run(&nhwc.into_optimized().unwrap()), *expected);
+    }
+
+    #[test]
+    fn concretized_shape_invalid() {
+        let model = streaming_depthwise_model();
+        assert!(model.concretize(&hashmap!('S' => 1)).is_err());
+    }
+
+    #[test]
+    fn incompatible_input_constraint() {
+        let mut model = dynamic_model();
+        model.constrain_input_shape(0, tvec!(2usize, 3)).unwrap();
+        assert!(model.constrain_input_shape(0, tvec!(4usize, 3)).is_err());
+        assert!(model.constrain_input_shape(0, tvec!(2usize, 3, 1)).is_err());
+        assert!(model.constrain_input_shape(0, tvec!(2usize, 3)).is_ok());
+    }
 }
@@ -14,11 +14,19 @@ pub struct Model<TI: TensorInfo> {
     pub(crate) inputs: Vec<OutletId>,
     /// model outputs
     pub(crate) outputs: Vec<OutletId>,
+    /// model outputs names, as given by the framework, in outputs order
+    pub(crate) output_labels: Vec<String>,
 }
 
 impl<TI: TensorInfo> Default for Model<TI> {
     fn default() -> Model<TI> {
-        Model { nodes: vec![], nodes_by_name: HashMap::new(), inputs: vec![], outputs: vec![] }
+        Model {
+            nodes: vec![],
+            nodes_by_name: HashMap::new(),
+            inputs: vec![],
+            outputs: vec![],
+            output_labels: vec![],
+        }
     }
 }
 
@@ -82,7 +90,12 @@ impl<TI: TensorInfo> Model<TI> {
         {
             let prec = &mut self.nodes[outlet.node];
             prec.outputs[outlet.slot].successors.push(inlet);
-            self.outputs.retain(|&o| o != outlet);
+            while let Some(ix) = self.outputs.iter().position(|&o| o == outlet) {
+                self.outputs.remove(ix);
+                if ix < self.output_labels.len() {
+                    self.output_labels.remove(ix);
+                }
+            }
         }
         let succ = &mut self.nodes[inlet.node];
         if inlet.slot == succ.inputs.len() {
@@ -124,6 +137,11 @@ impl<TI: TensorInfo> Model<TI> {
         Ok(())
     }
 
+    /// Get model inputs names, the names of their source nodes.
+    pub fn input_names(&self) -> Vec<&str> {
+        self.inputs.iter().map(|o| &*self.nodes[o.node].name).collect()
+    }
+
     /// Get the `ix`-th input tensor type information.
     pub fn input_fact(&self, ix: usize) -> TractResult<&TI> {
         let input = self.input_outlets()?[ix];
@@ -145,9 +163,51 @@ impl<TI: TensorInfo> Model<TI> {
     /// Change model outputs.
     pub fn set_output_outlets(&mut self, outputs: &[OutletId]) -> TractResult<()> {
         self.outputs = outputs.to_vec();
+        self.output_labels.clear();
         Ok(())
     }
 
+    /// Name the model outputs, in outputs order.
+    ///
+    /// Frameworks use this when their output tensors names differ from the
+    /// names of the nodes producing them.
+    pub fn set_output_labels(
+        &mut self,
+        labels: impl IntoIterator<Item = impl Into<String>>,
+    ) -> TractResult<()> {
+        let labels: Vec<String> = labels.into_iter().map(|l| l.into()).collect();
+        if labels.len() != self.outputs.len() {
+            bail!("Got {} labels for {} outputs", labels.len(), self.outputs.len());
+        }
+        self.output_labels = labels;
+        Ok(())
+    }
+
+    /// Get model outputs names.
+    ///
+    /// Outputs are named by their label if one was set, or by the name of
+    /// the node producing them (suffixed by the slot for slots other than 0).
+    pub fn output_names(&self) -> Vec<String> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .map(|(ix, &o)| {
+                self.output_labels.get(ix).cloned().unwrap_or_else(|| self.outlet_name(o))
+            })
+            .collect()
+    }
+
+    /// Default name for an outlet: its node name, suffixed by the slot for
+    /// slots other than 0.
+    pub fn outlet_name(&self, outlet: OutletId) -> String {
+        let name = &self.nodes[outlet.node].name;
+        if outlet.slot == 0 {
+            name.clone()
+        } else {
+            format!("{}:{}", name, outlet.slot)
+        }
+    }
+
     /// Set model outputs by node names.
     pub fn set_output_names(
         &mut self,
@@ -158,6 +218,7 @@ impl<TI: TensorInfo> Model<TI> {
             .map(|s| self.node_by_name(s.as_ref()).map(|n| OutletId::new(n.id, 0)))
             .collect::<TractResult<_>>()?;
         self.outputs = ids;
+        self.output_labels.clear();
         Ok(())
     }
 
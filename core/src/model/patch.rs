@@ -13,6 +13,8 @@ pub struct ModelPatch<TI: TensorInfo> {
     pub model: Model<TI>,
     incoming: HashMap<OutletId, OutletId>,
     shunt_outlet_by: HashMap<OutletId, OutletId>,
+    /// What the patch does, reported by the optimizer, see `with_label`.
+    label: Option<String>,
 }
 
 impl<TI: TensorInfo> Default for ModelPatch<TI> {
@@ -21,6 +23,7 @@ impl<TI: TensorInfo> Default for ModelPatch<TI> {
             model: Model::default(),
             incoming: HashMap::new(),
             shunt_outlet_by: HashMap::new(),
+            label: None,
         }
     }
 }
@@ -39,6 +42,17 @@ impl<TI: TensorInfo> DerefMut for ModelPatch<TI> {
 }
 
 impl<TI: TensorInfo> ModelPatch<TI> {
+    /// Describe the rewrite: the optimizer reports labelled patches as
+    /// `FusionEvent`s.
+    pub fn with_label(mut self, label: impl Into<String>) -> ModelPatch<TI> {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_ref().map(|s| &**s)
+    }
+
     /// Draw a tap from a preexisting node.
     ///
     /// returns an OutletId usable in the little "patch" model
@@ -57,6 +71,11 @@ impl<TI: TensorInfo> ModelPatch<TI> {
         Ok(())
     }
 
+    /// The patch outlet replacing `outlet` of the target model, if any.
+    pub fn shunted_by(&self, outlet: OutletId) -> Option<OutletId> {
+        self.shunt_outlet_by.get(&outlet).cloned()
+    }
+
     /// Convenience method creating a patch that replace a single operation.
     pub fn replace_single_op<O: Into<Box<Op>>>(
         patched_model: &Model<TI>,
@@ -89,7 +108,10 @@ impl<TI: TensorInfo> ModelPatch<TI> {
 
     /// Apply all changes in the patch to the target model.
     pub fn apply(self, target: &mut Model<TI>) -> TractResult<()> {
-        let ModelPatch { model: patch, incoming: mut mapping, shunt_outlet_by } = self;
+        let ModelPatch { model: patch, incoming: mut mapping, shunt_outlet_by, .. } = self;
+        // tapping a model output must not demote it
+        let outputs = target.outputs.clone();
+        let output_labels = target.output_labels.clone();
         for node in patch.nodes {
             if node.op_is::<crate::ops::source::Source>() {
                 continue;
@@ -105,6 +127,8 @@ impl<TI: TensorInfo> ModelPatch<TI> {
                 mapping.insert(OutletId::new(id, ix), OutletId::new(added_node_id, ix));
             }
         }
+        target.outputs = outputs;
+        target.output_labels = output_labels;
         for (outlet, by) in shunt_outlet_by {
             let fixed_by = mapping[&by];
             let succs = target.nodes()[outlet.node].outputs[outlet.slot].successors.clone();
@@ -0,0 +1,230 @@
+//! Critical path priorities for executors running several nodes at once.
+//!
+//! When more nodes are ready than there are workers, running them in
+//! evaluation order can leave the heaviest chain of the graph for last, and
+//! the run waits for it. A `ReadyQueue` hands ready nodes out heaviest
+//! critical path first instead.
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::internal::*;
+
+/// Multiply-accumulate count of each node, as reported by `Op::cost`.
+///
+/// Nodes with no cost, or a symbolic one, count for 0.
+pub fn node_flops(model: &TypedModel) -> TractResult<Vec<u64>> {
+    let mut flops = vec![0; model.nodes().len()];
+    for (ix, node) in model.nodes().iter().enumerate() {
+        let inputs = model.node_input_facts(ix)?;
+        for (cost, n) in node.op().cost(&*inputs)? {
+            if let Cost::FMA(_) = cost {
+                flops[ix] += n.to_integer().map(|n| n as u64).unwrap_or(0);
+            }
+        }
+    }
+    Ok(flops)
+}
+
+/// Multiply-accumulates on the heaviest path from each node to an output,
+/// the node itself included.
+pub fn critical_path_priorities(model: &TypedModel) -> TractResult<Vec<u64>> {
+    let flops = node_flops(model)?;
+    let mut priorities = flops.clone();
+    for &node in model.eval_order()?.iter().rev() {
+        let tail = model.nodes()[node]
+            .outputs
+            .iter()
+            .flat_map(|o| o.successors.iter())
+            .map(|succ| priorities[succ.node])
+            .max()
+            .unwrap_or(0);
+        priorities[node] = flops[node] + tail;
+    }
+    Ok(priorities)
+}
+
+/// Nodes ready to run, handed out highest priority first.
+///
+/// Ties are broken by evaluation order. The model graph is assumed to be
+/// acyclic.
+#[derive(Debug, Clone)]
+pub struct ReadyQueue {
+    priorities: Vec<u64>,
+    position: Vec<usize>,
+    successors: Vec<TVec<usize>>,
+    missing: Vec<usize>,
+    ready: BinaryHeap<(u64, Reverse<usize>, usize)>,
+}
+
+impl ReadyQueue {
+    /// Prioritize nodes by their critical path, see
+    /// `critical_path_priorities`.
+    pub fn new(model: &TypedModel) -> TractResult<ReadyQueue> {
+        Self::with_priorities(model, critical_path_priorities(model)?)
+    }
+
+    /// Hand nodes out in evaluation order.
+    pub fn in_eval_order(model: &TypedModel) -> TractResult<ReadyQueue> {
+        Self::with_priorities(model, vec![0; model.nodes().len()])
+    }
+
+    fn with_priorities(model: &TypedModel, priorities: Vec<u64>) -> TractResult<ReadyQueue> {
+        let order = model.eval_order()?;
+        let mut position = vec![usize::max_value(); model.nodes().len()];
+        for (ix, &node) in order.iter().enumerate() {
+            position[node] = ix;
+        }
+        let mut successors = vec![tvec!(); model.nodes().len()];
+        let mut missing = vec![0; model.nodes().len()];
+        for &node in &order {
+            let mut inputs: TVec<usize> =
+                model.nodes()[node].inputs.iter().map(|i| i.node).collect();
+            inputs.sort();
+            inputs.dedup();
+            for input in inputs {
+                successors[input].push(node);
+                missing[node] += 1;
+            }
+        }
+        let mut queue =
+            ReadyQueue { priorities, position, successors, missing, ready: BinaryHeap::new() };
+        for &node in &order {
+            if queue.missing[node] == 0 {
+                queue.push(node);
+            }
+        }
+        Ok(queue)
+    }
+
+    fn push(&mut self, node: usize) {
+        self.ready.push((self.priorities[node], Reverse(self.position[node]), node));
+    }
+
+    /// The next node to run, if any is ready.
+    pub fn pop(&mut self) -> Option<usize> {
+        self.ready.pop().map(|(_, _, node)| node)
+    }
+
+    /// Record that `node` has run, readying the successors it was the last
+    /// missing input of.
+    pub fn done(&mut self, node: usize) {
+        for ix in 0..self.successors[node].len() {
+            let succ = self.successors[node][ix];
+            self.missing[succ] -= 1;
+            if self.missing[succ] == 0 {
+                self.push(succ);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::math::mat_mul::MatMulUnaryA;
+    use crate::ops::math::Add;
+
+    /// Three branches joined by adds: two light ones, one matmul each, and a
+    /// heavy one, a chain of five matmuls, last in evaluation order.
+    fn branches() -> TypedModel {
+        let mut model = TypedModel::default();
+        let fact = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(&[1, 8][..]),
+            konst: None,
+        };
+        let source = model.add_source("source", fact.clone()).unwrap();
+        let matmul = || MatMulUnaryA::new(Tensor::from(ndarray::Array2::<f32>::eye(8)));
+        let mut branch = |name: &str, len: usize| {
+            let mut wire = OutletId::new(source, 0);
+            for i in 0..len {
+                let id = model
+                    .add_node(format!("{}.{}", name, i), matmul(), tvec!(fact.clone()))
+                    .unwrap();
+                model.add_edge(wire, InletId::new(id, 0)).unwrap();
+                wire = OutletId::new(id, 0);
+            }
+            wire
+        };
+        let light_1 = branch("light_1", 1);
+        let light_2 = branch("light_2", 1);
+        let heavy = branch("heavy", 5);
+        let mut join = |name: &str, a: OutletId, b: OutletId| {
+            let id = model.add_node(name, Add::default(), tvec!(fact.clone())).unwrap();
+            model.add_edge(a, InletId::new(id, 0)).unwrap();
+            model.add_edge(b, InletId::new(id, 1)).unwrap();
+            OutletId::new(id, 0)
+        };
+        let lights = join("lights", light_1, light_2);
+        let all = join("all", lights, heavy);
+        model.set_output_outlets(&[all]).unwrap();
+        model
+    }
+
+    /// Simulate a run on `workers` workers, each node taking as long as its
+    /// multiply-accumulate count.
+    fn makespan(model: &TypedModel, mut queue: ReadyQueue, workers: usize) -> u64 {
+        let flops = node_flops(model).unwrap();
+        let mut now = 0;
+        let mut running: Vec<(u64, usize)> = vec![];
+        loop {
+            while running.len() < workers {
+                match queue.pop() {
+                    Some(node) => running.push((now + flops[node], node)),
+                    None => break,
+                }
+            }
+            if running.is_empty() {
+                return now;
+            }
+            running.sort();
+            let (end, node) = running.remove(0);
+            now = end;
+            queue.done(node);
+        }
+    }
+
+    #[test]
+    fn priorities_follow_heaviest_path() {
+        let model = branches();
+        let priorities = critical_path_priorities(&model).unwrap();
+        let source = model.node_by_name("source").unwrap().id;
+        assert_eq!(priorities[source], 5 * 64);
+        assert_eq!(priorities[model.node_by_name("heavy.0").unwrap().id], 5 * 64);
+        assert_eq!(priorities[model.node_by_name("light_1.0").unwrap().id], 64);
+        assert_eq!(priorities[model.node_by_name("all").unwrap().id], 0);
+    }
+
+    #[test]
+    fn heavy_branch_first() {
+        let model = branches();
+        let mut queue = ReadyQueue::new(&model).unwrap();
+        let source = queue.pop().unwrap();
+        queue.done(source);
+        assert_eq!(queue.pop(), Some(model.node_by_name("heavy.0").unwrap().id));
+        assert_eq!(queue.pop(), Some(model.node_by_name("light_1.0").unwrap().id));
+        assert_eq!(queue.pop(), Some(model.node_by_name("light_2.0").unwrap().id));
+    }
+
+    #[test]
+    fn critical_path_lowers_makespan() {
+        let model = branches();
+        let in_order = makespan(&model, ReadyQueue::in_eval_order(&model).unwrap(), 2);
+        let critical = makespan(&model, ReadyQueue::new(&model).unwrap(), 2);
+        assert_eq!(in_order, 6 * 64);
+        assert_eq!(critical, 5 * 64);
+    }
+
+    #[test]
+    fn one_worker_runs_everything() {
+        let model = branches();
+        let mut queue = ReadyQueue::new(&model).unwrap();
+        let mut seen = vec![];
+        while let Some(node) = queue.pop() {
+            seen.push(node);
+            queue.done(node);
+        }
+        seen.sort();
+        assert_eq!(seen, (0..model.nodes().len()).collect::<Vec<_>>());
+    }
+}
@@ -28,14 +28,7 @@ impl<'a> TryFrom<&'a TensorFact> for TypedTensorInfo {
         if let (Some(datum_type), Some(shape)) =
             (fact.datum_type.concretize(), fact.shape.concretize())
         {
-            let stream_info = shape
-                .iter()
-                .cloned()
-                .enumerate()
-                .find(|d| d.1.to_integer().is_err())
-                .map(|(axis, len)| StreamInfo { axis, len });
-            let shape = shape.iter().map(|d| d.to_integer().unwrap_or(0) as usize).collect();
-            let shape = ShapeInfo { shape, stream_info };
+            let shape = shape.iter().cloned().collect();
             Ok(TypedTensorInfo { datum_type, shape, konst: fact.value.concretize() })
         } else {
             bail!("Can not make a TypedTensorInfo out of {:?}", fact)
@@ -132,6 +125,22 @@ impl<T: AsRef<[usize]>> From<T> for ShapeInfo {
     }
 }
 
+/// A shape from its dimensions, the first one that is not an integer being
+/// the streaming one.
+impl std::iter::FromIterator<TDim> for ShapeInfo {
+    fn from_iter<I: IntoIterator<Item = TDim>>(iter: I) -> ShapeInfo {
+        let dims: TVec<TDim> = iter.into_iter().collect();
+        let stream_info = dims
+            .iter()
+            .cloned()
+            .enumerate()
+            .find(|d| d.1.to_integer().is_err())
+            .map(|(axis, len)| StreamInfo { axis, len });
+        let shape = dims.iter().map(|d| d.to_integer().unwrap_or(0) as usize).collect();
+        ShapeInfo { shape, stream_info }
+    }
+}
+
 impl fmt::Debug for ShapeInfo {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use itertools::Itertools;
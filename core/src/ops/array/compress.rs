@@ -0,0 +1,128 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// Slices of the data along `axis` for which the boolean condition is true,
+/// or elements of the flattened data without an axis, as `np.compress`.
+///
+/// The condition may be shorter than the axis: slices past its end are
+/// dropped. The output length depends on the condition values, not only on
+/// the input shapes.
+#[derive(Debug, Clone, new)]
+pub struct Compress {
+    axis: Option<i64>,
+}
+
+impl Compress {
+    fn eval_t<T: Datum>(&self, input: &Tensor, condition: &[bool]) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let (rank, len) = (input.ndim(), input.len());
+        let (input, axis) = match self.axis {
+            Some(axis) => (input, crate::ops::normalize_axis("Compress", axis, rank)?),
+            None => (input.into_shape(IxDyn(&[len]))?, 0),
+        };
+        if condition.len() > input.shape()[axis] {
+            bail!(
+                "Compress condition has {} values for an axis of length {}",
+                condition.len(),
+                input.shape()[axis]
+            );
+        }
+        let selected: Vec<usize> = (0..condition.len()).filter(|&ix| condition[ix]).collect();
+        let mut shape: TVec<usize> = input.shape().into();
+        shape[axis] = selected.len();
+        let output = ArrayD::from_shape_fn(&*shape, |mut coords| {
+            coords[axis] = selected[coords[axis]];
+            input[coords].clone()
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for Compress {
+    fn name(&self) -> Cow<str> {
+        "Compress".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("axis: {:?}", self.axis)))
+    }
+}
+
+impl StatelessOp for Compress {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (input, condition) = args_2!(inputs);
+        let condition = condition.to_array_view::<bool>()?;
+        if condition.ndim() != 1 {
+            bail!("Compress expects a 1D condition, got a shape of {:?}", condition.shape());
+        }
+        let condition: Vec<bool> = condition.iter().cloned().collect();
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, &input, &condition))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Compress {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[1].datum_type, DatumType::Bool)?;
+        s.equals(&inputs[1].rank, 1)?;
+        if let Some(axis) = self.axis {
+            s.equals(&inputs[0].rank, &outputs[0].rank)?;
+            s.given(&inputs[0].rank, move |s, rank| {
+                let axis = crate::ops::normalize_axis("Compress", axis, rank as usize)?;
+                for ix in (0..rank as usize).filter(|&ix| ix != axis) {
+                    s.equals(&inputs[0].shape[ix], &outputs[0].shape[ix])?;
+                }
+                Ok(())
+            })?;
+        } else {
+            s.equals(&outputs[0].rank, 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn compress(axis: Option<i64>, input: Tensor, condition: &[bool]) -> TractResult<Tensor> {
+        let inputs = tvec!(input.into_arc_tensor(), rctensor1(condition));
+        Ok(Compress::new(axis).eval(inputs)?.remove(0).into_tensor())
+    }
+
+    #[test]
+    fn rows_of_a_matrix() {
+        let input = tensor2(&[[1i32, 2], [3, 4], [5, 6], [7, 8]]);
+        let found = compress(Some(0), input.clone(), &[true, false, false, true]).unwrap();
+        assert_eq!(found, tensor2(&[[1i32, 2], [7, 8]]));
+        // only the rows covered by the condition are considered
+        let found = compress(Some(-2), input.clone(), &[false, true, true]).unwrap();
+        assert_eq!(found, tensor2(&[[3i32, 4], [5, 6]]));
+        let found = compress(Some(0), input.clone(), &[false, false]).unwrap();
+        assert_eq!(found.shape(), &[0, 2]);
+        let found = compress(Some(1), input, &[false, true]).unwrap();
+        assert_eq!(found, tensor2(&[[2i32], [4], [6], [8]]));
+    }
+
+    #[test]
+    fn flattened_without_axis() {
+        let s = |s: &str| s.to_string();
+        let input = tensor2(&[[s("a"), s("b")], [s("c"), s("d")]]);
+        let found = compress(None, input, &[false, true, false, true]).unwrap();
+        assert_eq!(found, tensor1(&[s("b"), s("d")]));
+    }
+
+    #[test]
+    fn condition_longer_than_axis() {
+        let input = tensor2(&[[1.0f32, 2.0], [3.0, 4.0]]);
+        assert!(compress(Some(0), input, &[true, true, false]).is_err());
+    }
+}
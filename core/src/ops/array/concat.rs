@@ -11,19 +11,13 @@ pub struct Concat {
 }
 
 impl Concat {
-    fn resolve_axis(&self, rank: i64) -> TractResult<usize> {
-        if 0 <= self.axis && self.axis <= rank - 1 {
-            Ok(self.axis as usize)
-        } else if -rank <= self.axis && self.axis < 0 {
-            Ok((self.axis + rank) as usize)
-        } else {
-            bail!("Illegal combination of values for rank and axis: {} and {}", rank, self.axis)
-        }
+    fn resolve_axis(&self, rank: usize) -> TractResult<usize> {
+        normalize_axis("Concat", self.axis, rank)
     }
 
     /// Evaluates the operation given the input tensors.
     fn eval_t<T: Datum + Copy>(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
-        let axis = self.resolve_axis(inputs[0].shape().len() as i64)?;
+        let axis = self.resolve_axis(inputs[0].shape().len())?;
         let mut slices: TVec<FixedConcatSlice<T>> = tvec![];
         for input in &inputs {
             let shape = Tensor::shape(&input);
@@ -46,7 +40,7 @@ impl Op for Concat {
         let inputs = model.node_input_facts(node.id)?;
 
         if let Some(super_type) = DatumType::super_type_for(inputs.iter().map(|x| x.datum_type)) {
-            let axis = self.resolve_axis(inputs[0].shape.rank() as i64)?;
+            let axis = self.resolve_axis(inputs[0].shape.rank())?;
 
             fn fixed<T: Datum + Copy>(
                 axis: usize,
@@ -110,7 +104,7 @@ impl InferenceRulesOp for Concat {
         let n = inputs.len() as usize;
         s.equals_all((0..n).map(|i| (&inputs[i].rank).bex()).collect())?;
         s.given(&inputs[0].rank, move |s, rank| {
-            let axis = self.resolve_axis(rank as i64)?;
+            let axis = self.resolve_axis(rank as usize)?;
             s.equals(
                 crate::analyser::rules::expr::SumExp::new(
                     (0..n).map(|i| (&inputs[i].shape[axis]).bex()).collect(),
@@ -88,10 +88,11 @@ impl StatelessOp for EyeLike {
     /// Evaluates the operation given the input tensors.
     fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
         let input = args_1!(inputs);
-        Ok(tvec!(dispatch_numbers!(Self::make(input.datum_type())(
-            self,
-            (input.shape()[0], input.shape()[1])
-        ))?))
+        if input.shape().len() != 2 {
+            bail!("EyeLike expects a 2D input, got {:?}", input.shape());
+        }
+        let dt = self.dt.unwrap_or(input.datum_type());
+        Ok(tvec!(dispatch_numbers!(Self::make(dt)(self, (input.shape()[0], input.shape()[1])))?))
     }
 }
 
@@ -128,3 +129,44 @@ impl InferenceRulesOp for EyeLike {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eye(dt: Option<DatumType>, k: isize, input: Tensor) -> Arc<Tensor> {
+        EyeLike::new(dt, k).eval(tvec!(input.into_arc_tensor())).unwrap().remove(0)
+    }
+
+    #[test]
+    fn eye_like_3x4_k1() {
+        let found = eye(None, 1, Array2::<f32>::zeros((3, 4)).into());
+        let expected = arr2(&[[0f32, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(*found, expected.into());
+    }
+
+    #[test]
+    fn eye_like_off_the_matrix() {
+        let input = || Tensor::from(Array2::<i32>::zeros((3, 4)));
+        let found = eye(None, -2, input());
+        assert_eq!(*found, arr2(&[[0i32, 0, 0, 0], [0, 0, 0, 0], [1, 0, 0, 0]]).into());
+        assert_eq!(*eye(None, 4, input()), Array2::<i32>::zeros((3, 4)).into());
+        assert_eq!(*eye(None, -3, input()), Array2::<i32>::zeros((3, 4)).into());
+    }
+
+    #[test]
+    fn eye_like_datum_type() {
+        let found = eye(Some(DatumType::I64), 0, Array2::<f32>::zeros((2, 2)).into());
+        assert_eq!(*found, arr2(&[[1i64, 0], [0, 1]]).into());
+    }
+
+    #[test]
+    fn eye_like_inference_matches_eval() {
+        let op = EyeLike::new(None, 1);
+        let input = TensorFact::dt_shape(DatumType::F32, shapefact!(3, 4));
+        let output = TensorFact::default();
+        let (_, outputs) = op.infer_facts(tvec!(&input), tvec!(&output)).unwrap();
+        let expected = eye(None, 1, Array2::<f32>::zeros((3, 4)).into());
+        assert_eq!(outputs[0].value.concretize(), Some(expected));
+    }
+}
@@ -19,19 +19,7 @@ impl Gather {
         indices: &Arc<Tensor>,
     ) -> TractResult<Arc<Tensor>> {
         let data_view = data.to_array_view::<T>()?;
-        let rank = data.shape().len() as i64;
-        let axis = {
-            let axis_res: TractResult<i64> = {
-                if 0 <= self.axis && self.axis <= rank - 1 {
-                    Ok(self.axis)
-                } else if -rank <= self.axis && self.axis < 0 {
-                    Ok(self.axis + rank)
-                } else {
-                    bail!("Illegal combination of values for rank and axis")
-                }
-            };
-            axis_res? as usize
-        };
+        let axis = normalize_axis("Gather", self.axis, data.shape().len())?;
 
         if indices.shape().len() == 0 {
             return Ok(data_view
@@ -84,7 +72,10 @@ impl InferenceRulesOp for Gather {
         check_output_arity(&outputs, 1)?;
         s.equals(&inputs[1].datum_type, i64::datum_type())?;
         s.equals(inputs[0].rank.bex() - 1 + inputs[1].rank.bex(), outputs[0].rank.bex())?;
-        Ok(())
+        s.given(&inputs[0].rank, move |_, rank| {
+            normalize_axis("Gather", self.axis, rank as usize)?;
+            Ok(())
+        })
     }
 }
 
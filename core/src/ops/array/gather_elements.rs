@@ -0,0 +1,91 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// Gathers along `axis`, element by element: the indices tensor has the
+/// rank of the data, and the output its shape.
+///
+/// `output[i][j][k] = data[indices[i][j][k]][j][k]` for axis 0, and so on.
+/// Negative indices count from the end of the axis.
+#[derive(Debug, Clone, new)]
+pub struct GatherElements {
+    axis: i64,
+}
+
+impl GatherElements {
+    fn eval_t<T: Datum>(&self, data: &Tensor, indices: &Tensor) -> TractResult<Tensor> {
+        let data = data.to_array_view::<T>()?;
+        let indices = indices.cast_to::<i64>()?;
+        let indices = indices.to_array_view::<i64>()?;
+        if indices.ndim() != data.ndim() {
+            bail!(
+                "GatherElements expects data and indices of the same rank, got {:?} and {:?}",
+                data.shape(),
+                indices.shape()
+            );
+        }
+        let axis = normalize_axis("GatherElements", self.axis, data.ndim())?;
+        let len = data.shape()[axis] as i64;
+        let mut coords = vec![0; data.ndim()];
+        let output = indices.indexed_iter().map(|(pattern, &index)| {
+            let index = if index < 0 { index + len } else { index };
+            if index < 0 || index >= len {
+                bail!("Index {} out of bounds for axis {} of length {}", index, axis, len);
+            }
+            coords.copy_from_slice(pattern.slice());
+            coords[axis] = index as usize;
+            Ok(data[&*coords].clone())
+        });
+        let output = output.collect::<TractResult<Vec<T>>>()?;
+        Ok(ArrayD::from_shape_vec(indices.shape(), output)?.into())
+    }
+}
+
+impl Op for GatherElements {
+    fn name(&self) -> Cow<str> {
+        "GatherElements".into()
+    }
+}
+
+impl StatelessOp for GatherElements {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (data, indices) = args_2!(inputs);
+        let output = dispatch_datum!(Self::eval_t(data.datum_type())(self, &data, &indices))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for GatherElements {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, &inputs[1].rank)?;
+        s.equals(&inputs[1].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gather_along_axis_1() {
+        let data = rctensor2(&[[1i32, 2, 3], [4, 5, 6], [7, 8, 9]]);
+        let indices = rctensor2(&[[2i64, 0], [1, 1], [0, -1]]);
+        let output = GatherElements::new(1).eval(tvec!(data, indices)).unwrap();
+        assert_eq!(output[0], rctensor2(&[[3i32, 1], [5, 5], [7, 9]]));
+    }
+
+    #[test]
+    fn out_of_bounds_index() {
+        let data = rctensor2(&[[1i32, 2], [3, 4]]);
+        let indices = rctensor2(&[[0i64, 2]]);
+        assert!(GatherElements::new(1).eval(tvec!(data, indices)).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// Gathers the slices of data addressed by the tuples in the last axis of
+/// indices.
+///
+/// The first `batch_dims` axes of data and indices are matched one to one.
+/// With indices of shape `[b.., n.., k]`, the output shape is
+/// `[b.., n.., data.shape[batch_dims + k..]]`. Negative indices count from
+/// the end of their axis.
+#[derive(Debug, Clone, new)]
+pub struct GatherNd {
+    batch_dims: usize,
+}
+
+impl GatherNd {
+    fn output_shape<D: DimLike>(
+        &self,
+        data_shape: &[D],
+        indices_shape: &[D],
+    ) -> TractResult<TVec<D>> {
+        let k = indices_shape
+            .last()
+            .and_then(|k| k.to_integer().ok())
+            .ok_or("GatherNd expects indices with a known last dimension")?
+            as usize;
+        if k == 0
+            || self.batch_dims >= indices_shape.len()
+            || self.batch_dims + k > data_shape.len()
+        {
+            bail!(
+                "Invalid GatherNd tuples of {} indices with {} batch dims in data of rank {}",
+                k,
+                self.batch_dims,
+                data_shape.len()
+            );
+        }
+        Ok(indices_shape[..indices_shape.len() - 1]
+            .iter()
+            .chain(data_shape[self.batch_dims + k..].iter())
+            .cloned()
+            .collect())
+    }
+
+    fn eval_t<T: Datum>(&self, data: &Tensor, indices: &Tensor) -> TractResult<Tensor> {
+        let shape = self.output_shape(data.shape(), indices.shape())?;
+        let data = data.to_array_view::<T>()?;
+        let indices = indices.cast_to::<i64>()?;
+        let indices = indices.to_array_view::<i64>()?;
+        let k = *indices.shape().last().unwrap();
+        let tuple_count = indices.len() / k;
+        let tuples = indices.into_shape((tuple_count, k))?;
+        let batch: usize = data.shape()[..self.batch_dims].iter().product();
+        let per_batch = tuples.shape()[0] / batch.max(1);
+        let mut output = Vec::with_capacity(shape.iter().product());
+        for (ix, tuple) in tuples.outer_iter().enumerate() {
+            let mut slice = data.view();
+            let mut batch_ix = ix / per_batch.max(1);
+            for axis in (0..self.batch_dims).rev() {
+                let dim = data.shape()[axis];
+                slice.collapse_axis(Axis(axis), batch_ix % dim);
+                batch_ix /= dim;
+            }
+            for (i, &index) in tuple.iter().enumerate() {
+                let axis = self.batch_dims + i;
+                let len = data.shape()[axis] as i64;
+                let index = if index < 0 { index + len } else { index };
+                if index < 0 || index >= len {
+                    bail!("Index {} out of bounds for axis {} of length {}", index, axis, len);
+                }
+                slice.collapse_axis(Axis(axis), index as usize);
+            }
+            output.extend(slice.iter().cloned());
+        }
+        Ok(ArrayD::from_shape_vec(&*shape, output)?.into())
+    }
+}
+
+impl Op for GatherNd {
+    fn name(&self) -> Cow<str> {
+        "GatherNd".into()
+    }
+}
+
+impl StatelessOp for GatherNd {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (data, indices) = args_2!(inputs);
+        let output = dispatch_datum!(Self::eval_t(data.datum_type())(self, &data, &indices))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for GatherNd {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.given_2(&inputs[0].shape, &inputs[1].shape, move |s, data_shape, indices_shape| {
+            let shape = self.output_shape(&data_shape[..], &indices_shape[..])?;
+            s.equals(&outputs[0].shape, ShapeFact::from(shape))
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_rows() {
+        let data = rctensor2(&[[1i32, 2], [3, 4], [5, 6]]);
+        let indices = rctensor2(&[[2i64], [0]]);
+        let output = GatherNd::new(0).eval(tvec!(data, indices)).unwrap();
+        assert_eq!(output[0], rctensor2(&[[5i32, 6], [1, 2]]));
+    }
+
+    #[test]
+    fn select_elements() {
+        let data = rctensor2(&[[1i32, 2], [3, 4]]);
+        let indices = rctensor2(&[[1i64, 0], [0, -1]]);
+        let output = GatherNd::new(0).eval(tvec!(data, indices)).unwrap();
+        assert_eq!(output[0], rctensor1(&[3i32, 2]));
+    }
+
+    #[test]
+    fn batch_dims() {
+        let data = rctensor3(&[[[0i32, 1], [2, 3]], [[4, 5], [6, 7]]]);
+        let indices = rctensor2(&[[1i64], [0]]);
+        let op = GatherNd::new(1);
+        assert_eq!(op.output_shape(data.shape(), indices.shape()).unwrap(), tvec!(2, 2));
+        let output = op.eval(tvec!(data, indices)).unwrap();
+        assert_eq!(output[0], rctensor2(&[[2i32, 3], [4, 5]]));
+    }
+}
@@ -43,37 +43,57 @@
 /// * Slice, unary, mandatory attrs are begin and end.
 mod add_dims;
 mod broadcast;
+mod compress;
 mod concat;
 mod constant_like;
 mod constant_of_shape;
 mod flatten;
 mod gather;
+mod gather_elements;
+mod gather_nd;
+mod nonzero;
 mod pad;
 mod permute_axes;
 mod reshape;
+mod reverse_sequence;
 mod rm_dims;
+mod sequence;
 mod shape;
 mod size;
 mod slice;
 mod split;
 mod squeeze;
 mod tile;
+mod trilu;
+mod unique;
 
 pub use self::add_dims::AddDims;
 pub use self::broadcast::MultiBroadcastTo;
+pub use self::compress::Compress;
 pub use self::concat::Concat;
 pub use self::constant_like::ConstantLike;
 pub use self::constant_like::EyeLike;
 pub use self::constant_of_shape::ConstantOfShape;
 pub use self::flatten::Flatten;
 pub use self::gather::Gather;
+pub use self::gather_elements::GatherElements;
+pub use self::gather_nd::GatherNd;
+pub use self::nonzero::NonZero;
+pub(crate) use self::pad::pulsify_mirror_pad;
 pub use self::pad::{Pad, PadMode};
 pub use self::permute_axes::PermuteAxes;
 pub use self::reshape::Reshape;
+pub use self::reverse_sequence::ReverseSequence;
 pub use self::rm_dims::RmDims;
+pub use self::sequence::{
+    ConcatFromSequence, SequenceAt, SequenceConstruct, SequenceErase, SequenceInsert,
+    SequenceLength, SplitToSequence,
+};
 pub use self::shape::Shape;
 pub use self::size::Size;
 pub use self::slice::Slice;
 pub use self::split::Split;
 pub use self::squeeze::Squeeze;
 pub use self::tile::Tile;
+pub use self::trilu::Trilu;
+pub use self::unique::Unique;
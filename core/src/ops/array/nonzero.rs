@@ -0,0 +1,74 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// Coordinates of the non-zero items of the input, in row-major order, as a
+/// (rank, count) i64 tensor. The count depends on the input values, not
+/// only on its shape.
+#[derive(Debug, Clone, Default)]
+pub struct NonZero;
+
+impl NonZero {
+    fn eval_t<T: Datum>(input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let zero = T::default();
+        let coords: Vec<IxDyn> =
+            input.indexed_iter().filter(|(_, x)| **x != zero).map(|(ix, _)| ix).collect();
+        let output = Array2::from_shape_fn((input.ndim(), coords.len()), |(axis, ix)| {
+            coords[ix][axis] as i64
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for NonZero {
+    fn name(&self) -> Cow<str> {
+        "NonZero".into()
+    }
+}
+
+impl StatelessOp for NonZero {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(&input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for NonZero {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, DatumType::I64)?;
+        s.equals(&outputs[0].rank, 2)?;
+        s.given(&inputs[0].rank, move |s, r| s.equals(&outputs[0].shape[0], r.to_dim()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn non_zero(input: Tensor) -> Tensor {
+        NonZero.eval(tvec!(input.into_arc_tensor())).unwrap().remove(0).into_tensor()
+    }
+
+    #[test]
+    fn coordinates_in_row_major_order() {
+        let found = non_zero(tensor2(&[[0.0f32, 2.0, 0.0], [-1.0, 0.0, 0.5]]));
+        assert_eq!(found, tensor2(&[[0i64, 1, 1], [1, 0, 2]]));
+        let found = non_zero(tensor1(&[false, true, true, false]));
+        assert_eq!(found, tensor2(&[[1i64, 2]]));
+    }
+
+    #[test]
+    fn all_zeros() {
+        let found = non_zero(tensor2(&[[0i32, 0], [0, 0]]));
+        assert_eq!(found.shape(), &[2, 0]);
+        assert_eq!(found.datum_type(), DatumType::I64);
+    }
+}
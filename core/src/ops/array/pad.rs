@@ -1,5 +1,6 @@
 use crate::internal::*;
 use crate::pulse::delay::Delay;
+use crate::pulse::PulsedTensorFact;
 use ndarray::*;
 use num_traits::AsPrimitive;
 
@@ -18,8 +19,8 @@ impl Default for PadMode {
 
 #[derive(Debug, Clone, new, Default)]
 pub struct Pad {
-    pads: Vec<(usize, usize)>,
-    mode: PadMode,
+    pub pads: Vec<(usize, usize)>,
+    pub mode: PadMode,
 }
 
 impl Pad {
@@ -135,7 +136,10 @@ impl Op for Pad {
 
             Ok(tvec!(OutletId::new(id, 0)))
         } else {
-            bail!("Pad pulse only implemented for constant");
+            let (before, after) = self.pads[input_fact.axis];
+            let padded =
+                pulsify_mirror_pad(target, input, &*node.name, self.mode.clone(), before, after)?;
+            Ok(tvec!(padded))
         }
     }
 }
@@ -243,3 +247,146 @@ impl<T: Datum + Copy> InferenceRulesOp for PulsePad<T> {
         Ok(())
     }
 }
+
+/// Chain a reflect or edge padding of the streaming axis after `input`.
+pub(crate) fn pulsify_mirror_pad(
+    target: &mut PulsedModel,
+    input: OutletId,
+    name: &str,
+    mode: PadMode,
+    before: usize,
+    after: usize,
+) -> TractResult<OutletId> {
+    let input_fact = target.outlet_fact(input)?.clone();
+    let mut fact = input_fact.clone();
+    fact.dim += (before + after).to_dim();
+    fact.delay += before;
+    let op = PulseMirrorPad::new(mode, input_fact, before, after);
+    let id = target.chain_after(input, name, op, tvec!(fact))?;
+    Ok(OutletId::new(id, 0))
+}
+
+#[derive(Debug, Clone)]
+struct PulseMirrorPadState {
+    current_pos: usize,
+    /// The last `2 * (before + after)` input samples.
+    history: Tensor,
+}
+
+impl PulseMirrorPadState {
+    fn eval_t<T: Copy + Datum>(
+        &mut self,
+        session: &SessionState,
+        op: &PulseMirrorPad,
+        input: Arc<Tensor>,
+    ) -> TractResult<Arc<Tensor>> {
+        let axis = Axis(op.input_fact.axis);
+        let pulse = op.input_fact.pulse();
+        let input = input.to_array_view::<T>()?;
+        let window = stack(axis, &[self.history.to_array_view::<T>()?, input.view()])?;
+        let window_len = window.shape()[axis.index()];
+        let window_start = self.current_pos as isize - (window_len - pulse) as isize;
+        let len =
+            session.known_stream_len.map(|s| op.input_fact.dim.eval(s as i32).unwrap() as usize);
+        let input_delay = op.input_fact.delay;
+        let padded_start = input_delay + op.before;
+        let mut output = ArrayD::<T>::default(input.shape());
+        for i in 0..pulse {
+            let pos = self.current_pos + i;
+            if pos < padded_start {
+                continue;
+            }
+            let j = pos - padded_start;
+            // index in the input of the sample to copy
+            let k = if j < op.before {
+                match op.mode {
+                    PadMode::Reflect => (op.before - j) as isize,
+                    _ => 0,
+                }
+            } else {
+                match len {
+                    Some(len) if j - op.before >= len => match op.mode {
+                        PadMode::Reflect => 2 * len as isize - 2 - (j - op.before) as isize,
+                        _ => len as isize - 1,
+                    },
+                    _ => (j - op.before) as isize,
+                }
+            };
+            let k = len.map(|len| k.min(len as isize - 1)).unwrap_or(k).max(0);
+            let w = input_delay as isize + k - window_start;
+            if w >= 0 && (w as usize) < window_len {
+                let w = w as usize;
+                output
+                    .slice_axis_mut(axis, Slice::from(i..i + 1))
+                    .assign(&window.slice_axis(axis, Slice::from(w..w + 1)));
+            }
+        }
+        self.history = window.slice_axis(axis, Slice::from(pulse..)).to_owned().into();
+        self.current_pos += pulse;
+        Ok(output.into_arc_tensor())
+    }
+}
+
+impl OpState for PulseMirrorPadState {
+    fn eval(
+        &mut self,
+        session: &mut SessionState,
+        op: &Op,
+        mut inputs: TVec<Arc<Tensor>>,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let op = op.downcast_ref::<PulseMirrorPad>().ok_or("Wrong Op type")?;
+        Ok(tvec!(dispatch_copy!(Self::eval_t(input.datum_type())(self, session, op, input))?))
+    }
+}
+
+/// Reflect or edge padding of the streaming axis.
+///
+/// The padding before the stream mirrors samples arriving after it starts,
+/// and the one after mirrors samples up to `before + after` back: this keeps
+/// a history of `2 * (before + after)` samples, and the padded stream is
+/// delayed by `before` more than its input. The first pulses, before any
+/// sample has been seen, only cover that delay and are zeros.
+///
+/// The end of the stream is padded once the session knows its length. A
+/// stream shorter than the padding mirrors what it has, where a plain
+/// reflect Pad fails.
+#[derive(Debug, Clone, new)]
+struct PulseMirrorPad {
+    mode: PadMode,
+    input_fact: PulsedTensorFact,
+    before: usize,
+    after: usize,
+}
+
+impl Op for PulseMirrorPad {
+    fn name(&self) -> Cow<str> {
+        "PulseMirrorPad".into()
+    }
+}
+
+fn empty_history<T: Copy + Datum>(shape: &[usize]) -> Tensor {
+    ArrayD::<T>::default(shape).into()
+}
+
+impl StatefullOp for PulseMirrorPad {
+    fn state(&self, _session: &mut SessionState) -> TractResult<Option<Box<OpState>>> {
+        let mut shape = self.input_fact.shape.clone();
+        shape[self.input_fact.axis] = 2 * (self.before + self.after);
+        let history = dispatch_copy!(self::empty_history(self.input_fact.dt)(&shape));
+        Ok(Some(Box::new(PulseMirrorPadState { current_pos: 0, history })))
+    }
+}
+
+impl InferenceRulesOp for PulseMirrorPad {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        _s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        Ok(())
+    }
+}
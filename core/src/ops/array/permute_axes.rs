@@ -6,6 +6,15 @@ pub struct PermuteAxes {
 }
 
 impl PermuteAxes {
+    /// The permutation of a tensor of `rank`: output axis `ix` is input
+    /// axis `axes[ix]`.
+    pub fn axes_for_rank(&self, rank: usize) -> Vec<usize> {
+        match &self.axes {
+            Some(axes) => axes.clone(),
+            None => (0..rank).rev().collect(),
+        }
+    }
+
     fn compute_shape<D: DimLike>(&self, input: &[D]) -> TVec<D> {
         if let Some(ref axes) = self.axes {
             let mut new_shape = tvec![D::zero(); input.len()];
@@ -39,6 +48,36 @@ impl Op for PermuteAxes {
         "PermuteAxes".into()
     }
 
+    /// Drops identity permutations and folds a permutation of a permutation
+    /// in a single one, so transposes undoing each other vanish.
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let rank = model.outlet_fact(node.inputs[0])?.shape.rank();
+        let axes = self.axes_for_rank(rank);
+        if axes.iter().enumerate().all(|(ix, &axis)| ix == axis) {
+            let mut patch = TypedModelPatch::default();
+            let tap = patch.tap_model(model, node.inputs[0])?;
+            patch.shunt_outside(OutletId::new(node.id, 0), tap)?;
+            return Ok(Some(patch.with_label("identity transpose")));
+        }
+        let prec = model.node(node.inputs[0].node);
+        if let Some(prec_op) = prec.op_as::<PermuteAxes>() {
+            let prec_axes = prec_op.axes_for_rank(rank);
+            let axes: Vec<usize> = axes.iter().map(|&axis| prec_axes[axis]).collect();
+            let patch = TypedModelPatch::replace_single_op(
+                model,
+                node,
+                &[prec.inputs[0]],
+                PermuteAxes::new(Some(axes)),
+            )?;
+            return Ok(Some(patch.with_label("folded transposes")));
+        }
+        Ok(None)
+    }
+
     fn pulsify(
         &self,
         _source: &NormalizedModel,
@@ -0,0 +1,124 @@
+use crate::internal::*;
+use crate::ops::normalize_axis;
+use ndarray::*;
+
+/// Reverses the first `sequence_lens[b]` items along `time_axis` of each
+/// batch item `b` along `batch_axis`. Items past the length of their
+/// sequence, usually padding, are left in place.
+#[derive(Debug, Clone, new)]
+pub struct ReverseSequence {
+    batch_axis: i64,
+    time_axis: i64,
+}
+
+impl ReverseSequence {
+    fn eval_t<T: Datum>(&self, input: &Tensor, lens: &[i64]) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let batch_axis = normalize_axis("ReverseSequence", self.batch_axis, input.ndim())?;
+        let time_axis = normalize_axis("ReverseSequence", self.time_axis, input.ndim())?;
+        if batch_axis == time_axis {
+            bail!("ReverseSequence batch and time axes must differ, both are {}", batch_axis);
+        }
+        if lens.len() != input.shape()[batch_axis] {
+            bail!(
+                "ReverseSequence got {} sequence lengths for a batch of {}",
+                lens.len(),
+                input.shape()[batch_axis]
+            );
+        }
+        let time = input.shape()[time_axis];
+        if let Some(len) = lens.iter().find(|&&len| len < 0 || len as usize > time) {
+            bail!("ReverseSequence sequence length {} out of 0..={}", len, time);
+        }
+        let output = ArrayD::from_shape_fn(input.shape(), |mut coords| {
+            let len = lens[coords[batch_axis]] as usize;
+            if coords[time_axis] < len {
+                coords[time_axis] = len - 1 - coords[time_axis];
+            }
+            input[coords].clone()
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for ReverseSequence {
+    fn name(&self) -> Cow<str> {
+        "ReverseSequence".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("batch_axis: {}, time_axis: {}", self.batch_axis, self.time_axis)))
+    }
+}
+
+impl StatelessOp for ReverseSequence {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (input, lens) = args_2!(inputs);
+        let lens = lens.cast_to::<i64>()?;
+        let lens = lens.as_slice::<i64>()?;
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, &input, lens))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for ReverseSequence {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        s.equals(&inputs[1].rank, 1)?;
+        s.given(&inputs[0].rank, move |s, rank| {
+            let batch_axis = normalize_axis("ReverseSequence", self.batch_axis, rank as usize)?;
+            s.equals(&inputs[1].shape[0], &inputs[0].shape[batch_axis])
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reverse(op: ReverseSequence, input: Tensor, lens: &[i64]) -> TractResult<Tensor> {
+        let inputs = tvec!(input.into_arc_tensor(), rctensor1(lens));
+        Ok(op.eval(inputs)?.remove(0).into_tensor())
+    }
+
+    #[test]
+    fn batch_of_sequences_keeps_padding() {
+        // time major: [time, batch]
+        let input = tensor2(&[[1i32, 10, 100, 1000], [2, 20, 200, 0], [3, 30, 0, 0], [4, 0, 0, 0]]);
+        let found = reverse(ReverseSequence::new(1, 0), input, &[4, 3, 2, 0]).unwrap();
+        let expected =
+            tensor2(&[[4i32, 30, 200, 1000], [3, 20, 100, 0], [2, 10, 0, 0], [1, 0, 0, 0]]);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn batch_major() {
+        let input = tensor3(&[
+            [[1.0f32, -1.0], [2.0, -2.0], [3.0, -3.0]],
+            [[4.0, -4.0], [5.0, -5.0], [0.0, 0.0]],
+        ]);
+        let found = reverse(ReverseSequence::new(0, 1), input, &[3, 2]).unwrap();
+        let expected = tensor3(&[
+            [[3.0f32, -3.0], [2.0, -2.0], [1.0, -1.0]],
+            [[5.0, -5.0], [4.0, -4.0], [0.0, 0.0]],
+        ]);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        let input = || tensor2(&[[1i32, 2], [3, 4]]);
+        assert!(reverse(ReverseSequence::new(1, 0), input(), &[3, 1]).is_err());
+        assert!(reverse(ReverseSequence::new(1, 0), input(), &[-1, 1]).is_err());
+        assert!(reverse(ReverseSequence::new(1, 0), input(), &[1]).is_err());
+        assert!(reverse(ReverseSequence::new(1, 1), input(), &[1, 1]).is_err());
+    }
+}
@@ -0,0 +1,538 @@
+//! Sequences of tensors.
+//!
+//! A sequence is a rank 1 tensor of `SequenceItem`, each of them sharing one
+//! of the sequence tensors. Items must have the same datum type, but may
+//! have different shapes, like the chunks of an uneven split.
+
+use crate::internal::*;
+use ndarray::*;
+
+/// The tensors of a sequence.
+fn items(seq: &Tensor) -> TractResult<Vec<Arc<Tensor>>> {
+    Ok(seq.as_slice::<SequenceItem>()?.iter().map(|item| item.0.clone()).collect())
+}
+
+/// Build a sequence from its tensors.
+fn sequence(items: Vec<Arc<Tensor>>) -> Arc<Tensor> {
+    Array1::from_vec(items.into_iter().map(SequenceItem).collect()).into_arc_tensor()
+}
+
+/// Build a sequence from its inputs.
+#[derive(Debug, Clone, new, Default)]
+pub struct SequenceConstruct;
+
+impl Op for SequenceConstruct {
+    fn name(&self) -> Cow<str> {
+        "SequenceConstruct".into()
+    }
+}
+
+impl StatelessOp for SequenceConstruct {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        if inputs.len() == 0 {
+            bail!("Can not build an empty sequence");
+        }
+        if inputs.iter().any(|t| t.datum_type() != inputs[0].datum_type()) {
+            bail!("Sequence items must share the same datum type");
+        }
+        Ok(tvec!(sequence(inputs.into_vec())))
+    }
+}
+
+impl InferenceRulesOp for SequenceConstruct {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_output_arity(&outputs, 1)?;
+        for i in 1..inputs.len() {
+            s.equals(&inputs[0].datum_type, &inputs[i].datum_type)?;
+        }
+        s.equals(&outputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&outputs[0].rank, 1)?;
+        s.equals(&outputs[0].shape[0], inputs.len().to_dim())
+    }
+}
+
+/// Extract one item from a sequence. Negative positions count from the end.
+#[derive(Debug, Clone, new, Default)]
+pub struct SequenceAt;
+
+impl Op for SequenceAt {
+    fn name(&self) -> Cow<str> {
+        "SequenceAt".into()
+    }
+}
+
+impl StatelessOp for SequenceAt {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (seq, position) = args_2!(inputs);
+        let items = items(&seq)?;
+        let len = items.len() as i64;
+        let position = *position.cast_to::<i64>()?.to_scalar::<i64>()?;
+        let fixed = if position < 0 { position + len } else { position };
+        if fixed < 0 || fixed >= len {
+            bail!("Position {} out of sequence of length {}", position, len);
+        }
+        Ok(tvec!(items[fixed as usize].clone()))
+    }
+}
+
+impl InferenceRulesOp for SequenceAt {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&inputs[0].rank, 1)?;
+        s.equals(&inputs[1].rank, 0)
+    }
+}
+
+/// Split a tensor into a sequence of chunks along `axis`.
+///
+/// With no split input, chunks are of size 1, and the axis is removed if
+/// keepdims is false. The split input can be a scalar chunk size, the last
+/// chunk being shorter if needed, or the list of the chunk sizes.
+#[derive(Debug, Clone, new)]
+pub struct SplitToSequence {
+    axis: i64,
+    keepdims: bool,
+}
+
+impl SplitToSequence {
+    /// Sizes of the chunks to split a dimension of `dim` into.
+    fn chunks(&self, split: Option<&Tensor>, dim: usize) -> TractResult<TVec<usize>> {
+        let split = if let Some(split) = split {
+            split.cast_to::<i64>()?
+        } else {
+            return Ok(std::iter::repeat(1).take(dim).collect());
+        };
+        if split.shape().len() == 0 {
+            let chunk = *split.to_scalar::<i64>()?;
+            if chunk <= 0 {
+                bail!("Can not split dimension {} in chunks of {}", dim, chunk);
+            }
+            let chunk = chunk as usize;
+            Ok((0..dim).step_by(chunk).map(|start| chunk.min(dim - start)).collect())
+        } else {
+            let split = split.as_slice::<i64>()?;
+            if split.iter().any(|&s| s < 0) || split.iter().sum::<i64>() != dim as i64 {
+                bail!("Can not split dimension {} in chunks of {:?}", dim, split);
+            }
+            Ok(split.iter().map(|&s| s as usize).collect())
+        }
+    }
+
+    fn eval_t<T: Datum>(
+        &self,
+        input: &Tensor,
+        axis: usize,
+        chunks: &[usize],
+        squeeze: bool,
+    ) -> TractResult<Arc<Tensor>> {
+        let input = input.to_array_view::<T>()?;
+        let mut start = 0;
+        let items = chunks
+            .iter()
+            .map(|&chunk| {
+                let item = input.slice_axis(Axis(axis), (start..start + chunk).into());
+                let item = if squeeze { item.index_axis_move(Axis(axis), 0) } else { item };
+                start += chunk;
+                item.to_owned().into_arc_tensor()
+            })
+            .collect();
+        Ok(sequence(items))
+    }
+}
+
+impl Op for SplitToSequence {
+    fn name(&self) -> Cow<str> {
+        "SplitToSequence".into()
+    }
+}
+
+impl StatelessOp for SplitToSequence {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = &inputs[0];
+        let axis = normalize_axis("SplitToSequence", self.axis, input.shape().len())?;
+        let split = inputs.get(1).map(|t| &**t);
+        let chunks = self.chunks(split, input.shape()[axis])?;
+        let squeeze = split.is_none() && !self.keepdims;
+        let seq =
+            dispatch_datum!(Self::eval_t(input.datum_type())(self, input, axis, &chunks, squeeze))?;
+        Ok(tvec!(seq))
+    }
+}
+
+impl InferenceRulesOp for SplitToSequence {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&outputs[0].rank, 1)?;
+        if inputs.len() == 1 {
+            s.given(&inputs[0].shape, move |s, shape| {
+                let axis = normalize_axis("SplitToSequence", self.axis, shape.len())?;
+                s.equals(&outputs[0].shape[0], shape[axis])
+            })
+        } else {
+            check_input_arity(&inputs, 2)?;
+            s.given_2(&inputs[0].shape, &inputs[1].value, move |s, shape, split| {
+                let axis = normalize_axis("SplitToSequence", self.axis, shape.len())?;
+                if let Ok(dim) = shape[axis].to_integer() {
+                    let chunks = self.chunks(Some(&*split), dim as usize)?;
+                    s.equals(&outputs[0].shape[0], chunks.len().to_dim())?;
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+/// Resolve an optional position input, negative positions counting from
+/// the end, to an index in `0..=last`. No position means `last`.
+fn sequence_position(
+    op: &str,
+    position: Option<&Arc<Tensor>>,
+    len: usize,
+    last: usize,
+) -> TractResult<usize> {
+    let position = match position {
+        Some(position) => *position.cast_to::<i64>()?.to_scalar::<i64>()?,
+        None => return Ok(last),
+    };
+    let fixed = if position < 0 { position + len as i64 } else { position };
+    if fixed < 0 || fixed > last as i64 {
+        bail!("{}: position {} out of sequence of length {}", op, position, len);
+    }
+    Ok(fixed as usize)
+}
+
+/// Insert a tensor in a sequence, before the item at the optional position
+/// input, or at the end. Negative positions count from the end.
+#[derive(Debug, Clone, new, Default)]
+pub struct SequenceInsert;
+
+impl Op for SequenceInsert {
+    fn name(&self) -> Cow<str> {
+        "SequenceInsert".into()
+    }
+}
+
+impl StatelessOp for SequenceInsert {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let mut items = items(&inputs[0])?;
+        let item = inputs[1].clone();
+        if items.len() > 0 && items[0].datum_type() != item.datum_type() {
+            bail!(
+                "Can not insert a {:?} item in a sequence of {:?}",
+                item.datum_type(),
+                items[0].datum_type()
+            );
+        }
+        let len = items.len();
+        let position = sequence_position("SequenceInsert", inputs.get(2), len, len)?;
+        items.insert(position, item);
+        Ok(tvec!(sequence(items)))
+    }
+}
+
+impl InferenceRulesOp for SequenceInsert {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() < 2 || inputs.len() > 3 {
+            bail!("Wrong number of inputs. Expected 2 or 3, got {}", inputs.len());
+        }
+        check_output_arity(&outputs, 1)?;
+        if inputs.len() == 3 {
+            s.equals(&inputs[2].rank, 0)?;
+        }
+        s.equals(&inputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&outputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&inputs[0].rank, 1)?;
+        s.equals(&outputs[0].rank, 1)?;
+        s.equals(&outputs[0].shape[0], inputs[0].shape[0].bex() + 1.to_dim())
+    }
+}
+
+/// Remove the item at the optional position input, or the last one, from a
+/// sequence. Negative positions count from the end.
+#[derive(Debug, Clone, new, Default)]
+pub struct SequenceErase;
+
+impl Op for SequenceErase {
+    fn name(&self) -> Cow<str> {
+        "SequenceErase".into()
+    }
+}
+
+impl StatelessOp for SequenceErase {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let mut items = items(&inputs[0])?;
+        let len = items.len();
+        if len == 0 {
+            bail!("Can not erase from an empty sequence");
+        }
+        let position = sequence_position("SequenceErase", inputs.get(1), len, len - 1)?;
+        items.remove(position);
+        Ok(tvec!(sequence(items)))
+    }
+}
+
+impl InferenceRulesOp for SequenceErase {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() < 1 || inputs.len() > 2 {
+            bail!("Wrong number of inputs. Expected 1 or 2, got {}", inputs.len());
+        }
+        check_output_arity(&outputs, 1)?;
+        if inputs.len() == 2 {
+            s.equals(&inputs[1].rank, 0)?;
+        }
+        s.equals(&inputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&outputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&inputs[0].rank, 1)?;
+        s.equals(&outputs[0].rank, 1)?;
+        s.equals(&inputs[0].shape[0], outputs[0].shape[0].bex() + 1.to_dim())
+    }
+}
+
+/// Number of items of a sequence, as an i64 scalar.
+#[derive(Debug, Clone, new, Default)]
+pub struct SequenceLength;
+
+impl Op for SequenceLength {
+    fn name(&self) -> Cow<str> {
+        "SequenceLength".into()
+    }
+}
+
+impl StatelessOp for SequenceLength {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let seq = args_1!(inputs);
+        Ok(tvec!(rctensor0(items(&seq)?.len() as i64)))
+    }
+}
+
+impl InferenceRulesOp for SequenceLength {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&inputs[0].rank, 1)?;
+        s.equals(&outputs[0].datum_type, DatumType::I64)?;
+        s.equals(&outputs[0].rank, 0)?;
+        s.given(&inputs[0].shape[0], move |s, len| {
+            if let Ok(len) = len.to_integer() {
+                s.equals(&outputs[0].value, rctensor0(len as i64))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Concatenate the items of a sequence along `axis`, or stack them along a
+/// new axis inserted at `axis` with `new_axis`.
+///
+/// Concatenated items may have different sizes along `axis`, stacked items
+/// must share the same shape.
+#[derive(Debug, Clone, new)]
+pub struct ConcatFromSequence {
+    axis: i64,
+    new_axis: bool,
+}
+
+impl ConcatFromSequence {
+    fn eval_t<T: Datum + Copy>(&self, items: &[Arc<Tensor>]) -> TractResult<Tensor> {
+        let rank = items[0].shape().len();
+        let axis = if self.new_axis {
+            normalize_insertion_axis("ConcatFromSequence", self.axis, rank)?
+        } else {
+            normalize_axis("ConcatFromSequence", self.axis, rank)?
+        };
+        let views = items
+            .iter()
+            .map(|item| {
+                let view = item.to_array_view::<T>()?;
+                Ok(if self.new_axis { view.insert_axis(Axis(axis)) } else { view })
+            })
+            .collect::<TractResult<Vec<_>>>()?;
+        Ok(ndarray::stack(Axis(axis), &*views)?.into())
+    }
+}
+
+impl Op for ConcatFromSequence {
+    fn name(&self) -> Cow<str> {
+        "ConcatFromSequence".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("axis: {}, new_axis: {}", self.axis, self.new_axis)))
+    }
+}
+
+impl StatelessOp for ConcatFromSequence {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let seq = args_1!(inputs);
+        let items = items(&seq)?;
+        if items.len() == 0 {
+            bail!("Can not concatenate an empty sequence");
+        }
+        let output = dispatch_copy!(Self::eval_t(items[0].datum_type())(self, &items))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for ConcatFromSequence {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::SequenceItem)?;
+        s.equals(&inputs[0].rank, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_construct_and_index() {
+        let input = rctensor2(&[[1.0f32, 2.0, 3.0, 4.0], [5.0, 6.0, 7.0, 8.0]]);
+        let chunks = super::super::Split::new(1, 2, None).eval(tvec!(input.clone())).unwrap();
+        let seq = SequenceConstruct::default().eval(chunks.clone()).unwrap().remove(0);
+        assert_eq!(seq.shape(), &[2]);
+        for (ix, chunk) in chunks.iter().enumerate() {
+            let item =
+                SequenceAt::default().eval(tvec!(seq.clone(), rctensor0(ix as i64))).unwrap();
+            assert_eq!(&item[0], chunk);
+        }
+        let last = SequenceAt::default().eval(tvec!(seq.clone(), rctensor0(-1i64))).unwrap();
+        assert_eq!(last[0], chunks[1]);
+        assert!(SequenceAt::default().eval(tvec!(seq.clone(), rctensor0(2i64))).is_err());
+        assert!(SequenceAt::default().eval(tvec!(seq.clone(), rctensor0(-3i64))).is_err());
+
+        let split = SplitToSequence::new(1, true).eval(tvec!(input, rctensor0(2i64))).unwrap();
+        assert_eq!(split[0], seq);
+    }
+
+    #[test]
+    fn split_to_sequence_without_keepdims() {
+        let input = rctensor2(&[[1i32, 2, 3], [4, 5, 6]]);
+        let seq = SplitToSequence::new(-1, false).eval(tvec!(input)).unwrap().remove(0);
+        assert_eq!(seq.shape(), &[3]);
+        let item = SequenceAt::default().eval(tvec!(seq, rctensor0(1i32))).unwrap();
+        assert_eq!(item[0], rctensor1(&[2i32, 5]));
+    }
+
+    #[test]
+    fn uneven_splits_and_mixed_shapes() {
+        let at = |seq: &Arc<Tensor>, ix: i64| {
+            SequenceAt::default().eval(tvec!(seq.clone(), rctensor0(ix))).unwrap().remove(0)
+        };
+        let input = rctensor1(&[1i32, 2, 3, 4, 5]);
+        let seq = SplitToSequence::new(0, true).eval(tvec!(input.clone(), rctensor0(2i64)));
+        let seq = seq.unwrap().remove(0);
+        assert_eq!(seq.shape(), &[3]);
+        assert_eq!(at(&seq, 0), rctensor1(&[1i32, 2]));
+        assert_eq!(at(&seq, -1), rctensor1(&[5i32]));
+
+        let split = rctensor1(&[1i64, 4]);
+        let seq = SplitToSequence::new(0, true).eval(tvec!(input.clone(), split)).unwrap();
+        assert_eq!(at(&seq[0], 1), rctensor1(&[2i32, 3, 4, 5]));
+        let split = rctensor1(&[1i64, 3]);
+        assert!(SplitToSequence::new(0, true).eval(tvec!(input.clone(), split)).is_err());
+
+        let mixed = tvec!(rctensor0(1i32), input.clone(), rctensor2(&[[1i32], [2]]));
+        let seq = SequenceConstruct::default().eval(mixed.clone()).unwrap().remove(0);
+        for (ix, item) in mixed.iter().enumerate() {
+            assert_eq!(&at(&seq, ix as i64), item);
+        }
+        let mixed = tvec!(rctensor0(1i32), rctensor0(1f32));
+        assert!(SequenceConstruct::default().eval(mixed).is_err());
+    }
+
+    #[test]
+    fn insert_erase_and_concat() {
+        let item = |x: i32| rctensor1(&[x, -x]);
+        let seq = SequenceConstruct::default().eval(tvec!(item(1), item(2))).unwrap().remove(0);
+        let insert = |seq: Arc<Tensor>, item: Arc<Tensor>, position: Option<i64>| {
+            let mut inputs = tvec!(seq, item);
+            inputs.extend(position.map(rctensor0));
+            SequenceInsert::default().eval(inputs).map(|mut o| o.remove(0))
+        };
+        let erase = |seq: Arc<Tensor>, position: Option<i64>| {
+            let mut inputs = tvec!(seq);
+            inputs.extend(position.map(rctensor0));
+            SequenceErase::default().eval(inputs).map(|mut o| o.remove(0))
+        };
+        let concat = |seq: &Arc<Tensor>, axis: i64, new_axis: bool| {
+            ConcatFromSequence::new(axis, new_axis)
+                .eval(tvec!(seq.clone()))
+                .map(|mut o| o.remove(0))
+        };
+        // [1, 2] -> [1, 2, 3] -> [0, 1, 2, 3] -> [0, 1, 2, 5, 3]
+        let seq = insert(seq, item(3), None).unwrap();
+        let seq = insert(seq, item(0), Some(0)).unwrap();
+        let seq = insert(seq, item(5), Some(-1)).unwrap();
+        assert_eq!(
+            concat(&seq, 0, true).unwrap(),
+            rctensor2(&[[0, 0], [1, -1], [2, -2], [5, -5], [3, -3]])
+        );
+        assert!(insert(seq.clone(), item(4), Some(6)).is_err());
+        assert!(insert(seq.clone(), item(4), Some(-6)).is_err());
+        assert!(insert(seq.clone(), rctensor0(4f32), None).is_err());
+        // -> [0, 1, 2, 3] -> [0, 2, 3] -> [0, 2]
+        let seq = erase(seq, Some(-2)).unwrap();
+        let seq = erase(seq, Some(1)).unwrap();
+        let seq = erase(seq, None).unwrap();
+        assert!(erase(seq.clone(), Some(2)).is_err());
+        assert!(erase(seq.clone(), Some(-3)).is_err());
+        let len = SequenceLength::default().eval(tvec!(seq.clone())).unwrap();
+        assert_eq!(len[0], rctensor0(2i64));
+
+        assert_eq!(concat(&seq, -1, false).unwrap(), rctensor1(&[0, 0, 2, -2]));
+        assert_eq!(concat(&seq, 1, true).unwrap(), rctensor2(&[[0, 2], [0, -2]]));
+
+        // items of different lengths concatenate, but do not stack
+        let seq = insert(seq, rctensor1(&[7]), None).unwrap();
+        assert_eq!(concat(&seq, 0, false).unwrap(), rctensor1(&[0, 0, 2, -2, 7]));
+        assert!(concat(&seq, 0, true).is_err());
+
+        let empty = erase(erase(erase(seq, None).unwrap(), None).unwrap(), None).unwrap();
+        assert_eq!(empty.shape(), &[0]);
+        assert!(erase(empty.clone(), None).is_err());
+        assert!(concat(&empty, 0, false).is_err());
+        let seq = insert(empty, item(7), Some(0)).unwrap();
+        assert_eq!(concat(&seq, 0, true).unwrap(), rctensor2(&[[7, -7]]));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::internal::*;
+
+/// Keep the upper or lower triangle of the matrices in the two inner
+/// dimensions, zeroing the other one.
+///
+/// The optional second input is the diagonal offset `k` (0 if absent):
+/// positive values move the diagonal up, negative values down.
+#[derive(Debug, Clone, new)]
+pub struct Trilu {
+    upper: bool,
+}
+
+impl Trilu {
+    fn eval_t<T: Datum>(&self, input: &Tensor, k: i64) -> TractResult<Tensor> {
+        let mut output = input.to_array_view::<T>()?.to_owned();
+        let rank = output.ndim();
+        for (coords, x) in output.indexed_iter_mut() {
+            let diag = coords[rank - 1] as i64 - coords[rank - 2] as i64;
+            let keep = if self.upper { diag >= k } else { diag <= k };
+            if !keep {
+                *x = T::default();
+            }
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for Trilu {
+    fn name(&self) -> Cow<str> {
+        "Trilu".into()
+    }
+}
+
+impl StatelessOp for Trilu {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = &inputs[0];
+        if input.shape().len() < 2 {
+            bail!("Trilu expects an input of rank 2 or more, got {:?}", input.shape());
+        }
+        let k =
+            if let Some(k) = inputs.get(1) { *k.cast_to::<i64>()?.to_scalar::<i64>()? } else { 0 };
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, input, k))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Trilu {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() != 1 {
+            check_input_arity(&inputs, 2)?;
+            s.equals(&inputs[1].rank, 0)?;
+        }
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::*;
+
+    #[test]
+    fn causal_mask() {
+        let ones = Array3::<f32>::ones((2, 3, 4)).into_arc_tensor();
+        let mask = Trilu::new(false).eval(tvec!(ones)).unwrap().remove(0);
+        let expected = arr2(&[[1.0f32, 0.0, 0.0, 0.0], [1.0, 1.0, 0.0, 0.0], [1.0, 1.0, 1.0, 0.0]]);
+        let mask = mask.to_array_view::<f32>().unwrap().into_dimensionality::<Ix3>().unwrap();
+        for m in mask.outer_iter() {
+            assert_eq!(m, expected);
+        }
+    }
+
+    #[test]
+    fn upper_with_offset() {
+        let input = rctensor2(&[[1i32, 2, 3], [4, 5, 6], [7, 8, 9]]);
+        let output = Trilu::new(true).eval(tvec!(input.clone(), rctensor0(-1i64))).unwrap();
+        assert_eq!(output[0], rctensor2(&[[1i32, 2, 3], [4, 5, 6], [0, 8, 9]]));
+        let output = Trilu::new(true).eval(tvec!(input, rctensor0(1i64))).unwrap();
+        assert_eq!(output[0], rctensor2(&[[0i32, 2, 3], [0, 0, 6], [0, 0, 0]]));
+    }
+
+    #[test]
+    fn offset_beyond_bounds() {
+        let input = rctensor2(&[[1i32, 2], [3, 4]]);
+        let all = Trilu::new(true).eval(tvec!(input.clone(), rctensor0(-5i64))).unwrap();
+        assert_eq!(all[0], input);
+        let none = Trilu::new(true).eval(tvec!(input.clone(), rctensor0(5i64))).unwrap();
+        assert_eq!(none[0], rctensor2(&[[0i32, 0], [0, 0]]));
+        let all = Trilu::new(false).eval(tvec!(input.clone(), rctensor0(5i64))).unwrap();
+        assert_eq!(all[0], input);
+        let none = Trilu::new(false).eval(tvec!(input, rctensor0(-5i64))).unwrap();
+        assert_eq!(none[0], rctensor2(&[[0i32, 0], [0, 0]]));
+    }
+}
@@ -0,0 +1,181 @@
+use crate::internal::*;
+use ndarray::*;
+use std::cmp::Ordering;
+
+/// Unique slices along `axis`, or unique values of the flattened input
+/// without one.
+///
+/// Up to four outputs, as in ONNX: the unique slices, the index of the
+/// first occurrence of each of them, the index of each input slice in the
+/// unique ones, and the occurrences of each unique slice. Their lengths
+/// depend on the input values, not only on its shape.
+///
+/// Unique slices are sorted, or kept in the order of their first
+/// occurrence.
+#[derive(Debug, Clone, new)]
+pub struct Unique {
+    axis: Option<i64>,
+    sorted: bool,
+    outputs: usize,
+}
+
+impl Unique {
+    fn compare<T: Datum + PartialOrd>(a: &ArrayViewD<T>, b: &ArrayViewD<T>) -> Ordering {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+
+    fn eval_t<T: Datum + PartialOrd>(&self, input: &Tensor) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = input.to_array_view::<T>()?;
+        let (rank, len) = (input.ndim(), input.len());
+        let (input, axis) = match self.axis {
+            Some(axis) => (input, crate::ops::normalize_axis("Unique", axis, rank)?),
+            None => (input.into_shape(IxDyn(&[len]))?, 0),
+        };
+        let slices: Vec<ArrayViewD<T>> = input.axis_iter(Axis(axis)).collect();
+        // a stable sort keeps each run of equal slices in input order
+        let mut order: Vec<usize> = (0..slices.len()).collect();
+        order.sort_by(|&a, &b| Self::compare(&slices[a], &slices[b]));
+        let mut groups: Vec<Vec<usize>> = vec![];
+        for &ix in &order {
+            match groups.last_mut() {
+                Some(group) if slices[group[0]] == slices[ix] => group.push(ix),
+                _ => groups.push(vec![ix]),
+            }
+        }
+        if !self.sorted {
+            groups.sort_by_key(|group| group[0]);
+        }
+        let mut inverse = vec![0i64; slices.len()];
+        for (u, group) in groups.iter().enumerate() {
+            for &ix in group {
+                inverse[ix] = u as i64;
+            }
+        }
+        let mut shape: TVec<usize> = input.shape().into();
+        shape[axis] = groups.len();
+        let unique = ArrayD::from_shape_fn(&*shape, |mut coords| {
+            coords[axis] = groups[coords[axis]][0];
+            input[coords].clone()
+        });
+        let indices: Vec<i64> = groups.iter().map(|group| group[0] as i64).collect();
+        let counts: Vec<i64> = groups.iter().map(|group| group.len() as i64).collect();
+        let outputs = tvec!(
+            unique.into_arc_tensor(),
+            rctensor1(&indices),
+            rctensor1(&inverse),
+            rctensor1(&counts)
+        );
+        Ok(outputs.into_iter().take(self.outputs).collect())
+    }
+}
+
+impl Op for Unique {
+    fn name(&self) -> Cow<str> {
+        "Unique".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("axis: {:?}, sorted: {}", self.axis, self.sorted)))
+    }
+}
+
+impl StatelessOp for Unique {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        if input.datum_type() == DatumType::String {
+            self.eval_t::<String>(&input)
+        } else {
+            dispatch_numbers!(Self::eval_t(input.datum_type())(self, &input))
+        }
+    }
+}
+
+impl InferenceRulesOp for Unique {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        if self.outputs < 1 || self.outputs > 4 {
+            bail!("Unique has one to four outputs, got {}", self.outputs);
+        }
+        check_output_arity(&outputs, self.outputs)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        for output in &outputs[1..] {
+            s.equals(&output.datum_type, DatumType::I64)?;
+            s.equals(&output.rank, 1)?;
+        }
+        if let Some(axis) = self.axis {
+            s.equals(&inputs[0].rank, &outputs[0].rank)?;
+            s.given(&inputs[0].rank, move |s, rank| {
+                let axis = crate::ops::normalize_axis("Unique", axis, rank as usize)?;
+                for ix in (0..rank as usize).filter(|&ix| ix != axis) {
+                    s.equals(&inputs[0].shape[ix], &outputs[0].shape[ix])?;
+                }
+                if self.outputs > 2 {
+                    s.equals(&inputs[0].shape[axis], &outputs[2].shape[0])?;
+                }
+                Ok(())
+            })?;
+        } else {
+            s.equals(&outputs[0].rank, 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn unique(op: Unique, input: Tensor) -> TVec<Arc<Tensor>> {
+        op.eval(tvec!(input.into_arc_tensor())).unwrap()
+    }
+
+    #[test]
+    fn vector_with_duplicates() {
+        let input = tensor1(&[2.0f32, 1.0, 1.0, 3.0, 4.0, 3.0]);
+        let found = unique(Unique::new(None, false, 4), input.clone());
+        assert_eq!(found[0], rctensor1(&[2.0f32, 1.0, 3.0, 4.0]));
+        assert_eq!(found[1], rctensor1(&[0i64, 1, 3, 4]));
+        assert_eq!(found[2], rctensor1(&[0i64, 1, 1, 2, 3, 2]));
+        assert_eq!(found[3], rctensor1(&[1i64, 2, 2, 1]));
+        let found = unique(Unique::new(None, true, 4), input);
+        assert_eq!(found[0], rctensor1(&[1.0f32, 2.0, 3.0, 4.0]));
+        assert_eq!(found[1], rctensor1(&[1i64, 0, 3, 4]));
+        assert_eq!(found[2], rctensor1(&[1i64, 0, 0, 2, 3, 2]));
+        assert_eq!(found[3], rctensor1(&[2i64, 1, 2, 1]));
+        // the inverse indices rebuild the input from the unique values
+        let rebuilt: Vec<f32> = found[2]
+            .as_slice::<i64>()
+            .unwrap()
+            .iter()
+            .map(|&u| found[0].as_slice::<f32>().unwrap()[u as usize])
+            .collect();
+        assert_eq!(rebuilt, vec![2.0, 1.0, 1.0, 3.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn rows_and_flattened() {
+        let input = tensor2(&[[1, 0, 0], [1, 0, 0], [2, 3, 4]]);
+        let found = unique(Unique::new(Some(0), true, 4), input.clone());
+        assert_eq!(found[0], rctensor2(&[[1, 0, 0], [2, 3, 4]]));
+        assert_eq!(found[1], rctensor1(&[0i64, 2]));
+        assert_eq!(found[2], rctensor1(&[0i64, 0, 1]));
+        assert_eq!(found[3], rctensor1(&[2i64, 1]));
+        // the last two columns are the same
+        let columns = tensor2(&[[1, 0, 0], [1, 0, 0], [2, 3, 3]]);
+        let found = unique(Unique::new(Some(-1), false, 1), columns);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0], rctensor2(&[[1, 0], [1, 0], [2, 3]]));
+        let found = unique(Unique::new(None, false, 3), input);
+        assert_eq!(found[0], rctensor1(&[1, 0, 2, 3, 4]));
+        assert_eq!(found[2], rctensor1(&[0i64, 1, 1, 0, 1, 1, 2, 3, 4]));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+use num_traits::Float;
+
+use crate::ops::cnn::pools::PoolSpec;
+use crate::ops::cnn::{PaddingSpec, Patch, PatchPadMode};
+use crate::ops::nn::DataFormat;
+
+/// Anti-aliased downsampling, or BlurPool: each channel is blurred by a
+/// fixed binomial filter, then strided, so that shifting the input by one
+/// pixel does not change the output abruptly.
+///
+/// The filter is the outer product of the `filter_size` binomial
+/// coefficients on each spatial axis, normalized to a sum of one: [1, 2, 1]
+/// / 4 for 3. Nothing is learned: the op is a depthwise convolution whose
+/// kernel needs not be stored.
+#[derive(Debug, Clone)]
+pub struct BlurPool {
+    pub pool_spec: PoolSpec,
+    pub data_format: DataFormat,
+    pub filter_size: usize,
+    /// How the padding is read, reflecting the input in the reference
+    /// implementation.
+    pub pad_mode: PatchPadMode,
+}
+
+impl BlurPool {
+    pub fn new(
+        data_format: DataFormat,
+        filter_size: usize,
+        strides: TVec<usize>,
+        padding: PaddingSpec,
+        pad_mode: PatchPadMode,
+    ) -> BlurPool {
+        let kernel_shape = tvec!(filter_size; strides.len());
+        let pool_spec = PoolSpec::new(data_format, kernel_shape, padding, Some(strides));
+        BlurPool { pool_spec, data_format, filter_size, pad_mode }
+    }
+
+    /// The normalized binomial filter of `size` taps.
+    pub fn filter(size: usize) -> Vec<f64> {
+        let mut filter = vec![1.0f64];
+        for _ in 1..size {
+            let mut next = vec![1.0; filter.len() + 1];
+            for ix in 1..filter.len() {
+                next[ix] = filter[ix - 1] + filter[ix];
+            }
+            filter = next;
+        }
+        let sum = filter.iter().sum::<f64>();
+        filter.iter().map(|x| x / sum).collect()
+    }
+
+    fn patch(&self, input_full_shape: &[usize]) -> Patch {
+        let input_shape = self.data_format.shape(input_full_shape);
+        self.pool_spec
+            .window()
+            .patch_spec(self.data_format, input_full_shape)
+            .with_pad_mode(self.pad_mode)
+            .with_output_inner_stride(input_shape.w_stride())
+            .into_patch()
+    }
+
+    fn eval_t<T: Datum + Float>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let patch = self.patch(input.shape());
+        patch.check_pad_mode()?;
+        let input_shape = self.data_format.shape(input.shape());
+        let output_shape =
+            self.data_format.from_n_c_hw(input_shape.n(), input_shape.c(), &*patch.output_shape);
+        // the field of the patch runs over the kernel in row-major order
+        let filter = Self::filter(self.filter_size);
+        let kernel_shape = tvec!(self.filter_size; input_shape.hw_rank());
+        let weights: Vec<T> = ndarray::indices(&*kernel_shape)
+            .into_iter()
+            .map(|k| T::from(k.slice().iter().map(|&k| filter[k]).product::<f64>()).unwrap())
+            .collect();
+        let spatial_stride = match self.data_format {
+            DataFormat::NCHW => 1,
+            DataFormat::NHWC => input_shape.c(),
+        };
+        let input = input.to_array_view::<T>()?;
+        let input = input.as_slice().ok_or("BlurPool needs a contiguous input")?;
+        let mut output = ArrayD::<T>::zeros(&*output_shape.shape);
+        let output_data = output.as_slice_mut().unwrap();
+        for (j, coords) in ndarray::indices(&*patch.output_shape).into_iter().enumerate() {
+            let field: TVec<Option<isize>> = patch.at(coords.slice()).collect();
+            for n in 0..input_shape.n() {
+                for c in 0..input_shape.c() {
+                    let offset = input_shape.n_stride() * n + input_shape.c_stride() * c;
+                    let sum = weights
+                        .iter()
+                        .zip(field.iter())
+                        .filter_map(|(&w, pos)| pos.map(|pos| w * input[offset + pos as usize]))
+                        .fold(T::zero(), |acc, x| acc + x);
+                    let output_offset = output_shape.n_stride() * n
+                        + output_shape.c_stride() * c
+                        + j * spatial_stride;
+                    output_data[output_offset] = sum;
+                }
+            }
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for BlurPool {
+    fn name(&self) -> Cow<str> {
+        "BlurPool".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("{} taps, {:?} padding", self.filter_size, self.pad_mode)))
+    }
+}
+
+impl StatelessOp for BlurPool {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = match input.datum_type() {
+            DatumType::F32 => self.eval_t::<f32>(&input)?,
+            DatumType::F64 => self.eval_t::<f64>(&input)?,
+            dt => bail!("BlurPool does not support {:?}", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for BlurPool {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, &inputs[0].datum_type)?;
+        self.pool_spec.rules_for_shape(s, inputs, outputs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn binomial_filters() {
+        assert_eq!(BlurPool::filter(1), vec![1.0]);
+        assert_eq!(BlurPool::filter(3), vec![0.25, 0.5, 0.25]);
+        assert_eq!(BlurPool::filter(4), vec![0.125, 0.375, 0.375, 0.125]);
+    }
+
+    /// Blur then stride by 2, reflecting the input by one pixel on all sides.
+    fn reference(input: &Array4<f32>) -> Array4<f32> {
+        let (n, c, h, w) = input.dim();
+        let filter = BlurPool::filter(3);
+        let reflect = |x: isize, len: usize| {
+            if x < 0 {
+                -x as usize
+            } else if x as usize >= len {
+                2 * (len - 1) - x as usize
+            } else {
+                x as usize
+            }
+        };
+        Array4::from_shape_fn((n, c, (h + 1) / 2, (w + 1) / 2), |(n, c, y, x)| {
+            let mut sum = 0.0;
+            for ky in 0..3 {
+                for kx in 0..3 {
+                    let iy = reflect((2 * y + ky) as isize - 1, h);
+                    let ix = reflect((2 * x + kx) as isize - 1, w);
+                    sum += filter[ky] * filter[kx] * input[(n, c, iy, ix)] as f64;
+                }
+            }
+            sum as f32
+        })
+    }
+
+    #[test]
+    fn blur_then_stride_with_reflect_padding() {
+        let input = Array4::from_shape_fn((2, 3, 6, 5), |(n, c, y, x)| {
+            ((n * 90 + c * 30 + y * 5 + x) * 37 % 23) as f32
+        });
+        let expected = reference(&input);
+        let padding = PaddingSpec::Explicit(tvec!(1, 1), tvec!(1, 1));
+        let op =
+            BlurPool::new(DataFormat::NCHW, 3, tvec!(2, 2), padding.clone(), PatchPadMode::Reflect);
+        let found = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap();
+        assert_close!(*found[0], Tensor::from(expected.clone()));
+        let nhwc = BlurPool::new(DataFormat::NHWC, 3, tvec!(2, 2), padding, PatchPadMode::Reflect);
+        let to_nhwc = |a: &Array4<f32>| {
+            let (n, c, h, w) = a.dim();
+            Array4::from_shape_fn((n, h, w, c), |(n, y, x, c)| a[(n, c, y, x)])
+        };
+        let found = nhwc.eval(tvec!(to_nhwc(&input).into_arc_tensor())).unwrap();
+        assert_close!(*found[0], Tensor::from(to_nhwc(&expected)));
+    }
+
+    #[test]
+    fn constant_input_stays_constant() {
+        let input = Array4::from_elem((1, 2, 7, 7), 3.0f64).into_arc_tensor();
+        let padding = PaddingSpec::Explicit(tvec!(1, 2), tvec!(2, 1));
+        let op = BlurPool::new(DataFormat::NCHW, 4, tvec!(2, 2), padding, PatchPadMode::Edge);
+        let found = op.eval(tvec!(input)).unwrap();
+        let found = found[0].to_array_view::<f64>().unwrap();
+        assert_eq!(found.shape(), &[1, 2, 4, 4]);
+        assert!(found.iter().all(|&x| (x - 3.0).abs() < 1e-12));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::kernel_cache::KernelCache;
+use super::validate::kernel_input_channel;
+use super::ConvUnary;
+
+impl ConvUnary {
+    /// Fold a constant per-channel scale of the input, a `Mul` by a tensor
+    /// spanning the channel axis only, into the kernel: the product is
+    /// linear in each input channel, so scaling the kernel items reading a
+    /// channel is the same as scaling the channel, without the pass over
+    /// the input.
+    pub(super) fn fuse_input_scale(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::math::Mul;
+        let prec = if let Some(prec) = model.single_prec(node.id)? {
+            prec
+        } else {
+            return Ok(None);
+        };
+        let scale = if let Some(mul) = prec.op_as::<Mul::UnaryA>() {
+            mul.b.clone()
+        } else {
+            return Ok(None);
+        };
+        let dt = self.kernel.datum_type();
+        let input_fact = model.outlet_fact(prec.inputs[0])?;
+        let input_shape: TVec<TDim> = input_fact.shape.iter().collect();
+        if !(dt == DatumType::F32 || dt == DatumType::F64)
+            || scale.datum_type() != dt
+            || input_fact.datum_type != dt
+            || input_shape != self.full_input_shape
+            || model.output_outlets()?.contains(&OutletId::new(prec.id, 0))
+        {
+            return Ok(None);
+        }
+        let shape = self.data_format.shape(&self.full_input_shape);
+        let rank = self.full_input_shape.len();
+        if scale.shape().len() > rank {
+            return Ok(None);
+        }
+        // scale axes line up with the input ones from the right
+        let offset = rank - scale.shape().len();
+        let len = scale.shape().iter().product::<usize>();
+        if scale.shape().iter().enumerate().any(|(ax, &d)| d != 1 && ax + offset != shape.c_axis())
+            || (len != 1 && shape.c_dim().to_integer().ok() != Some(len as i32))
+        {
+            return Ok(None);
+        }
+        let mut op = self.clone();
+        op.kernel = dispatch_floatlike!(Self::scaled_kernel(dt)(self, &scale))?;
+        op.kernel_cache = KernelCache::default();
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, prec.inputs[0])?;
+        let out =
+            patch.chain(&*node.name, op, node.outputs.iter().map(|o| o.fact.clone()).collect())?;
+        for ix in 0..node.outputs.len() {
+            patch.shunt_outside(OutletId::new(node.id, ix), OutletId::new(out, ix))?;
+        }
+        Ok(Some(patch.with_label("fused input scale")))
+    }
+
+    /// The kernel, each item multiplied by the scale of the input channel
+    /// it reads. `scale` holds one value per input channel, or a single one.
+    fn scaled_kernel<T>(&self, scale: &Tensor) -> TractResult<Tensor>
+    where
+        T: Datum + num_traits::Float,
+    {
+        let scale = scale.as_slice::<T>()?;
+        let mut kernel = self.kernel.to_array_view::<T>()?.to_owned();
+        let kshape = self.kernel.shape();
+        for (ix, x) in kernel.indexed_iter_mut() {
+            let c = kernel_input_channel(self.kernel_fmt, kshape, self.group, ix.slice());
+            *x = *x * if scale.len() == 1 { scale[0] } else { scale[c] };
+        }
+        Ok(kernel.into())
+    }
+
+    /// Fold a constant per-channel `Add` of the output, a tensor spanning
+    /// the output channel axis only, into the bias.
+    pub(super) fn fuse_bias_add(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::math::Add;
+        if self.summary.is_some() || self.token_output || self.f64_output {
+            return Ok(None);
+        }
+        let succ = if let Some(succ) = model.single_succ(node.id)? {
+            succ
+        } else {
+            return Ok(None);
+        };
+        let add = if let Some(add) = succ.op_as::<Add::UnaryA>() {
+            add.b.clone()
+        } else {
+            return Ok(None);
+        };
+        let dt = self.kernel.datum_type();
+        let output_fact = &succ.outputs[0].fact;
+        let output_shape: TVec<TDim> = output_fact.shape.iter().collect();
+        if !(dt == DatumType::F32 || dt == DatumType::F64)
+            || add.datum_type() != dt
+            || output_fact.datum_type != dt
+            || output_shape != self.full_output_shape
+            || model.output_outlets()?.contains(&OutletId::new(node.id, 0))
+        {
+            return Ok(None);
+        }
+        let shape = self.data_format.shape(&self.full_output_shape);
+        let rank = self.full_output_shape.len();
+        if add.shape().len() > rank {
+            return Ok(None);
+        }
+        // add axes line up with the output ones from the right
+        let offset = rank - add.shape().len();
+        let len = add.shape().iter().product::<usize>();
+        let channels = self.output_channels();
+        if add.shape().iter().enumerate().any(|(ax, &d)| d != 1 && ax + offset != shape.c_axis())
+            || (len != 1 && len != channels)
+        {
+            return Ok(None);
+        }
+        if self
+            .bias
+            .as_ref()
+            .map(|b| b.shape().iter().product::<usize>() != channels)
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
+        let mut op = self.clone();
+        op.bias = Some(dispatch_floatlike!(Self::added_bias(dt)(self, &add))?);
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        let out = patch.chain(&*node.name, op, tvec!(output_fact.clone()))?;
+        patch.shunt_outside(OutletId::new(succ.id, 0), OutletId::new(out, 0))?;
+        Ok(Some(patch.with_label("fused bias add")))
+    }
+
+    /// The bias, one value per output channel, plus `add`, holding one value
+    /// per output channel or a single one.
+    fn added_bias<T>(&self, add: &Tensor) -> TractResult<Tensor>
+    where
+        T: Datum + num_traits::Float,
+    {
+        let add = add.as_slice::<T>()?;
+        let bias = self.bias.as_ref().map(|b| b.as_slice::<T>()).transpose()?;
+        Ok(Array1::from_shape_fn(self.output_channels(), |c| {
+            bias.map(|b| b[c]).unwrap_or(T::zero()) + if add.len() == 1 { add[0] } else { add[c] }
+        })
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use crate::ops::nn::DataFormat;
+
+    /// A per-channel scale of the input, then a conv, in a model.
+    fn scaled_conv(
+        data_format: DataFormat,
+        kernel_fmt: KernelFormat,
+        scale: Tensor,
+    ) -> (TypedModel, Tensor) {
+        use crate::ops::math::Mul;
+        let input_shape = data_format.from_n_c_hw(1, 4, [5usize, 6]).shape;
+        let input = ArrayD::from_shape_fn(&*input_shape, |ix| {
+            ix.slice().iter().fold(0, |acc, x| acc * 5 + x) as f32 % 7.0 - 3.0
+        });
+        let input: Tensor = input.into();
+        let kernel_shape: &[usize] = match kernel_fmt {
+            KernelFormat::OIHW => &[6, 2, 3, 3],
+            KernelFormat::HWIO => &[3, 3, 4, 3],
+        };
+        let kernel = ArrayD::from_shape_fn(kernel_shape, |ix| {
+            ix.slice().iter().fold(1, |acc, x| acc * 3 + x) as f32 % 5.0 - 2.0
+        });
+        let mut conv = Conv::default();
+        conv.data_format = data_format;
+        conv.kernel_fmt = kernel_fmt;
+        conv.group = 2;
+        conv.padding = PaddingSpec::SameUpper;
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(tensor1(&[1.0f32, -1.0, 0.5, 2.0, 0.0, -0.5]));
+        let mut model = TypedModel::default();
+        let input_fact = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(input.shape()),
+            konst: None,
+        };
+        model.add_source("input", input_fact.clone()).unwrap();
+        let mul = Mul::UnaryA::new(f32::datum_type().into(), scale.into());
+        model.chain("scale", mul, tvec!(input_fact)).unwrap();
+        let output = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: op.output_shape().into_iter().collect(),
+            konst: None,
+        };
+        model.chain("conv", op, tvec!(output)).unwrap();
+        (model, input)
+    }
+
+    fn check_scale_fused(data_format: DataFormat, kernel_fmt: KernelFormat, scale: Tensor) {
+        let (model, input) = scaled_conv(data_format, kernel_fmt, scale);
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone())).unwrap();
+        let node = model.node_by_name("conv").unwrap();
+        let op = node.op_as::<ConvUnary>().unwrap();
+        let patch = op.fuse_input_scale(&model, node).unwrap().unwrap();
+        let mut fused = model.clone();
+        patch.apply(&mut fused).unwrap();
+        let order = fused.eval_order().unwrap();
+        assert!(order.iter().all(|&n| !fused.nodes()[n].op_is::<crate::ops::math::Mul::UnaryA>()));
+        let found = SimplePlan::new(&fused).unwrap().run(tvec!(input)).unwrap();
+        assert!(found[0].close_enough(&expected[0], true));
+    }
+
+    #[test]
+    fn input_scale_fused_nchw_oihw() {
+        let scale = tensor3(&[[[2.0f32]], [[-1.0]], [[0.5]], [[3.0]]]);
+        check_scale_fused(DataFormat::NCHW, KernelFormat::OIHW, scale);
+    }
+
+    #[test]
+    fn input_scale_fused_nhwc_hwio() {
+        check_scale_fused(DataFormat::NHWC, KernelFormat::HWIO, tensor1(&[2.0f32, -1.0, 0.5, 3.0]));
+    }
+
+    #[test]
+    fn scalar_input_scale_fused() {
+        check_scale_fused(DataFormat::NCHW, KernelFormat::OIHW, tensor0(0.25f32));
+    }
+
+    #[test]
+    fn spatial_scale_kept() {
+        let scale = Tensor::from(ArrayD::from_elem(&[5usize, 6][..], 2.0f32));
+        let (model, _) = scaled_conv(DataFormat::NCHW, KernelFormat::OIHW, scale);
+        let node = model.node_by_name("conv").unwrap();
+        let op = node.op_as::<ConvUnary>().unwrap();
+        assert!(op.fuse_input_scale(&model, node).unwrap().is_none());
+    }
+
+    /// A conv with no bias followed by an `Add` of `add`.
+    fn conv_add(data_format: DataFormat, add: Tensor) -> (TypedModel, Tensor) {
+        use crate::ops::math::Add;
+        let input_shape = data_format.from_n_c_hw(1, 2, [5usize, 3]).shape;
+        let input = ArrayD::from_shape_fn(&*input_shape, |ix| {
+            ix.slice().iter().fold(0, |acc, x| acc * 5 + x) as f32 % 7.0 - 3.0
+        });
+        let input: Tensor = input.into();
+        let kernel = ArrayD::from_shape_fn(&[3, 2, 3, 3][..], |ix| {
+            ix.slice().iter().fold(1, |acc, x| acc * 3 + x) as f32 % 5.0 - 2.0
+        });
+        let mut conv = Conv::default();
+        conv.data_format = data_format;
+        conv.padding = PaddingSpec::SameUpper;
+        let facts = conv_facts(input.clone(), kernel);
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let mut model = TypedModel::default();
+        let fact = |shape: ShapeInfo| TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape,
+            konst: None,
+        };
+        model.add_source("input", fact(ShapeInfo::from(input.shape()))).unwrap();
+        let output = fact(op.output_shape().into_iter().collect());
+        model.chain("conv", op, tvec!(output.clone())).unwrap();
+        let add = Add::UnaryA::new(f32::datum_type().into(), add.into());
+        model.chain("add", add, tvec!(output)).unwrap();
+        (model, input)
+    }
+
+    /// Whether the `Add` of `add` is folded in the bias, checking the output
+    /// is the same if it is.
+    fn bias_add_fused(data_format: DataFormat, add: Tensor) -> bool {
+        let (model, input) = conv_add(data_format, add);
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone())).unwrap();
+        let node = model.node_by_name("conv").unwrap();
+        let op = node.op_as::<ConvUnary>().unwrap();
+        let patch = match op.fuse_bias_add(&model, node).unwrap() {
+            Some(patch) => patch,
+            None => return false,
+        };
+        let mut fused = model.clone();
+        patch.apply(&mut fused).unwrap();
+        let order = fused.eval_order().unwrap();
+        assert!(order.iter().all(|&n| !fused.nodes()[n].op_is::<crate::ops::math::Add::UnaryA>()));
+        let found = SimplePlan::new(&fused).unwrap().run(tvec!(input)).unwrap();
+        assert!(found[0].close_enough(&expected[0], true));
+        true
+    }
+
+    #[test]
+    fn bias_add_fused_per_channel() {
+        let nchw = tensor4(&[[[[1.0f32]], [[-2.0]], [[0.5]]]]);
+        assert!(bias_add_fused(DataFormat::NCHW, nchw));
+        let nchw = tensor3(&[[[1.0f32]], [[-2.0]], [[0.5]]]);
+        assert!(bias_add_fused(DataFormat::NCHW, nchw));
+        assert!(bias_add_fused(DataFormat::NHWC, tensor1(&[1.0f32, -2.0, 0.5])));
+        assert!(bias_add_fused(DataFormat::NHWC, tensor0(3.0f32)));
+    }
+
+    #[test]
+    fn bias_add_off_channel_axis_kept() {
+        // three values along the width of a NCHW output, or the height of a
+        // NHWC one: as many as channels, on the wrong axis
+        assert!(!bias_add_fused(DataFormat::NCHW, tensor1(&[1.0f32, -2.0, 0.5])));
+        let nhwc = tensor3(&[[[1.0f32], [-2.0], [0.5]]]);
+        assert!(!bias_add_fused(DataFormat::NHWC, nhwc));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::blocked::BlockedMatMat;
+use super::error::ConvError;
+use super::im2col::Im2Col;
+use super::mat_mat::MatMat;
+use super::scratch::{ScratchAllocator, ScratchLayout};
+use super::vec_mat::VecMat;
+use super::ConvUnary;
+use crate::ops::cnn::PatchPadMode;
+
+use std::mem::{align_of, size_of};
+use std::ops::Range;
+
+/// Outputs of the bands of a conv, concatenated along `axis`.
+fn concat_bands<T: Datum + Copy>(bands: &[Arc<Tensor>], axis: usize) -> TractResult<Tensor> {
+    let views = bands.iter().map(|t| t.to_array_view::<T>()).collect::<TractResult<Vec<_>>>()?;
+    Ok(ndarray::stack(Axis(axis), &*views)?.into())
+}
+
+impl ConvUnary {
+    fn layout<T: Datum>(im2col: &Im2Col<T>, gemm: &Op) -> ScratchLayout
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let c_panel = gemm.downcast_ref::<MatMat<T>>().map(|mm| mm.mm.c_panel_len()).unwrap_or(0);
+        ScratchLayout {
+            im2col_len: im2col.output_shape().iter().product::<usize>() * size_of::<T>(),
+            im2col_alignment: im2col.b_pack.alignment(),
+            c_panel_len: c_panel * size_of::<T>(),
+            c_panel_alignment: align_of::<T>(),
+        }
+    }
+
+    /// Scratch memory `eval_with_scratch` takes for an input of this shape.
+    pub fn scratch_layout(&self, input_full_shape: &[usize]) -> TractResult<ScratchLayout> {
+        dispatch_floatlike!(Self::scratch_layout_t(self.kernel.datum_type())(
+            self,
+            input_full_shape
+        ))
+    }
+
+    fn scratch_layout_t<T>(&self, input_full_shape: &[usize]) -> TractResult<ScratchLayout>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let (im2col, _, gemm) = self.to_im2col_pair::<T>(input_full_shape)?;
+        Ok(Self::layout(&im2col, &*gemm))
+    }
+
+    /// Output rows of the bands an input of this shape is evaluated in, when
+    /// its im2col scratch exceeds `max_scratch_bytes`.
+    pub(super) fn scratch_band_rows(
+        &self,
+        input_full_shape: &[usize],
+    ) -> TractResult<Option<usize>> {
+        let allowed = match self.options.max_scratch_bytes {
+            Some(allowed) => allowed,
+            None => return Ok(None),
+        };
+        let required = self.scratch_layout(input_full_shape)?.size();
+        if required <= allowed {
+            return Ok(None);
+        }
+        let rows = self.patch(input_full_shape).output_shape[0];
+        // bands of a summary would need merging, and other pad modes sample
+        // rows outside of the band
+        if rows < 2 || self.summary.is_some() || self.pad_mode != PatchPadMode::Zero {
+            bail!(ConvError::ScratchBudget { required, allowed });
+        }
+        // the scratch grows with the band rows, start from a proportional
+        // guess and shrink it until it fits
+        let mut band = (rows * allowed / required).max(1).min(rows - 1);
+        loop {
+            let (input_rows, op) = self.band(input_full_shape, 0..band);
+            let mut band_shape: TVec<usize> = input_full_shape.into();
+            band_shape[self.data_format.shape(input_full_shape).h_axis()] = input_rows.len();
+            let required = op.scratch_layout(&band_shape)?.size();
+            if required <= allowed {
+                return Ok(Some(band));
+            } else if band == 1 {
+                bail!(ConvError::ScratchBudget { required, allowed });
+            }
+            band = (band * allowed / required).max(1).min(band - 1);
+        }
+    }
+
+    /// The input rows the `rows` of the output read, and the conv computing
+    /// them from these rows alone, padded as the full conv is.
+    pub(super) fn band(
+        &self,
+        input_full_shape: &[usize],
+        rows: Range<usize>,
+    ) -> (Range<usize>, ConvUnary) {
+        let (mut input, op) = self.region(input_full_shape, &[rows]);
+        (input.remove(0), op)
+    }
+
+    /// Evaluate in bands of `band` output rows, each band through its own
+    /// im2col.
+    pub(super) fn eval_in_bands<T: Datum>(
+        &self,
+        input: &Tensor,
+        band: usize,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let h_axis = self.data_format.shape(input.shape()).h_axis();
+        let rows = self.patch(input.shape()).output_shape[0];
+        let view = input.to_array_view::<T>()?;
+        let mut outputs = vec![];
+        for start in (0..rows).step_by(band) {
+            let (input_rows, op) = self.band(input.shape(), start..rows.min(start + band));
+            let slice = view.slice_axis(Axis(h_axis), input_rows.into()).to_owned();
+            outputs.push(op.eval(tvec!(slice.into_arc_tensor()))?.remove(0));
+        }
+        // tokens are written row after row too
+        let axis = if self.token_output { 1 } else { h_axis };
+        let dt = outputs[0].datum_type();
+        let output = dispatch_copy!(self::concat_bands(dt)(&outputs, axis))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+
+    /// Evaluate through im2col, taking the packed input and product scratch
+    /// from `scratch` instead of the global allocator.
+    ///
+    /// The outputs are still allocated, as they outlive the evaluation.
+    pub fn eval_with_scratch(
+        &self,
+        input: &Tensor,
+        scratch: &mut ScratchAllocator,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        if input.datum_type() != self.kernel.datum_type() {
+            bail!(ConvError::DtypeMismatch {
+                expected: self.kernel.datum_type(),
+                found: input.datum_type()
+            });
+        }
+        dispatch_floatlike!(Self::eval_with_scratch_t(input.datum_type())(self, input, scratch))
+    }
+
+    fn eval_with_scratch_t<T>(
+        &self,
+        input: &Tensor,
+        scratch: &mut ScratchAllocator,
+    ) -> TractResult<TVec<Arc<Tensor>>>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let (im2col, _, gemm) = self.to_im2col_pair::<T>(input.shape())?;
+        let layout = Self::layout(&im2col, &*gemm);
+        let packed = unsafe {
+            let ptr = scratch.alloc(layout.im2col_len, layout.im2col_alignment)? as *mut T;
+            std::slice::from_raw_parts_mut(ptr, layout.im2col_len / size_of::<T>())
+        };
+        im2col.im2col_into(&input.to_array_view::<T>()?, packed)?;
+        let packed = ArrayView3::from_shape(
+            (im2col.output_shape()[0], im2col.output_shape()[1], im2col.output_shape()[2]),
+            &*packed,
+        )?;
+        let (output, summary) = if let Some(mat_mat) = gemm.downcast_ref::<MatMat<T>>() {
+            let c_panel = unsafe {
+                let ptr = scratch.alloc(layout.c_panel_len, layout.c_panel_alignment)? as *mut T;
+                std::slice::from_raw_parts_mut(ptr, layout.c_panel_len / size_of::<T>())
+            };
+            if mat_mat.f64_output {
+                let (output, summary) = mat_mat.conv_gemm_f64(&packed, c_panel)?;
+                let mut outputs = tvec!(output.into_arc_tensor());
+                outputs.extend(summary.map(|s| s.into_arc_tensor()));
+                return Ok(outputs);
+            }
+            mat_mat.conv_gemm(&packed, None, c_panel)?
+        } else if let Some(vec_mat) = gemm.downcast_ref::<VecMat<T>>() {
+            vec_mat.conv_gemm(&packed)?
+        } else if let Some(blocked) = gemm.downcast_ref::<BlockedMatMat<T>>() {
+            blocked.conv_gemm(&packed)?
+        } else {
+            unreachable!()
+        };
+        let mut outputs = tvec!(output.into_arc_tensor());
+        if let Some(summary) = summary {
+            outputs.push(summary.into_arc_tensor());
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    #[test]
+    fn scratch_budget_evaluates_in_bands() {
+        let input = Array4::from_shape_fn((2, 3, 11, 6), |(n, c, y, x)| {
+            ((n * 29 + c * 17 + y * 5 + x) % 13) as f32 - 6.0
+        });
+        let kernel = Array4::from_shape_fn((4, 3, 3, 2), |(o, c, y, x)| {
+            ((o * 18 + c * 6 + y * 2 + x) % 7) as f32 / 2.0 - 1.5
+        });
+        // the first output row only sees padding
+        let paddings = [PaddingSpec::SameUpper, PaddingSpec::Explicit(tvec!(3, 1), tvec!(2, 0))];
+        for padding in &paddings {
+            for &(stride, tokens) in &[(1, false), (2, false), (1, true)] {
+                let input = input.clone().into_arc_tensor();
+                let run = |budget| {
+                    let facts = conv_facts(input.clone(), kernel.clone());
+                    let mut conv = Conv::default().with_max_scratch_bytes(budget);
+                    conv.padding = padding.clone();
+                    conv.strides = Some(tvec!(stride, 1));
+                    let mut op = conv.to_unary(&facts).unwrap().unwrap();
+                    op.bias = Some(tensor1(&[1.0f32, -2.0, 0.5, 3.0]));
+                    op.token_output = tokens;
+                    (op.scratch_layout(input.shape()).unwrap(), op.eval(tvec!(input.clone())))
+                };
+                let (layout, expected) = run(None);
+                let (_, found) = run(Some(layout.size() / 3));
+                let (expected, found) = (expected.unwrap().remove(0), found.unwrap().remove(0));
+                assert!(
+                    found.close_enough(&expected, true),
+                    "{:?} stride {} tokens {}",
+                    padding,
+                    stride,
+                    tokens
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn scratch_budget_too_small() {
+        let input = Tensor::from(ArrayD::<f32>::zeros(&[1, 2, 8, 8][..])).into_arc_tensor();
+        let kernel = Tensor::from(ArrayD::<f32>::zeros(&[3, 2, 3, 3][..]));
+        let facts = conv_facts(input.clone(), kernel);
+        let conv = Conv::default().with_max_scratch_bytes(Some(64));
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let err = op.eval(tvec!(input)).unwrap_err();
+        match err.kind() {
+            crate::TractErrorKind::Conv(ConvError::ScratchBudget { required, allowed }) => {
+                assert_eq!(*allowed, 64);
+                assert!(*required > 64);
+            }
+            e => panic!("expected a scratch budget error, got {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use num_traits::Zero;
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul};
+
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use super::summary::{writeback, ChannelSummary};
+use super::timing::{ConvPhase, PhaseTimer};
+use crate::ops::cnn::Patch;
+use crate::ops::nn::DataShape;
+
+/*
+ * The kernel of each group is stored in blocks of `block` output channels,
+ * the channels of a block interleaved along k (OIHW8o for block = 8):
+ *
+ *   kernel[g][b][k][lane] = weight(g * m + b * block + lane, k)
+ *
+ * The last block is padded with zero channels. The im2col input is plain
+ * (k, n) rows. For each output point, a block accumulates `block` lanes at
+ * once, over k: the loop over lanes reads contiguous weights and writes
+ * contiguous accumulators, the shape SIMD units want. The lanes are then
+ * scattered to their channel planes, back to the output data format.
+ */
+
+#[derive(Clone, new)]
+pub struct BlockedMatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    pub patch: Patch,
+    pub output_shape: DataShape,
+    pub m: usize,
+    pub k: usize,
+    pub n: usize,
+    /// Output channels per block.
+    pub block: usize,
+    pub packed_kernels: Arc<Vec<Tensor>>,
+    pub bias: Option<ArrayD<T>>,
+    pub group: usize,
+    pub summary: Option<ChannelSummary>,
+    #[new(default)]
+    pub timer: PhaseTimer,
+}
+
+impl<T> fmt::Debug for BlockedMatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("BlockedMatMat")
+            .field("patch", &self.patch)
+            .field("output_shape", &self.output_shape)
+            .field("m", &self.m)
+            .field("k", &self.k)
+            .field("n", &self.n)
+            .field("block", &self.block)
+            .field("bias", &self.bias)
+            .field("group", &self.group)
+            .field("summary", &self.summary)
+            .finish()
+    }
+}
+
+impl<T> BlockedMatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    /// Blocks of `block` output channels covering `m`.
+    pub(super) fn blocks(m: usize, block: usize) -> usize {
+        (m + block - 1) / block
+    }
+
+    /// Packs each group of a (group, m, k) kernel in (blocks, k, block)
+    /// channel blocks.
+    pub fn pack_kernels(block: usize, kernel: ArrayView3<T>) -> TractResult<Vec<Tensor>> {
+        let (m, k) = (kernel.shape()[1], kernel.shape()[2]);
+        let blocks = Self::blocks(m, block);
+        let packed = kernel
+            .outer_iter()
+            .map(|subkernel| {
+                Array3::from_shape_fn((blocks, k, block), |(b, k, lane)| {
+                    let o = b * block + lane;
+                    if o < m {
+                        subkernel[(o, k)]
+                    } else {
+                        T::zero()
+                    }
+                })
+                .into()
+            })
+            .collect();
+        Ok(packed)
+    }
+}
+
+impl<T> BlockedMatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy + AddAssign + ndarray::LinalgScalar + num_traits::Float,
+{
+    pub(super) fn conv_gemm<'i>(
+        &'i self,
+        packed_input: &'i ArrayView3<'i, T>,
+    ) -> TractResult<(ArrayD<T>, Option<ArrayD<T>>)> {
+        let mut output = ArrayD::<T>::zeros(&*self.output_shape.shape);
+        self.timer.time(ConvPhase::Gemm, || self.products(packed_input, &mut output))?;
+        let result = self.timer.time(ConvPhase::Writeback, || {
+            writeback(&mut output, &self.output_shape, self.bias.as_ref(), self.summary)
+        });
+        self.timer.eval_done();
+        Ok((output, result?))
+    }
+
+    /// The products, without bias.
+    fn products(&self, packed_input: &ArrayView3<T>, output: &mut ArrayD<T>) -> TractResult<()> {
+        let input = packed_input.as_slice().ok_or("Blocked product needs a contiguous input")?;
+        let packed_input_len = packed_input.shape()[2];
+        let (k, n, block) = (self.k, self.n, self.block);
+        if k == 0 || n == 0 {
+            return Ok(());
+        }
+        let n_stride = self.output_shape.n_stride();
+        let c_stride = self.output_shape.c_stride();
+        // flattened output points are w_stride apart in both data formats
+        let point_stride = self.output_shape.w_stride();
+        let output = output.as_slice_mut().unwrap();
+        let mut acc = vec![T::zero(); n * block];
+        for i in 0..self.output_shape.n() {
+            for g in 0..self.group {
+                let data = &input[(self.group * i + g) * packed_input_len..][..k * n];
+                let kernel = self.packed_kernels[g].as_slice::<T>()?;
+                for (b, weights) in kernel.chunks(k * block).enumerate() {
+                    acc.iter_mut().for_each(|a| *a = T::zero());
+                    for (row, w) in data.chunks(n).zip(weights.chunks(block)) {
+                        for (&x, a) in row.iter().zip(acc.chunks_mut(block)) {
+                            for lane in 0..block {
+                                a[lane] += x * w[lane];
+                            }
+                        }
+                    }
+                    let channels = block.min(self.m - b * block);
+                    for lane in 0..channels {
+                        let c = g * self.m + b * block + lane;
+                        let plane = &mut output[n_stride * i + c_stride * c..];
+                        for (p, a) in acc.chunks(block).enumerate() {
+                            plane[p * point_stride] = a[lane];
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<D> Op for BlockedMatMat<D>
+where
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
+{
+    fn name(&self) -> Cow<str> {
+        "BlockedMatMat".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!(
+            "m={} k={} n={} in blocks of {} channels",
+            self.m, self.k, self.n, self.block
+        )))
+    }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let batch = inputs[0].shape.dim(0);
+        let padded_m = Self::blocks(self.m, self.block) * self.block;
+        let params =
+            self.group * self.m * self.k + self.bias.as_ref().map(|b| b.len()).unwrap_or(0);
+        Ok(tvec!(
+            (Cost::FMA(f32::datum_type()), batch * self.group * padded_m * self.k * self.n),
+            (Cost::Params(D::datum_type()), params.to_dim())
+        ))
+    }
+}
+
+impl<D> StatelessOp for BlockedMatMat<D>
+where
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
+{
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let (output, summary) =
+            self.conv_gemm(&input.to_array_view::<D>()?.into_dimensionality()?)?;
+        if let Some(summary) = summary {
+            Ok(tvec!(output.into_arc_tensor(), summary.into_arc_tensor()))
+        } else {
+            Ok(tvec!(output.into_arc_tensor()))
+        }
+    }
+}
+
+impl<D> InferenceRulesOp for BlockedMatMat<D>
+where
+    D: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<D> + num_traits::Float,
+{
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        _s: &mut Solver<'r>,
+        _inputs: &'p [TensorProxy],
+        _outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        unreachable!()
+    }
+}
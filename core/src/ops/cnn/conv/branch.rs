@@ -0,0 +1,193 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::error::ConvError;
+use super::ConvUnary;
+use std::ops::Range;
+
+/// Convolutions reading consecutive channel splits of the same input, each
+/// with its own kernel size, padding and strides, their outputs concatenated
+/// along the channel axis, as in Inception blocks.
+///
+/// The first branch reads the first input channels, the second one the
+/// following ones, and so on. Each branch is lowered with its own geometry,
+/// but the block stays one op: no split and concat around the convolutions.
+#[derive(Debug, Clone)]
+pub struct BranchConv {
+    pub branches: Vec<ConvUnary>,
+    full_input_shape: TVec<TDim>,
+    full_output_shape: TVec<TDim>,
+}
+
+fn channels<T: Datum>(input: &Tensor, axis: usize, range: Range<usize>) -> TractResult<Tensor> {
+    Ok(input.to_array_view::<T>()?.slice_axis(Axis(axis), range.into()).to_owned().into())
+}
+
+fn concat<T: Datum + Copy>(outputs: &[Arc<Tensor>], axis: usize) -> TractResult<Tensor> {
+    let views = outputs.iter().map(|o| o.to_array_view::<T>()).collect::<TractResult<Vec<_>>>()?;
+    Ok(stack(Axis(axis), &views)?.into())
+}
+
+/// Add the channels of `shape` to `full`, checking the other axes agree.
+fn concat_shape(
+    what: &'static str,
+    full: &mut TVec<TDim>,
+    shape: &[TDim],
+    c_axis: usize,
+) -> TractResult<()> {
+    if shape.len() != full.len() {
+        bail!(ConvError::ShapeMismatch { what, expected: full.len(), found: shape.len() })
+    }
+    for (ax, (a, b)) in full.iter_mut().zip(shape.iter()).enumerate() {
+        if ax == c_axis {
+            *a += b.clone();
+        } else if a != b {
+            bail!("BranchConv {} shapes disagree on axis {}: {:?} and {:?}", what, ax, a, b)
+        }
+    }
+    Ok(())
+}
+
+impl BranchConv {
+    /// Check the branches agree on everything but their channels.
+    pub fn new(branches: Vec<ConvUnary>) -> TractResult<BranchConv> {
+        let first = match branches.first() {
+            Some(first) => first,
+            None => bail!("BranchConv needs at least one branch"),
+        };
+        let c_axis = first.data_format.shape(&*first.full_input_shape).c_axis();
+        let mut full_input_shape = first.full_input_shape.clone();
+        let mut full_output_shape = first.full_output_shape.clone();
+        for branch in &branches[1..] {
+            if branch.data_format != first.data_format || branch.f64_output != first.f64_output {
+                bail!("BranchConv branches must share their data format and output type")
+            }
+            concat_shape("branch input", &mut full_input_shape, &branch.full_input_shape, c_axis)?;
+            concat_shape(
+                "branch output",
+                &mut full_output_shape,
+                &branch.full_output_shape,
+                c_axis,
+            )?;
+        }
+        if branches.iter().any(|b| b.summary.is_some() || b.token_output) {
+            bail!("BranchConv branches can produce neither a channel summary nor tokens")
+        }
+        Ok(BranchConv { branches, full_input_shape, full_output_shape })
+    }
+
+    fn c_axis(&self) -> usize {
+        self.branches[0].data_format.shape(&*self.full_input_shape).c_axis()
+    }
+}
+
+impl Op for BranchConv {
+    fn name(&self) -> Cow<str> {
+        "BranchConv".into()
+    }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let mut cost = tvec!();
+        for branch in &self.branches {
+            let shape = branch
+                .full_input_shape
+                .iter()
+                .map(|d| Ok(d.to_integer()? as usize))
+                .collect::<TractResult<TVec<usize>>>()?;
+            let input = TypedTensorInfo {
+                shape: shape.into(),
+                datum_type: inputs[0].datum_type,
+                konst: None,
+            };
+            cost.extend(branch.cost(&[&input])?);
+        }
+        Ok(cost)
+    }
+}
+
+impl StatelessOp for BranchConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let c_axis = self.c_axis();
+        let mut outputs = vec![];
+        let mut start = 0;
+        for branch in &self.branches {
+            let len = branch.full_input_shape[c_axis].to_integer()? as usize;
+            let split =
+                dispatch_datum!(channels(input.datum_type())(&input, c_axis, start..start + len))?;
+            outputs.push(branch.eval(tvec!(split.into_arc_tensor()))?.remove(0));
+            start += len;
+        }
+        let output = dispatch_copy!(concat(outputs[0].datum_type())(&outputs, c_axis))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for BranchConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        if self.branches[0].f64_output {
+            s.equals(&inputs[0].datum_type, DatumType::F32)?;
+            s.equals(&outputs[0].datum_type, DatumType::F64)?;
+        } else {
+            s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        }
+        s.equals(&inputs[0].shape, self.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    /// A conv reading `channels` channels of a 1x`channels`x5x6 input with
+    /// a `k`x`k` kernel, keeping the spatial shape.
+    fn branch(channels: usize, k: usize, padding: PaddingSpec) -> ConvUnary {
+        let input = Tensor::from(ArrayD::<f32>::zeros(&[1, channels, 5, 6][..]));
+        let kernel = ArrayD::from_shape_fn(&[2, channels, k, k][..], |ix| {
+            ((ix[0] * 5 + ix[1] * 3 + ix[2] * 2 + ix[3]) % 7) as f32 / 4.0 - 0.75
+        });
+        let facts = conv_facts(input, kernel);
+        let conv = Conv::new(Default::default(), Default::default(), None, None, padding, None, 1);
+        conv.to_unary(&facts).unwrap().unwrap()
+    }
+
+    #[test]
+    fn branches_are_concatenated() {
+        let mut branches = vec![
+            branch(1, 1, PaddingSpec::Valid),
+            branch(2, 3, PaddingSpec::SameUpper),
+            branch(1, 5, PaddingSpec::SameUpper),
+        ];
+        branches[1].bias = Some(tensor1(&[1.0f32, -2.0]));
+        let op = BranchConv::new(branches.clone()).unwrap();
+        let input = Array4::from_shape_fn((1, 4, 5, 6), |(_, c, y, x)| {
+            ((c * 7 + y * 3 + x) % 11) as f32 / 2.0 - 2.5
+        });
+        let found = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap();
+        let found = found[0].to_array_view::<f32>().unwrap();
+        assert_eq!(found.shape(), &[1, 6, 5, 6]);
+        for (ix, (branch, channels)) in branches.iter().zip(&[0..1, 1..3, 3..4]).enumerate() {
+            let split = input.slice(s![.., channels.clone(), .., ..]).to_owned();
+            let expected = branch.eval(tvec!(split.into_arc_tensor())).unwrap();
+            let expected = expected[0].to_array_view::<f32>().unwrap();
+            assert_eq!(found.slice(s![.., 2 * ix..2 * ix + 2, .., ..]).into_dyn(), expected);
+        }
+    }
+
+    #[test]
+    fn mismatched_output_shape() {
+        let branches = vec![branch(1, 1, PaddingSpec::Valid), branch(2, 3, PaddingSpec::Valid)];
+        assert!(BranchConv::new(branches).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use num_traits::Zero;
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul};
+
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use crate::ops::cnn::Patch;
+use crate::ops::nn::channel_blocks;
+
+/*
+ * Both the input and the output are in channel blocks (NCHWc, see
+ * ops::nn::ToChannelBlocks), the kernel in blocks of input and output
+ * channels:
+ *
+ *   kernel[ob][ib][k][i][o] = weight(ob * block + o, ib * block + i, k)
+ *
+ * `k` running over the kernel spatial positions. For each output point and
+ * input pixel of the patch, an input lane is broadcast against a row of
+ * `block` contiguous weights into `block` contiguous accumulators, and the
+ * accumulators are written as they are to the output block: nothing is
+ * gathered or scattered across channel planes.
+ *
+ * Padding lanes are zero in the input and in the kernel, so they add
+ * nothing and the padding lanes of the output stay zero.
+ */
+
+#[derive(Clone, new)]
+pub struct ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    /// Patch over the spatial axes of a single channel block, its inner
+    /// strides being the block.
+    pub patch: Patch,
+    /// Blocked input shape.
+    pub input_shape: TVec<usize>,
+    /// Blocked output shape.
+    pub output_shape: TVec<usize>,
+    pub block: usize,
+    pub packed_kernel: Arc<Tensor>,
+    /// Bias of each output channel, padded to whole blocks.
+    pub bias: Option<Array1<T>>,
+}
+
+impl<T> fmt::Debug for ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ChannelBlockedConv")
+            .field("patch", &self.patch)
+            .field("input_shape", &self.input_shape)
+            .field("output_shape", &self.output_shape)
+            .field("block", &self.block)
+            .field("bias", &self.bias)
+            .finish()
+    }
+}
+
+impl<T> ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    /// Packs a (output, input * spatial) kernel of `c` input channels in
+    /// channel blocks.
+    pub fn pack_kernel(block: usize, kernel: ArrayView2<T>, c: usize) -> Tensor {
+        let m = kernel.shape()[0];
+        let k_len = kernel.shape()[1] / c;
+        let shape = (channel_blocks(m, block), channel_blocks(c, block), k_len, block, block);
+        Array5::from_shape_fn(shape, |(ob, ib, k, i, o)| {
+            let (o, i) = (ob * block + o, ib * block + i);
+            if o < m && i < c {
+                kernel[(o, i * k_len + k)]
+            } else {
+                T::zero()
+            }
+        })
+        .into()
+    }
+}
+
+impl<T> ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul<Output = T> + Zero + Copy + AddAssign,
+{
+    fn eval_t(&self, input: &Tensor) -> TractResult<Tensor> {
+        if input.shape() != &*self.input_shape {
+            bail!("Expected an input of {:?}, got {:?}", self.input_shape, input.shape());
+        }
+        let input = input.as_slice::<T>()?;
+        let kernel = self.packed_kernel.as_slice::<T>()?;
+        let block = self.block;
+        let (input_blocks, output_blocks) = (self.input_shape[1], self.output_shape[1]);
+        let input_plane = self.input_shape[2..].iter().product::<usize>();
+        let output_plane = self.output_shape[2..].iter().product::<usize>();
+        let kernel_len = self.patch.standard_layout_data_field.len();
+        let mut output = vec![T::zero(); self.output_shape.iter().product()];
+        let mut acc = vec![T::zero(); block];
+        for n in 0..self.input_shape[0] {
+            for ob in 0..output_blocks {
+                let plane = &mut output[(n * output_blocks + ob) * output_plane..][..output_plane];
+                let bias = self.bias.as_ref().map(|b| b.slice(s![ob * block..(ob + 1) * block]));
+                self.patch.visit_output(|scanner| {
+                    match bias {
+                        Some(ref bias) => acc.iter_mut().zip(bias).for_each(|(a, &b)| *a = b),
+                        None => acc.iter_mut().for_each(|a| *a = T::zero()),
+                    }
+                    for ib in 0..input_blocks {
+                        let data = &input[(n * input_blocks + ib) * input_plane..][..input_plane];
+                        let weights =
+                            &kernel[(ob * input_blocks + ib) * kernel_len * block * block..];
+                        for (k, offset) in scanner.valid_offsets_with_indexes() {
+                            let pixel = &data[offset as usize..][..block];
+                            let rows = &weights[k * block * block..][..block * block];
+                            for (&x, w) in pixel.iter().zip(rows.chunks(block)) {
+                                for lane in 0..block {
+                                    acc[lane] += x * w[lane];
+                                }
+                            }
+                        }
+                    }
+                    plane[scanner.output_offset as usize..][..block].copy_from_slice(&acc);
+                });
+            }
+        }
+        Ok(Tensor::from(ArrayD::from_shape_vec(&*self.output_shape, output)?))
+    }
+}
+
+impl<T> Op for ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul<Output = T> + Zero + Copy + AddAssign,
+{
+    fn name(&self) -> Cow<str> {
+        "ChannelBlockedConv".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!(
+            "NCHW{}c, {} to {} channel blocks",
+            self.block, self.input_shape[1], self.output_shape[1]
+        )))
+    }
+
+    fn cost(&self, _inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let fma = self.output_shape.iter().product::<usize>()
+            * self.input_shape[1]
+            * self.block
+            * self.patch.standard_layout_data_field.len();
+        Ok(tvec!(
+            (Cost::FMA(T::datum_type()), fma.to_dim()),
+            (
+                Cost::Params(T::datum_type()),
+                self.packed_kernel.shape().iter().product::<usize>().to_dim()
+            )
+        ))
+    }
+}
+
+impl<T> StatelessOp for ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul<Output = T> + Zero + Copy + AddAssign,
+{
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        Ok(tvec!(self.eval_t(&input)?.into_arc_tensor()))
+    }
+}
+
+impl<T> InferenceRulesOp for ChannelBlockedConv<T>
+where
+    T: Datum + Add + Mul<Output = T> + Zero + Copy + AddAssign,
+{
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, T::datum_type())?;
+        s.equals(&outputs[0].datum_type, T::datum_type())?;
+        s.equals(&inputs[0].shape, ShapeFact::from(&*self.input_shape))?;
+        s.equals(&outputs[0].shape, ShapeFact::from(&*self.output_shape))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use crate::internal::*;
+
+use super::kernel_cache::KernelCache;
+use super::ConvUnary;
+
+impl ConvUnary {
+    /// A copy of this conv, its kernel clamped to `weight_clamp`.
+    pub(super) fn clamp_weights(&self) -> TractResult<ConvUnary> {
+        let mut conv = self.clone();
+        if let Some((min, max)) = conv.options.weight_clamp.take() {
+            let dt = self.kernel.datum_type();
+            conv.kernel = dispatch_floatlike!(Self::clamp_t(dt)(&self.kernel, min, max))?;
+            conv.kernel_cache = KernelCache::default();
+        }
+        Ok(conv)
+    }
+
+    fn clamp_t<T: Datum + num_traits::Float>(
+        kernel: &Tensor,
+        min: f32,
+        max: f32,
+    ) -> TractResult<Tensor> {
+        let cast = |x: f32| num_traits::cast::<f32, T>(x).ok_or("Weight clamp out of range");
+        let (min, max) = (cast(min)?, cast(max)?);
+        Ok(kernel.to_array_view::<T>()?.mapv(|w| w.max(min).min(max)).into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use ndarray::*;
+
+    #[test]
+    fn weight_clamp_leaves_kernel() {
+        let input = Array4::from_shape_fn((1, 2, 5, 4), |(_, c, y, x)| {
+            ((c * 20 + y * 4 + x) % 7) as f32 - 3.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((3, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) % 9) as f32 / 4.0 - 1.0
+        });
+        let clamped = kernel.mapv(|w| w.max(-0.5).min(0.5));
+        let facts = conv_facts(input.clone(), clamped);
+        let expected = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let expected = expected.eval(tvec!(input.clone())).unwrap().remove(0);
+
+        let facts = conv_facts(input.clone(), kernel.clone());
+        let conv = Conv::default().with_weight_clamp(Some((-0.5, 0.5)));
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let found = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert!(found.close_enough(&expected, true));
+        assert_eq!(op.kernel, kernel.into_tensor());
+
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 2, 5, 4))).unwrap();
+        model.chain_default("conv", op).unwrap();
+        let model = model.into_typed().unwrap().into_optimized().unwrap();
+        let found =
+            SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into_tensor())).unwrap();
+        assert!(found[0].close_enough(&expected, true));
+    }
+}
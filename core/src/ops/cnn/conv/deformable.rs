@@ -0,0 +1,337 @@
+use crate::internal::*;
+use ndarray::*;
+use num_traits::{Float, FromPrimitive};
+
+use super::error::ConvError;
+use crate::ops::cnn::{PaddingSpec, Patch, PatchSpec};
+use crate::ops::nn::DataFormat;
+
+/// Deformable convolution: each kernel item reads the input at its regular
+/// grid position shifted by a learned offset, interpolating bilinearly
+/// between the four surrounding pixels (zero outside the image).
+///
+/// Inputs are the NCHW image, the OIHW kernel, and the offsets as
+/// (N, offset_group * kh * kw * 2, H', W'), a (dy, dx) pair per kernel item
+/// and output position. With `mask` (deformable conv v2), a fourth
+/// (N, offset_group * kh * kw, H', W') input scales each sample. The input
+/// channels are split in `offset_group` consecutive groups, each following
+/// its own offsets.
+#[derive(Debug, Clone, new)]
+pub struct DeformableConv {
+    pub padding: PaddingSpec,
+    pub strides: TVec<usize>,
+    pub dilations: TVec<usize>,
+    pub group: usize,
+    pub offset_group: usize,
+    pub mask: bool,
+}
+
+/// Sample `plane` at (y, x), reading zero outside of it.
+fn bilinear<T: Datum + Float + FromPrimitive>(plane: ArrayView2<T>, y: T, x: T) -> T {
+    let (h, w) = plane.dim();
+    let one = T::one();
+    if y <= -one || x <= -one || y >= T::from_usize(h).unwrap() || x >= T::from_usize(w).unwrap() {
+        return T::zero();
+    }
+    let (y0, x0) = (y.floor(), x.floor());
+    let (dy, dx) = (y - y0, x - x0);
+    let (y0, x0) = (y0.to_isize().unwrap(), x0.to_isize().unwrap());
+    let at = |y: isize, x: isize| {
+        if y >= 0 && x >= 0 && (y as usize) < h && (x as usize) < w {
+            plane[(y as usize, x as usize)]
+        } else {
+            T::zero()
+        }
+    };
+    at(y0, x0) * (one - dy) * (one - dx)
+        + at(y0, x0 + 1) * (one - dy) * dx
+        + at(y0 + 1, x0) * dy * (one - dx)
+        + at(y0 + 1, x0 + 1) * dy * dx
+}
+
+fn check_shape(what: &'static str, expected: &[usize], found: &[usize]) -> TractResult<()> {
+    if expected != found {
+        bail!("DeformableConv {} shape: expected {:?}, found {:?}", what, expected, found)
+    }
+    Ok(())
+}
+
+impl DeformableConv {
+    fn patch(&self, input_shape: &[usize], kernel_shape: &[usize]) -> Patch {
+        PatchSpec::for_full_shape(DataFormat::NCHW, input_shape)
+            .with_kernel_shape(kernel_shape[2..].into())
+            .with_dilations(self.dilations.clone())
+            .with_strides(self.strides.clone())
+            .with_padding(self.padding.clone())
+            .into_patch()
+    }
+
+    /// Check the input shapes, and compute the sampling grid.
+    fn check(&self, inputs: &[Arc<Tensor>]) -> TractResult<Patch> {
+        let ranks = ["input rank", "kernel rank", "offsets rank", "mask rank"];
+        for (&what, input) in ranks.iter().zip(inputs) {
+            if input.shape().len() != 4 {
+                bail!(ConvError::ShapeMismatch { what, expected: 4, found: input.shape().len() })
+            }
+        }
+        let (input, kernel) = (inputs[0].shape(), inputs[1].shape());
+        if self.group == 0 || kernel[0] % self.group != 0 {
+            bail!(ConvError::ChannelsNotDivisibleByGroup { channels: kernel[0], group: self.group })
+        }
+        if self.offset_group == 0 || input[1] % self.offset_group != 0 {
+            bail!(ConvError::ChannelsNotDivisibleByGroup {
+                channels: input[1],
+                group: self.offset_group
+            })
+        }
+        if input[1] != kernel[1] * self.group {
+            bail!(ConvError::ShapeMismatch {
+                what: "kernel input channels",
+                expected: input[1],
+                found: kernel[1] * self.group
+            })
+        }
+        let patch = self.patch(input, kernel);
+        let (h, w) = (patch.output_shape[0], patch.output_shape[1]);
+        let samples = self.offset_group * kernel[2] * kernel[3];
+        check_shape("offsets", &[input[0], samples * 2, h, w], inputs[2].shape())?;
+        if self.mask {
+            check_shape("mask", &[input[0], samples, h, w], inputs[3].shape())?;
+        }
+        Ok(patch)
+    }
+
+    fn eval_t<T: Datum + LinalgScalar + Float + FromPrimitive>(
+        &self,
+        inputs: &[Arc<Tensor>],
+        patch: &Patch,
+    ) -> TractResult<Tensor> {
+        let input = inputs[0].to_array_view::<T>()?.into_dimensionality::<Ix4>()?;
+        let kernel = inputs[1].to_array_view::<T>()?.into_dimensionality::<Ix4>()?;
+        let offsets = inputs[2].to_array_view::<T>()?.into_dimensionality::<Ix4>()?;
+        let mask = if self.mask {
+            Some(inputs[3].to_array_view::<T>()?.into_dimensionality::<Ix4>()?)
+        } else {
+            None
+        };
+        let (n, c, _, _) = input.dim();
+        let (co, ci_per_group, kh, kw) = kernel.dim();
+        let kernel_len = kh * kw;
+        let (h, w) = (patch.output_shape[0], patch.output_shape[1]);
+        let co_per_group = co / self.group;
+        let c_per_offset_group = c / self.offset_group;
+        let kernel = kernel.into_shape((self.group, co_per_group, ci_per_group * kernel_len))?;
+        let mut output = Array4::<T>::zeros((n, co, h, w));
+        // im2col matrix of a group, the samples taken at the shifted positions
+        let mut columns = Array2::<T>::zeros((ci_per_group * kernel_len, h * w));
+        for i in 0..n {
+            for g in 0..self.group {
+                for ci in 0..ci_per_group {
+                    let c = g * ci_per_group + ci;
+                    let plane = input.slice(s![i, c, .., ..]);
+                    let og = c / c_per_offset_group;
+                    for (kitem, field) in patch.data_field.outer_iter().enumerate() {
+                        let k = og * kernel_len + kitem;
+                        let mut row = columns.row_mut(ci * kernel_len + kitem);
+                        for y in 0..h {
+                            for x in 0..w {
+                                let sy = (y * self.strides[0]) as isize + field[0];
+                                let sx = (x * self.strides[1]) as isize + field[1];
+                                let sy = T::from_isize(sy).unwrap() + offsets[(i, 2 * k, y, x)];
+                                let sx = T::from_isize(sx).unwrap() + offsets[(i, 2 * k + 1, y, x)];
+                                let mut v = bilinear(plane, sy, sx);
+                                if let Some(mask) = &mask {
+                                    v = v * mask[(i, k, y, x)];
+                                }
+                                row[y * w + x] = v;
+                            }
+                        }
+                    }
+                }
+                let product = kernel.index_axis(Axis(0), g).dot(&columns);
+                output
+                    .slice_mut(s![i, g * co_per_group..(g + 1) * co_per_group, .., ..])
+                    .assign(&product.into_shape((co_per_group, h, w))?);
+            }
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for DeformableConv {
+    fn name(&self) -> Cow<str> {
+        "DeformableConv".into()
+    }
+}
+
+impl StatelessOp for DeformableConv {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let dt = inputs[0].datum_type();
+        for input in &inputs[1..] {
+            if input.datum_type() != dt {
+                bail!(ConvError::DtypeMismatch { expected: dt, found: input.datum_type() })
+            }
+        }
+        let patch = self.check(&inputs)?;
+        let output = dispatch_floatlike!(Self::eval_t(dt)(self, &inputs, &patch))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for DeformableConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 3 + self.mask as usize)?;
+        check_output_arity(&outputs, 1)?;
+        for input in inputs {
+            s.equals(&input.rank, 4)?;
+            s.equals(&input.datum_type, &outputs[0].datum_type)?;
+        }
+        s.equals(&outputs[0].rank, 4)?;
+        s.equals(&outputs[0].shape[0], &inputs[0].shape[0])?;
+        s.equals(&outputs[0].shape[1], &inputs[1].shape[0])?;
+        s.equals(inputs[0].shape[1].bex(), self.group as i32 * inputs[1].shape[1].bex())?;
+        for input in &inputs[2..] {
+            s.equals(&input.shape[0], &inputs[0].shape[0])?;
+            s.equals(&input.shape[2], &outputs[0].shape[2])?;
+            s.equals(&input.shape[3], &outputs[0].shape[3])?;
+        }
+        s.given_2(&inputs[0].shape, &inputs[1].shape, move |s, ishape, kshape| {
+            if let (Ok(kh), Ok(kw)) = (kshape[2].to_integer(), kshape[3].to_integer()) {
+                let dims = self.padding.compute(
+                    &ishape[2..],
+                    &[kh as usize, kw as usize],
+                    &*self.dilations,
+                    &*self.strides,
+                );
+                s.equals(&outputs[0].shape[2], dims[0].output)?;
+                s.equals(&outputs[0].shape[3], dims[1].output)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat};
+
+    fn input() -> Array4<f32> {
+        Array4::from_shape_fn((1, 4, 5, 6), |(_, c, y, x)| ((c * 30 + y * 6 + x) % 7) as f32 - 3.0)
+    }
+
+    fn kernel(co: usize, ci: usize, k: usize) -> Array4<f32> {
+        Array4::from_shape_fn((co, ci, k, k), |(o, i, y, x)| {
+            ((o * 9 + i * 5 + y * 3 + x) % 5) as f32 / 2.0 - 1.0
+        })
+    }
+
+    fn op(padding: PaddingSpec, group: usize, offset_group: usize, mask: bool) -> DeformableConv {
+        DeformableConv::new(padding, tvec!(1, 1), tvec!(1, 1), group, offset_group, mask)
+    }
+
+    fn run(op: &DeformableConv, inputs: TVec<ArrayD<f32>>) -> TractResult<ArrayD<f32>> {
+        let inputs = inputs.into_iter().map(|i| i.into_arc_tensor()).collect();
+        Ok(op.eval(inputs)?.remove(0).into_tensor().into_array::<f32>()?)
+    }
+
+    fn conv(
+        padding: PaddingSpec,
+        group: usize,
+        input: &Array4<f32>,
+        kernel: Array4<f32>,
+    ) -> ArrayD<f32> {
+        let input = input.clone().into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let conv =
+            Conv::new(DataFormat::NCHW, KernelFormat::OIHW, None, None, padding, None, group);
+        let conv = conv.to_unary(&facts).unwrap().unwrap();
+        conv.eval(tvec!(input)).unwrap().remove(0).into_tensor().into_array::<f32>().unwrap()
+    }
+
+    #[test]
+    fn zero_offsets_are_a_conv() {
+        let padding = PaddingSpec::Explicit(tvec!(1, 1), tvec!(1, 1));
+        let input = input();
+        let kernel = kernel(6, 2, 3);
+        let offsets = ArrayD::zeros(&[1, 2 * 2 * 9, 5, 6][..]);
+        let op = op(padding.clone(), 2, 2, false);
+        let found = run(&op, tvec!(input.clone().into_dyn(), kernel.clone().into_dyn(), offsets));
+        assert_eq!(found.unwrap(), conv(padding, 2, &input, kernel));
+    }
+
+    #[test]
+    fn mask_scales_samples() {
+        let input = input();
+        let kernel = kernel(3, 4, 3);
+        let offsets = ArrayD::zeros(&[1, 2 * 9, 3, 4][..]);
+        let mask = ArrayD::from_elem(&[1, 9, 3, 4][..], 0.5);
+        let op = op(PaddingSpec::Valid, 1, 1, true);
+        let found =
+            run(&op, tvec!(input.clone().into_dyn(), kernel.clone().into_dyn(), offsets, mask));
+        let expected = conv(PaddingSpec::Valid, 1, &input, kernel) / 2.0;
+        assert!(found.unwrap().all_close(&expected, 1e-5));
+    }
+
+    #[test]
+    fn integer_offsets_shift_the_input() {
+        let input = input();
+        let kernel = kernel(2, 4, 3);
+        // one pixel right and down for every item
+        let offsets = ArrayD::from_elem(&[1, 2 * 9, 3, 4][..], 1.0);
+        let op = op(PaddingSpec::Valid, 1, 1, false);
+        let found = run(&op, tvec!(input.clone().into_dyn(), kernel.clone().into_dyn(), offsets));
+        let mut shifted = Array4::zeros((1, 4, 5, 6));
+        shifted.slice_mut(s![.., .., ..4, ..5]).assign(&input.slice(s![.., .., 1.., 1..]));
+        assert_eq!(found.unwrap(), conv(PaddingSpec::Valid, 1, &shifted, kernel));
+    }
+
+    #[test]
+    fn fractional_offsets_interpolate() {
+        let input = input();
+        let kernel = kernel(1, 4, 1);
+        // a quarter pixel down, half a pixel left, for the second offset
+        // group only
+        let mut offsets = ArrayD::zeros(&[1, 4, 5, 6][..]);
+        offsets.slice_mut(s![.., 2, .., ..]).fill(0.25);
+        offsets.slice_mut(s![.., 3, .., ..]).fill(-0.5);
+        let op = op(PaddingSpec::Valid, 1, 2, false);
+        let found = run(&op, tvec!(input.clone().into_dyn(), kernel.clone().into_dyn(), offsets));
+        let at = |c: usize, y: usize, x: isize| {
+            if x < 0 || y >= 5 {
+                0.0
+            } else {
+                input[(0, c, y, x as usize)]
+            }
+        };
+        let expected = Array4::from_shape_fn((1, 1, 5, 6), |(_, _, y, x)| {
+            (0..4)
+                .map(|c| {
+                    let v = if c < 2 {
+                        input[(0, c, y, x)]
+                    } else {
+                        let x = x as isize;
+                        0.75 * 0.5 * (at(c, y, x - 1) + at(c, y, x))
+                            + 0.25 * 0.5 * (at(c, y + 1, x - 1) + at(c, y + 1, x))
+                    };
+                    kernel[(0, c, 0, 0)] * v
+                })
+                .sum::<f32>()
+        });
+        assert!(found.unwrap().all_close(&expected.into_dyn(), 1e-5));
+    }
+
+    #[test]
+    fn offsets_shape_is_checked() {
+        let offsets = ArrayD::zeros(&[1, 2 * 9, 5, 6][..]);
+        let op = op(PaddingSpec::Valid, 1, 1, false);
+        let inputs = tvec!(input().into_dyn(), kernel(2, 4, 3).into_dyn(), offsets);
+        assert!(run(&op, inputs).is_err());
+    }
+}
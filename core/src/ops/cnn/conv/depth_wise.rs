@@ -43,8 +43,10 @@ where
         let iptr = img.as_ptr();
         let mut output = unsafe { ArrayD::<T>::uninitialized(&*self.output_shape.shape) };
         let optr = output.as_mut_ptr();
-        let k_stride_o = self.kernel_chw.strides()[0];
-        let k_stride_i = self.kernel_chw.strides()[1];
+        // kernel_chw is (input channel, multiplier, kernel field): input
+        // channel c feeds output channels c * mult to (c + 1) * mult
+        let k_stride_c = self.kernel_chw.strides()[0];
+        let k_stride_m = self.kernel_chw.strides()[1];
         let mult = self.output_shape.c() / self.input_shape.c();
         unsafe {
             self.patch.visit_output(|visitor| {
@@ -59,7 +61,7 @@ where
                             let kptr = self
                                 .kernel_chw
                                 .as_ptr()
-                                .offset(k_stride_i * c as isize + k_stride_o * m as isize);
+                                .offset(k_stride_c * c as isize + k_stride_m * m as isize);
                             let mut sum = T::zero();
                             for (ix, v) in visitor.valid_offsets_with_indexes() {
                                 let k = *kptr.offset(ix as isize);
@@ -0,0 +1,419 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use super::error::ConvError;
+use super::im2col::Im2Col;
+use super::kernel_cache::KernelCache;
+use super::summary::writeback;
+use super::ConvUnary;
+use crate::ops::cnn::conv::KernelFormat;
+use crate::ops::nn::DataFormat;
+
+/// Convolution of f32 activations by int8 weights with per-channel f32
+/// scales.
+///
+/// Weights are dequantized one group at a time into an f32 scratch buffer,
+/// then packed and multiplied with the regular f32 kernels, so the kernel
+/// stays four times smaller than its f32 counterpart.
+#[derive(Debug, Clone, new)]
+pub struct DequantConv {
+    /// Geometry, i8 kernel and f32 bias.
+    pub conv: ConvUnary,
+    /// One scale for the whole kernel, or one per output channel.
+    pub kernel_scales: TVec<f32>,
+}
+
+impl DequantConv {
+    /// Quantize the kernel of a f32 convolution, using symmetric per-channel
+    /// scales.
+    pub fn quantize(conv: &ConvUnary) -> TractResult<DequantConv> {
+        let output_channels = conv.output_channels();
+        let m = output_channels / conv.group;
+        let grouped = conv.kernel_as_group_o_ihw::<f32>()?;
+        let kernel_scales: TVec<f32> = grouped
+            .outer_iter()
+            .flat_map(|g| {
+                g.outer_iter()
+                    .map(|row| row.iter().fold(0.0f32, |acc, x| acc.max(x.abs())) / 127.0)
+                    .collect::<Vec<_>>()
+            })
+            .map(|s| if s == 0.0 { 1.0 } else { s })
+            .collect();
+        let kernel = conv.kernel.to_array_view::<f32>()?;
+        let rank = kernel.ndim();
+        let ci_per_group = kernel.shape()[rank - 2] / conv.group;
+        let quantized = ArrayD::from_shape_fn(kernel.shape(), |coords| {
+            let channel = match conv.kernel_fmt {
+                KernelFormat::OIHW => coords[0],
+                KernelFormat::HWIO => coords[rank - 2] / ci_per_group * m + coords[rank - 1],
+            };
+            (kernel[coords.slice()] / kernel_scales[channel]).round().clamp(-127.0, 127.0) as i8
+        });
+        let mut conv = conv.clone();
+        conv.kernel = quantized.into();
+        conv.kernel_cache = KernelCache::default();
+        Ok(DequantConv { conv, kernel_scales })
+    }
+
+    fn scale(&self, channel: usize) -> f32 {
+        if self.kernel_scales.len() == 1 {
+            self.kernel_scales[0]
+        } else {
+            self.kernel_scales[channel]
+        }
+    }
+
+    fn eval_f32(&self, input: &Tensor) -> TractResult<Tensor> {
+        let output_channels = self.conv.output_channels();
+        if self.kernel_scales.len() != 1 && self.kernel_scales.len() != output_channels {
+            bail!(ConvError::ShapeMismatch {
+                what: "kernel scales",
+                expected: output_channels,
+                found: self.kernel_scales.len()
+            });
+        }
+        eval_upcast(&self.conv, input, |channel, w: i8| w as f32 * self.scale(channel))
+    }
+}
+
+/// Convolution of f32 activations by f16 weights.
+///
+/// Like `DequantConv`, weights are upcast one group at a time before
+/// packing, so the kernel takes half the memory of its f32 counterpart
+/// while accumulation stays in f32.
+#[derive(Debug, Clone, new)]
+pub struct HalfKernelConv {
+    /// Geometry, f16 kernel and f32 bias.
+    pub conv: ConvUnary,
+}
+
+impl HalfKernelConv {
+    /// Convert the kernel of a f32 convolution to f16.
+    pub fn from_f32(conv: &ConvUnary) -> TractResult<HalfKernelConv> {
+        let kernel = conv.kernel.to_array_view::<f32>()?;
+        let mut conv = conv.clone();
+        conv.kernel = kernel.mapv(|x| f16(half::f16::from_f32(x))).into();
+        conv.kernel_cache = KernelCache::default();
+        Ok(HalfKernelConv { conv })
+    }
+}
+
+impl Op for HalfKernelConv {
+    fn name(&self) -> Cow<str> {
+        "HalfKernelConv".into()
+    }
+}
+
+impl StatelessOp for HalfKernelConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = eval_upcast(&self.conv, &input, |_, w: f16| w.0.to_f32())?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for HalfKernelConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::F32)?;
+        s.equals(&outputs[0].datum_type, DatumType::F32)?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.conv.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+/// Convolution of f32 activations by bf16 weights.
+///
+/// bf16 keeps the exponent of f32 and drops 16 bits of its mantissa: the
+/// kernel takes half the memory of its f32 counterpart, like with
+/// `HalfKernelConv`, but large and tiny weights keep their range. Weights
+/// are upcast one group at a time before packing, and accumulation stays in
+/// f32.
+///
+/// tract has no bf16 datum type: the kernel holds the bf16 bit patterns as
+/// u16.
+#[derive(Debug, Clone, new)]
+pub struct BFloat16KernelConv {
+    /// Geometry, bf16 kernel as u16 and f32 bias.
+    pub conv: ConvUnary,
+}
+
+impl BFloat16KernelConv {
+    /// Convert the kernel of a f32 convolution to bf16, rounding to nearest.
+    pub fn from_f32(conv: &ConvUnary) -> TractResult<BFloat16KernelConv> {
+        let kernel = conv.kernel.to_array_view::<f32>()?;
+        let mut conv = conv.clone();
+        conv.kernel = kernel.mapv(|x| half::bf16::from_f32(x).to_bits()).into();
+        conv.kernel_cache = KernelCache::default();
+        Ok(BFloat16KernelConv { conv })
+    }
+}
+
+impl Op for BFloat16KernelConv {
+    fn name(&self) -> Cow<str> {
+        "BFloat16KernelConv".into()
+    }
+}
+
+impl StatelessOp for BFloat16KernelConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output =
+            eval_upcast(&self.conv, &input, |_, w: u16| half::bf16::from_bits(w).to_f32())?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for BFloat16KernelConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::F32)?;
+        s.equals(&outputs[0].datum_type, DatumType::F32)?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.conv.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+/// Run `conv` on f32 input, upcasting its `K` kernel to f32 one group at a
+/// time. `upcast` gets the output channel and the weight.
+fn eval_upcast<K: Datum + Copy>(
+    conv: &ConvUnary,
+    input: &Tensor,
+    upcast: impl Fn(usize, K) -> f32,
+) -> TractResult<Tensor> {
+    if conv.kernel.datum_type() != K::datum_type() {
+        bail!(ConvError::DtypeMismatch {
+            expected: K::datum_type(),
+            found: conv.kernel.datum_type()
+        });
+    }
+    if input.datum_type() != DatumType::F32 {
+        bail!(ConvError::DtypeMismatch { expected: DatumType::F32, found: input.datum_type() });
+    }
+    let patch = conv.patch(input.shape());
+    patch.check_pad_mode()?;
+    let input_shape = conv.data_format.shape(input.shape().into());
+    let output_channels = conv.output_channels();
+    let output_shape =
+        conv.data_format.from_n_c_hw(input_shape.n(), output_channels, &*patch.output_shape);
+    let group = conv.group;
+    let kernel = conv.kernel_as_group_o_ihw::<K>()?;
+    let m = output_channels / group;
+    let k = kernel.shape()[2];
+    let n = patch.output_shape.iter().cloned().product::<usize>();
+
+    let mm = f32::packed_mat_mul(m, k, n);
+    let b_pack = mm.b_pack();
+    let packed_b_len = b_pack.len();
+    let ci_per_group = input_shape.c() / group;
+    let im2col = Im2Col::new(patch, input_shape.clone(), m, k, n, group, ci_per_group, b_pack);
+    let packed_input = im2col.im2col(&input.to_array_view::<f32>()?)?;
+    let packed_input = packed_input.as_slice::<f32>()?;
+
+    let (rsc, csc) = match output_shape.fmt {
+        DataFormat::NHWC => (1, output_channels as isize),
+        DataFormat::NCHW => (n as isize, 1),
+    };
+    let mut scratch = vec![0.0f32; m * k];
+    let mut packed_a = unsafe {
+        Tensor::uninitialized_aligned::<f32>(&[mm.packed_a_len()], mm.packed_a_alignment())?
+    };
+    let mut output = ArrayD::<f32>::zeros(&*output_shape.shape);
+    for g in 0..group {
+        for (row, weights) in kernel.index_axis(Axis(0), g).outer_iter().enumerate() {
+            for (x, w) in scratch[row * k..][..k].iter_mut().zip(weights.iter()) {
+                *x = upcast(g * m + row, *w);
+            }
+        }
+        mm.pack_a(packed_a.as_slice_mut::<f32>()?.as_mut_ptr(), scratch.as_ptr(), k as isize, 1);
+        for i in 0..input_shape.n() {
+            unsafe {
+                let output_i_g = output.as_mut_ptr().offset(
+                    (output_shape.n_stride() * i + output_shape.c_stride() * m * g) as isize,
+                );
+                mm.mat_mul_prepacked(
+                    packed_a.as_ptr::<f32>()?,
+                    packed_input[(group * i + g) * packed_b_len..].as_ptr(),
+                    output_i_g,
+                    rsc,
+                    csc,
+                );
+            }
+        }
+    }
+    let bias = conv.bias_reshaped::<f32>(&output_shape)?;
+    writeback(&mut output, &output_shape, bias.as_ref(), None)?;
+    Ok(output.into())
+}
+
+impl Op for DequantConv {
+    fn name(&self) -> Cow<str> {
+        "DequantConv".into()
+    }
+}
+
+impl StatelessOp for DequantConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        Ok(tvec!(self.eval_f32(&input)?.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for DequantConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::F32)?;
+        s.equals(&outputs[0].datum_type, DatumType::F32)?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.conv.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    fn check(conv: Conv, input_shape: &[usize], kernel_shape: &[usize]) {
+        let len = input_shape.iter().product::<usize>();
+        let input = ArrayD::from_shape_vec(
+            input_shape,
+            (0..len).map(|i| ((i * 37 % 101) as f32 - 50.0) / 25.0).collect(),
+        )
+        .unwrap()
+        .into_arc_tensor();
+        let klen = kernel_shape.iter().product::<usize>();
+        let kernel = ArrayD::from_shape_vec(
+            kernel_shape,
+            (0..klen).map(|i| ((i * 53 % 97) as f32 - 48.0) / 100.0).collect(),
+        )
+        .unwrap()
+        .into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(Array1::from_shape_fn(unary.output_channels(), |c| c as f32).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+        let dequant = DequantConv::quantize(&unary).unwrap();
+        assert_eq!(dequant.conv.kernel.datum_type(), DatumType::I8);
+        let found = dequant.eval(tvec!(input)).unwrap().remove(0);
+        let expected = expected.to_array_view::<f32>().unwrap();
+        let found = found.to_array_view::<f32>().unwrap();
+        assert_eq!(expected.shape(), found.shape());
+        let max = expected.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        for (e, f) in expected.iter().zip(found.iter()) {
+            assert!((e - f).abs() < max * 0.02, "expected {} found {}", e, f);
+        }
+    }
+
+    #[test]
+    fn half_kernel_matches_f32() {
+        let input = Array4::from_shape_fn((2, 4, 5, 5), |(n, c, y, x)| {
+            ((n * 100 + c * 25 + y * 5 + x) * 37 % 101) as f32 / 25.0 - 2.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((6, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) * 53 % 97) as f32 / 100.0 - 0.48
+        });
+        let mut conv = Conv::default();
+        conv.group = 2;
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(Array1::from_shape_fn(6, |c| c as f32).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+        let half = HalfKernelConv::from_f32(&unary).unwrap();
+        let bytes = |t: &Tensor| t.shape().iter().product::<usize>() * t.datum_type().size_of();
+        assert_eq!(bytes(&half.conv.kernel) * 2, bytes(&unary.kernel));
+        let found = half.eval(tvec!(input)).unwrap().remove(0);
+        assert_close!(*found, *expected);
+    }
+
+    #[test]
+    fn bf16_kernel_keeps_f32_range() {
+        let input = Array4::from_shape_fn((1, 5, 5, 4), |(n, y, x, c)| {
+            ((n * 100 + c * 25 + y * 5 + x) * 37 % 101) as f32 / 25.0 - 2.0
+        })
+        .into_arc_tensor();
+        // weights far out of the f16 range on some output channels
+        let kernel = Array4::from_shape_fn((3, 3, 4, 3), |(y, x, c, o)| {
+            let w = ((o * 36 + c * 9 + y * 3 + x) * 53 % 97) as f32 / 100.0 - 0.48;
+            w * [1.0, 1e6, 1e-7][o]
+        });
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            1,
+        );
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(arr1(&[1.0f32, -1.0, 0.0]).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+        let bf16 = BFloat16KernelConv::from_f32(&unary).unwrap();
+        let bytes = |t: &Tensor| t.shape().iter().product::<usize>() * t.datum_type().size_of();
+        assert_eq!(bytes(&bf16.conv.kernel) * 2, bytes(&unary.kernel));
+        let found = bf16.eval(tvec!(input)).unwrap().remove(0);
+        let expected = expected.to_array_view::<f32>().unwrap();
+        let found = found.to_array_view::<f32>().unwrap();
+        for o in 0..3 {
+            let expected = expected.index_axis(Axis(3), o);
+            let found = found.index_axis(Axis(3), o);
+            let max = expected.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+            for (e, f) in expected.iter().zip(found.iter()) {
+                assert!((e - f).abs() <= max * 0.01, "channel {}: {} vs {}", o, e, f);
+            }
+        }
+    }
+
+    #[test]
+    fn dequant_nchw_grouped() {
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            2,
+        );
+        check(conv, &[2, 4, 5, 5], &[6, 2, 3, 3]);
+    }
+
+    #[test]
+    fn dequant_nhwc_hwio() {
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            None,
+            None,
+            PaddingSpec::Valid,
+            None,
+            1,
+        );
+        check(conv, &[1, 5, 5, 3], &[3, 3, 3, 4]);
+    }
+}
@@ -0,0 +1,100 @@
+use super::ConvStrategy;
+use crate::internal::*;
+use crate::ops::nn::DataFormat;
+use std::fmt;
+
+/// Convolution specific failures, convertible into `TractError`.
+///
+/// Downstream code can get them back by matching on
+/// `TractErrorKind::Conv(..)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConvError {
+    /// Two dimensions (ranks, channel counts, bias length...) do not agree.
+    ShapeMismatch {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    ChannelsNotDivisibleByGroup {
+        channels: usize,
+        group: usize,
+    },
+    InvalidStride {
+        strides: TVec<usize>,
+        spatial_rank: usize,
+    },
+    UnsupportedLayout {
+        format: DataFormat,
+        rank: usize,
+    },
+    DtypeMismatch {
+        expected: DatumType,
+        found: DatumType,
+    },
+    /// A forced strategy can not lower the convolution.
+    UnsupportedStrategy {
+        strategy: ConvStrategy,
+        reason: &'static str,
+    },
+    /// The im2col scratch does not fit `Conv::with_max_scratch_bytes`, even
+    /// one output row at a time.
+    ScratchBudget {
+        required: usize,
+        allowed: usize,
+    },
+    /// The output is beyond tolerance of the golden attached to the conv.
+    GoldenMismatch {
+        max_abs_diff: f32,
+        tolerance: f32,
+    },
+    /// More than one problem, see `validate_config`.
+    Several {
+        problems: Vec<ConvError>,
+    },
+}
+
+impl fmt::Display for ConvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvError::ShapeMismatch { what, expected, found } => {
+                write!(f, "{}: expected {}, found {}", what, expected, found)
+            }
+            ConvError::ChannelsNotDivisibleByGroup { channels, group } => {
+                write!(f, "channels {} not divisible by group {}", channels, group)
+            }
+            ConvError::InvalidStride { strides, spatial_rank } => write!(
+                f,
+                "invalid strides {:?}, expected {} strides greater than zero",
+                strides, spatial_rank
+            ),
+            ConvError::UnsupportedLayout { format, rank } => {
+                write!(f, "unsupported layout {:?} for an input of rank {}", format, rank)
+            }
+            ConvError::DtypeMismatch { expected, found } => {
+                write!(f, "expected {:?} data, found {:?}", expected, found)
+            }
+            ConvError::UnsupportedStrategy { strategy, reason } => {
+                write!(f, "can not use {:?}: {}", strategy, reason)
+            }
+            ConvError::ScratchBudget { required, allowed } => write!(
+                f,
+                "im2col needs {} bytes of scratch, the budget is {} bytes",
+                required, allowed
+            ),
+            ConvError::GoldenMismatch { max_abs_diff, tolerance } => write!(
+                f,
+                "output differs from its golden by up to {}, the tolerance is {}",
+                max_abs_diff, tolerance
+            ),
+            ConvError::Several { problems } => {
+                write!(f, "{} problems: ", problems.len())?;
+                for (ix, problem) in problems.iter().enumerate() {
+                    write!(f, "{}{}", if ix > 0 { "; " } else { "" }, problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConvError {}
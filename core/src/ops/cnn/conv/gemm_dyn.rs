@@ -0,0 +1,142 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use super::blocked::BlockedMatMat;
+use super::mat_mat::MatMat;
+use super::vec_mat::VecMat;
+use super::{ConvError, ConvUnary};
+
+/// The product half of an im2col convolution, for a datum type only known
+/// at run time.
+///
+/// `MatMat`, `VecMat` and `BlockedMatMat` are monomorphized on their element type, and read
+/// the packed input as an array of it. This wrapper dispatches on the datum
+/// type of the packed input instead, so a loop driving a graph of mixed
+/// types does not have to name it.
+#[derive(Debug, Clone)]
+pub struct ConvGemmDyn {
+    datum_type: DatumType,
+    gemm: Box<Op>,
+}
+
+impl ConvGemmDyn {
+    /// Lower `conv` for an input of this shape. Returns the im2col op, the
+    /// shape of the packed input it produces, and the product.
+    pub fn from_conv(
+        conv: &ConvUnary,
+        input_full_shape: &[usize],
+    ) -> TractResult<(Box<Op>, TVec<usize>, ConvGemmDyn)> {
+        let datum_type = conv.kernel.datum_type();
+        let (im2col, shape, gemm) = dispatch_floatlike!(ConvUnary::to_boxed_im2col_pair(
+            datum_type
+        )(conv, input_full_shape))?;
+        Ok((im2col, shape, ConvGemmDyn { datum_type, gemm }))
+    }
+
+    /// Datum type of the packed inputs and outputs.
+    pub fn datum_type(&self) -> DatumType {
+        self.datum_type
+    }
+
+    /// Multiply the kernel with the packed input. The output comes first,
+    /// followed by the channel summary if the convolution has one.
+    pub fn eval(&self, packed_input: &Tensor) -> TractResult<TVec<Arc<Tensor>>> {
+        if packed_input.datum_type() != self.datum_type {
+            bail!(ConvError::DtypeMismatch {
+                expected: self.datum_type,
+                found: packed_input.datum_type()
+            });
+        }
+        dispatch_floatlike!(Self::eval_t(self.datum_type)(self, packed_input))
+    }
+
+    fn eval_t<T>(&self, packed_input: &Tensor) -> TractResult<TVec<Arc<Tensor>>>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let packed = packed_input.to_array_view::<T>()?.into_dimensionality::<Ix3>()?;
+        let (output, summary) = if let Some(mat_mat) = self.gemm.downcast_ref::<MatMat<T>>() {
+            let mut c_panel = vec![T::zero(); mat_mat.mm.c_panel_len()];
+            mat_mat.conv_gemm(&packed, None, &mut c_panel)?
+        } else if let Some(vec_mat) = self.gemm.downcast_ref::<VecMat<T>>() {
+            vec_mat.conv_gemm(&packed)?
+        } else if let Some(blocked) = self.gemm.downcast_ref::<BlockedMatMat<T>>() {
+            blocked.conv_gemm(&packed)?
+        } else {
+            bail!("Unexpected conv product {}", self.gemm.name())
+        };
+        let mut outputs = tvec!(output.into_arc_tensor());
+        if let Some(summary) = summary {
+            outputs.push(summary.into_arc_tensor());
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+
+    fn conv(input: &Arc<Tensor>, kernel: Arc<Tensor>) -> ConvUnary {
+        let facts = conv_facts(input.clone(), kernel);
+        Conv::default().to_unary(&facts).unwrap().unwrap()
+    }
+
+    /// Drive a conv from tensors only, as a dynamically typed loop would.
+    fn run_dyn(conv: &ConvUnary, input: Arc<Tensor>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (im2col, _, gemm) = ConvGemmDyn::from_conv(conv, input.shape())?;
+        let packed = im2col.as_stateless().unwrap().eval(tvec!(input))?.remove(0);
+        gemm.eval(&packed)
+    }
+
+    #[test]
+    fn dispatches_on_datum_type() {
+        let inputs = [
+            rctensor4(&[[
+                [[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]],
+                [[1.0, 0.0, -1.0], [2.0, 1.0, 0.0]],
+            ]]),
+            rctensor4(&[[
+                [[0.0f64, 1.0, 2.0], [3.0, 4.0, 5.0]],
+                [[1.0, 0.0, -1.0], [2.0, 1.0, 0.0]],
+            ]]),
+        ];
+        let kernels = [
+            rctensor4(&[[[[1.0f32, 2.0]], [[0.5, -1.0]]], [[[-2.0f32, 0.0]], [[1.0, 1.0]]]]),
+            rctensor4(&[[[[1.0f64, 2.0]], [[0.5, -1.0]]], [[[-2.0f64, 0.0]], [[1.0, 1.0]]]]),
+        ];
+        for (input, kernel) in inputs.iter().zip(kernels.iter()) {
+            let conv = conv(input, kernel.clone());
+            let expected = conv.eval(tvec!(input.clone())).unwrap();
+            let found = run_dyn(&conv, input.clone()).unwrap();
+            assert_eq!(found[0].datum_type(), input.datum_type());
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn single_output_channel() {
+        let input = rctensor4(&[[[[0.0f64, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let conv = conv(&input, rctensor4(&[[[[1.0f64, -1.0]]]]));
+        let found = run_dyn(&conv, input.clone()).unwrap();
+        assert_eq!(found, conv.eval(tvec!(input)).unwrap());
+    }
+
+    #[test]
+    fn packed_input_of_another_type() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0]]]]);
+        let conv = conv(&input, rctensor4(&[[[[1.0f32]]]]));
+        let (_, shape, gemm) = ConvGemmDyn::from_conv(&conv, input.shape()).unwrap();
+        assert_eq!(gemm.datum_type(), DatumType::F32);
+        let packed = Tensor::from(ArrayD::<f64>::zeros(&*shape));
+        let e = gemm.eval(&packed).unwrap_err();
+        assert!(e.to_string().contains("F64"), "{}", e);
+    }
+}
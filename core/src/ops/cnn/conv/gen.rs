@@ -1,9 +1,11 @@
 use crate::internal::*;
 
-use super::ConvUnary;
+use super::unary::MAX_FOLDED_OUTPUT_LEN;
+use super::validate::{kernel_channels, validate_config};
+use super::{ConvError, ConvOptions, ConvStrategy, ConvUnary, KernelGroupLayout, KernelPacking};
 use crate::dim::DimLike;
 use crate::ops::cnn::conv::KernelFormat;
-use crate::ops::cnn::PaddingSpec;
+use crate::ops::cnn::{PaddingSpec, Window};
 use crate::ops::nn::DataFormat;
 use std::borrow::Borrow;
 
@@ -12,10 +14,16 @@ pub struct Conv {
     pub(super) data_format: DataFormat,
     pub(super) kernel_fmt: KernelFormat,
     pub(super) dilations: Option<TVec<usize>>,
-    kernel_shape: Option<TVec<usize>>,
+    pub(super) kernel_shape: Option<TVec<usize>>,
     pub(super) padding: PaddingSpec,
     pub(super) strides: Option<TVec<usize>>,
     pub(super) group: usize,
+    #[new(default)]
+    pub(super) kernel_group_layout: KernelGroupLayout,
+    #[new(default)]
+    pub(super) options: ConvOptions,
+    #[new(default)]
+    pub(super) independent_axes: usize,
 }
 
 impl ::std::default::Default for Conv {
@@ -28,32 +36,249 @@ impl ::std::default::Default for Conv {
             padding: PaddingSpec::default(),
             strides: None,
             group: 1,
+            kernel_group_layout: KernelGroupLayout::Contiguous,
+            options: ConvOptions::default(),
+            independent_axes: 0,
         }
     }
 }
 
+/// Configuration of a `Conv`, as plain data.
+///
+/// It holds everything but the weights: kernel and bias are inputs of the
+/// op, to be wired again from wherever they are stored when the op is rebuilt
+/// with `Conv::from_config`. Serializable with the `serialize` feature.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ConvConfig {
+    pub data_format: DataFormat,
+    pub kernel_format: KernelFormat,
+    pub dilations: Option<Vec<usize>>,
+    pub kernel_shape: Option<Vec<usize>>,
+    pub padding: PaddingSpec,
+    pub strides: Option<Vec<usize>>,
+    pub group: usize,
+    pub kernel_group_layout: KernelGroupLayout,
+    #[cfg_attr(feature = "serialize", serde(flatten))]
+    pub options: ConvOptions,
+    pub independent_axes: usize,
+}
+
 impl Conv {
+    /// Rebuild a convolution from its configuration, checking it is
+    /// consistent first.
+    pub fn from_config(config: &ConvConfig) -> TractResult<Conv> {
+        if config.group == 0 {
+            bail!("Conv group must be at least 1");
+        }
+        if let Some(strides) = &config.strides {
+            if strides.iter().any(|&s| s == 0) {
+                bail!(ConvError::InvalidStride {
+                    strides: strides.iter().cloned().collect(),
+                    spatial_rank: strides.len(),
+                });
+            }
+        }
+        let explicit = match &config.padding {
+            PaddingSpec::Explicit(before, after) => {
+                if before.len() != after.len() {
+                    bail!(ConvError::ShapeMismatch {
+                        what: "padding after",
+                        expected: before.len(),
+                        found: after.len(),
+                    });
+                }
+                Some(before.len())
+            }
+            _ => None,
+        };
+        let ranks = [
+            ("strides", config.strides.as_ref().map(|s| s.len())),
+            ("dilations", config.dilations.as_ref().map(|d| d.len())),
+            ("kernel shape", config.kernel_shape.as_ref().map(|k| k.len())),
+            ("explicit padding", explicit),
+        ];
+        let mut spatial_rank = None;
+        for &(what, rank) in &ranks {
+            match (spatial_rank, rank) {
+                (Some(expected), Some(found)) if expected != found => {
+                    bail!(ConvError::ShapeMismatch { what, expected, found })
+                }
+                (None, Some(_)) => spatial_rank = rank,
+                _ => (),
+            }
+        }
+        if config.dilations.as_ref().map(|d| d.contains(&0)).unwrap_or(false) {
+            bail!("Conv dilations must be at least 1, got {:?}", config.dilations);
+        }
+        if config.options.kernel_packing == KernelPacking::ChannelBlocks(0) {
+            bail!("Conv kernel channel blocks must hold at least 1 channel");
+        }
+        if let Some((min, max)) = config.options.weight_clamp {
+            if !(min <= max) {
+                bail!("Conv weight clamp must be an ordered range, got {} to {}", min, max);
+            }
+        }
+        let to_tvec = |v: &Option<Vec<usize>>| v.as_ref().map(|v| v.iter().cloned().collect());
+        Ok(Conv {
+            data_format: config.data_format,
+            kernel_fmt: config.kernel_format,
+            dilations: to_tvec(&config.dilations),
+            kernel_shape: to_tvec(&config.kernel_shape),
+            padding: config.padding.clone(),
+            strides: to_tvec(&config.strides),
+            group: config.group,
+            kernel_group_layout: config.kernel_group_layout,
+            options: config.options.clone(),
+            independent_axes: config.independent_axes,
+        })
+    }
+
+    /// The configuration of this convolution, see `ConvConfig`.
+    pub fn to_config(&self) -> ConvConfig {
+        let to_vec = |v: &Option<TVec<usize>>| v.as_ref().map(|v| v.to_vec());
+        ConvConfig {
+            data_format: self.data_format,
+            kernel_format: self.kernel_fmt,
+            dilations: to_vec(&self.dilations),
+            kernel_shape: to_vec(&self.kernel_shape),
+            padding: self.padding.clone(),
+            strides: to_vec(&self.strides),
+            group: self.group,
+            kernel_group_layout: self.kernel_group_layout,
+            options: self.options.clone(),
+            independent_axes: self.independent_axes,
+        }
+    }
+
+    /// Set all the lowering options at once, see `ConvOptions`.
+    pub fn with_options(self, options: ConvOptions) -> Conv {
+        Conv { options, ..self }
+    }
+
+    /// Force the implementation codegen will pick.
+    pub fn with_strategy(self, strategy: ConvStrategy) -> Conv {
+        Conv { options: ConvOptions { strategy, ..self.options }, ..self }
+    }
+
+    /// Declare the ordering of the kernel channels across groups.
+    pub fn with_kernel_group_layout(self, kernel_group_layout: KernelGroupLayout) -> Conv {
+        Conv { kernel_group_layout, ..self }
+    }
+
+    /// Trade speed for bit-reproducible results: the lowered products never
+    /// split across threads, whatever the host, so every output is summed in
+    /// the same order on every run.
+    ///
+    /// This only covers the convolution loops. The matrix product kernel
+    /// must be order-stable too: pin it rather than let `tract_linalg::tune`
+    /// pick one per CPU.
+    pub fn with_deterministic(self, deterministic: bool) -> Conv {
+        Conv { options: ConvOptions { deterministic, ..self.options }, ..self }
+    }
+
+    /// Pack the kernel in output channel blocks instead of for the matrix
+    /// product, see `KernelPacking`.
+    pub fn with_kernel_packing(self, kernel_packing: KernelPacking) -> Conv {
+        Conv { options: ConvOptions { kernel_packing, ..self.options }, ..self }
+    }
+
+    /// Cap the im2col scratch memory, in bytes, see
+    /// `ConvUnary::scratch_layout`.
+    ///
+    /// An im2col conv needing more is evaluated in bands of output rows,
+    /// each within the budget. If a single row does not fit, evaluation
+    /// fails with `ConvError::ScratchBudget`.
+    pub fn with_max_scratch_bytes(self, max_scratch_bytes: Option<usize>) -> Conv {
+        Conv { options: ConvOptions { max_scratch_bytes, ..self.options }, ..self }
+    }
+
+    /// Lower im2col convs to a single `PackedConv`, kernels packed and
+    /// geometry computed once for all, instead of an im2col op feeding a
+    /// product op. The product then fuses no residual add.
+    pub fn with_packed_lowering(self, packed_lowering: bool) -> Conv {
+        Conv { options: ConvOptions { packed_lowering, ..self.options }, ..self }
+    }
+
+    /// Clamp the kernel weights to `[min, max]` when evaluating, to compare
+    /// a model across weight ranges without exporting its weights again.
+    ///
+    /// The stored kernel is left as is: evaluation clamps a copy of it, and
+    /// the optimized model gets its own clamped kernel.
+    pub fn with_weight_clamp(self, weight_clamp: Option<(f32, f32)>) -> Conv {
+        Conv { options: ConvOptions { weight_clamp, ..self.options }, ..self }
+    }
+
+    /// Convolve the input slices along the `independent_axes` axes following
+    /// the batch axis independently, as batch entries are, the kernel and
+    /// bias being shared. The frontend does not have to fold such axes
+    /// (parallel heads, graph nodes...) in the batch axis and split them
+    /// back after the conv.
+    ///
+    /// The input is (N, G..., C, H, W) or (N, G..., H, W, C), and so is the
+    /// output. The G dimensions must be known.
+    pub fn with_independent_axes(self, independent_axes: usize) -> Conv {
+        Conv { independent_axes, ..self }
+    }
+
+    /// `ishape` with its independent axes folded in the batch axis.
+    fn fold_independent<D: DimLike>(&self, ishape: &[D]) -> TVec<D> {
+        let k = self.independent_axes;
+        let mut folded: TVec<D> = ishape[k..].into();
+        folded[0] = ishape[..=k].iter().cloned().product();
+        folded
+    }
+
     fn output_shape<D: DimLike>(&self, ishape: &[D], kshape: &[usize]) -> TVec<D> {
-        let mut result: TVec<D> = ishape.into();
-        let ishape = self.data_format.shape(ishape);
-        let spatial_rank = ishape.hw_rank();
-        let ones = tvec![1; spatial_rank];
+        let independent = &ishape[1..][..self.independent_axes];
+        let mut result = self.folded_output_shape(&self.fold_independent(ishape), kshape);
+        result[0] = ishape[0];
+        for (ix, &d) in independent.iter().enumerate() {
+            result.insert(1 + ix, d);
+        }
+        result
+    }
+
+    fn folded_output_shape<D: DimLike>(&self, ishape: &[D], kshape: &[usize]) -> TVec<D> {
+        let spatial_rank = self.data_format.shape(ishape).hw_rank();
         let kernel_spatial_shape = &kshape[self.kernel_fmt.h_axis()..][..spatial_rank];
-        let computed = self.padding.compute(
-            ishape.hw_dims(),
-            kernel_spatial_shape,
-            self.dilations.as_ref().unwrap_or(&ones),
-            self.strides.as_ref().unwrap_or(&ones),
+        let window = Window::new(
+            kernel_spatial_shape.into(),
+            self.strides.clone(),
+            self.dilations.clone(),
+            self.padding.clone(),
         );
         let channels_out = match self.kernel_fmt {
             KernelFormat::OIHW => kshape[0],
             KernelFormat::HWIO => kshape[kshape.len() - 1] * self.group,
         };
-        result[ishape.c_axis()] = channels_out.into();
-        for (ix, d) in computed.iter().enumerate() {
-            result[ishape.h_axis() + ix] = d.output;
+        window.output_shape(self.data_format, ishape, channels_out.into())
+    }
+
+    /// The unary conv, its shapes with the independent axes folded in the
+    /// batch axis.
+    fn unary(
+        &self,
+        ishape: &[TDim],
+        kernel: Tensor,
+        bias: Option<Tensor>,
+    ) -> TractResult<ConvUnary> {
+        if ishape.len() < self.independent_axes + 3 {
+            bail!(ConvError::UnsupportedLayout { format: self.data_format, rank: ishape.len() });
         }
-        result
+        let independent_shape = ishape[1..][..self.independent_axes]
+            .iter()
+            .map(|d| Ok(d.to_integer()? as usize))
+            .collect::<TractResult<TVec<usize>>>()
+            .map_err(|_| format!("Conv independent axes must be known, got {:?}", ishape))?;
+        let folded = self.fold_independent(ishape);
+        // the output shape is read from the kernel: check it first
+        let bias_len = bias.as_ref().map(|b| b.shape().iter().product());
+        validate_config(&self, &folded, kernel.shape(), bias_len, self.group)?;
+        let oshape = self.folded_output_shape(&folded, kernel.shape());
+        let mut unary = ConvUnary::new(&self, &folded, &oshape, kernel, bias, self.group)?;
+        unary.independent_shape = independent_shape;
+        Ok(unary)
     }
 
     pub fn to_unary(
@@ -65,15 +290,7 @@ impl Conv {
         if inputs.len() == 2 {
             if let Some(kvalue) = kernel.borrow().konst.clone() {
                 let ishape: TVec<TDim> = input.borrow().shape.iter().collect();
-                let reduced = ConvUnary::new(
-                    &self,
-                    &ishape,
-                    &self.output_shape(&*ishape, kvalue.shape()),
-                    kvalue.into_tensor(),
-                    None,
-                    self.group,
-                )?;
-                return Ok(Some(reduced));
+                return Ok(Some(self.unary(&ishape, kvalue.into_tensor(), None)?));
             }
         } else {
             let bias = &inputs[2];
@@ -81,15 +298,8 @@ impl Conv {
                 (kernel.borrow().konst.clone(), bias.borrow().konst.clone())
             {
                 let ishape: TVec<TDim> = input.borrow().shape.iter().collect();
-                let reduced = ConvUnary::new(
-                    &self,
-                    &ishape,
-                    &self.output_shape(&ishape, kvalue.shape()),
-                    kvalue.into_tensor(),
-                    Some(bias.into_tensor()),
-                    self.group,
-                )?;
-                return Ok(Some(reduced));
+                let bias = Some(bias.into_tensor());
+                return Ok(Some(self.unary(&ishape, kvalue.into_tensor(), bias)?));
             }
         }
         Ok(None)
@@ -107,6 +317,26 @@ impl Op for Conv {
         unary.cost(&[inputs[0]])
     }
 
+    /// Only evaluates constant inputs whose output is small enough to fold.
+    fn infer(
+        &self,
+        inputs: TVec<&TensorFact>,
+        outputs: TVec<&TensorFact>,
+    ) -> TractResult<(TVec<TensorFact>, TVec<TensorFact>)> {
+        let (inputs, outputs) = self.infer_facts(inputs, outputs)?;
+        let small = outputs[0]
+            .shape
+            .as_concrete_finite()?
+            .map(|shape| shape.iter().product::<usize>() <= MAX_FOLDED_OUTPUT_LEN)
+            .unwrap_or(false);
+        if small && inputs.iter().all(|i| i.value.is_concrete()) {
+            let values = inputs.iter().map(|i| i.value.concretize().unwrap()).collect();
+            let outputs = self.eval(values)?.into_iter().map(|t| t.into()).collect();
+            return Ok((inputs, outputs));
+        }
+        Ok((inputs, outputs))
+    }
+
     fn declutter(
         &self,
         model: &TypedModel,
@@ -114,7 +344,8 @@ impl Op for Conv {
     ) -> TractResult<Option<TypedModelPatch>> {
         let inputs = model.node_input_facts(node.id)?;
         if let Some(op) = self.to_unary(&*inputs)? {
-            return Ok(Some(TypedModelPatch::single_unary_op(model, node, op)?));
+            let patch = TypedModelPatch::single_unary_op(model, node, op)?;
+            return Ok(Some(patch.with_label("constant kernel and bias made unary")));
         } else {
             Ok(None)
         }
@@ -143,37 +374,41 @@ impl InferenceRulesOp for Conv {
                 s.equals(&inputs[1].shape[ix + self.kernel_fmt.h_axis()], TDim::from(*dim as i32))?;
             }
         }
-        s.equals(&inputs[0].rank, &inputs[1].rank)?;
-        s.equals(&outputs[0].rank, &inputs[1].rank)?;
+        let k = self.independent_axes as i32;
+        s.equals(&inputs[0].rank, inputs[1].rank.bex() + k)?;
+        s.equals(&outputs[0].rank, inputs[1].rank.bex() + k)?;
         check_output_arity(&outputs, 1)?;
         s.equals_all(wrap![&outputs[0].datum_type, &inputs[0].datum_type, &inputs[1].datum_type])?;
         if inputs.len() == 3 {
             s.equals(&inputs[2].rank, 1)?;
             s.equals(&outputs[0].datum_type, &inputs[2].datum_type)?;
-            s.given(&inputs[1].rank, move |s, krank| {
-                let filter_o = match self.kernel_fmt {
-                    KernelFormat::OIHW => &inputs[1].shape[0],
-                    KernelFormat::HWIO => &inputs[1].shape[krank as usize - 1],
-                };
-                s.equals(&inputs[2].shape[0], filter_o)
+            s.given_2(&inputs[1].shape, &inputs[2].shape[0], move |s, kshape, bias_len| {
+                // a bias of one value per group is valid too
+                if self.group > 1 && bias_len == self.group.to_dim() {
+                    return Ok(());
+                }
+                if let Some(kshape) = known_shape(&kshape) {
+                    let (output_channels, _) =
+                        kernel_channels(self.kernel_fmt, &kshape, self.group);
+                    s.equals(&inputs[2].shape[0], output_channels.to_dim())?;
+                }
+                Ok(())
             })?
         }
-        s.given_2(&inputs[0].rank, &inputs[1].rank, move |s, irank, krank| {
+        s.given_2(&inputs[0].rank, &inputs[1].shape, move |s, irank, kshape| {
             let input_c = if self.data_format == DataFormat::NHWC {
                 &inputs[0].shape[irank as usize - 1]
             } else {
-                &inputs[0].shape[1]
+                &inputs[0].shape[1 + self.independent_axes]
             };
-            let filter_i = match self.kernel_fmt {
-                KernelFormat::OIHW => &inputs[1].shape[1],
-                KernelFormat::HWIO => &inputs[1].shape[krank as usize - 2],
-            };
-            s.equals(input_c.bex(), self.group as i32 * filter_i.bex())
+            if let Some(kshape) = known_shape(&kshape) {
+                let (_, input_channels) = kernel_channels(self.kernel_fmt, &kshape, self.group);
+                s.equals(input_c, input_channels.to_dim())?;
+            }
+            Ok(())
         })?;
         s.given_2(&inputs[0].shape, &inputs[1].shape, move |s, ishape, kshape| {
-            if kshape.iter().all(|d| d.to_integer().is_ok()) {
-                let kshape: TVec<usize> =
-                    kshape.iter().map(|d| d.to_integer().unwrap() as _).collect();
+            if let Some(kshape) = known_shape(&kshape) {
                 let oshape = self.output_shape(&*ishape, &*kshape);
                 s.equals(&outputs[0].shape, oshape)?;
             }
@@ -182,6 +417,11 @@ impl InferenceRulesOp for Conv {
     }
 }
 
+/// `shape` as integers, if all its dimensions are known.
+fn known_shape(shape: &[TDim]) -> Option<TVec<usize>> {
+    shape.iter().map(|d| d.to_integer().ok().map(|d| d as usize)).collect()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -337,4 +577,128 @@ mod test {
             .unwrap();
         assert_eq!(result, tvec!(rctensor3(&[[[2.0f32]]])));
     }
+
+    #[test]
+    fn test_infer_independent_axes() {
+        let op =
+            Conv::new(NHWC, HWIO, None, None, PaddingSpec::Valid, None, 1).with_independent_axes(1);
+        let ifact = TensorFact::dt_shape(DatumType::F32, shapefact!(2, 3, 5, 4, 2));
+        let kfact = TensorFact::dt_shape(DatumType::F32, shapefact!(3, 3, 2, 4));
+        let ofact = TensorFact::default();
+        let facts = op.infer_facts(tvec!(&ifact, &kfact), tvec!(&ofact)).unwrap();
+        assert_eq!(facts.1, tvec!(TensorFact::dt_shape(DatumType::F32, shapefact!(2, 3, 3, 2, 4))));
+    }
+
+    /// A conv with two independent axes, against the same conv with these
+    /// folded in the batch axis.
+    fn check_independent_axes(format: DataFormat, optimize: bool) {
+        let ishape: &[usize] =
+            if format == NHWC { &[2, 3, 2, 5, 4, 2] } else { &[2, 3, 2, 2, 5, 4] };
+        let input = ArrayD::from_shape_fn(ishape, |ix| {
+            ix.slice().iter().enumerate().map(|(a, &i)| i * (a + 3)).sum::<usize>() as f32 % 7.0
+        });
+        let kernel = ArrayD::from_shape_fn(&[4, 2, 3, 3][..], |ix| {
+            ix.slice().iter().sum::<usize>() as f32 / 4.0 - 1.0
+        })
+        .into_arc_tensor();
+        let bias = rctensor1(&[1.0f32, -1.0, 0.5, 0.0]);
+        let op = Conv::new(format, KernelFormat::OIHW, None, None, PaddingSpec::SameUpper, None, 1);
+        let folded_shape: TVec<usize> =
+            std::iter::once(12).chain(ishape[3..].iter().cloned()).collect();
+        let folded = input.clone().into_shape(&*folded_shape).unwrap().into_arc_tensor();
+        let expected = op.eval(tvec!(folded, kernel.clone(), bias.clone())).unwrap().remove(0);
+        let mut oshape: TVec<usize> = ishape[..3].into();
+        oshape.extend(expected.shape()[1..].iter().cloned());
+        let expected = expected.into_tensor().into_array::<f32>().unwrap();
+        let expected = expected.into_shape(&*oshape).unwrap().into_tensor();
+
+        let op = op.with_independent_axes(2);
+        let found = if optimize {
+            let mut model = InferenceModel::default();
+            let fact = TensorFact::dt_shape(DatumType::F32, ShapeFact::from(ishape));
+            let source = model.add_source("input", fact).unwrap();
+            let kernel = model.add_const("kernel", kernel).unwrap();
+            let bias = model.add_const("bias", bias).unwrap();
+            let conv = model.add_node_default("conv", op).unwrap();
+            for (slot, &id) in [source, kernel, bias].iter().enumerate() {
+                model.add_edge(OutletId::new(id, 0), InletId::new(conv, slot)).unwrap();
+            }
+            model.set_output_outlets(&[OutletId::new(conv, 0)]).unwrap();
+            let model = model.into_optimized().unwrap();
+            assert!(!model.nodes().iter().any(|n| n.op_is::<ConvUnary>()));
+            let plan = SimplePlan::new(&model).unwrap();
+            plan.run(tvec!(input.into_tensor())).unwrap().remove(0)
+        } else {
+            op.eval(tvec!(input.into_arc_tensor(), kernel, bias)).unwrap().remove(0)
+        };
+        assert!(found.close_enough(&expected, true));
+    }
+
+    #[test]
+    fn independent_axes_nchw() {
+        check_independent_axes(DataFormat::NCHW, false);
+    }
+
+    #[test]
+    fn independent_axes_nhwc() {
+        check_independent_axes(NHWC, false);
+    }
+
+    #[test]
+    fn independent_axes_optimized() {
+        check_independent_axes(DataFormat::NCHW, true);
+        check_independent_axes(NHWC, true);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let op = Conv::new(
+            NHWC,
+            HWIO,
+            Some(tvec!(1, 2)),
+            Some(tvec!(3, 3)),
+            PaddingSpec::Explicit(tvec!(1, 0), tvec!(0, 1)),
+            Some(tvec!(2, 1)),
+            2,
+        )
+        .with_strategy(ConvStrategy::ForceGemm)
+        .with_kernel_group_layout(KernelGroupLayout::Interleaved)
+        .with_deterministic(true);
+        let config = op.to_config();
+        assert_eq!(config.strides, Some(vec![2, 1]));
+        assert_eq!(Conv::from_config(&config).unwrap().to_config(), config);
+    }
+
+    #[test]
+    fn config_rebuilds_same_conv() {
+        let op = Conv::new(NHWC, HWIO, None, None, PaddingSpec::SameUpper, Some(tvec!(2)), 1);
+        let rebuilt = Conv::from_config(&op.to_config()).unwrap();
+        let input = rctensor3(&[[[1.0f32], [2.0], [3.0], [4.0]]]);
+        let kernel = rctensor3(&[[[1.0f32]], [[2.0]]]);
+        assert_eq!(
+            rebuilt.eval(tvec!(input.clone(), kernel.clone())).unwrap(),
+            op.eval(tvec!(input, kernel)).unwrap()
+        );
+    }
+
+    #[test]
+    fn config_checked() {
+        let config = Conv::default().to_config();
+        let zero_stride = ConvConfig { strides: Some(vec![1, 0]), ..config.clone() };
+        assert!(Conv::from_config(&zero_stride).is_err());
+        let zero_group = ConvConfig { group: 0, ..config.clone() };
+        assert!(Conv::from_config(&zero_group).is_err());
+        let clamp = ConvConfig {
+            options: ConvOptions { weight_clamp: Some((1.0, -1.0)), ..config.options.clone() },
+            ..config.clone()
+        };
+        assert!(Conv::from_config(&clamp).is_err());
+        let ranks = ConvConfig {
+            strides: Some(vec![1, 1]),
+            dilations: Some(vec![1, 1, 1]),
+            ..config.clone()
+        };
+        let e = Conv::from_config(&ranks).unwrap_err().to_string();
+        assert!(e.contains("dilations: expected 2, found 3"), "{}", e);
+    }
 }
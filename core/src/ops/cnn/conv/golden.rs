@@ -0,0 +1,181 @@
+use crate::internal::*;
+
+use super::error::ConvError;
+use super::ConvUnary;
+
+/// An output a conv must reproduce, to check a deployment against outputs
+/// recorded on a reference setup.
+///
+/// The comparison is made in f32, so quantized and half float outputs are
+/// checked against goldens of any of their float types.
+#[derive(Debug, Clone, new)]
+pub struct GoldenOutput {
+    pub output: Arc<Tensor>,
+    /// Largest absolute difference allowed on any item.
+    pub tolerance: f32,
+}
+
+impl GoldenOutput {
+    /// Largest absolute difference between `found` and the golden output.
+    /// A NaN is only close to another NaN.
+    pub fn max_abs_diff(&self, found: &Tensor) -> TractResult<f32> {
+        if found.shape() != self.output.shape() {
+            bail!("Golden output of shape {:?}, found {:?}", self.output.shape(), found.shape());
+        }
+        let golden = self.output.cast_to::<f32>()?;
+        let found = found.cast_to::<f32>()?;
+        let diff = golden
+            .to_array_view::<f32>()?
+            .iter()
+            .zip(found.to_array_view::<f32>()?.iter())
+            .map(|(g, f)| match (g.is_nan(), f.is_nan()) {
+                (true, true) => 0.0,
+                (false, false) => (g - f).abs(),
+                _ => std::f32::INFINITY,
+            })
+            .fold(0.0, f32::max);
+        Ok(diff)
+    }
+
+    /// Fails if `found` is not within tolerance, logging the difference
+    /// under `name`.
+    pub fn check(&self, name: &str, found: &Tensor) -> TractResult<()> {
+        let max_abs_diff = self.max_abs_diff(found)?;
+        if max_abs_diff > self.tolerance {
+            error!(
+                "{}: output differs from its golden by up to {}, the tolerance is {}",
+                name, max_abs_diff, self.tolerance
+            );
+            bail!(ConvError::GoldenMismatch { max_abs_diff, tolerance: self.tolerance });
+        }
+        Ok(())
+    }
+}
+
+/// Attach a golden output to the conv node called `name`.
+pub fn attach_golden<TI: TensorInfo>(
+    model: &mut Model<TI>,
+    name: &str,
+    golden: GoldenOutput,
+) -> TractResult<()> {
+    let id = model.node_by_name(name)?.id;
+    let conv = model
+        .node_mut(id)
+        .op_as_mut::<ConvUnary>()
+        .ok_or_else(|| format!("{} is not a ConvUnary", name))?;
+    conv.golden = Some(golden);
+    Ok(())
+}
+
+/// Checks its input against a golden output, and passes it through.
+///
+/// Follows the ops a conv with a golden output is lowered to, so the
+/// check survives codegen.
+#[derive(Debug, Clone, new)]
+pub struct GoldenCheck {
+    /// Name of the checked conv, for the logs.
+    pub name: String,
+    pub golden: GoldenOutput,
+}
+
+impl Op for GoldenCheck {
+    fn name(&self) -> Cow<str> {
+        "GoldenCheck".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("{} within {}", self.name, self.golden.tolerance)))
+    }
+}
+
+impl StatelessOp for GoldenCheck {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        self.golden.check(&self.name, &input)?;
+        Ok(tvec!(input))
+    }
+}
+
+impl InferenceRulesOp for GoldenCheck {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use ndarray::*;
+
+    fn setup() -> (Arc<Tensor>, ConvUnary, Arc<Tensor>) {
+        let input = Array4::from_shape_fn((1, 2, 5, 4), |(_, c, y, x)| {
+            ((c * 20 + y * 4 + x) % 7) as f32 - 3.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((3, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) % 5) as f32 / 2.0 - 1.0
+        });
+        let facts = conv_facts(input.clone(), kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        (input, op, expected)
+    }
+
+    fn off_by(golden: &Tensor, delta: f32) -> Arc<Tensor> {
+        let mut golden = golden.to_array_view::<f32>().unwrap().to_owned();
+        golden[[0, 1, 2, 1]] += delta;
+        golden.into_arc_tensor()
+    }
+
+    #[test]
+    fn eval_checks_golden() {
+        let (input, mut op, expected) = setup();
+        op.golden = Some(GoldenOutput::new(off_by(&expected, 0.01), 0.1));
+        assert!(op.eval(tvec!(input.clone())).is_ok());
+        op.golden = Some(GoldenOutput::new(off_by(&expected, 0.5), 0.1));
+        match op.eval(tvec!(input)).unwrap_err().kind() {
+            crate::TractErrorKind::Conv(ConvError::GoldenMismatch { max_abs_diff, tolerance }) => {
+                assert!((max_abs_diff - 0.5).abs() < 1e-5, "{}", max_abs_diff);
+                assert_eq!(*tolerance, 0.1);
+            }
+            e => panic!("expected a golden mismatch, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn nan_is_only_close_to_nan() {
+        let golden = GoldenOutput::new(rctensor1(&[1.0f32, std::f32::NAN]), 0.1);
+        assert_eq!(golden.max_abs_diff(&tensor1(&[1.0f32, std::f32::NAN])).unwrap(), 0.0);
+        assert!(golden.max_abs_diff(&tensor1(&[1.0f32, 2.0])).unwrap().is_infinite());
+    }
+
+    #[test]
+    fn optimized_model_checks_golden() {
+        let (input, op, expected) = setup();
+        let run = |golden: Arc<Tensor>| -> TractResult<TVec<Arc<Tensor>>> {
+            let mut model = InferenceModel::default();
+            model.add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 2, 5, 4)))?;
+            model.chain_default("conv", op.clone())?;
+            let mut model = model.into_typed()?;
+            attach_golden(&mut model, "conv", GoldenOutput::new(golden, 0.1))?;
+            let model = model.into_optimized()?;
+            assert!(model.nodes().iter().any(|n| n.op_is::<GoldenCheck>()));
+            assert!(!model.nodes().iter().any(|n| n.op_is::<ConvUnary>()));
+            SimplePlan::new(&model)?.run(tvec!(input.clone().into_tensor()))
+        };
+        let found = run(expected.clone()).unwrap();
+        assert!(found[0].close_enough(&expected, true));
+        assert!(run(off_by(&expected, 0.5)).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::unary::ConvUnary;
+use super::KernelFormat;
+use crate::ops::cnn::Patch;
+use crate::ops::nn::DataShape;
+
+/// Gradient of a convolution with respect to its input, from the gradient
+/// with respect to its output.
+///
+/// This is the transposed convolution of the output gradient by the kernel:
+/// every output gradient item is scattered back over the input items its
+/// patch read. The input shape must be known.
+#[derive(Debug, Clone, new)]
+pub struct ConvInputGrad {
+    pub conv: ConvUnary,
+}
+
+/// Gradient of a convolution with respect to its kernel, from its input and
+/// the gradient with respect to its output.
+///
+/// The result has the kernel shape and format. Interleaved kernels come out
+/// in contiguous group layout, as they are stored in `ConvUnary`.
+#[derive(Debug, Clone, new)]
+pub struct ConvKernelGrad {
+    pub conv: ConvUnary,
+}
+
+fn geometry(conv: &ConvUnary, input_full_shape: &[usize]) -> (Patch, DataShape, DataShape) {
+    let patch = conv.patch(input_full_shape);
+    let input: DataShape = conv.data_format.shape(input_full_shape.into());
+    let output =
+        conv.data_format.from_n_c_hw(input.n(), conv.output_channels(), &*patch.output_shape);
+    (patch, input, output)
+}
+
+/// Visits every product the forward convolution accumulates, as
+/// `(group, output channel in group, kernel item in group row, input offset, output offset)`.
+fn for_each_product(
+    group: usize,
+    patch: &Patch,
+    input: &DataShape,
+    output: &DataShape,
+    mut f: impl FnMut(usize, usize, usize, usize, usize),
+) {
+    let ci_per_group = input.c() / group;
+    let co_per_group = output.c() / group;
+    let kernel_len = patch.standard_layout_data_field.len();
+    for n in 0..input.n() {
+        for coords in ndarray::indices(&*patch.output_shape) {
+            let coords = coords.slice();
+            let output_spatial: usize =
+                coords.iter().zip(output.hw_strides()).map(|(a, b)| a * b).sum();
+            for (kitem, offset) in patch.at(coords).enumerate() {
+                let offset = if let Some(offset) = offset { offset as usize } else { continue };
+                for g in 0..group {
+                    for ci in 0..ci_per_group {
+                        let i = n * input.n_stride()
+                            + (g * ci_per_group + ci) * input.c_stride()
+                            + offset;
+                        for co in 0..co_per_group {
+                            let o = n * output.n_stride()
+                                + (g * co_per_group + co) * output.c_stride()
+                                + output_spatial;
+                            f(g, co, ci * kernel_len + kitem, i, o);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn check_output_grad(output: &DataShape, output_grad: &Tensor) -> TractResult<()> {
+    if output_grad.shape() != &*output.shape {
+        bail!(
+            "Output gradient shape {:?} does not match conv output {:?}",
+            output_grad.shape(),
+            output.shape
+        );
+    }
+    Ok(())
+}
+
+impl ConvInputGrad {
+    fn input_full_shape(&self) -> TractResult<TVec<usize>> {
+        self.conv
+            .full_input_shape
+            .iter()
+            .map(|d| Ok(d.to_integer()? as usize))
+            .collect::<TractResult<_>>()
+            .map_err(|_| "ConvInputGrad needs a known input shape".into())
+    }
+
+    fn eval_t<T: Datum + LinalgScalar>(&self, output_grad: &Tensor) -> TractResult<Tensor> {
+        let input_full_shape = self.input_full_shape()?;
+        let (patch, input, output) = geometry(&self.conv, &input_full_shape);
+        check_output_grad(&output, output_grad)?;
+        let kernel = self.conv.kernel_as_group_o_ihw::<T>()?;
+        let output_grad = output_grad.as_slice::<T>()?;
+        let mut input_grad = vec![T::zero(); input.shape.iter().product()];
+        for_each_product(self.conv.group, &patch, &input, &output, |g, co, k, i, o| {
+            input_grad[i] = input_grad[i] + kernel[(g, co, k)] * output_grad[o];
+        });
+        Ok(Tensor::from(ArrayD::from_shape_vec(&*input.shape, input_grad)?))
+    }
+}
+
+impl Op for ConvInputGrad {
+    fn name(&self) -> Cow<str> {
+        "ConvInputGrad".into()
+    }
+}
+
+impl StatelessOp for ConvInputGrad {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let output_grad = args_1!(inputs);
+        let input_grad =
+            dispatch_floatlike!(Self::eval_t(output_grad.datum_type())(self, &output_grad))?;
+        Ok(tvec!(input_grad.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for ConvInputGrad {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, self.conv.kernel.datum_type())?;
+        s.equals(&outputs[0].datum_type, self.conv.kernel.datum_type())?;
+        s.equals(&inputs[0].shape, self.conv.full_output_shape.clone())?;
+        s.equals(&outputs[0].shape, self.conv.full_input_shape.clone())?;
+        Ok(())
+    }
+}
+
+impl ConvKernelGrad {
+    fn eval_t<T: Datum + LinalgScalar>(
+        &self,
+        input: &Tensor,
+        output_grad: &Tensor,
+    ) -> TractResult<Tensor> {
+        let (patch, input_shape, output) = geometry(&self.conv, input.shape());
+        check_output_grad(&output, output_grad)?;
+        let input = input.as_slice::<T>()?;
+        let output_grad = output_grad.as_slice::<T>()?;
+        let kernel = &self.conv.kernel;
+        let co_per_group = output.c() / self.conv.group;
+        let mut grad = Array3::<T>::zeros((
+            self.conv.group,
+            co_per_group,
+            kernel.shape().iter().product::<usize>() / output.c(),
+        ));
+        for_each_product(self.conv.group, &patch, &input_shape, &output, |g, co, k, i, o| {
+            grad[(g, co, k)] = grad[(g, co, k)] + input[i] * output_grad[o];
+        });
+        let grad = match self.conv.kernel_fmt {
+            KernelFormat::OIHW => grad.into_shape(kernel.shape())?.into_dyn(),
+            KernelFormat::HWIO => {
+                // (g, o, i, hw..) to (hw.., g, i, o)
+                let hw_rank = kernel.shape().len() - 2;
+                let mut shape =
+                    tvec!(self.conv.group, co_per_group, input_shape.c() / self.conv.group);
+                shape.extend(kernel.shape()[..hw_rank].iter().cloned());
+                let mut permutation: Vec<usize> = (3..3 + hw_rank).collect();
+                permutation.extend(&[0, 2, 1]);
+                let grad = grad.into_shape(&*shape)?.permuted_axes(permutation);
+                ArrayD::from_shape_vec(kernel.shape(), grad.iter().cloned().collect())?
+            }
+        };
+        Ok(grad.into())
+    }
+}
+
+impl Op for ConvKernelGrad {
+    fn name(&self) -> Cow<str> {
+        "ConvKernelGrad".into()
+    }
+}
+
+impl StatelessOp for ConvKernelGrad {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (input, output_grad) = args_2!(inputs);
+        if output_grad.datum_type() != input.datum_type() {
+            bail!(
+                "Output gradient type {:?} does not match input {:?}",
+                output_grad.datum_type(),
+                input.datum_type()
+            );
+        }
+        let kernel_grad =
+            dispatch_floatlike!(Self::eval_t(input.datum_type())(self, &input, &output_grad))?;
+        Ok(tvec!(kernel_grad.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for ConvKernelGrad {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, self.conv.kernel.datum_type())?;
+        s.equals(&inputs[1].datum_type, self.conv.kernel.datum_type())?;
+        s.equals(&outputs[0].datum_type, self.conv.kernel.datum_type())?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        s.equals(&inputs[1].shape, self.conv.full_output_shape.clone())?;
+        s.equals(&outputs[0].shape, ShapeFact::from(self.conv.kernel.shape()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+    use crate::ops::nn::DataFormat;
+
+    fn value(i: usize) -> f64 {
+        ((i * 7919) % 17) as f64 / 8.0 - 1.0
+    }
+
+    /// Checks both gradients of `sum(conv(x, k) * w)` against central
+    /// finite differences.
+    fn check_gradients(conv: Conv, input_shape: &[usize], kernel_shape: &[usize]) {
+        let input =
+            ArrayD::from_shape_fn(input_shape, |ix| value(ix.slice().iter().sum::<usize>()));
+        let kernel = ArrayD::from_shape_fn(kernel_shape, |ix| {
+            value(ix.slice().iter().product::<usize>() + 3)
+        });
+        let unary = |kernel: &ArrayD<f64>| {
+            let facts = conv_facts(input.clone(), kernel.clone());
+            conv.to_unary(&facts).unwrap().unwrap()
+        };
+        let loss_weights = |shape: &[usize]| {
+            ArrayD::from_shape_fn(shape, |ix| value(ix.slice().iter().sum::<usize>() * 3 + ix[1]))
+        };
+        let loss = |input: &ArrayD<f64>, kernel: &ArrayD<f64>| {
+            let output = unary(kernel).eval(tvec!(input.clone().into_arc_tensor())).unwrap();
+            let output = output[0].to_array_view::<f64>().unwrap();
+            (&output * &loss_weights(output.shape())).sum()
+        };
+        let op = unary(&kernel);
+        let output_shape: TVec<usize> =
+            op.full_output_shape.iter().map(|d| d.to_integer().unwrap() as usize).collect();
+        let output_grad = loss_weights(&output_shape).into_arc_tensor();
+
+        let input_grad =
+            ConvInputGrad::new(op.clone()).eval(tvec!(output_grad.clone())).unwrap().remove(0);
+        let kernel_grad = ConvKernelGrad::new(op)
+            .eval(tvec!(input.clone().into_arc_tensor(), output_grad))
+            .unwrap()
+            .remove(0);
+
+        let eps = 1e-3;
+        for (ix, grad) in input_grad.to_array_view::<f64>().unwrap().indexed_iter() {
+            let (mut plus, mut minus) = (input.clone(), input.clone());
+            plus[ix.clone()] += eps;
+            minus[ix.clone()] -= eps;
+            let numerical = (loss(&plus, &kernel) - loss(&minus, &kernel)) / (2.0 * eps);
+            assert!((numerical - grad).abs() < 1e-6, "input {:?}: {} vs {}", ix, numerical, grad);
+        }
+        for (ix, grad) in kernel_grad.to_array_view::<f64>().unwrap().indexed_iter() {
+            let (mut plus, mut minus) = (kernel.clone(), kernel.clone());
+            plus[ix.clone()] += eps;
+            minus[ix.clone()] -= eps;
+            let numerical = (loss(&input, &plus) - loss(&input, &minus)) / (2.0 * eps);
+            assert!((numerical - grad).abs() < 1e-6, "kernel {:?}: {} vs {}", ix, numerical, grad);
+        }
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        check_gradients(Conv::default(), &[1, 2, 4, 4], &[3, 2, 2, 2]);
+    }
+
+    #[test]
+    fn strided_padded_grouped_gradients() {
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::Explicit(tvec!(1, 0), tvec!(0, 1)),
+            Some(tvec!(2, 1)),
+            2,
+        );
+        check_gradients(conv, &[2, 4, 5, 3], &[4, 2, 3, 2]);
+    }
+
+    #[test]
+    fn hwio_gradients() {
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            Some(tvec!(2, 1)),
+            None,
+            PaddingSpec::Valid,
+            None,
+            2,
+        );
+        check_gradients(conv, &[1, 5, 4, 4], &[2, 2, 4, 3]);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::internal::*;
+
+use super::ConvUnary;
+
+impl ConvUnary {
+    /// Whether the conv outputs its input unchanged: a 1x1 kernel with unit
+    /// strides and no padding, weighting each channel by one into itself
+    /// only, and no bias. `eval` then returns the input tensor itself, with
+    /// no copy, even when the optimizer has not removed the conv.
+    pub fn is_identity(&self) -> bool {
+        let kernel_shape = &self.kernel.shape()[self.kernel_fmt.h_axis()..][..self.strides.len()];
+        if self.summary.is_some()
+            || self.token_output
+            || self.f64_output
+            || self.options.weight_clamp.is_some()
+            || kernel_shape.iter().any(|&k| k != 1)
+            || self.strides.iter().any(|&s| s != 1)
+            || self.full_input_shape != self.full_output_shape
+            || self.bias.as_ref().map(|b| !Self::is_all_zero(b)).unwrap_or(false)
+        {
+            return false;
+        }
+        match self.kernel.datum_type() {
+            DatumType::F32 => self.is_identity_kernel::<f32>(),
+            DatumType::F64 => self.is_identity_kernel::<f64>(),
+            _ => Ok(false),
+        }
+        .unwrap_or(false)
+    }
+
+    fn is_identity_kernel<T: Datum + num_traits::Float>(&self) -> TractResult<bool> {
+        let kernel = self.kernel_as_group_o_ihw::<T>()?;
+        Ok(kernel
+            .indexed_iter()
+            .all(|((_, o, i), &w)| w == if o == i { T::one() } else { T::zero() }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use crate::ops::nn::DataFormat;
+    use ndarray::*;
+
+    #[test]
+    fn identity_returns_input() {
+        let input = Array4::from_shape_fn((1, 4, 3, 2), |(_, c, y, x)| (c * 6 + y * 2 + x) as f32)
+            .into_arc_tensor();
+        let eval = |kernel: ArrayD<f32>, group: usize, bias: Option<Tensor>| {
+            let facts = conv_facts(input.clone(), kernel);
+            let conv = Conv::new(
+                DataFormat::NCHW,
+                KernelFormat::OIHW,
+                None,
+                None,
+                PaddingSpec::Valid,
+                None,
+                group,
+            );
+            let mut op = conv.to_unary(&facts).unwrap().unwrap();
+            op.bias = bias;
+            let output = op.eval(tvec!(input.clone())).unwrap().remove(0);
+            (op.is_identity(), output)
+        };
+        let eye = ArrayD::from_shape_fn(&[4, 4, 1, 1][..], |ix| (ix[0] == ix[1]) as usize as f32);
+        let (identity, output) = eval(eye.clone(), 1, None);
+        assert!(identity && Arc::ptr_eq(&output, &input));
+        let (identity, output) =
+            eval(ArrayD::ones(&[4, 1, 1, 1][..]), 4, Some(tensor1(&[0.0f32; 4])));
+        assert!(identity && Arc::ptr_eq(&output, &input));
+
+        // a bias, a weight of two, or swapped channels are computed
+        let (identity, output) = eval(eye.clone(), 1, Some(tensor1(&[1.0f32; 4])));
+        assert!(!identity);
+        assert_eq!(*output, input.to_array_view::<f32>().unwrap().mapv(|x| x + 1.0).into());
+        let (identity, output) = eval(eye.mapv(|w| w * 2.0), 1, None);
+        assert!(!identity);
+        assert_eq!(*output, input.to_array_view::<f32>().unwrap().mapv(|x| x * 2.0).into());
+        let swap =
+            ArrayD::from_shape_fn(&[4, 4, 1, 1][..], |ix| (ix[0] == ix[1] ^ 1) as usize as f32);
+        let (identity, output) = eval(swap, 1, None);
+        assert!(!identity && *output != *input);
+    }
+}
@@ -3,7 +3,8 @@ use tract_linalg::PackB;
 use crate::internal::*;
 use ndarray::prelude::*;
 
-use crate::ops::cnn::Patch;
+use super::timing::{ConvPhase, PhaseTimer};
+use crate::ops::cnn::{Patch, PatchPadMode};
 use crate::ops::nn::DataShape;
 
 use num_traits::Zero;
@@ -20,9 +21,15 @@ pub(super) struct Im2Col<T: Copy + Datum + Mul + Zero> {
     pub group: usize,
     pub ci_per_group: usize,
     pub b_pack: PackB<T>,
+    /// Threads the columns of each packed matrix are gathered across.
+    pub threads: usize,
+    pub timer: PhaseTimer,
     patcher: Patcher,
 }
 
+/// Splitting the gather gives each thread at least this many items.
+const MIN_ITEMS_PER_THREAD: usize = 1 << 16;
+
 impl<T: Copy + Datum + Mul + Zero> PartialEq for Im2Col<T> {
     fn eq(&self, other: &Im2Col<T>) -> bool {
         self.patch == other.patch
@@ -45,7 +52,9 @@ impl<T: Copy + Datum + Mul + Zero> Im2Col<T> {
         ci_per_group: usize,
         b_pack: PackB<T>,
     ) -> Im2Col<T> {
-        let patcher = if !patch.padded && patch.rank() == 2 {
+        let patcher = if patch.padded && patch.spec.pad_mode != PatchPadMode::Zero {
+            Patcher::Generic
+        } else if !patch.padded && patch.rank() == 2 {
             Patcher::Valid2d
         } else if patch.rank() == 2 {
             Patcher::Padded2d
@@ -55,7 +64,28 @@ impl<T: Copy + Datum + Mul + Zero> Im2Col<T> {
             Patcher::Generic
         };
         let output_shape = input_shape.fmt.shape(tvec!(input_shape.n_dim(), group, b_pack.len()));
-        Im2Col { patch, input_shape, output_shape, m, k, n, group, ci_per_group, b_pack, patcher }
+        Im2Col {
+            patch,
+            input_shape,
+            output_shape,
+            m,
+            k,
+            n,
+            group,
+            ci_per_group,
+            b_pack,
+            threads: 1,
+            timer: PhaseTimer::default(),
+            patcher,
+        }
+    }
+
+    /// Threads worth splitting the gather of a `k` by `n` matrix across,
+    /// at most one per panel of the packed matrix.
+    pub(super) fn spatial_threads(&self) -> usize {
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let panels = (self.n + self.b_pack.panel_width() - 1) / self.b_pack.panel_width();
+        cores.min(self.k * self.n / MIN_ITEMS_PER_THREAD).min(panels).max(1)
     }
 
     pub(super) fn output_shape(&self) -> &[usize] {
@@ -66,15 +96,39 @@ impl<T: Copy + Datum + Mul + Zero> Im2Col<T> {
         let mut packed = unsafe {
             Tensor::uninitialized_aligned::<T>(&*self.output_shape.shape, self.b_pack.alignment())?
         };
+        self.im2col_into(input, packed.as_slice_mut::<T>()?)?;
+        Ok(packed)
+    }
+
+    /// Pack into `packed`, with the layout of `output_shape()`.
+    pub(super) fn im2col_into<'i>(
+        &'i self,
+        input: &'i ArrayViewD<'i, T>,
+        packed: &mut [T],
+    ) -> TractResult<()> {
+        self.timer.time(ConvPhase::Im2col, || self.pack(input, packed))
+    }
+
+    fn pack<'i>(&'i self, input: &'i ArrayViewD<'i, T>, packed: &mut [T]) -> TractResult<()> {
+        let patcher = if self.threads > 1 { Patcher::Threaded } else { self.patcher };
+        if self.input_shape.n_dim() == 1 {
+            // a single image: each group packs straight into its own chunk
+            // of the output, no batch subview
+            for (g, chunk) in packed.chunks_mut(self.b_pack.len()).take(self.group).enumerate() {
+                patcher.patch(self, input, chunk, 0, g);
+            }
+            return Ok(());
+        }
+        let mut packed = ArrayViewMutD::from_shape(&*self.output_shape.shape, packed)?;
         for i in 0..self.input_shape.n_dim() {
             for g in 0..self.group {
-                let mut packed = packed.to_array_view_mut::<T>()?;
+                let mut packed = packed.view_mut();
                 packed.slice_axis_inplace(Axis(0), (i..=i).into());
                 packed.slice_axis_inplace(Axis(1), (g..=g).into());
-                self.patcher.patch(self, input, packed.as_slice_mut().unwrap(), i, g);
+                patcher.patch(self, input, packed.as_slice_mut().unwrap(), i, g);
             }
         }
-        Ok(packed)
+        Ok(())
     }
 }
 
@@ -86,7 +140,9 @@ impl<T: Copy + Datum + Mul + Zero> Op for Im2Col<T> {
     impl_op_same_as!();
 
     fn info(&self) -> TractResult<Option<String>> {
-        Ok(Some(format!("Pack: {:?}\nMatMul: {:?}", self.patch, self.b_pack)))
+        let threads =
+            if self.threads > 1 { format!(" on {} threads", self.threads) } else { String::new() };
+        Ok(Some(format!("Pack: {:?}{}\nMatMul: {:?}", self.patch, threads, self.b_pack)))
     }
 }
 
@@ -120,6 +176,7 @@ enum Patcher {
     Valid1d,
     Valid2d,
     Padded2d,
+    Threaded,
 }
 
 impl Patcher {
@@ -153,6 +210,7 @@ impl Patcher {
                 i,
                 g,
             ),
+            Patcher::Threaded => Self::threaded(im2col, input, pack, i, g),
             _ => Self::generic(im2col, input, pack, i, g),
         }
     }
@@ -165,24 +223,14 @@ impl Patcher {
         i: usize,
         g: usize,
     ) {
-        let ptr = input.as_ptr();
         let mut mega_matrix = unsafe { Array2::<T>::uninitialized((im2col.k, im2col.n)) };
-        let shape = &im2col.input_shape;
         unsafe {
-            let ptr = ptr.offset((shape.n_stride() * i) as isize);
-            let ptr = ptr.offset((shape.c_stride() * (g * im2col.ci_per_group)) as isize);
-            for (spatial, mut col) in ndarray::indices(&*im2col.patch.output_shape)
+            let ptr = Self::group_ptr(im2col, input, i, g);
+            for (spatial, col) in ndarray::indices(&*im2col.patch.output_shape)
                 .into_iter()
                 .zip(mega_matrix.axis_iter_mut(Axis(1)))
             {
-                let mut col = col.iter_mut();
-                for ci in 0..im2col.ci_per_group {
-                    let ptr = ptr.offset((shape.c_stride() * ci) as isize);
-                    for v in im2col.patch.at(spatial.slice()) {
-                        *col.next().expect("geometry error in conv") =
-                            v.map(|o| *ptr.offset(o)).unwrap_or(T::default());
-                    }
-                }
+                Self::gather(im2col, ptr, spatial.slice(), col);
             }
             im2col.b_pack.pack(
                 pack.as_mut_ptr(),
@@ -193,6 +241,92 @@ impl Patcher {
         }
     }
 
+    /// First channel of group `g` in image `i`.
+    unsafe fn group_ptr<T: Copy + Datum + Mul + Zero>(
+        im2col: &Im2Col<T>,
+        input: &ArrayViewD<T>,
+        i: usize,
+        g: usize,
+    ) -> *const T {
+        let shape = &im2col.input_shape;
+        input
+            .as_ptr()
+            .offset((shape.n_stride() * i) as isize)
+            .offset((shape.c_stride() * (g * im2col.ci_per_group)) as isize)
+    }
+
+    /// Fill the column of the output position at `spatial`: padding
+    /// positions read as zero.
+    unsafe fn gather<T: Copy + Datum + Mul + Zero>(
+        im2col: &Im2Col<T>,
+        ptr: *const T,
+        spatial: &[usize],
+        mut col: ArrayViewMut1<T>,
+    ) {
+        let shape = &im2col.input_shape;
+        let mut col = col.iter_mut();
+        for ci in 0..im2col.ci_per_group {
+            let ptr = ptr.offset((shape.c_stride() * ci) as isize);
+            for v in im2col.patch.at(spatial) {
+                *col.next().expect("geometry error in conv") =
+                    v.map(|o| *ptr.offset(o)).unwrap_or(T::default());
+            }
+        }
+    }
+
+    /// The generic gather, each of `im2col.threads` filling and packing the
+    /// columns of a disjoint range of panels.
+    #[inline(never)]
+    fn threaded<'i, 'p, T: Copy + Datum + Mul + Zero>(
+        im2col: &'i Im2Col<T>,
+        input: &'i ArrayViewD<'i, T>,
+        pack: &'p mut [T],
+        i: usize,
+        g: usize,
+    ) {
+        let (k, n) = (im2col.k, im2col.n);
+        let nr = im2col.b_pack.panel_width();
+        let panels = (n + nr - 1) / nr;
+        let chunk = (panels + im2col.threads - 1) / im2col.threads;
+        let mut mega_matrix = unsafe { Array2::<T>::uninitialized((k, n)) };
+        let output_shape = &*im2col.patch.output_shape;
+        // raw pointers are not Send: the threads read the input and write
+        // disjoint columns of the matrix and panels of the pack
+        let ptr = unsafe { Self::group_ptr(im2col, input, i, g) } as usize;
+        let (rs, cs) = (mega_matrix.strides()[0], mega_matrix.strides()[1]);
+        let matrix = mega_matrix.as_mut_ptr() as usize;
+        let pack = pack.as_mut_ptr() as usize;
+        let fill = move |panels: std::ops::Range<usize>| unsafe {
+            let cols = (panels.start * nr)..(panels.end * nr).min(n);
+            let mut spatial: TVec<usize> = output_shape.into();
+            for j in cols.clone() {
+                let mut rem = j;
+                for (axis, dim) in output_shape.iter().enumerate().rev() {
+                    spatial[axis] = rem % dim;
+                    rem /= dim;
+                }
+                let col = ArrayViewMut1::from_shape_ptr(
+                    (k,).strides((rs as usize,)),
+                    (matrix as *mut T).offset(j as isize * cs),
+                );
+                Self::gather(im2col, ptr as *const T, &spatial, col);
+            }
+            let alignment = std::mem::align_of::<T>();
+            PackB::<T>::new(k, cols.len(), nr, alignment).pack(
+                (pack as *mut T).offset((panels.start * nr * k) as isize),
+                (matrix as *const T).offset(cols.start as isize * cs),
+                rs,
+                cs,
+            );
+        };
+        std::thread::scope(|s| {
+            for start in (chunk..panels).step_by(chunk) {
+                s.spawn(move || fill(start..(start + chunk).min(panels)));
+            }
+            fill(0..chunk.min(panels));
+        });
+    }
+
     #[inline(never)]
     fn valid_1d<'i, 'p, T: Copy + Datum + Mul + Zero>(
         im2col: &'i Im2Col<T>,
@@ -243,11 +377,12 @@ impl Patcher {
                 for kitem in 0..kernel_len {
                     let dy = *im2col.patch.data_field.as_ptr().offset(kitem as isize * 2);
                     let dx = *im2col.patch.data_field.as_ptr().offset(1 + kitem as isize * 2);
-                    let iptr =
-                        iptr.offset(*im2col.patch.standard_layout_data_field.get_unchecked(kitem));
+                    let iptr = iptr.wrapping_offset(
+                        *im2col.patch.standard_layout_data_field.get_unchecked(kitem),
+                    );
                     for yo in 0..*im2col.patch.output_shape.get_unchecked(0) {
                         let y = yo as isize * y_stride + dy;
-                        let iptr = iptr.offset(yo as isize * y_stride_ptr);
+                        let iptr = iptr.wrapping_offset(yo as isize * y_stride_ptr);
                         if y >= 0 && y < input_heigth {
                             for xo in 0..*im2col.patch.output_shape.get_unchecked(1) {
                                 let x = xo as isize * x_stride + dx;
@@ -0,0 +1,96 @@
+use crate::internal::*;
+
+use super::golden::GoldenCheck;
+use super::ConvUnary;
+use crate::dim::DimLike;
+
+impl ConvUnary {
+    /// `shape` with the independent axes folded in the batch axis.
+    pub(super) fn fold_independent<D: DimLike>(&self, shape: &[D]) -> TVec<D> {
+        let k = self.independent_shape.len();
+        let mut folded: TVec<D> = shape[k..].into();
+        folded[0] = shape[..=k].iter().cloned().product();
+        folded
+    }
+
+    /// `shape`, of the folded conv, with the independent axes back after the
+    /// batch axis.
+    pub fn unfold_independent<D: DimLike>(&self, shape: &[D]) -> TVec<D> {
+        let mut unfolded: TVec<D> = shape.into();
+        unfolded[0] = shape[0] / self.independent_shape.iter().product::<usize>();
+        for (ix, &d) in self.independent_shape.iter().enumerate() {
+            unfolded.insert(1 + ix, D::from(d));
+        }
+        unfolded
+    }
+
+    /// Fold the independent axes in the batch axis with reshapes around a
+    /// plain conv, which lowers like any other.
+    pub(super) fn fold_independent_patch(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let finite = |outlet: OutletId| -> TractResult<Option<TVec<usize>>> {
+            Ok(model.outlet_fact(outlet)?.shape.as_finite().map(|s| s.into()))
+        };
+        let ishape = match finite(node.inputs[0])? {
+            Some(shape) => shape,
+            None => return Ok(None),
+        };
+        let mut conv = self.clone();
+        conv.independent_shape = tvec!();
+        conv.golden = None;
+        let mut facts = tvec!();
+        for ix in 0..node.outputs.len() {
+            let mut fact = node.outputs[ix].fact.clone();
+            match finite(OutletId::new(node.id, ix))? {
+                Some(shape) => fact.shape = ShapeInfo::from(&*self.fold_independent(&shape)),
+                None => return Ok(None),
+            }
+            facts.push(fact);
+        }
+        let mut patch = TypedModelPatch::default();
+        let input = patch.tap_model(model, node.inputs[0])?;
+        let folded = self.fold_independent(&ishape);
+        let input = Self::reshape(&mut patch, format!("{}-fold", node.name), input, &folded)?;
+        let id = patch.add_node(&*node.name, conv, facts)?;
+        patch.add_edge(input, InletId::new(id, 0))?;
+        for ix in 0..node.outputs.len() {
+            let shape = self.unfold_independent(
+                patch.outlet_fact(OutletId::new(id, ix))?.shape.as_finite().unwrap(),
+            );
+            let name = format!("{}-unfold.{}", node.name, ix);
+            let mut output = Self::reshape(&mut patch, name, OutletId::new(id, ix), &shape)?;
+            if let (0, Some(golden)) = (ix, self.golden.clone()) {
+                let fact = patch.outlet_fact(output)?.clone();
+                let check = patch.add_node(
+                    format!("{}-golden", node.name),
+                    GoldenCheck::new(node.name.clone(), golden),
+                    tvec!(fact),
+                )?;
+                patch.add_edge(output, InletId::new(check, 0))?;
+                output = OutletId::new(check, 0);
+            }
+            patch.shunt_outside(OutletId::new(node.id, ix), output)?;
+        }
+        Ok(Some(patch.with_label("folded independent axes in batch")))
+    }
+
+    /// Add a node reshaping `input` to `shape`.
+    fn reshape(
+        patch: &mut TypedModelPatch,
+        name: String,
+        input: OutletId,
+        shape: &[usize],
+    ) -> TractResult<OutletId> {
+        let datum_type = patch.outlet_fact(input)?.datum_type;
+        let shape_tensor = tensor1(&shape.iter().map(|&d| d as i64).collect::<Vec<_>>());
+        let konst = patch.add_const(format!("{}-shape", name), shape_tensor)?;
+        let fact = TypedTensorInfo { shape: ShapeInfo::from(shape), datum_type, konst: None };
+        let id = patch.add_node(name, crate::ops::array::Reshape::default(), tvec!(fact))?;
+        patch.add_edge(input, InletId::new(id, 0))?;
+        patch.add_edge(OutletId::new(konst, 0), InletId::new(id, 1))?;
+        Ok(OutletId::new(id, 0))
+    }
+}
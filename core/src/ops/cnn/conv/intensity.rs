@@ -0,0 +1,70 @@
+/// Work and memory traffic of a convolution, for roofline analysis.
+///
+/// A convolution whose intensity is under the ridge point of a machine, its
+/// peak FLOPs over its memory bandwidth, is memory-bound there: making its
+/// arithmetic faster does not make it faster.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArithmeticIntensity {
+    /// Floating point operations, a multiply-add counting for two.
+    pub flops: u64,
+    /// Bytes of the input, kernel and output, each moved once: the least
+    /// traffic the convolution can get away with.
+    pub bytes: u64,
+}
+
+impl ArithmeticIntensity {
+    /// Intensity of `group` products of (m, k) kernel by (k, n) data
+    /// matrices, `n` counting the output points of the whole batch.
+    pub fn new(
+        m: usize,
+        k: usize,
+        n: usize,
+        group: usize,
+        input_bytes: usize,
+        kernel_bytes: usize,
+        output_bytes: usize,
+    ) -> ArithmeticIntensity {
+        ArithmeticIntensity {
+            flops: 2 * (m * k * n * group) as u64,
+            bytes: (input_bytes + kernel_bytes + output_bytes) as u64,
+        }
+    }
+
+    pub fn flops_per_byte(&self) -> f64 {
+        if self.bytes == 0 {
+            0.0
+        } else {
+            self.flops as f64 / self.bytes as f64
+        }
+    }
+
+    /// Whether the convolution is memory-bound on a machine running
+    /// `peak_flops` operations and moving `bandwidth` bytes per second.
+    pub fn is_memory_bound(&self, peak_flops: f64, bandwidth: f64) -> bool {
+        self.flops_per_byte() < peak_flops / bandwidth
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pointwise_is_memory_bound_where_3x3_is_not() {
+        // 64 to 64 channels on 56x56 f32 maps
+        let maps = 64 * 56 * 56 * 4;
+        let pointwise = ArithmeticIntensity::new(64, 64, 56 * 56, 1, maps, 64 * 64 * 4, maps);
+        let conv3x3 = ArithmeticIntensity::new(64, 64 * 9, 56 * 56, 1, maps, 64 * 64 * 36, maps);
+        assert_eq!(pointwise.flops, 2 * 64 * 64 * 56 * 56);
+        assert_eq!(conv3x3.flops, 9 * pointwise.flops);
+        // 1 TFLOPs over 25 GB/s: a ridge at 40 FLOPs per byte, pointwise
+        // doing about 16 and 3x3 about 130
+        assert!(pointwise.flops_per_byte() < 20.0);
+        assert!(conv3x3.flops_per_byte() > 100.0);
+        assert!(pointwise.is_memory_bound(1e12, 2.5e10));
+        assert!(!conv3x3.is_memory_bound(1e12, 2.5e10));
+        let depthwise = ArithmeticIntensity::new(1, 9, 56 * 56, 64, maps, 64 * 36, maps);
+        // 9 MACs per output value, about 2 FLOPs per byte
+        assert!(depthwise.flops_per_byte() < 4.0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+use crate::internal::*;
+use once_cell::sync::OnceCell;
+
+/// Packed kernels of a convolution.
+///
+/// Packing happens only once, on first use, even if several threads evaluate
+/// the op concurrently. Clones of the op share the same cache, so a kernel
+/// is packed once for all of them.
+///
+/// The cache is not aware of kernel changes: reset it to
+/// `KernelCache::default()` after altering a kernel.
+///
+/// Kernels are packed either as the A operand of the product, as its B
+/// operand when the product is computed transposed, or in channel blocks.
+#[derive(Clone, Default)]
+pub struct KernelCache(Arc<OnceCell<PackedKernels>>);
+
+/// How a cached kernel is packed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) enum PackedLayout {
+    A,
+    B,
+    /// See `BlockedMatMat`.
+    Blocked(usize),
+}
+
+impl PackedLayout {
+    /// The operand of the product the kernel is packed as.
+    pub(super) fn operand(as_b: bool) -> PackedLayout {
+        if as_b {
+            PackedLayout::B
+        } else {
+            PackedLayout::A
+        }
+    }
+}
+
+struct PackedKernels {
+    datum_type: DatumType,
+    layout: PackedLayout,
+    kernels: Arc<Vec<Tensor>>,
+}
+
+impl KernelCache {
+    /// Get the packed kernels for `T`, calling `pack` if they are missing.
+    ///
+    /// The cache holds one datum type and layout. Others are packed but not
+    /// cached.
+    pub(super) fn get_or_pack<T: Datum>(
+        &self,
+        layout: PackedLayout,
+        pack: impl FnOnce() -> TractResult<Vec<Tensor>>,
+    ) -> TractResult<Arc<Vec<Tensor>>> {
+        let mut pack = Some(pack);
+        let packed = self.0.get_or_try_init(|| -> TractResult<PackedKernels> {
+            let kernels = (pack.take().unwrap())()?;
+            Ok(PackedKernels { datum_type: T::datum_type(), layout, kernels: Arc::new(kernels) })
+        })?;
+        if packed.datum_type == T::datum_type() && packed.layout == layout {
+            Ok(packed.kernels.clone())
+        } else if let Some(pack) = pack.take() {
+            Ok(Arc::new(pack()?))
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Has packing already happened ?
+    pub fn is_packed(&self) -> bool {
+        self.0.get().is_some()
+    }
+}
+
+impl fmt::Debug for KernelCache {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.get() {
+            Some(packed) => {
+                write!(fmt, "KernelCache({:?}, {:?})", packed.datum_type, packed.layout)
+            }
+            None => write!(fmt, "KernelCache(empty)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn packs_once_under_concurrent_first_use() {
+        let cache = KernelCache::default();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                std::thread::spawn(move || {
+                    cache
+                        .get_or_pack::<f32>(PackedLayout::A, || {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(10));
+                            Ok(vec![tensor1(&[1.0f32, 2.0])])
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(results.iter().all(|r| Arc::ptr_eq(r, &results[0])));
+    }
+
+    #[test]
+    fn other_datum_type_or_layout_is_not_cached() {
+        let cache = KernelCache::default();
+        cache.get_or_pack::<f32>(PackedLayout::A, || Ok(vec![tensor1(&[1.0f32])])).unwrap();
+        let f64s =
+            cache.get_or_pack::<f64>(PackedLayout::A, || Ok(vec![tensor1(&[1.0f64])])).unwrap();
+        assert_eq!(f64s[0].datum_type(), DatumType::F64);
+        let as_b =
+            cache.get_or_pack::<f32>(PackedLayout::B, || Ok(vec![tensor1(&[2.0f32])])).unwrap();
+        assert_eq!(as_b[0], tensor1(&[2.0f32]));
+        let f32s = cache
+            .get_or_pack::<f32>(PackedLayout::A, || -> TractResult<Vec<Tensor>> {
+                panic!("should be cached")
+            })
+            .unwrap();
+        assert_eq!(f32s[0], tensor1(&[1.0f32]));
+    }
+}
@@ -3,7 +3,13 @@ use std::ops::{Add, AddAssign, Mul};
 
 use crate::internal::*;
 use ndarray::prelude::*;
+use ndarray::Slice;
 
+use super::kernel_cache::PackedLayout;
+use super::scratch::PanelPool;
+use super::summary::{writeback, ChannelSummary};
+use super::timing::{ConvPhase, PhaseTimer};
+use super::{ConvError, KernelCache};
 use crate::ops::cnn::conv::KernelFormat;
 use crate::ops::cnn::Patch;
 use crate::ops::nn::{DataFormat, DataShape};
@@ -36,6 +42,14 @@ use tract_linalg::MatMul;
  *              +--------------+  +----------------+
  */
 
+/// Loads a convolution kernel on demand, as a (output channels, k) matrix
+/// with the channels of each group contiguous.
+pub type KernelProvider<T> = Arc<Fn() -> Array2<T> + Send + Sync>;
+
+/// Receives the output channels of a group of an image, given their image
+/// and group indices, as soon as they are computed. See `with_group_sink`.
+pub type GroupSink<T> = Arc<Fn(usize, usize, ArrayViewD<T>) + Send + Sync>;
+
 #[derive(CustomDebug, Clone, new)]
 pub struct MatMat<T>
 where
@@ -48,31 +62,251 @@ where
     pub n: usize,
     pub kernel_fmt: KernelFormat,
     #[debug(skip)]
-    pub packed_kernels: Vec<Tensor>,
+    pub packed_kernels: Arc<Vec<Tensor>>,
     pub bias: Option<ArrayD<T>>,
     pub group: usize,
+    pub summary: Option<ChannelSummary>,
     pub mm: Box<MatMul<T>>,
+    /// The kernel is the B operand, and `mm` computes the output transposed.
+    pub kernel_as_b: bool,
+    /// A second input, with the output shape, is added to the product: the
+    /// output is accumulated into it instead of a fresh buffer.
+    #[new(default)]
+    pub residual: bool,
+    /// Threads each product is split across, along its spatial dimension.
+    #[new(value = "1")]
+    pub threads: usize,
+    /// Threads the groups of each image are spread across, each product
+    /// running whole on one of them. Replaces `threads` when above one.
+    #[new(value = "1")]
+    pub group_threads: usize,
+    /// Loads the kernel at each evaluation instead of `packed_kernels`, see
+    /// `with_kernel_provider`.
+    /// The f32 products are written back to a f64 output, bias and summary
+    /// computed in f64.
+    #[new(default)]
+    pub f64_output: bool,
+    /// `output_shape` is channels last, and the output is flattened to
+    /// (N, H * W, C) tokens after the writeback.
+    #[new(default)]
+    pub token_output: bool,
+    #[new(default)]
+    #[debug(skip)]
+    pub kernel_provider: Option<(KernelProvider<T>, Option<KernelCache>)>,
+    #[new(default)]
+    #[debug(skip)]
+    pub group_sink: Option<GroupSink<T>>,
+    /// C panels of the threads products are split across.
+    #[new(value = "PanelPool::shared()")]
+    #[debug(skip)]
+    pub panel_pool: Arc<PanelPool<T>>,
+    #[new(default)]
+    #[debug(skip)]
+    pub timer: PhaseTimer,
+}
+
+/// Splitting a product gives each thread at least this many multiply-adds.
+const MIN_FMA_PER_THREAD: usize = 1 << 20;
+
+impl<T> MatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    /// Threads worth splitting the spatial dimension of `mm` across: a
+    /// batch-1, group-1 convolution is otherwise a single serial product.
+    pub(super) fn spatial_threads(mm: &MatMul<T>, kernel_as_b: bool) -> usize {
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let (rows, cols) = mm.panels();
+        let panels = if kernel_as_b { rows } else { cols };
+        let work = mm.m() * mm.k() * mm.n() / MIN_FMA_PER_THREAD;
+        cores.min(work).min(panels).max(1)
+    }
+
+    /// Threads worth spreading the `group` products of `mm` across: with a
+    /// few dozen output channels per group, each product is too small to
+    /// split, but they are independent.
+    pub(super) fn group_threads(mm: &MatMul<T>, group: usize) -> usize {
+        let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let work = group * mm.m() * mm.k() * mm.n() / MIN_FMA_PER_THREAD;
+        cores.min(work).min(group).max(1)
+    }
+}
+
+impl<T> MatMat<T>
+where
+    T: Datum + Add + Mul + Zero + Copy,
+{
+    /// Packs each group of a (group, m, k) kernel as an operand of `mm`.
+    pub fn pack_kernels(
+        mm: &MatMul<T>,
+        kernel_as_b: bool,
+        kernel: ArrayView3<T>,
+    ) -> TractResult<Vec<Tensor>> {
+        let mut packed_kernels: Vec<Tensor> = vec![];
+        for subkernel in kernel.outer_iter() {
+            let (rs, cs) = (subkernel.strides()[0], subkernel.strides()[1]);
+            let packed = if kernel_as_b {
+                let kernel_pack = mm.b_pack();
+                let mut packed = unsafe {
+                    Tensor::uninitialized_aligned::<T>(
+                        &[kernel_pack.len()],
+                        kernel_pack.alignment(),
+                    )?
+                };
+                kernel_pack.pack(packed.as_slice_mut()?.as_mut_ptr(), subkernel.as_ptr(), cs, rs);
+                packed
+            } else {
+                let mut packed = unsafe {
+                    Tensor::uninitialized_aligned::<T>(
+                        &[mm.packed_a_len()],
+                        mm.packed_a_alignment(),
+                    )?
+                };
+                mm.pack_a(packed.as_slice_mut()?.as_mut_ptr(), subkernel.as_ptr(), rs, cs);
+                packed
+            };
+            packed_kernels.push(packed);
+        }
+        Ok(packed_kernels)
+    }
+
+    /// Stream the kernel from `provider` instead of holding it packed.
+    ///
+    /// The provider is called when an evaluation starts, and the packed
+    /// kernel is dropped when it ends: this trades speed for memory on
+    /// models whose weights do not fit in it. With `cache`, the provider is
+    /// only called once and the packed kernel kept in the cache.
+    pub fn with_kernel_provider(
+        self,
+        provider: KernelProvider<T>,
+        cache: Option<KernelCache>,
+    ) -> MatMat<T> {
+        MatMat {
+            packed_kernels: Arc::new(vec![]),
+            kernel_provider: Some((provider, cache)),
+            ..self
+        }
+    }
+
+    /// Hand each group of each image to `sink` as soon as its product is
+    /// done, before the next products run.
+    ///
+    /// The sink sees the group output channels, bias added, in the output
+    /// data format with a batch axis of one. The channel summary and the
+    /// tokens only come with the whole output, and f64 outputs can not be
+    /// streamed.
+    pub fn with_group_sink(self, sink: GroupSink<T>) -> MatMat<T> {
+        MatMat { group_sink: Some(sink), ..self }
+    }
+
+    /// The packed kernels, loading them from the provider if there is one.
+    fn kernels(&self) -> TractResult<Arc<Vec<Tensor>>> {
+        let (provider, cache) = match &self.kernel_provider {
+            Some(p) => p,
+            None => return Ok(self.packed_kernels.clone()),
+        };
+        let load = || -> TractResult<Vec<Tensor>> {
+            let kernel = provider();
+            let shape = (self.group, self.m, self.k);
+            if kernel.shape() != &[self.group * self.m, self.k] {
+                bail!("Provided kernel is {:?}, expected {:?}", kernel.shape(), shape);
+            }
+            let kernel = kernel.into_shape(shape)?;
+            Self::pack_kernels(&*self.mm, self.kernel_as_b, kernel.view())
+        };
+        match cache {
+            Some(cache) => cache.get_or_pack::<T>(PackedLayout::operand(self.kernel_as_b), load),
+            None => Ok(Arc::new(load()?)),
+        }
+    }
 }
 
 impl<T> MatMat<T>
 where
-    T: Datum + Add + Mul + Zero + Copy + AddAssign + ndarray::LinalgScalar,
+    T: Datum + Add + Mul + Zero + Copy + AddAssign + ndarray::LinalgScalar + num_traits::Float,
 {
-    pub(super) fn conv_gemm<'i>(
-        &'i self,
-        packed_input: &'i ArrayView3<'i, T>,
+    pub(super) fn conv_gemm(
+        &self,
+        packed_input: &ArrayView3<T>,
+        residual: Option<Arc<Tensor>>,
+        c_panel: &mut [T],
+    ) -> TractResult<(ArrayD<T>, Option<ArrayD<T>>)> {
+        let mut output =
+            self.timer.time(ConvPhase::Gemm, || self.products(packed_input, residual, c_panel))?;
+        let result = self.timer.time(ConvPhase::Writeback, || -> TractResult<_> {
+            // a sink got the groups with their bias already
+            let bias = self.bias.as_ref().filter(|_| self.group_sink.is_none());
+            let summary = writeback(&mut output, &self.output_shape, bias, self.summary)?;
+            Ok((self.tokens(output)?, summary))
+        });
+        self.timer.eval_done();
+        result
+    }
+
+    /// Same as `conv_gemm`, upcasting the products to f64 before the
+    /// writeback.
+    pub(super) fn conv_gemm_f64(
+        &self,
+        packed_input: &ArrayView3<T>,
+        c_panel: &mut [T],
+    ) -> TractResult<(ArrayD<f64>, Option<ArrayD<f64>>)> {
+        if self.group_sink.is_some() {
+            bail!("f64 conv outputs can not be streamed to a group sink");
+        }
+        let output =
+            self.timer.time(ConvPhase::Gemm, || self.products(packed_input, None, c_panel))?;
+        let result = self.timer.time(ConvPhase::Writeback, || -> TractResult<_> {
+            let mut output = output.mapv(|x| x.to_f64().unwrap());
+            let bias = self.bias.as_ref().map(|b| b.mapv(|x| x.to_f64().unwrap()));
+            let summary = writeback(&mut output, &self.output_shape, bias.as_ref(), self.summary)?;
+            Ok((self.tokens(output)?, summary))
+        });
+        self.timer.eval_done();
+        result
+    }
+
+    /// Flatten the output to tokens if `token_output`.
+    fn tokens<U>(&self, output: ArrayD<U>) -> TractResult<ArrayD<U>> {
+        if !self.token_output {
+            return Ok(output);
+        }
+        let hw = self.output_shape.hw_dims().iter().product::<usize>();
+        Ok(output.into_shape(&[self.output_shape.n(), hw, self.output_shape.c()][..])?)
+    }
+
+    /// The products, without bias.
+    fn products(
+        &self,
+        packed_input: &ArrayView3<T>,
+        residual: Option<Arc<Tensor>>,
+        c_panel: &mut [T],
     ) -> TractResult<ArrayD<T>> {
-        let mut output = unsafe { ArrayD::<T>::uninitialized(&*self.output_shape.shape) };
-        let packed_b_len = self.mm.b_pack().len();
+        let mut output = if let Some(residual) = residual {
+            if residual.shape() != &*self.output_shape.shape {
+                bail!(
+                    "Residual shape {:?} does not match conv output {:?}",
+                    residual.shape(),
+                    self.output_shape.shape
+                );
+            }
+            // reuses the buffer if nobody else holds it
+            residual.into_tensor().into_array::<T>()?
+        } else {
+            ArrayD::<T>::zeros(&*self.output_shape.shape)
+        };
+        let packed_input_len =
+            if self.kernel_as_b { self.mm.a_pack().len() } else { self.mm.b_pack().len() };
 
         let co_per_group = self.output_shape.c() / self.group;
+        let kernels = self.kernels()?;
 
         for i in 0..self.output_shape.n() {
+            let mut operands = tvec!();
             unsafe {
                 let output_i =
                     output.as_mut_ptr().offset(self.output_shape.n_stride() as isize * i as isize);
                 for g in 0..self.group {
-                    let a = &self.packed_kernels[g];
+                    let a = &kernels[g];
                     let output_i_g = output_i.offset(
                         self.output_shape.c_stride() as isize * co_per_group as isize * g as isize,
                     );
@@ -82,62 +316,382 @@ where
                         DataFormat::NCHW => (self.n as isize, 1),
                     };
 
-                    self.mm.mat_mul_prepacked(
-                        a.as_ptr()?,
-                        packed_input
-                            .as_ptr()
-                            .offset(((self.group * i + g) * packed_b_len) as isize),
-                        output_i_g,
-                        rsc,
-                        csc,
-                    );
+                    let input = packed_input
+                        .as_ptr()
+                        .offset(((self.group * i + g) * packed_input_len) as isize);
+                    operands.push(if self.kernel_as_b {
+                        (input, a.as_ptr()?, output_i_g, csc, rsc)
+                    } else {
+                        (a.as_ptr()?, input, output_i_g, rsc, csc)
+                    });
                 }
             }
+            if self.group_threads > 1 {
+                self.mat_mul_groups(&operands, c_panel);
+                for g in 0..self.group {
+                    self.complete_group(&mut output, i, g);
+                }
+            } else {
+                for (g, &(pa, pb, c, rsc, csc)) in operands.iter().enumerate() {
+                    self.mat_mul(pa, pb, c, rsc, csc, c_panel);
+                    self.complete_group(&mut output, i, g);
+                }
+            }
+        }
+        Ok(output)
+    }
+
+    /// Adds the bias to the channels of group `g` of image `i` and hands
+    /// them to the group sink, if there is one.
+    fn complete_group(&self, output: &mut ArrayD<T>, i: usize, g: usize) {
+        let sink = match &self.group_sink {
+            Some(sink) => sink,
+            None => return,
+        };
+        let co_per_group = self.output_shape.c() / self.group;
+        let channels = Slice::from(g * co_per_group..(g + 1) * co_per_group);
+        let c_axis = Axis(self.output_shape.c_axis());
+        let mut block = output.slice_axis_mut(Axis(0), Slice::from(i..i + 1));
+        block.slice_axis_inplace(c_axis, channels);
+        if let Some(bias) = &self.bias {
+            block += &bias.slice_axis(c_axis, channels);
         }
+        sink(i, g, block.view());
+    }
+
+    /// Runs the products of the groups of an image, spread across
+    /// `group_threads`: each group writes its own output channels.
+    fn mat_mul_groups(
+        &self,
+        operands: &[(*const T, *const T, *mut T, isize, isize)],
+        c_panel: &mut [T],
+    ) {
+        let chunk = (operands.len() + self.group_threads - 1) / self.group_threads;
+        let mm = &*self.mm;
+        let acc = self.residual;
+        let pool = &*self.panel_pool;
+        // raw pointers are not Send: the threads write disjoint channels of C
+        let operands: TVec<_> = operands
+            .iter()
+            .map(|&(pa, pb, c, rsc, csc)| (pa as usize, pb as usize, c as usize, rsc, csc))
+            .collect();
+        let run = move |operands: &[(usize, usize, usize, isize, isize)], c_panel: &mut [T]| {
+            for &(pa, pb, c, rsc, csc) in operands {
+                mm.mat_mul_prepacked_in(
+                    pa as *const T,
+                    pb as *const T,
+                    c as *mut T,
+                    rsc,
+                    csc,
+                    acc,
+                    c_panel,
+                );
+            }
+        };
+        std::thread::scope(|s| {
+            let mut chunks = operands.chunks(chunk);
+            let first = chunks.next().unwrap_or(&[]);
+            for operands in chunks {
+                s.spawn(move || {
+                    let mut c_panel = pool.take(mm.c_panel_len());
+                    run(operands, &mut c_panel[..mm.c_panel_len()]);
+                    pool.give(c_panel);
+                });
+            }
+            run(first, c_panel);
+        });
+    }
 
-        if let Some(ref bias) = self.bias {
-            output += &bias;
+    /// Same as `conv_gemm`, but each output channel goes to its own (H, W)
+    /// plane, image by image then channel by channel.
+    pub(super) fn conv_gemm_planar(
+        &self,
+        packed_input: &ArrayView3<T>,
+        c_panel: &mut [T],
+    ) -> TractResult<Vec<Array2<T>>> {
+        if self.residual || self.summary.is_some() || self.token_output {
+            bail!("Planar output supports neither residuals, channel summaries nor tokens");
+        }
+        let hw = self.output_shape.hw_dims();
+        if hw.len() != 2 {
+            bail!(ConvError::UnsupportedLayout {
+                format: self.output_shape.fmt,
+                rank: self.output_shape.rank()
+            });
+        }
+        let channels = self.output_shape.c();
+        let mut planes = vec![Array2::<T>::zeros((hw[0], hw[1])); self.output_shape.n() * channels];
+        let packed_input_len =
+            if self.kernel_as_b { self.mm.a_pack().len() } else { self.mm.b_pack().len() };
+        let co_per_group = channels / self.group;
+        let kernels = self.kernels()?;
+        // the transposed product lays channels along C columns: it goes
+        // through a scratch instead
+        let mut transposed = vec![T::zero(); if self.kernel_as_b { self.m * self.n } else { 0 }];
+        for i in 0..self.output_shape.n() {
+            for g in 0..self.group {
+                let a = kernels[g].as_ptr::<T>()?;
+                let input = unsafe {
+                    packed_input.as_ptr().offset(((self.group * i + g) * packed_input_len) as isize)
+                };
+                let planes = &mut planes[i * channels + g * co_per_group..][..co_per_group];
+                if self.kernel_as_b {
+                    self.mm.mat_mul_prepacked_in(
+                        input,
+                        a,
+                        transposed.as_mut_ptr(),
+                        self.m as isize,
+                        1,
+                        false,
+                        c_panel,
+                    );
+                    for (c, plane) in planes.iter_mut().enumerate() {
+                        for (x, t) in
+                            plane.iter_mut().zip(transposed.iter().skip(c).step_by(self.m))
+                        {
+                            *x = *t;
+                        }
+                    }
+                } else {
+                    let rows: Vec<*mut T> = planes.iter_mut().map(|p| p.as_mut_ptr()).collect();
+                    self.mm.mat_mul_prepacked_rows(a, input, &rows, c_panel);
+                }
+            }
         }
+        if let Some(bias) = self.bias.as_ref() {
+            let bias: Vec<T> = bias.iter().cloned().collect();
+            for (ix, plane) in planes.iter_mut().enumerate() {
+                let b = bias[ix % channels];
+                plane.mapv_inplace(|x| x + b);
+            }
+        }
+        Ok(planes)
+    }
 
-        Ok(output)
+    /// Runs one product, splitting its spatial panels (the columns of C, or
+    /// its rows with the kernel as B) across `threads`.
+    fn mat_mul(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        c_panel: &mut [T],
+    ) {
+        let (rows, cols) = self.mm.panels();
+        let panels = if self.kernel_as_b { rows } else { cols };
+        let threads = self.threads.min(panels);
+        if threads <= 1 {
+            self.mm.mat_mul_prepacked_in(pa, pb, c, rsc, csc, self.residual, c_panel);
+            return;
+        }
+        let chunk = (panels + threads - 1) / threads;
+        let mm = &*self.mm;
+        let acc = self.residual;
+        let kernel_as_b = self.kernel_as_b;
+        let pool = &*self.panel_pool;
+        let tiles = move |range: std::ops::Range<usize>| {
+            if kernel_as_b {
+                (range, 0..cols)
+            } else {
+                (0..rows, range)
+            }
+        };
+        // raw pointers are not Send: the threads write disjoint tiles of C
+        let (pa, pb, c) = (pa as usize, pb as usize, c as usize);
+        std::thread::scope(|s| {
+            for start in (chunk..panels).step_by(chunk) {
+                let (rows, cols) = tiles(start..(start + chunk).min(panels));
+                s.spawn(move || {
+                    let mut c_panel = pool.take(mm.c_panel_len());
+                    mm.mat_mul_prepacked_tiles(
+                        pa as *const T,
+                        pb as *const T,
+                        c as *mut T,
+                        rsc,
+                        csc,
+                        acc,
+                        rows,
+                        cols,
+                        &mut c_panel[..mm.c_panel_len()],
+                    );
+                    pool.give(c_panel);
+                });
+            }
+            let (rows, cols) = tiles(0..chunk);
+            mm.mat_mul_prepacked_tiles(
+                pa as *const T,
+                pb as *const T,
+                c as *mut T,
+                rsc,
+                csc,
+                acc,
+                rows,
+                cols,
+                c_panel,
+            );
+        });
     }
 }
 
 impl<D> Op for MatMat<D>
 where
-    D: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<D> + PartialEq,
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
 {
     fn name(&self) -> Cow<str> {
         "MatMat".into()
     }
 
     fn info(&self) -> TractResult<Option<String>> {
-        Ok(Some(format!("{:?}", self.mm)))
+        let orientation = if self.kernel_as_b { " (kernel as B)" } else { "" };
+        let residual = if self.residual { " + residual" } else { "" };
+        let threads = if self.group_threads > 1 {
+            format!(" across {} group threads", self.group_threads)
+        } else if self.threads > 1 {
+            format!(" on {} threads", self.threads)
+        } else {
+            String::new()
+        };
+        let upcast = if self.f64_output { " to f64" } else { "" };
+        let tokens = if self.token_output { " as tokens" } else { "" };
+        Ok(Some(format!("{:?}{}{}{}{}{}", self.mm, orientation, residual, threads, upcast, tokens)))
+    }
+
+    /// Accumulate into the other input of an Add reading our output.
+    fn codegen(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        if self.residual
+            || self.summary.is_some()
+            || self.f64_output
+            || self.token_output
+            || node.outputs[0].successors.len() != 1
+        {
+            return Ok(None);
+        }
+        let ours = OutletId::new(node.id, 0);
+        if model.output_outlets()?.contains(&ours) {
+            return Ok(None);
+        }
+        let add = model.node(node.outputs[0].successors[0].node);
+        if !add.op_is::<crate::ops::math::Add::Bin>() {
+            return Ok(None);
+        }
+        let skip = if add.inputs[0] == ours { add.inputs[1] } else { add.inputs[0] };
+        if skip == ours {
+            return Ok(None);
+        }
+        let skip_fact = model.outlet_fact(skip)?;
+        if skip_fact.datum_type != D::datum_type() || skip_fact.shape != node.outputs[0].fact.shape
+        {
+            return Ok(None);
+        }
+        let mut op = self.clone();
+        op.residual = true;
+        let mut patch = TypedModelPatch::default();
+        let input = patch.tap_model(&model, node.inputs[0])?;
+        let skip = patch.tap_model(&model, skip)?;
+        let id = patch.add_node(&*node.name, op, tvec!(add.outputs[0].fact.clone()))?;
+        patch.add_edge(input, InletId::new(id, 0))?;
+        patch.add_edge(skip, InletId::new(id, 1))?;
+        patch.shunt_outside(OutletId::new(add.id, 0), OutletId::new(id, 0))?;
+        Ok(Some(patch.with_label("fused residual add")))
     }
 
     fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
         let batch = inputs[0].shape.dim(0);
-        Ok(tvec!((
-            Cost::FMA(f32::datum_type()),
-            batch * self.group * self.mm.m() * self.mm.k() * self.mm.n()
-        )))
+        let params =
+            self.group * self.m * self.k + self.bias.as_ref().map(|b| b.len()).unwrap_or(0);
+        Ok(tvec!(
+            (
+                Cost::FMA(f32::datum_type()),
+                batch * self.group * self.mm.m() * self.mm.k() * self.mm.n()
+            ),
+            (Cost::Params(D::datum_type()), params.to_dim())
+        ))
     }
 }
 
-impl<D> StatelessOp for MatMat<D>
+impl<D> MatMat<D>
 where
-    D: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<D> + PartialEq,
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
 {
-    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+    fn eval_with_c_panel(
+        &self,
+        mut inputs: TVec<Arc<Tensor>>,
+        c_panel: &mut [D],
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let residual = if self.residual { Some(inputs.pop().unwrap()) } else { None };
         let input = args_1!(inputs);
-        let output = self.conv_gemm(&input.to_array_view::<D>()?.into_dimensionality()?)?;
-        Ok(tvec!(output.into_arc_tensor()))
+        let input = input.to_array_view::<D>()?.into_dimensionality()?;
+        let outputs = if self.f64_output {
+            let (output, summary) = self.conv_gemm_f64(&input, c_panel)?;
+            tvec!(Some(output.into_arc_tensor()), summary.map(|s| s.into_arc_tensor()))
+        } else {
+            let (output, summary) = self.conv_gemm(&input, residual, c_panel)?;
+            tvec!(Some(output.into_arc_tensor()), summary.map(|s| s.into_arc_tensor()))
+        };
+        Ok(outputs.into_iter().flatten().collect())
+    }
+}
+
+/// Scratch of one evaluation context: the op itself stays immutable, so a
+/// plan can be run concurrently from as many states as needed.
+#[derive(Debug, Clone)]
+struct MatMatState<D> {
+    c_panel: Vec<D>,
+}
+
+impl<D> OpState for MatMatState<D>
+where
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
+{
+    fn eval(
+        &mut self,
+        _session: &mut SessionState,
+        op: &Op,
+        inputs: TVec<Arc<Tensor>>,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let op = op.downcast_ref::<MatMat<D>>().ok_or("Wrong Op type")?;
+        op.eval_with_c_panel(inputs, &mut self.c_panel)
+    }
+}
+
+impl<D> StatefullOp for MatMat<D>
+where
+    D: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<D>
+        + PartialEq
+        + num_traits::Float,
+{
+    fn state(&self, _session: &mut SessionState) -> TractResult<Option<Box<OpState>>> {
+        Ok(Some(Box::new(MatMatState { c_panel: vec![D::zero(); self.mm.c_panel_len()] })))
     }
 }
 
 impl<D> InferenceRulesOp for MatMat<D>
 where
-    D: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<D>,
+    D: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<D> + num_traits::Float,
 {
     fn rules<'r, 'p: 'r, 's: 'r>(
         &'s self,
@@ -148,3 +702,82 @@ where
         unreachable!()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    fn residual_model() -> InferenceModel {
+        let mut model = InferenceModel::default();
+        let fact = |shape: TVec<usize>| TensorFact::dt_shape(f32::datum_type(), shape);
+        let input = model.add_source("input", fact(tvec!(1, 3, 6, 6))).unwrap();
+        let skip = model.add_source("skip", fact(tvec!(1, 4, 6, 6))).unwrap();
+        let mut conv = Conv::default();
+        conv.padding = PaddingSpec::SameUpper;
+        let conv = model.add_node_default("conv", conv).unwrap();
+        let kernel = Array4::from_shape_fn((4, 3, 3, 3), |(o, i, y, x)| {
+            ((o * 27 + i * 9 + y * 3 + x) % 11) as f32 - 5.0
+        });
+        let kernel = model.add_const("kernel", kernel).unwrap();
+        let add = model.add_node_default("add", crate::ops::math::Add::default()).unwrap();
+        model.add_edge(OutletId::new(input, 0), InletId::new(conv, 0)).unwrap();
+        model.add_edge(OutletId::new(kernel, 0), InletId::new(conv, 1)).unwrap();
+        model.add_edge(OutletId::new(conv, 0), InletId::new(add, 0)).unwrap();
+        model.add_edge(OutletId::new(skip, 0), InletId::new(add, 1)).unwrap();
+        // skip is also an output: accumulating must not alter it
+        model.set_output_outlets(&[OutletId::new(add, 0), OutletId::new(skip, 0)]).unwrap();
+        model
+    }
+
+    #[test]
+    fn residual_add_is_fused() {
+        let model = residual_model();
+        let reference = SimplePlan::new(model.clone().into_typed().unwrap()).unwrap();
+        let optimized = model.into_typed().unwrap().into_optimized().unwrap();
+        assert!(optimized.nodes().iter().all(|n| !n.op_is::<crate::ops::math::Add::Bin>()));
+        assert!(optimized.nodes().iter().any(|n| n
+            .op()
+            .info()
+            .unwrap()
+            .map(|i| i.contains("+ residual"))
+            .unwrap_or(false)));
+        let optimized = SimplePlan::new(optimized).unwrap();
+        let input = Array4::from_shape_fn((1, 3, 6, 6), |(_, c, y, x)| (c + y * x) as f32 / 10.0);
+        let skip = Array4::from_shape_fn((1, 4, 6, 6), |(_, c, y, x)| (c * y + x) as f32);
+        let expected = reference.run(tvec!(input.clone().into(), skip.clone().into())).unwrap();
+        let found = optimized.run(tvec!(input.into(), skip.clone().into())).unwrap();
+        assert_close!(*found[0], *expected[0]);
+        assert_eq!(*found[1], skip.into_tensor());
+    }
+
+    #[test]
+    fn shared_plan_runs_concurrently() {
+        let optimized = residual_model().into_typed().unwrap().into_optimized().unwrap();
+        let plan = Arc::new(SimplePlan::new(optimized).unwrap());
+        let inputs = |seed: usize| -> TVec<Tensor> {
+            let input = Array4::from_shape_fn((1, 3, 6, 6), |(_, c, y, x)| {
+                ((seed + c * 36 + y * 6 + x) % 7) as f32
+            });
+            let skip =
+                Array4::from_shape_fn((1, 4, 6, 6), |(_, c, y, x)| (seed * c + y + x) as f32);
+            tvec!(input.into(), skip.into())
+        };
+        let expected: Vec<_> = (0..8).map(|seed| plan.run(inputs(seed)).unwrap()).collect();
+        let threads: Vec<_> = (0..8)
+            .map(|seed| {
+                let plan = plan.clone();
+                std::thread::spawn(move || {
+                    let mut state =
+                        SimpleState::<TypedTensorInfo, TypedModel, _>::new(plan).unwrap();
+                    (0..4).map(|_| state.run(inputs(seed)).unwrap()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for (seed, thread) in threads.into_iter().enumerate() {
+            for found in thread.join().unwrap() {
+                assert_eq!(found, expected[seed]);
+            }
+        }
+    }
+}
@@ -1,16 +1,71 @@
+mod affine;
+mod bands;
+mod blocked;
+mod branch;
+mod channel_blocked;
+mod clamp;
+mod deformable;
 mod depth_wise;
+mod dequant;
 mod direct;
+mod error;
+mod gemm_dyn;
 mod gen;
+mod golden;
+mod grad;
+mod identity;
 mod im2col;
+mod independent;
+mod intensity;
+mod kernel_cache;
 mod mat_mat;
+mod options;
+mod packed;
+mod pad;
+mod planar;
+mod quant;
+mod rank1;
+mod scratch;
+mod separable;
+mod shuffle;
+mod summary;
+mod tiles;
+mod timing;
+mod tokens;
 mod unary;
+mod validate;
 mod vec_mat;
+mod winograd;
 
+pub use self::blocked::BlockedMatMat;
+pub use self::branch::BranchConv;
+pub use self::channel_blocked::ChannelBlockedConv;
+pub use self::deformable::DeformableConv;
+pub use self::dequant::{BFloat16KernelConv, DequantConv, HalfKernelConv};
 pub use self::direct::Direct;
-pub use self::gen::Conv;
+pub use self::error::ConvError;
+pub use self::gemm_dyn::ConvGemmDyn;
+pub use self::gen::{Conv, ConvConfig};
+pub use self::golden::{attach_golden, GoldenCheck, GoldenOutput};
+pub use self::grad::{ConvInputGrad, ConvKernelGrad};
+pub use self::intensity::ArithmeticIntensity;
+pub use self::kernel_cache::KernelCache;
+pub use self::mat_mat::{GroupSink, KernelProvider, MatMat};
+pub use self::options::ConvOptions;
+pub use self::packed::PackedConv;
+pub use self::quant::{CalibrationStats, Overflow, QConvI16, Rounding};
+pub use self::scratch::{Arena, PanelPool, ScratchAllocator, ScratchLayout};
+pub use self::separable::SeparableConv;
+pub use self::shuffle::PixelShuffleConv;
+pub use self::summary::ChannelSummary;
+pub use self::tiles::ConvTile;
+pub use self::timing::{ConvPhase, PhaseTimer, PhaseTimes};
 pub use self::unary::ConvUnary;
+pub use self::validate::validate_config;
+pub use self::winograd::Winograd;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum KernelFormat {
     OIHW,
     HWIO,
@@ -22,6 +77,72 @@ impl Default for KernelFormat {
     }
 }
 
+/// How the output channels of a grouped kernel are ordered along its group
+/// axis (O for OIHW, I for HWIO).
+///
+/// Lowerings take the channels of group `g` as the `g`-th contiguous slice
+/// of that axis. `Interleaved` kernels, where channel `j` of group `g` sits
+/// at `j * group + g`, are reordered once when the convolution is built.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum KernelGroupLayout {
+    Contiguous,
+    Interleaved,
+}
+
+impl Default for KernelGroupLayout {
+    fn default() -> KernelGroupLayout {
+        KernelGroupLayout::Contiguous
+    }
+}
+
+/// Implementation used when lowering a convolution.
+///
+/// `Auto` lets codegen pick one from the geometry. The others force an
+/// implementation, failing codegen when it can not handle the convolution.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum ConvStrategy {
+    Auto,
+    /// Im2col followed by a matrix product.
+    ForceGemm,
+    /// Winograd F(2x2, 3x3), for 2D 3x3 f32 kernels, stride 1.
+    ForceWinograd,
+    ForceDepthwise,
+    ForceDirect,
+    /// Direct convolution of NCHW inputs in blocks of this many channels
+    /// (NCHWc), between conversions to and from the blocked layout.
+    ForceChannelBlocked(usize),
+}
+
+impl Default for ConvStrategy {
+    fn default() -> ConvStrategy {
+        ConvStrategy::Auto
+    }
+}
+
+/// How the im2col lowering packs the kernel for the product.
+///
+/// Input and output stay in the data format of the convolution whatever the
+/// packing: only the kernel and the product loops change.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum KernelPacking {
+    /// As an operand of the tuned matrix product kernels.
+    Product,
+    /// In blocks of this many output channels, interleaved along the rest
+    /// of the kernel (OIHW8o for 8): each block computes as many channels at
+    /// once, in SIMD lanes, and the product scatters them back to the output
+    /// layout. Convolutions with an f64 or token output use `Product`.
+    ChannelBlocks(usize),
+}
+
+impl Default for KernelPacking {
+    fn default() -> KernelPacking {
+        KernelPacking::Product
+    }
+}
+
 impl KernelFormat {
     pub(super) fn h_axis(&self) -> usize {
         match self {
@@ -29,4 +150,21 @@ impl KernelFormat {
             KernelFormat::HWIO => 0,
         }
     }
+
+    /// Axis the groups are sliced from.
+    pub(super) fn group_axis(&self, rank: usize) -> usize {
+        match self {
+            KernelFormat::OIHW => 0,
+            KernelFormat::HWIO => rank - 2,
+        }
+    }
+}
+
+/// Facts for an input and a constant kernel, as `Conv::to_unary` gets them.
+#[cfg(test)]
+pub(crate) fn conv_facts(
+    input: impl crate::tensor::IntoArcTensor,
+    kernel: impl crate::tensor::IntoArcTensor,
+) -> [crate::model::TypedTensorInfo; 2] {
+    [input.into_arc_tensor().into(), kernel.into_arc_tensor().into()]
 }
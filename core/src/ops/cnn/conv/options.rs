@@ -0,0 +1,25 @@
+use super::{ConvStrategy, KernelPacking};
+
+/// How a convolution is lowered and evaluated, independently of its
+/// geometry and weights.
+///
+/// `Conv` carries them from the frontend and hands them over to the
+/// `ConvUnary` it is made into, and on to the convs derived from that one
+/// when it is decluttered or split.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ConvOptions {
+    /// See `Conv::with_strategy`.
+    pub strategy: ConvStrategy,
+    /// Run every product serially, so the summation order does not depend on
+    /// the host. See `Conv::with_deterministic`.
+    pub deterministic: bool,
+    /// See `Conv::with_kernel_packing`.
+    pub kernel_packing: KernelPacking,
+    /// See `Conv::with_max_scratch_bytes`.
+    pub max_scratch_bytes: Option<usize>,
+    /// See `Conv::with_packed_lowering`.
+    pub packed_lowering: bool,
+    /// Range the kernel weights are clamped to, see `Conv::with_weight_clamp`.
+    pub weight_clamp: Option<(f32, f32)>,
+}
@@ -0,0 +1,157 @@
+use crate::internal::*;
+
+use super::im2col::Im2Col;
+use super::ConvUnary;
+
+/*
+ * An im2col convolution as a single op: the patch, the packing of the input
+ * and the packed kernels are all computed when the conv is lowered, for a
+ * given input shape. Evaluating packs the input and runs the product, with
+ * no geometry nor kernel preparation left to do.
+ *
+ * The im2col pair of ops computes the same thing over two nodes, the packed
+ * input being a value of the plan in between. This form keeps it private to
+ * the op, but the product can not fuse the ops around it.
+ */
+
+#[derive(Debug, Clone)]
+pub struct PackedConv<T>
+where
+    T: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<T>
+        + FloatLike
+        + num_traits::Float,
+{
+    im2col: Im2Col<T>,
+    gemm: Box<Op>,
+}
+
+impl<T> PackedConv<T>
+where
+    T: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<T>
+        + FloatLike
+        + num_traits::Float,
+{
+    /// Lower `conv` for an input of this shape.
+    pub fn from_conv(conv: &ConvUnary, input_full_shape: &[usize]) -> TractResult<PackedConv<T>> {
+        let (im2col, _, gemm) = conv.to_im2col_pair::<T>(input_full_shape)?;
+        Ok(PackedConv { im2col, gemm })
+    }
+
+    /// The product the packed input goes through: `MatMat`, `VecMat` or
+    /// `BlockedMatMat`.
+    pub fn gemm(&self) -> &Op {
+        &*self.gemm
+    }
+}
+
+impl<T> Op for PackedConv<T>
+where
+    T: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<T>
+        + FloatLike
+        + num_traits::Float,
+{
+    fn name(&self) -> Cow<str> {
+        "PackedConv".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        let gemm = self.gemm.info()?.unwrap_or_default();
+        Ok(Some(format!("{} {}", self.gemm.name(), gemm)))
+    }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        // the products read the batch off the first axis, as the data does
+        self.gemm.cost(inputs)
+    }
+}
+
+/// The state of the product, if it has one.
+#[derive(Debug)]
+struct PackedConvState {
+    gemm: Option<Box<OpState>>,
+}
+
+impl Clone for PackedConvState {
+    fn clone(&self) -> PackedConvState {
+        PackedConvState { gemm: self.gemm.as_ref().map(|b| ::objekt::clone_box(&**b)) }
+    }
+}
+
+impl<T> StatefullOp for PackedConv<T>
+where
+    T: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<T>
+        + FloatLike
+        + num_traits::Float,
+{
+    fn state(&self, session: &mut SessionState) -> TractResult<Option<Box<OpState>>> {
+        Ok(Some(Box::new(PackedConvState { gemm: self.gemm.state(session)? })))
+    }
+}
+
+impl OpState for PackedConvState {
+    fn eval(
+        &mut self,
+        session: &mut SessionState,
+        op: &Op,
+        mut inputs: TVec<Arc<Tensor>>,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let dt = input.datum_type();
+        dispatch_floatlike!(Self::eval_t(dt)(self, session, op, &input))
+    }
+}
+
+impl PackedConvState {
+    fn eval_t<T>(
+        &mut self,
+        session: &mut SessionState,
+        op: &Op,
+        input: &Tensor,
+    ) -> TractResult<TVec<Arc<Tensor>>>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let op = op.downcast_ref::<PackedConv<T>>().ok_or("Wrong Op type")?;
+        let packed = op.im2col.im2col(&input.to_array_view::<T>()?)?;
+        match self.gemm {
+            Some(ref mut state) => state.eval(session, &*op.gemm, tvec!(packed.into())),
+            None => op.gemm.as_stateless().unwrap().eval(tvec!(packed.into())),
+        }
+    }
+}
+
+impl<T> InferenceRulesOp for PackedConv<T>
+where
+    T: Datum
+        + Clone
+        + ::ndarray::LinalgScalar
+        + ::std::ops::AddAssign<T>
+        + FloatLike
+        + num_traits::Float,
+{
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        _s: &mut Solver<'r>,
+        _inputs: &'p [TensorProxy],
+        _outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        unreachable!()
+    }
+}
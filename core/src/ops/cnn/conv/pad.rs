@@ -0,0 +1,128 @@
+use crate::internal::*;
+
+use super::ConvUnary;
+use crate::ops::cnn::{PaddingSpec, PatchPadMode};
+
+impl ConvUnary {
+    /// Absorb a reflect or edge Pad feeding the conv into its patch.
+    pub(super) fn fuse_pad(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::array::{Pad, PadMode};
+        let spatial_rank = self.full_input_shape.len() - 2;
+        if self.summary.is_some()
+            || self.pad_mode != PatchPadMode::Zero
+            || !(0..spatial_rank).all(|ax| self.padding.valid_dim(ax))
+        {
+            return Ok(None);
+        }
+        let prec = if let Some(prec) = model.single_prec(node.id)? {
+            prec
+        } else {
+            return Ok(None);
+        };
+        let pad = if let Some(pad) = prec.op_as::<Pad>() {
+            pad
+        } else {
+            return Ok(None);
+        };
+        let pad_mode = match pad.mode {
+            PadMode::Reflect => PatchPadMode::Reflect,
+            PadMode::Edge => PatchPadMode::Edge,
+            _ => return Ok(None),
+        };
+        if model.single_succ(prec.id)?.is_none() {
+            return Ok(None);
+        }
+        let input_shape: TVec<TDim> = model.outlet_fact(prec.inputs[0])?.shape.iter().collect();
+        let shape = self.data_format.shape(&input_shape);
+        let hw_axes = shape.hw_axes();
+        let mut before = tvec!();
+        let mut after = tvec!();
+        for (ax, &(a, b)) in pad.pads.iter().enumerate() {
+            if hw_axes.contains(&ax) {
+                if pad_mode == PatchPadMode::Reflect {
+                    match input_shape[ax].to_integer() {
+                        Ok(d) if (a.max(b) as i32) < d => (),
+                        _ => return Ok(None),
+                    }
+                }
+                before.push(a);
+                after.push(b);
+            } else if a != 0 || b != 0 {
+                return Ok(None);
+            }
+        }
+        let mut op = self.clone();
+        op.padding = PaddingSpec::Explicit(before, after);
+        op.pad_mode = pad_mode;
+        op.full_input_shape = input_shape;
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, prec.inputs[0])?;
+        let out =
+            patch.chain(&*node.name, op, node.outputs.iter().map(|o| o.fact.clone()).collect())?;
+        for ix in 0..node.outputs.len() {
+            patch.shunt_outside(OutletId::new(node.id, ix), OutletId::new(out, ix))?;
+        }
+        Ok(Some(patch.with_label("fused pad")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use ndarray::*;
+
+    fn padded_conv(mode: PatchPadMode, pads: [usize; 4]) -> (Arc<Tensor>, Arc<Tensor>) {
+        use crate::ops::array::{Pad, PadMode};
+        let input: Vec<f32> = (0..2 * 3 * 4).map(|i| i as f32).collect();
+        let input = Array4::from_shape_vec((1, 2, 3, 4), input).unwrap().into_arc_tensor();
+        let kernel: Vec<f32> = (0..3 * 2 * 3 * 3).map(|i| (i % 7) as f32 - 3.0).collect();
+        let kernel = Array4::from_shape_vec((3, 2, 3, 3), kernel).unwrap().into_arc_tensor();
+        let pad_mode = match mode {
+            PatchPadMode::Reflect => PadMode::Reflect,
+            PatchPadMode::Edge => PadMode::Edge,
+            PatchPadMode::Zero => PadMode::Constant(0.0),
+        };
+        let pad = Pad::new(vec![(0, 0), (0, 0), (pads[0], pads[2]), (pads[1], pads[3])], pad_mode);
+        let padded = pad.eval(tvec!(input.clone())).unwrap().remove(0);
+        let facts = conv_facts(padded.clone(), kernel.clone());
+        let explicit = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let expected = explicit.eval(tvec!(padded)).unwrap().remove(0);
+
+        let mut conv = Conv::default();
+        conv.padding = PaddingSpec::Explicit(tvec!(pads[0], pads[1]), tvec!(pads[2], pads[3]));
+        let facts = conv_facts(input.clone(), kernel);
+        let mut integrated = conv.to_unary(&facts).unwrap().unwrap();
+        integrated.pad_mode = mode;
+        (expected, integrated.eval(tvec!(input)).unwrap().remove(0))
+    }
+
+    #[test]
+    fn integrated_reflect_pad_matches_explicit_pad() {
+        let (expected, found) = padded_conv(PatchPadMode::Reflect, [1, 2, 2, 1]);
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn integrated_edge_pad_matches_explicit_pad() {
+        let (expected, found) = padded_conv(PatchPadMode::Edge, [2, 1, 3, 4]);
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn reflect_pad_larger_than_input_is_an_error() {
+        let input = rctensor4(&[[[[0.0f32, 1.0], [2.0, 3.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]]]);
+        let mut conv = Conv::default();
+        conv.padding = PaddingSpec::Explicit(tvec!(2, 0), tvec!(0, 0));
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.pad_mode = PatchPadMode::Reflect;
+        assert!(op.eval(tvec!(input)).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::blocked::BlockedMatMat;
+use super::error::ConvError;
+use super::mat_mat::MatMat;
+use super::vec_mat::VecMat;
+use super::ConvUnary;
+
+impl ConvUnary {
+    /// Evaluate through im2col, writing each output channel to its own
+    /// (H, W) plane instead of a single tensor: image by image, then channel
+    /// by channel.
+    ///
+    /// Only convolutions over two spatial dimensions, without channel
+    /// summary, qualify. Groups of a single output channel, and kernels
+    /// packed in channel blocks, go through the regular output and a copy.
+    pub fn eval_planar<T>(&self, input: &Tensor) -> TractResult<Vec<Array2<T>>>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        for dt in &[T::datum_type(), input.datum_type()] {
+            if *dt != self.kernel.datum_type() {
+                bail!(ConvError::DtypeMismatch { expected: self.kernel.datum_type(), found: *dt });
+            }
+        }
+        if self.summary.is_some() || self.f64_output || self.token_output {
+            bail!("Planar output supports neither channel summaries, f64 output nor tokens");
+        }
+        let (im2col, _, gemm) = self.to_im2col_pair::<T>(input.shape())?;
+        let packed = im2col.im2col(&input.to_array_view::<T>()?)?;
+        let packed = packed.to_array_view::<T>()?.into_dimensionality()?;
+        if let Some(mat_mat) = gemm.downcast_ref::<MatMat<T>>() {
+            let mut c_panel = vec![T::zero(); mat_mat.mm.c_panel_len()];
+            return mat_mat.conv_gemm_planar(&packed, &mut c_panel);
+        }
+        let (output, _) = if let Some(vec_mat) = gemm.downcast_ref::<VecMat<T>>() {
+            vec_mat.conv_gemm(&packed)?
+        } else {
+            gemm.downcast_ref::<BlockedMatMat<T>>().unwrap().conv_gemm(&packed)?
+        };
+        let shape = self.data_format.shape(output.shape());
+        if shape.hw_rank() != 2 {
+            bail!(ConvError::UnsupportedLayout { format: self.data_format, rank: shape.rank() });
+        }
+        let mut planes = vec![];
+        for n in 0..shape.n() {
+            let image = output.index_axis(Axis(shape.n_axis()), n);
+            for c in 0..shape.c() {
+                // n axis is gone, c axis moves accordingly
+                let plane = image.index_axis(Axis(shape.c_axis() - 1), c);
+                planes.push(plane.to_owned().into_dimensionality()?);
+            }
+        }
+        Ok(planes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::{conv_facts, ChannelSummary};
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use crate::ops::nn::DataFormat;
+
+    fn check_planar(conv: Conv, input: Array4<f32>, kernel: Array4<f32>, group: usize) {
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = conv;
+        conv.group = group;
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        let channels = op.output_channels();
+        op.bias = Some(Array1::from_shape_fn(channels, |c| c as f32 * 10.0).into_tensor());
+        let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        let expected = expected.to_array_view::<f32>().unwrap();
+        let shape = op.data_format.shape(expected.shape());
+        let planes = op.eval_planar::<f32>(&input).unwrap();
+        assert_eq!(planes.len(), shape.n() * channels);
+        for n in 0..shape.n() {
+            let image = expected.index_axis(Axis(shape.n_axis()), n);
+            for c in 0..channels {
+                let plane = image.index_axis(Axis(shape.c_axis() - 1), c);
+                assert_eq!(planes[n * channels + c].view().into_dyn(), plane);
+            }
+        }
+    }
+
+    #[test]
+    fn planar_output_matches_tensor_output() {
+        let input = Array4::from_shape_fn((2, 4, 7, 9), |(n, c, y, x)| {
+            ((n * 252 + c * 63 + y * 9 + x) % 11) as f32 - 5.0
+        });
+        let nhwc = || {
+            Conv::new(
+                DataFormat::NHWC,
+                KernelFormat::HWIO,
+                None,
+                None,
+                PaddingSpec::SameUpper,
+                None,
+                1,
+            )
+        };
+        // kernel as A, kernel as B on the narrow ones, and a vector product
+        // for single-channel groups
+        for &(o, group) in &[(16, 1), (8, 2), (2, 1), (4, 4)] {
+            let kernel = Array4::from_shape_fn((o, 4 / group, 3, 3), |(o, c, y, x)| {
+                ((o * 7 + c * 9 + y * 3 + x) % 5) as f32 - 2.0
+            });
+            check_planar(Conv::default(), input.clone(), kernel.clone(), group);
+            if group == 1 {
+                check_planar(
+                    nhwc(),
+                    input.clone().permuted_axes([0, 2, 3, 1]),
+                    kernel.permuted_axes([2, 3, 1, 0]),
+                    group,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn planar_output_rejects_summaries() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        op.summary = Some(ChannelSummary::Avg);
+        assert!(op.eval_planar::<f32>(&input).is_err());
+        op.summary = None;
+        assert!(op.eval_planar::<f64>(&input).is_err());
+    }
+}
@@ -0,0 +1,878 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use tract_linalg::quant::{
+    mat_mul_i16_i8_i32, mat_mul_i16_i8_i64, quantize_multiplier, requantize_i64_to_i16,
+    scale_by_fixed_point, MatRef,
+};
+pub use tract_linalg::quant::{Overflow, Rounding};
+use tract_linalg::PackB;
+
+use super::dequant::DequantConv;
+use super::error::ConvError;
+use super::im2col::Im2Col;
+use super::ConvUnary;
+use crate::ops::cnn::{PaddingSpec, PatchPadMode};
+use crate::ops::nn::DataFormat;
+
+/// Convolution of symmetric int16 activations by symmetric int8 weights.
+///
+/// Products are accumulated in i32 or i64 (`accumulator`), then requantized
+/// to int16 with the output scale. Bias, if any, is expressed in accumulator
+/// units (input_scale * kernel_scale).
+///
+/// Ties of the requantization are broken by `rounding`: away from zero by
+/// default, like TFLite reference kernels, or to even with `with_rounding`.
+///
+/// An i32 accumulator is exact for up to 512 taps (see mat_mul_i16_i8_i32).
+/// Convolutions with more are rejected, unless `with_overflow` says how sums
+/// leaving its range behave, to match the runtime the model comes from.
+///
+/// Asymmetric activations and weights get their zero points with
+/// `with_zero_points`.
+///
+/// Activations can also come in and go out as i8 or u8, independently of
+/// each other, with `with_datum_types`: they are widened to int16 for the
+/// product, and the requantized outputs are offset by the output zero point
+/// then saturated to the range of the output type.
+///
+/// With `with_fixed_point`, the requantization uses integer arithmetic
+/// only, and so does the whole evaluation.
+#[derive(Debug, Clone, new)]
+pub struct QConvI16 {
+    /// Geometry and i8 kernel. Its float bias is ignored.
+    pub conv: ConvUnary,
+    pub bias: Option<Tensor>,
+    pub input_scale: f32,
+    /// One scale for the whole kernel, or one per output channel.
+    pub kernel_scales: TVec<f32>,
+    pub output_scale: f32,
+    pub accumulator: DatumType,
+    #[new(value = "Rounding::HalfAwayFromZero")]
+    pub rounding: Rounding,
+    #[new(default)]
+    pub overflow: Option<Overflow>,
+    /// See `with_zero_points`.
+    #[new(default)]
+    pub input_zero_point: i16,
+    #[new(default)]
+    pub kernel_zero_point: i8,
+    /// See `with_datum_types`.
+    #[new(value = "DatumType::I16")]
+    pub input_datum_type: DatumType,
+    #[new(value = "DatumType::I16")]
+    pub output_datum_type: DatumType,
+    #[new(default)]
+    pub output_zero_point: i32,
+    /// See `with_fixed_point`.
+    #[new(default)]
+    pub fixed_point: Option<TVec<(i32, i32)>>,
+}
+
+/// How the accumulators of an output channel are scaled to i16.
+#[derive(Debug, Clone, Copy)]
+enum Requantization {
+    Float(f32),
+    /// Q31 multiplier and right shift, see `quantize_multiplier`.
+    FixedPoint(i32, i32),
+}
+
+impl Requantization {
+    fn apply(self, acc: i64, rounding: Rounding) -> i16 {
+        match self {
+            Requantization::Float(multiplier) => requantize_i64_to_i16(acc, multiplier, rounding),
+            Requantization::FixedPoint(multiplier, shift) => {
+                scale_by_fixed_point(acc, multiplier, shift, rounding)
+                    .max(std::i16::MIN as i64)
+                    .min(std::i16::MAX as i64) as i16
+            }
+        }
+    }
+}
+
+/// (min, max) of the activation types of `QConvI16`.
+fn activation_range(dt: DatumType) -> TractResult<(i32, i32)> {
+    match dt {
+        DatumType::I16 => Ok((std::i16::MIN as i32, std::i16::MAX as i32)),
+        DatumType::I8 => Ok((std::i8::MIN as i32, std::i8::MAX as i32)),
+        DatumType::U8 => Ok((0, std::u8::MAX as i32)),
+        dt => bail!("Unsupported activation type {:?}, expected I16, I8 or U8", dt),
+    }
+}
+
+/// Activation ranges of a convolution, observed by running it in f32 on
+/// calibration data.
+#[derive(Debug, Clone, new)]
+pub struct CalibrationStats {
+    /// (min, max) of each input channel.
+    pub input: TVec<(f32, f32)>,
+    /// (min, max) of each output channel.
+    pub output: TVec<(f32, f32)>,
+}
+
+impl CalibrationStats {
+    /// Symmetric int16 scale covering every channel of `ranges`.
+    fn scale(ranges: &[(f32, f32)]) -> f32 {
+        let bound =
+            ranges.iter().fold(0.0f32, |acc, &(min, max)| acc.max(min.abs()).max(max.abs()));
+        if bound == 0.0 {
+            1.0
+        } else {
+            bound / std::i16::MAX as f32
+        }
+    }
+
+    /// Asymmetric int8 scale and zero point covering every channel of
+    /// `ranges`, and zero.
+    fn scale_and_zero_point_i8(ranges: &[(f32, f32)]) -> (f32, i8) {
+        let (min, max) = ranges
+            .iter()
+            .fold((0.0f32, 0.0f32), |(lo, hi), &(min, max)| (lo.min(min), hi.max(max)));
+        if max == min {
+            return (1.0, 0);
+        }
+        let scale = (max - min) / 255.0;
+        let zero_point = (std::i8::MIN as f32 - min / scale).round().max(-128.0).min(127.0);
+        (scale, zero_point as i8)
+    }
+}
+
+impl QConvI16 {
+    /// Post-training quantization of a f32 convolution.
+    ///
+    /// The kernel gets symmetric per-channel int8 scales, activations one
+    /// int16 scale each from the calibration ranges, and the bias is
+    /// pre-scaled to accumulator units. i32 accumulation is picked when it
+    /// can not overflow.
+    pub fn quantize(conv: &ConvUnary, calib: &CalibrationStats) -> TractResult<QConvI16> {
+        check_calibrated_channels(conv, calib)?;
+        let input_scale = CalibrationStats::scale(&calib.input);
+        let output_scale = CalibrationStats::scale(&calib.output);
+        QConvI16::quantize_with_scales(conv, input_scale, output_scale)
+    }
+
+    /// Quantizes the kernel and the bias of `conv` for these activation
+    /// scales.
+    fn quantize_with_scales(
+        conv: &ConvUnary,
+        input_scale: f32,
+        output_scale: f32,
+    ) -> TractResult<QConvI16> {
+        let output_channels = conv.output_channels();
+        let DequantConv { conv: mut quantized, kernel_scales } = DequantConv::quantize(conv)?;
+        let bias = quantized
+            .bias
+            .take()
+            .map(|b| -> TractResult<Tensor> {
+                let b = b.cast_to::<f32>()?;
+                let b = b.as_slice::<f32>()?;
+                if b.len() != 1 && b.len() != output_channels {
+                    bail!(ConvError::ShapeMismatch {
+                        what: "bias",
+                        expected: output_channels,
+                        found: b.len()
+                    });
+                }
+                let bias: Vec<i64> = (0..output_channels)
+                    .map(|c| {
+                        let b = if b.len() == 1 { b[0] } else { b[c] };
+                        (b / (input_scale * kernel_scales[c])).round() as i64
+                    })
+                    .collect();
+                Ok(Array1::from_vec(bias).into())
+            })
+            .transpose()?;
+        let k = quantized.kernel_as_group_o_ihw::<i8>()?.shape()[2];
+        // see mat_mul_i16_i8_i32
+        let accumulator = if k <= 512 { DatumType::I32 } else { DatumType::I64 };
+        Ok(QConvI16::new(quantized, bias, input_scale, kernel_scales, output_scale, accumulator))
+    }
+
+    /// Break requantization ties with `rounding` instead.
+    pub fn with_rounding(self, rounding: Rounding) -> QConvI16 {
+        QConvI16 { rounding, ..self }
+    }
+
+    /// Make the i32 accumulator behave as `overflow` when a sum leaves its
+    /// range.
+    pub fn with_overflow(self, overflow: Overflow) -> QConvI16 {
+        QConvI16 { overflow: Some(overflow), ..self }
+    }
+
+    /// Offset the input and kernel by their zero points.
+    ///
+    /// Over the k taps of an output, the products of the offset values are
+    ///
+    ///   sum(x.w) - kernel_zp * sum(x) - input_zp * sum(w) + k * input_zp * kernel_zp
+    ///
+    /// The last two terms only depend on the output channel: they are folded
+    /// into `bias` here, once. Evaluation only subtracts `kernel_zp * sum(x)`
+    /// from the product, and pads the input with `input_zp`, its real zero.
+    pub fn with_zero_points(
+        self,
+        input_zero_point: i16,
+        kernel_zero_point: i8,
+    ) -> TractResult<QConvI16> {
+        if self.input_zero_point != 0 || self.kernel_zero_point != 0 {
+            bail!(
+                "Zero points are already folded: {} for the input, {} for the kernel",
+                self.input_zero_point,
+                self.kernel_zero_point
+            );
+        }
+        let output_channels = self.conv.output_channels();
+        let kernel = self.conv.kernel_as_group_o_ihw::<i8>()?;
+        let (m, k) = (kernel.shape()[1], kernel.shape()[2]);
+        let mut bias = match &self.bias {
+            Some(b) => b.cast_to::<i64>()?.as_slice::<i64>()?.to_vec(),
+            None => vec![0; output_channels],
+        };
+        if bias.len() == 1 {
+            bias = vec![bias[0]; output_channels];
+        } else if bias.len() != output_channels {
+            bail!(ConvError::ShapeMismatch {
+                what: "bias",
+                expected: output_channels,
+                found: bias.len()
+            });
+        }
+        let (input_zp, kernel_zp) = (input_zero_point as i64, kernel_zero_point as i64);
+        for (c, b) in bias.iter_mut().enumerate() {
+            let weights = kernel.index_axis(Axis(0), c / m);
+            let sum = weights.index_axis(Axis(0), c % m).iter().map(|&w| w as i64).sum::<i64>();
+            *b += k as i64 * input_zp * kernel_zp - input_zp * sum;
+        }
+        let op = QConvI16 {
+            bias: Some(Array1::from_vec(bias).into()),
+            input_zero_point,
+            kernel_zero_point,
+            ..self
+        };
+        op.check_zero_points()?;
+        Ok(op)
+    }
+
+    /// Read `input` activations and write `output` ones, each of I16, I8 or
+    /// U8, the outputs being offset by `output_zero_point`.
+    pub fn with_datum_types(
+        self,
+        input: DatumType,
+        output: DatumType,
+        output_zero_point: i32,
+    ) -> TractResult<QConvI16> {
+        let op = QConvI16 {
+            input_datum_type: input,
+            output_datum_type: output,
+            output_zero_point,
+            ..self
+        };
+        op.check_zero_points()?;
+        Ok(op)
+    }
+
+    /// Requantize with integer arithmetic only, for targets without a FPU.
+    ///
+    /// The multiplier of each output channel is turned here, once, into the
+    /// (multiplier, shift) fixed-point form of TFLite: evaluation then never
+    /// touches a float. Results stay within one unit of the float
+    /// requantization.
+    pub fn with_fixed_point(self) -> TractResult<QConvI16> {
+        let output_channels = self.conv.output_channels();
+        let channels = self.kernel_scales.len();
+        if channels != 1 && channels != output_channels {
+            bail!(
+                "Expected 1 or {} kernel scales, got {}",
+                output_channels,
+                self.kernel_scales.len()
+            );
+        }
+        let fixed_point = (0..channels)
+            .map(|c| {
+                let multiplier = self.multiplier(c) as f64;
+                // keeps the shift in the range of scale_by_fixed_point
+                if !(multiplier >= 2f64.powi(-96) && multiplier < 2f64.powi(30)) {
+                    bail!("Can not requantize by {} in fixed point", multiplier);
+                }
+                Ok(quantize_multiplier(multiplier))
+            })
+            .collect::<TractResult<_>>()?;
+        Ok(QConvI16 { fixed_point: Some(fixed_point), ..self })
+    }
+
+    fn requantization(&self, channel: usize) -> Requantization {
+        match &self.fixed_point {
+            Some(fp) => {
+                let (multiplier, shift) = fp[if fp.len() == 1 { 0 } else { channel }];
+                Requantization::FixedPoint(multiplier, shift)
+            }
+            None => Requantization::Float(self.multiplier(channel)),
+        }
+    }
+
+    /// Zero points must be values of the activations types.
+    fn check_zero_points(&self) -> TractResult<()> {
+        for &(dt, zero_point, what) in &[
+            (self.input_datum_type, self.input_zero_point as i32, "input"),
+            (self.output_datum_type, self.output_zero_point, "output"),
+        ] {
+            let (min, max) = activation_range(dt)?;
+            if zero_point < min || zero_point > max {
+                bail!("The {} zero point {} is not a {:?} value", what, zero_point, dt);
+            }
+        }
+        Ok(())
+    }
+
+    /// The input activations, widened to i16.
+    fn widen_input(&self, input: &Tensor) -> TractResult<Tensor> {
+        if input.datum_type() != self.input_datum_type {
+            bail!(ConvError::DtypeMismatch {
+                expected: self.input_datum_type,
+                found: input.datum_type()
+            });
+        }
+        match self.input_datum_type {
+            DatumType::I8 => Ok(input.to_array_view::<i8>()?.mapv(|x| x as i16).into()),
+            DatumType::U8 => Ok(input.to_array_view::<u8>()?.mapv(|x| x as i16).into()),
+            _ => Ok(input.clone()),
+        }
+    }
+
+    /// The saturated outputs, narrowed to the output type.
+    fn narrow_output(&self, output: ArrayD<i16>) -> Tensor {
+        match self.output_datum_type {
+            DatumType::I8 => output.mapv(|x| x as i8).into(),
+            DatumType::U8 => output.mapv(|x| x as u8).into(),
+            _ => output.into(),
+        }
+    }
+
+    /// The input padded with its zero point, and the conv reading it
+    /// unpadded: im2col would pad with 0, which is not the real zero.
+    fn pad_with_zero_point(&self, input: &Tensor) -> TractResult<Option<(Tensor, ConvUnary)>> {
+        let patch = self.conv.patch(input.shape());
+        if self.input_zero_point == 0 || !patch.padded || self.conv.pad_mode != PatchPadMode::Zero {
+            return Ok(None);
+        }
+        let shape = self.conv.data_format.shape(input.shape());
+        let mut padded_shape: TVec<usize> = input.shape().into();
+        for (ix, axis) in shape.hw_axes().enumerate() {
+            padded_shape[axis] += patch.pad_before[ix] + patch.pad_after[ix];
+        }
+        let mut padded = ArrayD::from_elem(&*padded_shape, self.input_zero_point);
+        let mut inner = padded.view_mut();
+        for (ix, axis) in shape.hw_axes().enumerate() {
+            let range = patch.pad_before[ix]..padded_shape[axis] - patch.pad_after[ix];
+            inner.slice_axis_inplace(Axis(axis), range.into());
+        }
+        inner.assign(&input.to_array_view::<i16>()?);
+        let mut conv = self.conv.clone();
+        conv.padding = PaddingSpec::Valid;
+        conv.full_input_shape = padded_shape.iter().map(|&d| d.to_dim()).collect();
+        Ok(Some((padded.into(), conv)))
+    }
+
+    fn multiplier(&self, channel: usize) -> f32 {
+        let kernel_scale = if self.kernel_scales.len() == 1 {
+            self.kernel_scales[0]
+        } else {
+            self.kernel_scales[channel]
+        };
+        self.input_scale * kernel_scale / self.output_scale
+    }
+
+    fn eval_i16(&self, input: &Tensor) -> TractResult<Tensor> {
+        self.check_zero_points()?;
+        let input = &self.widen_input(input)?;
+        let (output_min, output_max) = activation_range(self.output_datum_type)?;
+        if self.conv.kernel.datum_type() != DatumType::I8 {
+            bail!(ConvError::DtypeMismatch {
+                expected: DatumType::I8,
+                found: self.conv.kernel.datum_type()
+            });
+        }
+        let padded = self.pad_with_zero_point(input)?;
+        let (input, conv) = match padded {
+            Some((ref input, ref conv)) => (input, conv),
+            None => (input, &self.conv),
+        };
+        let patch = conv.patch(input.shape());
+        patch.check_pad_mode()?;
+        let input_shape = conv.data_format.shape(input.shape().into());
+        let output_channels = conv.output_channels();
+        let output_shape =
+            conv.data_format.from_n_c_hw(input_shape.n(), output_channels, &*patch.output_shape);
+        if self.kernel_scales.len() != 1 && self.kernel_scales.len() != output_channels {
+            bail!(
+                "Expected 1 or {} kernel scales, got {}",
+                output_channels,
+                self.kernel_scales.len()
+            );
+        }
+        let group = conv.group;
+        let kernel = conv.kernel_as_group_o_ihw::<i8>()?;
+        let m = output_channels / group;
+        let k = kernel.shape()[2];
+        let n = patch.output_shape.iter().cloned().product::<usize>();
+        let overflow = match self.overflow {
+            Some(overflow) => overflow,
+            None if self.accumulator == DatumType::I32 && k > 512 => {
+                bail!("An I32 accumulator may overflow over {} taps, accumulate in I64", k)
+            }
+            None => Overflow::Wrap,
+        };
+
+        // nr = 1 lays im2col data out as n contiguous rows of k values
+        let b_pack = PackB::<i16>::new(k, n, 1, std::mem::size_of::<i16>());
+        let packed_b_len = b_pack.len();
+        let ci_per_group = input_shape.c() / group;
+        let im2col = Im2Col::new(patch, input_shape.clone(), m, k, n, group, ci_per_group, b_pack);
+        let packed = im2col.im2col(&input.to_array_view::<i16>()?)?;
+        let packed = packed.as_slice::<i16>()?;
+
+        let bias = self
+            .bias
+            .as_ref()
+            .map(|b| -> TractResult<Vec<i64>> {
+                Ok(b.cast_to::<i64>()?.as_slice::<i64>()?.to_vec())
+            })
+            .transpose()?;
+
+        let mut output = ArrayD::<i16>::zeros(&*output_shape.shape);
+        let spatial_stride = match conv.data_format {
+            DataFormat::NCHW => 1,
+            DataFormat::NHWC => output_channels,
+        };
+        let mut acc32 = vec![0i32; m * n];
+        let mut acc = vec![0i64; m * n];
+        let mut kernel_zp_terms = vec![0i64; n];
+        for i in 0..input_shape.n() {
+            for g in 0..group {
+                let a = kernel.index_axis(Axis(0), g);
+                let a = MatRef::row_major(a.as_slice().unwrap(), m, k);
+                let columns = &packed[(i * group + g) * packed_b_len..][..n * k];
+                let b = MatRef::new(columns, (k, n), (1, k));
+                if self.kernel_zero_point != 0 {
+                    for (sum, column) in kernel_zp_terms.iter_mut().zip(columns.chunks(k)) {
+                        *sum = column.iter().map(|&x| x as i64).sum::<i64>()
+                            * self.kernel_zero_point as i64;
+                    }
+                }
+                match self.accumulator {
+                    DatumType::I32 => {
+                        mat_mul_i16_i8_i32(a, b, &mut acc32, overflow);
+                        for (acc, v) in acc.iter_mut().zip(acc32.iter()) {
+                            *acc = *v as i64;
+                        }
+                    }
+                    DatumType::I64 => mat_mul_i16_i8_i64(a, b, &mut acc),
+                    dt => bail!("Unsupported accumulator {:?}, expected I32 or I64", dt),
+                }
+                let output_ptr = output.as_mut_ptr();
+                for row in 0..m {
+                    let c = g * m + row;
+                    let requantization = self.requantization(c);
+                    let bias = bias.as_ref().map(|b| b[c]).unwrap_or(0);
+                    let offset = output_shape.n_stride() * i + output_shape.c_stride() * c;
+                    for j in 0..n {
+                        let v = requantization
+                            .apply(acc[row * n + j] - kernel_zp_terms[j] + bias, self.rounding)
+                            as i32
+                            + self.output_zero_point;
+                        unsafe {
+                            *output_ptr.offset((offset + j * spatial_stride) as isize) =
+                                v.max(output_min).min(output_max) as i16;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(self.narrow_output(output))
+    }
+}
+
+fn check_calibrated_channels(conv: &ConvUnary, calib: &CalibrationStats) -> TractResult<()> {
+    let output_channels = conv.output_channels();
+    if calib.output.len() != output_channels {
+        bail!(ConvError::ShapeMismatch {
+            what: "calibrated output channels",
+            expected: output_channels,
+            found: calib.output.len()
+        });
+    }
+    Ok(())
+}
+
+impl ConvUnary {
+    /// Post-training quantization to int8.
+    ///
+    /// The kernel gets symmetric per-channel int8 scales. The input and
+    /// output get an int8 scale and zero point each, from their calibration
+    /// ranges. The bias is pre-scaled by input_scale * kernel_scale, and
+    /// the input zero point folded into it. The returned op reads and writes
+    /// i8 activations, see `QConvI16::with_datum_types`.
+    pub fn quantize(&self, calib: &CalibrationStats) -> TractResult<QConvI16> {
+        check_calibrated_channels(self, calib)?;
+        let (input_scale, input_zero_point) =
+            CalibrationStats::scale_and_zero_point_i8(&calib.input);
+        let (output_scale, output_zero_point) =
+            CalibrationStats::scale_and_zero_point_i8(&calib.output);
+        QConvI16::quantize_with_scales(self, input_scale, output_scale)?
+            .with_datum_types(DatumType::I8, DatumType::I8, output_zero_point as i32)?
+            .with_zero_points(input_zero_point as i16, 0)
+    }
+}
+
+impl Op for QConvI16 {
+    fn name(&self) -> Cow<str> {
+        "QConvI16".into()
+    }
+}
+
+impl StatelessOp for QConvI16 {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        Ok(tvec!(self.eval_i16(&input)?.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for QConvI16 {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, self.input_datum_type)?;
+        s.equals(&outputs[0].datum_type, self.output_datum_type)?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.conv.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+
+    fn float_and_quant(accumulator: DatumType) -> (Arc<Tensor>, Arc<Tensor>) {
+        let input: Vec<i16> = (0..2 * 4 * 4).map(|i| (i * 997 % 2001) as i16 - 1000).collect();
+        let input = Array4::from_shape_vec((1, 2, 4, 4), input).unwrap();
+        let kernel: Vec<i8> = (0..4 * 3 * 3).map(|i| (i * 37 % 255) as i8).collect();
+        let kernel = Array4::from_shape_vec((4, 1, 3, 3), kernel).unwrap();
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            2,
+        );
+        let finput = input.mapv(|x| x as f32).into_arc_tensor();
+        let fkernel = kernel.mapv(|x| x as f32).into_arc_tensor();
+        let facts = conv_facts(finput.clone(), fkernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        let expected = unary.eval(tvec!(finput)).unwrap().remove(0);
+        unary.kernel = kernel.into();
+        let op = QConvI16::new(unary, None, 1.0, tvec!(1.0 / 64.0), 1.0, accumulator);
+        let found = op.eval(tvec!(input.into_arc_tensor())).unwrap().remove(0);
+        let expected = expected
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| (x / 64.0).round().max(-32768.0).min(32767.0) as i16)
+            .into_arc_tensor();
+        (expected, found)
+    }
+
+    #[test]
+    fn i16_i8_i32() {
+        let (expected, found) = float_and_quant(DatumType::I32);
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn i16_i8_i64() {
+        let (expected, found) = float_and_quant(DatumType::I64);
+        assert_eq!(expected, found);
+    }
+
+    #[test]
+    fn rounding_breaks_ties() {
+        let input = Array4::from_shape_vec((1, 1, 1, 6), vec![1i16, 3, 5, -1, -3, 4]).unwrap();
+        let kernel = Array4::<f32>::ones((1, 1, 1, 1));
+        let facts = conv_facts(input.mapv(|x| x as f32), kernel);
+        let mut unary = Conv::default().to_unary(&facts).unwrap().unwrap();
+        unary.kernel = Array4::<i8>::ones((1, 1, 1, 1)).into();
+        let op = QConvI16::new(unary, None, 1.0, tvec!(0.5), 1.0, DatumType::I32);
+        let input = input.into_arc_tensor();
+        let away = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(*away, Tensor::from(arr4(&[[[[1i16, 2, 3, -1, -2, 2]]]])));
+        let op = op.with_rounding(Rounding::HalfToEven);
+        let even = op.eval(tvec!(input)).unwrap().remove(0);
+        assert_eq!(*even, Tensor::from(arr4(&[[[[0i16, 2, 2, 0, -2, 2]]]])));
+    }
+
+    #[test]
+    fn i32_accumulator_overflow() {
+        // 1024 products of -128 * -32768 sum to 2^32, scaled back by 2^-17
+        let input = Array4::from_elem((1, 1024, 1, 1), std::i16::MIN);
+        let facts = conv_facts(input.mapv(|x| x as f32), Array4::<f32>::ones((1, 1024, 1, 1)));
+        let mut unary = Conv::default().to_unary(&facts).unwrap().unwrap();
+        unary.kernel = Array4::from_elem((1, 1024, 1, 1), std::i8::MIN).into();
+        let op = QConvI16::new(unary, None, 1.0, tvec!(1.0 / 131072.0), 1.0, DatumType::I32);
+        let input = input.into_arc_tensor();
+        assert!(op.eval(tvec!(input.clone())).is_err());
+        let op = op.with_overflow(Overflow::Wrap);
+        let wrapped = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(*wrapped, Tensor::from(arr4(&[[[[0i16]]]])));
+        let op = op.with_overflow(Overflow::Saturate);
+        let saturated = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(*saturated, Tensor::from(arr4(&[[[[16384i16]]]])));
+        let op = QConvI16 { accumulator: DatumType::I64, ..op };
+        let exact = op.eval(tvec!(input)).unwrap().remove(0);
+        assert_eq!(*exact, Tensor::from(arr4(&[[[[std::i16::MAX]]]])));
+    }
+
+    #[test]
+    fn zero_points() {
+        let (input_zp, kernel_zp) = (37i16, -5i8);
+        let input: Vec<i16> = (0..2 * 4 * 5).map(|i| (i * 997 % 2001) as i16 - 1000).collect();
+        let input = Array4::from_shape_vec((1, 4, 2, 5), input).unwrap();
+        let kernel: Vec<i8> =
+            (0..6 * 2 * 3 * 3).map(|i| ((i * 37 % 201) as i16 - 100) as i8).collect();
+        let kernel = Array4::from_shape_vec((6, 2, 3, 3), kernel).unwrap();
+        let mut conv = Conv::default();
+        conv.group = 2;
+        conv.padding = PaddingSpec::SameUpper;
+        // the float conv reads the real values, and pads with real zeros
+        let finput = input.mapv(|x| (x - input_zp) as f32).into_arc_tensor();
+        let fkernel = kernel.mapv(|w| (w as i16 - kernel_zp as i16) as f32);
+        let facts = conv_facts(finput.clone(), fkernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(tensor1(&[64.0f32, -640.0, 0.0, 32.0, 1.0, -96.0]));
+        let expected = unary.eval(tvec!(finput)).unwrap().remove(0);
+        let expected = expected
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| (x / 64.0).round().max(-32768.0).min(32767.0) as i16)
+            .into_arc_tensor();
+        unary.bias = None;
+        unary.kernel = kernel.into();
+        let bias = tensor1(&[64i64, -640, 0, 32, 1, -96]);
+        for &accumulator in &[DatumType::I32, DatumType::I64] {
+            let op = QConvI16::new(
+                unary.clone(),
+                Some(bias.clone()),
+                1.0,
+                tvec!(1.0 / 64.0),
+                1.0,
+                accumulator,
+            )
+            .with_zero_points(input_zp, kernel_zp)
+            .unwrap();
+            assert_ne!(op.bias, Some(bias.clone()));
+            let found = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap().remove(0);
+            assert_eq!(expected, found, "{:?}", accumulator);
+            assert!(op.with_zero_points(1, 1).is_err());
+        }
+    }
+
+    #[test]
+    fn u8_input_i8_output() {
+        let (input_zp, output_zp) = (128i16, -3);
+        let input: Vec<u8> = (0..2 * 4 * 5).map(|i| (i * 97 % 256) as u8).collect();
+        let input = Array4::from_shape_vec((1, 2, 4, 5), input).unwrap();
+        let kernel: Vec<i8> =
+            (0..3 * 2 * 3 * 3).map(|i| ((i * 37 % 201) as i16 - 100) as i8).collect();
+        let kernel = Array4::from_shape_vec((3, 2, 3, 3), kernel).unwrap();
+        let mut conv = Conv::default();
+        conv.padding = PaddingSpec::SameUpper;
+        let finput = input.mapv(|x| (x as i16 - input_zp) as f32).into_arc_tensor();
+        let facts = conv_facts(finput.clone(), kernel.mapv(|w| w as f32));
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        let expected = unary.eval(tvec!(finput)).unwrap().remove(0);
+        let expected = expected
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| ((x / 128.0).round() + output_zp as f32).max(-128.0).min(127.0) as i8);
+        // both ends of the i8 range are reached, not those of i16 or u8
+        assert!(expected.iter().any(|&x| x == std::i8::MIN));
+        assert!(expected.iter().any(|&x| x == std::i8::MAX));
+        unary.kernel = kernel.into();
+        let op = QConvI16::new(unary, None, 1.0, tvec!(1.0 / 128.0), 1.0, DatumType::I32)
+            .with_datum_types(DatumType::U8, DatumType::I8, output_zp)
+            .unwrap()
+            .with_zero_points(input_zp, 0)
+            .unwrap();
+        let input = input.into_arc_tensor();
+        let found = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(*found, Tensor::from(expected));
+        let as_i16 = input.to_array_view::<u8>().unwrap().mapv(|x| x as i16);
+        assert!(op.eval(tvec!(as_i16.into_arc_tensor())).is_err());
+    }
+
+    #[test]
+    fn zero_points_in_activation_ranges() {
+        let facts =
+            conv_facts(Array4::<f32>::zeros((1, 1, 2, 2)), Array4::<f32>::ones((1, 1, 1, 1)));
+        let mut unary = Conv::default().to_unary(&facts).unwrap().unwrap();
+        unary.kernel = Array4::<i8>::ones((1, 1, 1, 1)).into();
+        let op = QConvI16::new(unary, None, 1.0, tvec!(1.0), 1.0, DatumType::I32);
+        assert!(op.clone().with_datum_types(DatumType::U8, DatumType::I8, 128).is_err());
+        assert!(op.clone().with_datum_types(DatumType::U8, DatumType::U8, -1).is_err());
+        assert!(op.clone().with_datum_types(DatumType::F32, DatumType::I8, 0).is_err());
+        let op = op.with_datum_types(DatumType::U8, DatumType::U8, 255).unwrap();
+        assert!(op.clone().with_zero_points(-1, 0).is_err());
+        assert!(op.clone().with_zero_points(256, 0).is_err());
+        // the output zero point saturates at the top of the u8 range
+        let input = arr4(&[[[[0u8, 1], [2, 3]]]]).into_arc_tensor();
+        let found = op.eval(tvec!(input)).unwrap().remove(0);
+        assert_eq!(*found, Tensor::from(arr4(&[[[[255u8, 255], [255, 255]]]])));
+    }
+
+    #[test]
+    fn fixed_point_within_one_lsb() {
+        let input = Array4::from_shape_fn((1, 4, 5, 5), |(_, c, y, x)| {
+            ((c * 25 + y * 5 + x) * 37 % 101) as f32 / 25.0 - 2.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((6, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) * 53 % 97) as f32 / 100.0 - 0.48
+        });
+        let mut conv = Conv::default();
+        conv.group = 2;
+        conv.padding = PaddingSpec::SameUpper;
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(Array1::from_shape_fn(6, |c| c as f32 - 2.5).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+        let calib = CalibrationStats::new(channel_ranges(&input), channel_ranges(&expected));
+        let quantized = QConvI16::quantize(&unary, &calib).unwrap();
+        let qinput = input
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| (x / quantized.input_scale).round() as i16 / 2 + 3)
+            .into_arc_tensor();
+        for &accumulator in &[DatumType::I32, DatumType::I64] {
+            for &rounding in &[Rounding::HalfAwayFromZero, Rounding::HalfToEven] {
+                let float = QConvI16 { accumulator, ..quantized.clone() }
+                    .with_rounding(rounding)
+                    .with_zero_points(3, 0)
+                    .unwrap();
+                let fixed = float.clone().with_fixed_point().unwrap();
+                let float = float.eval(tvec!(qinput.clone())).unwrap().remove(0);
+                let fixed = fixed.eval(tvec!(qinput.clone())).unwrap().remove(0);
+                let float = float.to_array_view::<i16>().unwrap();
+                let fixed = fixed.to_array_view::<i16>().unwrap();
+                assert!(float.iter().any(|&x| x.abs() > 1000));
+                for (f, x) in float.iter().zip(fixed.iter()) {
+                    assert!((*f as i32 - *x as i32).abs() <= 1, "float {} fixed {}", f, x);
+                }
+            }
+        }
+        let mut mismatched = quantized;
+        mismatched.kernel_scales.pop();
+        assert!(mismatched.with_fixed_point().is_err());
+    }
+
+    fn channel_ranges(t: &Tensor) -> TVec<(f32, f32)> {
+        t.to_array_view::<f32>()
+            .unwrap()
+            .axis_iter(Axis(1))
+            .map(|c| c.iter().fold((0.0f32, 0.0f32), |(lo, hi), &x| (lo.min(x), hi.max(x))))
+            .collect()
+    }
+
+    #[test]
+    fn quantize_f32_conv() {
+        let input = Array4::from_shape_fn((2, 4, 5, 5), |(n, c, y, x)| {
+            ((n * 100 + c * 25 + y * 5 + x) * 37 % 101) as f32 / 25.0 - 2.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((6, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) * 53 % 97) as f32 / 100.0 - 0.48
+        });
+        let mut conv = Conv::default();
+        conv.group = 2;
+        conv.padding = PaddingSpec::SameUpper;
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(Array1::from_shape_fn(6, |c| c as f32 - 2.5).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+
+        let calib = CalibrationStats::new(channel_ranges(&input), channel_ranges(&expected));
+        let op = QConvI16::quantize(&unary, &calib).unwrap();
+        assert_eq!(op.conv.kernel.datum_type(), DatumType::I8);
+        assert_eq!(op.accumulator, DatumType::I32);
+        let qinput = input
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| (x / op.input_scale).round() as i16)
+            .into_arc_tensor();
+        let found = op.eval(tvec!(qinput)).unwrap().remove(0);
+        let found = found.to_array_view::<i16>().unwrap();
+        let expected = expected.to_array_view::<f32>().unwrap();
+        assert_eq!(expected.shape(), found.shape());
+        let max = expected.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+        for (e, f) in expected.iter().zip(found.iter()) {
+            let f = *f as f32 * op.output_scale;
+            assert!((e - f).abs() < max * 0.02, "expected {} found {}", e, f);
+        }
+    }
+
+    #[test]
+    fn quantize_to_int8() {
+        let input = Array4::from_shape_fn((2, 4, 5, 5), |(n, c, y, x)| {
+            ((n * 100 + c * 25 + y * 5 + x) * 37 % 101) as f32 / 25.0 - 1.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((6, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) * 53 % 97) as f32 / 100.0 - 0.48
+        });
+        let mut conv = Conv::default();
+        conv.group = 2;
+        conv.padding = PaddingSpec::SameUpper;
+        let facts = conv_facts(input.clone(), kernel);
+        let mut unary = conv.to_unary(&facts).unwrap().unwrap();
+        unary.bias = Some(Array1::from_shape_fn(6, |c| c as f32 - 2.5).into());
+        let expected = unary.eval(tvec!(input.clone())).unwrap().remove(0);
+
+        let calib = CalibrationStats::new(channel_ranges(&input), channel_ranges(&expected));
+        let op = unary.quantize(&calib).unwrap();
+        assert_eq!(op.conv.kernel.datum_type(), DatumType::I8);
+        assert_eq!((op.input_datum_type, op.output_datum_type), (DatumType::I8, DatumType::I8));
+        // the input range is not centered on zero
+        assert_ne!(op.input_zero_point, 0);
+        let zero_point = op.input_zero_point as f32;
+        let qinput = input
+            .to_array_view::<f32>()
+            .unwrap()
+            .mapv(|x| ((x / op.input_scale).round() + zero_point).max(-128.0).min(127.0) as i8)
+            .into_arc_tensor();
+        let found = op.eval(tvec!(qinput)).unwrap().remove(0);
+        let found = found.to_array_view::<i8>().unwrap();
+        let expected = expected.to_array_view::<f32>().unwrap();
+        assert_eq!(expected.shape(), found.shape());
+        // within one step of the output quantization
+        for (e, f) in expected.iter().zip(found.iter()) {
+            let f = (*f as i32 - op.output_zero_point) as f32 * op.output_scale;
+            assert!((e - f).abs() <= op.output_scale, "expected {} found {}", e, f);
+        }
+    }
+
+    #[test]
+    fn quantize_checks_calibrated_channels() {
+        let input = Array4::<f32>::zeros((1, 2, 3, 3)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((3, 2, 1, 1));
+        let facts = conv_facts(input, kernel);
+        let unary = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let calib = CalibrationStats::new(tvec!((0.0, 1.0); 2), tvec!((0.0, 1.0); 2));
+        assert!(QConvI16::quantize(&unary, &calib).is_err());
+        assert!(unary.quantize(&calib).is_err());
+    }
+}
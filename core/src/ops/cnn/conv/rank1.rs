@@ -0,0 +1,263 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::{ConvOptions, ConvStrategy, ConvUnary, KernelCache, KernelFormat, PhaseTimer};
+use crate::ops::cnn::{PaddingSpec, PatchPadMode};
+
+/// Factor a matrix as the outer product of a column and a row, if its rank
+/// is at most one.
+///
+/// The column is taken through the largest item, the row scaled by it. The
+/// factorization is accepted when it rebuilds every item within a few ulps
+/// of the largest, so kernels computed as float outer products (gaussian
+/// blurs, Sobel filters) still qualify.
+pub(super) fn factor_rank1<T: Datum + num_traits::Float>(
+    m: ArrayView2<T>,
+) -> Option<(Vec<T>, Vec<T>)> {
+    let ((pi, pj), max) = m
+        .indexed_iter()
+        .map(|(ix, x)| (ix, x.abs()))
+        .fold(((0, 0), T::zero()), |best, it| if it.1 > best.1 { it } else { best });
+    if max == T::zero() {
+        return Some((vec![T::zero(); m.rows()], vec![T::zero(); m.cols()]));
+    }
+    let pivot = m[(pi, pj)];
+    let col: Vec<T> = m.column(pj).to_vec();
+    let row: Vec<T> = m.row(pi).iter().map(|&x| x / pivot).collect();
+    let tolerance = max * T::epsilon() * T::from(16).unwrap();
+    if m.indexed_iter().all(|((i, j), &x)| (x - col[i] * row[j]).abs() <= tolerance) {
+        Some((col, row))
+    } else {
+        None
+    }
+}
+
+impl ConvUnary {
+    /// Split a 2D depthwise convolution whose kernels all have rank one in
+    /// a vertical pass and a horizontal one: kh + kw multiply-adds per
+    /// output point instead of kh * kw.
+    pub(super) fn split_rank1_kernel(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let input_fact = model.outlet_fact(node.inputs[0])?;
+        let dt = input_fact.datum_type;
+        let shape = if let Some(shape) = input_fact.shape.as_finite() {
+            shape
+        } else {
+            return Ok(None);
+        };
+        if self.options.strategy != ConvStrategy::Auto
+            || self.summary.is_some()
+            || self.pad_mode != PatchPadMode::Zero
+            || self.f64_output
+            || self.token_output
+            || shape.len() != 4
+            || self.group != self.data_format.shape(&*shape).c()
+            || self.group != self.output_channels()
+            || self.kernel.datum_type() != dt
+            || !(dt == DatumType::F32 || dt == DatumType::F64)
+        {
+            return Ok(None);
+        }
+        let (kh, kw) = {
+            let spatial = &self.kernel.shape()[self.kernel_fmt.h_axis()..][..2];
+            (spatial[0], spatial[1])
+        };
+        if kh * kw <= kh + kw {
+            return Ok(None);
+        }
+        let factors = if dt == DatumType::F32 {
+            self.factor_kernels::<f32>(kh, kw)?
+        } else {
+            self.factor_kernels::<f64>(kh, kw)?
+        };
+        let (cols, rows) = if let Some(factors) = factors {
+            factors
+        } else {
+            return Ok(None);
+        };
+        let geometry = self.patch(&*shape);
+        let mut mid_shape: TVec<TDim> = self.full_input_shape.clone();
+        let h_axis = self.data_format.shape(&*shape).h_axis();
+        mid_shape[h_axis] = geometry.output_shape[0].to_dim();
+        let pass = |kernel: Tensor, axis: usize, input: &[TDim], output: &[TDim]| {
+            let keep = |v: &[usize], default: usize| -> TVec<usize> {
+                (0..2).map(|ax| if ax == axis { v[ax] } else { default }).collect()
+            };
+            ConvUnary {
+                data_format: self.data_format,
+                kernel_fmt: KernelFormat::OIHW,
+                padding: PaddingSpec::Explicit(
+                    keep(&geometry.pad_before, 0),
+                    keep(&geometry.pad_after, 0),
+                ),
+                dilations: keep(&self.dilations, 1),
+                strides: keep(&self.strides, 1),
+                kernel,
+                bias: if axis == 1 { self.bias.clone() } else { None },
+                full_input_shape: input.into(),
+                full_output_shape: output.into(),
+                group: self.group,
+                summary: None,
+                pad_mode: PatchPadMode::Zero,
+                options: ConvOptions { strategy: ConvStrategy::Auto, ..self.options.clone() },
+                f64_output: false,
+                token_output: false,
+                independent_shape: tvec!(),
+                golden: None,
+                kernel_cache: KernelCache::default(),
+                timer: PhaseTimer::default(),
+            }
+        };
+        let vertical = pass(cols, 0, &self.full_input_shape, &mid_shape);
+        let horizontal = pass(rows, 1, &mid_shape, &self.full_output_shape);
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        patch.chain(
+            format!("{}-vertical", node.name),
+            vertical,
+            tvec!(TypedTensorInfo {
+                shape: mid_shape.iter().cloned().collect(),
+                datum_type: dt,
+                konst: None
+            }),
+        )?;
+        let out = patch.chain(&*node.name, horizontal, tvec!(node.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(out, 0))?;
+        Ok(Some(patch.with_label("split rank-1 kernel in two passes")))
+    }
+
+    /// The (C, 1, kh, 1) and (C, 1, 1, kw) kernels the depthwise kernel is
+    /// the product of, channel by channel.
+    fn factor_kernels<T: Datum + num_traits::Float>(
+        &self,
+        kh: usize,
+        kw: usize,
+    ) -> TractResult<Option<(Tensor, Tensor)>> {
+        let kernel = self.kernel_as_group_o_ihw::<T>()?;
+        let channels = self.group;
+        let mut cols = Array4::<T>::zeros((channels, 1, kh, 1));
+        let mut rows = Array4::<T>::zeros((channels, 1, 1, kw));
+        for c in 0..channels {
+            let k = kernel.slice(s![c, 0, ..]);
+            let k = k.into_shape((kh, kw))?;
+            let (col, row) = if let Some(factors) = factor_rank1(k) {
+                factors
+            } else {
+                return Ok(None);
+            };
+            cols.slice_mut(s![c, 0, .., 0]).assign(&Array1::from_vec(col));
+            rows.slice_mut(s![c, 0, 0, ..]).assign(&Array1::from_vec(row));
+        }
+        Ok(Some((cols.into(), rows.into())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use crate::ops::nn::DataFormat;
+
+    #[test]
+    fn factors_outer_products() {
+        let m = arr2(&[[1f32, 2.0, 1.0], [2.0, 4.0, 2.0], [1.0, 2.0, 1.0]]);
+        let (col, row) = factor_rank1(m.view()).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert_eq!(col[i] * row[j], m[(i, j)]);
+            }
+        }
+        let sobel = arr2(&[[1f32, 0.0, -1.0], [2.0, 0.0, -2.0], [1.0, 0.0, -1.0]]);
+        assert!(factor_rank1(sobel.view()).is_some());
+        assert!(factor_rank1(Array2::<f32>::zeros((2, 3)).view()).is_some());
+    }
+
+    #[test]
+    fn rejects_full_rank() {
+        let m = arr2(&[[1f32, 2.0], [3.0, 4.0]]);
+        assert!(factor_rank1(m.view()).is_none());
+        assert!(factor_rank1(Array2::<f64>::eye(3).view()).is_none());
+    }
+
+    fn gaussian(sigma: f32) -> Vec<f32> {
+        let g: Vec<f32> =
+            (0..5).map(|i| (-((i as f32 - 2.0) / sigma).powi(2) / 2.0).exp()).collect();
+        let sum: f32 = g.iter().sum();
+        g.iter().map(|x| x / sum).collect()
+    }
+
+    fn blur_model(
+        data_format: DataFormat,
+        padding: PaddingSpec,
+        strides: Option<TVec<usize>>,
+        kernel: Array4<f32>,
+    ) -> (TypedModel, Tensor) {
+        let input_shape = match data_format {
+            DataFormat::NCHW => (1, 3, 11, 9),
+            DataFormat::NHWC => (1, 11, 9, 3),
+        };
+        let input = Array4::from_shape_fn(input_shape, |(_, a, b, c)| {
+            ((a * 7 + b * 5 + c * 3) % 11) as f32 - 5.0
+        });
+        let input: Tensor = input.into();
+        let conv = Conv::new(data_format, KernelFormat::OIHW, None, None, padding, strides, 3);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(arr1(&[0.5f32, -1.0, 2.0]).into());
+        let mut model = TypedModel::default();
+        let fact = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(input.shape()),
+            konst: None,
+        };
+        model.add_source("input", fact).unwrap();
+        let output_shape = op.output_shape();
+        let output = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: output_shape.iter().cloned().collect(),
+            konst: None,
+        };
+        model.chain("conv", op, tvec!(output)).unwrap();
+        (model, input)
+    }
+
+    fn check(data_format: DataFormat, padding: PaddingSpec, strides: Option<TVec<usize>>) {
+        let kernel = Array4::from_shape_fn((3, 1, 5, 5), |(c, _, y, x)| {
+            gaussian(1.0 + c as f32)[y] * gaussian(0.5 + c as f32)[x]
+        });
+        let (model, input) = blur_model(data_format, padding, strides, kernel);
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone())).unwrap();
+        let node = &model.nodes()[1];
+        let op = node.op_as::<ConvUnary>().unwrap();
+        let patch = op.split_rank1_kernel(&model, node).unwrap().unwrap();
+        let mut split = model.clone();
+        patch.apply(&mut split).unwrap();
+        let convs = split.eval_order().unwrap();
+        assert_eq!(convs.iter().filter(|&&n| split.nodes()[n].op_is::<ConvUnary>()).count(), 2);
+        let found = SimplePlan::new(&split).unwrap().run(tvec!(input)).unwrap();
+        assert!(found[0].close_enough(&expected[0], true));
+    }
+
+    #[test]
+    fn split_gaussian_nchw() {
+        check(DataFormat::NCHW, PaddingSpec::SameUpper, None);
+    }
+
+    #[test]
+    fn split_gaussian_nhwc_strided() {
+        check(DataFormat::NHWC, PaddingSpec::Explicit(tvec!(1, 2), tvec!(2, 0)), Some(tvec!(2, 3)));
+    }
+
+    #[test]
+    fn full_rank_kernel_kept() {
+        let kernel = Array4::from_shape_fn((3, 1, 3, 3), |(c, _, y, x)| (c + y * x + x) as f32);
+        let (model, _) = blur_model(DataFormat::NCHW, PaddingSpec::Valid, None, kernel);
+        let node = &model.nodes()[1];
+        let op = node.op_as::<ConvUnary>().unwrap();
+        assert!(op.split_rank1_kernel(&model, node).unwrap().is_none());
+    }
+}
@@ -0,0 +1,186 @@
+use crate::internal::*;
+use num_traits::Zero;
+use once_cell::sync::Lazy;
+use std::any::{Any, TypeId};
+use std::fmt;
+use std::sync::Mutex;
+
+/// Scratch buffers an im2col convolution needs for one evaluation, in bytes.
+///
+/// Obtained from `ConvUnary::scratch_layout` for a given input shape, so a
+/// caller can size its memory before running `ConvUnary::eval_with_scratch`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScratchLayout {
+    /// The packed im2col matrix.
+    pub im2col_len: usize,
+    pub im2col_alignment: usize,
+    /// The tile partial matrix product blocks go through. Empty for
+    /// single output channel convolutions.
+    pub c_panel_len: usize,
+    pub c_panel_alignment: usize,
+}
+
+impl ScratchLayout {
+    /// Bytes covering all buffers, whatever the alignment of the region.
+    pub fn size(&self) -> usize {
+        self.im2col_len + self.im2col_alignment + self.c_panel_len + self.c_panel_alignment
+    }
+}
+
+/// Source of scratch memory for an evaluation.
+pub trait ScratchAllocator {
+    /// Uninitialized memory for `len` bytes, aligned on `alignment`.
+    ///
+    /// It must stay valid, and not be handed out again, for the duration
+    /// of the evaluation it is requested for.
+    fn alloc(&mut self, len: usize, alignment: usize) -> TractResult<*mut u8>;
+}
+
+/// Bump allocator over a buffer allocated once.
+///
+/// Allocations are released all at once by `reset`. Running out of space is
+/// an error: the arena never grows.
+#[derive(Debug)]
+pub struct Arena {
+    buffer: Vec<u8>,
+    used: usize,
+}
+
+impl Arena {
+    pub fn new(capacity: usize) -> Arena {
+        Arena { buffer: vec![0; capacity], used: 0 }
+    }
+
+    pub fn for_layout(layout: &ScratchLayout) -> Arena {
+        Arena::new(layout.size())
+    }
+
+    /// Bytes handed out (alignment padding included) since the last reset.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.used = 0;
+    }
+}
+
+impl ScratchAllocator for Arena {
+    fn alloc(&mut self, len: usize, alignment: usize) -> TractResult<*mut u8> {
+        let base = self.buffer.as_mut_ptr() as usize;
+        let alignment = alignment.max(1);
+        let start = (base + self.used + alignment - 1) / alignment * alignment - base;
+        if start + len > self.buffer.len() {
+            bail!(
+                "Arena of {} bytes exhausted: {} used, {} requested",
+                self.buffer.len(),
+                self.used,
+                len
+            );
+        }
+        self.used = start + len;
+        Ok(unsafe { self.buffer.as_mut_ptr().add(start) })
+    }
+}
+
+/// C panels for the threads a product is split across.
+///
+/// A worker takes a panel for its task and gives it back when done, so once
+/// warm, splitting products allocates nothing. Panels grow to the longest
+/// length asked for: the pool `shared` returns for an element type serves
+/// all the convolutions of the process, whatever their size.
+pub struct PanelPool<T> {
+    panels: Mutex<Vec<Vec<T>>>,
+}
+
+static SHARED_POOLS: Lazy<Mutex<HashMap<TypeId, Arc<Any + Send + Sync>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+impl<T: Copy + Zero + Send + 'static> PanelPool<T> {
+    pub fn new() -> PanelPool<T> {
+        PanelPool { panels: Mutex::new(vec![]) }
+    }
+
+    /// The process wide pool for `T` panels.
+    pub fn shared() -> Arc<PanelPool<T>> {
+        let mut pools = SHARED_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+        let pool = pools
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Arc::new(PanelPool::<T>::new()))
+            .clone();
+        pool.downcast::<PanelPool<T>>().unwrap()
+    }
+
+    /// A panel of at least `len` items, reused if one is idle.
+    pub fn take(&self, len: usize) -> Vec<T> {
+        let mut panel = self.lock().pop().unwrap_or_default();
+        if panel.len() < len {
+            panel.resize(len, T::zero());
+        }
+        panel
+    }
+
+    /// Hand a panel back for other tasks to reuse.
+    pub fn give(&self, panel: Vec<T>) {
+        self.lock().push(panel)
+    }
+
+    /// Panels waiting to be reused.
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<Vec<Vec<T>>> {
+        // a panic in a worker leaves the panels usable: they are scratch
+        self.panels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<T> fmt::Debug for PanelPool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PanelPool")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arena_aligns_and_bails_when_full() {
+        let mut arena = Arena::new(64);
+        let a = arena.alloc(3, 1).unwrap();
+        let b = arena.alloc(8, 16).unwrap();
+        assert_eq!(b as usize % 16, 0);
+        assert!(b as usize >= a as usize + 3);
+        assert!(arena.alloc(64, 1).is_err());
+        arena.reset();
+        assert_eq!(arena.alloc(64, 1).unwrap(), a);
+    }
+
+    #[test]
+    fn panel_pool_reuses_and_grows() {
+        let pool = PanelPool::<f32>::new();
+        let panel = pool.take(8);
+        let ptr = panel.as_ptr();
+        pool.give(panel);
+        assert_eq!(pool.idle(), 1);
+        let panel = pool.take(4);
+        assert_eq!(panel.as_ptr(), ptr);
+        assert_eq!(pool.idle(), 0);
+        pool.give(vec![0.0; 4]);
+        assert_eq!(pool.take(16).len(), 16);
+    }
+
+    #[test]
+    fn shared_pool_per_type() {
+        assert!(Arc::ptr_eq(&PanelPool::<f32>::shared(), &PanelPool::<f32>::shared()));
+        let f64_pool = PanelPool::<f64>::shared();
+        let f32_pool = PanelPool::<f32>::shared();
+        assert_ne!(&*f64_pool as *const _ as usize, &*f32_pool as *const _ as usize);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::error::ConvError;
+use super::{ConvStrategy, ConvUnary};
+use crate::ops::nn::DataFormat;
+
+use std::mem::size_of;
+
+/// Bytes of depthwise output kept between the two convolutions.
+const TILE_BYTES: usize = 32 * 1024;
+
+/// A depthwise convolution followed by a pointwise (1x1) one, as in
+/// MobileNet blocks.
+///
+/// Output points are computed a tile at a time: the depthwise results for
+/// all channels of a tile stay in cache while the pointwise product reads
+/// them, instead of going through the full intermediate feature map.
+#[derive(Debug, Clone, new)]
+pub struct SeparableConv {
+    pub depthwise: ConvUnary,
+    pub pointwise: ConvUnary,
+}
+
+fn channel_values<T: Datum>(bias: &Option<Tensor>) -> TractResult<Option<Vec<T>>> {
+    bias.as_ref().map(|b| Ok(b.to_array_view::<T>()?.iter().cloned().collect())).transpose()
+}
+
+impl SeparableConv {
+    fn eval_t<T>(&self, input: &Tensor) -> TractResult<Tensor>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        let (dw, pw) = (&self.depthwise, &self.pointwise);
+        if input.datum_type() != dw.kernel.datum_type() {
+            bail!(ConvError::DtypeMismatch {
+                expected: dw.kernel.datum_type(),
+                found: input.datum_type()
+            });
+        }
+        let patch = dw.patch(input.shape());
+        patch.check_pad_mode()?;
+        let input_shape: TVec<usize> = input.shape().into();
+        let input_shape = dw.data_format.shape(input_shape);
+        let mid_channels = dw.output_channels();
+        let output_channels = pw.output_channels();
+        let output_shape =
+            dw.data_format.from_n_c_hw(input_shape.n(), output_channels, &*patch.output_shape);
+        // (input channels, multiplier, kernel field)
+        let dw_kernel = dw.kernel_as_group_o_ihw::<T>()?;
+        let mult = mid_channels / dw.group;
+        let dw_bias =
+            channel_values::<T>(&dw.bias)?.unwrap_or_else(|| vec![T::zero(); mid_channels]);
+        let pw_kernel = pw.kernel_as_group_o_ihw::<T>()?;
+        let pw_kernel = pw_kernel.index_axis(Axis(0), 0);
+        let pw_bias = channel_values::<T>(&pw.bias)?;
+
+        let n = patch.output_shape.iter().cloned().product::<usize>();
+        let tile = (TILE_BYTES / (mid_channels * size_of::<T>())).min(n).max(1);
+        let positions: Vec<IxDyn> = ndarray::indices(&*patch.output_shape).into_iter().collect();
+        let spatial_stride = match dw.data_format {
+            DataFormat::NCHW => 1,
+            DataFormat::NHWC => output_channels,
+        };
+
+        // one product for full tiles, one for the last, shorter, tile
+        let mut products = vec![];
+        for len in &[tile, n % tile] {
+            if *len == 0 {
+                continue;
+            }
+            let mm = T::packed_mat_mul(output_channels, mid_channels, *len);
+            let mut packed_a = unsafe {
+                Tensor::uninitialized_aligned::<T>(&[mm.packed_a_len()], mm.packed_a_alignment())?
+            };
+            mm.pack_a(
+                packed_a.as_slice_mut::<T>()?.as_mut_ptr(),
+                pw_kernel.as_ptr(),
+                pw_kernel.strides()[0],
+                pw_kernel.strides()[1],
+            );
+            let b_pack = mm.b_pack();
+            let packed_b =
+                unsafe { Tensor::uninitialized_aligned::<T>(&[b_pack.len()], b_pack.alignment())? };
+            products.push((mm, packed_a, b_pack, packed_b));
+        }
+
+        let input = input.to_array_view::<T>()?;
+        let iptr = input.as_ptr();
+        let mut output = ArrayD::<T>::zeros(&*output_shape.shape);
+        let optr = output.as_mut_ptr();
+        // depthwise results of a tile, one row of `tile` points per channel
+        let mut mid = vec![T::zero(); mid_channels * tile];
+        for i in 0..input_shape.n() {
+            for (t, chunk) in positions.chunks(tile).enumerate() {
+                for (j, coords) in chunk.iter().enumerate() {
+                    let field: TVec<Option<isize>> = patch.at(coords.slice()).collect();
+                    for c in 0..dw.group {
+                        let offset =
+                            (input_shape.n_stride() * i + input_shape.c_stride() * c) as isize;
+                        for m in 0..mult {
+                            let channel = c * mult + m;
+                            let weights = dw_kernel.slice(s![c, m, ..]);
+                            let mut sum = dw_bias[channel];
+                            for (w, pos) in weights.iter().zip(field.iter()) {
+                                if let Some(pos) = pos {
+                                    sum += *w * unsafe { *iptr.offset(offset + pos) };
+                                }
+                            }
+                            mid[channel * tile + j] = sum;
+                        }
+                    }
+                }
+                let (mm, packed_a, b_pack, packed_b) =
+                    &mut products[if chunk.len() == tile { 0 } else { 1 }];
+                b_pack.pack(
+                    packed_b.as_slice_mut::<T>()?.as_mut_ptr(),
+                    mid.as_ptr(),
+                    tile as isize,
+                    1,
+                );
+                unsafe {
+                    mm.mat_mul_prepacked(
+                        packed_a.as_ptr::<T>()?,
+                        packed_b.as_ptr::<T>()?,
+                        optr.offset(
+                            (output_shape.n_stride() * i + t * tile * spatial_stride) as isize,
+                        ),
+                        output_shape.c_stride() as isize,
+                        spatial_stride as isize,
+                    );
+                }
+            }
+        }
+        if let Some(bias) = pw_bias {
+            for (mut channel, b) in
+                output.axis_iter_mut(Axis(output_shape.c_axis())).zip(bias.into_iter())
+            {
+                channel.mapv_inplace(|x| x + b);
+            }
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for SeparableConv {
+    fn name(&self) -> Cow<str> {
+        "SeparableConv".into()
+    }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let mid_shape = self
+            .pointwise
+            .full_input_shape
+            .iter()
+            .map(|d| Ok(d.to_integer()? as usize))
+            .collect::<TractResult<TVec<usize>>>()?;
+        let mid = TypedTensorInfo {
+            shape: mid_shape.into(),
+            datum_type: inputs[0].datum_type,
+            konst: None,
+        };
+        let mut cost = self.depthwise.cost(inputs)?;
+        cost.extend(self.pointwise.cost(&[&mid])?);
+        Ok(cost)
+    }
+}
+
+impl StatelessOp for SeparableConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_floatlike!(Self::eval_t(input.datum_type())(self, &input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for SeparableConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, self.depthwise.full_input_shape.clone())?;
+        s.equals(&outputs[0].shape, self.pointwise.full_output_shape.clone())?;
+        Ok(())
+    }
+}
+
+impl ConvUnary {
+    /// Fuse a depthwise conv and the pointwise (1x1) conv reading its
+    /// output into a `SeparableConv`.
+    pub(super) fn fuse_separable(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let input_fact = model.outlet_fact(node.inputs[0])?;
+        let input_shape = if let Some(shape) = input_fact.shape.as_finite() {
+            self.data_format.shape(shape)
+        } else {
+            return Ok(None);
+        };
+        if self.options.strategy != ConvStrategy::Auto
+            || self.summary.is_some()
+            || self.f64_output
+            || self.token_output
+            || self.group == 1
+            || self.group != input_shape.c()
+            || self.kernel.datum_type() != input_fact.datum_type
+            || !(input_fact.datum_type == DatumType::F32 || input_fact.datum_type == DatumType::F64)
+            || model.output_outlets()?.contains(&OutletId::new(node.id, 0))
+        {
+            return Ok(None);
+        }
+        let succ = if let Some(succ) = model.single_succ(node.id)? {
+            succ
+        } else {
+            return Ok(None);
+        };
+        let pointwise = if let Some(pointwise) = succ.op_as::<ConvUnary>() {
+            pointwise
+        } else {
+            return Ok(None);
+        };
+        let spatial_rank = self.full_input_shape.len() - 2;
+        let kernel_spatial_shape =
+            &pointwise.kernel.shape()[pointwise.kernel_fmt.h_axis()..][..spatial_rank];
+        if pointwise.options.strategy != ConvStrategy::Auto
+            || pointwise.summary.is_some()
+            || pointwise.f64_output
+            || pointwise.token_output
+            || pointwise.group != 1
+            || pointwise.data_format != self.data_format
+            || pointwise.kernel.datum_type() != self.kernel.datum_type()
+            || pointwise.full_input_shape != self.full_output_shape
+            || kernel_spatial_shape.iter().any(|&d| d != 1)
+            || pointwise.strides.iter().any(|&s| s != 1)
+            || !(0..spatial_rank).all(|ax| pointwise.padding.valid_dim(ax))
+        {
+            return Ok(None);
+        }
+        let op = SeparableConv::new(self.clone(), pointwise.clone());
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        let out = patch.chain(&*succ.name, op, tvec!(succ.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(succ.id, 0), OutletId::new(out, 0))?;
+        Ok(Some(patch.with_label("fused with pointwise conv")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+
+    /// Depthwise conv (multiplier 2, stride 2 on h) followed by a `k`x`k`
+    /// conv with biases, strided by `s`.
+    fn block(data_format: DataFormat, k: usize, s: usize) -> InferenceModel {
+        let mut model = InferenceModel::default();
+        let (input_shape, dw_shape, pw_shape, kernel_fmt) = match data_format {
+            DataFormat::NCHW => ([2, 4, 9, 8], [8, 1, 3, 3], [5, 8, k, k], KernelFormat::OIHW),
+            DataFormat::NHWC => ([2, 9, 8, 4], [3, 3, 4, 2], [k, k, 8, 5], KernelFormat::HWIO),
+        };
+        let fact = TensorFact::dt_shape(f32::datum_type(), input_shape.to_vec());
+        let input = model.add_source("input", fact).unwrap();
+        let dw = Conv::new(
+            data_format,
+            kernel_fmt,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            Some(tvec!(2, 1)),
+            4,
+        );
+        let dw = model.add_node_default("depthwise", dw).unwrap();
+        let dw_kernel = ArrayD::from_shape_fn(&dw_shape[..], |ix| {
+            ((ix[0] * 5 + ix[1] * 3 + ix[2] * 2 + ix[3]) % 7) as f32 / 4.0 - 0.75
+        });
+        let dw_kernel = model.add_const("depthwise_kernel", dw_kernel).unwrap();
+        let dw_bias =
+            model.add_const("depthwise_bias", Array1::from_shape_fn(8, |c| c as f32)).unwrap();
+        let pw = Conv::new(
+            data_format,
+            kernel_fmt,
+            None,
+            None,
+            PaddingSpec::Valid,
+            Some(tvec!(s, s)),
+            1,
+        );
+        let pw = model.add_node_default("pointwise", pw).unwrap();
+        let pw_kernel = ArrayD::from_shape_fn(&pw_shape[..], |ix| {
+            ((ix[0] * 3 + ix[1] * 5 + ix[2] + ix[3] * 2) % 5) as f32 / 2.0 - 1.0
+        });
+        let pw_kernel = model.add_const("pointwise_kernel", pw_kernel).unwrap();
+        let pw_bias =
+            model.add_const("pointwise_bias", Array1::from_shape_fn(5, |c| -(c as f32))).unwrap();
+        model.add_edge(OutletId::new(input, 0), InletId::new(dw, 0)).unwrap();
+        model.add_edge(OutletId::new(dw_kernel, 0), InletId::new(dw, 1)).unwrap();
+        model.add_edge(OutletId::new(dw_bias, 0), InletId::new(dw, 2)).unwrap();
+        model.add_edge(OutletId::new(dw, 0), InletId::new(pw, 0)).unwrap();
+        model.add_edge(OutletId::new(pw_kernel, 0), InletId::new(pw, 1)).unwrap();
+        model.add_edge(OutletId::new(pw_bias, 0), InletId::new(pw, 2)).unwrap();
+        model.set_output_outlets(&[OutletId::new(pw, 0)]).unwrap();
+        model
+    }
+
+    /// Optimize the block, check whether it was fused, and compare it to
+    /// the unfused pair.
+    fn check(data_format: DataFormat, k: usize, s: usize, fused: bool) {
+        let model = block(data_format, k, s);
+        let reference = SimplePlan::new(model.clone().into_typed().unwrap()).unwrap();
+        let optimized = model.into_typed().unwrap().into_optimized().unwrap();
+        assert_eq!(optimized.nodes().iter().any(|n| n.op_is::<SeparableConv>()), fused);
+        let shape = match data_format {
+            DataFormat::NCHW => (2, 4, 9, 8),
+            DataFormat::NHWC => (2, 9, 8, 4),
+        };
+        let input = Array4::from_shape_fn(shape, |(n, a, b, c)| {
+            ((n * 31 + a * 7 + b * 3 + c) % 13) as f32 / 3.0 - 2.0
+        });
+        let expected = reference.run(tvec!(input.clone().into())).unwrap();
+        let found = SimplePlan::new(optimized).unwrap().run(tvec!(input.into())).unwrap();
+        assert_close!(*found[0], *expected[0]);
+    }
+
+    #[test]
+    fn fused_nchw() {
+        check(DataFormat::NCHW, 1, 1, true);
+    }
+
+    #[test]
+    fn fused_nhwc() {
+        check(DataFormat::NHWC, 1, 1, true);
+    }
+
+    #[test]
+    fn not_pointwise_is_left_alone() {
+        check(DataFormat::NCHW, 3, 1, false);
+        check(DataFormat::NHWC, 1, 2, false);
+    }
+
+    #[test]
+    fn intermediate_output_is_left_alone() {
+        let mut model = block(DataFormat::NCHW, 1, 1).into_typed().unwrap();
+        let depthwise = model.node_by_name("depthwise").unwrap().id;
+        let pointwise = model.node_by_name("pointwise").unwrap().id;
+        model
+            .set_output_outlets(&[OutletId::new(pointwise, 0), OutletId::new(depthwise, 0)])
+            .unwrap();
+        let optimized = model.into_optimized().unwrap();
+        assert!(!optimized.nodes().iter().any(|n| n.op_is::<SeparableConv>()));
+    }
+}
@@ -0,0 +1,218 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::error::ConvError;
+use super::ConvUnary;
+use crate::ops::cnn::PatchPadMode;
+use crate::ops::nn::DepthToSpace;
+
+use std::mem::size_of;
+
+/// Bytes of conv output computed before they are shuffled.
+const BAND_BYTES: usize = 256 * 1024;
+
+/// A convolution followed by a pixel shuffle (`DepthToSpace`), as in
+/// sub-pixel super-resolution networks.
+///
+/// The conv is computed in bands of output rows, each band shuffled into
+/// the upscaled output as it comes: the `C * r * r` channels output of the
+/// conv is never held whole.
+#[derive(Debug, Clone, new)]
+pub struct PixelShuffleConv {
+    pub conv: ConvUnary,
+    pub shuffle: DepthToSpace,
+}
+
+impl PixelShuffleConv {
+    /// Output rows of the conv computed at once, for an input of this shape.
+    fn band_rows<T>(&self, input_full_shape: &[usize]) -> usize {
+        let output_shape = self.conv.patch(input_full_shape).output_shape;
+        let n = self.conv.data_format.shape(input_full_shape).n();
+        let row = n * self.conv.output_channels() * output_shape[1..].iter().product::<usize>();
+        (BAND_BYTES / (row * size_of::<T>()).max(1)).max(1).min(output_shape[0])
+    }
+
+    fn eval_t<T>(&self, input: &Tensor, band: usize) -> TractResult<Tensor>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        if input.datum_type() != self.conv.kernel.datum_type() {
+            bail!(ConvError::DtypeMismatch {
+                expected: self.conv.kernel.datum_type(),
+                found: input.datum_type()
+            });
+        }
+        let patch = self.conv.patch(input.shape());
+        let input_shape = self.conv.data_format.shape(input.shape());
+        let conv_output_shape = self.conv.data_format.from_n_c_hw(
+            input_shape.n(),
+            self.conv.output_channels(),
+            &*patch.output_shape,
+        );
+        let h_axis = input_shape.h_axis();
+        let rows = patch.output_shape[0];
+        let r = self.shuffle.block_size;
+        let mut output =
+            ArrayD::<T>::zeros(&*self.shuffle.output_shape(&conv_output_shape.shape[..])?);
+        let view = input.to_array_view::<T>()?;
+        for start in (0..rows).step_by(band) {
+            let end = rows.min(start + band);
+            let (input_rows, op) = self.conv.band(input.shape(), start..end);
+            let slice = view.slice_axis(Axis(h_axis), input_rows.into()).to_owned();
+            let band_output = op.eval(tvec!(slice.into_arc_tensor()))?.remove(0);
+            let mut dst = output.view_mut();
+            dst.slice_axis_inplace(Axis(h_axis), (start * r..end * r).into());
+            self.shuffle.shuffle_into(band_output.to_array_view::<T>()?, dst)?;
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for PixelShuffleConv {
+    fn name(&self) -> Cow<str> {
+        "PixelShuffleConv".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        self.shuffle.info()
+    }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        self.conv.cost(inputs)
+    }
+}
+
+impl StatelessOp for PixelShuffleConv {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let dt = input.datum_type();
+        let band = dispatch_floatlike!(Self::band_rows(dt)(self, input.shape()));
+        let output = dispatch_floatlike!(Self::eval_t(dt)(self, &input, band))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for PixelShuffleConv {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, self.conv.full_input_shape.clone())?;
+        let output_shape = self.shuffle.output_shape(&self.conv.full_output_shape[..])?;
+        s.equals(&outputs[0].shape, ShapeFact::from(output_shape))?;
+        Ok(())
+    }
+}
+
+impl ConvUnary {
+    /// Fuse a pixel shuffle reading our output into a `PixelShuffleConv`.
+    pub(super) fn fuse_pixel_shuffle(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::nn::DepthToSpace;
+        // the conv is evaluated in bands, shuffled as they come
+        if self.summary.is_some()
+            || self.token_output
+            || self.f64_output
+            || self.pad_mode != PatchPadMode::Zero
+            || !self.independent_shape.is_empty()
+            || self.full_input_shape.len() != 4
+            || model.output_outlets()?.contains(&OutletId::new(node.id, 0))
+        {
+            return Ok(None);
+        }
+        let succ = match model.single_succ(node.id)? {
+            Some(succ) => succ,
+            None => return Ok(None),
+        };
+        let shuffle = match succ.op_as::<DepthToSpace>() {
+            Some(shuffle) if shuffle.data_format == self.data_format => shuffle.clone(),
+            _ => return Ok(None),
+        };
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        let op = PixelShuffleConv::new(self.clone(), shuffle);
+        let out = patch.chain(&*node.name, op, tvec!(succ.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(succ.id, 0), OutletId::new(out, 0))?;
+        Ok(Some(patch.with_label("fused pixel shuffle")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use crate::ops::nn::{DataFormat, DepthToSpaceMode};
+
+    fn setup(format: DataFormat, mode: DepthToSpaceMode) -> (Arc<Tensor>, PixelShuffleConv) {
+        let shape: &[usize] = match format {
+            DataFormat::NCHW => &[1, 2, 7, 5],
+            DataFormat::NHWC => &[1, 7, 5, 2],
+        };
+        let input = ArrayD::from_shape_fn(shape, |ix| {
+            ix.slice().iter().enumerate().map(|(a, &i)| i * (a + 3)).sum::<usize>() as f32 % 7.0
+                - 3.0
+        })
+        .into_arc_tensor();
+        // two output channels upscaled by 2
+        let kernel = Array4::from_shape_fn((8, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) % 11) as f32 / 4.0 - 1.25
+        });
+        let conv =
+            Conv::new(format, KernelFormat::OIHW, None, None, PaddingSpec::SameUpper, None, 1);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = conv.to_unary(&facts).unwrap().unwrap();
+        conv.bias = Some(Array1::from_shape_fn(8, |c| c as f32 / 2.0 - 2.0).into_tensor());
+        (input, PixelShuffleConv::new(conv, DepthToSpace::new(2, mode, format)))
+    }
+
+    fn check(format: DataFormat, mode: DepthToSpaceMode) {
+        let (input, op) = setup(format, mode);
+        let conv_output = op.conv.eval(tvec!(input.clone())).unwrap();
+        let expected = op.shuffle.eval(conv_output).unwrap().remove(0);
+        for band in 1..8 {
+            let found = op.eval_t::<f32>(&input, band).unwrap();
+            assert!(found.close_enough(&expected, true), "bands of {}", band);
+        }
+    }
+
+    #[test]
+    fn pixel_shuffle_nchw() {
+        check(DataFormat::NCHW, DepthToSpaceMode::CRD);
+    }
+
+    #[test]
+    fn depth_to_space_nhwc() {
+        check(DataFormat::NHWC, DepthToSpaceMode::DCR);
+    }
+
+    #[test]
+    fn optimized_model_fuses_shuffle() {
+        let (input, op) = setup(DataFormat::NCHW, DepthToSpaceMode::CRD);
+        let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 2, 7, 5))).unwrap();
+        model.chain_default("conv", op.conv.clone()).unwrap();
+        model.chain_default("shuffle", op.shuffle.clone()).unwrap();
+        let model = model.into_typed().unwrap().into_optimized().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<PixelShuffleConv>()));
+        assert!(!model.nodes().iter().any(|n| n.op_is::<DepthToSpace>()));
+        let found =
+            SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into_tensor())).unwrap();
+        assert!(found[0].close_enough(&expected, true));
+        assert_eq!(found[0].shape(), &[1, 2, 14, 10]);
+    }
+}
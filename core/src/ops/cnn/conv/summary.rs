@@ -0,0 +1,280 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+use ndarray::Zip;
+
+use super::ConvUnary;
+use crate::ops::nn::{DataFormat, DataShape};
+
+/// Reduction a convolution can compute while writing back its output,
+/// making a following global pool, or the max pass of a following softmax,
+/// free.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChannelSummary {
+    /// Average of each channel.
+    Avg,
+    /// Max of each channel.
+    Max,
+    /// Max over the channels at each position: the shift a softmax over
+    /// the channels subtracts, see `ShiftedSoftmax`.
+    PositionMax,
+}
+
+impl ChannelSummary {
+    /// Shape of the summary output: the conv output shape with all spatial
+    /// dimensions collapsed to 1, as a global pool would produce, or the
+    /// channel one for `PositionMax`.
+    pub fn output_shape<D: DimLike>(&self, fmt: DataFormat, output_shape: &[D]) -> TVec<D> {
+        let mut shape: TVec<D> = output_shape.into();
+        let output_shape = fmt.shape(output_shape);
+        if *self == ChannelSummary::PositionMax {
+            shape[output_shape.c_axis()] = D::one();
+        } else {
+            for ax in output_shape.hw_axes() {
+                shape[ax] = D::one();
+            }
+        }
+        shape
+    }
+}
+
+/// Add bias and compute the optional channel summary on a conv output, in a
+/// single pass.
+pub(super) fn writeback<T>(
+    output: &mut ArrayD<T>,
+    output_shape: &DataShape,
+    bias: Option<&ArrayD<T>>,
+    summary: Option<ChannelSummary>,
+) -> TractResult<Option<ArrayD<T>>>
+where
+    T: Datum + ndarray::LinalgScalar + std::ops::AddAssign + num_traits::Float,
+{
+    let summary = if let Some(summary) = summary {
+        summary
+    } else {
+        if let Some(bias) = bias {
+            *output += bias;
+        }
+        return Ok(None);
+    };
+    let bias: Option<Vec<T>> = bias.map(|b| b.iter().cloned().collect());
+    let mut result =
+        ArrayD::<T>::zeros(&*summary.output_shape(output_shape.fmt, &output_shape.shape));
+    if summary == ChannelSummary::PositionMax {
+        result.fill(T::neg_infinity());
+        let c_axis = Axis(output_shape.c_axis());
+        for c in 0..output_shape.c() {
+            let b = bias.as_ref().map(|b| b[c]).unwrap_or(T::zero());
+            let mut max = result.index_axis_mut(c_axis, 0);
+            Zip::from(&mut max).and(output.index_axis_mut(c_axis, c)).apply(|max, x| {
+                *x = *x + b;
+                *max = max.max(*x);
+            });
+        }
+        return Ok(Some(result));
+    }
+    let divisor =
+        <T as num_traits::NumCast>::from(output_shape.hw_dims().iter().product::<usize>()).unwrap();
+    for n in 0..output_shape.n() {
+        let mut output = output.index_axis_mut(Axis(output_shape.n_axis()), n);
+        // n axis is gone, c axis moves accordingly
+        let c_axis = output_shape.c_axis() - 1;
+        for c in 0..output_shape.c() {
+            let b = bias.as_ref().map(|b| b[c]).unwrap_or(T::zero());
+            let mut acc = match summary {
+                ChannelSummary::Avg => T::zero(),
+                _ => T::neg_infinity(),
+            };
+            for x in output.index_axis_mut(Axis(c_axis), c).iter_mut() {
+                *x = *x + b;
+                acc = match summary {
+                    ChannelSummary::Avg => acc + *x,
+                    _ => acc.max(*x),
+                };
+            }
+            if summary == ChannelSummary::Avg {
+                acc = acc / divisor;
+            }
+            let mut coords = tvec!(0; output_shape.rank());
+            coords[output_shape.n_axis()] = n;
+            coords[output_shape.c_axis()] = c;
+            result[&*coords] = acc;
+        }
+    }
+    Ok(Some(result))
+}
+
+impl ConvUnary {
+    /// Fuse a global pool reading our output into the conv writeback.
+    pub(super) fn fuse_global_pool(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::nn::{GlobalAvgPool, GlobalMaxPool};
+        if self.summary.is_some() || self.token_output || self.data_format != DataFormat::NCHW {
+            return Ok(None);
+        }
+        for succ in &node.outputs[0].successors {
+            let pool = model.node(succ.node);
+            let summary = if pool.op_is::<GlobalAvgPool>() {
+                ChannelSummary::Avg
+            } else if pool.op_is::<GlobalMaxPool>() {
+                ChannelSummary::Max
+            } else {
+                continue;
+            };
+            let mut op = self.clone();
+            op.summary = Some(summary);
+            let mut patch = TypedModelPatch::default();
+            let tap = patch.tap_model(&model, node.inputs[0])?;
+            let id = patch.add_node(
+                &*node.name,
+                op,
+                tvec!(node.outputs[0].fact.clone(), pool.outputs[0].fact.clone()),
+            )?;
+            patch.add_edge(tap, InletId::new(id, 0))?;
+            patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(id, 0))?;
+            patch.shunt_outside(OutletId::new(pool.id, 0), OutletId::new(id, 1))?;
+            return Ok(Some(patch.with_label("fused global pool")));
+        }
+        Ok(None)
+    }
+
+    /// Write back the max over the channels a following softmax over them
+    /// shifts its input by, sparing the softmax its max pass.
+    pub(super) fn fuse_softmax_max(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::nn::{LayerSoftmax, ShiftedSoftmax};
+        if self.summary.is_some() || self.token_output || self.f64_output {
+            return Ok(None);
+        }
+        let softmax = match model.single_succ(node.id)? {
+            Some(succ) if succ.op_is::<LayerSoftmax>() => succ,
+            _ => return Ok(None),
+        };
+        let rank = self.full_output_shape.len();
+        let c_axis = self.data_format.shape(&self.full_output_shape).c_axis();
+        if softmax.op_as::<LayerSoftmax>().unwrap().normalized_axis(rank) != Some(c_axis)
+            || model.output_outlets()?.contains(&OutletId::new(node.id, 0))
+        {
+            return Ok(None);
+        }
+        let summary = ChannelSummary::PositionMax;
+        let mut op = self.clone();
+        op.summary = Some(summary);
+        let max_fact = TypedTensorInfo {
+            datum_type: node.outputs[0].fact.datum_type,
+            shape: summary
+                .output_shape(self.data_format, &self.full_output_shape)
+                .iter()
+                .cloned()
+                .collect(),
+            konst: None,
+        };
+        let mut patch = TypedModelPatch::default();
+        let tap = patch.tap_model(&model, node.inputs[0])?;
+        let conv =
+            patch.add_node(&*node.name, op, tvec!(node.outputs[0].fact.clone(), max_fact))?;
+        patch.add_edge(tap, InletId::new(conv, 0))?;
+        let shifted = patch.add_node(
+            &*softmax.name,
+            ShiftedSoftmax::new(c_axis),
+            tvec!(softmax.outputs[0].fact.clone()),
+        )?;
+        patch.add_edge(OutletId::new(conv, 0), InletId::new(shifted, 0))?;
+        patch.add_edge(OutletId::new(conv, 1), InletId::new(shifted, 1))?;
+        patch.shunt_outside(OutletId::new(softmax.id, 0), OutletId::new(shifted, 0))?;
+        Ok(Some(patch.with_label("fused softmax max")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    #[test]
+    fn avg_and_max_nchw() {
+        let shape = DataFormat::NCHW.shape(tvec!(1, 2, 1, 3));
+        let bias = arr1(&[1.0f32, -1.0]).into_shape((1, 2, 1, 1)).unwrap().into_dyn();
+        let mut output =
+            arr1(&[0.0f32, 1.0, 2.0, 3.0, 4.0, 5.0]).into_shape((1, 2, 1, 3)).unwrap().into_dyn();
+        let mut output2 = output.clone();
+        let avg = writeback(&mut output, &shape, Some(&bias), Some(ChannelSummary::Avg)).unwrap();
+        let max = writeback(&mut output2, &shape, Some(&bias), Some(ChannelSummary::Max)).unwrap();
+        assert_eq!(output, output2);
+        assert_eq!(avg.unwrap(), arr1(&[2.0f32, 3.0]).into_shape((1, 2, 1, 1)).unwrap().into_dyn());
+        assert_eq!(max.unwrap(), arr1(&[3.0f32, 4.0]).into_shape((1, 2, 1, 1)).unwrap().into_dyn());
+    }
+
+    #[test]
+    fn max_nhwc() {
+        let shape = DataFormat::NHWC.shape(tvec!(1, 3, 2));
+        let mut output =
+            arr1(&[0.0f32, 5.0, 2.0, 3.0, 4.0, 1.0]).into_shape((1, 3, 2)).unwrap().into_dyn();
+        let max = writeback(&mut output, &shape, None, Some(ChannelSummary::Max)).unwrap();
+        assert_eq!(max.unwrap(), arr1(&[4.0f32, 5.0]).into_shape((1, 1, 2)).unwrap().into_dyn());
+    }
+
+    #[test]
+    fn position_max() {
+        let nchw = DataFormat::NCHW.shape(tvec!(1, 3, 1, 2));
+        let bias = arr1(&[1.0f32, -1.0, 0.0]).into_shape((1, 3, 1, 1)).unwrap().into_dyn();
+        let mut output =
+            arr1(&[0.0f32, 5.0, 2.0, 3.0, 1.0, 1.0]).into_shape((1, 3, 1, 2)).unwrap().into_dyn();
+        let max = writeback(&mut output, &nchw, Some(&bias), Some(ChannelSummary::PositionMax));
+        assert_eq!(
+            max.unwrap().unwrap(),
+            arr1(&[1.0f32, 6.0]).into_shape((1, 1, 1, 2)).unwrap().into_dyn()
+        );
+        let nhwc = DataFormat::NHWC.shape(tvec!(1, 2, 3));
+        let mut output =
+            arr1(&[0.0f32, 5.0, 2.0, 3.0, 1.0, 1.0]).into_shape((1, 2, 3)).unwrap().into_dyn();
+        let max = writeback(&mut output, &nhwc, None, Some(ChannelSummary::PositionMax));
+        assert_eq!(
+            max.unwrap().unwrap(),
+            arr1(&[5.0f32, 3.0]).into_shape((1, 2, 1)).unwrap().into_dyn()
+        );
+    }
+
+    /// A classifier tail: conv to 10 classes then softmax over them.
+    fn softmax_tail(data_format: DataFormat, softmax: crate::ops::nn::LayerSoftmax) {
+        use crate::ops::nn::ShiftedSoftmax;
+        let shape = data_format.from_n_c_hw(2, 3, [4usize, 5]).shape;
+        let input = ArrayD::from_shape_fn(&*shape, |ix| {
+            ix.slice().iter().fold(0, |acc, x| acc * 7 + x) as f32 % 11.0 - 5.0
+        });
+        let kernel = Array4::from_shape_fn((10, 3, 3, 3), |(o, i, y, x)| {
+            ((o * 27 + i * 9 + y * 3 + x) % 13) as f32 / 3.0 - 2.0
+        });
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = Conv::default();
+        conv.data_format = data_format;
+        conv.padding = PaddingSpec::SameUpper;
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(Array1::from_shape_fn(10, |c| c as f32 - 4.0).into());
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, shape)).unwrap();
+        model.chain_default("conv", op).unwrap();
+        model.chain_default("softmax", softmax).unwrap();
+        let model = model.into_typed().unwrap();
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into())).unwrap();
+        let model = model.into_optimized().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<ShiftedSoftmax>()));
+        let found = SimplePlan::new(&model).unwrap().run(tvec!(input.into())).unwrap();
+        assert!(found[0].close_enough(&expected[0], true));
+    }
+
+    #[test]
+    fn softmax_max_fused_in_writeback() {
+        use crate::ops::nn::LayerSoftmax;
+        softmax_tail(DataFormat::NCHW, LayerSoftmax::single_axis(1));
+        softmax_tail(DataFormat::NHWC, LayerSoftmax::single_axis(-1));
+        // flattening from the last axis on
+        softmax_tail(DataFormat::NHWC, LayerSoftmax::new(3));
+    }
+}
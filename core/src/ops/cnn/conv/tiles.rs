@@ -0,0 +1,176 @@
+use crate::internal::*;
+
+use super::ConvUnary;
+use crate::ops::cnn::{PaddingSpec, PatchPadMode};
+use std::ops::Range;
+
+/// A spatial tile of a conv output, with the input region computing it: the
+/// tile itself plus a halo of `(kernel - 1) * dilation` on each spatial
+/// axis, clipped to the input.
+#[derive(Debug, Clone)]
+pub struct ConvTile {
+    /// Input region read by the tile, one range per spatial axis.
+    pub input: TVec<Range<usize>>,
+    /// Output region of the tile, one range per spatial axis.
+    pub output: TVec<Range<usize>>,
+    /// The conv computing the output region from the input region alone,
+    /// padded as the full conv is at the borders of the input.
+    pub conv: ConvUnary,
+}
+
+impl ConvUnary {
+    /// Split the spatial output of the conv, for an input of this shape, in
+    /// a grid of `tiles[axis]` tiles along each spatial axis, and find out
+    /// the input region each tile reads.
+    ///
+    /// The tiles can be computed apart, on different devices, each from its
+    /// input region only. Their outputs cover the output of the conv without
+    /// overlap, in row-major order of the grid.
+    pub fn spatial_tiles(
+        &self,
+        input_full_shape: &[usize],
+        tiles: &[usize],
+    ) -> TractResult<Vec<ConvTile>> {
+        let output_shape = self.patch(input_full_shape).output_shape;
+        if tiles.len() != output_shape.len() {
+            bail!("Expected tile counts for {} spatial axes, got {:?}", output_shape.len(), tiles);
+        }
+        if tiles.iter().zip(output_shape.iter()).any(|(&t, &d)| t == 0 || t > d) {
+            bail!("Can not split an output of shape {:?} in {:?} tiles", output_shape, tiles);
+        }
+        // a summary reduces over the whole output, other pad modes sample
+        // the input outside of the tile, tokens are not written in tiles
+        if self.summary.is_some() || self.pad_mode != PatchPadMode::Zero || self.token_output {
+            bail!("Only zero padded convs without summary nor tokens can be tiled");
+        }
+        if !self.independent_shape.is_empty() {
+            bail!("Convs with independent axes can not be tiled, fold them in the batch first");
+        }
+        let mut regions: Vec<TVec<Range<usize>>> = vec![tvec!()];
+        for (&dim, &count) in output_shape.iter().zip(tiles.iter()) {
+            let splits = (0..count).map(|i| (i * dim / count)..((i + 1) * dim / count));
+            regions = regions
+                .iter()
+                .flat_map(|region| {
+                    splits.clone().map(move |split| {
+                        let mut region = region.clone();
+                        region.push(split);
+                        region
+                    })
+                })
+                .collect();
+        }
+        Ok(regions
+            .into_iter()
+            .map(|output| {
+                let (input, conv) = self.region(input_full_shape, &output);
+                ConvTile { input, output, conv }
+            })
+            .collect())
+    }
+
+    /// The input region the `output` region reads, one range per spatial
+    /// axis, and the conv computing it from this region alone, padded as the
+    /// full conv is.
+    pub(super) fn region(
+        &self,
+        input_full_shape: &[usize],
+        output: &[Range<usize>],
+    ) -> (TVec<Range<usize>>, ConvUnary) {
+        let patch = self.patch(input_full_shape);
+        let h_axis = self.data_format.shape(input_full_shape).h_axis();
+        let kernel_shape = &self.kernel.shape()[self.kernel_fmt.h_axis()..];
+        let mut before = patch.pad_before.clone();
+        let mut after = patch.pad_after.clone();
+        let mut op = self.clone();
+        let mut input = tvec!();
+        for (ax, rows) in output.iter().enumerate() {
+            let dim = input_full_shape[h_axis + ax];
+            let (pad, stride, dilation) =
+                (patch.pad_before[ax], self.strides[ax], self.dilations[ax]);
+            // positions in the padded input
+            let start = rows.start * stride;
+            let end = (rows.end - 1) * stride + (kernel_shape[ax] - 1) * dilation + 1;
+            let input_start = start.saturating_sub(pad).min(dim);
+            let input_end = end.saturating_sub(pad).min(dim).max(input_start);
+            before[ax] = input_start + pad - start;
+            after[ax] = end - input_end - pad;
+            op.full_input_shape[h_axis + ax] = (input_end - input_start).to_dim();
+            op.full_output_shape[h_axis + ax] = rows.len().to_dim();
+            input.push(input_start..input_end);
+        }
+        op.padding = PaddingSpec::Explicit(before, after);
+        op.options.max_scratch_bytes = None;
+        op.golden = None;
+        (input, op)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::Conv;
+    use crate::ops::nn::DataFormat;
+    use ndarray::*;
+
+    fn check(format: DataFormat, tiles: &[usize]) {
+        let shape: &[usize] =
+            if format == DataFormat::NHWC { &[1, 11, 9, 2] } else { &[1, 2, 11, 9] };
+        let input = ArrayD::from_shape_fn(shape, |ix| {
+            ix.slice().iter().enumerate().map(|(a, &i)| i * (2 * a + 1)).sum::<usize>() as f32 % 5.0
+        });
+        let kernel = Array4::from_shape_fn((3, 2, 3, 2), |(o, c, y, x)| {
+            ((o * 12 + c * 6 + y * 2 + x) % 7) as f32 / 2.0 - 1.5
+        });
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = Conv::default();
+        conv.data_format = format;
+        conv.padding = PaddingSpec::SameUpper;
+        conv.strides = Some(tvec!(2, 1));
+        conv.dilations = Some(tvec!(1, 2));
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let expected = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap().remove(0);
+        let expected = expected.to_array_view::<f32>().unwrap();
+
+        let h_axis = op.data_format.shape(shape).h_axis();
+        let mut found = ArrayD::<f32>::zeros(expected.shape());
+        let mut covered = 0;
+        let tiles = op.spatial_tiles(shape, tiles).unwrap();
+        for tile in &tiles {
+            let mut slice = input.view();
+            let mut dst = found.view_mut();
+            for ax in 0..2 {
+                slice.slice_axis_inplace(Axis(h_axis + ax), tile.input[ax].clone().into());
+                dst.slice_axis_inplace(Axis(h_axis + ax), tile.output[ax].clone().into());
+            }
+            // the halo of the second axis, dilated
+            if tile.input[1].start > 0 && tile.input[1].end < shape[h_axis + 1] {
+                assert_eq!(tile.input[1].len(), tile.output[1].len() + 2);
+            }
+            let output = tile.conv.eval(tvec!(slice.to_owned().into_arc_tensor())).unwrap();
+            dst.assign(&output[0].to_array_view::<f32>().unwrap());
+            covered += dst.len();
+        }
+        assert_eq!(covered, expected.len());
+        assert_eq!(found.view(), expected);
+    }
+
+    #[test]
+    fn tiles_stitch_to_output() {
+        check(DataFormat::NCHW, &[2, 3]);
+        check(DataFormat::NHWC, &[3, 2]);
+        check(DataFormat::NCHW, &[1, 1]);
+    }
+
+    #[test]
+    fn too_many_tiles() {
+        let input = Tensor::from(ArrayD::<f32>::zeros(&[1, 1, 4, 4][..]));
+        let kernel = ArrayD::<f32>::zeros(&[1, 1, 3, 3][..]);
+        let facts = conv_facts(input, kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        assert!(op.spatial_tiles(&[1, 1, 4, 4], &[3, 1]).is_err());
+        assert!(op.spatial_tiles(&[1, 1, 4, 4], &[2]).is_err());
+        assert_eq!(op.spatial_tiles(&[1, 1, 4, 4], &[2, 2]).unwrap().len(), 4);
+    }
+}
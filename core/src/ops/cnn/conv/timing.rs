@@ -0,0 +1,148 @@
+//! Where im2col convolutions spend their time, behind the `conv_timing`
+//! feature.
+//!
+//! A conv and the im2col and product ops it is lowered to share one
+//! `PhaseTimer`, so the phases add up per conv node whichever way it runs.
+//! Without the feature, timers measure nothing and cost a branch.
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The distinct regions of an im2col convolution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConvPhase {
+    /// Gathering and packing the input patches.
+    Im2col,
+    /// The matrix products.
+    Gemm,
+    /// Bias, channel summary and output layout.
+    Writeback,
+}
+
+/// Time spent in each phase, summed over `evals` evaluations.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhaseTimes {
+    pub im2col: Duration,
+    pub gemm: Duration,
+    pub writeback: Duration,
+    pub evals: u64,
+}
+
+impl PhaseTimes {
+    pub fn total(&self) -> Duration {
+        self.im2col + self.gemm + self.writeback
+    }
+
+    fn add(&mut self, phase: ConvPhase, duration: Duration) {
+        match phase {
+            ConvPhase::Im2col => self.im2col += duration,
+            ConvPhase::Gemm => self.gemm += duration,
+            ConvPhase::Writeback => self.writeback += duration,
+        }
+    }
+}
+
+impl fmt::Display for PhaseTimes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().as_secs_f64().max(std::f64::MIN_POSITIVE);
+        let phases = [("im2col", self.im2col), ("gemm", self.gemm), ("writeback", self.writeback)];
+        for (ix, (name, duration)) in phases.iter().enumerate() {
+            if ix > 0 {
+                write!(f, ", ")?;
+            }
+            let secs = duration.as_secs_f64();
+            write!(f, "{} {:.3} ms ({:.0}%)", name, secs * 1e3, secs / total * 100.0)?;
+        }
+        write!(f, " over {} evals", self.evals)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Timings {
+    label: Option<String>,
+    times: PhaseTimes,
+}
+
+/// Phase times of one conv node, shared by the ops it is lowered to.
+#[derive(Clone, Default)]
+pub struct PhaseTimer(Arc<Mutex<Timings>>);
+
+impl fmt::Debug for PhaseTimer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PhaseTimer")
+    }
+}
+
+impl PhaseTimer {
+    /// Run `f`, adding its duration to `phase`.
+    pub fn time<R>(&self, phase: ConvPhase, f: impl FnOnce() -> R) -> R {
+        if cfg!(feature = "conv_timing") {
+            let start = Instant::now();
+            let result = f();
+            self.0.lock().unwrap().times.add(phase, start.elapsed());
+            result
+        } else {
+            f()
+        }
+    }
+
+    /// Count an evaluation, and log the breakdown so far.
+    pub fn eval_done(&self) {
+        if cfg!(feature = "conv_timing") {
+            let mut timings = self.0.lock().unwrap();
+            timings.times.evals += 1;
+            info!("{}: {}", timings.label.as_ref().map(|s| &**s).unwrap_or("conv"), timings.times);
+        }
+    }
+
+    /// Name the node the breakdown is logged for.
+    pub fn set_label(&self, label: impl Into<String>) {
+        self.0.lock().unwrap().label = Some(label.into());
+    }
+
+    /// Whether both timers add to the same times.
+    pub fn shares_times_with(&self, other: &PhaseTimer) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn times(&self) -> PhaseTimes {
+        self.0.lock().unwrap().times
+    }
+
+    pub fn reset(&self) {
+        self.0.lock().unwrap().times = PhaseTimes::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn phases_add_up() {
+        let mut times = PhaseTimes::default();
+        times.add(ConvPhase::Gemm, Duration::from_millis(3));
+        times.add(ConvPhase::Im2col, Duration::from_millis(1));
+        times.add(ConvPhase::Gemm, Duration::from_millis(3));
+        times.evals = 2;
+        assert_eq!(times.total(), Duration::from_millis(7));
+        assert_eq!(
+            times.to_string(),
+            "im2col 1.000 ms (14%), gemm 6.000 ms (86%), writeback 0.000 ms (0%) over 2 evals"
+        );
+    }
+
+    #[test]
+    fn clones_share_times() {
+        let timer = PhaseTimer::default();
+        let lowered = timer.clone();
+        assert!(lowered.shares_times_with(&timer));
+        assert!(!PhaseTimer::default().shares_times_with(&timer));
+        assert_eq!(lowered.time(ConvPhase::Writeback, || 42), 42);
+        lowered.eval_done();
+        let expected = if cfg!(feature = "conv_timing") { 1 } else { 0 };
+        assert_eq!(timer.times().evals, expected);
+        timer.reset();
+        assert_eq!(lowered.times(), PhaseTimes::default());
+    }
+}
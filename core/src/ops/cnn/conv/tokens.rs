@@ -0,0 +1,144 @@
+use crate::internal::*;
+
+use super::ConvUnary;
+use crate::ops::nn::DataFormat;
+
+impl ConvUnary {
+    /// Write tokens instead of flattening the output to them afterwards: a
+    /// channels-last output reshaped to (N, H * W, C), or a channels-first
+    /// one reshaped to (N, C, H * W) then transposed.
+    pub(super) fn fuse_tokens(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::array::{PermuteAxes, Reshape};
+        if self.summary.is_some() || self.token_output {
+            return Ok(None);
+        }
+        // the reshape also takes its shape as input: no single_succ
+        let reshape = match &*node.outputs[0].successors {
+            &[inlet] if node.outputs.len() == 1 && inlet.slot == 0 => model.node(inlet.node),
+            _ => return Ok(None),
+        };
+        if !reshape.op_is::<Reshape>() {
+            return Ok(None);
+        }
+        let shape = self.data_format.shape(&self.full_output_shape);
+        let hw = shape.hw_dims().iter().cloned().product::<TDim>();
+        let reshaped: TVec<TDim> = reshape.outputs[0].fact.shape.iter().collect();
+        let last = match self.data_format {
+            DataFormat::NHWC if reshaped == tvec!(shape.n(), hw, shape.c()) => reshape,
+            DataFormat::NCHW if reshaped == tvec!(shape.n(), shape.c(), hw) => {
+                match model.single_succ(reshape.id)? {
+                    Some(succ)
+                        if succ
+                            .op_as::<PermuteAxes>()
+                            .map(|p| p.axes == Some(vec![0, 2, 1]))
+                            .unwrap_or(false) =>
+                    {
+                        succ
+                    }
+                    _ => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        let mut op = self.clone();
+        op.token_output = true;
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        let out = patch.chain(&*node.name, op, tvec!(last.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(last.id, 0), OutletId::new(out, 0))?;
+        Ok(Some(patch.with_label("fused token flatten")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use ndarray::*;
+
+    /// 4x4 patches of a 8x8x3 image embedded in 5 channels, flattened to
+    /// (1, 4, 5) tokens.
+    fn patch_embedding(data_format: DataFormat) -> InferenceModel {
+        use crate::ops::array::{PermuteAxes, Reshape};
+        let nchw = data_format == DataFormat::NCHW;
+        let (input_shape, kernel_fmt) = if nchw {
+            (tvec!(1, 3, 8, 8), KernelFormat::OIHW)
+        } else {
+            (tvec!(1, 8, 8, 3), KernelFormat::HWIO)
+        };
+        let kernel = Array4::from_shape_fn((5, 3, 4, 4), |(o, i, y, x)| {
+            ((o * 48 + i * 16 + y * 4 + x) % 9) as f32 - 4.0
+        });
+        let kernel = if nchw { kernel } else { kernel.permuted_axes([2, 3, 1, 0]) };
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, input_shape)).unwrap();
+        let conv = Conv::new(
+            data_format,
+            kernel_fmt,
+            None,
+            None,
+            PaddingSpec::Valid,
+            Some(tvec!(4, 4)),
+            1,
+        );
+        let conv = model.chain_default("conv", conv).unwrap();
+        let kernel = model.add_const("kernel", kernel.into_arc_tensor()).unwrap();
+        model.add_edge(OutletId::new(kernel, 0), InletId::new(conv, 1)).unwrap();
+        let shape = if nchw { [1i64, 5, 4] } else { [1i64, 4, 5] };
+        let reshape = model
+            .chain_after(
+                OutletId::new(conv, 0),
+                "reshape",
+                Reshape::default(),
+                tvec!(TensorFact::default()),
+            )
+            .unwrap();
+        let shape = model.add_const("shape", tensor1(&shape)).unwrap();
+        model.add_edge(OutletId::new(shape, 0), InletId::new(reshape, 1)).unwrap();
+        if nchw {
+            let transpose = PermuteAxes::new(Some(vec![0, 2, 1]));
+            model
+                .chain_after(
+                    OutletId::new(reshape, 0),
+                    "transpose",
+                    transpose,
+                    tvec!(TensorFact::default()),
+                )
+                .unwrap();
+        }
+        model
+    }
+
+    fn check_tokens(data_format: DataFormat) {
+        use crate::ops::array::{PermuteAxes, Reshape};
+        let model = patch_embedding(data_format).into_typed().unwrap();
+        let input_shape = model.input_fact(0).unwrap().shape.as_finite().unwrap().to_vec();
+        let input = ArrayD::from_shape_fn(&*input_shape, |ix| {
+            ((ix[1] * 64 + ix[2] * 8 + ix[3]) % 13) as f32 / 2.0 - 3.0
+        });
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into())).unwrap();
+        let (optimized, events) = model.into_optimized_reporting().unwrap();
+        assert!(events.iter().any(|e| e.transformation == "fused token flatten"));
+        assert!(optimized
+            .nodes()
+            .iter()
+            .all(|n| !n.op_is::<Reshape>() && !n.op_is::<PermuteAxes>()));
+        let found = SimplePlan::new(&optimized).unwrap().run(tvec!(input.into())).unwrap();
+        assert_eq!(found[0].shape(), &[1, 4, 5]);
+        assert_close!(*found[0], *expected[0]);
+    }
+
+    #[test]
+    fn nchw_patch_embedding_writes_tokens() {
+        check_tokens(DataFormat::NCHW);
+    }
+
+    #[test]
+    fn nhwc_patch_embedding_writes_tokens() {
+        check_tokens(DataFormat::NHWC);
+    }
+}
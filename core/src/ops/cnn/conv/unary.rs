@@ -3,16 +3,42 @@ use ndarray::*;
 use crate::internal::*;
 use crate::model::*;
 
+use super::blocked::BlockedMatMat;
+use super::channel_blocked::ChannelBlockedConv;
 use super::depth_wise::DepthWise;
+use super::error::ConvError;
+use super::golden::{GoldenCheck, GoldenOutput};
 use super::im2col::Im2Col;
+use super::kernel_cache::{KernelCache, PackedLayout};
 use super::mat_mat::MatMat;
+use super::packed::PackedConv;
+use super::scratch::ScratchAllocator;
+use super::summary::ChannelSummary;
+use super::timing::PhaseTimer;
+use super::validate::{kernel_channels, validate_config};
 use super::vec_mat::VecMat;
-use super::Conv;
+use super::{Conv, ConvOptions, ConvStrategy, KernelGroupLayout, KernelPacking};
 use crate::ops::cnn::conv::KernelFormat;
-use crate::ops::cnn::{PaddingSpec, Patch, PatchSpec};
-use crate::ops::nn::DataFormat;
+use crate::ops::cnn::{PaddingSpec, Patch, PatchPadMode, Window};
+use crate::ops::nn::{
+    channel_blocked_shape, channel_blocks, DataFormat, DataShape, FromChannelBlocks,
+    ToChannelBlocks,
+};
 
 use std::iter::Sum;
+use std::mem::align_of;
+use tract_linalg::PackB;
+
+/// Convolutions of a constant input are folded into a constant only if
+/// their output has at most this many items, not to bloat the model.
+pub(super) const MAX_FOLDED_OUTPUT_LEN: usize = 1 << 20;
+
+/// Cost of writing one item of the im2col matrix, in product multiply-adds.
+const IM2COL_ITEM_COST: usize = 4;
+
+/// Cost of a direct convolution multiply-add, in product multiply-adds: the
+/// direct kernels gather their data through offsets.
+const DIRECT_FMA_COST: usize = 2;
 
 #[derive(Debug, Clone)]
 pub struct ConvUnary {
@@ -27,6 +53,29 @@ pub struct ConvUnary {
     pub full_input_shape: TVec<TDim>,
     pub full_output_shape: TVec<TDim>,
     pub group: usize,
+    pub summary: Option<ChannelSummary>,
+    pub pad_mode: PatchPadMode,
+    /// Lowering options, as set on the `Conv`.
+    pub options: ConvOptions,
+    /// Write the output, and the summary if any, as f64 while the products
+    /// stay in f32. Stabilizes logits without the cost of a f64 conv: ties
+    /// that f32 rounding would break arbitrarily are kept.
+    pub f64_output: bool,
+    /// Write the output as a (N, H * W, C) sequence of tokens, the layout
+    /// vision transformers read patch embeddings in, instead of in
+    /// `data_format`. `full_output_shape` stays in `data_format`.
+    pub token_output: bool,
+    /// Dimensions of the axes following the batch axis, convolved
+    /// independently, see `Conv::with_independent_axes`. The other shapes
+    /// have these axes folded in the batch axis.
+    pub independent_shape: TVec<usize>,
+    /// Output the conv must reproduce, checked after each evaluation. See
+    /// `attach_golden`.
+    pub golden: Option<GoldenOutput>,
+    pub kernel_cache: KernelCache,
+    /// Phase times of the im2col evaluations, with the `conv_timing`
+    /// feature. Shared with the ops the conv is lowered to.
+    pub timer: PhaseTimer,
 }
 
 impl ConvUnary {
@@ -38,11 +87,36 @@ impl ConvUnary {
         bias: Option<Tensor>,
         group: usize,
     ) -> TractResult<ConvUnary> {
+        let bias_len = bias.as_ref().map(|b| b.shape().iter().product());
+        validate_config(conv, full_input_shape, kernel.shape(), bias_len, group)?;
         let spatial_rank = full_input_shape.len() - 2;
         let dilations =
             conv.dilations.as_ref().map(|a| TVec::from(&**a)).unwrap_or(tvec!(1; spatial_rank));
         let strides =
             conv.strides.as_ref().map(|a| TVec::from(&**a)).unwrap_or(tvec!(1; spatial_rank));
+        let (output_channels, _) = kernel_channels(conv.kernel_fmt, kernel.shape(), group);
+        let bias = match bias {
+            Some(ref b)
+                if b.shape().iter().product::<usize>() == group && group != output_channels =>
+            {
+                Some(dispatch_datum!(Self::bias_per_channel(b.datum_type())(
+                    b,
+                    output_channels / group
+                ))?)
+            }
+            bias => bias,
+        };
+        // machine-generated graphs often carry a vestigial all-zero bias
+        let bias = bias.filter(|b| !Self::is_all_zero(b));
+        let kernel = match conv.kernel_group_layout {
+            KernelGroupLayout::Contiguous => kernel,
+            KernelGroupLayout::Interleaved => {
+                let axis = conv.kernel_fmt.group_axis(kernel.shape().len());
+                dispatch_datum!(Self::ungroup_interleaved(kernel.datum_type())(
+                    &kernel, axis, group
+                ))?
+            }
+        };
 
         let unary = ConvUnary {
             data_format: conv.data_format,
@@ -55,37 +129,171 @@ impl ConvUnary {
             full_input_shape: full_input_shape.into(),
             full_output_shape: full_output_shape.into(),
             group,
+            summary: None,
+            pad_mode: PatchPadMode::Zero,
+            options: conv.options.clone(),
+            f64_output: false,
+            token_output: false,
+            independent_shape: tvec!(),
+            golden: None,
+            kernel_cache: KernelCache::default(),
+            timer: PhaseTimer::default(),
         };
         Ok(unary)
     }
 
-    fn patch(&self, input_full_shape: &[usize]) -> Patch {
+    /// Repeat a bias given per group for each of the channels in the group.
+    fn bias_per_channel<T: Datum>(bias: &Tensor, channels_per_group: usize) -> TractResult<Tensor> {
+        let bias = bias.as_slice::<T>()?;
+        let len = bias.len() * channels_per_group;
+        Ok(Array1::from_shape_fn(len, |c| bias[c / channels_per_group].clone()).into())
+    }
+
+    pub(super) fn is_all_zero(tensor: &Tensor) -> bool {
+        tensor
+            .cast_to::<f64>()
+            .and_then(|t| Ok(t.as_slice::<f64>()?.iter().all(|x| *x == 0.0)))
+            .unwrap_or(false)
+    }
+
+    /// Reorder `axis` from `j * group + g` to `g * (len / group) + j`.
+    fn ungroup_interleaved<T: Datum>(
+        kernel: &Tensor,
+        axis: usize,
+        group: usize,
+    ) -> TractResult<Tensor> {
+        let len = kernel.shape()[axis];
+        if len % group != 0 {
+            bail!(ConvError::ChannelsNotDivisibleByGroup { channels: len, group });
+        }
+        let mut shape = kernel.shape().to_vec();
+        shape[axis] = len / group;
+        shape.insert(axis + 1, group);
+        let mut split = kernel.to_array_view::<T>()?.into_shape(shape)?;
+        split.swap_axes(axis, axis + 1);
+        let reordered = ArrayD::from_shape_vec(kernel.shape(), split.iter().cloned().collect())?;
+        Ok(reordered.into())
+    }
+
+    pub(super) fn patch(&self, input_full_shape: &[usize]) -> Patch {
         let kernel_spatial_shape =
             &self.kernel.shape()[self.kernel_fmt.h_axis()..][..(input_full_shape.len() - 2)];
         let output_inner_stride = match self.data_format {
             DataFormat::NHWC => self.output_channels(),
             DataFormat::NCHW => 1,
         };
-        let spec = PatchSpec::for_full_shape(self.data_format.clone(), input_full_shape)
-            .with_kernel_shape(kernel_spatial_shape.into())
-            .with_padding(self.padding.clone())
-            .with_dilations(self.dilations.clone())
-            .with_strides(self.strides.clone())
-            .with_output_inner_stride(output_inner_stride);
-        spec.into_patch()
+        self.window(kernel_spatial_shape)
+            .patch_spec(self.data_format, input_full_shape)
+            .with_pad_mode(self.pad_mode)
+            .with_output_inner_stride(output_inner_stride)
+            .into_patch()
     }
 
-    fn input_channels(&self) -> usize {
-        match self.kernel_fmt {
-            KernelFormat::OIHW => self.kernel.shape()[1],
-            KernelFormat::HWIO => self.kernel.shape()[self.kernel.shape().len() - 2],
+    /// The sliding window of the conv, `kernel_spatial_shape` being that of
+    /// its kernel.
+    fn window(&self, kernel_spatial_shape: &[usize]) -> Window {
+        Window::new(
+            kernel_spatial_shape.into(),
+            Some(self.strides.clone()),
+            Some(self.dilations.clone()),
+            self.padding.clone(),
+        )
+    }
+
+    /// Input channels over all the groups, whatever the kernel format.
+    pub(super) fn input_channels(&self) -> usize {
+        kernel_channels(self.kernel_fmt, self.kernel.shape(), self.group).1
+    }
+
+    /// Check the input has the `k / (H * W) * group` channels the kernel
+    /// reads: the im2col gather would read past them otherwise.
+    fn check_input_channels(&self, input_full_shape: &[usize]) -> TractResult<()> {
+        if input_full_shape.len() != self.full_input_shape.len() {
+            bail!(ConvError::ShapeMismatch {
+                what: "input rank",
+                expected: self.full_input_shape.len(),
+                found: input_full_shape.len()
+            });
         }
+        let kshape = self.kernel.shape();
+        let spatial =
+            kshape[self.kernel_fmt.h_axis()..][..kshape.len() - 2].iter().product::<usize>();
+        let k = self.kernel.shape().iter().product::<usize>() / self.output_channels();
+        let expected = k / spatial.max(1) * self.group;
+        let found = self.data_format.shape(input_full_shape).c_dim();
+        if found != expected {
+            bail!(ConvError::ShapeMismatch { what: "input channels", expected, found });
+        }
+        Ok(())
+    }
+
+    /// The shape of the output: `full_output_shape`, or its tokens.
+    pub fn output_shape(&self) -> TVec<TDim> {
+        if !self.token_output {
+            return self.full_output_shape.clone();
+        }
+        let shape = self.data_format.shape(&self.full_output_shape);
+        tvec!(shape.n(), shape.hw_dims().iter().cloned().product::<TDim>(), shape.c())
     }
 
-    fn output_channels(&self) -> usize {
+    pub(super) fn output_channels(&self) -> usize {
         self.data_format.shape(&self.full_output_shape).c_dim().to_integer().unwrap() as usize
     }
 
+    /// Compare the estimated costs of a direct convolution and an im2col
+    /// product for this input.
+    ///
+    /// The product computes whole tiles, padding included, after writing the
+    /// whole im2col matrix: on small feature maps, this outweighs the slower
+    /// multiply-adds of the direct kernels.
+    pub(super) fn prefers_direct(&self, input_full_shape: &[usize]) -> bool {
+        let patch = self.patch(input_full_shape);
+        let m = self.output_channels();
+        let k = self.input_channels() * patch.standard_layout_data_field.len();
+        let n = patch.output_shape.iter().product::<usize>();
+        if m * k * n == 0 {
+            return false;
+        }
+        let mm = (tract_linalg::ops().smm)(m, k, n);
+        let padded_m = mm.packed_a_len() / k;
+        let padded_n = mm.b_pack().len() / k;
+        let gemm = padded_m * padded_n * k + IM2COL_ITEM_COST * mm.b_pack().len();
+        let direct = DIRECT_FMA_COST * m * k * n;
+        direct < gemm
+    }
+
+    /// Arithmetic intensity of the convolution of `input`, see
+    /// `ArithmeticIntensity`. The output is of the type of the input.
+    pub fn arithmetic_intensity(
+        &self,
+        input: &TypedTensorInfo,
+    ) -> TractResult<super::ArithmeticIntensity> {
+        let shape = input
+            .shape
+            .as_finite()
+            .ok_or("Can not tell the arithmetic intensity of a streaming convolution")?;
+        let shape = self.fold_independent(shape);
+        let patch = self.patch(&shape);
+        let input_shape = self.data_format.shape(&*shape);
+        let m = self.output_channels() / self.group;
+        let k = self.input_channels() / self.group * patch.standard_layout_data_field.len();
+        let n = input_shape.n() * patch.output_shape.iter().product::<usize>();
+        let item = input.datum_type.size_of();
+        let tensor_bytes =
+            |t: &Tensor| t.shape().iter().product::<usize>() * t.datum_type().size_of();
+        let kernel_bytes =
+            tensor_bytes(&self.kernel) + self.bias.as_ref().map(tensor_bytes).unwrap_or(0);
+        Ok(super::ArithmeticIntensity::new(
+            m,
+            k,
+            n,
+            self.group,
+            shape.iter().product::<usize>() * item,
+            kernel_bytes,
+            m * self.group * n * item,
+        ))
+    }
+
     pub fn to_direct(&self, input_full_shape: &[usize]) -> TractResult<super::Direct> {
         assert!(
             (0..input_full_shape.len() - 2).all(|ax| self.padding.valid_dim(ax))
@@ -130,8 +338,20 @@ impl ConvUnary {
         Ok(super::Direct::new(conv, input_shape, output_shape, packed))
     }
 
-    fn kernel_as_group_o_ihw<T: Datum>(&self) -> TractResult<Array3<T>> {
+    /// The kernel as (group, output channels per group, rest).
+    ///
+    /// Group `g` is the `g`-th contiguous slice of the kernel group axis,
+    /// output channels in OIHW and input channels in HWIO: interleaved
+    /// kernels have been reordered by `new`.
+    pub(super) fn kernel_as_group_o_ihw<T: Datum>(&self) -> TractResult<Array3<T>> {
         let kernel = self.kernel.to_array_view::<T>()?;
+        let axis = self.kernel_fmt.group_axis(kernel.ndim());
+        if self.group == 0 || kernel.shape()[axis] % self.group != 0 {
+            bail!(ConvError::ChannelsNotDivisibleByGroup {
+                channels: kernel.shape()[axis],
+                group: self.group
+            });
+        }
         let final_shape = (
             self.group,
             self.output_channels() / self.group,
@@ -143,7 +363,7 @@ impl ConvUnary {
             KernelFormat::HWIO => {
                 let mut shape = kernel.shape().to_vec();
                 shape.insert(hw_rank, self.group);
-                shape[hw_rank] /= self.group;
+                shape[hw_rank + 1] /= self.group;
                 let kernel = kernel.into_shape(shape)?;
                 let mut permutation: Vec<usize> = vec![hw_rank, hw_rank + 2, hw_rank + 1];
                 permutation.extend(0..hw_rank);
@@ -154,7 +374,15 @@ impl ConvUnary {
         }
     }
 
-    fn bias_reshaped<T>(&self, output_shape: &[usize]) -> TractResult<Option<ArrayD<T>>>
+    /// The bias, shaped to broadcast along the channel axis of an output of
+    /// `output_shape`.
+    ///
+    /// Any bias holding one value per output channel on a single axis is
+    /// accepted: [C], or [1, C, 1, 1] whatever the data format.
+    pub(super) fn bias_reshaped<T>(
+        &self,
+        output_shape: &DataShape,
+    ) -> TractResult<Option<ArrayD<T>>>
     where
         T: Datum + Clone + ndarray::LinalgScalar + std::ops::AddAssign<T>,
     {
@@ -162,25 +390,51 @@ impl ConvUnary {
             .bias
             .as_ref()
             .map(|bias| -> TractResult<_> {
+                let output_channels = self.output_channels();
+                let bias_len = bias.shape().iter().product::<usize>();
+                if bias_len != output_channels {
+                    bail!(ConvError::ShapeMismatch {
+                        what: "bias length",
+                        expected: output_channels,
+                        found: bias_len
+                    });
+                }
+                let channel_axes = bias.shape().iter().filter(|&&d| d != 1).count();
+                if channel_axes > 1 {
+                    bail!(ConvError::ShapeMismatch {
+                        what: "bias non-unit axes",
+                        expected: 1,
+                        found: channel_axes
+                    });
+                }
                 let mut bias_shape: Vec<usize> =
-                    ::std::iter::repeat(1).take(output_shape.len()).collect();
-                bias_shape[self.data_format.shape(output_shape).c_axis()] = self.output_channels();
+                    ::std::iter::repeat(1).take(output_shape.rank()).collect();
+                bias_shape[output_shape.c_axis()] = output_channels;
                 Ok(bias.to_array_view::<T>()?.into_shape(&*bias_shape)?.to_owned())
             })
             .transpose()?)
     }
 
-    fn to_im2col_pair<T>(
+    pub(super) fn to_im2col_pair<T>(
         &self,
         input_full_shape: &[usize],
     ) -> TractResult<(Im2Col<T>, TVec<usize>, Box<Op>)>
     where
-        T: Datum + Clone + ndarray::LinalgScalar + std::ops::AddAssign<T> + FloatLike,
+        T: Datum
+            + Clone
+            + ndarray::LinalgScalar
+            + std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
     {
         trace!("to_im2col_pair: {:?}", self);
+        self.check_input_channels(input_full_shape)?;
         let patch = self.patch(input_full_shape);
+        patch.check_pad_mode()?;
         let input_shape = self.data_format.shape(input_full_shape.into());
-        let output_shape = self.data_format.from_n_c_hw(
+        // tokens are the channels last output, flattened
+        let output_format = if self.token_output { DataFormat::NHWC } else { self.data_format };
+        let output_shape = output_format.from_n_c_hw(
             input_shape.n(),
             self.output_channels(),
             &*patch.output_shape,
@@ -192,33 +446,76 @@ impl ConvUnary {
         let k = kernel.len() / self.output_channels();
         let n = patch.output_shape.iter().cloned().product::<usize>();
 
-        let bias = self.bias_reshaped(&*output_shape.shape)?;
+        let bias = self.bias_reshaped(&output_shape)?;
 
-        let kernel = self.kernel_as_group_o_ihw()?;
-        let mut packed_kernels: Vec<Tensor> = vec![];
+        let blocks = match self.options.kernel_packing {
+            KernelPacking::ChannelBlocks(0) => {
+                bail!("Conv kernel channel blocks must hold at least 1 channel")
+            }
+            KernelPacking::ChannelBlocks(block) if !self.f64_output && !self.token_output => {
+                Some(block)
+            }
+            _ => None,
+        };
 
-        let (op2, b_pack): (Box<Op>, _) = if m > 1 {
-            let mm = T::packed_mat_mul(m, k, n);
-            let b_pack = mm.b_pack();
+        let (op2, b_pack): (Box<Op>, _) = if let Some(block) = blocks {
+            // a single panel as wide as the output: plain (k, n) rows
+            let b_pack = PackB::new(k, n, n.max(1), align_of::<T>());
 
-            trace!("Gemm iters={} m={} k={} n={}", input_shape.n_dim() * self.group, m, k, n);
+            trace!(
+                "Blocked gemm iters={} m={} k={} n={}",
+                input_shape.n_dim() * self.group,
+                m,
+                k,
+                n
+            );
 
-            for subkernel in kernel.outer_iter() {
-                let mut packed = unsafe {
-                    Tensor::uninitialized_aligned::<T>(
-                        &[mm.packed_a_len()],
-                        mm.packed_a_alignment(),
-                    )?
-                };
-                mm.pack_a(
-                    packed.as_slice_mut()?.as_mut_ptr(),
-                    subkernel.as_ptr(),
-                    subkernel.strides()[0],
-                    subkernel.strides()[1],
-                );
-                packed_kernels.push(packed);
+            let packed_kernels =
+                self.kernel_cache.get_or_pack::<T>(PackedLayout::Blocked(block), || {
+                    BlockedMatMat::pack_kernels(block, self.kernel_as_group_o_ihw::<T>()?.view())
+                })?;
+            let mut conv_gemm = BlockedMatMat::new(
+                patch.clone(),
+                output_shape,
+                m,
+                k,
+                n,
+                block,
+                packed_kernels,
+                bias,
+                self.group,
+                self.summary,
+            );
+            conv_gemm.timer = self.timer.clone();
+            (Box::new(conv_gemm), b_pack)
+        } else if m > 1
+            || self.f64_output
+            || self.token_output
+            // there is no f64 vector-matrix kernel
+            || T::datum_type() == DatumType::F64
+        {
+            let mut mm = T::packed_mat_mul(m, k, n);
+            // with the kernel as B, the product computes C^T = data^T.kernel^T
+            let kernel_as_b = mm.prefer_transposed();
+            if kernel_as_b {
+                mm = T::packed_mat_mul(n, k, m);
             }
-            let conv_gemm = MatMat::new(
+            let b_pack = if kernel_as_b { mm.a_pack() } else { mm.b_pack() };
+
+            trace!(
+                "Gemm iters={} m={} k={} n={} kernel_as_b={}",
+                input_shape.n_dim() * self.group,
+                m,
+                k,
+                n,
+                kernel_as_b
+            );
+
+            let packed_kernels =
+                self.kernel_cache.get_or_pack::<T>(PackedLayout::operand(kernel_as_b), || {
+                    MatMat::pack_kernels(&*mm, kernel_as_b, self.kernel_as_group_o_ihw()?.view())
+                })?;
+            let mut conv_gemm = MatMat::new(
                 patch.clone(),
                 output_shape,
                 m,
@@ -228,8 +525,19 @@ impl ConvUnary {
                 packed_kernels,
                 bias,
                 self.group,
+                self.summary,
                 mm.clone(),
+                kernel_as_b,
             );
+            if !self.options.deterministic {
+                conv_gemm.threads = MatMat::spatial_threads(&*mm, kernel_as_b);
+                if self.group > 1 {
+                    conv_gemm.group_threads = MatMat::group_threads(&*mm, self.group);
+                }
+            }
+            conv_gemm.f64_output = self.f64_output;
+            conv_gemm.token_output = self.token_output;
+            conv_gemm.timer = self.timer.clone();
             (Box::new(conv_gemm), b_pack)
         } else {
             let mm = T::packed_vec_mat_mul(k, n);
@@ -237,21 +545,26 @@ impl ConvUnary {
 
             trace!("Gemm iters={} m={} k={} n={}", input_shape.n_dim() * self.group, m, k, n);
 
-            for subkernel in kernel.outer_iter() {
-                let mut packed = unsafe {
-                    Tensor::uninitialized_aligned::<T>(
-                        &[mm.packed_a_len()],
-                        mm.packed_a_alignment(),
-                    )?
-                };
-                mm.pack_a(
-                    packed.as_slice_mut()?.as_mut_ptr(),
-                    subkernel.as_ptr(),
-                    subkernel.strides()[1],
-                );
-                packed_kernels.push(packed);
-            }
-            let conv_gemm = VecMat::new(
+            let packed_kernels = self.kernel_cache.get_or_pack::<T>(PackedLayout::A, || {
+                let kernel = self.kernel_as_group_o_ihw()?;
+                let mut packed_kernels: Vec<Tensor> = vec![];
+                for subkernel in kernel.outer_iter() {
+                    let mut packed = unsafe {
+                        Tensor::uninitialized_aligned::<T>(
+                            &[mm.packed_a_len()],
+                            mm.packed_a_alignment(),
+                        )?
+                    };
+                    mm.pack_a(
+                        packed.as_slice_mut()?.as_mut_ptr(),
+                        subkernel.as_ptr(),
+                        subkernel.strides()[1],
+                    );
+                    packed_kernels.push(packed);
+                }
+                Ok(packed_kernels)
+            })?;
+            let mut conv_gemm = VecMat::new(
                 patch.clone(),
                 output_shape,
                 k,
@@ -260,13 +573,15 @@ impl ConvUnary {
                 packed_kernels,
                 bias,
                 self.group,
+                self.summary,
                 mm,
             );
+            conv_gemm.timer = self.timer.clone();
             (Box::new(conv_gemm), b_pack)
         };
         let c_dim = input_shape.c_dim();
 
-        let im2col = Im2Col::new(
+        let mut im2col = Im2Col::new(
             patch.clone(),
             input_shape,
             m,
@@ -276,6 +591,10 @@ impl ConvUnary {
             c_dim / self.group,
             b_pack,
         );
+        if !self.options.deterministic {
+            im2col.threads = im2col.spatial_threads();
+        }
+        im2col.timer = self.timer.clone();
         let intermediary_shape = im2col.output_shape().into();
         Ok((im2col, intermediary_shape, op2))
     }
@@ -285,21 +604,139 @@ impl ConvUnary {
         input_full_shape: &[usize],
     ) -> TractResult<(Box<Op>, TVec<usize>, Box<Op>)>
     where
-        T: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<T> + FloatLike,
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
     {
         let (op1, shape, op2) = self.to_im2col_pair::<T>(input_full_shape)?;
         Ok((Box::new(op1), shape, op2))
     }
 
+    /// Lower to the im2col pair, unless the scratch exceeds the budget: the
+    /// conv is then left to evaluate itself in bands.
+    fn im2col_pair_patch(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+        shape: &[usize],
+    ) -> TractResult<Option<TypedModelPatch>> {
+        if self.scratch_band_rows(shape)?.is_some() {
+            return Ok(None);
+        }
+        let dt = model.outlet_fact(node.inputs[0])?.datum_type;
+        self.timer.set_label(&*node.name);
+        if self.options.packed_lowering {
+            let op = dispatch_floatlike!(Self::to_packed(dt)(self, shape))?;
+            return Ok(Some(
+                TypedModelPatch::single_unary_op(model, node, op)?
+                    .with_label("lowered to packed im2col product"),
+            ));
+        }
+        let (op1, shape, op2) = dispatch_floatlike!(Self::to_boxed_im2col_pair(dt)(self, shape))?;
+        let mut patch = TypedModelPatch::default();
+        let _ = patch.tap_model(&model, node.inputs[0])?;
+        patch.chain(
+            format!("{}-im2col", node.name),
+            op1,
+            tvec!(TypedTensorInfo { shape: ShapeInfo::from(&*shape), datum_type: dt, konst: None }),
+        )?;
+        let mm =
+            patch.chain(&*node.name, op2, node.outputs.iter().map(|o| o.fact.clone()).collect())?;
+        for ix in 0..node.outputs.len() {
+            patch.shunt_outside(OutletId::new(node.id, ix), OutletId::new(mm, ix))?;
+        }
+        Ok(Some(patch.with_label("lowered to im2col and matrix product")))
+    }
+
+    /// Lower the conv as if it had no golden output, then check the output
+    /// of what it is lowered to.
+    fn checked_codegen(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let golden = self.golden.clone().unwrap();
+        let mut bare = self.clone();
+        bare.golden = None;
+        let mut patch = match bare.codegen(model, node)? {
+            Some(patch) => patch,
+            None => return Ok(None),
+        };
+        let outlet = OutletId::new(node.id, 0);
+        let lowered = match patch.shunted_by(outlet) {
+            Some(lowered) => lowered,
+            // the conv was fused with its successors: the golden is lost
+            None => return Ok(None),
+        };
+        let fact = patch.outlet_fact(lowered)?.clone();
+        let check = patch.add_node(
+            format!("{}-golden", node.name),
+            GoldenCheck::new(node.name.clone(), golden),
+            tvec!(fact),
+        )?;
+        patch.add_edge(lowered, InletId::new(check, 0))?;
+        patch.shunt_outside(outlet, OutletId::new(check, 0))?;
+        Ok(Some(patch))
+    }
+
+    /// Replace the conv by its outputs when its input is a constant.
+    fn fold_const(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let input = match model.outlet_fact(node.inputs[0])?.konst.clone() {
+            Some(input) => input,
+            None => return Ok(None),
+        };
+        let len = self
+            .full_output_shape
+            .iter()
+            .map(|d| d.to_integer().ok().map(|d| d as usize))
+            .product::<Option<usize>>();
+        if len.map(|len| len > MAX_FOLDED_OUTPUT_LEN).unwrap_or(true) {
+            return Ok(None);
+        }
+        let mut patch = TypedModelPatch::default();
+        for (ix, output) in self.eval(tvec!(input))?.into_iter().enumerate() {
+            let id = patch.add_const(format!("{}.{}", node.name, ix), output)?;
+            patch.shunt_outside(OutletId::new(node.id, ix), OutletId::new(id, 0))?;
+        }
+        Ok(Some(patch.with_label("folded constant input")))
+    }
+
     fn eval_t<T>(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>>
     where
-        T: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<T> + FloatLike,
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
     {
         let input = args_1!(inputs);
-        let (im2col, _shape, conv_gemm) = self.to_im2col_pair::<T>(input.shape())?;
-        let mega = im2col.im2col(&input.to_array_view()?)?;
-        trace!("im2col: {:?}", mega);
-        conv_gemm.as_stateless().unwrap().eval(tvec!(mega.into()))
+        if let Some(band) = self.scratch_band_rows(input.shape())? {
+            return self.eval_in_bands::<T>(&input, band);
+        }
+        let packed = PackedConv::<T>::from_conv(self, input.shape())?;
+        let mut session = SessionState::default();
+        let mut state = packed.state(&mut session)?.unwrap();
+        state.eval(&mut session, &packed, tvec!(input))
+    }
+
+    fn to_packed<T>(&self, input_full_shape: &[usize]) -> TractResult<Box<Op>>
+    where
+        T: Datum
+            + Clone
+            + ::ndarray::LinalgScalar
+            + ::std::ops::AddAssign<T>
+            + FloatLike
+            + num_traits::Float,
+    {
+        Ok(Box::new(PackedConv::<T>::from_conv(self, input_full_shape)?))
     }
 
     pub fn rm_dummy_axis(&self, axis: usize) -> TractResult<Option<ConvUnary>> {
@@ -307,6 +744,9 @@ impl ConvUnary {
         if axis < shape.h_axis() {
             return Ok(None);
         }
+        if self.summary.is_some() || self.token_output || !self.independent_shape.is_empty() {
+            return Ok(None);
+        }
         let geo_axis = axis - shape.h_axis();
         if geo_axis >= shape.hw_rank() {
             return Ok(None);
@@ -327,7 +767,7 @@ impl ConvUnary {
         }
         let kernel_shape: TVec<usize> =
             copy_rm_nth(self.kernel.shape().clone(), geo_axis + self.kernel_fmt.h_axis());
-        let kernel = unsafe { self.kernel.clone().into_shape(&kernel_shape)? }; 
+        let kernel = unsafe { self.kernel.clone().into_shape(&kernel_shape)? };
         let new_op = ConvUnary {
             data_format: self.data_format,
             kernel_fmt: self.kernel_fmt,
@@ -339,10 +779,86 @@ impl ConvUnary {
             full_input_shape: copy_rm_nth(&self.full_input_shape, axis),
             full_output_shape: copy_rm_nth(&self.full_output_shape, axis),
             group: self.group,
+            summary: None,
+            pad_mode: self.pad_mode,
+            options: self.options.clone(),
+            f64_output: self.f64_output,
+            token_output: false,
+            independent_shape: tvec!(),
+            golden: None,
+            kernel_cache: KernelCache::default(),
+            timer: self.timer.clone(),
         };
         Ok(Some(new_op))
     }
 
+    fn forced_codegen(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let inputs = model.node_input_facts(node.id)?;
+        let shape = if let Some(shape) = inputs[0].shape.as_finite() {
+            shape
+        } else {
+            return Ok(None);
+        };
+        let dt = inputs[0].datum_type;
+        let spatial_rank = self.full_input_shape.len() - 2;
+        let input_channels = shape[self.data_format.shape(shape).c_axis()];
+        let reason = match self.options.strategy {
+            ConvStrategy::Auto | ConvStrategy::ForceGemm => None,
+            _ if self.summary.is_some() => Some("only gemm can produce a channel summary"),
+            _ if self.pad_mode != PatchPadMode::Zero => Some("only gemm can sample padding"),
+            _ if self.f64_output => Some("only gemm can upcast its output"),
+            _ if self.token_output => Some("only gemm can write tokens"),
+            ConvStrategy::ForceWinograd => self.winograd_unsupported(dt),
+            ConvStrategy::ForceDirect if dt != f32::datum_type() => Some("direct is f32 only"),
+            ConvStrategy::ForceDirect
+                if !(0..spatial_rank).all(|ax| self.padding.valid_dim(ax)) =>
+            {
+                Some("direct does not pad")
+            }
+            ConvStrategy::ForceDirect if self.group != 1 => Some("direct does not support groups"),
+            ConvStrategy::ForceDirect if self.bias.is_some() => Some("direct ignores bias"),
+            ConvStrategy::ForceDepthwise if self.group != input_channels => {
+                Some("depthwise needs one group per input channel")
+            }
+            ConvStrategy::ForceChannelBlocked(0) => Some("channel blocks can not be empty"),
+            ConvStrategy::ForceChannelBlocked(_) if self.data_format != DataFormat::NCHW => {
+                Some("channel blocks split a NCHW input")
+            }
+            ConvStrategy::ForceChannelBlocked(_) if self.group != 1 => {
+                Some("channel blocked does not support groups")
+            }
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            bail!(ConvError::UnsupportedStrategy { strategy: self.options.strategy, reason });
+        }
+        let patch = match self.options.strategy {
+            ConvStrategy::ForceDirect => {
+                TypedModelPatch::single_unary_op(model, node, self.to_direct(&*shape)?)?
+                    .with_label("lowered to direct")
+            }
+            ConvStrategy::ForceWinograd => {
+                TypedModelPatch::single_unary_op(model, node, self.to_winograd(&*shape)?)?
+                    .with_label("lowered to winograd")
+            }
+            ConvStrategy::ForceDepthwise => TypedModelPatch::single_unary_op(
+                model,
+                node,
+                dispatch_floatlike!(Self::to_depth_wise(dt)(self, &shape))?,
+            )?
+            .with_label("lowered to depthwise"),
+            ConvStrategy::ForceChannelBlocked(block) => {
+                self.channel_blocked_patch(model, node, &shape, block)?
+            }
+            _ => return self.im2col_pair_patch(model, node, &shape),
+        };
+        Ok(Some(patch))
+    }
+
     pub fn to_depth_wise<T>(&self, shape: &[usize]) -> TractResult<Box<Op>>
     where
         T: Datum + Clone + ::ndarray::LinalgScalar + ::std::ops::AddAssign<T> + PartialEq + Sum,
@@ -355,15 +871,153 @@ impl ConvUnary {
             .map(|a| a.to_integer().map(|a| a as usize))
             .collect::<TractResult<TVec<usize>>>()?;
         let output_shape = self.data_format.shape(output_shape);
+        let bias = self.bias_reshaped(&output_shape)?;
         let op = DepthWise::<T>::new(
             patch,
             input_shape,
             output_shape,
             self.kernel_as_group_o_ihw()?.into_dyn(),
-            self.bias_reshaped(&*shape)?,
+            bias,
         );
         Ok(Box::new(op))
     }
+
+    /// Transposes a NCHW input to NHWC, convolves it, and transposes the
+    /// output back, see `TypedModel::into_nhwc_convs`.
+    pub(crate) fn nhwc_patch(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        use crate::ops::array::PermuteAxes;
+        if self.data_format != DataFormat::NCHW
+            || !self.independent_shape.is_empty()
+            || self.summary.is_some()
+            || self.token_output
+            || self.golden.is_some()
+        {
+            return Ok(None);
+        }
+        if let ConvStrategy::ForceChannelBlocked(_) = self.options.strategy {
+            return Ok(None);
+        }
+        let rank = self.full_input_shape.len();
+        let to_nhwc: Vec<usize> = Some(0).into_iter().chain(2..rank).chain(Some(1)).collect();
+        let to_nchw: Vec<usize> =
+            Some(0).into_iter().chain(Some(rank - 1)).chain(1..rank - 1).collect();
+        let nhwc = |shape: &[TDim]| to_nhwc.iter().map(|&ax| shape[ax]).collect::<TVec<TDim>>();
+        let mut conv = self.clone();
+        conv.data_format = DataFormat::NHWC;
+        conv.full_input_shape = nhwc(&self.full_input_shape);
+        conv.full_output_shape = nhwc(&self.full_output_shape);
+        conv.kernel_cache = KernelCache::default();
+        let fact = |fact: &TypedTensorInfo| {
+            let shape: TVec<TDim> = fact.shape.iter().collect();
+            TypedTensorInfo {
+                datum_type: fact.datum_type,
+                shape: nhwc(&shape).into_iter().collect(),
+                konst: None,
+            }
+        };
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(&model, node.inputs[0])?;
+        patch.chain(
+            format!("{}-to-nhwc", node.name),
+            PermuteAxes::new(Some(to_nhwc.clone())),
+            tvec!(fact(model.outlet_fact(node.inputs[0])?)),
+        )?;
+        patch.chain(format!("{}-nhwc", node.name), conv, tvec!(fact(&node.outputs[0].fact)))?;
+        let back = patch.chain(
+            &*node.name,
+            PermuteAxes::new(Some(to_nchw)),
+            tvec!(node.outputs[0].fact.clone()),
+        )?;
+        patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(back, 0))?;
+        Ok(Some(patch.with_label("convolving in NHWC")))
+    }
+
+    /// Converts the input to channel blocks, convolves the blocks, and
+    /// converts the output back. Consecutive blocked convs lose the
+    /// conversions between them when decluttered.
+    fn channel_blocked_patch(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+        shape: &[usize],
+        block: usize,
+    ) -> TractResult<TypedModelPatch> {
+        let dt = model.outlet_fact(node.inputs[0])?.datum_type;
+        let conv = dispatch_floatlike!(Self::to_channel_blocked(dt)(self, shape, block))?;
+        let fact = |shape: &[usize]| TypedTensorInfo {
+            shape: ShapeInfo::from(shape),
+            datum_type: dt,
+            konst: None,
+        };
+        let mut patch = TypedModelPatch::default();
+        let _ = patch.tap_model(&model, node.inputs[0])?;
+        patch.chain(
+            format!("{}-to-blocks", node.name),
+            ToChannelBlocks::new(block),
+            tvec!(fact(&channel_blocked_shape(shape, block)?)),
+        )?;
+        let output_shape =
+            node.outputs[0].fact.shape.as_finite().ok_or("Expected a concrete output shape")?;
+        let output_shape = channel_blocked_shape(&*output_shape, block)?;
+        patch.chain(format!("{}-blocked", node.name), conv, tvec!(fact(&output_shape)))?;
+        let from = patch.chain(
+            &*node.name,
+            FromChannelBlocks::new(block, self.output_channels()),
+            tvec!(node.outputs[0].fact.clone()),
+        )?;
+        patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(from, 0))?;
+        Ok(patch.with_label(format!("lowered to NCHW{}c channel blocks", block)))
+    }
+
+    pub fn to_channel_blocked<T>(&self, shape: &[usize], block: usize) -> TractResult<Box<Op>>
+    where
+        T: Datum + Copy + ::ndarray::LinalgScalar + ::std::ops::AddAssign<T>,
+    {
+        let blocked_shape = channel_blocked_shape(shape, block)?;
+        let kernel_spatial_shape =
+            &self.kernel.shape()[self.kernel_fmt.h_axis()..][..shape.len() - 2];
+        let lanes: TVec<usize> =
+            Some(1).into_iter().chain(shape[2..].iter().cloned()).chain(Some(block)).collect();
+        let patch = self
+            .window(kernel_spatial_shape)
+            .patch_spec(DataFormat::NHWC, &*lanes)
+            .with_output_inner_stride(block)
+            .into_patch();
+        let output_shape: TVec<usize> = Some(shape[0])
+            .into_iter()
+            .chain(Some(channel_blocks(self.output_channels(), block)))
+            .chain(patch.output_shape.iter().cloned())
+            .chain(Some(block))
+            .collect();
+        let kernel = self.kernel_as_group_o_ihw::<T>()?;
+        let m = self.output_channels();
+        let bias = self
+            .bias
+            .as_ref()
+            .map(|b| -> TractResult<_> {
+                let b = b.as_slice::<T>()?;
+                let len = output_shape[1] * block;
+                Ok(Array1::from_shape_fn(len, |c| if c < m { b[c] } else { T::zero() }))
+            })
+            .transpose()?;
+        let packed_kernel = ChannelBlockedConv::pack_kernel(
+            block,
+            kernel.index_axis(Axis(0), 0),
+            self.input_channels(),
+        );
+        Ok(Box::new(ChannelBlockedConv::new(
+            patch,
+            blocked_shape,
+            output_shape,
+            block,
+            Arc::new(packed_kernel),
+            bias,
+        )))
+    }
 }
 
 impl Op for ConvUnary {
@@ -372,23 +1026,24 @@ impl Op for ConvUnary {
     }
 
     fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
-        let shape = self.data_format.shape(inputs[0].shape.iter().collect::<TVec<TDim>>());
+        let shape = inputs[0].shape.iter().collect::<TVec<TDim>>();
+        let shape = self.data_format.shape(self.fold_independent(&shape));
         let kernel_spatial_shape =
             &self.kernel.shape()[self.kernel_fmt.h_axis()..][..shape.hw_rank()];
-        let output_dims = self.padding.compute(
-            shape.hw_dims(),
-            kernel_spatial_shape,
-            &*self.dilations,
-            &*self.strides,
-        );
+        let output_dims = self.window(kernel_spatial_shape).output_dims(shape.hw_dims());
         let n_output_points: TDim = output_dims.iter().map(|d| d.output).product::<TDim>();
         let n_output_channels = self.output_channels().to_dim();
         let kernel_surface = kernel_spatial_shape.into_iter().product::<usize>().to_dim();
-        Ok(tvec!((
-            Cost::FMA(f32::datum_type()),
-            shape.n() * shape.c() * n_output_channels * n_output_points * kernel_surface
-                / self.group
-        )))
+        let params = self.kernel.shape().iter().product::<usize>()
+            + self.bias.as_ref().map(|b| b.shape().iter().product::<usize>()).unwrap_or(0);
+        Ok(tvec!(
+            (
+                Cost::FMA(f32::datum_type()),
+                shape.n() * shape.c() * n_output_channels * n_output_points * kernel_surface
+                    / self.group
+            ),
+            (Cost::Params(self.kernel.datum_type()), params.to_dim())
+        ))
     }
 
     fn declutter(
@@ -397,6 +1052,24 @@ impl Op for ConvUnary {
         node: &TypedNode,
     ) -> TractResult<Option<TypedModelPatch>> {
         use crate::ops::array::{AddDims, RmDims};
+        if !self.independent_shape.is_empty() {
+            return self.fold_independent_patch(model, node);
+        }
+        if self.options.weight_clamp.is_some() {
+            // the rewrites may scale the kernel, clamp it first
+            let patch = TypedModelPatch::single_unary_op(model, node, self.clamp_weights()?)?;
+            return Ok(Some(patch.with_label("clamped weights")));
+        }
+        if self.golden.is_some() {
+            // only the rewrites keeping the output the golden is checked on
+            if let Some(patch) = self.fuse_pad(model, node)? {
+                return Ok(Some(patch));
+            }
+            return self.fuse_input_scale(model, node);
+        }
+        if let Some(patch) = self.fold_const(model, node)? {
+            return Ok(Some(patch));
+        }
         if let (Some(add_node), Some(rm_node)) =
             (model.single_prec(node.id)?, model.single_succ(node.id)?)
         {
@@ -414,12 +1087,27 @@ impl Op for ConvUnary {
                             tvec!(rm_node.outputs[0].fact.clone()),
                         )?;
                         patch.shunt_outside(OutletId::new(rm_node.id, 0), OutletId::new(out, 0))?;
-                        return Ok(Some(patch));
+                        return Ok(Some(patch.with_label("removed dummy axis")));
                     }
                 }
             }
         }
-        Ok(None)
+        if let Some(patch) = self.fuse_pad(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.fuse_input_scale(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.fuse_bias_add(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.fuse_tokens(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.fuse_softmax_max(model, node)? {
+            return Ok(Some(patch));
+        }
+        self.fuse_global_pool(model, node)
     }
 
     fn codegen(
@@ -427,9 +1115,36 @@ impl Op for ConvUnary {
         model: &TypedModel,
         node: &TypedNode,
     ) -> TractResult<Option<TypedModelPatch>> {
+        if self.golden.is_some() {
+            return self.checked_codegen(model, node);
+        }
+        if self.options.strategy != ConvStrategy::Auto {
+            return self.forced_codegen(model, node);
+        }
+        if let Some(patch) = self.fuse_pixel_shuffle(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.fuse_separable(model, node)? {
+            return Ok(Some(patch));
+        }
+        if let Some(patch) = self.split_rank1_kernel(model, node)? {
+            return Ok(Some(patch));
+        }
         let inputs = model.node_input_facts(node.id)?;
         let spatial_rank = self.full_input_shape.len() - 2;
         let kernel_spatial_shape = &self.kernel.shape()[self.kernel_fmt.h_axis()..][..spatial_rank];
+        if self.summary.is_some()
+            || self.pad_mode != PatchPadMode::Zero
+            || self.f64_output
+            || self.token_output
+        {
+            // only the im2col pair knows how to produce the summary, sample
+            // non-zero padding, upcast the output, or write tokens
+            if let Some(shape) = inputs[0].shape.as_finite() {
+                return self.im2col_pair_patch(model, node, &shape);
+            }
+            return Ok(None);
+        }
         if kernel_spatial_shape.iter().product::<usize>() == 1
             && self.dilations.iter().all(|&x| x == 1)
             && self.strides.iter().all(|&x| x == 1)
@@ -439,12 +1154,11 @@ impl Op for ConvUnary {
             if self.kernel_fmt == KernelFormat::HWIO && self.data_format == DataFormat::NHWC {
                 use crate::ops::math::mat_mul::MatMulUnaryA;
                 let kernel_shape = &self.kernel.shape()[spatial_rank..];
-                let kernel = unsafe { self.kernel.clone().into_shape(&kernel_shape)? }; 
-                return Ok(Some(TypedModelPatch::single_unary_op(
-                    model,
-                    node,
-                    MatMulUnaryA::new(kernel),
-                )?));
+                let kernel = unsafe { self.kernel.clone().into_shape(&kernel_shape)? };
+                return Ok(Some(
+                    TypedModelPatch::single_unary_op(model, node, MatMulUnaryA::new(kernel))?
+                        .with_label("lowered to matrix product"),
+                ));
             }
         } else {
             if let Some(shape) = inputs[0].shape.as_finite() {
@@ -453,32 +1167,26 @@ impl Op for ConvUnary {
                     && dt == f32::datum_type()
                     && self.group == 1
                     && self.bias.is_none()
+                    && self.prefers_direct(&*shape)
                 {
                     let op = self.to_direct(&*shape)?;
-                    return Ok(Some(TypedModelPatch::single_unary_op(model, node, op)?));
-                } else if self.group != 1 && self.group == self.output_channels() {
-                    return Ok(Some(TypedModelPatch::single_unary_op(
-                        model,
-                        node,
-                        dispatch_floatlike!(Self::to_depth_wise(dt)(self, &shape))?,
-                    )?));
+                    return Ok(Some(
+                        TypedModelPatch::single_unary_op(model, node, op)?
+                            .with_label("lowered to direct"),
+                    ));
+                } else if self.group != 1 && self.group == self.data_format.shape(&*shape).c_dim() {
+                    // one group per input channel, whatever the channel
+                    // multiplier
+                    return Ok(Some(
+                        TypedModelPatch::single_unary_op(
+                            model,
+                            node,
+                            dispatch_floatlike!(Self::to_depth_wise(dt)(self, &shape))?,
+                        )?
+                        .with_label("lowered to depthwise"),
+                    ));
                 } else {
-                    let (op1, shape, op2) =
-                        dispatch_floatlike!(Self::to_boxed_im2col_pair(dt)(self, &shape))?;
-                    let mut patch = TypedModelPatch::default();
-                    let _ = patch.tap_model(&model, node.inputs[0])?;
-                    patch.chain(
-                        format!("{}-im2col", node.name),
-                        op1,
-                        tvec!(TypedTensorInfo {
-                            shape: ShapeInfo::from(&*shape),
-                            datum_type: dt,
-                            konst: None,
-                        }),
-                    )?;
-                    let mm = patch.chain(&*node.name, op2, tvec!(node.outputs[0].fact.clone()))?;
-                    patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(mm, 0))?;
-                    return Ok(Some(patch));
+                    return self.im2col_pair_patch(model, node, &shape);
                 }
             }
         }
@@ -492,7 +1200,54 @@ impl Op for ConvUnary {
         target: &mut PulsedModel,
         mapping: &HashMap<OutletId, OutletId>,
     ) -> TractResult<TVec<OutletId>> {
+        if self.summary.is_some() {
+            bail!("Can not pulsify convolution with a channel summary");
+        }
+        if !self.independent_shape.is_empty() {
+            bail!("Can not pulsify convolution with independent axes");
+        }
         let input = mapping[&node.inputs[0]];
+        if self.pad_mode == PatchPadMode::Zero {
+            return self.pulsify_input(&*node.name, target, input);
+        }
+        let (mut before, mut after) = match &self.padding {
+            PaddingSpec::Explicit(before, after) => (before.clone(), after.clone()),
+            _ => bail!("Can not pulsify convolution with {:?} padding", self.pad_mode),
+        };
+        let axis = target.outlet_fact(input)?.axis;
+        let shape = self.data_format.shape(&self.full_input_shape);
+        if !shape.hw_axes().contains(&axis) {
+            return self.pulsify_input(&*node.name, target, input);
+        }
+        // the streaming axis is padded ahead of the conv, over its history
+        let geo_axis = axis - shape.h_axis();
+        let mode = match self.pad_mode {
+            PatchPadMode::Reflect => crate::ops::array::PadMode::Reflect,
+            _ => crate::ops::array::PadMode::Edge,
+        };
+        let padded = crate::ops::array::pulsify_mirror_pad(
+            target,
+            input,
+            &format!("{}/Pad", node.name),
+            mode,
+            before[geo_axis],
+            after[geo_axis],
+        )?;
+        before[geo_axis] = 0;
+        after[geo_axis] = 0;
+        let mut op = self.clone();
+        op.padding = PaddingSpec::Explicit(before, after);
+        op.pulsify_input(&*node.name, target, padded)
+    }
+}
+
+impl ConvUnary {
+    fn pulsify_input(
+        &self,
+        name: &str,
+        target: &mut PulsedModel,
+        input: OutletId,
+    ) -> TractResult<TVec<OutletId>> {
         let mut fact = target.outlet_fact(input)?.clone();
         let shape = self.data_format.shape(&fact.shape);
         if fact.axis == shape.n_axis() {
@@ -510,7 +1265,7 @@ impl Op for ConvUnary {
                         }
                     })
                     .collect();
-            let id = target.chain_after(input, &*node.name, self.clone(), tvec!(fact))?;
+            let id = target.chain_after(input, name, self.clone(), tvec!(fact))?;
             Ok(tvec!(OutletId::new(id, 0)))
         } else if fact.axis == shape.c_axis() {
             bail!("Can not pulsify convolution alongs the input channel axis");
@@ -547,22 +1302,83 @@ impl Op for ConvUnary {
             conv_fact.dim -= kernel_len.to_dim();
 
             let delay = crate::pulse::delay::Delay::new(fact, 0, kernel_len);
-            target.chain_after(
-                input,
-                format!("{}/Delay", node.name),
-                delay,
-                tvec!(augmented_fact),
-            )?;
-            let id = target.chain(&*node.name, conv_op, tvec!(conv_fact))?;
+            target.chain_after(input, format!("{}/Delay", name), delay, tvec!(augmented_fact))?;
+            let id = target.chain(name, conv_op, tvec!(conv_fact))?;
 
             Ok(tvec!(OutletId::new(id, 0)))
         }
     }
 }
 
+impl ConvUnary {
+    /// Can `eval` go through `eval_with_scratch` ?
+    fn evals_with_scratch(&self) -> bool {
+        self.independent_shape.is_empty()
+            && self.options.weight_clamp.is_none()
+            && self.golden.is_none()
+            && self.options.max_scratch_bytes.is_none()
+            && !self.token_output
+            && !self.is_identity()
+    }
+}
+
 impl StatelessOp for ConvUnary {
-    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
-        dispatch_floatlike!(Self::eval_t(inputs[0].datum_type())(self, inputs))
+    fn scratch_bytes(&self, input_shapes: &[&[usize]]) -> TractResult<usize> {
+        if !self.evals_with_scratch() {
+            return Ok(0);
+        }
+        Ok(self.scratch_layout(input_shapes[0])?.size())
+    }
+
+    fn eval_with_scratch(
+        &self,
+        mut inputs: TVec<Arc<Tensor>>,
+        scratch: &mut ScratchAllocator,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        if !self.evals_with_scratch() {
+            return self.eval(inputs);
+        }
+        let input = args_1!(inputs);
+        ConvUnary::eval_with_scratch(self, &input, scratch)
+    }
+
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        if inputs[0].datum_type() != self.kernel.datum_type() {
+            bail!(ConvError::DtypeMismatch {
+                expected: self.kernel.datum_type(),
+                found: inputs[0].datum_type()
+            });
+        }
+        if self.f64_output && inputs[0].datum_type() != DatumType::F32 {
+            bail!(ConvError::DtypeMismatch {
+                expected: DatumType::F32,
+                found: inputs[0].datum_type()
+            });
+        }
+        if self.options.weight_clamp.is_some() {
+            return self.clamp_weights()?.eval(inputs);
+        }
+        let dt = inputs[0].datum_type();
+        let outputs = if self.is_identity() {
+            inputs
+        } else if self.independent_shape.is_empty() {
+            dispatch_floatlike!(Self::eval_t(dt)(self, inputs))?
+        } else {
+            let input = args_1!(inputs);
+            let folded = self.fold_independent(input.shape());
+            let input = unsafe { input.into_tensor().into_shape(&folded)? };
+            dispatch_floatlike!(Self::eval_t(dt)(self, tvec!(input.into_arc_tensor())))?
+                .into_iter()
+                .map(|output| {
+                    let shape = self.unfold_independent(output.shape());
+                    Ok(unsafe { output.into_tensor().into_shape(&shape)? }.into_arc_tensor())
+                })
+                .collect::<TractResult<_>>()?
+        };
+        if let Some(ref golden) = self.golden {
+            golden.check("ConvUnary", &outputs[0])?;
+        }
+        Ok(outputs)
     }
 }
 
@@ -574,10 +1390,1190 @@ impl InferenceRulesOp for ConvUnary {
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
         check_input_arity(&inputs, 1)?;
-        check_output_arity(&outputs, 1)?;
-        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
-        s.equals(&inputs[0].shape, self.full_input_shape.clone())?;
-        s.equals(&outputs[0].shape, self.full_output_shape.clone())?;
+        check_output_arity(&outputs, 1 + self.summary.is_some() as usize)?;
+        if self.f64_output {
+            s.equals(&inputs[0].datum_type, DatumType::F32)?;
+            s.equals(&outputs[0].datum_type, DatumType::F64)?;
+        } else {
+            s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        }
+        s.equals(&inputs[0].shape, self.unfold_independent(&self.full_input_shape))?;
+        s.equals(&outputs[0].shape, self.unfold_independent(&self.output_shape()))?;
+        if let Some(summary) = self.summary {
+            s.equals(&outputs[0].datum_type, &outputs[1].datum_type)?;
+            s.equals(
+                &outputs[1].shape,
+                self.unfold_independent(
+                    &summary.output_shape(self.data_format, &self.full_output_shape),
+                ),
+            )?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::conv::{GroupSink, KernelProvider, PanelPool};
+    use crate::ops::nn::{GlobalAvgPool, GlobalMaxPool};
+
+    fn conv_with_summary(summary: ChannelSummary) -> TVec<Arc<Tensor>> {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        op.summary = Some(summary);
+        op.eval(tvec!(input)).unwrap()
+    }
+
+    fn conv_error(input_shape: &[usize], kernel_shape: &[usize], group: usize) -> ConvError {
+        let input = Tensor::from(ArrayD::<f32>::zeros(input_shape));
+        let kernel = Tensor::from(ArrayD::<f32>::zeros(kernel_shape));
+        let facts = conv_facts(input, kernel);
+        let mut conv = Conv::default();
+        conv.group = group;
+        match conv.to_unary(&facts).unwrap_err().kind() {
+            crate::TractErrorKind::Conv(e) => e.clone(),
+            e => panic!("expected a conv error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn channels_not_divisible_by_group() {
+        let e = conv_error(&[1, 30, 3, 3], &[8, 7, 1, 1], 4);
+        let not_divisible = ConvError::ChannelsNotDivisibleByGroup { channels: 30, group: 4 };
+        assert_eq!(not_divisible.to_string(), "channels 30 not divisible by group 4");
+        let mismatch =
+            ConvError::ShapeMismatch { what: "kernel input channels", expected: 30, found: 28 };
+        assert_eq!(e, ConvError::Several { problems: vec![not_divisible, mismatch] });
+        assert_eq!(
+            e.to_string(),
+            "2 problems: channels 30 not divisible by group 4; \
+             kernel input channels: expected 30, found 28"
+        );
+    }
+
+    #[test]
+    fn kernel_input_channels_mismatch() {
+        let e = conv_error(&[1, 4, 3, 3], &[2, 3, 1, 1], 1);
+        assert_eq!(
+            e,
+            ConvError::ShapeMismatch { what: "kernel input channels", expected: 4, found: 3 }
+        );
+    }
+
+    #[test]
+    fn input_dtype_mismatch() {
+        let input = rctensor4(&[[[[1.0f32]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]]]);
+        let facts = conv_facts(input, kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let err = op.eval(tvec!(rctensor4(&[[[[1.0f64]]]]))).unwrap_err();
+        match err.kind() {
+            crate::TractErrorKind::Conv(ConvError::DtypeMismatch { expected, found }) => {
+                assert_eq!((*expected, *found), (DatumType::F32, DatumType::F64))
+            }
+            e => panic!("expected a dtype mismatch, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn runtime_input_channels_mismatch() {
+        let input = Tensor::from(ArrayD::<f32>::zeros(&[1, 4, 3, 3][..]));
+        let kernel = Tensor::from(ArrayD::<f32>::zeros(&[6, 2, 3, 3][..]));
+        let facts = conv_facts(input, kernel);
+        let mut conv = Conv::default();
+        conv.group = 2;
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let input = ArrayD::<f32>::zeros(&[1, 3, 3, 3][..]).into_arc_tensor();
+        let err = op.eval(tvec!(input)).unwrap_err();
+        match err.kind() {
+            crate::TractErrorKind::Conv(e) => {
+                assert_eq!(
+                    e,
+                    &ConvError::ShapeMismatch { what: "input channels", expected: 4, found: 3 }
+                );
+                assert_eq!(e.to_string(), "input channels: expected 4, found 3");
+            }
+            e => panic!("expected a channel mismatch, got {:?}", e),
+        }
+    }
+
+    fn biased_identity(data_format: DataFormat, bias: Tensor) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = match data_format {
+            DataFormat::NCHW => rctensor4(&[[[[0.0f32, 1.0, 2.0]], [[3.0, 4.0, 5.0]]]]),
+            DataFormat::NHWC => rctensor4(&[[[[0.0f32, 3.0], [1.0, 4.0], [2.0, 5.0]]]]),
+        };
+        let kernel = rctensor4(&[[[[1.0f32]], [[0.0]]], [[[0.0]], [[1.0]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = Conv::default();
+        conv.data_format = data_format;
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(bias);
+        op.eval(tvec!(input))
+    }
+
+    #[test]
+    fn bias_follows_channel_axis() {
+        let nchw = biased_identity(DataFormat::NCHW, tensor1(&[10.0f32, 20.0])).unwrap();
+        assert_eq!(nchw[0], rctensor4(&[[[[10.0f32, 11.0, 12.0]], [[23.0, 24.0, 25.0]]]]));
+        let nhwc = biased_identity(DataFormat::NHWC, tensor1(&[10.0f32, 20.0])).unwrap();
+        assert_eq!(nhwc[0], rctensor4(&[[[[10.0f32, 23.0], [11.0, 24.0], [12.0, 25.0]]]]));
+        let nhwc = biased_identity(DataFormat::NHWC, tensor4(&[[[[10.0f32]], [[20.0]]]])).unwrap();
+        assert_eq!(nhwc[0], rctensor4(&[[[[10.0f32, 23.0], [11.0, 24.0], [12.0, 25.0]]]]));
+    }
+
+    #[test]
+    fn bias_shape_mismatch() {
+        let err = biased_identity(DataFormat::NHWC, tensor1(&[1.0f32, 2.0, 3.0])).unwrap_err();
+        match err.kind() {
+            crate::TractErrorKind::Conv(e) => assert_eq!(
+                e,
+                &ConvError::ShapeMismatch { what: "bias length", expected: 2, found: 3 }
+            ),
+            e => panic!("expected a bias mismatch, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn lowered_ops_share_timer() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap();
+        assert!(op.timer.shares_times_with(&im2col.timer));
+        assert!(op.timer.shares_times_with(&gemm.timer));
+        op.eval(tvec!(input)).unwrap();
+        let evals = if cfg!(feature = "conv_timing") { 1 } else { 0 };
+        assert_eq!(op.timer.times().evals, evals);
+    }
+
+    #[test]
+    fn zero_bias_is_dropped() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]);
+        let facts = |bias: Arc<Tensor>| {
+            [input.clone(), kernel.clone(), bias]
+                .iter()
+                .map(|t| TypedTensorInfo::from(t.clone()))
+                .collect::<Vec<_>>()
+        };
+        let op = Conv::default().to_unary(&*facts(rctensor1(&[0.0f32, -0.0]))).unwrap().unwrap();
+        assert!(op.bias.is_none());
+        let op = Conv::default().to_unary(&*facts(rctensor1(&[0.0f32, 1.0]))).unwrap().unwrap();
+        assert!(op.bias.is_some());
+    }
+
+    #[test]
+    fn bias_per_group() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0]], [[3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0]]], [[[0.5]]], [[[3.0]]]]);
+        let mut conv = Conv::default();
+        conv.group = 2;
+        let eval = |bias: Arc<Tensor>| {
+            let facts: Vec<TypedTensorInfo> =
+                [input.clone(), kernel.clone(), bias].iter().map(|t| t.clone().into()).collect();
+            conv.to_unary(&*facts).unwrap().unwrap().eval(tvec!(input.clone())).unwrap()
+        };
+        let per_group = eval(rctensor1(&[1.0f32, -2.0]));
+        let per_channel = eval(rctensor1(&[1.0f32, 1.0, -2.0, -2.0]));
+        assert_eq!(per_group, per_channel);
+    }
+
+    #[test]
+    fn clones_share_packed_kernels() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let clone = op.clone();
+        assert!(!op.kernel_cache.is_packed());
+        let expected = clone.eval(tvec!(input.clone())).unwrap();
+        assert!(op.kernel_cache.is_packed());
+        assert_eq!(op.eval(tvec!(input)).unwrap(), expected);
+    }
+
+    fn conv_of_constant(shape: (usize, usize, usize, usize)) -> (TypedModel, ConvUnary) {
+        let input = Array4::from_shape_fn(shape, |(_, c, y, x)| ((c + y * x) % 5) as f32);
+        let input = input.into_arc_tensor();
+        let kernel = Array4::from_shape_fn((3, shape.1, 1, 1), |(o, c, _, _)| (o * c) as f32);
+        let facts = conv_facts(input.clone(), kernel);
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let mut model = TypedModel::default();
+        model.add_const("input", input).unwrap();
+        let fact = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(&[shape.0, 3, shape.2, shape.3][..]),
+            konst: None,
+        };
+        model.chain("conv", op.clone(), tvec!(fact)).unwrap();
+        (model, op)
+    }
+
+    #[test]
+    fn conv_of_constant_is_folded() {
+        let (model, op) = conv_of_constant((1, 2, 5, 7));
+        let input = model.outlet_fact(OutletId::new(0, 0)).unwrap().konst.clone().unwrap();
+        let expected = op.eval(tvec!(input)).unwrap();
+        let model = model.declutter().unwrap();
+        assert!(model.nodes().iter().all(|n| !n.op_is::<ConvUnary>()));
+        let output = model.output_outlets().unwrap()[0];
+        assert!(model.node(output.node).op_is::<crate::ops::konst::Const>());
+        assert_eq!(model.outlet_fact(output).unwrap().konst, Some(expected[0].clone()));
+    }
+
+    #[test]
+    fn large_conv_of_constant_is_not_folded() {
+        let (model, _) = conv_of_constant((1, 1, 1, MAX_FOLDED_OUTPUT_LEN / 3 + 1));
+        let model = model.declutter().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<ConvUnary>()));
+    }
+
+    fn lowered(conv: Conv, input: Arc<Tensor>, kernel: Arc<Tensor>) -> TractResult<Vec<String>> {
+        let facts = conv_facts(input.clone(), kernel);
+        let op = conv.to_unary(&facts)?.unwrap();
+        let output = op.eval(tvec!(input.clone()))?.remove(0);
+        let mut model = TypedModel::default();
+        model.add_source("input", TypedTensorInfo::from(input))?;
+        let id = model.chain("conv", op.clone(), tvec!(TypedTensorInfo::from(output)))?;
+        let patch = op.codegen(&model, model.node(id))?.unwrap();
+        Ok(patch.nodes().iter().map(|n| n.op().name().to_string()).collect())
+    }
+
+    #[test]
+    fn forced_strategies() {
+        let input = Array4::<f32>::zeros((1, 2, 5, 5)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((2, 2, 3, 3)).into_arc_tensor();
+        let auto = lowered(Conv::default(), input.clone(), kernel.clone()).unwrap();
+        assert!(auto.iter().any(|n| n == "ConvDirect"));
+        let conv = Conv::default().with_strategy(ConvStrategy::ForceGemm);
+        let gemm = lowered(conv, input.clone(), kernel.clone()).unwrap();
+        assert!(gemm.iter().any(|n| n == "Conv::Im2col"));
+        let conv = Conv::default().with_strategy(ConvStrategy::ForceDirect);
+        let direct = lowered(conv, input.clone(), kernel.clone()).unwrap();
+        assert!(direct.iter().any(|n| n == "ConvDirect"));
+        let conv = Conv::default().with_strategy(ConvStrategy::ForceWinograd);
+        let winograd = lowered(conv, input.clone(), kernel.clone()).unwrap();
+        assert!(winograd.iter().any(|n| n == "ConvWinograd"));
+    }
+
+    #[test]
+    fn depthwise_channel_multiplier() {
+        // TensorFlow layout: [h, w, in channels, multiplier] kernel, input
+        // channel c feeding output channels 2c and 2c + 1
+        let input = Array4::from_shape_fn((1, 5, 4, 2), |(_, y, x, c)| {
+            (y * 4 + x) as f32 * if c == 0 { 1.0 } else { -0.5 }
+        });
+        let kernel = Array4::from_shape_fn((3, 3, 2, 2), |(y, x, c, m)| {
+            (y * 3 + x) as f32 * (m as f32 + 1.0) - c as f32
+        });
+        let expected = Array4::from_shape_fn((1, 3, 2, 4), |(_, y, x, o)| {
+            let (c, m) = (o / 2, o % 2);
+            let mut sum = 0.0;
+            for ky in 0..3 {
+                for kx in 0..3 {
+                    sum += input[(0, y + ky, x + kx, c)] * kernel[(ky, kx, c, m)];
+                }
+            }
+            sum
+        });
+        for c in 0..2 {
+            assert_ne!(expected[(0, 1, 1, 2 * c)], expected[(0, 1, 1, 2 * c + 1)]);
+        }
+        let conv = |strategy| {
+            Conv::new(DataFormat::NHWC, KernelFormat::HWIO, None, None, PaddingSpec::Valid, None, 2)
+                .with_strategy(strategy)
+        };
+        let input = input.into_arc_tensor();
+        let kernel = kernel.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel.clone());
+        let op = conv(ConvStrategy::Auto).to_unary(&facts).unwrap().unwrap();
+        assert_eq!(*op.eval(tvec!(input.clone())).unwrap()[0], expected.clone().into_tensor());
+        let depthwise = op.to_depth_wise::<f32>(input.shape()).unwrap();
+        let output = depthwise.as_stateless().unwrap().eval(tvec!(input.clone())).unwrap();
+        assert_eq!(*output[0], expected.into_tensor());
+        for strategy in &[ConvStrategy::Auto, ConvStrategy::ForceDepthwise] {
+            let ops = lowered(conv(*strategy), input.clone(), kernel.clone()).unwrap();
+            assert!(ops.iter().any(|n| n.starts_with("Conv::DepthWise")), "{:?}", ops);
+        }
+    }
+
+    #[test]
+    fn per_axis_strides() {
+        let input = Array4::from_shape_fn((1, 2, 7, 5), |(_, c, y, x)| (c * 35 + y * 5 + x) as f32);
+        let kernel = Array4::from_shape_fn((3, 2, 3, 2), |(o, i, y, x)| {
+            (o + i) as f32 - (y * 2 + x) as f32 * 0.5
+        });
+        let mut conv = Conv::default();
+        conv.strides = Some(tvec![2, 1]);
+        conv.dilations = Some(tvec![1, 2]);
+        conv.padding = PaddingSpec::Explicit(tvec![1, 0], tvec![1, 1]);
+        let facts = conv_facts(input.clone(), kernel.clone());
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let output = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap().remove(0);
+        let expected = Array4::from_shape_fn((1, 3, 4, 4), |(_, o, y, x)| {
+            let mut sum = 0.0;
+            for i in 0..2 {
+                for ky in 0..3 {
+                    for kx in 0..2 {
+                        let iy = (y * 2 + ky) as isize - 1;
+                        let ix = x + kx * 2;
+                        if iy >= 0 && iy < 7 && ix < 5 {
+                            sum += input[(0, i, iy as usize, ix)] * kernel[(o, i, ky, kx)];
+                        }
+                    }
+                }
+            }
+            sum
+        });
+        assert_eq!(*output, expected.into_tensor());
+    }
+
+    #[test]
+    fn asymmetric_pads_with_dilation() {
+        // ONNX pads [top=1, left=1, bottom=0, right=0] and dilations [2, 2]:
+        // the kernel field is 5 wide, and the last output of each axis sits
+        // flush against the unpadded bottom and right edges
+        let input = Array4::from_shape_fn((1, 2, 7, 6), |(_, c, y, x)| {
+            ((c * 42 + y * 6 + x) % 13) as f32 - 6.0
+        });
+        let kernel = Array4::from_shape_fn((3, 2, 3, 3), |(o, i, y, x)| {
+            ((o * 18 + i * 9 + y * 3 + x) % 5) as f32 - 2.0
+        });
+        for &stride in &[1usize, 2] {
+            // floor((in + pad_begin + pad_end - dilation * (k - 1) - 1) / stride) + 1
+            let (oh, ow) = ((7 + 1 - 5) / stride + 1, (6 + 1 - 5) / stride + 1);
+            let expected = Array4::from_shape_fn((1, 3, oh, ow), |(_, o, y, x)| {
+                let mut sum = 0.0;
+                for i in 0..2 {
+                    for ky in 0..3 {
+                        for kx in 0..3 {
+                            let iy = (y * stride + ky * 2) as isize - 1;
+                            let ix = (x * stride + kx * 2) as isize - 1;
+                            if iy >= 0 && iy < 7 && ix >= 0 && ix < 6 {
+                                sum += input[(0, i, iy as usize, ix as usize)]
+                                    * kernel[(o, i, ky, kx)];
+                            }
+                        }
+                    }
+                }
+                sum
+            })
+            .into_tensor();
+            let mut conv = Conv::default();
+            conv.strides = Some(tvec![stride, stride]);
+            conv.dilations = Some(tvec![2, 2]);
+            conv.padding = PaddingSpec::Explicit(tvec![1, 1], tvec![0, 0]);
+            let input = input.clone().into_arc_tensor();
+            let facts = conv_facts(input.clone(), kernel.clone());
+            let op = conv.to_unary(&facts).unwrap().unwrap();
+            let output = op.eval(tvec!(input.clone())).unwrap().remove(0);
+            assert_eq!(*output, expected);
+
+            let mut model = TypedModel::default();
+            model.add_source("input", TypedTensorInfo::from(input.clone())).unwrap();
+            model.chain("conv", op, tvec!(TypedTensorInfo::from(output))).unwrap();
+            let model = model.into_optimized().unwrap();
+            let plan = SimplePlan::new(&model).unwrap();
+            let output = plan.run(tvec!(input.into_tensor())).unwrap().remove(0);
+            assert_eq!(*output, expected);
+        }
+    }
+
+    #[test]
+    fn direct_only_on_small_feature_maps() {
+        let kernel = Array4::<f32>::zeros((64, 32, 3, 3)).into_arc_tensor();
+        let unary = |input_shape: (usize, usize, usize, usize)| {
+            let input = Array4::<f32>::zeros(input_shape).into_arc_tensor();
+            let facts = conv_facts(input, kernel.clone());
+            Conv::default().to_unary(&facts).unwrap().unwrap()
+        };
+        assert!(unary((1, 32, 3, 3)).prefers_direct(&[1, 32, 3, 3]));
+        assert!(!unary((1, 32, 32, 32)).prefers_direct(&[1, 32, 32, 32]));
+        let input = Array4::<f32>::zeros((1, 32, 32, 32)).into_arc_tensor();
+        let auto = lowered(Conv::default(), input, kernel.clone()).unwrap();
+        assert!(auto.iter().any(|n| n == "Conv::Im2col"));
+    }
+
+    #[test]
+    fn arithmetic_intensity_of_grouped_conv() {
+        let input = Array4::<f32>::zeros((2, 8, 10, 10)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((12, 4, 3, 3));
+        let mut conv = Conv::default();
+        conv.group = 2;
+        let facts = conv_facts(input.clone(), kernel);
+        let unary = conv.to_unary(&facts).unwrap().unwrap();
+        let intensity = unary.arithmetic_intensity(&facts[0]).unwrap();
+        // 2 groups of 6 output channels, 4 input channels by 3x3, over 2x8x8 points
+        assert_eq!(intensity.flops, 2 * 2 * 6 * 36 * 128);
+        assert_eq!(intensity.bytes, 4 * (2 * 8 * 100 + 12 * 36 + 2 * 12 * 64));
+        let cost = unary.cost(&[&facts[0]]).unwrap();
+        assert_eq!(cost[0].1, (intensity.flops as usize / 2).to_dim());
+    }
+
+    #[test]
+    fn inapplicable_forced_strategies() {
+        let input = Array4::<f32>::zeros((1, 2, 5, 5)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((2, 2, 3, 3)).into_arc_tensor();
+        for strategy in &[ConvStrategy::ForceDepthwise, ConvStrategy::ForceWinograd] {
+            let mut conv = Conv::default().with_strategy(*strategy);
+            conv.strides = Some(tvec![2, 2]);
+            let err = lowered(conv, input.clone(), kernel.clone()).unwrap_err();
+            match err.kind() {
+                crate::TractErrorKind::Conv(ConvError::UnsupportedStrategy {
+                    strategy: s, ..
+                }) => {
+                    assert_eq!(s, strategy)
+                }
+                e => panic!("expected an unsupported strategy error, got {:?}", e),
+            }
+        }
+        let mut conv = Conv::default().with_strategy(ConvStrategy::ForceDirect);
+        conv.padding = PaddingSpec::SameUpper;
+        assert!(lowered(conv, input, kernel).is_err());
+    }
+
+    #[test]
+    fn channel_blocked_round_trip_matches_nchw() {
+        let input = Array4::from_shape_fn((2, 5, 7, 6), |(n, c, y, x)| {
+            ((n * 31 + c * 7 + y * 5 + x * 3) % 11) as f32 - 5.0
+        });
+        let kernel = Array4::from_shape_fn((6, 5, 3, 3), |(o, i, y, x)| {
+            ((o * 13 + i * 5 + y * 3 + x) % 7) as f32 * 0.25 - 0.75
+        });
+        let mut conv = Conv::default();
+        conv.strides = Some(tvec![2, 1]);
+        conv.padding = PaddingSpec::SameUpper;
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(arr1(&[0.5f32, -1.0, 2.0, 0.0, 1.5, -0.25]).into_tensor());
+        let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        let blocked = op.to_channel_blocked::<f32>(input.shape(), 4).unwrap();
+        let blocks = ToChannelBlocks::new(4).eval(tvec!(input.clone())).unwrap();
+        let blocks = blocked.as_stateless().unwrap().eval(blocks).unwrap();
+        let lanes = blocks[0].to_array_view::<f32>().unwrap();
+        assert_eq!(lanes.shape(), &[2, 2, 4, 6, 4]);
+        assert!(lanes.slice(s![.., 1, .., .., 2..]).iter().all(|&x| x == 0.0));
+        let output = FromChannelBlocks::new(4, 6).eval(blocks).unwrap().remove(0);
+        assert_close!(*output, *expected);
+
+        let mut conv = Conv::default().with_strategy(ConvStrategy::ForceChannelBlocked(4));
+        conv.padding = PaddingSpec::SameUpper;
+        let kernel = Array4::from_elem((6, 5, 3, 3), 0.5f32).into_arc_tensor();
+        let ops = lowered(conv.clone(), input.clone(), kernel.clone()).unwrap();
+        assert_eq!(ops[1..], ["ToChannelBlocks", "ChannelBlockedConv", "FromChannelBlocks"]);
+        conv.data_format = DataFormat::NHWC;
+        conv.kernel_fmt = KernelFormat::HWIO;
+        let kernel = Array4::from_elem((3, 3, 6, 5), 0.5f32).into_arc_tensor();
+        assert!(lowered(conv, input, kernel).is_err());
+    }
+
+    fn kernel_as_b(conv: Conv, input: Array4<f64>, kernel: Array4<f64>, expected: Array4<f64>) {
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let (_, _, gemm) = op.to_im2col_pair::<f64>(input.shape()).unwrap();
+        assert!(gemm.info().unwrap().iter().any(|i| i.contains("kernel as B")));
+        let output = op.eval(tvec!(input)).unwrap().remove(0);
+        assert_close!(*output, expected.into_tensor());
+    }
+
+    #[test]
+    fn kernel_as_b_matches_reference() {
+        // m=2, k=27, n=16: padding m to 4 rows wastes more than padding it
+        // to 2 columns on the B side.
+        assert!(f64::packed_mat_mul(2, 27, 16).prefer_transposed());
+        let input = Array4::from_shape_fn((1, 3, 6, 6), |(_, c, y, x)| (c * 36 + y * 6 + x) as f64);
+        let kernel =
+            Array4::from_shape_fn((2, 3, 3, 3), |(o, c, y, x)| (o * 27 + c * 9 + y * 3 + x) as f64);
+        let expected = Array4::from_shape_fn((1, 2, 4, 4), |(_, o, y, x)| {
+            let mut sum = 0.0;
+            for c in 0..3 {
+                for ky in 0..3 {
+                    for kx in 0..3 {
+                        sum += input[(0, c, y + ky, x + kx)] * kernel[(o, c, ky, kx)];
+                    }
+                }
+            }
+            sum
+        });
+        kernel_as_b(Conv::default(), input.clone(), kernel.clone(), expected.clone());
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            None,
+            None,
+            PaddingSpec::Valid,
+            None,
+            1,
+        );
+        kernel_as_b(
+            conv,
+            input.permuted_axes([0, 2, 3, 1]),
+            kernel.permuted_axes([2, 3, 1, 0]),
+            expected.permuted_axes([0, 2, 3, 1]),
+        );
+    }
+
+    #[test]
+    fn grouped_batched_gemm_matches_reference() {
+        let input = Array4::from_shape_fn((2, 4, 3, 3), |(n, c, y, x)| {
+            (n * 36 + c * 9 + y * 3 + x) as f32 - 30.0
+        });
+        let kernel =
+            Array4::from_shape_fn((4, 2, 2, 2), |(o, c, y, x)| (o * 8 + c * 4 + y * 2 + x) as f32);
+        let expected = Array4::from_shape_fn((2, 4, 2, 2), |(n, o, y, x)| {
+            let g = o / 2;
+            let mut sum = 0.0;
+            for c in 0..2 {
+                for ky in 0..2 {
+                    for kx in 0..2 {
+                        sum += input[(n, g * 2 + c, y + ky, x + kx)] * kernel[(o, c, ky, kx)];
+                    }
+                }
+            }
+            sum
+        });
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = Conv::default().with_strategy(ConvStrategy::ForceGemm);
+        conv.group = 2;
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let first = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(*first, expected.into_tensor());
+        assert_eq!(first, op.eval(tvec!(input)).unwrap().remove(0));
+    }
+
+    fn split_across_threads(conv: Conv, input: Array4<f32>, kernel: Array4<f32>) {
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap();
+        let packed = im2col.eval(tvec!(input)).unwrap().remove(0);
+        let packed = packed.to_array_view::<f32>().unwrap().into_dimensionality().unwrap();
+        let mut c_panel = vec![0.0; gemm.mm.c_panel_len()];
+        let mut serial = gemm.clone();
+        serial.threads = 1;
+        let (expected, _) = serial.conv_gemm(&packed, None, &mut c_panel).unwrap();
+        for threads in 2..6 {
+            let mut split = gemm.clone();
+            split.threads = threads;
+            split.panel_pool = Arc::new(PanelPool::new());
+            assert!(split.info().unwrap().unwrap().contains("threads"));
+            let (found, _) = split.conv_gemm(&packed, None, &mut c_panel).unwrap();
+            assert_eq!(found, expected);
+            // at most one panel per extra thread, reused by the next
+            // evaluations: how many depends on how the threads overlap
+            assert!(split.panel_pool.idle() < threads);
+            for _ in 0..3 {
+                split.conv_gemm(&packed, None, &mut c_panel).unwrap();
+                assert!(split.panel_pool.idle() < threads);
+            }
+        }
+    }
+
+    fn provided_kernel_evals(cache: Option<KernelCache>) -> usize {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let input = Array4::from_shape_fn((1, 3, 7, 7), |(_, c, y, x)| (c * 49 + y * 7 + x) as f32)
+            .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((4, 3, 3, 3), |(o, c, y, x)| {
+            ((o * 27 + c * 9 + y * 3 + x) % 7) as f32 - 3.0
+        });
+        let facts = conv_facts(input.clone(), kernel.clone());
+        let op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap();
+        let packed = im2col.eval(tvec!(input)).unwrap().remove(0);
+        let packed = packed.to_array_view::<f32>().unwrap().into_dimensionality().unwrap();
+        let mut c_panel = vec![0.0; gemm.mm.c_panel_len()];
+        let (expected, _) = gemm.conv_gemm(&packed, None, &mut c_panel).unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let kernel = kernel.into_shape((4, 27)).unwrap();
+        let provider: KernelProvider<f32> = Arc::new(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            kernel.clone()
+        });
+        let lazy = gemm.clone().with_kernel_provider(provider, cache);
+        assert!(lazy.packed_kernels.is_empty());
+        for _ in 0..2 {
+            let (found, _) = lazy.conv_gemm(&packed, None, &mut c_panel).unwrap();
+            assert_eq!(found, expected);
+        }
+        calls.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn group_sink_streams_groups_in_order() {
+        use std::sync::Mutex;
+        let input = Array4::from_shape_fn((2, 4, 6, 5), |(n, c, y, x)| {
+            ((n * 17 + c * 11 + y * 5 + x) % 9) as f32 - 4.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((6, 2, 3, 3), |(o, i, y, x)| {
+            ((o * 18 + i * 9 + y * 3 + x) % 7) as f32 - 3.0
+        });
+        let facts = conv_facts(input.clone(), kernel);
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::Valid,
+            None,
+            2,
+        );
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(tensor1(&[1.0f32, -2.0, 0.5, 3.0, 0.0, -1.5]));
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap();
+        let packed = im2col.eval(tvec!(input)).unwrap().remove(0);
+        let packed = packed.to_array_view::<f32>().unwrap().into_dimensionality().unwrap();
+        let mut c_panel = vec![0.0; gemm.mm.c_panel_len()];
+        let (expected, _) = gemm.conv_gemm(&packed, None, &mut c_panel).unwrap();
+        for &group_threads in &[1, 2] {
+            let blocks = Arc::new(Mutex::new(vec![]));
+            let sink_blocks = blocks.clone();
+            let sink: GroupSink<f32> = Arc::new(move |i, g, block: ArrayViewD<f32>| {
+                sink_blocks.lock().unwrap().push((i, g, block.to_owned()));
+            });
+            let mut streamed = gemm.clone().with_group_sink(sink);
+            streamed.group_threads = group_threads;
+            let (output, _) = streamed.conv_gemm(&packed, None, &mut c_panel).unwrap();
+            assert_eq!(output, expected);
+            let blocks = blocks.lock().unwrap();
+            let order: Vec<_> = blocks.iter().map(|(i, g, _)| (*i, *g)).collect();
+            assert_eq!(order, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+            for (i, g, block) in blocks.iter() {
+                let slice = expected.slice(s![*i..*i + 1, g * 3..(g + 1) * 3, .., ..]);
+                assert_eq!(block.view(), slice.into_dyn());
+            }
+        }
+    }
+
+    #[test]
+    fn kernel_provider_called_at_each_eval() {
+        assert_eq!(provided_kernel_evals(None), 2);
+    }
+
+    #[test]
+    fn cached_kernel_provider_called_once() {
+        assert_eq!(provided_kernel_evals(Some(KernelCache::default())), 1);
+    }
+
+    #[test]
+    fn f64_output_keeps_bias_precision() {
+        let input = rctensor4(&[[[[1.0f32, 2.0]], [[3.0, 4.0]]]]);
+        let kernel = Array4::from_shape_vec((2, 2, 1, 1), vec![1.0f32, 0.0, 0.0, 1.0]).unwrap();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        // 2^24 + 1 is not a f32
+        op.bias = Some(tensor1(&[16777216.0f32, 0.5]));
+        let narrow = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(narrow, rctensor4(&[[[[16777216.0f32, 16777218.0]], [[3.5, 4.5]]]]));
+        op.f64_output = true;
+        let wide = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        assert_eq!(wide, rctensor4(&[[[[16777217.0f64, 16777218.0]], [[3.5, 4.5]]]]));
+
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 2, 1, 2))).unwrap();
+        model.chain_default("conv", op).unwrap();
+        let model = model.into_typed().unwrap();
+        assert_eq!(model.output_fact(0).unwrap().datum_type, DatumType::F64);
+        let model = model.into_optimized().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<MatMat<f32>>()));
+        let found = SimplePlan::new(&model).unwrap().run(tvec!(input.into_tensor())).unwrap();
+        assert_eq!(found[0], wide);
+    }
+
+    #[test]
+    fn optimizer_reports_conv_fusions() {
+        let input = Array4::<f32>::zeros((1, 2, 4, 4)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((3, 2, 3, 3));
+        let facts = conv_facts(input, kernel);
+        let mut op = Conv::default().to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(tensor1(&[1.0f32, 2.0, 3.0]));
+        let mut model = InferenceModel::default();
+        model.add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 2, 4, 4))).unwrap();
+        model.chain_default("conv", op).unwrap();
+        model.chain_default("pool", GlobalAvgPool::default()).unwrap();
+        let (_, events) = model.into_typed().unwrap().into_optimized_reporting().unwrap();
+        let event = |transformation: &str| FusionEvent {
+            node: "conv".to_string(),
+            op: "ConvUnary".to_string(),
+            transformation: transformation.to_string(),
+        };
+        assert_eq!(
+            events,
+            vec![event("fused global pool"), event("lowered to im2col and matrix product")]
+        );
+    }
+
+    #[test]
+    fn deterministic_conv_runs_serially() {
+        let input = Array4::<f32>::zeros((1, 3, 256, 256)).into_arc_tensor();
+        let kernel = Array4::<f32>::zeros((16, 3, 3, 3));
+        let facts = conv_facts(input.clone(), kernel);
+        let conv = Conv::default().with_deterministic(true);
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        assert!(op.options.deterministic);
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        assert_eq!(im2col.threads, 1);
+        assert_eq!(gemm.downcast_ref::<MatMat<f32>>().unwrap().threads, 1);
+    }
+
+    #[test]
+    fn spatial_split_matches_serial_product() {
+        let input = Array4::from_shape_fn((1, 3, 19, 23), |(_, c, y, x)| {
+            ((c * 437 + y * 23 + x) % 13) as f32 - 6.0
+        });
+        // 16 output channels keep the kernel as A, 2 put it on the B side
+        for &o in &[16, 2] {
+            let kernel = Array4::from_shape_fn((o, 3, 3, 3), |(o, c, y, x)| {
+                ((o + c * 9 + y * 3 + x) % 5) as f32 - 2.0
+            });
+            split_across_threads(Conv::default(), input.clone(), kernel.clone());
+            let conv = Conv::new(
+                DataFormat::NHWC,
+                KernelFormat::HWIO,
+                None,
+                None,
+                PaddingSpec::SameUpper,
+                None,
+                1,
+            );
+            split_across_threads(
+                conv,
+                input.clone().permuted_axes([0, 2, 3, 1]),
+                kernel.permuted_axes([2, 3, 1, 0]),
+            );
+        }
+    }
+
+    fn gather_across_threads(conv: Conv, input: Array4<f32>, kernel: Array4<f32>) {
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let (mut im2col, _, _) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        // the padding columns of the last panel are left uninitialized
+        let (k, n, nr, len) =
+            (im2col.k, im2col.n, im2col.b_pack.panel_width(), im2col.b_pack.len());
+        let columns = |packed: Arc<Tensor>| -> Vec<f32> {
+            let packed = packed.as_slice::<f32>().unwrap();
+            packed
+                .chunks(len)
+                .flat_map(|pack| {
+                    (0..k).flat_map(move |row| {
+                        (0..n).map(move |col| pack[col / nr * nr * k + row * nr + col % nr])
+                    })
+                })
+                .collect()
+        };
+        im2col.threads = 1;
+        let expected = columns(im2col.eval(tvec!(input.clone())).unwrap().remove(0));
+        for threads in 2..6 {
+            im2col.threads = threads;
+            assert!(im2col.info().unwrap().unwrap().contains("threads"));
+            let found = columns(im2col.eval(tvec!(input.clone())).unwrap().remove(0));
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn threaded_im2col_matches_serial_gather() {
+        let input = Array4::from_shape_fn((2, 4, 19, 23), |(n, c, y, x)| {
+            ((n * 1748 + c * 437 + y * 23 + x) % 13) as f32 - 6.0
+        });
+        let weight =
+            |o: usize, c: usize, y: usize, x: usize| ((o + c * 9 + y * 3 + x) % 5) as f32 - 2.0;
+        let kernel = Array4::from_shape_fn((4, 2, 3, 3), |(o, c, y, x)| weight(o, c, y, x));
+        let mut conv = Conv::default();
+        conv.group = 2;
+        gather_across_threads(conv, input.clone(), kernel);
+        // HWIO kernels store all the input channels, and output channels per group
+        let kernel =
+            Array4::from_shape_fn((3, 3, 4, 2), |(y, x, c, o)| weight(c / 2 * 2 + o, c % 2, y, x));
+        // padding columns are zero, whichever thread gathers them
+        for &strides in &[1, 2] {
+            let conv = Conv::new(
+                DataFormat::NHWC,
+                KernelFormat::HWIO,
+                None,
+                None,
+                PaddingSpec::SameUpper,
+                Some(tvec!(strides, strides)),
+                2,
+            );
+            gather_across_threads(conv, input.clone().permuted_axes([0, 2, 3, 1]), kernel.clone());
+        }
+    }
+
+    #[test]
+    fn groups_across_threads_match_serial_products() {
+        // ShuffleNet style: 8 groups of 4 channels, a few products per image
+        let input = Array4::from_shape_fn((2, 32, 9, 11), |(n, c, y, x)| {
+            ((n * 3168 + c * 99 + y * 11 + x) % 13) as f32 - 6.0
+        })
+        .into_arc_tensor();
+        let kernel = Array4::from_shape_fn((32, 4, 3, 3), |(o, c, y, x)| {
+            ((o + c * 9 + y * 3 + x) % 5) as f32 - 2.0
+        });
+        let facts = conv_facts(input.clone(), kernel);
+        let mut conv = Conv::default();
+        conv.group = 8;
+        conv.padding = PaddingSpec::SameUpper;
+        let op = conv.clone().to_unary(&facts).unwrap().unwrap();
+        let (im2col, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        let gemm = gemm.downcast_ref::<MatMat<f32>>().unwrap();
+        let packed = im2col.eval(tvec!(input.clone())).unwrap().remove(0);
+        let packed = packed.to_array_view::<f32>().unwrap().into_dimensionality().unwrap();
+        let mut c_panel = vec![0.0; gemm.mm.c_panel_len()];
+        let mut serial = gemm.clone();
+        serial.threads = 1;
+        serial.group_threads = 1;
+        let (expected, _) = serial.conv_gemm(&packed, None, &mut c_panel).unwrap();
+        for threads in 2..10 {
+            let mut split = serial.clone();
+            split.group_threads = threads;
+            split.panel_pool = Arc::new(PanelPool::new());
+            assert!(split.info().unwrap().unwrap().contains("group threads"));
+            let (found, _) = split.conv_gemm(&packed, None, &mut c_panel).unwrap();
+            assert_eq!(found, expected);
+        }
+        let op = conv.with_deterministic(true).to_unary(&facts).unwrap().unwrap();
+        let (_, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+        assert_eq!(gemm.downcast_ref::<MatMat<f32>>().unwrap().group_threads, 1);
+    }
+
+    #[test]
+    fn interleaved_kernel_groups() {
+        let input = Array4::from_shape_fn((1, 4, 3, 3), |(_, c, y, x)| (c * 9 + y * 3 + x) as f32)
+            .into_arc_tensor();
+        let kernel =
+            Array4::from_shape_fn((4, 2, 2, 2), |(o, c, y, x)| (o * 8 + c * 4 + y * 2 + x) as f32);
+        // output channel j of group g at row j * 2 + g
+        let interleaved = kernel.select(Axis(0), &[0, 2, 1, 3]);
+        let run = |kernel: Array4<f32>, layout| {
+            let facts = conv_facts(input.clone(), kernel);
+            let mut conv = Conv::default().with_kernel_group_layout(layout);
+            conv.group = 2;
+            conv.to_unary(&facts).unwrap().unwrap().eval(tvec!(input.clone())).unwrap().remove(0)
+        };
+        assert_eq!(
+            run(kernel, KernelGroupLayout::Contiguous),
+            run(interleaved, KernelGroupLayout::Interleaved)
+        );
+    }
+
+    #[test]
+    fn channel_blocked_kernel_matches_product_packing() {
+        for &data_format in &[DataFormat::NCHW, DataFormat::NHWC] {
+            let input = Array4::from_shape_fn((2, 6, 5, 4), |(n, c, y, x)| {
+                ((n * 31 + c * 20 + y * 4 + x) % 11) as f32 - 5.0
+            });
+            let input = match data_format {
+                DataFormat::NCHW => input,
+                DataFormat::NHWC => input.permuted_axes([0, 2, 3, 1]).to_owned(),
+            }
+            .into_arc_tensor();
+            // 5 channels per group: the second block of 4 is padded
+            let kernel = Array4::from_shape_fn((10, 3, 3, 3), |(o, c, y, x)| {
+                ((o * 27 + c * 9 + y * 3 + x) % 13) as f32 / 4.0 - 1.5
+            });
+            let run = |packing| {
+                let facts = conv_facts(input.clone(), kernel.clone());
+                let mut conv = Conv::default().with_kernel_packing(packing);
+                conv.data_format = data_format;
+                conv.padding = PaddingSpec::SameUpper;
+                conv.group = 2;
+                let mut op = conv.to_unary(&facts).unwrap().unwrap();
+                op.bias = Some(Array1::from_shape_fn(10, |c| c as f32 - 4.5).into_tensor());
+                let (_, _, gemm) = op.to_im2col_pair::<f32>(input.shape()).unwrap();
+                assert_eq!(
+                    gemm.name(),
+                    if packing == KernelPacking::Product { "MatMat" } else { "BlockedMatMat" }
+                );
+                op.eval(tvec!(input.clone())).unwrap().remove(0)
+            };
+            let expected = run(KernelPacking::Product);
+            let found = run(KernelPacking::ChannelBlocks(4));
+            assert!(found.close_enough(&expected, true), "{:?}", data_format);
+        }
+    }
+
+    #[test]
+    fn single_image_matches_batch() {
+        let batch = Array4::from_shape_fn((2, 6, 5, 4), |(n, c, y, x)| {
+            ((n * 31 + c * 20 + y * 4 + x) % 11) as f32 - 5.0
+        });
+        let kernel = Array4::from_shape_fn((9, 2, 3, 3), |(o, c, y, x)| {
+            ((o * 18 + c * 9 + y * 3 + x) % 7) as f32 - 3.0
+        });
+        let run = |input: Array4<f32>| {
+            let input = input.into_arc_tensor();
+            let facts = conv_facts(input.clone(), kernel.clone());
+            let mut conv = Conv::default();
+            conv.group = 3;
+            conv.padding = PaddingSpec::SameUpper;
+            conv.to_unary(&facts).unwrap().unwrap().eval(tvec!(input)).unwrap().remove(0)
+        };
+        let batched = run(batch.clone());
+        let batched = batched.to_array_view::<f32>().unwrap();
+        for i in 0..2 {
+            let single = run(batch.slice(s![i..=i, .., .., ..]).to_owned());
+            assert_eq!(
+                single.to_array_view::<f32>().unwrap(),
+                batched.slice(s![i..=i, .., .., ..]).into_dyn()
+            );
+        }
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    struct Geometry {
+        n: usize,
+        ci: usize,
+        hw: (usize, usize),
+        co: usize,
+        k: (usize, usize),
+        group: usize,
+        strides: (usize, usize),
+        dilations: (usize, usize),
+        // before (h, w), after (h, w)
+        pads: [usize; 4],
+    }
+
+    fn value(i: usize) -> f32 {
+        ((i * 7919) % 13) as f32 / 4.0 - 1.5
+    }
+
+    /// NCHW output of a conv with an OIHW kernel, from windows of the
+    /// zero-padded input.
+    fn reference_conv(g: &Geometry, input: &Array4<f32>, kernel: &Array4<f32>) -> Array4<f32> {
+        let (h, w) = (g.hw.0 + g.pads[0] + g.pads[2], g.hw.1 + g.pads[1] + g.pads[3]);
+        let mut padded = Array4::<f32>::zeros((g.n, g.ci, h, w));
+        padded
+            .slice_mut(s![.., .., g.pads[0]..h - g.pads[2], g.pads[1]..w - g.pads[3]])
+            .assign(input);
+        let field = |len: usize, k: usize, d: usize, s: usize| (len - (k - 1) * d - 1) / s + 1;
+        let oh = field(h, g.k.0, g.dilations.0, g.strides.0);
+        let ow = field(w, g.k.1, g.dilations.1, g.strides.1);
+        let (ipg, opg) = (g.ci / g.group, g.co / g.group);
+        Array4::from_shape_fn((g.n, g.co, oh, ow), |(n, o, y, x)| {
+            let (y, x) = (y * g.strides.0, x * g.strides.1);
+            let c = o / opg * ipg;
+            let window = padded.slice(s![
+                n,
+                c..c + ipg,
+                y..y + (g.k.0 - 1) * g.dilations.0 + 1;g.dilations.0,
+                x..x + (g.k.1 - 1) * g.dilations.1 + 1;g.dilations.1
+            ]);
+            let window: Vec<f32> = window.iter().cloned().collect();
+            let weights: Vec<f32> = kernel.slice(s![o, .., .., ..]).iter().cloned().collect();
+            Array1::from_vec(window).dot(&Array1::from_vec(weights))
+        })
+    }
+
+    fn check_against_reference(g: Geometry, data_format: DataFormat) {
+        check_with_bias(g, data_format, None)
+    }
+
+    /// `bias` holds one value per output channel, or one per group.
+    fn check_with_bias(g: Geometry, data_format: DataFormat, bias: Option<&[f32]>) {
+        let input = Array4::from_shape_fn((g.n, g.ci, g.hw.0, g.hw.1), |(n, c, y, x)| {
+            value(((n * g.ci + c) * g.hw.0 + y) * g.hw.1 + x)
+        });
+        let kernel = Array4::from_shape_fn((g.co, g.ci / g.group, g.k.0, g.k.1), |(o, c, y, x)| {
+            value(((o * g.ci + c) * g.k.0 + y) * g.k.1 + x + 5)
+        });
+        let mut expected = reference_conv(&g, &input, &kernel);
+        if let Some(bias) = bias {
+            let per = g.co / bias.len();
+            for ((_, o, _, _), e) in expected.indexed_iter_mut() {
+                *e += bias[o / per];
+            }
+        }
+        let (input, kernel, expected, kernel_fmt) = match data_format {
+            DataFormat::NCHW => (input, kernel, expected, KernelFormat::OIHW),
+            DataFormat::NHWC => {
+                // HWIO kernels hold all input channels, and outputs per group
+                let (ipg, opg) = (g.ci / g.group, g.co / g.group);
+                let hwio = Array4::from_shape_fn((g.k.0, g.k.1, g.ci, opg), |(y, x, c, o)| {
+                    kernel[(c / ipg * opg + o, c % ipg, y, x)]
+                });
+                let nhwc = |a: Array4<f32>| a.permuted_axes([0, 2, 3, 1]);
+                (nhwc(input), hwio, nhwc(expected), KernelFormat::HWIO)
+            }
+        };
+        let conv = Conv::new(
+            data_format,
+            kernel_fmt,
+            Some(tvec!(g.dilations.0, g.dilations.1)),
+            None,
+            PaddingSpec::Explicit(tvec!(g.pads[0], g.pads[1]), tvec!(g.pads[2], g.pads[3])),
+            Some(tvec!(g.strides.0, g.strides.1)),
+            g.group,
+        );
+        let input = input.into_arc_tensor();
+        let mut facts = conv_facts(input.clone(), kernel).to_vec();
+        facts.extend(bias.map(|b| TypedTensorInfo::from(tensor1(b))));
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        let found = op.eval(tvec!(input)).unwrap().remove(0);
+        let found = found.to_array_view::<f32>().unwrap();
+        assert_eq!(found.shape(), expected.shape(), "{:?} {:?}", data_format, g);
+        for (ix, e) in expected.indexed_iter() {
+            let f = found[IxDyn(&[ix.0, ix.1, ix.2, ix.3])];
+            if (f - e).abs() > 1e-4 * (1.0 + e.abs()) {
+                panic!("{:?} {:?}: at {:?} expected {} found {}", data_format, g, ix, e, f);
+            }
+        }
+    }
+
+    #[test]
+    fn conv_matches_ndarray_reference() {
+        let base = Geometry {
+            n: 1,
+            ci: 3,
+            hw: (5, 6),
+            co: 4,
+            k: (3, 3),
+            group: 1,
+            strides: (1, 1),
+            dilations: (1, 1),
+            pads: [0; 4],
+        };
+        let geometries = [
+            base,
+            Geometry { n: 2, ..base },
+            Geometry { co: 1, ..base },
+            Geometry { k: (1, 1), ..base },
+            Geometry { k: (2, 3), strides: (2, 1), ..base },
+            Geometry { dilations: (2, 1), ..base },
+            Geometry { pads: [1, 2, 0, 1], ..base },
+            Geometry { ci: 4, co: 6, group: 2, ..base },
+            Geometry { n: 2, ci: 4, co: 4, group: 4, pads: [1; 4], ..base },
+            Geometry { ci: 2, co: 6, group: 2, strides: (2, 2), ..base },
+            Geometry { ci: 8, hw: (9, 9), co: 16, ..base },
+        ];
+        for g in &geometries {
+            check_against_reference(*g, DataFormat::NCHW);
+            check_against_reference(*g, DataFormat::NHWC);
+        }
+    }
+
+    #[test]
+    fn grouped_conv_bias_in_both_kernel_formats() {
+        let g = Geometry {
+            n: 2,
+            ci: 6,
+            hw: (4, 5),
+            co: 9,
+            k: (2, 3),
+            group: 3,
+            strides: (1, 2),
+            dilations: (1, 1),
+            pads: [1, 0, 0, 1],
+        };
+        let per_channel: Vec<f32> = (0..9).map(|c| c as f32 - 4.0).collect();
+        for bias in &[None, Some(&[0.5f32, -1.0, 2.0][..]), Some(&*per_channel)] {
+            check_with_bias(g, DataFormat::NCHW, *bias);
+            check_with_bias(g, DataFormat::NHWC, *bias);
+        }
+    }
+
+    #[test]
+    fn stride_larger_than_kernel() {
+        let base = Geometry {
+            n: 1,
+            ci: 2,
+            hw: (11, 13),
+            co: 3,
+            k: (3, 3),
+            group: 1,
+            strides: (4, 4),
+            dilations: (1, 1),
+            pads: [0; 4],
+        };
+        let geometries = [
+            base,
+            Geometry { pads: [1, 2, 2, 1], ..base },
+            // first and last output rows only see padding
+            Geometry { hw: (5, 13), pads: [4, 0, 8, 0], ..base },
+            Geometry { ci: 4, co: 4, group: 4, ..base },
+        ];
+        for g in &geometries {
+            check_against_reference(*g, DataFormat::NCHW);
+            check_against_reference(*g, DataFormat::NHWC);
+        }
+    }
+
+    #[test]
+    fn same_upper_and_same_lower_diverge() {
+        let input = rctensor4(&[[[[1.0f32, 2.0, 3.0]]]]);
+        let kernel = rctensor4(&[[[[1.0f32, 10.0]]]]);
+        let facts = conv_facts(input.clone(), kernel);
+        let run = |padding| {
+            let mut conv = Conv::default();
+            conv.padding = padding;
+            conv.to_unary(&facts).unwrap().unwrap().eval(tvec!(input.clone())).unwrap().remove(0)
+        };
+        assert_eq!(*run(PaddingSpec::SameUpper), tensor4(&[[[[21.0f32, 32.0, 3.0]]]]));
+        assert_eq!(*run(PaddingSpec::SameLower), tensor4(&[[[[10.0f32, 21.0, 32.0]]]]));
+    }
+
+    #[test]
+    fn summary_avg_matches_global_avg_pool() {
+        let mut outputs = conv_with_summary(ChannelSummary::Avg);
+        let pooled = GlobalAvgPool::default().eval(tvec!(outputs.remove(0))).unwrap();
+        assert_close!(pooled[0], outputs[0]);
+    }
+
+    #[test]
+    fn summary_max_matches_global_max_pool() {
+        let mut outputs = conv_with_summary(ChannelSummary::Max);
+        let pooled = GlobalMaxPool::default().eval(tvec!(outputs.remove(0))).unwrap();
+        assert_eq!(pooled[0], outputs[0]);
+    }
+
+    #[test]
+    fn packed_lowering_matches_unlowered() {
+        let input = Array4::from_shape_fn((1, 3, 6, 5), |(_, c, y, x)| {
+            ((c * 30 + y * 5 + x) % 11) as f32 - 5.0
+        })
+        .into_arc_tensor();
+        // one output channel goes through VecMat, more through MatMat
+        for &channels in &[1, 4] {
+            let kernel = Array4::from_shape_fn((channels, 3, 3, 3), |(o, c, y, x)| {
+                ((o * 27 + c * 9 + y * 3 + x) % 7) as f32 / 2.0 - 1.5
+            })
+            .into_arc_tensor();
+            let mut conv =
+                Conv::default().with_packed_lowering(true).with_strategy(ConvStrategy::ForceGemm);
+            conv.padding = PaddingSpec::SameUpper;
+            let ops = lowered(conv.clone(), input.clone(), kernel.clone()).unwrap();
+            assert!(ops.contains(&"PackedConv".to_string()), "{:?}", ops);
+            assert!(!ops.iter().any(|op| op.contains("Im2col")), "{:?}", ops);
+
+            let facts = conv_facts(input.clone(), kernel);
+            let mut op = conv.to_unary(&facts).unwrap().unwrap();
+            op.bias = Some(Array1::from_shape_fn(channels, |c| c as f32 - 1.5).into_tensor());
+            let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+            let mut model = InferenceModel::default();
+            model
+                .add_source("input", TensorFact::dt_shape(DatumType::F32, tvec!(1, 3, 6, 5)))
+                .unwrap();
+            model.chain_default("conv", op).unwrap();
+            let model = model.into_typed().unwrap().into_optimized().unwrap();
+            assert!(model.nodes().iter().any(|n| n.op_is::<PackedConv<f32>>()));
+            let found =
+                SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into_tensor())).unwrap();
+            assert!(found[0].close_enough(&expected, true), "{} channels", channels);
+        }
+    }
+}
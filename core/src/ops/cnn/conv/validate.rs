@@ -0,0 +1,211 @@
+use crate::internal::*;
+
+use super::error::ConvError;
+use super::{Conv, KernelFormat};
+use crate::ops::cnn::PaddingSpec;
+
+/// Output channels and input channels, over all the groups, of a kernel of
+/// shape `kshape`.
+///
+/// The two formats split the channels across groups the other way around:
+/// an OIHW kernel holds all the output channels and the input channels of a
+/// single group, a HWIO kernel all the input channels and the output
+/// channels of a single group.
+pub(super) fn kernel_channels(
+    kernel_fmt: KernelFormat,
+    kshape: &[usize],
+    group: usize,
+) -> (usize, usize) {
+    match kernel_fmt {
+        KernelFormat::OIHW => (kshape[0], kshape[1] * group),
+        KernelFormat::HWIO => (kshape[kshape.len() - 1] * group, kshape[kshape.len() - 2]),
+    }
+}
+
+/// Input channel, over all the groups, the item at `ix` of a kernel of shape
+/// `kshape` reads, see `kernel_channels`.
+pub(super) fn kernel_input_channel(
+    kernel_fmt: KernelFormat,
+    kshape: &[usize],
+    group: usize,
+    ix: &[usize],
+) -> usize {
+    match kernel_fmt {
+        KernelFormat::OIHW => ix[0] / (kshape[0] / group) * kshape[1] + ix[1],
+        KernelFormat::HWIO => ix[kshape.len() - 2],
+    }
+}
+
+/// Check `conv` convolving an input of `full_input_shape` with a kernel of
+/// `kernel_shape` in `group` groups, reporting all the problems at once.
+///
+/// This cross-checks the kernel rank against the input rank, the strides,
+/// dilations, kernel shape and explicit padding against the spatial rank,
+/// the kernel shape of `conv` against the kernel array, and the channels of
+/// input, kernel (m and k of the products) and bias against each other and
+/// the group.
+///
+/// A single problem is returned as is, several in `ConvError::Several`, in
+/// the order above.
+pub fn validate_config(
+    conv: &Conv,
+    full_input_shape: &[TDim],
+    kernel_shape: &[usize],
+    bias_len: Option<usize>,
+    group: usize,
+) -> Result<(), ConvError> {
+    let mut problems = vec![];
+    let rank = full_input_shape.len();
+    let spatial_rank = rank.saturating_sub(2);
+    if rank < 3 {
+        problems.push(ConvError::UnsupportedLayout { format: conv.data_format, rank });
+    }
+    if kernel_shape.len() != rank {
+        problems.push(ConvError::ShapeMismatch {
+            what: "kernel rank",
+            expected: rank,
+            found: kernel_shape.len(),
+        });
+    }
+    if let Some(strides) = &conv.strides {
+        if strides.len() != spatial_rank || strides.contains(&0) {
+            problems.push(ConvError::InvalidStride { strides: strides.clone(), spatial_rank });
+        }
+    }
+    let (before, after) = match &conv.padding {
+        PaddingSpec::Explicit(before, after) => (Some(before.len()), Some(after.len())),
+        _ => (None, None),
+    };
+    let ranks = [
+        ("dilations", conv.dilations.as_ref().map(|d| d.len())),
+        ("kernel shape", conv.kernel_shape.as_ref().map(|k| k.len())),
+        ("padding before", before),
+        ("padding after", after),
+    ];
+    for &(what, found) in &ranks {
+        match found {
+            Some(found) if found != spatial_rank => {
+                problems.push(ConvError::ShapeMismatch { what, expected: spatial_rank, found })
+            }
+            _ => (),
+        }
+    }
+    if rank >= 3 && kernel_shape.len() == rank {
+        let spatial = &kernel_shape[conv.kernel_fmt.h_axis()..][..spatial_rank];
+        if let Some(declared) = conv.kernel_shape.as_ref().filter(|k| k.len() == spatial_rank) {
+            for (&expected, &found) in declared.iter().zip(spatial.iter()) {
+                if expected != found {
+                    problems.push(ConvError::ShapeMismatch {
+                        what: "kernel spatial dimension",
+                        expected,
+                        found,
+                    });
+                }
+            }
+        }
+        let (output_channels, kernel_i) = kernel_channels(conv.kernel_fmt, kernel_shape, group);
+        if group == 0 || output_channels % group != 0 {
+            problems
+                .push(ConvError::ChannelsNotDivisibleByGroup { channels: output_channels, group });
+        }
+        let input_c = conv.data_format.shape(full_input_shape).c_dim().to_integer();
+        match input_c {
+            Ok(input_c) => {
+                let input_c = input_c as usize;
+                if group != 0 && input_c % group != 0 {
+                    problems
+                        .push(ConvError::ChannelsNotDivisibleByGroup { channels: input_c, group });
+                }
+                if input_c != kernel_i {
+                    problems.push(ConvError::ShapeMismatch {
+                        what: "kernel input channels",
+                        expected: input_c,
+                        found: kernel_i,
+                    });
+                }
+            }
+            Err(_) if group != 0 && kernel_i % group != 0 => {
+                problems.push(ConvError::ChannelsNotDivisibleByGroup { channels: kernel_i, group });
+            }
+            Err(_) => (),
+        }
+        if let Some(bias_len) = bias_len {
+            if bias_len != output_channels && bias_len != group {
+                problems.push(ConvError::ShapeMismatch {
+                    what: "bias length",
+                    expected: output_channels,
+                    found: bias_len,
+                });
+            }
+        }
+    }
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err(problems.remove(0)),
+        _ => Err(ConvError::Several { problems }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::nn::DataFormat;
+
+    fn shape(dims: &[usize]) -> TVec<TDim> {
+        dims.iter().map(|&d| d.to_dim()).collect()
+    }
+
+    #[test]
+    fn valid_grouped_conv() {
+        let conv = Conv::default();
+        assert_eq!(
+            validate_config(&conv, &shape(&[1, 6, 5, 5]), &[4, 3, 3, 3], Some(4), 2),
+            Ok(())
+        );
+        // a bias per group is accepted
+        assert_eq!(
+            validate_config(&conv, &shape(&[1, 6, 5, 5]), &[4, 3, 3, 3], Some(2), 2),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn all_problems_are_listed() {
+        let mut conv = Conv::default();
+        conv.strides = Some(tvec!(1, 0));
+        conv.kernel_shape = Some(tvec!(3, 3));
+        let e = validate_config(&conv, &shape(&[1, 6, 5, 5]), &[5, 2, 3, 1], Some(7), 2);
+        let problems = vec![
+            ConvError::InvalidStride { strides: tvec!(1, 0), spatial_rank: 2 },
+            ConvError::ShapeMismatch { what: "kernel spatial dimension", expected: 3, found: 1 },
+            ConvError::ChannelsNotDivisibleByGroup { channels: 5, group: 2 },
+            ConvError::ShapeMismatch { what: "kernel input channels", expected: 6, found: 4 },
+            ConvError::ShapeMismatch { what: "bias length", expected: 5, found: 7 },
+        ];
+        assert_eq!(e, Err(ConvError::Several { problems }));
+        let e = e.unwrap_err().to_string();
+        assert!(e.starts_with("5 problems: invalid strides"), "{}", e);
+    }
+
+    #[test]
+    fn rank_problems_skip_channel_checks() {
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            Some(tvec!(1)),
+            None,
+            PaddingSpec::Explicit(tvec!(0, 0), tvec!(0)),
+            None,
+            1,
+        );
+        let e = validate_config(&conv, &shape(&[1, 5, 5, 3]), &[3, 3, 3], None, 1);
+        let problems = vec![
+            ConvError::ShapeMismatch { what: "kernel rank", expected: 4, found: 3 },
+            ConvError::ShapeMismatch { what: "dilations", expected: 2, found: 1 },
+            ConvError::ShapeMismatch { what: "padding after", expected: 2, found: 1 },
+        ];
+        assert_eq!(e, Err(ConvError::Several { problems }));
+        let e = validate_config(&Conv::default(), &shape(&[1, 5]), &[1, 5], None, 1);
+        assert_eq!(e, Err(ConvError::UnsupportedLayout { format: DataFormat::NCHW, rank: 2 }));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+use std::fmt;
+
+use super::ConvUnary;
+use crate::ops::nn::DataShape;
+
+/*
+ * Winograd F(2x2, 3x3): each 2x2 tile of the output is computed from the
+ * 4x4 tile of the input covering it, as
+ *
+ *   Y = At . sum_c [ (G . g_c . Gt) * (Bt . d_c . B) ] . A
+ *
+ * `*` being the element-wise product, `g_c` the 3x3 kernel of input
+ * channel `c` and `d_c` the input tile of that channel. The kernel
+ * transforms are computed once, when lowering: a tile then costs 16
+ * multiplications per input and output channel instead of the 36 of the
+ * direct product.
+ */
+
+const BT: [[f32; 4]; 4] =
+    [[1.0, 0.0, -1.0, 0.0], [0.0, 1.0, 1.0, 0.0], [0.0, -1.0, 1.0, 0.0], [0.0, 1.0, 0.0, -1.0]];
+const G: [[f32; 3]; 4] = [[1.0, 0.0, 0.0], [0.5, 0.5, 0.5], [0.5, -0.5, 0.5], [0.0, 0.0, 1.0]];
+const AT: [[f32; 4]; 2] = [[1.0, 1.0, 1.0, 0.0], [0.0, 1.0, -1.0, -1.0]];
+
+/// G . g . Gt, for a 3x3 kernel `g`.
+fn transform_kernel(g: &[f32]) -> [[f32; 4]; 4] {
+    let mut gg = [[0.0f32; 3]; 4];
+    for i in 0..4 {
+        for j in 0..3 {
+            gg[i][j] = (0..3).map(|k| G[i][k] * g[k * 3 + j]).sum();
+        }
+    }
+    let mut u = [[0.0f32; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            u[i][j] = (0..3).map(|k| gg[i][k] * G[j][k]).sum();
+        }
+    }
+    u
+}
+
+/// Bt . d . B, for a 4x4 input tile `d`.
+fn transform_input(d: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut bd = [[0.0f32; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            bd[i][j] = (0..4).map(|k| BT[i][k] * d[k][j]).sum();
+        }
+    }
+    let mut v = [[0.0f32; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            v[i][j] = (0..4).map(|k| bd[i][k] * BT[j][k]).sum();
+        }
+    }
+    v
+}
+
+/// At . m . A, the 2x2 output tile of the accumulated products `m`.
+fn transform_output(m: &[[f32; 4]; 4]) -> [[f32; 2]; 2] {
+    let mut am = [[0.0f32; 4]; 2];
+    for i in 0..2 {
+        for j in 0..4 {
+            am[i][j] = (0..4).map(|k| AT[i][k] * m[k][j]).sum();
+        }
+    }
+    let mut y = [[0.0f32; 2]; 2];
+    for i in 0..2 {
+        for j in 0..2 {
+            y[i][j] = (0..4).map(|k| am[i][k] * AT[j][k]).sum();
+        }
+    }
+    y
+}
+
+/// A 2D, 3x3, stride 1 and undilated f32 convolution, without groups,
+/// computed by Winograd F(2x2, 3x3).
+#[derive(Clone, new)]
+pub struct Winograd {
+    input_shape: DataShape,
+    output_shape: DataShape,
+    /// Zero padding before the input, as (top, left).
+    pad: (usize, usize),
+    /// Transformed kernel of each (output channel, input channel) pair.
+    kernel: Array4<f32>,
+    bias: Option<Array1<f32>>,
+}
+
+impl fmt::Debug for Winograd {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Winograd")
+            .field("input_shape", &self.input_shape)
+            .field("output_shape", &self.output_shape)
+            .field("pad", &self.pad)
+            .field("bias", &self.bias)
+            .finish()
+    }
+}
+
+impl Winograd {
+    fn tiles(&self) -> (usize, usize) {
+        let hw = self.output_shape.hw_dims();
+        ((hw[0] + 1) / 2, (hw[1] + 1) / 2)
+    }
+}
+
+impl Op for Winograd {
+    fn name(&self) -> Cow<str> {
+        "ConvWinograd".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        let (th, tw) = self.tiles();
+        Ok(Some(format!("{}x{} tiles of 2x2, padding {:?}", th, tw, self.pad)))
+    }
+
+    fn cost(&self, _inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let (th, tw) = self.tiles();
+        let products = self.input_shape.n() * th * tw * self.kernel.len();
+        Ok(tvec!((Cost::FMA(f32::datum_type()), products.to_dim())))
+    }
+
+    fn rounding_errors(&self) -> bool {
+        true
+    }
+}
+
+impl StatelessOp for Winograd {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let input = input.as_slice::<f32>()?;
+        let (is, os) = (&self.input_shape, &self.output_shape);
+        let (ih, iw) = (is.hw_dims()[0], is.hw_dims()[1]);
+        let (oh, ow) = (os.hw_dims()[0], os.hw_dims()[1]);
+        let (ci, co) = (is.c(), os.c());
+        let (th, tw) = self.tiles();
+        let mut output = vec![0.0f32; os.shape.iter().product()];
+        let mut v = vec![[[0.0f32; 4]; 4]; ci];
+        for n in 0..is.n() {
+            for ty in 0..th {
+                for tx in 0..tw {
+                    for c in 0..ci {
+                        let mut d = [[0.0f32; 4]; 4];
+                        for (y, row) in d.iter_mut().enumerate() {
+                            let iy = (ty * 2 + y) as isize - self.pad.0 as isize;
+                            if iy < 0 || iy >= ih as isize {
+                                continue;
+                            }
+                            for (x, value) in row.iter_mut().enumerate() {
+                                let ix = (tx * 2 + x) as isize - self.pad.1 as isize;
+                                if ix < 0 || ix >= iw as isize {
+                                    continue;
+                                }
+                                *value = input[n * is.n_stride()
+                                    + c * is.c_stride()
+                                    + iy as usize * is.hw_strides()[0]
+                                    + ix as usize * is.hw_strides()[1]];
+                            }
+                        }
+                        v[c] = transform_input(&d);
+                    }
+                    for o in 0..co {
+                        let mut m = [[0.0f32; 4]; 4];
+                        for (c, v) in v.iter().enumerate() {
+                            let u = self.kernel.slice(s![o, c, .., ..]);
+                            for i in 0..4 {
+                                for j in 0..4 {
+                                    m[i][j] += u[(i, j)] * v[i][j];
+                                }
+                            }
+                        }
+                        let y = transform_output(&m);
+                        let bias = self.bias.as_ref().map(|b| b[o]).unwrap_or(0.0);
+                        for (dy, row) in y.iter().enumerate() {
+                            for (dx, value) in row.iter().enumerate() {
+                                let (oy, ox) = (ty * 2 + dy, tx * 2 + dx);
+                                if oy < oh && ox < ow {
+                                    output[n * os.n_stride()
+                                        + o * os.c_stride()
+                                        + oy * os.hw_strides()[0]
+                                        + ox * os.hw_strides()[1]] = value + bias;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let output = ArrayD::from_shape_vec(&*os.shape, output)?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Winograd {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        _s: &mut Solver<'r>,
+        _inputs: &'p [TensorProxy],
+        _outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        unreachable!()
+    }
+}
+
+impl ConvUnary {
+    /// Why Winograd F(2x2, 3x3) can not compute this convolution, if it can
+    /// not.
+    pub(super) fn winograd_unsupported(&self, dt: DatumType) -> Option<&'static str> {
+        let kernel_spatial_shape =
+            &self.kernel.shape()[self.kernel_fmt.h_axis()..][..self.full_input_shape.len() - 2];
+        if dt != f32::datum_type() {
+            Some("winograd is f32 only")
+        } else if kernel_spatial_shape != [3, 3] {
+            Some("winograd needs a 2D 3x3 kernel")
+        } else if self.strides.iter().any(|&s| s != 1) {
+            Some("winograd needs unit strides")
+        } else if self.dilations.iter().any(|&d| d != 1) {
+            Some("winograd does not dilate")
+        } else if self.group != 1 {
+            Some("winograd does not support groups")
+        } else if !self.independent_shape.is_empty() {
+            Some("winograd does not fold independent axes")
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn to_winograd(&self, input_full_shape: &[usize]) -> TractResult<Winograd> {
+        assert!(self.winograd_unsupported(f32::datum_type()).is_none());
+        let patch = self.patch(input_full_shape);
+        let input_shape = self.data_format.shape(input_full_shape.into());
+        let output_shape = self.data_format.from_n_c_hw(
+            input_shape.n(),
+            self.output_channels(),
+            &*patch.output_shape,
+        );
+        let kernel = self.kernel_as_group_o_ihw::<f32>()?;
+        let (co, ci) = (self.output_channels(), self.input_channels());
+        let mut transformed = Array4::zeros((co, ci, 4, 4));
+        for o in 0..co {
+            for c in 0..ci {
+                let g: Vec<f32> = kernel.slice(s![0, o, c * 9..(c + 1) * 9]).to_vec();
+                let u = transform_kernel(&g);
+                transformed.slice_mut(s![o, c, .., ..]).assign(&arr2(&u));
+            }
+        }
+        let bias = self.bias_reshaped::<f32>(&output_shape)?.map(|b| b.iter().cloned().collect());
+        Ok(Winograd::new(
+            input_shape,
+            output_shape,
+            (patch.pad_before[0], patch.pad_before[1]),
+            transformed,
+            bias,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, PaddingSpec};
+    use crate::ops::nn::DataFormat;
+
+    /// Compares Winograd to the im2col product.
+    fn check(conv: Conv, input: ArrayD<f32>, kernel: ArrayD<f32>, bias: bool) {
+        let input = input.into_arc_tensor();
+        let facts = conv_facts(input.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        if bias {
+            let bias = Array1::from_shape_fn(op.output_channels(), |c| c as f32 - 1.5);
+            op.bias = Some(bias.into_tensor());
+        }
+        let expected = op.eval(tvec!(input.clone())).unwrap().remove(0);
+        let found = op.to_winograd(input.shape()).unwrap().eval(tvec!(input)).unwrap().remove(0);
+        assert_close!(*found, *expected);
+    }
+
+    #[test]
+    fn matches_gemm_nchw() {
+        // odd output sizes: the last tiles are cut
+        let input = Array4::from_shape_fn((2, 3, 7, 6), |(n, c, y, x)| {
+            ((n * 31 + c * 7 + y * 5 + x * 3) % 11) as f32 - 5.0
+        });
+        let kernel = Array4::from_shape_fn((4, 3, 3, 3), |(o, c, y, x)| {
+            ((o * 13 + c * 5 + y * 3 + x) % 7) as f32 * 0.25 - 0.75
+        });
+        check(Conv::default(), input.clone().into_dyn(), kernel.clone().into_dyn(), false);
+        let mut conv = Conv::default();
+        conv.padding = PaddingSpec::SameUpper;
+        check(conv, input.into_dyn(), kernel.into_dyn(), true);
+    }
+
+    #[test]
+    fn matches_gemm_nhwc() {
+        let input = Array4::from_shape_fn((1, 5, 8, 2), |(_, y, x, c)| {
+            ((c * 7 + y * 5 + x * 3) % 13) as f32 * 0.5 - 3.0
+        });
+        let kernel = Array4::from_shape_fn((3, 3, 2, 5), |(y, x, c, o)| {
+            ((o * 11 + c * 5 + y * 3 + x) % 9) as f32 * 0.25 - 1.0
+        });
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            None,
+            None,
+            PaddingSpec::SameLower,
+            None,
+            1,
+        );
+        check(conv, input.into_dyn(), kernel.into_dyn(), true);
+    }
+}
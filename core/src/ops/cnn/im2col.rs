@@ -0,0 +1,301 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use crate::ops::cnn::{ConvError, PaddingSpec, Patch, Window};
+use crate::ops::nn::{DataFormat, DataShape};
+
+/// Unfold the sliding windows of an image in columns, like the first half
+/// of a convolution.
+///
+/// An N,C,spatial... image (or N,spatial...,C in NHWC) becomes a
+/// (N, C * K, L) tensor, where K is the number of kernel positions and L the
+/// number of window positions. Row `c * K + k` holds kernel position `k` of
+/// channel `c` for every window; padded positions read as zero.
+#[derive(Debug, Clone, new)]
+pub struct Im2Col {
+    pub data_format: DataFormat,
+    pub kernel_shape: TVec<usize>,
+    pub strides: Option<TVec<usize>>,
+    pub dilations: Option<TVec<usize>>,
+    pub padding: PaddingSpec,
+}
+
+impl Im2Col {
+    /// Windows of an image of shape `image_full_shape`.
+    pub fn patch(&self, image_full_shape: &[usize]) -> TractResult<(DataShape, Patch)> {
+        let image_shape = self.data_format.shape(image_full_shape.into());
+        let spatial_rank = image_shape.hw_rank();
+        if self.kernel_shape.len() != spatial_rank {
+            bail!(ConvError::ShapeMismatch {
+                what: "kernel rank",
+                expected: spatial_rank,
+                found: self.kernel_shape.len()
+            });
+        }
+        if let Some(ref strides) = self.strides {
+            if strides.len() != spatial_rank || strides.iter().any(|&s| s == 0) {
+                bail!(ConvError::InvalidStride { strides: strides.clone(), spatial_rank });
+            }
+        }
+        if let Some(ref dilations) = self.dilations {
+            if dilations.len() != spatial_rank {
+                bail!(ConvError::ShapeMismatch {
+                    what: "dilations",
+                    expected: spatial_rank,
+                    found: dilations.len()
+                });
+            }
+        }
+        let patch = self.window().patch_spec(self.data_format, image_full_shape).into_patch();
+        Ok((image_shape, patch))
+    }
+
+    /// The sliding window, shared with convolutions and pools.
+    pub fn window(&self) -> Window {
+        Window::new(
+            self.kernel_shape.clone(),
+            self.strides.clone(),
+            self.dilations.clone(),
+            self.padding.clone(),
+        )
+    }
+
+    fn kernel_len(&self) -> usize {
+        self.kernel_shape.iter().product()
+    }
+
+    /// N, C * K and L of the columns of an image of shape `image_full_shape`.
+    fn columns_shape<D: DimLike>(&self, image_full_shape: &[D]) -> TVec<D> {
+        let image_shape = self.data_format.shape(image_full_shape);
+        let windows = self
+            .window()
+            .output_dims(image_shape.hw_dims())
+            .into_iter()
+            .map(|d| d.output)
+            .product();
+        tvec!(image_shape.n_dim(), image_shape.c_dim() * self.kernel_len(), windows)
+    }
+
+    fn eval_t<T: Datum + Copy>(&self, image: &Tensor) -> TractResult<Tensor> {
+        let (image_shape, patch) = self.patch(image.shape())?;
+        let image = image.as_slice::<T>()?;
+        let k = self.kernel_len();
+        let windows: usize = patch.output_shape.iter().product();
+        let mut columns = Array3::<T>::default((image_shape.n(), image_shape.c() * k, windows));
+        for n in 0..image_shape.n() {
+            for c in 0..image_shape.c() {
+                let offset = (image_shape.n_stride() * n + image_shape.c_stride() * c) as isize;
+                for (l, window) in ndarray::indices(&*patch.output_shape).into_iter().enumerate() {
+                    for (ix, v) in patch.at(window.slice()).enumerate() {
+                        if let Some(v) = v {
+                            columns[(n, c * k + ix, l)] = image[(offset + v) as usize];
+                        }
+                    }
+                }
+            }
+        }
+        Ok(columns.into())
+    }
+}
+
+impl Op for Im2Col {
+    fn name(&self) -> Cow<str> {
+        "Im2Col".into()
+    }
+}
+
+impl StatelessOp for Im2Col {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        Ok(tvec!(dispatch_copy!(Self::eval_t(input.datum_type())(self, &input))?.into()))
+    }
+}
+
+impl InferenceRulesOp for Im2Col {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, self.kernel_shape.len() as i32 + 2)?;
+        s.equals(&outputs[0].rank, 3)?;
+        s.given(&inputs[0].shape, move |s, shape| {
+            s.equals(&outputs[0].shape, ShapeFact::from(self.columns_shape(&*shape)))
+        })
+    }
+}
+
+/// Fold columns back into an image of spatial shape `image_shape`, the
+/// inverse layout of `Im2Col`.
+///
+/// Values of overlapping windows landing on the same pixel are summed, and
+/// those falling in the padding are dropped. Without overlap and padding,
+/// `Col2Im` undoes `Im2Col`.
+#[derive(Debug, Clone, new)]
+pub struct Col2Im {
+    pub im2col: Im2Col,
+    pub image_shape: TVec<usize>,
+}
+
+impl Col2Im {
+    fn image_full_shape<D: DimLike>(&self, columns_shape: &[D]) -> TVec<D> {
+        let image_shape: TVec<D> = self.image_shape.iter().map(|&d| d.into()).collect();
+        let c = columns_shape[1] / self.im2col.kernel_len();
+        self.im2col.data_format.from_n_c_hw(columns_shape[0], c, image_shape).shape
+    }
+
+    fn eval_t<T: Datum + Copy + num_traits::Zero>(&self, columns: &Tensor) -> TractResult<Tensor> {
+        let columns = columns.to_array_view::<T>()?.into_dimensionality::<Ix3>()?;
+        let k = self.im2col.kernel_len();
+        if columns.shape()[1] % k != 0 {
+            bail!(
+                "Col2Im: {} rows of columns is not a multiple of the kernel size {}",
+                columns.shape()[1],
+                k
+            );
+        }
+        let image_full_shape = self.image_full_shape(columns.shape());
+        let (image_shape, patch) = self.im2col.patch(&image_full_shape)?;
+        let windows: usize = patch.output_shape.iter().product();
+        if columns.shape()[2] != windows {
+            bail!(ConvError::ShapeMismatch {
+                what: "columns windows",
+                expected: windows,
+                found: columns.shape()[2]
+            });
+        }
+        let mut image = ArrayD::<T>::zeros(&*image_full_shape);
+        {
+            let image = image.as_slice_mut().unwrap();
+            for n in 0..image_shape.n() {
+                for c in 0..image_shape.c() {
+                    let offset = (image_shape.n_stride() * n + image_shape.c_stride() * c) as isize;
+                    for (l, window) in
+                        ndarray::indices(&*patch.output_shape).into_iter().enumerate()
+                    {
+                        for (ix, v) in patch.at(window.slice()).enumerate() {
+                            if let Some(v) = v {
+                                let pixel = &mut image[(offset + v) as usize];
+                                *pixel = *pixel + columns[(n, c * k + ix, l)];
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(image.into())
+    }
+}
+
+impl Op for Col2Im {
+    fn name(&self) -> Cow<str> {
+        "Col2Im".into()
+    }
+}
+
+impl StatelessOp for Col2Im {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        Ok(tvec!(dispatch_numbers!(Self::eval_t(input.datum_type())(self, &input))?.into()))
+    }
+}
+
+impl InferenceRulesOp for Col2Im {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, 3)?;
+        s.equals(&outputs[0].rank, self.image_shape.len() as i32 + 2)?;
+        s.given(&inputs[0].shape, move |s, shape| {
+            s.equals(&outputs[0].shape, ShapeFact::from(self.image_full_shape(&*shape)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn image(data_format: DataFormat) -> Tensor {
+        let shape = data_format.from_n_c_hw(2, 3, [4usize, 6]).shape;
+        ArrayD::from_shape_fn(&*shape, |ix| ix.slice().iter().fold(0, |acc, x| acc * 7 + x) as f32)
+            .into()
+    }
+
+    #[test]
+    fn round_trip_without_overlap() {
+        for &data_format in &[DataFormat::NCHW, DataFormat::NHWC] {
+            let im2col =
+                Im2Col::new(data_format, tvec!(2, 3), Some(tvec!(2, 3)), None, PaddingSpec::Valid);
+            let image = image(data_format);
+            let columns = im2col.eval(tvec!(image.clone().into())).unwrap().remove(0);
+            assert_eq!(columns.shape(), &[2, 3 * 6, 4]);
+            let col2im = Col2Im::new(im2col, tvec!(4, 6));
+            let found = col2im.eval(tvec!(columns)).unwrap().remove(0);
+            assert_eq!(*found, image);
+        }
+    }
+
+    #[test]
+    fn columns_follow_windows() {
+        let image = tensor4(&[[[[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]]]]);
+        let im2col = Im2Col::new(DataFormat::NCHW, tvec!(2, 2), None, None, PaddingSpec::Valid);
+        let columns = im2col.eval(tvec!(image.into())).unwrap().remove(0);
+        assert_eq!(*columns, tensor3(&[[[1.0f32, 2.0], [2.0, 3.0], [4.0, 5.0], [5.0, 6.0]]]));
+    }
+
+    #[test]
+    fn overlapping_windows_accumulate() {
+        let image = tensor4(&[[[[1.0f32, 1.0, 1.0, 1.0]]]]);
+        let im2col = Im2Col::new(DataFormat::NCHW, tvec!(1, 2), None, None, PaddingSpec::Valid);
+        let columns = im2col.eval(tvec!(image.into())).unwrap().remove(0);
+        let col2im = Col2Im::new(im2col, tvec!(1, 4));
+        let folded = col2im.eval(tvec!(columns)).unwrap().remove(0);
+        assert_eq!(*folded, tensor4(&[[[[1.0f32, 2.0, 2.0, 1.0]]]]));
+    }
+
+    #[test]
+    fn padding_reads_zero_and_folds_away() {
+        let image = tensor4(&[[[[1i32, 2, 3]]]]);
+        let im2col = Im2Col::new(
+            DataFormat::NCHW,
+            tvec!(1, 3),
+            None,
+            None,
+            PaddingSpec::Explicit(tvec!(0, 1), tvec!(0, 1)),
+        );
+        let columns = im2col.eval(tvec!(image.into())).unwrap().remove(0);
+        assert_eq!(*columns, tensor3(&[[[0i32, 1, 2], [1, 2, 3], [2, 3, 0]]]));
+        let col2im = Col2Im::new(im2col, tvec!(1, 3));
+        let folded = col2im.eval(tvec!(columns)).unwrap().remove(0);
+        assert_eq!(*folded, tensor4(&[[[[2i32, 6, 6]]]]));
+    }
+
+    #[test]
+    fn inferred_shapes() {
+        let im2col = Im2Col::new(
+            DataFormat::NHWC,
+            tvec!(3, 3),
+            Some(tvec!(2, 2)),
+            None,
+            PaddingSpec::SameUpper,
+        );
+        let image = TensorFact::dt_shape(f32::datum_type(), shapefact!(1, 7, 5, 4));
+        let any = TensorFact::default();
+        let (_, facts) = im2col.infer_facts(tvec!(&image), tvec!(&any)).unwrap();
+        assert_eq!(facts[0].shape, shapefact!(1, 36, 12));
+        let col2im = Col2Im::new(im2col, tvec!(7, 5));
+        let (_, facts) = col2im.infer_facts(tvec!(&facts[0]), tvec!(&any)).unwrap();
+        assert_eq!(facts[0].shape, shapefact!(1, 7, 5, 4));
+    }
+}
@@ -4,8 +4,11 @@ use num_traits::Float;
 
 use crate::ops::cnn::pools::PoolSpec;
 use crate::ops::cnn::Patch;
-use crate::ops::nn::DataShape;
+use crate::ops::nn::{max_with_index, DataShape};
 
+/// Max pooling, optionally outputting the index of each max too, as
+/// MaxUnpool needs them: the spatial index in the input image of the
+/// (n, c) of the output, the lowest one on ties.
 #[derive(Debug, Clone, new, Default)]
 pub struct MaxPool {
     pool_spec: PoolSpec,
@@ -103,10 +106,12 @@ impl<T: Datum + Float> StatelessOp for MaxPoolFixed<T> {
                     for c in 0..self.input_shape.c() {
                         let input_offset = input_offset + self.input_shape.c_stride() * c;
                         let output_offset = output_offset + self.output_shape.c_stride() * c;
-                        let max = visitor
-                            .valid_offsets()
-                            .map(|v| (v, *input_ptr.offset(v + input_offset as isize)))
-                            .fold((0, T::min_value()), |acc, v| if acc.1 < v.1 { v } else { acc });
+                        // values and indices in a single pass
+                        let max =
+                            max_with_index(visitor.valid_offsets().map(|v| {
+                                (v as usize, *input_ptr.offset(v + input_offset as isize))
+                            }))
+                            .unwrap_or((0, T::min_value()));
                         *values
                             .as_mut_ptr()
                             .offset(output_offset as isize + visitor.output_offset) = max.1;
@@ -141,3 +146,38 @@ impl<T: Datum + Float> InferenceRulesOp for MaxPoolFixed<T> {
         unreachable!()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::PaddingSpec;
+    use crate::ops::nn::DataFormat;
+    use ndarray::s;
+
+    #[test]
+    fn values_and_indices_in_one_pass() {
+        // few distinct values: windows hold ties
+        let input =
+            Array4::from_shape_fn((1, 2, 4, 5), |(_, c, y, x)| ((c + y * 3 + x * 7) % 4) as f32);
+        let pool_spec = PoolSpec::new(DataFormat::NCHW, tvec!(2, 3), PaddingSpec::Valid, None);
+        let op = MaxPool::new(pool_spec, Some(DatumType::I64));
+        let outputs = op.eval(tvec!(input.clone().into_arc_tensor())).unwrap();
+        let values = outputs[0].to_array_view::<f32>().unwrap();
+        let indices = outputs[1].to_array_view::<i64>().unwrap();
+        assert_eq!(values.shape(), &[1, 2, 3, 3]);
+        for c in 0..2 {
+            for y in 0..3 {
+                for x in 0..3 {
+                    let window = input.slice(s![0, c, y..y + 2, x..x + 3]);
+                    let max = window.fold(std::f32::MIN, |a, &b| a.max(b));
+                    // the lowest spatial index holding the max
+                    let (ix, _): ((usize, usize), &f32) =
+                        window.indexed_iter().find(|&(_, &v)| v == max).unwrap();
+                    let index = (y + ix.0) * 5 + x + ix.1;
+                    assert_eq!(values[[0, c, y, x]], max);
+                    assert_eq!(indices[[0, c, y, x]], index as i64);
+                }
+            }
+        }
+    }
+}
@@ -1,15 +1,27 @@
 mod avgpool;
+mod blurpool;
 pub mod conv;
+mod im2col;
 mod maxpool;
 mod padding;
 mod patch_axis;
 mod patches;
 pub mod pools;
+mod window;
 
 pub use self::avgpool::AvgPool;
-pub use self::conv::{Conv, ConvUnary, KernelFormat};
+pub use self::blurpool::BlurPool;
+pub use self::conv::{
+    validate_config, Arena, BFloat16KernelConv, BranchConv, CalibrationStats, ChannelSummary, Conv,
+    ConvConfig, ConvError, ConvInputGrad, ConvKernelGrad, ConvPhase, ConvStrategy, ConvUnary,
+    DeformableConv, DequantConv, HalfKernelConv, KernelFormat, KernelGroupLayout, KernelPacking,
+    Overflow, PanelPool, PhaseTimer, PhaseTimes, QConvI16, Rounding, ScratchAllocator,
+    ScratchLayout, SeparableConv,
+};
+pub use self::im2col::{Col2Im, Im2Col};
 pub use self::maxpool::MaxPool;
 pub use self::padding::PaddingSpec;
 pub use self::patch_axis::PatchAxis;
-pub use self::patches::{Patch, PatchSpec};
+pub use self::patches::{Patch, PatchPadMode, PatchSpec};
 pub use self::pools::PoolSpec;
+pub use self::window::Window;
@@ -1,10 +1,15 @@
 use crate::internal::*;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub enum PaddingSpec {
     Explicit(TVec<usize>, TVec<usize>),
     Valid,
+    /// Output is ceil(input / stride). The total padding is
+    /// max(0, (output - 1) * stride + (kernel - 1) * dilation + 1 - input),
+    /// the odd extra one going after: this is TensorFlow's SAME.
     SameUpper,
+    /// Same as SameUpper, but the odd extra padding goes before.
     SameLower,
 }
 
@@ -173,9 +178,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn explicit_asymmetric_dilated() {
+        // kernel field 5, padded 1 before and none after
+        assert_eq!(
+            PaddingSpec::explicit(7usize, 3usize, 2, 1, 1, 0),
+            ComputedPaddedDim::new(4, 1, 0)
+        );
+        assert_eq!(
+            PaddingSpec::explicit(4usize, 3usize, 2, 1, 1, 0),
+            ComputedPaddedDim::new(1, 1, 0)
+        );
+        // (8 + 1 - 5) / 2 + 1: the third window ends on the last item
+        assert_eq!(
+            PaddingSpec::explicit(8usize, 3usize, 2, 2, 1, 0),
+            ComputedPaddedDim::new(3, 1, 0)
+        );
+        assert_eq!(
+            PaddingSpec::explicit(7usize, 3usize, 2, 2, 1, 0),
+            ComputedPaddedDim::new(2, 1, 0)
+        );
+    }
+
     #[test]
     fn same_upper() {
         assert_eq!(PaddingSpec::same(7usize, 1usize, 1, 2, true), ComputedPaddedDim::new(4, 0, 0));
     }
 
+    #[test]
+    fn same_upper_dilated_even_total() {
+        // kernel field 5, pad total 4
+        assert_eq!(PaddingSpec::same(5usize, 3usize, 2, 1, true), ComputedPaddedDim::new(5, 2, 2));
+    }
+
+    #[test]
+    fn same_upper_dilated_odd_total() {
+        // kernel field 5, output 3, pad total (3-1)*2+5-6 = 3
+        assert_eq!(PaddingSpec::same(6usize, 3usize, 2, 2, true), ComputedPaddedDim::new(3, 1, 2));
+        // kernel field 7, output 4, pad total (4-1)*2+7-7 = 6
+        assert_eq!(PaddingSpec::same(7usize, 3usize, 3, 2, true), ComputedPaddedDim::new(4, 3, 3));
+        // kernel field 4, output 5, pad total 3
+        assert_eq!(PaddingSpec::same(5usize, 2usize, 3, 1, true), ComputedPaddedDim::new(5, 1, 2));
+    }
+
+    #[test]
+    fn same_lower_dilated_odd_total() {
+        assert_eq!(PaddingSpec::same(6usize, 3usize, 2, 2, false), ComputedPaddedDim::new(3, 2, 1));
+    }
+
+    #[test]
+    fn same_dilated_kernel_wider_than_input() {
+        // kernel field 9 on an input of 3: pad total 8
+        assert_eq!(PaddingSpec::same(3usize, 3usize, 4, 1, true), ComputedPaddedDim::new(3, 4, 4));
+        // kernel field 9, stride 3: output 1, pad total 6
+        assert_eq!(PaddingSpec::same(3usize, 3usize, 4, 3, true), ComputedPaddedDim::new(1, 3, 3));
+    }
+
+    #[test]
+    fn same_upper_and_lower_diverge_on_odd_total() {
+        // ONNX: SAME_UPPER pads 1 at the end, SAME_LOWER 1 at the beginning
+        let upper = PaddingSpec::SameUpper.compute(&[4usize, 5], &[2, 3], &[1, 1], &[1, 2]);
+        let lower = PaddingSpec::SameLower.compute(&[4usize, 5], &[2, 3], &[1, 1], &[1, 2]);
+        assert_eq!(upper, tvec!(ComputedPaddedDim::new(4, 0, 1), ComputedPaddedDim::new(3, 1, 1)));
+        assert_eq!(lower, tvec!(ComputedPaddedDim::new(4, 1, 0), ComputedPaddedDim::new(3, 1, 1)));
+    }
 }
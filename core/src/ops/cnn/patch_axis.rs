@@ -37,13 +37,14 @@ impl PatchAxis {
 
     fn invalid_at_left(&self, pos: usize) -> usize {
         let center_pos = pos * self.stride;
-        self.pad_before.saturating_sub(center_pos).div_ceil(self.dilation)
+        let invalid = self.pad_before.saturating_sub(center_pos).div_ceil(self.dilation);
+        invalid.min(self.kernel_dim)
     }
 
     fn invalid_at_right(&self, pos: usize) -> usize {
         let center_pos = pos * self.stride;
         let last_valid = self.input_dim + self.pad_before;
-        let valid = (last_valid - center_pos).div_ceil(self.dilation);
+        let valid = last_valid.saturating_sub(center_pos).div_ceil(self.dilation);
         self.kernel_dim.saturating_sub(valid)
     }
 
@@ -254,6 +255,31 @@ pub mod test {
         assert_eq!(regions, tvec!(Region::new(0..4, None),));
     }
 
+    #[test]
+    fn axis_11_3_s4_regions() {
+        // 0 1 2 3 4 5 6 7 8 9 10 -> 3 -> 0 4 8
+        let axis = PatchAxis::new(11, 3, 0, 0, 3, 4, 1);
+        assert_eq!(axis.valid_range(), Some(0..3));
+        assert_eq!(axis.regions(), tvec!(Region::new(0..3, None),));
+    }
+
+    #[test]
+    fn axis_5_3_s4_padding_wider_than_kernel() {
+        // • • • • 0 1 2 3 4 • • • • • • • • -> 3 -> (•) (4) (8) (•)
+        let axis = PatchAxis::new(5, 3, 4, 8, 4, 4, 1);
+        assert_eq!(axis.invalid_at_left(0), 3);
+        assert_eq!(axis.invalid_at_right(3), 3);
+        assert_eq!(
+            axis.regions(),
+            tvec!(
+                Region::new(0..1, Some(tvec!(true, true, true))),
+                Region::new(1..2, None),
+                Region::new(2..3, Some(tvec!(false, true, true))),
+                Region::new(3..4, Some(tvec!(true, true, true))),
+            )
+        );
+    }
+
     #[test]
     fn axis_1_2_regions() {
         // 0 -> 2 -> (0)
@@ -12,6 +12,23 @@ use std::ops::Range;
 use itertools::zip;
 use itertools::Itertools;
 
+/// How a patch samples positions falling in the padding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PatchPadMode {
+    /// Padded positions are absent, and read as zero.
+    Zero,
+    /// Mirror the input around its border, not repeating the edge value.
+    Reflect,
+    /// Repeat the value at the border.
+    Edge,
+}
+
+impl Default for PatchPadMode {
+    fn default() -> PatchPadMode {
+        PatchPadMode::Zero
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct PatchSpec {
     pub input_shape: TVec<usize>,
@@ -21,6 +38,7 @@ pub struct PatchSpec {
     pub strides: TVec<usize>,
     pub dilations: TVec<usize>,
     pub padding: PaddingSpec,
+    pub pad_mode: PatchPadMode,
 }
 
 impl PatchSpec {
@@ -38,6 +56,7 @@ impl PatchSpec {
             strides: tvec!(1; input_shape.len()),
             dilations: tvec!(1; input_shape.len()),
             padding: PaddingSpec::Valid,
+            pad_mode: PatchPadMode::Zero,
             input_shape,
         }
     }
@@ -58,6 +77,10 @@ impl PatchSpec {
         PatchSpec { padding, ..self }
     }
 
+    pub fn with_pad_mode(self, pad_mode: PatchPadMode) -> PatchSpec {
+        PatchSpec { pad_mode, ..self }
+    }
+
     pub fn with_output_inner_stride(self, output_inner_stride: usize) -> PatchSpec {
         PatchSpec { output_inner_stride, ..self }
     }
@@ -164,9 +187,11 @@ impl PatchSpec {
         let mut invalid_output_zones = tvec!();
         for ix in 0..self.input_shape.len() {
             let min_max = data_field_min_max[ix];
-            let min = (-min_max.0 as usize).div_ceil(self.strides[ix]) as usize;
-            let max =
-                (self.input_shape[ix] - min_max.1 as usize).div_ceil(self.strides[ix]) as usize;
+            // with padding wider than the kernel field (or strides larger than it), the
+            // field can sit entirely before the input: keep the arithmetic signed and clamp
+            let min = ((-min_max.0) as usize).div_ceil(self.strides[ix]).min(output[ix]);
+            let max = (self.input_shape[ix] as isize - min_max.1).max(0) as usize;
+            let max = max.div_ceil(self.strides[ix]).min(output[ix]).max(min);
             if min != 0 {
                 let mut invalid = valid_output_zone.clone();
                 invalid.push(0..min);
@@ -234,6 +259,25 @@ impl Patch {
         self.spec.input_shape.len()
     }
 
+    /// Padding values other than zero must be found in the input: reflecting
+    /// requires the padding to be smaller than the input dimension.
+    pub fn check_pad_mode(&self) -> TractResult<()> {
+        if self.spec.pad_mode == PatchPadMode::Reflect {
+            for ix in 0..self.rank() {
+                let pad = self.pad_before[ix].max(self.pad_after[ix]);
+                if pad >= self.spec.input_shape[ix] {
+                    bail!(
+                        "Reflect padding of {} needs an input dimension greater than {}, got {}",
+                        pad,
+                        pad,
+                        self.spec.input_shape[ix]
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     unsafe fn is_valid(&self, coords: &[usize]) -> bool {
         for ix in 0..self.rank() {
             let c = *coords.get_unchecked(ix) as isize;
@@ -501,13 +545,34 @@ impl<'p> Iterator for SafePatchIterator<'p> {
             let img_offset =
                 self.patch.data_field.as_ptr().offset((self.item * input_shape.len()) as isize);
 
+            let mut padded = false;
             for ix in 0..input_shape.len() {
                 let pos = *self.input_patch_center.get_unchecked(ix) as isize
                     + *img_offset.offset(ix as isize);
                 if pos < 0 || pos as usize >= *input_shape.get_unchecked(ix) {
-                    self.item += 1;
-                    return Some(None);
+                    if self.patch.spec.pad_mode == PatchPadMode::Zero {
+                        self.item += 1;
+                        return Some(None);
+                    }
+                    padded = true;
+                }
+            }
+            if padded {
+                let mut position = 0;
+                for ix in 0..input_shape.len() {
+                    let dim = *input_shape.get_unchecked(ix) as isize;
+                    let pos = *self.input_patch_center.get_unchecked(ix) as isize
+                        + *img_offset.offset(ix as isize);
+                    let pos = match self.patch.spec.pad_mode {
+                        PatchPadMode::Reflect if pos < 0 => -pos,
+                        PatchPadMode::Reflect if pos >= dim => 2 * (dim - 1) - pos,
+                        PatchPadMode::Edge => pos.max(0).min(dim - 1),
+                        _ => pos,
+                    };
+                    position += pos * *self.patch.input_layout_strides.get_unchecked(ix);
                 }
+                self.item += 1;
+                return Some(Some(position));
             }
             let position =
                 self.center + self.patch.standard_layout_data_field.get_unchecked(self.item);
@@ -579,6 +644,53 @@ pub mod test {
         assert_eq!(field(&[2, 2], &[2, 1]), arr2(&[[0, 0], [0, 1], [2, 0], [2, 1]]));
     }
 
+    fn gathered_offsets(input: usize, pad_before: usize, pad_after: usize) -> Vec<Vec<isize>> {
+        let patch = PatchSpec::for_full_shape(NCHW, &[1, 1, input])
+            .with_kernel_shape(tvec!(3))
+            .with_padding(PaddingSpec::Explicit(tvec![pad_before], tvec![pad_after]))
+            .with_strides(tvec![4])
+            .into_patch();
+        (0..patch.output_shape[0]).map(|x| patch.at(&[x]).flatten().collect()).collect()
+    }
+
+    #[test]
+    fn stride_larger_than_kernel_gathers_disjoint_windows() {
+        assert_eq!(gathered_offsets(11, 0, 0), vec![vec![0, 1, 2], vec![4, 5, 6], vec![8, 9, 10]]);
+        assert_eq!(
+            gathered_offsets(13, 1, 1),
+            vec![vec![0, 1], vec![3, 4, 5], vec![7, 8, 9], vec![11, 12]]
+        );
+        assert_eq!(gathered_offsets(5, 4, 8), vec![vec![], vec![0, 1, 2], vec![4], vec![]]);
+    }
+
+    #[test]
+    fn per_axis_strides_dilations_and_padding() {
+        let patch = PatchSpec::for_full_shape(NCHW, &[1, 1, 7, 5])
+            .with_kernel_shape(tvec![3, 2])
+            .with_dilations(tvec![1, 2])
+            .with_padding(PaddingSpec::Explicit(tvec![1, 0], tvec![1, 1]))
+            .with_strides(tvec![2, 1])
+            .into_patch();
+        assert_eq!(patch.output_shape, tvec![4, 4]);
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected: Vec<isize> = (0..3)
+                    .flat_map(|ky| (0..2).map(move |kx| (ky, kx)))
+                    .filter_map(|(ky, kx)| {
+                        let iy = (y * 2 + ky) as isize - 1;
+                        let ix = (x + kx * 2) as isize;
+                        if iy >= 0 && iy < 7 && ix < 5 {
+                            Some(iy * 5 + ix)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                assert_eq!(patch.at(&[y, x]).flatten().collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+
     pub fn patch_2d() -> BoxedStrategy<(DataShape, Patch)> {
         (
             Just(DataFormat::NCHW),
@@ -1,6 +1,6 @@
 use crate::internal::*;
 
-use crate::ops::cnn::{PaddingSpec, Patch, PatchSpec};
+use crate::ops::cnn::{PaddingSpec, Patch, Window};
 use crate::ops::nn::{DataFormat, DataShape};
 
 #[derive(Debug, Clone, new, Default)]
@@ -12,16 +12,18 @@ pub struct PoolSpec {
 }
 
 impl PoolSpec {
+    /// The window of the pool, shared with convolutions.
+    pub fn window(&self) -> Window {
+        Window::new(self.kernel_shape.clone(), self.strides.clone(), None, self.padding.clone())
+    }
+
     pub fn compute_geo(&self, input_full_shape: &[usize]) -> (DataShape, Patch, DataShape) {
         let input_shape = self.data_format.shape(input_full_shape.into());
-        let mut spec = PatchSpec::for_full_shape(self.data_format, input_full_shape)
+        let patch = self
+            .window()
+            .patch_spec(self.data_format, input_full_shape)
             .with_output_inner_stride(input_shape.w_stride())
-            .with_kernel_shape(self.kernel_shape.clone())
-            .with_padding(self.padding.clone());
-        if let Some(strides) = self.strides.clone() {
-            spec = spec.with_strides(strides);
-        }
-        let patch = spec.into_patch();
+            .into_patch();
         let output_shape =
             input_shape.fmt.from_n_c_hw(input_shape.n(), input_shape.c(), &*patch.output_shape);
         (input_shape, patch, output_shape)
@@ -35,20 +37,10 @@ impl PoolSpec {
     ) -> InferenceResult {
         s.equals(&outputs[0].rank, &inputs[0].rank)?;
         s.given(&inputs[0].shape, move |s, ishape| {
-            let ishape = self.data_format.shape(ishape);
-            let ones = tvec![1; ishape.hw_rank()];
-            let computed = self.padding.compute(
-                ishape.hw_dims(),
-                &*self.kernel_shape,
-                &ones,
-                self.strides.as_ref().unwrap_or(&ones),
-            );
+            let channels = self.data_format.shape(&*ishape).c_dim();
+            let oshape = self.window().output_shape(self.data_format, &ishape, channels);
             for o in 0..outputs.len() {
-                for (ix, d) in computed.iter().enumerate() {
-                    s.equals(&outputs[o].shape[ix + ishape.h_axis()], d.output)?;
-                }
-                s.equals(&outputs[o].shape[ishape.n_axis()], ishape.n_dim())?;
-                s.equals(&outputs[o].shape[ishape.c_axis()], ishape.c_dim())?;
+                s.equals(&outputs[o].shape, ShapeFact::from(oshape.clone()))?;
             }
             Ok(())
         })
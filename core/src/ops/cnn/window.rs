@@ -0,0 +1,119 @@
+use crate::internal::*;
+
+use crate::ops::cnn::padding::ComputedPaddedDim;
+use crate::ops::cnn::{PaddingSpec, PatchSpec};
+use crate::ops::nn::DataFormat;
+
+/// Sliding window of the patch based ops, convolutions and pools alike:
+/// kernel shape, strides, dilations and padding of the spatial axes.
+///
+/// Output shapes, symbolic ones included, and the `PatchSpec` windows are
+/// gathered with all derive from it: a conv and a pool with the same window
+/// agree on padding and output sizes.
+#[derive(Debug, Clone, PartialEq, new)]
+pub struct Window {
+    pub kernel_shape: TVec<usize>,
+    /// One per spatial axis, or ones.
+    pub strides: Option<TVec<usize>>,
+    /// One per spatial axis, or ones.
+    pub dilations: Option<TVec<usize>>,
+    pub padding: PaddingSpec,
+}
+
+impl Window {
+    pub fn strides(&self) -> TVec<usize> {
+        self.strides.clone().unwrap_or_else(|| tvec!(1; self.kernel_shape.len()))
+    }
+
+    pub fn dilations(&self) -> TVec<usize> {
+        self.dilations.clone().unwrap_or_else(|| tvec!(1; self.kernel_shape.len()))
+    }
+
+    /// Output dimension and padding of each axis of `input_spatial_shape`.
+    pub fn output_dims<D: DimLike>(&self, input_spatial_shape: &[D]) -> TVec<ComputedPaddedDim<D>> {
+        self.padding.compute(
+            input_spatial_shape,
+            &*self.kernel_shape,
+            &*self.dilations(),
+            &*self.strides(),
+        )
+    }
+
+    /// `input_full_shape` with the output spatial dimensions, and `channels`
+    /// channels.
+    pub fn output_shape<D: DimLike>(
+        &self,
+        data_format: DataFormat,
+        input_full_shape: &[D],
+        channels: D,
+    ) -> TVec<D> {
+        let shape = data_format.shape(input_full_shape);
+        let mut output: TVec<D> = input_full_shape.into();
+        output[shape.c_axis()] = channels;
+        for (ix, d) in self.output_dims(shape.hw_dims()).into_iter().enumerate() {
+            output[shape.h_axis() + ix] = d.output;
+        }
+        output
+    }
+
+    /// The spec of the patch gathering the windows of `input_full_shape`.
+    pub fn patch_spec(&self, data_format: DataFormat, input_full_shape: &[usize]) -> PatchSpec {
+        PatchSpec::for_full_shape(data_format, input_full_shape)
+            .with_kernel_shape(self.kernel_shape.clone())
+            .with_strides(self.strides())
+            .with_dilations(self.dilations())
+            .with_padding(self.padding.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, KernelFormat, MaxPool, PoolSpec};
+    use ndarray::prelude::*;
+
+    #[test]
+    fn symbolic_and_patch_shapes_agree() {
+        let window = Window::new(tvec!(3, 2), Some(tvec!(2, 3)), None, PaddingSpec::SameUpper);
+        let full = window.output_shape(DataFormat::NHWC, &[1usize, 7, 8, 3], 5);
+        assert_eq!(&*full, &[1, 4, 3, 5]);
+        let patch = window.patch_spec(DataFormat::NHWC, &[1, 7, 8, 3]).into_patch();
+        assert_eq!(&*patch.output_shape, &full[1..3]);
+        // a symbolic dimension strided by 2 overflows the expression stack
+        let window = Window::new(tvec!(3, 2), Some(tvec!(1, 3)), None, PaddingSpec::SameUpper);
+        let streamed = window.output_shape(
+            DataFormat::NCHW,
+            &[1.to_dim(), 3.to_dim(), TDim::s(), 8.to_dim()],
+            5.to_dim(),
+        );
+        assert_eq!(streamed[1], 5.to_dim());
+        assert_eq!(streamed[2], TDim::s());
+        assert_eq!(streamed[3], 3.to_dim());
+    }
+
+    #[test]
+    fn conv_and_pool_agree_on_same_padding() {
+        let input = Array4::<f32>::zeros((1, 1, 7, 6)).into_arc_tensor();
+        for &stride in &[1, 2, 3] {
+            let strides = Some(tvec!(stride, stride));
+            let conv = Conv::new(
+                DataFormat::NCHW,
+                KernelFormat::OIHW,
+                None,
+                None,
+                PaddingSpec::SameUpper,
+                strides.clone(),
+                1,
+            );
+            let kernel = Array4::<f32>::zeros((1, 1, 3, 3));
+            let facts = conv_facts(input.clone(), kernel);
+            let conv = conv.to_unary(&facts).unwrap().unwrap();
+            let convolved = conv.eval(tvec!(input.clone())).unwrap().remove(0);
+            let spec =
+                PoolSpec::new(DataFormat::NCHW, tvec!(3, 3), PaddingSpec::SameUpper, strides);
+            let pooled = MaxPool::new(spec, None).eval(tvec!(input.clone())).unwrap().remove(0);
+            assert_eq!(convolved.shape(), pooled.shape(), "stride {}", stride);
+        }
+    }
+}
@@ -1,11 +1,406 @@
 use crate::internal::*;
+use ndarray::*;
 
-element_map!(Not, [bool], |a: bool| !a);
+// logical on booleans, bitwise on integers
+element_map!(Not, [bool, u8, u16, i8, i16, i32, i64], |a| !a);
 
-element_bin!(And, [bool] { |a, b| a & b});
-element_bin!(Or, [bool] { |a, b| a | b});
-element_bin!(Xor, [bool] { |a, b| a ^ b});
+element_bin!(And, [bool, u8, u16, i8, i16, i32, i64] { |a, b| a & b});
+element_bin!(Or, [bool, u8, u16, i8, i16, i32, i64] { |a, b| a | b});
+element_bin!(Xor, [bool, u8, u16, i8, i16, i32, i64] { |a, b| a ^ b});
 
-element_bin!(Equals, [bool, u8, i8, i16, i32, i64, f32, f64, TDim] => bool { |a,b| a==b });
-element_bin!(Lesser, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a<b });
-element_bin!(Greater, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a>b });
+/// The type two tensors are compared or selected between as: their common
+/// super type, or a float for an integer and a float, f64 for the integers
+/// f32 can not represent exactly.
+///
+/// Arithmetic ops do not promote integers to floats.
+pub fn comparison_type(a: &DatumType, b: DatumType) -> Option<DatumType> {
+    use DatumType::*;
+    if let Some(dt) = a.common_super_type(b) {
+        return Some(dt);
+    }
+    let promote = |int: DatumType, float: DatumType| match int {
+        U8 | U16 | I8 | I16 => F32.common_super_type(float),
+        I32 | I64 => Some(F64),
+        _ => None,
+    };
+    match (*a, b) {
+        (F16, int) | (F32, int) | (F64, int) => promote(int, *a),
+        (int, F16) | (int, F32) | (int, F64) => promote(int, b),
+        _ => None,
+    }
+}
+
+element_bin!(Equals, [bool, u8, i8, i16, i32, i64, f32, f64, TDim] => bool { |a,b| a==b }
+    super_type crate::ops::logic::comparison_type);
+element_bin!(Lesser, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a<b }
+    super_type crate::ops::logic::comparison_type);
+element_bin!(Greater, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a>b }
+    super_type crate::ops::logic::comparison_type);
+element_bin!(LesserEqual, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a<=b }
+    super_type crate::ops::logic::comparison_type);
+element_bin!(GreaterEqual, [u8, i8, i16, i32, i64, f32, f64] => bool { |a,b| a>=b }
+    super_type crate::ops::logic::comparison_type);
+
+/// Element-wise selection between two tensors according to a boolean
+/// condition (ONNX Where, TF Select).
+#[derive(Debug, Clone, new, Default)]
+pub struct Iff;
+
+impl Iff {
+    /// Reads `cond` as a comparison of a tensor with a scalar constant:
+    /// returns the tensor, the constant, and whether `cond` holds above it.
+    fn threshold(model: &TypedModel, cond: OutletId) -> TractResult<Option<(OutletId, f32, bool)>> {
+        let node = model.node(cond.node);
+        let above = if node.op_is::<Greater::Bin>()
+            || node.op_is::<Greater::UnaryA>()
+            || node.op_is::<GreaterEqual::Bin>()
+            || node.op_is::<GreaterEqual::UnaryA>()
+        {
+            true
+        } else if node.op_is::<Lesser::Bin>()
+            || node.op_is::<Lesser::UnaryA>()
+            || node.op_is::<LesserEqual::Bin>()
+            || node.op_is::<LesserEqual::UnaryA>()
+        {
+            false
+        } else {
+            return Ok(None);
+        };
+        let b = if node.inputs.len() == 2 {
+            model.outlet_fact(node.inputs[1])?.konst.clone()
+        } else {
+            node.op_as::<Greater::UnaryA>()
+                .map(|op| op.b.clone())
+                .or_else(|| node.op_as::<GreaterEqual::UnaryA>().map(|op| op.b.clone()))
+                .or_else(|| node.op_as::<Lesser::UnaryA>().map(|op| op.b.clone()))
+                .or_else(|| node.op_as::<LesserEqual::UnaryA>().map(|op| op.b.clone()))
+        };
+        match b {
+            Some(b) if b.shape().iter().product::<usize>() == 1 => {
+                Ok(Some((node.inputs[0], b.cast_to::<f32>()?.as_slice::<f32>()?[0], above)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Bounds (min, max) `value` is clipped to, if it is `x` or a Clip of it.
+    fn clipped(model: &TypedModel, value: OutletId, x: OutletId) -> Option<(f32, f32)> {
+        if value == x {
+            return Some((std::f32::NEG_INFINITY, std::f32::INFINITY));
+        }
+        let node = model.node(value.node);
+        let clip = node.op_as::<crate::ops::math::Clip>()?;
+        if node.inputs[0] == x {
+            Some((clip.min(), clip.max()))
+        } else {
+            None
+        }
+    }
+
+    fn eval_t<T: Datum>(
+        shape: &[usize],
+        cond: &ArrayViewD<bool>,
+        t: Arc<Tensor>,
+        f: Arc<Tensor>,
+    ) -> TractResult<Tensor> {
+        let mut result = ArrayD::<T>::default(shape);
+        Zip::from(&mut result)
+            .and_broadcast(cond)
+            .and_broadcast(&t.to_array_view::<T>()?)
+            .and_broadcast(&f.to_array_view::<T>()?)
+            .apply(|r, c, t, f| *r = if *c { t.clone() } else { f.clone() });
+        Ok(result.into())
+    }
+}
+
+impl Op for Iff {
+    fn name(&self) -> Cow<str> {
+        "Iff".into()
+    }
+
+    /// Rewrite selections between a f32 tensor and the constant it is
+    /// compared to as Relu or Clip: `Where(x > 0, x, 0)` is a Relu, and
+    /// `Where(x < lo, lo, Where(x > hi, hi, x))` a Clip.
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let (x, c, above) = match Self::threshold(model, node.inputs[0])? {
+            Some(threshold) => threshold,
+            None => return Ok(None),
+        };
+        let x_fact = model.outlet_fact(x)?;
+        if x_fact.datum_type != f32::datum_type()
+            || x_fact.shape != node.outputs[0].fact.shape
+            || node.outputs[0].fact.datum_type != f32::datum_type()
+        {
+            return Ok(None);
+        }
+        let is_c = |outlet: OutletId| -> TractResult<bool> {
+            Ok(match &model.outlet_fact(outlet)?.konst {
+                Some(k) if k.shape().iter().product::<usize>() == 1 => {
+                    k.cast_to::<f32>()?.as_slice::<f32>()?[0] == c
+                }
+                _ => false,
+            })
+        };
+        // floor: the result is max(value, c), min(value, c) otherwise
+        let (floor, value) = if is_c(node.inputs[1])? {
+            (!above, node.inputs[2])
+        } else if is_c(node.inputs[2])? {
+            (above, node.inputs[1])
+        } else {
+            return Ok(None);
+        };
+        let (min, max) = match Self::clipped(model, value, x) {
+            Some((min, max)) if min <= c && c <= max => {
+                if floor {
+                    (c, max)
+                } else {
+                    (min, c)
+                }
+            }
+            _ => return Ok(None),
+        };
+        let op: Box<Op> = if min == 0.0 && max == std::f32::INFINITY {
+            Box::new(crate::ops::nn::Relu::default())
+        } else {
+            Box::new(crate::ops::math::Clip::new(min, max))
+        };
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(model, x)?;
+        let id = patch.chain(&*node.name, op, tvec!(node.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(id, 0))?;
+        Ok(Some(patch))
+    }
+}
+
+impl StatelessOp for Iff {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (cond, t, f) = args_3!(inputs);
+        let dt = comparison_type(&t.datum_type(), f.datum_type()).ok_or_else(|| {
+            format!("Incompatible types {:?} and {:?}", t.datum_type(), f.datum_type())
+        })?;
+        let shape: TVec<usize> =
+            crate::broadcast::multi_broadcast(&[cond.shape(), t.shape(), f.shape()]).ok_or_else(
+                || {
+                    format!(
+                        "Incompatible shapes {:?}, {:?} and {:?}",
+                        cond.shape(),
+                        t.shape(),
+                        f.shape()
+                    )
+                },
+            )?;
+        // u8 masks are read as bool, any non zero value for true
+        let cond = cond.cast_to::<bool>()?;
+        let cond = cond.to_array_view::<bool>()?;
+        let t = t.cast_to_dt(dt)?.into_owned().into_arc_tensor();
+        let f = f.cast_to_dt(dt)?.into_owned().into_arc_tensor();
+        let c = dispatch_datum!(Self::eval_t(dt)(&*shape, &cond, t, f))?;
+        Ok(tvec!(c.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Iff {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 3)?;
+        check_output_arity(&outputs, 1)?;
+        s.given(&inputs[0].datum_type, move |_, dt| {
+            if dt != DatumType::Bool && dt != DatumType::U8 {
+                bail!("Condition must be a bool or u8 mask, got {:?}", dt);
+            }
+            Ok(())
+        })?;
+        s.given_2(&inputs[1].datum_type, &inputs[2].datum_type, move |s, t, f| {
+            let dt = comparison_type(&t, f)
+                .ok_or_else(|| format!("Incompatible types {:?} and {:?}", t, f))?;
+            s.equals(&outputs[0].datum_type, dt)
+        })?;
+        s.with(&inputs[0].shape, move |s, c| {
+            s.with(&inputs[1].shape, move |s, t| {
+                let c = c.clone();
+                s.with(&inputs[2].shape, move |s, f| {
+                    if let Ok(Some(shape)) =
+                        crate::analyser::helpers::infer_shape_broadcasting(&[&c, &t, &f])
+                    {
+                        s.equals(&outputs[0].shape, shape)?;
+                    }
+                    Ok(())
+                })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr1;
+
+    #[test]
+    fn scalar_threshold_broadcast() {
+        let x = rctensor1(&[1.0f32, 5.0, 3.0]);
+        let threshold = rctensor0(3.0f32);
+        let ge = GreaterEqual::default().eval(tvec!(x.clone(), threshold.clone())).unwrap();
+        assert_eq!(ge[0], rctensor1(&[false, true, true]));
+        let lt = Lesser::default().eval(tvec!(x, threshold)).unwrap();
+        assert_eq!(lt[0], rctensor1(&[true, false, false]));
+    }
+
+    #[test]
+    fn compare_promotes_int_to_float() {
+        let a = rctensor1(&[1i32, 2, 3]);
+        let b = rctensor0(2.5f32);
+        let gt = Greater::default().eval(tvec!(a, b)).unwrap();
+        assert_eq!(gt[0], rctensor1(&[false, false, true]));
+    }
+
+    #[test]
+    fn promotion_is_for_comparisons_only() {
+        use DatumType::*;
+        assert_eq!(comparison_type(&U8, F32), Some(F32));
+        assert_eq!(comparison_type(&I32, F32), Some(F64));
+        assert_eq!(comparison_type(&F16, I8), Some(F32));
+        assert_eq!(comparison_type(&I64, F64), Some(F64));
+        assert_eq!(comparison_type(&TDim, F32), None);
+        assert_eq!(comparison_type(&U8, I8), Some(I16));
+        assert_eq!(I32.common_super_type(F32), None);
+        let add = crate::ops::math::Add::default().eval(tvec!(rctensor0(1i32), rctensor0(2.5f32)));
+        assert!(add.is_err());
+    }
+
+    #[test]
+    fn compare_chained_into_iff() {
+        let x = rctensor1(&[-1.0f32, 2.0, -3.0, 4.0]);
+        let mask = Greater::default().eval(tvec!(x.clone(), rctensor0(0.0f32))).unwrap();
+        let zero = rctensor0(0.0f32);
+        let relu = Iff::default().eval(tvec!(mask[0].clone(), x, zero)).unwrap();
+        assert_eq!(*relu[0], Tensor::from(arr1(&[0.0f32, 2.0, 0.0, 4.0])));
+    }
+
+    #[test]
+    fn iff_u8_mask() {
+        let mask = rctensor1(&[0u8, 1, 2, 255]);
+        let t = rctensor1(&[1.0f32, 2.0, 3.0, 4.0]);
+        let found = Iff::default().eval(tvec!(mask.clone(), t, rctensor0(0.0f32))).unwrap();
+        assert_eq!(found[0], rctensor1(&[0.0f32, 2.0, 3.0, 4.0]));
+        assert_eq!(*mask.cast_to::<bool>().unwrap(), tensor1(&[false, true, true, true]));
+        let back = tensor1(&[false, true]).cast_to::<u8>().unwrap().into_owned();
+        assert_eq!(back, tensor1(&[0u8, 1]));
+    }
+
+    #[test]
+    fn iff_u8_mask_in_model() {
+        let mut model = InferenceModel::default();
+        let fact = |dt, len| TensorFact::dt_shape(dt, tvec!(len));
+        let mask = model.add_source("mask", fact(DatumType::U8, 3)).unwrap();
+        let x = model.add_source("x", fact(DatumType::F32, 3)).unwrap();
+        let zero = model.add_const("zero", rctensor0(0.0f32)).unwrap();
+        let iff = model.add_node_default("where", Iff::default()).unwrap();
+        for (ix, input) in [mask, x, zero].iter().enumerate() {
+            model.add_edge(OutletId::new(*input, 0), InletId::new(iff, ix)).unwrap();
+        }
+        model.set_output_outlets(&[OutletId::new(iff, 0)]).unwrap();
+        let model = model.into_typed().unwrap();
+        let found = SimplePlan::new(&model)
+            .unwrap()
+            .run(tvec!(tensor1(&[3u8, 0, 1]), tensor1(&[1.0f32, 2.0, 3.0])))
+            .unwrap();
+        assert_eq!(found[0], rctensor1(&[1.0f32, 0.0, 3.0]));
+        let mut bad = InferenceModel::default();
+        let cond = bad.add_source("cond", fact(DatumType::I32, 3)).unwrap();
+        let iff = bad.add_node_default("where", Iff::default()).unwrap();
+        bad.add_edge(OutletId::new(cond, 0), InletId::new(iff, 0)).unwrap();
+        bad.add_edge(OutletId::new(cond, 0), InletId::new(iff, 1)).unwrap();
+        bad.add_edge(OutletId::new(cond, 0), InletId::new(iff, 2)).unwrap();
+        bad.set_output_outlets(&[OutletId::new(iff, 0)]).unwrap();
+        assert!(bad.into_typed().is_err());
+    }
+
+    /// Where(x > t, x, 0) if `relu`, Where(x < -1, -1, Where(x > 2, 2, x))
+    /// otherwise. The first comparison reads `y` if `other`.
+    fn selection(relu: bool, other: bool) -> TypedModel {
+        let mut model = InferenceModel::default();
+        let fact = TensorFact::dt_shape(f32::datum_type(), tvec!(4));
+        let x = OutletId::new(model.add_source("x", fact.clone()).unwrap(), 0);
+        let y = OutletId::new(model.add_source("y", fact).unwrap(), 0);
+        let mut node = |name: &str, op: Box<Op>, inputs: &[OutletId]| {
+            let id = model.add_node_default(name, op).unwrap();
+            for (ix, &i) in inputs.iter().enumerate() {
+                model.add_edge(i, InletId::new(id, ix)).unwrap();
+            }
+            OutletId::new(id, 0)
+        };
+        let compared = if other { y } else { x };
+        let wire = if relu {
+            let zero =
+                node("zero", Box::new(crate::ops::konst::Const::new(rctensor0(0.0f32))), &[]);
+            let gt = node("gt", Box::new(Greater::default()), &[compared, zero]);
+            node("where", Box::new(Iff::default()), &[gt, x, zero])
+        } else {
+            let lo = node("lo", Box::new(crate::ops::konst::Const::new(rctensor0(-1.0f32))), &[]);
+            let hi = node("hi", Box::new(crate::ops::konst::Const::new(rctensor0(2.0f32))), &[]);
+            let gt = node("gt", Box::new(Greater::default()), &[compared, hi]);
+            let inner = node("inner", Box::new(Iff::default()), &[gt, hi, x]);
+            let lt = node("lt", Box::new(Lesser::default()), &[x, lo]);
+            node("outer", Box::new(Iff::default()), &[lt, lo, inner])
+        };
+        model.set_output_outlets(&[wire]).unwrap();
+        model.into_typed().unwrap()
+    }
+
+    fn check_rewrite(model: TypedModel) -> TypedModel {
+        let inputs =
+            || tvec!(tensor1(&[-3.0f32, -0.5, 1.0, 5.0]), tensor1(&[1.0f32, -1.0, 1.0, -1.0]));
+        let expected = SimplePlan::new(&model).unwrap().run(inputs()).unwrap();
+        let model = model.declutter().unwrap();
+        assert_eq!(SimplePlan::new(&model).unwrap().run(inputs()).unwrap(), expected);
+        model
+    }
+
+    #[test]
+    fn where_greater_zero_is_relu() {
+        let model = check_rewrite(selection(true, false));
+        assert!(model.nodes().iter().any(|n| n.op_is::<crate::ops::nn::Relu>()));
+        assert!(model.nodes().iter().all(|n| !n.op_is::<Iff>()));
+    }
+
+    #[test]
+    fn nested_where_is_clip() {
+        let model = check_rewrite(selection(false, false));
+        let clips: Vec<_> =
+            model.nodes().iter().filter_map(|n| n.op_as::<crate::ops::math::Clip>()).collect();
+        assert_eq!(clips.len(), 1);
+        assert_eq!((clips[0].min(), clips[0].max()), (-1.0, 2.0));
+        assert!(model.nodes().iter().all(|n| !n.op_is::<Iff>()));
+    }
+
+    #[test]
+    fn other_tensor_comparison_is_kept() {
+        let model = check_rewrite(selection(true, true));
+        assert!(model.nodes().iter().any(|n| n.op_is::<Iff>()));
+        let model = check_rewrite(selection(false, true));
+        assert!(model.nodes().iter().any(|n| n.op_is::<Iff>()));
+    }
+
+    #[test]
+    fn logic_ops() {
+        let a = rctensor1(&[true, true, false, false]);
+        let b = rctensor1(&[true, false, true, false]);
+        let and = And::default().eval(tvec!(a.clone(), b.clone())).unwrap();
+        assert_eq!(and[0], rctensor1(&[true, false, false, false]));
+        let or = Or::default().eval(tvec!(a.clone(), b.clone())).unwrap();
+        assert_eq!(or[0], rctensor1(&[true, true, true, false]));
+        let xor = Xor::default().eval(tvec!(a.clone(), b)).unwrap();
+        assert_eq!(xor[0], rctensor1(&[false, true, true, false]));
+        let not = Not::default().eval(tvec!(a)).unwrap();
+        assert_eq!(not[0], rctensor1(&[false, false, true, true]));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// ONNX `Loop`: runs `body` once per iteration, while the condition holds
+/// and up to the trip count.
+///
+/// The inputs are the trip count and the initial condition, each only if
+/// the matching flag is set, then the initial values of the loop-carried
+/// dependencies. The body inputs are the iteration number (an i64 scalar),
+/// the condition (a bool scalar), and the carried values. Its outputs are
+/// the next condition, the next carried values, then the scan outputs of
+/// the iteration.
+///
+/// The outputs are the final carried values, then each scan output stacked
+/// along a new first axis. Without a condition input, the condition the
+/// body computes is ignored: the loop runs the trip count out.
+#[derive(Debug, Clone, new)]
+pub struct Loop {
+    pub body: InferenceModel,
+    pub has_trip_count: bool,
+    pub has_condition: bool,
+}
+
+impl Loop {
+    fn optional_inputs(&self) -> usize {
+        self.has_trip_count as usize + self.has_condition as usize
+    }
+
+    /// Loop-carried dependencies, for `inputs` inputs to the loop.
+    fn carried(&self, inputs: usize) -> TractResult<usize> {
+        let carried = inputs.checked_sub(self.optional_inputs());
+        match carried {
+            Some(n) if self.body.input_outlets()?.len() == n + 2 => Ok(n),
+            _ => bail!(
+                "Loop body takes {} inputs, can not carry {} loop inputs",
+                self.body.input_outlets()?.len(),
+                inputs
+            ),
+        }
+    }
+
+    /// Scan outputs, for `carried` loop-carried dependencies.
+    fn scans(&self, carried: usize) -> TractResult<usize> {
+        let outputs = self.body.output_outlets()?.len();
+        outputs.checked_sub(carried + 1).ok_or_else(|| {
+            format!("Loop body has {} outputs, can not carry {} values", outputs, carried).into()
+        })
+    }
+
+    /// The single value of a scalar input.
+    fn scalar<T: Datum>(tensor: &Tensor, what: &str) -> TractResult<T> {
+        match tensor.as_slice::<T>()? {
+            [value] => Ok(value.clone()),
+            _ => bail!("Loop {} must be a scalar, got {:?}", what, tensor.shape()),
+        }
+    }
+
+    /// Iterations of the body output `ix`, stacked along a new first axis.
+    fn stack(&self, ix: usize, iterations: &[Arc<Tensor>]) -> TractResult<Tensor> {
+        if iterations.is_empty() {
+            let fact = self.body.outlet_fact(self.body.output_outlets()?[ix])?;
+            let dt = fact
+                .datum_type
+                .concretize()
+                .ok_or_else(|| format!("Can not tell the type of empty Loop body output {}", ix))?;
+            let mut shape = tvec!(0);
+            shape.extend(fact.shape.as_concrete_finite()?.unwrap_or(tvec!()));
+            return dispatch_datum!(Self::empty(dt)(&shape));
+        }
+        dispatch_copy!(Self::stack_t(iterations[0].datum_type())(iterations))
+    }
+
+    fn empty<T: Datum>(shape: &[usize]) -> TractResult<Tensor> {
+        Ok(ArrayD::<T>::default(shape).into())
+    }
+
+    fn stack_t<T: Datum + Copy>(iterations: &[Arc<Tensor>]) -> TractResult<Tensor> {
+        let views = iterations
+            .iter()
+            .map(|t| Ok(t.to_array_view::<T>()?.insert_axis(Axis(0))))
+            .collect::<TractResult<Vec<_>>>()?;
+        Ok(ndarray::stack(Axis(0), &*views)
+            .map_err(|e| format!("Loop scan outputs differ in shape: {}", e))?
+            .into())
+    }
+}
+
+impl Op for Loop {
+    fn name(&self) -> Cow<str> {
+        "Loop".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!(
+            "body of {} nodes, trip count: {}, condition: {}",
+            self.body.nodes().len(),
+            self.has_trip_count,
+            self.has_condition
+        )))
+    }
+}
+
+impl StatelessOp for Loop {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let n = self.carried(inputs.len())?;
+        let k = self.scans(n)?;
+        let mut inputs = inputs.into_iter();
+        let trip_count = if self.has_trip_count {
+            Some(Self::scalar::<i64>(&inputs.next().unwrap(), "trip count")?)
+        } else {
+            None
+        };
+        let mut cond = if self.has_condition {
+            Self::scalar::<bool>(&inputs.next().unwrap(), "condition")?
+        } else {
+            true
+        };
+        if trip_count.is_none() && !self.has_condition {
+            bail!("Loop needs a trip count or a condition not to run forever");
+        }
+        let mut carried: TVec<Arc<Tensor>> = inputs.collect();
+        let mut scans = vec![vec![]; k];
+        let plan = SimplePlan::new(&self.body)?;
+        let mut i = 0i64;
+        while cond && trip_count.map(|m| i < m).unwrap_or(true) {
+            let mut body_inputs = tvec!(tensor0(i), tensor0(cond));
+            body_inputs.extend(carried.drain().map(|t| t.into_tensor()));
+            let mut outputs = plan.run(body_inputs)?.into_iter();
+            let next_cond = Self::scalar::<bool>(&outputs.next().unwrap(), "body condition")?;
+            if self.has_condition {
+                cond = next_cond;
+            }
+            carried = outputs.by_ref().take(n).collect();
+            for (scan, output) in scans.iter_mut().zip(outputs) {
+                scan.push(output);
+            }
+            i += 1;
+        }
+        for (ix, iterations) in scans.iter().enumerate() {
+            carried.push(self.stack(1 + n + ix, iterations)?.into_arc_tensor());
+        }
+        Ok(carried)
+    }
+}
+
+impl InferenceRulesOp for Loop {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        let n = self.carried(inputs.len())?;
+        let k = self.scans(n)?;
+        check_output_arity(&outputs, n + k)?;
+        let carried = &inputs[self.optional_inputs()..];
+        if self.has_trip_count {
+            s.equals(&inputs[0].datum_type, i64::datum_type())?;
+        }
+        if self.has_condition {
+            s.equals(&inputs[self.has_trip_count as usize].datum_type, bool::datum_type())?;
+        }
+        for (input, output) in carried.iter().zip(outputs) {
+            s.equals(&input.datum_type, &output.datum_type)?;
+        }
+        let body_outputs = self.body.output_outlets()?;
+        for (ix, output) in outputs[n..].iter().enumerate() {
+            let fact = self.body.outlet_fact(body_outputs[1 + n + ix])?;
+            if let Some(dt) = fact.datum_type.concretize() {
+                s.equals(&output.datum_type, dt)?;
+            }
+            if let Some(shape) = fact.shape.concretize() {
+                s.equals(&output.rank, shape.len() as i32 + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::identity::Identity;
+    use crate::ops::logic::Greater;
+    use crate::ops::math::Sub;
+
+    /// Counts a scalar down by one while it stays positive, scanning the
+    /// values it counts down from.
+    fn countdown() -> InferenceModel {
+        let mut body = InferenceModel::default();
+        let scalar = |dt: DatumType| TensorFact::dt_shape(dt, TVec::<usize>::new());
+        body.add_source("iter", scalar(i64::datum_type())).unwrap();
+        body.add_source("cond", scalar(bool::datum_type())).unwrap();
+        let x = body.add_source("x", scalar(i64::datum_type())).unwrap();
+        let one = body.add_const("one", tensor0(1i64)).unwrap();
+        let zero = body.add_const("zero", tensor0(0i64)).unwrap();
+        let next = body.add_node_default("next", Sub::default()).unwrap();
+        body.add_edge(OutletId::new(x, 0), InletId::new(next, 0)).unwrap();
+        body.add_edge(OutletId::new(one, 0), InletId::new(next, 1)).unwrap();
+        let positive = body.add_node_default("positive", Greater::default()).unwrap();
+        body.add_edge(OutletId::new(next, 0), InletId::new(positive, 0)).unwrap();
+        body.add_edge(OutletId::new(zero, 0), InletId::new(positive, 1)).unwrap();
+        let scan = body.add_node_default("scan", Identity::default()).unwrap();
+        body.add_edge(OutletId::new(x, 0), InletId::new(scan, 0)).unwrap();
+        body.set_outlet_fact(OutletId::new(scan, 0), scalar(i64::datum_type())).unwrap();
+        body.set_output_outlets(&[
+            OutletId::new(positive, 0),
+            OutletId::new(next, 0),
+            OutletId::new(scan, 0),
+        ])
+        .unwrap();
+        body
+    }
+
+    /// The countdown, unrolled.
+    fn reference(trip_count: i64, mut x: i64, honor_cond: bool) -> (i64, Vec<i64>) {
+        let mut scan = vec![];
+        for _ in 0..trip_count {
+            scan.push(x);
+            x -= 1;
+            if honor_cond && x <= 0 {
+                break;
+            }
+        }
+        (x, scan)
+    }
+
+    fn run(op: &Loop, inputs: TVec<Tensor>) -> (i64, Vec<i64>) {
+        let outputs = op.eval(inputs.into_iter().map(|t| t.into_arc_tensor()).collect()).unwrap();
+        assert_eq!(outputs.len(), 2);
+        let scan = outputs[1].to_array_view::<i64>().unwrap();
+        assert_eq!(scan.ndim(), 1);
+        (*outputs[0].to_scalar::<i64>().unwrap(), scan.iter().cloned().collect())
+    }
+
+    #[test]
+    fn countdown_stops_on_condition_or_trip_count() {
+        let op = Loop::new(countdown(), true, true);
+        for &(m, x) in &[(10, 3), (2, 3), (10, 1), (10, 7)] {
+            let found = run(&op, tvec!(tensor0(m), tensor0(true), tensor0(x)));
+            assert_eq!(found, reference(m, x, true));
+        }
+        let while_loop = Loop::new(countdown(), false, true);
+        let found = run(&while_loop, tvec!(tensor0(true), tensor0(4i64)));
+        assert_eq!(found, (0, vec![4, 3, 2, 1]));
+        // the body condition is ignored without a condition input
+        let for_loop = Loop::new(countdown(), true, false);
+        let found = run(&for_loop, tvec!(tensor0(5i64), tensor0(3i64)));
+        assert_eq!(found, reference(5, 3, false));
+        assert_eq!(found.1, vec![3, 2, 1, 0, -1]);
+    }
+
+    #[test]
+    fn zero_iterations_leave_scans_empty() {
+        let op = Loop::new(countdown(), true, true);
+        for inputs in vec![
+            tvec!(tensor0(0i64), tensor0(true), tensor0(3i64)),
+            tvec!(tensor0(5i64), tensor0(false), tensor0(3i64)),
+        ] {
+            let outputs =
+                op.eval(inputs.into_iter().map(|t| t.into_arc_tensor()).collect()).unwrap();
+            assert_eq!(*outputs[0], tensor0(3i64));
+            assert_eq!(outputs[1].shape(), &[0]);
+            assert_eq!(outputs[1].datum_type(), i64::datum_type());
+        }
+        let endless = Loop::new(countdown(), false, false);
+        assert!(endless.eval(tvec!(rctensor0(3i64))).is_err());
+    }
+}
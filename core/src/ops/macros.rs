@@ -57,9 +57,9 @@ macro_rules! element_map {
                 check_output_arity(&outputs, 1)?;
                 s.given(&inputs[0].datum_type, move |s, dt| {
                     $(if dt == <$type>::datum_type() {
-                        s.equals(&outputs[0].datum_type, <$to>::datum_type())?;
+                        return s.equals(&outputs[0].datum_type, <$to>::datum_type());
                     })*
-                    Ok(())
+                    bail!("{} not covering {:?}", stringify!($Name), dt)
                 })?;
                 s.equals(&inputs[0].shape, &outputs[0].shape)
             }
@@ -70,6 +70,11 @@ macro_rules! element_map {
 #[macro_export]
 macro_rules! element_map_with_params {
     ($Name:ident, [$($type:ty),*], {$($pname:ident : $pty:ty),*}, $expr:item) => {
+        element_map_with_params!($Name, [$($type),*], {$($pname : $pty),*}, $expr;
+            codegen |_: &$Name, _: &TypedModel, _: &TypedNode| Ok(None));
+    };
+    ($Name:ident, [$($type:ty),*], {$($pname:ident : $pty:ty),*}, $expr:item;
+        codegen $codegen:expr) => {
         #[allow(unused_imports)]
         use $crate::internal::*;
 
@@ -96,6 +101,14 @@ macro_rules! element_map_with_params {
             fn name(&self) -> Cow<str> {
                 stringify!($Name).into()
             }
+
+            fn codegen(
+                &self,
+                model: &TypedModel,
+                node: &TypedNode,
+            ) -> TractResult<Option<TypedModelPatch>> {
+                ($codegen)(self, model, node)
+            }
         }
 
         impl InferenceRulesOp for $Name {
@@ -123,10 +136,35 @@ macro_rules! element_bin {
     ($name:ident, [$($type:ty),*] => $to:ty { $expr:expr }) => {
         element_bin!($name, match $($type => $to { $expr } ),*);
     };
+    ($name:ident, [$($type:ty),*] => $to:ty { $expr:expr } super_type $super_type:expr) => {
+        element_bin!($name, match $($type => $to { $expr } ),* ; check |_: &Tensor| Ok(());
+            declutter |_: &$crate::model::TypedModel, _: &$crate::model::TypedNode| Ok(None);
+            super_type $super_type);
+    };
     ($name:ident, [$($type:ty),*] { $expr:expr }) => {
         element_bin!($name, match $($type => $type { $expr } ),*);
     };
+    ($name:ident, [$($type:ty),*] { $expr:expr } check $check:expr) => {
+        element_bin!($name, match $($type => $type { $expr } ),* ; check $check);
+    };
     ($name:ident, match $($type:ty => $to:ty { $expr:expr }),*) => {
+        element_bin!($name, match $($type => $to { $expr } ),* ; check |_: &Tensor| Ok(()));
+    };
+    ($name:ident, match $($type:ty => $to:ty { $expr:expr }),* ; declutter $declutter:expr) => {
+        element_bin!($name, match $($type => $to { $expr } ),* ; check |_: &Tensor| Ok(());
+            declutter $declutter);
+    };
+    ($name:ident, match $($type:ty => $to:ty { $expr:expr }),* ; check $check:expr) => {
+        element_bin!($name, match $($type => $to { $expr } ),* ; check $check;
+            declutter |_: &$crate::model::TypedModel, _: &$crate::model::TypedNode| Ok(None));
+    };
+    ($name:ident, match $($type:ty => $to:ty { $expr:expr }),* ; check $check:expr;
+        declutter $declutter:expr) => {
+        element_bin!($name, match $($type => $to { $expr } ),* ; check $check;
+            declutter $declutter; super_type DatumType::common_super_type);
+    };
+    ($name:ident, match $($type:ty => $to:ty { $expr:expr }),* ; check $check:expr;
+        declutter $declutter:expr; super_type $super_type:expr) => {
         #[allow(non_snake_case)]
         pub mod $name {
             #[allow(unused_imports)]
@@ -138,16 +176,23 @@ macro_rules! element_bin {
                 Bin::default()
             }
 
+            fn super_type(a: DatumType, b: DatumType) -> Option<DatumType> {
+                let super_type: fn(&DatumType, DatumType) -> Option<DatumType> = $super_type;
+                super_type(&a, b)
+            }
+
             fn eval_bin(a: Arc<Tensor>, b: &Arc<Tensor>) -> TractResult<Arc<Tensor>> {
                 let shape:TVec<usize> = $crate::broadcast::multi_broadcast(&[a.shape(), b.shape()])
                     .ok_or_else(|| format!("Incompatible shapes {:?} and{:?}",
                                            a.shape(), b.shape()))?;
-                let dt = a.datum_type().common_super_type(b.datum_type())
+                let dt = super_type(a.datum_type(), b.datum_type())
                     .ok_or_else(|| format!("Incompatible types {:?} and{:?}",
                                            a.datum_type(), b.datum_type()))?;
                 $(if dt == <$type>::datum_type() {
                     let a = a.cast_to::<$type>()?.into_owned().into_array::<$type>()?;
                     let b = b.cast_to::<$type>()?;
+                    let check: fn(&Tensor) -> TractResult<()> = $check;
+                    check(&*b)?;
                     let mut c = $crate::ndarray::ArrayD::<$to>::default(&*shape);
                     $crate::ndarray::Zip::from(&mut c)
                         .and_broadcast(&a)
@@ -176,7 +221,12 @@ macro_rules! element_bin {
 
                 fn declutter(&self, model: &$crate::model::TypedModel, node: &$crate::model::TypedNode)
                  -> TractResult<Option<TypedModelPatch>> {
-                     let inputs = model.node_input_facts(node.id)?;
+                    let declutter: fn(&$crate::model::TypedModel, &$crate::model::TypedNode)
+                        -> TractResult<Option<TypedModelPatch>> = $declutter;
+                    if let Some(patch) = declutter(model, node)? {
+                        return Ok(Some(patch));
+                    }
+                    let inputs = model.node_input_facts(node.id)?;
                     if let Some(b) = inputs[1].konst.clone() {
                         let op = UnaryA { dt: self.0, b };
                         return Ok(Some(TypedModelPatch::single_unary_op(&model, &node, op)?));
@@ -241,17 +291,17 @@ macro_rules! element_bin {
                     let b = &inputs[1];
                     let c = &outputs[0];
 
+                    check_input_arity(&inputs, 2)?;
+                    check_output_arity(&outputs, 1)?;
                     let n = inputs.len();
                     s.given_all((0..n).map(|i| &inputs[i as usize].datum_type), move |s, dts| {
-                        let dt:DatumType = DatumType::super_type_for(dts.iter().cloned())
+                        let dt:DatumType = super_type(dts[0], dts[1])
                             .ok_or_else(|| format!("No supertype for {:?}", dts))?;
                         $(if dt == <$type>::datum_type() {
                             return s.equals(&outputs[0].datum_type, <$to>::datum_type());
                         })*
                         bail!("{} not covering {:?}", stringify!($name), dt)
                     })?;
-                    check_input_arity(&inputs, 2)?;
-                    check_output_arity(&outputs, 1)?;
                     s.with(&a.shape, move |s, a_shape| {
                         s.with(&b.shape, move |s, b_shape| {
                             if let Ok(Some(c_shape)) = $crate::analyser::helpers::infer_shape_broadcasting(&[&a_shape, &b_shape]) {
@@ -266,7 +316,7 @@ macro_rules! element_bin {
             #[derive(Debug, Clone, new)]
             pub struct UnaryA {
                 dt: TypeFact,
-                b: Arc<Tensor>,
+                pub b: Arc<Tensor>,
             }
 
             impl StatelessOp for UnaryA {
@@ -574,6 +624,7 @@ macro_rules! dispatch_datum {
             DatumType::F64  => $($path)::*::<f64>($($args),*),
             DatumType::TDim => $($path)::*::<TDim>($($args),*),
             DatumType::String => $($path)::*::<String>($($args),*),
+            DatumType::SequenceItem => $($path)::*::<$crate::datum::SequenceItem>($($args),*),
         }
     } }
 }
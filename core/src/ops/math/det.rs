@@ -0,0 +1,94 @@
+use crate::internal::*;
+use ndarray::*;
+
+/// Determinant of the square matrices in the last two dimensions of the
+/// input, batched over the leading ones, computed from their LU
+/// decomposition.
+#[derive(Debug, Clone, Default)]
+pub struct Det;
+
+impl Det {
+    fn eval_t<T: Datum + num_traits::Float>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let rank = input.ndim();
+        if rank < 2 || input.shape()[rank - 1] != input.shape()[rank - 2] {
+            bail!("Det expects square matrices in the last two axes, got {:?}", input.shape());
+        }
+        let n = input.shape()[rank - 1];
+        let batch = &input.shape()[..rank - 2];
+        let matrices = input.to_owned().into_shape((batch.iter().product(), n * n))?;
+        let dets: Vec<T> = matrices
+            .outer_iter()
+            .map(|matrix| tract_linalg::lu::det(n, &mut matrix.to_vec()))
+            .collect();
+        Ok(ArrayD::from_shape_vec(batch, dets)?.into())
+    }
+}
+
+impl Op for Det {
+    fn name(&self) -> Cow<str> {
+        "Det".into()
+    }
+}
+
+impl StatelessOp for Det {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = match input.datum_type() {
+            DatumType::F32 => self.eval_t::<f32>(&input)?,
+            DatumType::F64 => self.eval_t::<f64>(&input)?,
+            dt => bail!("Det not covering {:?}", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Det {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, outputs[0].rank.bex() + 2)?;
+        s.given(&inputs[0].rank, move |s, rank| {
+            let rank = rank as usize;
+            if rank < 2 {
+                bail!("Det expects square matrices in the last two axes, got a rank of {}", rank);
+            }
+            s.equals(&inputs[0].shape[rank - 1], &inputs[0].shape[rank - 2])?;
+            for ix in 0..rank - 2 {
+                s.equals(&inputs[0].shape[ix], &outputs[0].shape[ix])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn det(input: Tensor) -> TractResult<Tensor> {
+        Ok(Det.eval(tvec!(input.into_arc_tensor()))?.remove(0).into_tensor())
+    }
+
+    #[test]
+    fn batched() {
+        let input = tensor3(&[[[1.0f32, 2.0], [3.0, 4.0]], [[2.0, 0.0], [0.0, 3.0]]]);
+        assert_close!(det(input).unwrap(), tensor1(&[-2.0f32, 6.0]));
+        let input = tensor2(&[[2.0f64, 0.0, 1.0], [1.0, 3.0, 2.0], [1.0, 1.0, 2.0]]);
+        assert_close!(det(input).unwrap(), tensor0(6.0f64));
+    }
+
+    #[test]
+    fn singular_and_non_square() {
+        let input = tensor2(&[[1.0f32, 2.0], [2.0, 4.0]]);
+        assert_eq!(det(input).unwrap(), tensor0(0.0f32));
+        assert!(det(tensor2(&[[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]])).is_err());
+        assert!(det(tensor1(&[1.0f32])).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use std::ops::{Add, Mul};
+
+use num_traits::Zero;
+
+use crate::internal::*;
+use ndarray::*;
+
+use super::MatMul;
+
+/// Axes of an einsum equation, one letter per axis, for each input and the
+/// output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EinsumExpr {
+    pub inputs: Vec<Vec<char>>,
+    pub output: Vec<char>,
+}
+
+impl EinsumExpr {
+    /// Parse an equation like "bik,bkj->bij". Without "->", the output
+    /// axes are the letters appearing once, in alphabetical order. Ellipsis
+    /// is not supported.
+    pub fn parse(equation: &str) -> TractResult<EinsumExpr> {
+        let equation: String = equation.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut sides = equation.split("->");
+        let inputs = sides.next().unwrap();
+        let output = sides.next();
+        if sides.next().is_some() {
+            bail!("Einsum equation {:?} has more than one \"->\"", equation);
+        }
+        let letters = |s: &str| -> TractResult<Vec<char>> {
+            if let Some(c) = s.chars().find(|c| !c.is_ascii_alphabetic()) {
+                bail!("Unsupported character {:?} in einsum equation {:?}", c, equation);
+            }
+            Ok(s.chars().collect())
+        };
+        let inputs = inputs.split(',').map(letters).collect::<TractResult<Vec<_>>>()?;
+        let output = match output {
+            Some(output) => letters(output)?,
+            None => {
+                let mut once: Vec<char> = inputs
+                    .iter()
+                    .flatten()
+                    .cloned()
+                    .filter(|c| inputs.iter().flatten().filter(|d| *d == c).count() == 1)
+                    .collect();
+                once.sort();
+                once
+            }
+        };
+        for (ix, c) in output.iter().enumerate() {
+            if output[..ix].contains(c) {
+                bail!("Einsum output axis {} is repeated in {:?}", c, equation);
+            }
+            if !inputs.iter().any(|input| input.contains(c)) {
+                bail!("Einsum output axis {} is not an input axis in {:?}", c, equation);
+            }
+        }
+        Ok(EinsumExpr { inputs, output })
+    }
+
+    /// Axes summed over, by order of appearance.
+    pub fn summed(&self) -> Vec<char> {
+        let mut summed: Vec<char> = vec![];
+        for &c in self.inputs.iter().flatten() {
+            if !self.output.contains(&c) && !summed.contains(&c) {
+                summed.push(c);
+            }
+        }
+        summed
+    }
+
+    /// Whether this is a matrix product, batched over leading axes shared
+    /// by both inputs and the output: "ik,kj->ij", "bik,bkj->bij"...
+    pub fn is_mat_mul(&self) -> bool {
+        if self.inputs.len() != 2 {
+            return false;
+        }
+        let (a, b, c) = (&self.inputs[0], &self.inputs[1], &self.output);
+        let rank = a.len();
+        if rank < 2 || b.len() != rank || c.len() != rank {
+            return false;
+        }
+        let batch = &a[..rank - 2];
+        let (i, k, j) = (a[rank - 2], a[rank - 1], b[rank - 1]);
+        let mut distinct: Vec<char> = batch.iter().cloned().chain(vec![i, k, j]).collect();
+        distinct.sort();
+        distinct.dedup();
+        &b[..rank - 2] == batch
+            && &c[..rank - 2] == batch
+            && b[rank - 2] == k
+            && c[rank - 2] == i
+            && c[rank - 1] == j
+            && distinct.len() == rank + 1
+    }
+}
+
+/// ONNX `Einsum`: sums of products of the inputs over the axes missing
+/// from the output.
+///
+/// Batched float matrix products are lowered to `MatMul` when decluttering.
+/// The other equations, transposed operands included, are interpreted.
+#[derive(Debug, Clone, new)]
+pub struct Einsum {
+    pub expr: EinsumExpr,
+}
+
+impl Einsum {
+    fn eval_t<T>(&self, inputs: &[Arc<Tensor>]) -> TractResult<Tensor>
+    where
+        T: Datum + Copy + Zero + Add<Output = T> + Mul<Output = T>,
+    {
+        if inputs.len() != self.expr.inputs.len() {
+            bail!("Einsum {:?} expects {} inputs", self.expr, self.expr.inputs.len());
+        }
+        let views =
+            inputs.iter().map(|t| t.to_array_view::<T>()).collect::<TractResult<Vec<_>>>()?;
+        let mut dims: HashMap<char, usize> = HashMap::new();
+        for (view, axes) in views.iter().zip(self.expr.inputs.iter()) {
+            if view.ndim() != axes.len() {
+                bail!("Einsum input {:?} is of shape {:?}", axes, view.shape());
+            }
+            for (&c, &dim) in axes.iter().zip(view.shape()) {
+                if *dims.entry(c).or_insert(dim) != dim {
+                    bail!("Einsum axis {} is of dimensions {} and {}", c, dims[&c], dim);
+                }
+            }
+        }
+        let summed = self.expr.summed();
+        let output_shape: Vec<usize> = self.expr.output.iter().map(|c| dims[c]).collect();
+        let summed_shape: Vec<usize> = summed.iter().map(|c| dims[c]).collect();
+        // for each input axis, where its coordinate comes from
+        let sources: Vec<Vec<(bool, usize)>> = self
+            .expr
+            .inputs
+            .iter()
+            .map(|axes| {
+                axes.iter()
+                    .map(|c| match self.expr.output.iter().position(|o| o == c) {
+                        Some(pos) => (true, pos),
+                        None => (false, summed.iter().position(|s| s == c).unwrap()),
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut coords: Vec<TVec<usize>> = views.iter().map(|v| tvec!(0; v.ndim())).collect();
+        let output = ArrayD::from_shape_fn(&*output_shape, |out| {
+            let mut sum = T::zero();
+            for inner in indices(&*summed_shape) {
+                let mut product: Option<T> = None;
+                for ((view, source), coords) in views.iter().zip(&sources).zip(&mut coords) {
+                    for (coord, &(is_output, pos)) in coords.iter_mut().zip(source) {
+                        *coord = if is_output { out[pos] } else { inner[pos] };
+                    }
+                    let v = view[&**coords];
+                    product = Some(product.map(|p| p * v).unwrap_or(v));
+                }
+                sum = sum + product.unwrap_or(T::zero());
+            }
+            sum
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for Einsum {
+    fn name(&self) -> Cow<str> {
+        "Einsum".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        let inputs: Vec<String> = self.expr.inputs.iter().map(|i| i.iter().collect()).collect();
+        let output: String = self.expr.output.iter().collect();
+        Ok(Some(format!("{}->{}", inputs.join(","), output)))
+    }
+
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        // MatMul only multiplies floats
+        let dt = model.outlet_fact(node.inputs[0])?.datum_type;
+        if self.expr.is_mat_mul() && (dt == DatumType::F32 || dt == DatumType::F64) {
+            let patch =
+                TypedModelPatch::replace_single_op(model, node, &node.inputs, MatMul::default())?;
+            return Ok(Some(patch.with_label("lowered to MatMul")));
+        }
+        Ok(None)
+    }
+}
+
+impl StatelessOp for Einsum {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let output = match inputs.get(0).map(|t| t.datum_type()) {
+            Some(DatumType::F32) => self.eval_t::<f32>(&inputs)?,
+            Some(DatumType::F64) => self.eval_t::<f64>(&inputs)?,
+            Some(DatumType::I32) => self.eval_t::<i32>(&inputs)?,
+            Some(DatumType::I64) => self.eval_t::<i64>(&inputs)?,
+            dt => bail!("Einsum does not support {:?}", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Einsum {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, self.expr.inputs.len())?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].rank, self.expr.output.len() as i32)?;
+        // the first input holding each axis gives its dimension
+        let mut first: HashMap<char, (usize, usize)> = HashMap::new();
+        for (ix, (input, axes)) in inputs.iter().zip(self.expr.inputs.iter()).enumerate() {
+            s.equals(&input.datum_type, &outputs[0].datum_type)?;
+            s.equals(&input.rank, axes.len() as i32)?;
+            for (axis, c) in axes.iter().enumerate() {
+                match first.get(c) {
+                    Some(&(i, a)) => s.equals(&input.shape[axis], &inputs[i].shape[a])?,
+                    None => {
+                        first.insert(*c, (ix, axis));
+                    }
+                }
+            }
+        }
+        for (axis, c) in self.expr.output.iter().enumerate() {
+            let (i, a) = first[c];
+            s.equals(&outputs[0].shape[axis], &inputs[i].shape[a])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(equation: &str, inputs: TVec<Tensor>) -> Tensor {
+        let op = Einsum::new(EinsumExpr::parse(equation).unwrap());
+        let inputs = inputs.into_iter().map(|t| t.into_arc_tensor()).collect();
+        op.eval(inputs).unwrap().remove(0).into_tensor()
+    }
+
+    #[test]
+    fn parse() {
+        let expr = EinsumExpr::parse("bik, bkj -> bij").unwrap();
+        assert_eq!(expr.inputs, vec![vec!['b', 'i', 'k'], vec!['b', 'k', 'j']]);
+        assert_eq!(expr.summed(), vec!['k']);
+        assert!(expr.is_mat_mul());
+        // implicit output: the letters appearing once
+        assert_eq!(EinsumExpr::parse("jk,ki").unwrap().output, vec!['i', 'j']);
+        assert!(!EinsumExpr::parse("bqd,bkd->bqk").unwrap().is_mat_mul());
+        assert!(!EinsumExpr::parse("ik,kj->ji").unwrap().is_mat_mul());
+        assert!(!EinsumExpr::parse("ii,ij->ij").unwrap().is_mat_mul());
+        assert!(EinsumExpr::parse("...ij->ji").is_err());
+        assert!(EinsumExpr::parse("ij->jk").is_err());
+    }
+
+    #[test]
+    fn interpreted() {
+        let a = arr2(&[[1.0f32, 2.0], [3.0, 4.0]]);
+        assert_eq!(run("ij->ji", tvec!(a.clone().into())), Tensor::from(a.t().to_owned()));
+        assert_eq!(run("ii", tvec!(a.clone().into())), Tensor::from(arr0(5.0f32)));
+        assert_eq!(run("ij->", tvec!(a.clone().into())), Tensor::from(arr0(10.0f32)));
+        let b = arr2(&[[1i64, 0, 2], [0, 1, 3]]);
+        let found = run("ki,kj->ij", tvec!(b.clone().into(), b.clone().into()));
+        assert_eq!(found, Tensor::from(b.t().dot(&b)));
+    }
+
+    #[test]
+    fn batched_mat_mul_is_lowered() {
+        let a = Array3::from_shape_fn((2, 3, 4), |(b, i, k)| (b * 12 + i * 4 + k) as f32 / 8.0);
+        let b = Array3::from_shape_fn((2, 4, 5), |(b, k, j)| (b + k * 5 + j) as f32 / 4.0 - 1.0);
+        let mut model = TypedModel::default();
+        let fact = |shape: &[usize]| TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(shape),
+            konst: None,
+        };
+        let x = model.add_source("a", fact(&[2, 3, 4])).unwrap();
+        let y = model.add_source("b", fact(&[2, 4, 5])).unwrap();
+        let op = Einsum::new(EinsumExpr::parse("bik,bkj->bij").unwrap());
+        let einsum = model.add_node("einsum", op, tvec!(fact(&[2, 3, 5]))).unwrap();
+        model.add_edge(OutletId::new(x, 0), InletId::new(einsum, 0)).unwrap();
+        model.add_edge(OutletId::new(y, 0), InletId::new(einsum, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(einsum, 0)]).unwrap();
+        let inputs = tvec!(a.clone().into(), b.clone().into());
+        let interpreted = SimplePlan::new(&model).unwrap().run(inputs.clone()).unwrap();
+
+        let model = model.declutter().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<MatMul>()));
+        assert!(!model.nodes().iter().any(|n| n.op_is::<Einsum>()));
+        let lowered = SimplePlan::new(&model).unwrap().run(inputs).unwrap();
+        let expected = Array3::from_shape_fn((2, 3, 5), |(n, i, j)| {
+            (0..4).map(|k| a[(n, i, k)] * b[(n, k, j)]).sum::<f32>()
+        });
+        assert!(interpreted[0].close_enough(&expected.clone().into(), true));
+        assert!(lowered[0].close_enough(&expected.into(), true));
+    }
+}
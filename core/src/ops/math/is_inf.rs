@@ -0,0 +1,105 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+/// Infinite values mask. `detect_positive` and `detect_negative` select the
+/// infinities detected, as the ONNX attributes of the same names do.
+///
+/// Integers have no infinities: their mask is all false.
+#[derive(Debug, Clone, new)]
+pub struct IsInf {
+    pub detect_positive: bool,
+    pub detect_negative: bool,
+}
+
+impl Default for IsInf {
+    fn default() -> IsInf {
+        IsInf::new(true, true)
+    }
+}
+
+impl IsInf {
+    fn eval_t<T: Datum + num_traits::Float>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        Ok(input
+            .mapv(|x| {
+                x.is_infinite()
+                    && if x > T::zero() { self.detect_positive } else { self.detect_negative }
+            })
+            .into())
+    }
+}
+
+impl Op for IsInf {
+    fn name(&self) -> Cow<str> {
+        "IsInf".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("positive: {}, negative: {}", self.detect_positive, self.detect_negative)))
+    }
+}
+
+impl StatelessOp for IsInf {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = match input.datum_type() {
+            DatumType::F16 => self.eval_t::<f16>(&input)?,
+            DatumType::F32 => self.eval_t::<f32>(&input)?,
+            DatumType::F64 => self.eval_t::<f64>(&input)?,
+            DatumType::U8
+            | DatumType::U16
+            | DatumType::I8
+            | DatumType::I16
+            | DatumType::I32
+            | DatumType::I64 => ArrayD::from_elem(input.shape(), false).into(),
+            dt => bail!("IsInf not covering {:?}", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for IsInf {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, bool::datum_type())?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::math::IsNan;
+
+    fn mask(op: &StatelessOp, input: Tensor) -> Vec<bool> {
+        let output = op.eval(tvec!(input.into())).unwrap();
+        output[0].as_slice::<bool>().unwrap().to_vec()
+    }
+
+    #[test]
+    fn nan_and_infinities() {
+        use std::f32::{INFINITY, NAN, NEG_INFINITY};
+        let input = || tensor1(&[1.0f32, NAN, INFINITY, NEG_INFINITY, -0.0, std::f32::MAX]);
+        assert_eq!(mask(&IsNan::default(), input()), [false, true, false, false, false, false]);
+        assert_eq!(mask(&IsInf::default(), input()), [false, false, true, true, false, false]);
+        let positive = IsInf::new(true, false);
+        assert_eq!(mask(&positive, input()), [false, false, true, false, false, false]);
+        let negative = IsInf::new(false, true);
+        assert_eq!(mask(&negative, input()), [false, false, false, true, false, false]);
+        let input = tensor1(&[std::f64::NEG_INFINITY, 2.0]);
+        assert_eq!(mask(&IsInf::default(), input), [true, false]);
+    }
+
+    #[test]
+    fn integers_are_finite_numbers() {
+        assert_eq!(mask(&IsNan::default(), tensor1(&[0i32, -1])), [false, false]);
+        assert_eq!(mask(&IsInf::default(), tensor1(&[0u8, 255])), [false, false]);
+        assert!(IsInf::default().eval(tvec!(rctensor1(&[true]))).is_err());
+    }
+}
@@ -58,6 +58,15 @@ fn eval_t<T: Copy + Datum + LinalgScalar + FloatLike>(
     Ok(c.into_tensor())
 }
 
+fn cost(dt: DatumType, ashape: TVec<TDim>, bshape: TVec<TDim>) -> TractResult<TVec<(Cost, TDim)>> {
+    let (bc_a_shape, bc_b_shape, bc_c_shape) = infer_shapes(ashape, bshape)?;
+    let mul = bc_c_shape.iter().rev().skip(2).cloned().product::<TDim>();
+    let m = bc_a_shape[bc_a_shape.len() - 2];
+    let k = bc_a_shape[bc_a_shape.len() - 1];
+    let n = bc_b_shape[bc_b_shape.len() - 1];
+    Ok(tvec!((Cost::FMA(dt), (mul * m * k * n))))
+}
+
 fn infer_shapes<D: DimLike>(
     mut ashape: TVec<D>,
     mut bshape: TVec<D>,
@@ -166,14 +175,11 @@ impl Op for MatMul {
     }
 
     fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
-        let dt = inputs[0].datum_type;
-        let (bc_a_shape, bc_b_shape, bc_c_shape) =
-            infer_shapes(inputs[0].shape.iter().collect(), inputs[1].shape.iter().collect())?;
-        let mul = bc_c_shape.iter().rev().skip(2).cloned().product::<TDim>();
-        let m = bc_a_shape[bc_a_shape.len() - 2];
-        let k = bc_a_shape[bc_a_shape.len() - 1];
-        let n = bc_b_shape[bc_b_shape.len() - 1];
-        Ok(tvec!((Cost::FMA(dt), (mul * m * k * n))))
+        cost(
+            inputs[0].datum_type,
+            inputs[0].shape.iter().collect(),
+            inputs[1].shape.iter().collect(),
+        )
     }
 }
 
@@ -233,6 +239,16 @@ impl Op for MatMulUnaryA {
         "MatMulUnaryA".into()
     }
 
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let bshape = self.b.shape().iter().map(|d| d.to_dim()).collect();
+        let mut cost = cost(inputs[0].datum_type, inputs[0].shape.iter().collect(), bshape)?;
+        cost.push((
+            Cost::Params(self.b.datum_type()),
+            self.b.shape().iter().product::<usize>().to_dim(),
+        ));
+        Ok(cost)
+    }
+
     fn pulsify(
         &self,
         _source: &NormalizedModel,
@@ -510,6 +526,16 @@ impl Op for MatMulUnaryB {
     fn name(&self) -> Cow<str> {
         "MatMulUnaryB".into()
     }
+
+    fn cost(&self, inputs: &[&TypedTensorInfo]) -> TractResult<TVec<(Cost, TDim)>> {
+        let ashape = self.a.shape().iter().map(|d| d.to_dim()).collect();
+        let mut cost = cost(inputs[0].datum_type, ashape, inputs[0].shape.iter().collect())?;
+        cost.push((
+            Cost::Params(self.a.datum_type()),
+            self.a.shape().iter().product::<usize>().to_dim(),
+        ));
+        Ok(cost)
+    }
 }
 
 impl StatelessOp for MatMulUnaryB {
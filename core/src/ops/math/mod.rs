@@ -1,12 +1,21 @@
+mod det;
+mod einsum;
 pub mod gemm;
+mod is_inf;
 pub mod mat_mul;
+pub mod qgemm;
 
+pub use self::det::Det;
+pub use self::einsum::{Einsum, EinsumExpr};
 pub use self::gemm::Gemm;
+pub use self::is_inf::IsInf;
 pub use self::mat_mul::MatMul;
+pub use self::qgemm::{QGemm, Requantize};
 use crate::internal::*;
 use num_traits::AsPrimitive;
 use num_traits::Float;
 use num_traits::Zero;
+pub use tract_linalg::quant::{Overflow, OverflowArith};
 
 element_map!(Abs, [f16, f32, i32], |x| x.abs());
 element_map!(Exp, [f16, f32, f64], |x| x.exp());
@@ -17,15 +26,53 @@ element_map!(Rsqrt, [f16, f32], |x| x.sqrt().recip());
 
 element_map!(Ceil, [f16, f32, f64], |x| x.ceil());
 element_map!(Floor, [f16, f32, f64], |x| x.floor());
+element_map!(Round, [f16, f32, f64], |x| round_half_to_even(x));
+
+/// Round to nearest, ties to even (ONNX and TF semantics, unlike `round`).
+fn round_half_to_even<F: Float>(x: F) -> F {
+    let two = F::one() + F::one();
+    if (x - x.trunc()).abs() == two.recip() {
+        (x / two).round() * two
+    } else {
+        x.round()
+    }
+}
 
 element_map_with_params!(Clip, [f16, f32, f64], { min: f32, max: f32 },
     fn eval_one<T>(clip: &Clip, x:T) -> T
     where T: Datum+::num_traits::Float, f32: ::num_traits::AsPrimitive<T>
     {
         x.max(clip.min.as_()).min(clip.max.as_())
-    }
+    };
+    codegen clip_codegen
 );
 
+/// Lower f32 `Clip(0, 6)` to the vectorized `Relu6`.
+fn clip_codegen(
+    clip: &Clip,
+    model: &TypedModel,
+    node: &TypedNode,
+) -> TractResult<Option<TypedModelPatch>> {
+    if clip.min == 0.0
+        && clip.max == 6.0
+        && model.outlet_fact(node.inputs[0])?.datum_type == f32::datum_type()
+    {
+        let patch = TypedModelPatch::single_unary_op(model, node, crate::ops::nn::Relu6)?;
+        return Ok(Some(patch.with_label("lowered to Relu6")));
+    }
+    Ok(None)
+}
+
+impl Clip {
+    pub fn min(&self) -> f32 {
+        self.min
+    }
+
+    pub fn max(&self) -> f32 {
+        self.max
+    }
+}
+
 element_map!(Cos, [f16, f32, f64], |x| x.cos());
 element_map!(Sin, [f16, f32, f64], |x| x.sin());
 element_map!(Tan, [f16, f32, f64], |x| x.tan());
@@ -41,11 +88,152 @@ element_map!(Asinh, [f16, f32, f64], |x| x.asinh());
 element_map!(Atanh, [f16, f32, f64], |x| x.atanh());
 
 element_map!(Neg, [i8, i16, i32, i64, f16, f32, f64, TDim], |x| -x);
-element_bin!(Add, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a + b });
-element_bin!(Sub, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a - b });
-element_bin!(Mul, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a * b });
-element_bin!(Div, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a / b });
-element_bin!(Rem, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a % b });
+
+/// Fold a negated operand into the node, replaced by `negated`:
+/// `Add(x, Neg(y))` is `Sub(x, y)`, `Sub(x, Neg(y))` is `Add(x, y)`. The
+/// negated operand may come first if the node op commutes.
+fn fold_neg(
+    model: &TypedModel,
+    node: &TypedNode,
+    commutative: bool,
+    negated: impl Into<Box<Op>>,
+) -> TractResult<Option<TypedModelPatch>> {
+    let neg_input = |slot: usize| {
+        let prec = model.node(node.inputs[slot].node);
+        if prec.op_is::<Neg>() {
+            Some(prec.inputs[0])
+        } else {
+            None
+        }
+    };
+    let (x, y) = if let Some(y) = neg_input(1) {
+        (node.inputs[0], y)
+    } else if let Some(y) = neg_input(0).filter(|_| commutative) {
+        (node.inputs[1], y)
+    } else {
+        return Ok(None);
+    };
+    let mut patch = TypedModelPatch::default();
+    let x = patch.tap_model(model, x)?;
+    let y = patch.tap_model(model, y)?;
+    let id = patch.add_node(&*node.name, negated, tvec!(node.outputs[0].fact.clone()))?;
+    patch.add_edge(x, InletId::new(id, 0))?;
+    patch.add_edge(y, InletId::new(id, 1))?;
+    patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(id, 0))?;
+    Ok(Some(patch.with_label("folded negation")))
+}
+
+// integers wrap around, like ONNX ones: see the Saturating* ops otherwise
+element_bin!(Add, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::add_with(a, b, super::Overflow::Wrap) },
+     f16 => f16 { |a, b| a + b },
+     f32 => f32 { |a, b| a + b },
+     f64 => f64 { |a, b| a + b },
+     TDim => TDim { |a, b| a + b };
+     declutter |model, node| super::fold_neg(model, node, true, super::Sub::default())
+);
+element_bin!(Sub, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::sub_with(a, b, super::Overflow::Wrap) },
+     f16 => f16 { |a, b| a - b },
+     f32 => f32 { |a, b| a - b },
+     f64 => f64 { |a, b| a - b },
+     TDim => TDim { |a, b| a - b };
+     declutter |model, node| super::fold_neg(model, node, false, super::Add::default())
+);
+element_bin!(Mul, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::mul_with(a, b, super::Overflow::Wrap) },
+     f16 => f16 { |a, b| a * b },
+     f32 => f32 { |a, b| a * b },
+     f64 => f64 { |a, b| a * b },
+     TDim => TDim { |a, b| a * b }
+);
+element_bin!(SaturatingAdd, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::add_with(a, b, super::Overflow::Saturate) }
+);
+element_bin!(SaturatingSub, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::sub_with(a, b, super::Overflow::Saturate) }
+);
+element_bin!(SaturatingMul, match
+     u8 => u8 { |a:u8, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) },
+     u16 => u16 { |a:u16, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) },
+     i8 => i8 { |a:i8, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) },
+     i16 => i16 { |a:i16, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) },
+     i32 => i32 { |a:i32, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) },
+     i64 => i64 { |a:i64, b| super::OverflowArith::mul_with(a, b, super::Overflow::Saturate) }
+);
+element_bin!(Div, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a / b }
+             check super::nonzero_divisor);
+element_bin!(Rem, [u8, u16, i8, i16, i32, i64, f16, f32, f64, TDim] { |a, b| a % b }
+             check super::nonzero_divisor);
+element_bin!(FloorDiv, match
+     u8 => u8 { |a:u8, b| a / b },
+     u16 => u16 { |a:u16, b| a / b },
+     i8 => i8 { |a:i8, b| ::num_integer::Integer::div_floor(&a, &b) },
+     i16 => i16 { |a:i16, b| ::num_integer::Integer::div_floor(&a, &b) },
+     i32 => i32 { |a:i32, b| ::num_integer::Integer::div_floor(&a, &b) },
+     i64 => i64 { |a:i64, b| ::num_integer::Integer::div_floor(&a, &b) },
+     f32 => f32 { |a:f32, b: f32| (a / b).floor() },
+     f64 => f64 { |a:f64, b: f64| (a / b).floor() };
+     check super::nonzero_divisor
+);
+element_bin!(FloorMod, match
+     u8 => u8 { |a:u8, b| a % b },
+     u16 => u16 { |a:u16, b| a % b },
+     i8 => i8 { |a:i8, b| ::num_integer::Integer::mod_floor(&a, &b) },
+     i16 => i16 { |a:i16, b| ::num_integer::Integer::mod_floor(&a, &b) },
+     i32 => i32 { |a:i32, b| ::num_integer::Integer::mod_floor(&a, &b) },
+     i64 => i64 { |a:i64, b| ::num_integer::Integer::mod_floor(&a, &b) },
+     f32 => f32 { |a:f32, b| { let r = a % b; if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r } } },
+     f64 => f64 { |a:f64, b| { let r = a % b; if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r } } };
+     check super::nonzero_divisor
+);
+
+/// Integer division (and remainder) by zero is an error, not a panic.
+/// Floats are left alone, yielding infinities and NaNs.
+fn nonzero_divisor(b: &Tensor) -> TractResult<()> {
+    fn has_zero<T: Datum + Zero>(b: &Tensor) -> TractResult<bool> {
+        Ok(b.to_array_view::<T>()?.iter().any(|x| x.is_zero()))
+    }
+    let zero = match b.datum_type() {
+        DatumType::U8 => has_zero::<u8>(b)?,
+        DatumType::U16 => has_zero::<u16>(b)?,
+        DatumType::I8 => has_zero::<i8>(b)?,
+        DatumType::I16 => has_zero::<i16>(b)?,
+        DatumType::I32 => has_zero::<i32>(b)?,
+        DatumType::I64 => has_zero::<i64>(b)?,
+        DatumType::TDim => has_zero::<TDim>(b)?,
+        _ => false,
+    };
+    if zero {
+        bail!("Integer division by zero")
+    }
+    Ok(())
+}
 element_bin!(Pow, match
      f16 => f16 { |a:f16, b| a.powf(b) },
      f32 => f32 { |a:f32, b| a.powf(b) },
@@ -58,12 +246,44 @@ element_map!(Sign, match
      f64 => f64 { |a:f64| if a == 0.0 { 0.0 } else { a.signum()} }
 );
 
+// integers are never NaN
 element_map!(IsNan, match
      f16 => bool { |a:f16| a.is_nan() },
      f32 => bool { |a:f32| a.is_nan() },
-     f64 => bool { |a:f64| a.is_nan() }
+     f64 => bool { |a:f64| a.is_nan() },
+     u8 => bool { |_| false },
+     u16 => bool { |_| false },
+     i8 => bool { |_| false },
+     i16 => bool { |_| false },
+     i32 => bool { |_| false },
+     i64 => bool { |_| false }
 );
 
+/// `a << b`, zero when `b` is negative or not less than the bit width.
+fn shift_left<T: ::num_traits::PrimInt>(a: T, b: T) -> T {
+    let bits = ::std::mem::size_of::<T>() * 8;
+    match b.to_usize() {
+        Some(b) if b < bits => a << b,
+        _ => T::zero(),
+    }
+}
+
+/// `a >> b`, arithmetic on signed integers. When `b` is negative or not
+/// less than the bit width, all bits are shifted out: this leaves zero, or
+/// minus one for a negative `a`.
+fn shift_right<T: ::num_traits::PrimInt>(a: T, b: T) -> T {
+    let bits = ::std::mem::size_of::<T>() * 8;
+    match b.to_usize() {
+        Some(b) if b < bits => a >> b,
+        _ if a < T::zero() => a >> (bits - 1),
+        _ => T::zero(),
+    }
+}
+
+// shifting by the bit width or more is defined, unlike for Rust integers
+element_bin!(ShiftLeft, [u8, u16, i8, i16, i32, i64] { |a, b| super::shift_left(a, b) });
+element_bin!(ShiftRight, [u8, u16, i8, i16, i32, i64] { |a, b| super::shift_right(a, b) });
+
 fn fcmp<F: ::num_traits::Float>(a: &F, b: &F) -> ::std::cmp::Ordering {
     a.partial_cmp(b).unwrap()
 }
@@ -92,7 +312,195 @@ element_nary!(MeanN, match
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use ndarray::arr2;
+
+    fn bin(op: &StatelessOp, a: Tensor, b: Tensor) -> TractResult<Tensor> {
+        let mut outputs = op.eval(tvec!(a.into(), b.into()))?;
+        Ok(outputs.remove(0).into_tensor())
+    }
+
+    #[test]
+    fn i64_arithmetic() {
+        let a = || tensor1(&[7i64, -7, 7, -7, 1 << 40]);
+        let b = || tensor1(&[2i64, 2, -2, -2, 3]);
+        assert_eq!(
+            bin(&Add::default(), a(), b()).unwrap(),
+            tensor1(&[9i64, -5, 5, -9, (1 << 40) + 3])
+        );
+        assert_eq!(
+            bin(&Sub::default(), a(), b()).unwrap(),
+            tensor1(&[5i64, -9, 9, -5, (1 << 40) - 3])
+        );
+        assert_eq!(
+            bin(&Mul::default(), a(), b()).unwrap(),
+            tensor1(&[14i64, -14, -14, 14, 3 << 40])
+        );
+        assert_eq!(
+            bin(&Div::default(), a(), b()).unwrap(),
+            tensor1(&[3i64, -3, -3, 3, 366503875925])
+        );
+        assert_eq!(bin(&Rem::default(), a(), b()).unwrap(), tensor1(&[1i64, -1, 1, -1, 1]));
+        assert_eq!(bin(&FloorMod::default(), a(), b()).unwrap(), tensor1(&[1i64, 1, -1, -1, 1]));
+        assert_eq!(
+            bin(&crate::ops::logic::Lesser::default(), a(), b()).unwrap(),
+            tensor1(&[false, true, false, true, false])
+        );
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let a = || tensor1(&[100i8, -100, 100]);
+        let b = || tensor1(&[100i8, 100, 2]);
+        assert_eq!(bin(&Add::default(), a(), b()).unwrap(), tensor1(&[-56i8, 0, 102]));
+        assert_eq!(bin(&SaturatingAdd::default(), a(), b()).unwrap(), tensor1(&[127i8, 0, 102]));
+        assert_eq!(bin(&Sub::default(), b(), a()).unwrap(), tensor1(&[0i8, -56, -98]));
+        assert_eq!(bin(&SaturatingSub::default(), b(), a()).unwrap(), tensor1(&[0i8, 127, -98]));
+        assert_eq!(bin(&Mul::default(), a(), b()).unwrap(), tensor1(&[16i8, -16, -56]));
+        assert_eq!(bin(&SaturatingMul::default(), a(), b()).unwrap(), tensor1(&[127i8, -128, 127]));
+        let a = tensor1(&[30000i16, -30000]);
+        let b = tensor1(&[30000i16, 30000]);
+        assert_eq!(bin(&Add::default(), a.clone(), b.clone()).unwrap(), tensor1(&[-5536i16, 0]));
+        assert_eq!(
+            bin(&SaturatingMul::default(), a, b).unwrap(),
+            tensor1(&[std::i16::MAX, std::i16::MIN])
+        );
+    }
+
+    #[test]
+    fn i32_arithmetic() {
+        assert_eq!(
+            bin(&Div::default(), tensor1(&[-9i32, 9]), tensor1(&[4i32, -4])).unwrap(),
+            tensor1(&[-2i32, -2])
+        );
+        assert_eq!(
+            bin(&FloorMod::default(), tensor1(&[-9i32, 9]), tensor1(&[4i32, -4])).unwrap(),
+            tensor1(&[3i32, -3])
+        );
+    }
+
+    #[test]
+    fn floor_div_and_mod_negative_operands() {
+        let a = || tensor1(&[7i32, -7, 7, -7]);
+        let b = || tensor1(&[2i32, 2, -2, -2]);
+        assert_eq!(bin(&FloorDiv::default(), a(), b()).unwrap(), tensor1(&[3i32, -4, -4, 3]));
+        let a = || tensor1(&[5.5f32, -5.5, 5.5, -5.5]);
+        let b = || tensor1(&[2f32, 2., -2., -2.]);
+        assert_eq!(bin(&FloorDiv::default(), a(), b()).unwrap(), tensor1(&[2f32, -3., -3., 2.]));
+        assert_eq!(
+            bin(&FloorMod::default(), a(), b()).unwrap(),
+            tensor1(&[1.5f32, 0.5, -0.5, -1.5])
+        );
+        assert_eq!(bin(&Rem::default(), a(), b()).unwrap(), tensor1(&[1.5f32, -1.5, 1.5, -1.5]));
+    }
+
+    #[test]
+    fn round_half_to_even() {
+        let rounded = Round::default()
+            .eval(tvec!(rctensor1(&[2.5f32, 3.5, -2.5, -3.5, 0.5, 1.4, -1.6])))
+            .unwrap();
+        assert_eq!(*rounded[0], tensor1(&[2f32, 4., -2., -4., 0., 1., -2.]));
+    }
+
+    #[test]
+    fn integer_division_by_zero() {
+        assert!(bin(&Div::default(), tensor1(&[1i64, 2]), tensor1(&[1i64, 0])).is_err());
+        assert!(bin(&Rem::default(), tensor1(&[1i32]), tensor0(0i32)).is_err());
+        assert!(bin(&FloorMod::default(), tensor1(&[1i64]), tensor0(0i64)).is_err());
+        let inf = bin(&Div::default(), tensor1(&[1f32]), tensor0(0f32)).unwrap();
+        assert!(inf.as_slice::<f32>().unwrap()[0].is_infinite());
+    }
+
+    #[test]
+    fn bitwise_i32() {
+        use crate::ops::logic::{And, Not, Or, Xor};
+        let a = || tensor1(&[0x0f0fi32, -1, -256, 1, 12345]);
+        let b = || tensor1(&[4i32, 31, 32, 40, -1]);
+        assert_eq!(
+            bin(&ShiftLeft::default(), a(), b()).unwrap(),
+            tensor1(&[0x0f0f0i32, std::i32::MIN, 0, 0, 0])
+        );
+        assert_eq!(
+            bin(&ShiftRight::default(), a(), b()).unwrap(),
+            tensor1(&[0x0f0i32, -1, -1, 0, 0])
+        );
+        let b = || tensor0(0x00ffi32);
+        assert_eq!(
+            bin(&And::default(), a(), b()).unwrap(),
+            tensor1(&[0x000fi32, 0x00ff, 0, 1, 12345 & 0xff])
+        );
+        assert_eq!(
+            bin(&Or::default(), a(), b()).unwrap(),
+            tensor1(&[0x0fffi32, -1, -1, 0xff, 12345 | 0xff])
+        );
+        assert_eq!(
+            bin(&Xor::default(), a(), b()).unwrap(),
+            tensor1(&[0x0ff0i32, -256, -1, 0xfe, 12345 ^ 0xff])
+        );
+        let not = Not::default().eval(tvec!(a().into())).unwrap();
+        assert_eq!(*not[0], tensor1(&[!0x0f0fi32, 0, 255, -2, !12345]));
+    }
+
+    #[test]
+    fn shift_u8() {
+        let a = || tensor1(&[0x81u8, 0x81, 0x81, 0x81]);
+        let b = || tensor1(&[1u8, 7, 8, 255]);
+        assert_eq!(bin(&ShiftLeft::default(), a(), b()).unwrap(), tensor1(&[0x02u8, 0x80, 0, 0]));
+        assert_eq!(bin(&ShiftRight::default(), a(), b()).unwrap(), tensor1(&[0x40u8, 1, 0, 0]));
+    }
+
+    fn neg_model(op: impl Into<Box<Op>>, negated_slot: usize) -> InferenceModel {
+        let mut model = InferenceModel::default();
+        let fact = TensorFact::dt_shape(DatumType::I32, tvec!(3));
+        let x = model.add_source("x", fact.clone()).unwrap();
+        let y = model.add_source("y", fact).unwrap();
+        let neg = model.add_node_default("neg", Neg::default()).unwrap();
+        let bin = model.add_node_default("bin", op).unwrap();
+        model.add_edge(OutletId::new(y, 0), InletId::new(neg, 0)).unwrap();
+        // edges are added in slot order
+        let mut inputs = [OutletId::new(x, 0), OutletId::new(x, 0)];
+        inputs[negated_slot] = OutletId::new(neg, 0);
+        for (slot, input) in inputs.iter().enumerate() {
+            model.add_edge(*input, InletId::new(bin, slot)).unwrap();
+        }
+        model.set_output_outlets(&[OutletId::new(bin, 0)]).unwrap();
+        model
+    }
+
+    #[test]
+    fn negation_is_folded() {
+        let x = tensor1(&[7i32, -3, 0]);
+        let y = tensor1(&[2i32, 5, -1]);
+        let cases: [(Box<Op>, usize, &str, Tensor); 3] = [
+            (Box::new(Add::default()), 1, "Sub::Binary", tensor1(&[5i32, -8, 1])),
+            (Box::new(Add::default()), 0, "Sub::Binary", tensor1(&[5i32, -8, 1])),
+            (Box::new(Sub::default()), 1, "Add::Binary", tensor1(&[9i32, 2, -1])),
+        ];
+        for (op, slot, folded, expected) in cases.iter() {
+            let model = neg_model(op.clone(), *slot).into_typed().unwrap().declutter().unwrap();
+            let ops: Vec<_> = model.nodes().iter().map(|n| n.op().name().to_string()).collect();
+            assert!(!ops.iter().any(|op| op == "Neg"), "{:?}", ops);
+            assert!(ops.iter().any(|op| op == folded), "{:?}", ops);
+            let found = SimplePlan::new(&model).unwrap().run(tvec!(x.clone(), y.clone())).unwrap();
+            assert_eq!(*found[0], *expected);
+        }
+        // a negated first operand does not commute out of a subtraction
+        let model = neg_model(Sub::default(), 0).into_typed().unwrap().declutter().unwrap();
+        assert!(model.nodes().iter().any(|n| n.op_is::<Neg>()));
+    }
+
+    #[test]
+    fn neg() {
+        let neg = |t: Tensor| Neg::default().eval(tvec!(t.into())).map(|mut o| o.remove(0));
+        assert_eq!(*neg(tensor1(&[3i32, -4, 0])).unwrap(), tensor1(&[-3i32, 4, 0]));
+        assert_eq!(*neg(tensor1(&[1.5f32, -0.0])).unwrap(), tensor1(&[-1.5f32, 0.0]));
+        assert!(neg(tensor1(&[1u8])).is_err());
+        let mut model = InferenceModel::default();
+        model.add_source("x", TensorFact::dt_shape(DatumType::U8, tvec!(3))).unwrap();
+        model.chain_default("neg", Neg::default()).unwrap();
+        assert!(model.into_typed().is_err());
+    }
+
     #[test]
     fn mul() {
         let a = arr2(&[[1., 2.], [3., 4.]]);
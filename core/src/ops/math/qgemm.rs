@@ -0,0 +1,235 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+use tract_linalg::quant::{
+    mat_mul_i8_i32, quantize_multiplier, requantize_i32_to_i8, MatRef, Overflow, Rounding,
+};
+
+/// Scaling of the i32 accumulators of a `QGemm` to i8.
+#[derive(Debug, Clone, new)]
+pub struct Requantize {
+    /// a_scale * b_scale / output_scale, one for the whole output or one per
+    /// output column: per row of the weights of a dense layer, stored for
+    /// `trans_b`.
+    pub scales: TVec<f32>,
+    pub zero_point: i8,
+    #[new(value = "Rounding::HalfAwayFromZero")]
+    pub rounding: Rounding,
+}
+
+/// Gemm of int8 operands, Y = A . B + C, accumulated in i32.
+///
+/// A and B are offset by their zero points. C, if any, is an i32 bias in
+/// accumulator units (a_scale * b_scale), broadcast to the output. The
+/// output is the i32 accumulator, or i8 once requantized with
+/// `with_requantize`.
+#[derive(Debug, Clone, new)]
+pub struct QGemm {
+    trans_a: bool,
+    trans_b: bool,
+    have_c: bool,
+    #[new(default)]
+    a_zero_point: i8,
+    #[new(default)]
+    b_zero_point: i8,
+    #[new(default)]
+    requantize: Option<Requantize>,
+}
+
+impl QGemm {
+    /// Offset A and B by their zero points.
+    pub fn with_zero_points(self, a_zero_point: i8, b_zero_point: i8) -> QGemm {
+        QGemm { a_zero_point, b_zero_point, ..self }
+    }
+
+    /// Requantize the output to i8.
+    pub fn with_requantize(self, requantize: Requantize) -> QGemm {
+        QGemm { requantize: Some(requantize), ..self }
+    }
+
+    fn accumulate(&self, a: ArrayView2<i8>, b: ArrayView2<i8>) -> TractResult<Array2<i32>> {
+        let a = if self.trans_a { a.reversed_axes() } else { a };
+        let b = if self.trans_b { b.reversed_axes() } else { b };
+        let (m, k, n) = (a.rows(), a.cols(), b.cols());
+        if b.rows() != k {
+            bail!("Can not multiply {:?} by {:?}", a.shape(), b.shape());
+        }
+        let mut c = Array2::<i32>::zeros((m, n));
+        let (mut a_copy, mut b_copy) = (None, None);
+        mat_mul_i8_i32(
+            mat_ref(&a, &mut a_copy),
+            mat_ref(&b, &mut b_copy),
+            c.as_slice_mut().unwrap(),
+            Overflow::Wrap,
+        );
+        let (za, zb) = (self.a_zero_point as i32, self.b_zero_point as i32);
+        if za != 0 || zb != 0 {
+            // sum((a - za).(b - zb)) = sum(a.b) - zb.sum(a) - za.sum(b) + k.za.zb
+            let a_sums = a.map_axis(Axis(1), |r| r.iter().map(|&x| x as i32).sum::<i32>());
+            let b_sums = b.map_axis(Axis(0), |c| c.iter().map(|&x| x as i32).sum::<i32>());
+            for ((row, col), acc) in c.indexed_iter_mut() {
+                *acc = acc
+                    .wrapping_sub(zb * a_sums[row])
+                    .wrapping_sub(za * b_sums[col])
+                    .wrapping_add(k as i32 * za * zb);
+            }
+        }
+        Ok(c)
+    }
+
+    fn requantize(&self, acc: Array2<i32>, requantize: &Requantize) -> TractResult<Array2<i8>> {
+        let scales = &requantize.scales;
+        if scales.len() != 1 && scales.len() != acc.cols() {
+            bail!("Expected 1 or {} requantization scales, got {}", acc.cols(), scales.len());
+        }
+        if scales.iter().any(|&s| !(s > 0.0 && s.is_finite())) {
+            bail!("Requantization scales must be positive, got {:?}", scales);
+        }
+        let multipliers: TVec<(i32, i32)> =
+            scales.iter().map(|&s| quantize_multiplier(s as f64)).collect();
+        Ok(Array2::from_shape_fn(acc.raw_dim(), |(row, col)| {
+            let (multiplier, shift) = multipliers[if multipliers.len() == 1 { 0 } else { col }];
+            requantize_i32_to_i8(
+                acc[(row, col)],
+                multiplier,
+                shift,
+                requantize.zero_point,
+                requantize.rounding,
+            )
+        }))
+    }
+}
+
+/// `view` as a `MatRef`: read in place through its strides if it is
+/// contiguous, as transposed tensors are, copied to `copy` otherwise.
+fn mat_ref<'v>(view: &'v ArrayView2<i8>, copy: &'v mut Option<Array2<i8>>) -> MatRef<'v, i8> {
+    let (rows, cols) = view.dim();
+    if let (Some(data), &[rs, cs]) = (view.as_slice_memory_order(), view.strides()) {
+        if rs >= 0 && cs >= 0 {
+            return MatRef::new(data, (rows, cols), (rs as usize, cs as usize));
+        }
+    }
+    let copy = copy.get_or_insert_with(|| Array2::from_shape_fn((rows, cols), |ix| view[ix]));
+    MatRef::row_major(copy.as_slice().unwrap(), rows, cols)
+}
+
+impl Op for QGemm {
+    fn name(&self) -> Cow<str> {
+        "QGemm".into()
+    }
+}
+
+impl StatelessOp for QGemm {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let c = if self.have_c { inputs.pop() } else { None };
+        let (a, b) = args_2!(inputs);
+        let a = a.to_array_view::<i8>()?.into_dimensionality()?;
+        let b = b.to_array_view::<i8>()?.into_dimensionality()?;
+        let mut acc = self.accumulate(a, b)?;
+        if let Some(c) = c {
+            let bias = c.to_array_view::<i32>()?;
+            let bias = bias.broadcast(acc.raw_dim()).ok_or_else(|| {
+                format!("Incompatible broadcast: {:?} to {:?}", c.shape(), acc.shape())
+            })?;
+            acc.zip_mut_with(&bias, |acc, &b| *acc = acc.wrapping_add(b));
+        }
+        match &self.requantize {
+            Some(requantize) => Ok(tvec!(self.requantize(acc, requantize)?.into_arc_tensor())),
+            None => Ok(tvec!(acc.into_arc_tensor())),
+        }
+    }
+}
+
+impl InferenceRulesOp for QGemm {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if self.have_c {
+            check_input_arity(&inputs, 3)?;
+            s.equals(&inputs[2].datum_type, DatumType::I32)?;
+        } else {
+            check_input_arity(&inputs, 2)?;
+        };
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::I8)?;
+        s.equals(&inputs[1].datum_type, DatumType::I8)?;
+        let output_dt = if self.requantize.is_some() { DatumType::I8 } else { DatumType::I32 };
+        s.equals(&outputs[0].datum_type, output_dt)?;
+        s.equals(&inputs[0].rank, 2)?;
+        s.equals(&inputs[1].rank, 2)?;
+        s.equals(&outputs[0].rank, 2)?;
+        let (ca, ra) = if self.trans_a { (0, 1) } else { (1, 0) };
+        let (cb, rb) = if self.trans_b { (0, 1) } else { (1, 0) };
+        s.equals(&inputs[0].shape[ra], &outputs[0].shape[0])?;
+        s.equals(&inputs[0].shape[ca], &inputs[1].shape[rb])?;
+        s.equals(&inputs[1].shape[cb], &outputs[0].shape[1])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // y = x.W^T + bias, W stored (n, k) as dense layers do
+    #[test]
+    fn dense_layer_matches_reference() {
+        let (m, k, n) = (3, 5, 4);
+        let x = Array2::from_shape_fn((m, k), |(i, l)| ((i * 37 + l * 11) % 41) as i8 - 20);
+        let w = Array2::from_shape_fn((n, k), |(j, l)| ((j * 23 + l * 7) % 31) as i8 - 15);
+        let bias = Array1::from_shape_fn(n, |j| j as i32 * 50 - 70);
+        let (x_scale, x_zero_point) = (0.05f32, 3i8);
+        let w_scales = [0.02f32, 0.01, 0.03, 0.015];
+        let (y_scale, y_zero_point) = (0.1f32, -5i8);
+        let scales = w_scales.iter().map(|ws| x_scale * ws / y_scale).collect();
+        let op = QGemm::new(false, true, true)
+            .with_zero_points(x_zero_point, 0)
+            .with_requantize(Requantize::new(scales, y_zero_point));
+        let inputs = tvec!(
+            x.clone().into_arc_tensor(),
+            w.clone().into_arc_tensor(),
+            bias.clone().into_arc_tensor()
+        );
+        let found = op.eval(inputs).unwrap();
+        let found = found[0].to_array_view::<i8>().unwrap();
+        // dequantize, run the layer in f64, quantize the output
+        for i in 0..m {
+            for j in 0..n {
+                let w_scale = w_scales[j] as f64;
+                let mut y = bias[j] as f64 * x_scale as f64 * w_scale;
+                for l in 0..k {
+                    let x = (x[(i, l)] as f64 - x_zero_point as f64) * x_scale as f64;
+                    y += x * w[(j, l)] as f64 * w_scale;
+                }
+                let q = ((y / y_scale as f64).round() + y_zero_point as f64).max(-128.0).min(127.0);
+                assert!(
+                    (found[[i, j]] as f64 - q).abs() <= 1.0,
+                    "{} {}: {} vs {}",
+                    i,
+                    j,
+                    found[[i, j]],
+                    q
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transposed_weights_with_zero_points() {
+        let a = Array2::from_shape_fn((2, 3), |(i, l)| (i * 3 + l) as i8 * 20 - 50);
+        let b = Array2::from_shape_fn((3, 4), |(l, j)| 60 - (l * 4 + j) as i8 * 11);
+        let expected = Array2::from_shape_fn((2, 4), |(i, j)| {
+            (0..3).map(|l| (a[(i, l)] as i32 + 7) * (b[(l, j)] as i32 - 9)).sum::<i32>()
+        });
+        for &trans_b in &[false, true] {
+            let b =
+                if trans_b { Array2::from_shape_fn((4, 3), |(j, l)| b[(l, j)]) } else { b.clone() };
+            let op = QGemm::new(false, trans_b, false).with_zero_points(-7, 9);
+            let found = op.eval(tvec!(a.clone().into_arc_tensor(), b.into_arc_tensor())).unwrap();
+            assert_eq!(found[0].to_array_view::<i32>().unwrap(), expected.view().into_dyn());
+        }
+    }
+}
@@ -16,8 +16,11 @@ pub mod identity;
 pub mod image;
 pub mod konst;
 pub mod logic;
+pub mod loops;
 pub mod math;
 pub mod nn;
+pub mod random;
+pub mod signal;
 pub mod source;
 pub mod unimpl;
 
@@ -37,9 +40,28 @@ pub fn check_output_arity(outputs: &[TensorProxy], expected: usize) -> TractResu
     }
 }
 
+/// Map a possibly negative `axis` of `op` to an index in `0..rank`.
+pub fn normalize_axis(op: &str, axis: i64, rank: usize) -> TractResult<usize> {
+    let rank = rank as i64;
+    if -rank <= axis && axis < rank {
+        Ok(if axis < 0 { axis + rank } else { axis } as usize)
+    } else {
+        bail!("{}: axis {} out of range for rank {} (expected {}..{})", op, axis, rank, -rank, rank)
+    }
+}
+
+/// Like `normalize_axis`, for ops inserting an axis in a tensor of rank
+/// `rank`: `axis == rank` (and `-rank - 1`) appends a trailing axis.
+pub fn normalize_insertion_axis(op: &str, axis: i64, rank: usize) -> TractResult<usize> {
+    normalize_axis(op, axis, rank + 1)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Cost {
+    /// Multiply-accumulate operations.
     FMA(DatumType),
+    /// Weights held by the operator (constant inputs included).
+    Params(DatumType),
 }
 
 use crate::internal::*;
@@ -55,6 +77,23 @@ pub trait OpState: Debug + Send + objekt::Clone {
 
 pub trait StatelessOp: Op {
     fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>>;
+
+    /// Bytes of scratch `eval_with_scratch` takes for inputs of these
+    /// shapes. Zero for ops allocating their scratch themselves.
+    fn scratch_bytes(&self, _input_shapes: &[&[usize]]) -> TractResult<usize> {
+        Ok(0)
+    }
+
+    /// Same as `eval`, taking the scratch buffers of the evaluation from
+    /// `scratch`, which holds at least `scratch_bytes`. The outputs are
+    /// allocated as usual, as they outlive the evaluation.
+    fn eval_with_scratch(
+        &self,
+        inputs: TVec<Arc<Tensor>>,
+        _scratch: &mut cnn::ScratchAllocator,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        self.eval(inputs)
+    }
 }
 
 pub trait StatefullOp {
@@ -172,3 +211,84 @@ impl<O: Op> From<O> for Box<Op> {
         Box::new(it)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::array::{Concat, Gather, GatherElements, SplitToSequence};
+    use crate::ops::nn::{LayerHardmax, LayerLogSoftmax, LayerSoftmax, Reduce, Reducer};
+
+    #[test]
+    fn axes_table() {
+        // (axis, rank, as an existing axis, as an insertion point)
+        let table: &[(i64, usize, Option<usize>, Option<usize>)] = &[
+            (0, 3, Some(0), Some(0)),
+            (2, 3, Some(2), Some(2)),
+            (3, 3, None, Some(3)),
+            (4, 3, None, None),
+            (-1, 3, Some(2), Some(3)),
+            (-3, 3, Some(0), Some(1)),
+            (-4, 3, None, Some(0)),
+            (-5, 3, None, None),
+            (0, 0, None, Some(0)),
+            (-1, 0, None, Some(0)),
+        ];
+        for &(axis, rank, existing, insertion) in table {
+            assert_eq!(normalize_axis("Op", axis, rank).ok(), existing, "{} {}", axis, rank);
+            assert_eq!(
+                normalize_insertion_axis("Op", axis, rank).ok(),
+                insertion,
+                "{} {}",
+                axis,
+                rank
+            );
+        }
+    }
+
+    #[test]
+    fn error_names_op() {
+        let e = normalize_axis("Concat", 3, 3).unwrap_err();
+        assert_eq!(e.to_string(), "Concat: axis 3 out of range for rank 3 (expected -3..3)");
+    }
+
+    fn eval(op: &StatelessOp, inputs: TVec<Tensor>) -> TractResult<TVec<Arc<Tensor>>> {
+        op.eval(inputs.into_iter().map(|t| t.into_arc_tensor()).collect())
+    }
+
+    #[test]
+    fn ops_take_negative_axes() {
+        let x = || Tensor::from(ndarray::arr2(&[[1f32, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+        let concat = eval(&Concat::new(-1), tvec!(x(), x())).unwrap();
+        assert_eq!(concat[0].shape(), &[2, 6]);
+        let gather = eval(&Gather::new(-1), tvec!(x(), Tensor::from(ndarray::arr1(&[2i64]))));
+        assert_eq!(gather.unwrap()[0].shape(), &[2, 1]);
+        let sum = eval(&Reduce::new(Some(vec![-2]), false, Reducer::Sum), tvec!(x())).unwrap();
+        assert_eq!(*sum[0], Tensor::from(ndarray::arr1(&[5f32, 7.0, 9.0])));
+        let softmax = eval(&LayerSoftmax::new(-1), tvec!(x())).unwrap();
+        assert_eq!(softmax[0].shape(), &[2, 3]);
+        // a sequence of the 2 rows
+        let split = eval(&SplitToSequence::new(-2, false), tvec!(x())).unwrap();
+        assert_eq!(split[0].shape(), &[2]);
+    }
+
+    #[test]
+    fn ops_reject_out_of_range_axes() {
+        let x = || Tensor::from(ndarray::arr2(&[[1f32, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+        let i = || Tensor::from(ndarray::arr2(&[[0i64], [1]]));
+        let cases: Vec<(Box<StatelessOp>, TVec<Tensor>)> = vec![
+            (Box::new(Concat::new(2)), tvec!(x(), x())),
+            (Box::new(Gather::new(-3)), tvec!(x(), i())),
+            (Box::new(GatherElements::new(2)), tvec!(x(), i())),
+            (Box::new(Reduce::new(Some(vec![2]), true, Reducer::Sum)), tvec!(x())),
+            (Box::new(LayerHardmax::new(2)), tvec!(x())),
+            (Box::new(LayerLogSoftmax::new(-3)), tvec!(x())),
+            (Box::new(LayerSoftmax::new(2)), tvec!(x())),
+            (Box::new(SplitToSequence::new(2, true)), tvec!(x())),
+        ];
+        for (op, inputs) in cases {
+            let e = eval(&*op, inputs).unwrap_err().to_string();
+            assert!(e.starts_with(&*op.name()), "{}", e);
+            assert!(e.contains("out of range for rank 2"), "{}", e);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::internal::*;
+use ndarray::*;
+
+/*
+ * The channel blocked layout (NCHWc, NCHW8c for blocks of 8) splits the
+ * channels of a NCHW tensor in blocks, moving the channels of a block to a
+ * new innermost axis:
+ *
+ *   blocked[n][c / block][h][w][c % block] = plain[n][c][h][w]
+ *
+ * The lanes of the last block past the channel count are zeros. Ops working
+ * in this layout rely on it: a zero lane contributes nothing to a sum, so
+ * they can run over whole blocks.
+ */
+
+/// Blocks of `block` channels covering `channels`.
+pub fn channel_blocks(channels: usize, block: usize) -> usize {
+    (channels + block - 1) / block
+}
+
+/// Shape of a NCHW shape in blocks of `block` channels.
+pub fn channel_blocked_shape<D: DimLike>(plain: &[D], block: usize) -> TractResult<TVec<D>> {
+    if plain.len() < 3 || block == 0 {
+        bail!("Can not split the channels of {:?} in blocks of {}", plain, block);
+    }
+    let mut blocked: TVec<D> = plain.into();
+    blocked[1] = plain[1].div_ceil(block);
+    blocked.push(block.into());
+    Ok(blocked)
+}
+
+/// Converts a NCHW tensor to channel blocks of `block` channels.
+#[derive(Debug, Clone, new)]
+pub struct ToChannelBlocks {
+    pub block: usize,
+}
+
+impl ToChannelBlocks {
+    fn eval_t<T: Datum>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let channels = input.shape()[1];
+        let shape = channel_blocked_shape(input.shape(), self.block)?;
+        let rank = shape.len();
+        let output = ArrayD::from_shape_fn(&*shape, |coords| {
+            let c = coords[1] * self.block + coords[rank - 1];
+            if c < channels {
+                let mut plain: TVec<usize> = coords.slice()[..rank - 1].into();
+                plain[1] = c;
+                input[&*plain].clone()
+            } else {
+                T::default()
+            }
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for ToChannelBlocks {
+    fn name(&self) -> Cow<str> {
+        "ToChannelBlocks".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("NCHW{}c", self.block)))
+    }
+
+    /// A conversion undoing a conversion from the same blocks is a no-op:
+    /// this removes the round trips between two blocked ops.
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let prec = model.node(node.inputs[0].node);
+        match prec.op_as::<FromChannelBlocks>() {
+            Some(from) if from.block == self.block => {
+                let mut patch = TypedModelPatch::default();
+                let tap = patch.tap_model(model, prec.inputs[0])?;
+                patch.shunt_outside(OutletId::new(node.id, 0), tap)?;
+                Ok(Some(patch.with_label("channel blocks round trip")))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl StatelessOp for ToChannelBlocks {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, &input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for ToChannelBlocks {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&outputs[0].rank, inputs[0].rank.bex() + 1)?;
+        s.given(&inputs[0].shape, move |s, shape| {
+            s.equals(&outputs[0].shape, ShapeFact::from(channel_blocked_shape(&shape, self.block)?))
+        })
+    }
+}
+
+/// Converts channel blocks of `block` channels back to a NCHW tensor of
+/// `channels` channels, dropping the padding lanes.
+#[derive(Debug, Clone, new)]
+pub struct FromChannelBlocks {
+    pub block: usize,
+    pub channels: usize,
+}
+
+impl FromChannelBlocks {
+    pub fn output_shape<D: DimLike>(&self, blocked: &[D]) -> TractResult<TVec<D>> {
+        let rank = blocked.len();
+        let blocks = blocked.get(1).and_then(|b| b.to_integer().ok());
+        if rank < 4
+            || blocked[rank - 1].to_integer().ok() != Some(self.block as i32)
+            || blocks
+                .map(|b| b as usize != channel_blocks(self.channels, self.block))
+                .unwrap_or(false)
+        {
+            bail!(
+                "{:?} does not hold {} channels in blocks of {}",
+                blocked,
+                self.channels,
+                self.block
+            );
+        }
+        let mut plain: TVec<D> = blocked[..rank - 1].into();
+        plain[1] = self.channels.into();
+        Ok(plain)
+    }
+
+    fn eval_t<T: Datum>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let shape = self.output_shape(input.shape())?;
+        let output = ArrayD::from_shape_fn(&*shape, |coords| {
+            let mut blocked: TVec<usize> = coords.slice().into();
+            blocked[1] = coords[1] / self.block;
+            blocked.push(coords[1] % self.block);
+            input[&*blocked].clone()
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for FromChannelBlocks {
+    fn name(&self) -> Cow<str> {
+        "FromChannelBlocks".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("NCHW{}c, {} channels", self.block, self.channels)))
+    }
+}
+
+impl StatelessOp for FromChannelBlocks {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, &input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for FromChannelBlocks {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, outputs[0].rank.bex() + 1)?;
+        s.given(&inputs[0].shape, move |s, shape| {
+            s.equals(&outputs[0].shape, ShapeFact::from(self.output_shape(&shape)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pads_last_block_and_round_trips() {
+        let input = Array4::from_shape_fn((2, 5, 3, 2), |(n, c, h, w)| {
+            (n * 1000 + c * 100 + h * 10 + w) as i32 + 1
+        });
+        let blocked = ToChannelBlocks::new(4).eval(tvec!(input.clone().into_arc_tensor())).unwrap();
+        let view = blocked[0].to_array_view::<i32>().unwrap();
+        assert_eq!(view.shape(), &[2, 2, 3, 2, 4]);
+        assert_eq!(view[[1, 1, 2, 1, 0]], input[[1, 4, 2, 1]]);
+        assert_eq!(view[[0, 0, 1, 0, 3]], input[[0, 3, 1, 0]]);
+        assert!(view.slice(s![.., 1, .., .., 1..]).iter().all(|&x| x == 0));
+        let plain = FromChannelBlocks::new(4, 5).eval(blocked).unwrap();
+        assert_eq!(plain[0].to_array_view::<i32>().unwrap(), input.into_dyn());
+    }
+
+    #[test]
+    fn rejects_mismatched_blocks() {
+        let blocked = Array5::<f32>::zeros((1, 2, 1, 1, 4)).into_arc_tensor();
+        assert!(FromChannelBlocks::new(4, 9).eval(tvec!(blocked.clone())).is_err());
+        assert!(FromChannelBlocks::new(2, 8).eval(tvec!(blocked)).is_err());
+    }
+}
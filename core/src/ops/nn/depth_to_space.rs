@@ -0,0 +1,153 @@
+use crate::internal::*;
+use ndarray::*;
+
+use super::DataFormat;
+
+/// Order of the (block row, block column, channel) split of the input
+/// channels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DepthToSpaceMode {
+    /// Block row, block column, then channel: TF and ONNX default.
+    DCR,
+    /// Channel, block row, then block column: ONNX CRD, PyTorch PixelShuffle.
+    CRD,
+}
+
+/// Rearranges blocks of channels into spatial blocks: a (C * r * r, H, W)
+/// image becomes (C, H * r, W * r), `r` being the block size.
+///
+/// This is the pixel shuffle of sub-pixel convolutions.
+#[derive(Debug, Clone, new)]
+pub struct DepthToSpace {
+    pub block_size: usize,
+    pub mode: DepthToSpaceMode,
+    pub data_format: DataFormat,
+}
+
+impl DepthToSpace {
+    /// The input channel going to channel `c` at (`i`, `j`) in its block.
+    pub fn source_channel(&self, c: usize, i: usize, j: usize, channels: usize) -> usize {
+        let r = self.block_size;
+        match self.mode {
+            DepthToSpaceMode::DCR => (i * r + j) * channels + c,
+            DepthToSpaceMode::CRD => (c * r + i) * r + j,
+        }
+    }
+
+    pub fn output_shape<D: DimLike>(&self, input: &[D]) -> TractResult<TVec<D>> {
+        let shape = self.data_format.shape(input);
+        if shape.hw_rank() != 2 {
+            bail!("DepthToSpace expects an image, got a shape of {:?}", input);
+        }
+        let r = self.block_size;
+        let channels = shape.c().to_integer().map(|c| c as usize);
+        if self.block_size == 0 || channels.map(|c| c % (r * r) != 0).unwrap_or(false) {
+            bail!("Can not split {:?} channels in {}x{} blocks", shape.c(), r, r);
+        }
+        let mut output: TVec<D> = input.into();
+        output[shape.c_axis()] = shape.c() / (r * r);
+        for axis in shape.hw_axes() {
+            output[axis] = input[axis] * r;
+        }
+        Ok(output)
+    }
+
+    /// Shuffle `input` into `output`, which has the output shape.
+    pub fn shuffle_into<T: Datum>(
+        &self,
+        input: ArrayViewD<T>,
+        mut output: ArrayViewMutD<T>,
+    ) -> TractResult<()> {
+        let shape = self.data_format.shape(input.shape());
+        if output.shape() != &*self.output_shape(input.shape())? {
+            bail!("Can not shuffle {:?} into {:?}", input.shape(), output.shape());
+        }
+        let (c_axis, h_axis) = (shape.c_axis(), shape.h_axis());
+        let r = self.block_size;
+        let channels = shape.c() / (r * r);
+        for c in 0..channels {
+            let mut output = output.index_axis_mut(Axis(c_axis), c);
+            for i in 0..r {
+                for j in 0..r {
+                    let source =
+                        input.index_axis(Axis(c_axis), self.source_channel(c, i, j, channels));
+                    // the channel axis is gone, the spatial ones may have moved
+                    let h = if c_axis < h_axis { h_axis - 1 } else { h_axis };
+                    let mut block = output.view_mut();
+                    block.slice_axis_inplace(Axis(h), Slice::new(i as isize, None, r as isize));
+                    block.slice_axis_inplace(Axis(h + 1), Slice::new(j as isize, None, r as isize));
+                    block.assign(&source);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn eval_t<T: Datum>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let mut output = ArrayD::<T>::default(&*self.output_shape(input.shape())?);
+        self.shuffle_into(input, output.view_mut())?;
+        Ok(output.into())
+    }
+}
+
+impl Op for DepthToSpace {
+    fn name(&self) -> Cow<str> {
+        "DepthToSpace".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("{:?} blocks of {}, {:?}", self.mode, self.block_size, self.data_format)))
+    }
+}
+
+impl StatelessOp for DepthToSpace {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_datum!(Self::eval_t(input.datum_type())(self, &input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for DepthToSpace {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, 4)?;
+        s.equals(&outputs[0].rank, 4)?;
+        s.given(&inputs[0].shape, move |s, shape| {
+            s.equals(&outputs[0].shape, ShapeFact::from(self.output_shape(&shape[..])?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pixel_shuffle() {
+        // PyTorch PixelShuffle(2) of 0..8 in (1, 4, 1, 2)
+        let input = Array4::from_shape_vec((1, 4, 1, 2), (0..8).collect()).unwrap();
+        let op = DepthToSpace::new(2, DepthToSpaceMode::CRD, DataFormat::NCHW);
+        let output = op.eval(tvec!(input.into_arc_tensor())).unwrap();
+        let expected = Array4::from_shape_vec((1, 1, 2, 4), vec![0, 2, 1, 3, 4, 6, 5, 7]).unwrap();
+        assert_eq!(output[0].to_array_view::<i32>().unwrap(), expected.into_dyn());
+    }
+
+    #[test]
+    fn depth_to_space_nhwc() {
+        // TF depth_to_space doc example, blocks of 2 over (1, 1, 1, 4)
+        let input = Array4::from_shape_vec((1, 1, 1, 4), vec![1, 2, 3, 4]).unwrap();
+        let op = DepthToSpace::new(2, DepthToSpaceMode::DCR, DataFormat::NHWC);
+        let output = op.eval(tvec!(input.into_arc_tensor())).unwrap();
+        let expected = Array4::from_shape_vec((1, 2, 2, 1), vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(output[0].to_array_view::<i32>().unwrap(), expected.into_dyn());
+    }
+}
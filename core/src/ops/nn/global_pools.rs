@@ -1,6 +1,8 @@
 use crate::internal::*;
 use ndarray::prelude::*;
 
+use super::max_with_index;
+
 #[derive(Debug, Clone, new, Default)]
 pub struct GlobalAvgPool {
     //    data_is_nhwc: bool, // default is nchw (onnx)
@@ -110,12 +112,21 @@ impl InferenceRulesOp for GlobalLpPool {
     }
 }
 
+/// Max over the spatial axes, optionally outputting the index of each max
+/// too, like `MaxPool`.
 #[derive(Debug, Clone, new, Default)]
 pub struct GlobalMaxPool {
     //    data_is_nhwc: bool, // default is nchw (onnx)
+    #[new(default)]
+    with_index_outputs: Option<DatumType>,
 }
 
 impl GlobalMaxPool {
+    /// Also output the spatial index of each max, as `dt`.
+    pub fn with_index_outputs(self, dt: DatumType) -> GlobalMaxPool {
+        GlobalMaxPool { with_index_outputs: Some(dt) }
+    }
+
     fn eval_t<D: Datum + ::num_traits::Float>(
         &self,
         input: Arc<Tensor>,
@@ -128,12 +139,17 @@ impl GlobalMaxPool {
             *dim = 1;
         }
         let divisor = array.len() / (n * c);
-        let result: Tensor = array
-            .into_shape(((n * c), divisor))?
-            .fold_axis(Axis(1), D::min_value(), |a, b| a.max(*b))
-            .into_shape(final_shape)?
-            .into();
-        Ok(tvec!(result.into()))
+        let maxes = array.into_shape(((n * c), divisor))?.map_axis(Axis(1), |image| {
+            max_with_index(image.iter().cloned().enumerate()).unwrap_or((0, D::min_value()))
+        });
+        let values = maxes.map(|max| max.1).into_shape(&*final_shape)?.into_arc_tensor();
+        if let Some(dt) = self.with_index_outputs {
+            let indices = maxes.map(|max| max.0 as i64).into_shape(&*final_shape)?;
+            let indices = indices.into_tensor().cast_to_dt(dt)?.into_owned();
+            Ok(tvec!(values, indices.into_arc_tensor()))
+        } else {
+            Ok(tvec!(values))
+        }
     }
 }
 
@@ -157,7 +173,12 @@ impl InferenceRulesOp for GlobalMaxPool {
         inputs: &'p [TensorProxy],
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
-        rules(solver, inputs, outputs)
+        check_output_arity(&outputs, 1 + self.with_index_outputs.is_some() as usize)?;
+        if let Some(idt) = self.with_index_outputs {
+            solver.equals(&outputs[1].datum_type, idt)?;
+            solver.equals(&outputs[1].shape, &outputs[0].shape)?;
+        }
+        rules(solver, inputs, &outputs[..1])
     }
 }
 
@@ -178,3 +199,27 @@ fn rules<'r, 'p: 'r, 's: 'r>(
         Ok(())
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn max_and_index_in_one_pass() {
+        let input = Array4::from_shape_fn((2, 3, 2, 3), |(n, c, y, x)| {
+            ((n + c * 5 + y * 2 + x) % 3) as f32
+        })
+        .into_arc_tensor();
+        let values = GlobalMaxPool::default().eval(tvec!(input.clone())).unwrap();
+        let op = GlobalMaxPool::default().with_index_outputs(DatumType::I32);
+        let outputs = op.eval(tvec!(input.clone())).unwrap();
+        assert_eq!(outputs[0], values[0]);
+        let input = input.to_array_view::<f32>().unwrap().into_shape((6, 6)).unwrap();
+        let indices = outputs[1].to_array_view::<i32>().unwrap();
+        assert_eq!(indices.shape(), &[2, 3, 1, 1]);
+        for (image, &index) in input.outer_iter().zip(indices.iter()) {
+            let max = image.fold(std::f32::MIN, |a, &b| a.max(b));
+            assert_eq!(image.iter().position(|&v| v == max).unwrap(), index as usize);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::internal::*;
+use ndarray::*;
+use num_traits::{Float, FromPrimitive};
+
+/// Interpolation between the pixels around a sampling point.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GridSampleMode {
+    Bilinear,
+    /// The nearest pixel, halves going to the even one.
+    Nearest,
+}
+
+/// Values read out of the input.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GridSamplePadding {
+    /// Pixels out of the input are zeros.
+    Zeros,
+    /// Coordinates are clamped to the input.
+    Border,
+    /// Coordinates are reflected by the borders of the input, then clamped.
+    Reflection,
+}
+
+/// Samples a (N, C, H, W) input at the points of a (N, H', W', 2) grid,
+/// giving a (N, C, H', W') output, like PyTorch `grid_sample` and ONNX
+/// `GridSample`.
+///
+/// Grid points are (x, y) pairs, normalized to [-1, 1] over the input. With
+/// `align_corners`, -1 and 1 are the centers of the corner pixels, otherwise
+/// their outer edges.
+#[derive(Debug, Clone, new)]
+pub struct GridSample {
+    pub mode: GridSampleMode,
+    pub padding_mode: GridSamplePadding,
+    pub align_corners: bool,
+}
+
+impl GridSample {
+    /// Pixel coordinate of the normalized `coord`, on an axis of `len`.
+    fn unnormalize<T: Float + FromPrimitive>(&self, coord: T, len: usize) -> T {
+        let one = T::one();
+        let two = T::from_f32(2.0).unwrap();
+        let len = T::from_usize(len).unwrap();
+        if self.align_corners {
+            (coord + one) / two * (len - one)
+        } else {
+            ((coord + one) * len - one) / two
+        }
+    }
+
+    /// `coord` moved in the input by the padding mode, if it does.
+    fn pad<T: Float + FromPrimitive>(&self, coord: T, len: usize) -> T {
+        let max = T::from_usize(len).unwrap() - T::one();
+        let clamp = |x: T| x.max(T::zero()).min(max);
+        match self.padding_mode {
+            GridSamplePadding::Zeros => coord,
+            GridSamplePadding::Border => clamp(coord),
+            GridSamplePadding::Reflection => {
+                // between the centers or the edges of the border pixels
+                let half = T::from_f32(0.5).unwrap();
+                let (low, high) =
+                    if self.align_corners { (T::zero(), max) } else { (-half, max + half) };
+                let span = high - low;
+                if span <= T::zero() {
+                    return T::zero();
+                }
+                let x = (coord - low).abs();
+                let flips = (x / span).floor();
+                let extra = x - flips * span;
+                let two = T::from_f32(2.0).unwrap();
+                let reflected = if flips % two == T::zero() { low + extra } else { high - extra };
+                clamp(reflected)
+            }
+        }
+    }
+
+    fn sample<T: Datum + Float + FromPrimitive>(&self, plane: ArrayView2<T>, x: T, y: T) -> T {
+        let (h, w) = plane.dim();
+        let (x, y) = (self.pad(self.unnormalize(x, w), w), self.pad(self.unnormalize(y, h), h));
+        let at = |y: T, x: T| match (y.to_isize(), x.to_isize()) {
+            (Some(y), Some(x)) if y >= 0 && x >= 0 && (y as usize) < h && (x as usize) < w => {
+                plane[(y as usize, x as usize)]
+            }
+            _ => T::zero(),
+        };
+        match self.mode {
+            GridSampleMode::Nearest => {
+                let round = |v: T| {
+                    let r = v.round();
+                    let two = T::from_f32(2.0).unwrap();
+                    if (r - v).abs() == T::from_f32(0.5).unwrap() && r % two != T::zero() {
+                        r - (r - v).signum()
+                    } else {
+                        r
+                    }
+                };
+                at(round(y), round(x))
+            }
+            GridSampleMode::Bilinear => {
+                let one = T::one();
+                let (y0, x0) = (y.floor(), x.floor());
+                let (dy, dx) = (y - y0, x - x0);
+                at(y0, x0) * (one - dy) * (one - dx)
+                    + at(y0, x0 + one) * (one - dy) * dx
+                    + at(y0 + one, x0) * dy * (one - dx)
+                    + at(y0 + one, x0 + one) * dy * dx
+            }
+        }
+    }
+
+    fn eval_t<T: Datum + Float + FromPrimitive>(
+        &self,
+        input: &Tensor,
+        grid: &Tensor,
+    ) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?.into_dimensionality::<Ix4>()?;
+        let grid = grid.to_array_view::<T>()?.into_dimensionality::<Ix4>()?;
+        let (n, c, _, _) = input.dim();
+        let (grid_n, h, w, pair) = grid.dim();
+        if grid_n != n || pair != 2 {
+            bail!("Can not sample {:?} on a grid of {:?}", input.shape(), grid.shape());
+        }
+        let output = Array4::from_shape_fn((n, c, h, w), |(n, c, y, x)| {
+            let plane = input.slice(s![n, c, .., ..]);
+            self.sample(plane, grid[(n, y, x, 0)], grid[(n, y, x, 1)])
+        });
+        Ok(output.into())
+    }
+}
+
+impl Op for GridSample {
+    fn name(&self) -> Cow<str> {
+        "GridSample".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!(
+            "{:?}, {:?} padding, align corners: {}",
+            self.mode, self.padding_mode, self.align_corners
+        )))
+    }
+}
+
+impl StatelessOp for GridSample {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (input, grid) = args_2!(inputs);
+        let output = match input.datum_type() {
+            DatumType::F32 => self.eval_t::<f32>(&input, &grid)?,
+            DatumType::F64 => self.eval_t::<f64>(&input, &grid)?,
+            dt => bail!("GridSample does not support {:?}", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for GridSample {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[1].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, 4)?;
+        s.equals(&inputs[1].rank, 4)?;
+        s.equals(&outputs[0].rank, 4)?;
+        s.equals(&inputs[1].shape[3], 2.to_dim())?;
+        s.equals(&outputs[0].shape[0], &inputs[0].shape[0])?;
+        s.equals(&outputs[0].shape[0], &inputs[1].shape[0])?;
+        s.equals(&outputs[0].shape[1], &inputs[0].shape[1])?;
+        s.equals(&outputs[0].shape[2], &inputs[1].shape[1])?;
+        s.equals(&outputs[0].shape[3], &inputs[1].shape[2])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn run(op: &GridSample, input: Array4<f32>, grid: Array4<f32>) -> Array4<f32> {
+        let output = op.eval(tvec!(input.into_arc_tensor(), grid.into_arc_tensor())).unwrap();
+        output[0].to_array_view::<f32>().unwrap().into_dimensionality().unwrap().to_owned()
+    }
+
+    /// The 4x4 ramp sampled on a 6x6 grid, of the ONNX GridSample example.
+    fn ramp_and_grid() -> (Array4<f32>, Array4<f32>) {
+        let input = Array4::from_shape_fn((1, 1, 4, 4), |(_, _, y, x)| (y * 4 + x) as f32);
+        let grid = Array4::from_shape_fn((1, 6, 6, 2), |(_, y, x, axis)| {
+            let i = if axis == 0 { x } else { y };
+            -1.0 + 0.4 * i as f32
+        });
+        (input, grid)
+    }
+
+    #[test]
+    fn bilinear_zeros() {
+        let (input, grid) = ramp_and_grid();
+        let op = GridSample::new(GridSampleMode::Bilinear, GridSamplePadding::Zeros, false);
+        let found = run(&op, input, grid);
+        let expected = arr2(&[
+            [0.0f32, 0.15, 0.55, 0.95, 1.35, 0.75],
+            [0.6, 1.5, 2.3, 3.1, 3.9, 2.1],
+            [2.2, 4.7, 5.5, 6.3, 7.1, 3.7],
+            [3.8, 7.9, 8.7, 9.5, 10.3, 5.3],
+            [5.4, 11.1, 11.9, 12.7, 13.5, 6.9],
+            [3.0, 6.15, 6.55, 6.95, 7.35, 3.75],
+        ]);
+        for (f, e) in found.iter().zip(expected.iter()) {
+            assert!((f - e).abs() < 1e-5, "found {} expected {}", f, e);
+        }
+        let aligned = GridSample { align_corners: true, ..op };
+        let (input, grid) = ramp_and_grid();
+        let found = run(&aligned, input, grid);
+        // corners are the corner pixels, the rest steps by 3 * 0.2 pixels
+        assert_eq!(found[(0, 0, 0, 0)], 0.0);
+        assert_eq!(found[(0, 0, 5, 5)], 15.0);
+        assert!((found[(0, 0, 0, 1)] - 0.6).abs() < 1e-5);
+    }
+
+    #[test]
+    fn out_of_range_points_by_padding_mode() {
+        let input = arr2(&[[1.0f32, 2.0], [3.0, 4.0]]).into_shape((1, 1, 2, 2)).unwrap();
+        // (x, y): far left, past the right edge, in the bottom right corner
+        let grid =
+            arr2(&[[-3.0f32, -1.0], [1.5, 0.0], [1.0, 1.0]]).into_shape((1, 1, 3, 2)).unwrap();
+        let cases = [
+            (GridSamplePadding::Zeros, false, [0.0f32, 0.0, 1.0]),
+            (GridSamplePadding::Border, false, [1.0, 3.0, 4.0]),
+            // x = -3 is -2.5, two pixels past the left edge: the right edge
+            (GridSamplePadding::Reflection, false, [2.0, 3.0, 4.0]),
+            (GridSamplePadding::Zeros, true, [0.0, 2.25, 4.0]),
+            // x = -3 is -1, reflected on the left pixel to the right one
+            (GridSamplePadding::Reflection, true, [2.0, 2.75, 4.0]),
+        ];
+        for &(padding, align_corners, expected) in &cases {
+            let op = GridSample::new(GridSampleMode::Bilinear, padding, align_corners);
+            let found = run(&op, input.clone(), grid.clone());
+            assert_eq!(found.as_slice().unwrap(), &expected, "{:?} {}", padding, align_corners);
+        }
+        let nearest = GridSample::new(GridSampleMode::Nearest, GridSamplePadding::Border, false);
+        let found = run(&nearest, input.clone(), grid.clone());
+        // y = 0.5 rounds to the even 0
+        assert_eq!(found.as_slice().unwrap(), &[1.0, 2.0, 4.0]);
+    }
+}
@@ -1,36 +1,42 @@
 use crate::internal::*;
+use ndarray::*;
 
 #[derive(Debug, Clone, new, Default)]
 pub struct LayerHardmax {
     axis: isize,
+    #[new(default)]
+    single_axis: bool,
 }
 
 impl LayerHardmax {
+    /// One-hot of the max of each lane along `axis`, see `map_layers`.
+    pub fn single_axis(axis: isize) -> LayerHardmax {
+        LayerHardmax { axis, single_axis: true }
+    }
+
     fn eval_t<D: Datum + ::num_traits::Float + ::num_traits::FromPrimitive>(
         &self,
         input: Arc<Tensor>,
     ) -> TractResult<TVec<Arc<Tensor>>> {
-        let array = input.into_tensor().into_array::<D>()?;
-        let shape = array.shape().to_vec();
-        let axis =
-            if self.axis < 0 { shape.len() as isize + self.axis } else { self.axis } as usize;
-        let first_dim: usize = array.shape()[0..axis].iter().product();
-        let second_dim: usize = array.len() / first_dim;
-        let mut array = array.into_shape((first_dim, second_dim))?;
-        array.outer_iter_mut().for_each(|mut layer| {
-            let max = layer
-                .iter()
-                .enumerate()
-                .rev()
-                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(b.0.cmp(&a.0)))
-                .map(|(ix, _)| ix)
-                .unwrap_or(0);
-            layer
-                .iter_mut()
-                .enumerate()
-                .for_each(|(ix, r)| *r = D::from_usize((ix == max) as usize).unwrap());
-        });
-        Ok(tvec!(array.into_shape(shape)?.into_arc_tensor()))
+        map_layers(
+            "LayerHardmax",
+            input,
+            self.axis,
+            self.single_axis,
+            |mut layer: ArrayViewMut1<D>| {
+                let max = layer
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(b.0.cmp(&a.0)))
+                    .map(|(ix, _)| ix)
+                    .unwrap_or(0);
+                layer
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(ix, r)| *r = D::from_usize((ix == max) as usize).unwrap());
+            },
+        )
     }
 }
 
@@ -54,38 +60,43 @@ impl InferenceRulesOp for LayerHardmax {
         inputs: &'p [TensorProxy],
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
-        rules(solver, inputs, outputs)
+        rules(solver, inputs, outputs, "LayerHardmax", self.axis)
     }
 }
 
 #[derive(Debug, Clone, new, Default)]
 pub struct LayerLogSoftmax {
     axis: isize,
+    #[new(default)]
+    single_axis: bool,
 }
 
 impl LayerLogSoftmax {
+    /// Log of the softmax of each lane along `axis`, see `map_layers`.
+    pub fn single_axis(axis: isize) -> LayerLogSoftmax {
+        LayerLogSoftmax { axis, single_axis: true }
+    }
+
     fn eval_t<D: Datum + ::num_traits::Float + ::num_traits::FromPrimitive + ::std::iter::Sum>(
         &self,
         input: Arc<Tensor>,
     ) -> TractResult<TVec<Arc<Tensor>>> {
-        let array = input.into_tensor().into_array::<D>()?;
-        let shape = array.shape().to_vec();
-        let axis =
-            if self.axis < 0 { shape.len() as isize + self.axis } else { self.axis } as usize;
-        let first_dim: usize = array.shape()[0..axis].iter().product();
-        let second_dim: usize = array.len() / first_dim;
-        let mut array = array.into_shape((first_dim, second_dim))?;
-        array.outer_iter_mut().for_each(|mut layer| {
-            // https://jamesmccaffrey.wordpress.com/2016/03/04/the-max-trick-when-computing-softmax/
-            let max: Option<D> = layer
-                .iter()
-                .max_by(|a, b| a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal))
-                .cloned();
-            layer.mapv_inplace(|x| (x - max.unwrap()).exp());
-            let divisor = layer.iter().cloned().sum();
-            layer.mapv_inplace(|x| (x / divisor).ln());
-        });
-        Ok(tvec!(array.into_shape(shape)?.into_arc_tensor()))
+        map_layers(
+            "LayerLogSoftmax",
+            input,
+            self.axis,
+            self.single_axis,
+            |mut layer: ArrayViewMut1<D>| {
+                // https://jamesmccaffrey.wordpress.com/2016/03/04/the-max-trick-when-computing-softmax/
+                let max: Option<D> = layer
+                    .iter()
+                    .max_by(|a, b| a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal))
+                    .cloned();
+                layer.mapv_inplace(|x| (x - max.unwrap()).exp());
+                let divisor = layer.iter().cloned().sum();
+                layer.mapv_inplace(|x| (x / divisor).ln());
+            },
+        )
     }
 }
 
@@ -109,38 +120,54 @@ impl InferenceRulesOp for LayerLogSoftmax {
         inputs: &'p [TensorProxy],
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
-        rules(solver, inputs, outputs)
+        rules(solver, inputs, outputs, "LayerLogSoftmax", self.axis)
     }
 }
 
 #[derive(Debug, Clone, new, Default)]
 pub struct LayerSoftmax {
     axis: isize,
+    #[new(default)]
+    single_axis: bool,
 }
 
 impl LayerSoftmax {
+    /// Softmax of each lane along `axis`, see `map_layers`.
+    pub fn single_axis(axis: isize) -> LayerSoftmax {
+        LayerSoftmax { axis, single_axis: true }
+    }
+
+    /// The only axis normalized over for an input of rank `rank`, if there
+    /// is one: flattening from the last axis on normalizes over it only.
+    pub fn normalized_axis(&self, rank: usize) -> Option<usize> {
+        let axis = normalize_axis("LayerSoftmax", self.axis as i64, rank).ok()?;
+        if self.single_axis || axis + 1 == rank {
+            Some(axis)
+        } else {
+            None
+        }
+    }
+
     fn eval_t<D: Datum + ::num_traits::Float + ::num_traits::FromPrimitive + ::std::iter::Sum>(
         &self,
         input: Arc<Tensor>,
     ) -> TractResult<TVec<Arc<Tensor>>> {
-        let array = input.into_tensor().into_array::<D>()?;
-        let shape = array.shape().to_vec();
-        let axis =
-            if self.axis < 0 { shape.len() as isize + self.axis } else { self.axis } as usize;
-        let first_dim: usize = array.shape()[0..axis].iter().product();
-        let second_dim: usize = array.len() / first_dim;
-        let mut array = array.into_shape((first_dim, second_dim))?;
-        array.outer_iter_mut().for_each(|mut layer| {
-            // https://jamesmccaffrey.wordpress.com/2016/03/04/the-max-trick-when-computing-softmax/
-            let max: Option<D> = layer
-                .iter()
-                .max_by(|a, b| a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal))
-                .cloned();
-            layer.mapv_inplace(|x| (x - max.unwrap()).exp());
-            let divisor = layer.iter().cloned().sum();
-            layer.mapv_inplace(|x| x / divisor);
-        });
-        Ok(tvec!(array.into_shape(shape)?.into_arc_tensor()))
+        map_layers(
+            "LayerSoftmax",
+            input,
+            self.axis,
+            self.single_axis,
+            |mut layer: ArrayViewMut1<D>| {
+                // https://jamesmccaffrey.wordpress.com/2016/03/04/the-max-trick-when-computing-softmax/
+                let max: Option<D> = layer
+                    .iter()
+                    .max_by(|a, b| a.partial_cmp(&b).unwrap_or(::std::cmp::Ordering::Equal))
+                    .cloned();
+                layer.mapv_inplace(|x| (x - max.unwrap()).exp());
+                let divisor = layer.iter().cloned().sum();
+                layer.mapv_inplace(|x| x / divisor);
+            },
+        )
     }
 }
 
@@ -164,7 +191,72 @@ impl InferenceRulesOp for LayerSoftmax {
         inputs: &'p [TensorProxy],
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
-        rules(solver, inputs, outputs)
+        rules(solver, inputs, outputs, "LayerSoftmax", self.axis)
+    }
+}
+
+/// Softmax over `axis` of its first input, shifted by its second input,
+/// the max along `axis` computed beforehand: a conv writes it back with its
+/// output (`ChannelSummary::PositionMax`), saving the max pass.
+#[derive(Debug, Clone, new)]
+pub struct ShiftedSoftmax {
+    axis: usize,
+}
+
+impl ShiftedSoftmax {
+    fn eval_t<D: Datum + ::num_traits::Float + ::std::iter::Sum>(
+        &self,
+        input: Arc<Tensor>,
+        max: Arc<Tensor>,
+    ) -> TractResult<TVec<Arc<Tensor>>> {
+        let mut array = input.into_tensor().into_array::<D>()?;
+        let max = max.to_array_view::<D>()?;
+        let axis = Axis(self.axis);
+        let mut expected = array.shape().to_vec();
+        if let Some(dim) = expected.get_mut(self.axis) {
+            *dim = 1;
+        }
+        if max.shape() != &*expected {
+            bail!("ShiftedSoftmax: can not shift {:?} by {:?}", array.shape(), max.shape());
+        }
+        let max = max.index_axis(axis, 0);
+        for (mut layer, &max) in array.lanes_mut(axis).into_iter().zip(max.iter()) {
+            layer.mapv_inplace(|x| (x - max).exp());
+            let divisor: D = layer.iter().cloned().sum();
+            layer.mapv_inplace(|x| x / divisor);
+        }
+        Ok(tvec!(array.into_arc_tensor()))
+    }
+}
+
+impl Op for ShiftedSoftmax {
+    fn name(&self) -> Cow<str> {
+        "ShiftedSoftmax".into()
+    }
+}
+
+impl StatelessOp for ShiftedSoftmax {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (input, max) = args_2!(inputs);
+        dispatch_floatlike!(Self::eval_t(input.datum_type())(self, input, max))
+    }
+}
+
+impl InferenceRulesOp for ShiftedSoftmax {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, &inputs[0].datum_type)?;
+        s.equals(&inputs[1].datum_type, &inputs[0].datum_type)?;
+        s.equals(&inputs[1].rank, &inputs[0].rank)?;
+        s.equals(&inputs[1].shape[self.axis], 1.to_dim())?;
+        s.equals(&outputs[0].shape, &inputs[0].shape)?;
+        Ok(())
     }
 }
 
@@ -172,10 +264,42 @@ fn rules<'r, 'p: 'r, 's: 'r>(
     s: &mut Solver<'r>,
     inputs: &'p [TensorProxy],
     outputs: &'p [TensorProxy],
+    op: &'static str,
+    axis: isize,
 ) -> InferenceResult {
     check_output_arity(&outputs, 1)?;
     s.equals(&outputs[0].datum_type, &inputs[0].datum_type)?;
     s.equals(&outputs[0].rank, &inputs[0].rank)?;
     s.equals(&outputs[0].shape, &inputs[0].shape)?;
-    Ok(())
+    s.given(&inputs[0].rank, move |_, rank| {
+        normalize_axis(op, axis as i64, rank as usize)?;
+        Ok(())
+    })
+}
+
+/// Apply `f` to each layer of `input`: the lanes along `axis` if
+/// `single_axis`, the input flattened to 2D around `axis` otherwise.
+///
+/// The layer ops follow the ONNX semantics of their opset: up to opset 12,
+/// Hardmax, LogSoftmax and Softmax normalize over all the axes from `axis`
+/// on, flattened together; from opset 13, over `axis` only.
+fn map_layers<D: Datum>(
+    op: &'static str,
+    input: Arc<Tensor>,
+    axis: isize,
+    single_axis: bool,
+    f: impl FnMut(ArrayViewMut1<D>),
+) -> TractResult<TVec<Arc<Tensor>>> {
+    let mut array = input.into_tensor().into_array::<D>()?;
+    let shape = array.shape().to_vec();
+    let axis = normalize_axis(op, axis as i64, shape.len())?;
+    if single_axis {
+        array.lanes_mut(Axis(axis)).into_iter().for_each(f);
+        return Ok(tvec!(array.into_arc_tensor()));
+    }
+    let first_dim: usize = shape[0..axis].iter().product();
+    let second_dim: usize = array.len() / first_dim;
+    let mut array = array.into_shape((first_dim, second_dim))?;
+    array.outer_iter_mut().for_each(f);
+    Ok(tvec!(array.into_shape(shape)?.into_arc_tensor()))
 }
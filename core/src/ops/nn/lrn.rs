@@ -1,36 +1,59 @@
 use crate::internal::*;
 use ndarray::prelude::*;
 
+use super::DataFormat;
+
+/// Local response normalization: each item is divided by
+/// `(bias + alpha / size * sum) ^ beta`, the sum being the one of the squares
+/// in a window of `size` channels around it.
+///
+/// The window is clamped to the channels that exist: it spans
+/// `(size - 1) / 2` channels before the item and `size / 2` after.
 #[derive(Debug, Clone, new, Default)]
 pub struct Lrn {
     alpha: f32,
     beta: f32,
     bias: f32,
     size: usize,
+    #[new(default)]
+    data_format: DataFormat,
 }
 
 impl Lrn {
+    pub fn with_data_format(self, data_format: DataFormat) -> Lrn {
+        Lrn { data_format, ..self }
+    }
+
+    /// The channels in the window around channel `c`, out of `channels`.
+    fn window(&self, c: usize, channels: usize) -> ::std::ops::Range<usize> {
+        let before = (self.size - 1) / 2;
+        let after = self.size / 2;
+        c.saturating_sub(before)..(c + after + 1).min(channels)
+    }
+
     fn eval_t<T: Datum + ::num_traits::Float + ::num_traits::FromPrimitive + ::std::iter::Sum>(
         &self,
         input: Arc<Tensor>,
     ) -> TractResult<TVec<Arc<Tensor>>> {
+        if self.size == 0 {
+            bail!("Lrn: size must be positive");
+        }
         let input = input.to_array_view::<T>()?;
-        let channels = input.shape()[1];
-        let output = Array::from_shape_fn(input.shape(), |mut coords| {
-            let c = coords[1];
-            let x = input[&coords];
-            let c_min = (c as isize - ((self.size as isize - 1) / 2)).max(0) as usize;
-            let c_max = (c + ((self.size - 1).div_ceil(2))).min(channels - 1);
-            let square_sum: T = (c_min..c_max)
-                .map(|c| {
-                    coords[1] = c;
-                    input[&coords].powi(2)
-                })
-                .sum();
-            x / (T::from(self.bias).unwrap()
-                + T::from(self.alpha).unwrap() / T::from(self.size).unwrap() * square_sum)
-                .powf(T::from(self.beta).unwrap())
-        });
+        let c_axis = self.data_format.shape(input.shape()).c_axis();
+        let channels = input.shape()[c_axis];
+        let alpha = T::from(self.alpha).unwrap() / T::from(self.size).unwrap();
+        let beta = T::from(self.beta).unwrap();
+        let bias = T::from(self.bias).unwrap();
+        let mut output = input.to_owned();
+        let mut squares = vec![T::zero(); channels];
+        for (x, mut y) in input.lanes(Axis(c_axis)).into_iter().zip(output.lanes_mut(Axis(c_axis)))
+        {
+            squares.iter_mut().zip(x.iter()).for_each(|(sq, &x)| *sq = x * x);
+            for c in 0..channels {
+                let square_sum: T = squares[self.window(c, channels)].iter().cloned().sum();
+                y[c] = x[c] / (bias + alpha * square_sum).powf(beta);
+            }
+        }
         Ok(tvec!(output.into_arc_tensor()))
     }
 }
@@ -55,8 +78,74 @@ impl InferenceRulesOp for Lrn {
         inputs: &'p [TensorProxy],
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
         s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
         s.equals(&inputs[0].shape, &outputs[0].shape)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The ONNX reference: the sum runs from c - floor((size - 1) / 2) to
+    /// c + ceil((size - 1) / 2), both included, clamped to the channels.
+    fn reference(x: &Array4<f32>, alpha: f32, beta: f32, bias: f32, size: usize) -> Array4<f32> {
+        let channels = x.shape()[1] as isize;
+        let before = (size as isize - 1) / 2;
+        let after = size as isize / 2;
+        Array4::from_shape_fn(x.dim(), |(n, c, h, w)| {
+            let lo = (c as isize - before).max(0);
+            let hi = (c as isize + after).min(channels - 1);
+            let sum: f32 = (lo..=hi).map(|c| x[(n, c as usize, h, w)].powi(2)).sum();
+            x[(n, c, h, w)] / (bias + alpha / size as f32 * sum).powf(beta)
+        })
+    }
+
+    fn input() -> Array4<f32> {
+        Array4::from_shape_fn((2, 5, 2, 3), |(n, c, h, w)| {
+            ((n * 7 + c * 5 + h * 3 + w) % 11) as f32 / 4.0 - 1.0
+        })
+    }
+
+    fn check(size: usize) {
+        let x = input();
+        let expected: Tensor = reference(&x, 0.5, 0.75, 2.0, size).into();
+        let op = Lrn::new(0.5, 0.75, 2.0, size);
+        let found = op.eval(tvec!(x.clone().into_arc_tensor())).unwrap();
+        assert!(found[0].close_enough(&expected, true), "{:?} {:?}", found[0], expected);
+        let nhwc = x.permuted_axes([0, 2, 3, 1]).to_owned();
+        let op = op.with_data_format(DataFormat::NHWC);
+        let found = op.eval(tvec!(nhwc.into_arc_tensor())).unwrap();
+        let found = found[0].to_array_view::<f32>().unwrap().permuted_axes(vec![0, 3, 1, 2]);
+        assert!(Tensor::from(found.to_owned()).close_enough(&expected, true));
+    }
+
+    #[test]
+    fn window_clamped_at_edges() {
+        let op = Lrn::new(1.0, 1.0, 1.0, 3);
+        assert_eq!(op.window(0, 5), 0..2);
+        assert_eq!(op.window(4, 5), 3..5);
+        let op = Lrn::new(1.0, 1.0, 1.0, 4);
+        assert_eq!(op.window(0, 5), 0..3);
+        assert_eq!(op.window(3, 5), 2..5);
+        assert_eq!(Lrn::new(1.0, 1.0, 1.0, 9).window(2, 5), 0..5);
+    }
+
+    #[test]
+    fn odd_size() {
+        check(3);
+    }
+
+    #[test]
+    fn even_size() {
+        check(2);
+    }
+
+    #[test]
+    fn window_wider_than_channels() {
+        check(7);
+    }
+}
@@ -1,28 +1,41 @@
 mod arg_max_min;
 mod batch_norm;
+mod channel_blocks;
 mod data_formats;
+mod depth_to_space;
 mod global_pools;
+mod grid_sample;
 mod layer_max;
 mod lrn;
+mod mvn;
 mod reduce;
+pub mod relu6;
 pub mod sigmoid;
 pub mod tanh;
 
 pub use self::arg_max_min::ArgMaxMin;
 pub use self::batch_norm::BatchNorm;
+pub use self::channel_blocks::{
+    channel_blocked_shape, channel_blocks, FromChannelBlocks, ToChannelBlocks,
+};
 pub use self::data_formats::{BaseDataShape, DataFormat, DataShape};
+pub use self::depth_to_space::{DepthToSpace, DepthToSpaceMode};
 pub use self::global_pools::{GlobalAvgPool, GlobalLpPool, GlobalMaxPool};
-pub use self::layer_max::{LayerHardmax, LayerLogSoftmax, LayerSoftmax};
+pub use self::grid_sample::{GridSample, GridSampleMode, GridSamplePadding};
+pub use self::layer_max::{LayerHardmax, LayerLogSoftmax, LayerSoftmax, ShiftedSoftmax};
 pub use self::lrn::Lrn;
-pub use self::reduce::{Reduce, Reducer};
+pub use self::mvn::MeanVarianceNorm;
+pub use self::reduce::{max_with_index, min_with_index, Reduce, Reducer};
+pub use self::relu6::Relu6;
 pub use self::sigmoid::Sigmoid;
 pub use self::tanh::Tanh;
 
 use num_traits::AsPrimitive;
 
 element_map!(Relu, [f32, i32], |x| if x < 0 as _ { 0 as _ } else { x });
-element_map!(Softplus, [f32], |x| (x.exp() + 1.0).ln());
-element_map!(Softsign, [f32], |x| x / (x.abs() + 1.0));
+// ln(1 + e^x) = max(x, 0) + ln(1 + e^-|x|): e^x would overflow for large x
+element_map!(Softplus, [f32, f64], |x| x.max(0.0) + (-x.abs()).exp().ln_1p());
+element_map!(Softsign, [f32, f64], |x| x / (x.abs() + 1.0));
 
 element_map_with_params!(
     Elu,
@@ -94,6 +107,21 @@ element_map_with_params!(Selu, [f32, f64], {alpha: f32, gamma: f32},
     }
 );
 
+// soft shrinkage for a bias equal to lambd, hard shrinkage for a zero bias
+element_map_with_params!(Shrink, [f16, f32, f64], { bias: f32, lambd: f32 },
+    fn eval_one<T>(s: &Shrink, x:T) -> T
+    where T: Datum+::num_traits::Float, f32: ::num_traits::AsPrimitive<T>
+    {
+        if x < -s.lambd.as_() {
+            x + s.bias.as_()
+        } else if x > s.lambd.as_() {
+            x - s.bias.as_()
+        } else {
+            T::zero()
+        }
+    }
+);
+
 element_map_with_params!(
     ThresholdedRelu,
     [f32, f64],
@@ -110,3 +138,62 @@ element_map_with_params!(
         }
     }
 );
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn map(op: &StatelessOp, x: Tensor) -> Tensor {
+        op.eval(tvec!(x.into_arc_tensor())).unwrap().remove(0).into_tensor()
+    }
+
+    #[test]
+    fn softplus_does_not_overflow() {
+        let xs = [-1000.0f32, -20.0, -1.0, 0.0, 0.5, 3.0, 50.0, 100.0, 1000.0];
+        let found = map(&Softplus::default(), tensor1(&xs));
+        for (&x, &y) in xs.iter().zip(found.as_slice::<f32>().unwrap()) {
+            assert!(y.is_finite() && y >= 0.0, "softplus({}) = {}", x, y);
+            if x > 20.0 {
+                assert_eq!(y, x);
+            } else {
+                let naive = (x as f64).exp().ln_1p() as f32;
+                assert!((y - naive).abs() <= naive * 1e-6, "softplus({}) = {}", x, y);
+            }
+        }
+        let found = map(&Softplus::default(), tensor1(&[50.0f64, 1000.0]));
+        assert_eq!(found, tensor1(&[50.0f64, 1000.0]));
+    }
+
+    #[test]
+    fn shrink_and_thresholded_relu_at_boundaries() {
+        let xs: Vec<f32> = (-12..=12).map(|i| i as f32 / 4.0).collect();
+        for &(bias, lambd) in &[(0.0f32, 0.5f32), (1.5, 1.5), (0.25, 1.0), (0.0, 0.0)] {
+            let found = map(&Shrink::new(bias, lambd), tensor1(&xs));
+            for (&x, &y) in xs.iter().zip(found.as_slice::<f32>().unwrap()) {
+                let expected = if x < -lambd {
+                    x + bias
+                } else if x > lambd {
+                    x - bias
+                } else {
+                    0.0
+                };
+                assert_eq!(y, expected, "shrink({}, {}, {})", bias, lambd, x);
+            }
+        }
+        for &alpha in &[1.0f32, 0.0, -0.5, 2.25] {
+            let found = map(&ThresholdedRelu::new(alpha), tensor1(&xs));
+            for (&x, &y) in xs.iter().zip(found.as_slice::<f32>().unwrap()) {
+                let expected = if x > alpha { x } else { 0.0 };
+                assert_eq!(y, expected, "thresholded_relu({}, {})", alpha, x);
+            }
+        }
+        let found = map(&Shrink::new(0.5, 0.5), tensor1(&[-0.5f64, -0.75, 0.5, 0.75]));
+        assert_eq!(found, tensor1(&[0.0f64, -0.25, 0.0, 0.25]));
+    }
+
+    #[test]
+    fn softsign() {
+        let found = map(&Softsign::default(), tensor1(&[-3.0f64, 0.0, 1.0, 1e300]));
+        assert_eq!(found, tensor1(&[-0.75f64, 0.0, 0.5, 1.0]));
+    }
+}
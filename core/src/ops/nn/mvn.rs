@@ -0,0 +1,128 @@
+use crate::internal::*;
+use ndarray::prelude::*;
+
+/// Mean variance normalization: `(x - mean) / sqrt(var + epsilon)`, the
+/// mean and variance being computed over `axes`.
+///
+/// The variance is the biased one, the sum of squared deviations divided by
+/// the number of items, not that number minus one. `epsilon` goes under the
+/// square root, keeping constant slices at zero instead of dividing by
+/// zero: the ONNX reference adds it after the root, which only differs for
+/// such slices.
+#[derive(Debug, Clone, new)]
+pub struct MeanVarianceNorm {
+    axes: TVec<i64>,
+    epsilon: f32,
+}
+
+impl Default for MeanVarianceNorm {
+    /// Per channel over batch and space of a NCHW image, as ONNX does.
+    fn default() -> MeanVarianceNorm {
+        MeanVarianceNorm::new(tvec!(0, 2, 3), 1e-9)
+    }
+}
+
+impl MeanVarianceNorm {
+    /// Mean of `x` over `axes`, keeping them with a dimension of one.
+    fn mean<T: Datum + num_traits::Float>(x: ArrayViewD<T>, axes: &[usize]) -> ArrayD<T> {
+        let mut sum = x.to_owned();
+        let mut count = 1;
+        for &axis in axes {
+            count *= x.shape()[axis];
+            sum = sum.sum_axis(Axis(axis)).insert_axis(Axis(axis));
+        }
+        let count = T::from(count).unwrap();
+        sum.mapv(|s| s / count)
+    }
+
+    fn eval_t<T: Datum + num_traits::Float>(&self, input: &Tensor) -> TractResult<Tensor> {
+        let input = input.to_array_view::<T>()?;
+        let mut axes = self
+            .axes
+            .iter()
+            .map(|&axis| crate::ops::normalize_axis(&*self.name(), axis, input.ndim()))
+            .collect::<TractResult<TVec<usize>>>()?;
+        axes.sort();
+        axes.dedup();
+        let mean = Self::mean(input.view(), &axes);
+        let centered = &input - &mean;
+        let variance = Self::mean(centered.mapv(|x| x * x).view(), &axes);
+        let epsilon = T::from(self.epsilon).unwrap();
+        let std = variance.mapv(|v| (v + epsilon).sqrt());
+        Ok((centered / &std).into())
+    }
+}
+
+impl Op for MeanVarianceNorm {
+    fn name(&self) -> Cow<str> {
+        "MeanVarianceNorm".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("axes: {:?}, epsilon: {}", self.axes, self.epsilon)))
+    }
+}
+
+impl StatelessOp for MeanVarianceNorm {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let output = dispatch_floatlike!(Self::eval_t(input.datum_type())(self, &input))?;
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for MeanVarianceNorm {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input() -> Array4<f32> {
+        Array4::from_shape_fn((2, 3, 4, 5), |(n, c, h, w)| {
+            ((n * 13 + c * 7 + h * 5 + w * 3) % 17) as f32 * (c + 1) as f32 - 8.0
+        })
+    }
+
+    #[test]
+    fn default_axes_match_manual() {
+        let x = input();
+        let found = MeanVarianceNorm::default().eval(tvec!(x.clone().into_arc_tensor())).unwrap();
+        let found = found[0].to_array_view::<f32>().unwrap();
+        for c in 0..3 {
+            let channel = x.index_axis(Axis(1), c);
+            let count = channel.len() as f64;
+            let mean = channel.iter().map(|&x| x as f64).sum::<f64>() / count;
+            let var = channel.iter().map(|&x| (x as f64 - mean).powi(2)).sum::<f64>() / count;
+            for ((n, h, w), &x) in channel.indexed_iter() {
+                let expected = (x as f64 - mean) / (var + 1e-9).sqrt();
+                let found = found[[n, c, h, w]] as f64;
+                assert!((found - expected).abs() < 1e-5, "{} vs {}", found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn constant_slices_and_negative_axes() {
+        let x = Array2::from_shape_vec((2, 3), vec![4.0f32, 4.0, 4.0, 1.0, 2.0, 3.0]).unwrap();
+        let op = MeanVarianceNorm::new(tvec!(-1), 1e-9);
+        let found = op.eval(tvec!(x.into_arc_tensor())).unwrap();
+        let found = found[0].to_array_view::<f32>().unwrap().into_dimensionality::<Ix2>().unwrap();
+        assert_eq!(found.row(0), arr1(&[0.0f32, 0.0, 0.0]));
+        let s = (1.5f32).sqrt();
+        assert!(found.row(1).iter().zip(&[-s, 0.0, s]).all(|(a, b)| (a - b).abs() < 1e-5));
+        assert!(MeanVarianceNorm::new(tvec!(2), 1e-9).eval(tvec!(rctensor1(&[1.0f32]))).is_err());
+    }
+}
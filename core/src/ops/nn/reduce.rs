@@ -82,6 +82,7 @@ impl Reducer {
     {
         use ndarray::*;
         let rank = input.shape().len();
+        reduce.resolve_axes(rank)?;
         let input = input.to_array_view::<T>()?;
         let full_output_shape: Vec<usize> = input
             .shape()
@@ -144,7 +145,11 @@ fn log_sum_exp_t<'a, T>(v: ArrayViewD<'a, T>) -> T
 where
     T: Copy + Datum + num_traits::Zero + num_traits::Float,
 {
-    let max = v.fold(T::min_value(), |acc, &v| if acc > v { acc } else { v });
+    let max = v.fold(T::neg_infinity(), |acc, &v| if acc > v { acc } else { v });
+    // an infinite max is the result, and shifting by it would give NaN
+    if max.is_infinite() {
+        return max;
+    }
     max + v.fold(T::zero(), |acc, &v| acc + (v - max).exp()).ln()
 }
 
@@ -152,7 +157,7 @@ fn max_t<'a, T>(v: ArrayViewD<'a, T>) -> T
 where
     T: Copy + Datum + num_traits::Bounded + ::std::cmp::PartialOrd,
 {
-    v.fold(T::min_value(), |acc, &v| if acc > v { acc } else { v })
+    max_with_index(v.iter().cloned().enumerate()).map(|(_, max)| max).unwrap_or(T::min_value())
 }
 
 fn mean_t<'a, T>(v: ArrayViewD<'a, T>) -> T
@@ -168,7 +173,41 @@ fn min_t<'a, T>(v: ArrayViewD<'a, T>) -> T
 where
     T: Copy + Datum + num_traits::Bounded + ::std::cmp::PartialOrd,
 {
-    v.fold(T::max_value(), |acc, &v| if acc < v { acc } else { v })
+    min_with_index(v.iter().cloned().enumerate()).map(|(_, min)| min).unwrap_or(T::max_value())
+}
+
+/// Largest of the indexed `values`, with its index, in a single pass.
+///
+/// Ties go to the lowest index. Values that do not compare to themselves
+/// (NaN) are skipped: None if there is no other value.
+pub fn max_with_index<T: PartialOrd + Copy>(
+    values: impl IntoIterator<Item = (usize, T)>,
+) -> Option<(usize, T)> {
+    extremum_with_index(values, |a, b| a > b)
+}
+
+/// Smallest of the indexed `values`, see `max_with_index`.
+pub fn min_with_index<T: PartialOrd + Copy>(
+    values: impl IntoIterator<Item = (usize, T)>,
+) -> Option<(usize, T)> {
+    extremum_with_index(values, |a, b| a < b)
+}
+
+fn extremum_with_index<T: PartialOrd + Copy>(
+    values: impl IntoIterator<Item = (usize, T)>,
+    better: impl Fn(T, T) -> bool,
+) -> Option<(usize, T)> {
+    let mut best: Option<(usize, T)> = None;
+    for (ix, v) in values {
+        if v.partial_cmp(&v).is_none() {
+            continue;
+        }
+        match best {
+            Some((best_ix, b)) if !(better(v, b) || v == b && ix < best_ix) => (),
+            _ => best = Some((ix, v)),
+        }
+    }
+    best
 }
 
 fn prod_t<'a, T>(v: ArrayViewD<'a, T>) -> T
@@ -201,28 +240,20 @@ pub struct Reduce {
 
 impl Reduce {
     pub fn must_reduce(&self, ax: usize, rank: usize) -> bool {
-        let resolved_axes: Option<Vec<usize>> = match &self.axes {
-            None => None,
-            Some(original_axes) => {
-                let mut ans: Vec<usize> = vec![];
-                for or_ax in original_axes.iter() {
-                    ans.push(Self::resolve_axis(*or_ax, rank as i64).unwrap());
-                }
-                Some(ans)
-            }
-        };
-
-        resolved_axes.as_ref().map(|axes| axes.contains(&ax)).unwrap_or(true)
+        self.resolve_axes(rank).unwrap().map(|axes| axes.contains(&ax)).unwrap_or(true)
     }
 
-    fn resolve_axis(axis: i64, rank: i64) -> TractResult<usize> {
-        if 0 <= axis && axis <= rank - 1 {
-            Ok(axis as usize)
-        } else if -rank <= axis && axis < 0 {
-            Ok((axis + rank) as usize)
-        } else {
-            bail!("Illegal combination of values for rank and axis: {} and {}", rank, axis)
-        }
+    /// The reduced axes, normalized for an input of rank `rank`. None for
+    /// all of them.
+    pub fn resolve_axes(&self, rank: usize) -> TractResult<Option<Vec<usize>>> {
+        self.axes
+            .as_ref()
+            .map(|axes| {
+                axes.iter()
+                    .map(|&axis| normalize_axis(&*self.name(), axis, rank))
+                    .collect::<TractResult<Vec<usize>>>()
+            })
+            .transpose()
     }
 }
 
@@ -268,6 +299,7 @@ impl InferenceRulesOp for Reduce {
             s.equals(&outputs[0].rank, 0)?;
         }
         s.given(&inputs[0].shape, move |s, shape| {
+            self.resolve_axes(shape.len())?;
             let out_shape: TVec<TDim> = shape
                 .iter()
                 .enumerate()
@@ -287,3 +319,89 @@ impl InferenceRulesOp for Reduce {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn input() -> ArrayD<f32> {
+        ArrayD::from_shape_fn(&[2, 3, 4][..], |ix| {
+            (ix[0] * 12 + ix[1] * 4 + ix[2]) as f32 / 4.0 - 3.0
+        })
+    }
+
+    fn run(reducer: Reducer, axes: &[i64], keep_dims: bool, input: ArrayD<f32>) -> ArrayD<f32> {
+        let op = Reduce::new(Some(axes.to_vec()), keep_dims, reducer);
+        let output = op.eval(tvec!(input.into_arc_tensor())).unwrap().remove(0);
+        output.into_tensor().into_array::<f32>().unwrap()
+    }
+
+    /// Reduce axes 0 and 2, folding each (i, k) line with `f`.
+    fn reference(input: &ArrayD<f32>, f: impl Fn(&[f32]) -> f32) -> ArrayD<f32> {
+        ArrayD::from_shape_fn(&[input.shape()[1]][..], |ix| {
+            let values: Vec<f32> = input.index_axis(Axis(1), ix[0]).iter().cloned().collect();
+            f(&values)
+        })
+    }
+
+    fn check(reducer: Reducer, f: impl Fn(&[f32]) -> f32) {
+        let input = input();
+        let expected = reference(&input, f);
+        let found = run(reducer, &[0, -1], false, input.clone());
+        assert!(found.all_close(&expected, 1e-4), "{:?}: {:?} {:?}", reducer, found, expected);
+        let found = run(reducer, &[0, 2], true, input);
+        assert_eq!(found.shape(), &[1, 3, 1]);
+        let expected = expected.into_shape(&[1, 3, 1][..]).unwrap();
+        assert!(found.all_close(&expected, 1e-4), "{:?}: {:?} {:?}", reducer, found, expected);
+    }
+
+    #[test]
+    fn extremum_ties_go_to_lowest_index() {
+        let values = [2.0f32, 5.0, std::f32::NAN, 5.0, -1.0, -1.0];
+        assert_eq!(max_with_index(values.iter().cloned().enumerate()), Some((1, 5.0)));
+        assert_eq!(min_with_index(values.iter().cloned().enumerate()), Some((4, -1.0)));
+        // whatever order the values come in
+        let reversed = values.iter().cloned().enumerate().rev();
+        assert_eq!(max_with_index(reversed), Some((1, 5.0)));
+        assert_eq!(max_with_index(vec![(0, std::f32::NAN)]), None);
+        assert_eq!(min_with_index(Vec::<(usize, f32)>::new()), None);
+    }
+
+    #[test]
+    fn l1() {
+        check(Reducer::L1, |v| v.iter().map(|x| x.abs()).sum());
+    }
+
+    #[test]
+    fn l2() {
+        check(Reducer::L2, |v| v.iter().map(|x| x * x).sum::<f32>().sqrt());
+    }
+
+    #[test]
+    fn sum_square() {
+        check(Reducer::SumSquare, |v| v.iter().map(|x| x * x).sum());
+    }
+
+    #[test]
+    fn log_sum_exp() {
+        check(Reducer::LogSumExp, |v| v.iter().map(|x| x.exp()).sum::<f32>().ln());
+    }
+
+    #[test]
+    fn log_sum_exp_of_large_values() {
+        // exp overflows f32 past 88
+        let input = input() * 10.0 + 200.0;
+        let found = run(Reducer::LogSumExp, &[0, 2], false, input.clone());
+        let expected =
+            reference(&input, |v| v.iter().map(|&x| (x as f64).exp()).sum::<f64>().ln() as f32);
+        assert!(found.iter().all(|x| x.is_finite()));
+        assert!(found.all_close(&expected, 1e-2), "{:?} {:?}", found, expected);
+    }
+
+    #[test]
+    fn log_sum_exp_of_neg_infinity() {
+        let input = ArrayD::from_elem(&[2, 2][..], std::f32::NEG_INFINITY);
+        let found = run(Reducer::LogSumExp, &[1], false, input);
+        assert_eq!(found, arr1(&[std::f32::NEG_INFINITY; 2]).into_dyn());
+    }
+}
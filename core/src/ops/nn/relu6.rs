@@ -0,0 +1,94 @@
+use crate::internal::*;
+
+/// Items clipped per iteration: the compiler turns the chunk loop into
+/// packed min and max instructions.
+const CHUNK: usize = 16;
+
+/// Clip `xs` to [0, 6] in place. NaN are kept.
+pub fn relu6_f32(xs: &mut [f32]) {
+    fn relu6(x: f32) -> f32 {
+        x.clamp(0.0, 6.0)
+    }
+    let mut chunks = xs.chunks_exact_mut(CHUNK);
+    for chunk in &mut chunks {
+        for x in chunk.iter_mut() {
+            *x = relu6(*x);
+        }
+    }
+    for x in chunks.into_remainder() {
+        *x = relu6(*x);
+    }
+}
+
+/// Relu bounded by 6, as in mobile nets. f32 `Clip(0, 6)` is lowered to it.
+#[derive(Debug, Clone, Default)]
+pub struct Relu6;
+
+impl Op for Relu6 {
+    fn name(&self) -> Cow<str> {
+        "Relu6".into()
+    }
+}
+
+impl StatelessOp for Relu6 {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let mut a = args_1!(inputs).into_tensor();
+        relu6_f32(a.as_slice_mut::<f32>()?);
+        Ok(tvec!(a.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Relu6 {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, f32::datum_type())?;
+        s.equals(&outputs[0].datum_type, f32::datum_type())?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::math::Clip;
+
+    fn clip(min: f32, max: f32) -> TypedModel {
+        let mut model = TypedModel::default();
+        let fact = TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: ShapeInfo::from(&[37][..]),
+            konst: None,
+        };
+        model.add_source("input", fact.clone()).unwrap();
+        model.chain("clip", Clip::new(min, max), tvec!(fact)).unwrap();
+        model
+    }
+
+    fn check(min: f32, max: f32, relu6: bool) {
+        let model = clip(min, max);
+        let input = ndarray::Array1::from_shape_fn(37, |i| i as f32 / 2.0 - 9.0);
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into())).unwrap();
+        let model = model.codegen().unwrap();
+        assert_eq!(model.nodes().iter().any(|n| n.op_is::<Relu6>()), relu6);
+        assert_eq!(model.nodes().iter().any(|n| n.op_is::<Clip>()), !relu6);
+        let found = SimplePlan::new(&model).unwrap().run(tvec!(input.into())).unwrap();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn clip_0_6_is_relu6() {
+        check(0.0, 6.0, true);
+    }
+
+    #[test]
+    fn other_clips_are_kept() {
+        check(0.0, 5.0, false);
+        check(-1.0, 6.0, false);
+    }
+}
@@ -0,0 +1,197 @@
+//! Random tensor generators.
+use std::hash::{BuildHasher, Hasher};
+
+use crate::internal::*;
+use ndarray::*;
+
+/// Law the values are drawn from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Uniform { low: f32, high: f32 },
+    Normal { mean: f32, scale: f32 },
+}
+
+/// Fill a tensor with random values.
+///
+/// With a `shape`, the op has no input. Without one, it takes the shape of
+/// its single input, and its datum type too if `datum_type` is not set:
+/// this is the `*Like` form. The datum type defaults to F32 otherwise.
+///
+/// A seeded op draws the same values at every evaluation, so it folds like
+/// any other op over constants. An unseeded op is seeded afresh at each
+/// evaluation, and never folded.
+#[derive(Debug, Clone, new)]
+pub struct Random {
+    pub distribution: Distribution,
+    pub datum_type: Option<DatumType>,
+    pub shape: Option<TVec<usize>>,
+    pub seed: Option<u64>,
+}
+
+/// xorshift64* generator, seeded through splitmix64 so that close seeds
+/// give unrelated streams.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // the all-zero state is a fixed point
+        Rng(if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z })
+    }
+
+    fn from_entropy() -> Rng {
+        Rng::new(std::collections::hash_map::RandomState::new().build_hasher().finish())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn sample(&mut self, distribution: Distribution) -> f64 {
+        match distribution {
+            Distribution::Uniform { low, high } => {
+                low as f64 + (high as f64 - low as f64) * self.next_f64()
+            }
+            Distribution::Normal { mean, scale } => {
+                // Box-Muller, with u1 in (0, 1] to keep the log finite
+                let u1 = 1.0 - self.next_f64();
+                let u2 = self.next_f64();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                mean as f64 + scale as f64 * z
+            }
+        }
+    }
+}
+
+impl Random {
+    fn eval_t<T: Datum + num_traits::Float>(&self, shape: &[usize]) -> TractResult<Tensor> {
+        let mut rng = self.seed.map(Rng::new).unwrap_or_else(Rng::from_entropy);
+        let values = ArrayD::from_shape_fn(shape, |_| {
+            T::from(rng.sample(self.distribution)).unwrap_or_else(T::nan)
+        });
+        Ok(values.into())
+    }
+}
+
+impl Op for Random {
+    fn name(&self) -> Cow<str> {
+        "Random".into()
+    }
+
+    /// Only seeded ops are evaluated on constant inputs.
+    fn infer(
+        &self,
+        inputs: TVec<&TensorFact>,
+        outputs: TVec<&TensorFact>,
+    ) -> TractResult<(TVec<TensorFact>, TVec<TensorFact>)> {
+        if self.seed.is_some() && inputs.iter().all(|i| i.value.is_concrete()) {
+            let (inputs, _) = self.infer_facts(inputs, outputs)?;
+            let values = inputs.iter().map(|i| i.value.concretize().unwrap()).collect();
+            let outputs = self.eval(values)?.into_iter().map(|t| t.into()).collect();
+            return Ok((inputs, outputs));
+        }
+        self.infer_facts(inputs, outputs)
+    }
+}
+
+impl StatelessOp for Random {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (shape, input_dt) = match (&self.shape, inputs.get(0)) {
+            (Some(shape), _) => (&**shape, None),
+            (None, Some(input)) => (input.shape(), Some(input.datum_type())),
+            (None, None) => bail!("Random without shape expects an input to take it from"),
+        };
+        let dt = self.datum_type.or(input_dt).unwrap_or(DatumType::F32);
+        let output = match dt {
+            DatumType::F16 => self.eval_t::<f32>(shape)?.cast_to_dt(dt)?.into_owned(),
+            DatumType::F32 => self.eval_t::<f32>(shape)?,
+            DatumType::F64 => self.eval_t::<f64>(shape)?,
+            _ => bail!("Random values of type {:?} are not supported", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Random {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_output_arity(&outputs, 1)?;
+        if let Some(shape) = &self.shape {
+            check_input_arity(&inputs, 0)?;
+            s.equals(&outputs[0].shape, ShapeFact::from(shape.iter().cloned()))?;
+        } else {
+            check_input_arity(&inputs, 1)?;
+            s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        }
+        match (self.datum_type, &self.shape) {
+            (Some(dt), _) => s.equals(&outputs[0].datum_type, dt)?,
+            (None, Some(_)) => s.equals(&outputs[0].datum_type, DatumType::F32)?,
+            (None, None) => s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normal(seed: Option<u64>) -> Random {
+        Random::new(Distribution::Normal { mean: 1.0, scale: 2.0 }, None, Some(tvec!(3, 100)), seed)
+    }
+
+    #[test]
+    fn same_seed_same_values() {
+        let a = normal(Some(42)).eval(tvec!()).unwrap();
+        let b = normal(Some(42)).eval(tvec!()).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a[0].shape(), &[3, 100]);
+        let c = normal(Some(43)).eval(tvec!()).unwrap();
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn unseeded_values_differ() {
+        let a = normal(None).eval(tvec!()).unwrap();
+        let b = normal(None).eval(tvec!()).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn uniform_like() {
+        let op = Random::new(Distribution::Uniform { low: -1.0, high: 3.0 }, None, None, Some(7));
+        let input = Array1::from_vec(vec![0.0f64; 200]).into_shape((4, 50)).unwrap();
+        let input = input.into_arc_tensor();
+        let output = op.eval(tvec!(input)).unwrap().remove(0);
+        assert_eq!(output.datum_type(), DatumType::F64);
+        assert_eq!(output.shape(), &[4, 50]);
+        assert!(output.to_array_view::<f64>().unwrap().iter().all(|&x| x >= -1.0 && x < 3.0));
+    }
+
+    #[test]
+    fn only_seeded_ops_are_folded() {
+        for &(seed, folded) in &[(Some(3), true), (None, false)] {
+            let mut model = InferenceModel::default();
+            let id = model.add_node_default("random", normal(seed)).unwrap();
+            model.set_output_outlets(&[OutletId::new(id, 0)]).unwrap();
+            let model = model.into_typed().unwrap();
+            assert_eq!(model.outlet_fact(OutletId::new(id, 0)).unwrap().konst.is_some(), folded);
+        }
+    }
+}
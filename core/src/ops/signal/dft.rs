@@ -0,0 +1,464 @@
+use std::f64::consts::PI;
+
+use crate::internal::*;
+use ndarray::*;
+use num_traits::Float;
+
+/// Cosines and sines of the `n` roots of unity, computed in f64.
+fn twiddles<T: Float>(n: usize) -> Vec<(T, T)> {
+    (0..n)
+        .map(|k| {
+            let angle = 2.0 * PI * k as f64 / n as f64;
+            (T::from(angle.cos()).unwrap(), T::from(angle.sin()).unwrap())
+        })
+        .collect()
+}
+
+/// The first `output.len()` bins of the transform of `input`, zero padded or
+/// truncated to the length of `twiddles`.
+///
+/// This is the plain O(n²) sum: the frames of audio frontends are short.
+fn dft_line<T: Float>(twiddles: &[(T, T)], inverse: bool, input: &[(T, T)], output: &mut [(T, T)]) {
+    let n = twiddles.len();
+    let input = &input[..input.len().min(n)];
+    for (bin, out) in output.iter_mut().enumerate() {
+        let (mut sr, mut si) = (T::zero(), T::zero());
+        for (j, &(re, im)) in input.iter().enumerate() {
+            let (c, s) = twiddles[(bin * j) % n];
+            let s = if inverse { -s } else { s };
+            sr = sr + re * c + im * s;
+            si = si + im * c - re * s;
+        }
+        *out = if inverse {
+            let n = T::from(n).unwrap();
+            (sr / n, si / n)
+        } else {
+            (sr, si)
+        };
+    }
+}
+
+/// Number of bins of a transform of `length` items.
+fn bins(length: usize, onesided: bool) -> usize {
+    if onesided {
+        length / 2 + 1
+    } else {
+        length
+    }
+}
+
+/// Checks the trailing axis of a signal, returning whether it is complex.
+fn is_complex(op: &str, shape: &[usize]) -> TractResult<bool> {
+    match shape.last() {
+        Some(1) => Ok(false),
+        Some(2) => Ok(true),
+        _ => bail!("{}: expected a trailing axis of 1 or 2 items, got {:?}", op, shape),
+    }
+}
+
+fn length_input(op: &str, length: &Tensor) -> TractResult<usize> {
+    let length = *length.cast_to::<i64>()?.to_scalar::<i64>()?;
+    if length < 1 {
+        bail!("{}: lengths must be positive, got {}", op, length);
+    }
+    Ok(length as usize)
+}
+
+/// Discrete Fourier transform of a signal along an axis.
+///
+/// Inputs are the signal, real or complex, and an optional length of the
+/// transform, the signal being zero padded or truncated to it. The output is
+/// complex. Onesided transforms of real signals only keep the first
+/// `length / 2 + 1` bins, the others being their conjugates.
+#[derive(Debug, Clone, new)]
+pub struct Dft {
+    pub axis: i64,
+    pub inverse: bool,
+    pub onesided: bool,
+}
+
+impl Dft {
+    fn eval_t<T: Datum + Float>(
+        &self,
+        signal: &Tensor,
+        length: Option<usize>,
+    ) -> TractResult<Tensor> {
+        let signal = signal.to_array_view::<T>()?;
+        let complex = is_complex("DFT", signal.shape())?;
+        let rank = signal.ndim();
+        let axis = normalize_axis("DFT", self.axis, rank)?;
+        if axis == rank - 1 {
+            bail!("DFT: can not transform the trailing real and imaginary axis");
+        }
+        if self.onesided && (complex || self.inverse) {
+            bail!("DFT: onesided transforms are for real forward ones only");
+        }
+        let mut signal = signal.view();
+        signal.swap_axes(axis, rank - 2);
+        let mut shape: TVec<usize> = signal.shape().into();
+        let n = shape[rank - 2];
+        let length = length.unwrap_or(n);
+        let bins = bins(length, self.onesided);
+        let outer = shape[..rank - 2].iter().product::<usize>();
+        let signal = Array::from_shape_vec(signal.shape(), signal.iter().cloned().collect())?
+            .into_shape((outer, n, shape[rank - 1]))?;
+        let twiddles = twiddles::<T>(length);
+        let mut output = Array3::<T>::zeros((outer, bins, 2));
+        let mut line = vec![(T::zero(), T::zero()); n];
+        let mut spectrum = vec![(T::zero(), T::zero()); bins];
+        for (lane, mut out) in signal.outer_iter().zip(output.outer_iter_mut()) {
+            for (j, x) in line.iter_mut().enumerate() {
+                *x = (lane[(j, 0)], if complex { lane[(j, 1)] } else { T::zero() });
+            }
+            dft_line(&twiddles, self.inverse, &line, &mut spectrum);
+            for (bin, &(re, im)) in spectrum.iter().enumerate() {
+                out[(bin, 0)] = re;
+                out[(bin, 1)] = im;
+            }
+        }
+        shape[rank - 2] = bins;
+        shape[rank - 1] = 2;
+        let mut output = output.into_shape(&*shape)?;
+        output.swap_axes(axis, rank - 2);
+        Ok(Array::from_shape_vec(output.shape(), output.iter().cloned().collect())?.into())
+    }
+}
+
+impl Op for Dft {
+    fn name(&self) -> Cow<str> {
+        "Dft".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!(
+            "axis: {}, inverse: {}, onesided: {}",
+            self.axis, self.inverse, self.onesided
+        )))
+    }
+}
+
+impl StatelessOp for Dft {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let length = inputs.get(1).map(|l| length_input("DFT", l)).transpose()?;
+        let signal = &inputs[0];
+        let output = match signal.datum_type() {
+            DatumType::F16 => self
+                .eval_t::<f32>(&*signal.cast_to::<f32>()?, length)?
+                .cast_to_dt(DatumType::F16)?
+                .into_owned(),
+            DatumType::F32 => self.eval_t::<f32>(signal, length)?,
+            DatumType::F64 => self.eval_t::<f64>(signal, length)?,
+            dt => bail!("DFT of {:?} is not supported", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Dft {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() < 1 || inputs.len() > 2 {
+            bail!("Wrong number of inputs. Expected 1 or 2, got {}", inputs.len());
+        }
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, &outputs[0].rank)?;
+        if inputs.len() == 2 {
+            s.equals(&inputs[1].rank, 0)?;
+        }
+        s.given(&inputs[0].rank, move |s, rank| {
+            let rank = rank as usize;
+            let axis = normalize_axis("DFT", self.axis, rank)?;
+            s.equals(&outputs[0].shape[rank - 1], 2.to_dim())?;
+            for ix in 0..rank - 1 {
+                if ix != axis {
+                    s.equals(&inputs[0].shape[ix], &outputs[0].shape[ix])?;
+                }
+            }
+            if inputs.len() == 2 {
+                s.given(&inputs[1].value, move |s, length| {
+                    let length = length_input("DFT", &length)?;
+                    s.equals(&outputs[0].shape[axis], bins(length, self.onesided).to_dim())
+                })
+            } else {
+                s.given(&inputs[0].shape[axis], move |s, n| {
+                    let n = n.to_integer()? as usize;
+                    s.equals(&outputs[0].shape[axis], bins(n, self.onesided).to_dim())
+                })
+            }
+        })
+    }
+}
+
+/// Short-time Fourier transform: the DFT of the frames of a signal.
+///
+/// Inputs are the signal, [batch, length, 1 or 2], the step between frames,
+/// then an optional window and an optional frame length, at least one of
+/// them being present. The output is [batch, frames, bins, 2], frames being
+/// `(length - frame_length) / frame_step + 1`.
+#[derive(Debug, Clone, new)]
+pub struct Stft {
+    pub onesided: bool,
+    pub have_window: bool,
+    pub have_frame_length: bool,
+}
+
+impl Stft {
+    /// Frame length, from the window and frame length inputs, after the
+    /// signal and the frame step.
+    fn frame_length(&self, inputs: &[Arc<Tensor>]) -> TractResult<usize> {
+        let window = if self.have_window { Some(inputs[2].shape().iter().product()) } else { None };
+        let frame_length = if self.have_frame_length {
+            Some(length_input("STFT", &inputs[2 + self.have_window as usize])?)
+        } else {
+            None
+        };
+        match (window, frame_length) {
+            (Some(w), Some(fl)) if w != fl => {
+                bail!("STFT: window of {} items for frames of {}", w, fl)
+            }
+            (Some(w), _) => Ok(w),
+            (None, Some(fl)) => Ok(fl),
+            (None, None) => bail!("STFT needs a window or a frame length"),
+        }
+    }
+
+    fn eval_t<T: Datum + Float>(
+        &self,
+        signal: &Tensor,
+        step: usize,
+        window: Option<&Tensor>,
+        frame_length: usize,
+    ) -> TractResult<Tensor> {
+        let signal = signal.to_array_view::<T>()?.into_dimensionality::<Ix3>()?;
+        let complex = is_complex("STFT", signal.shape())?;
+        if self.onesided && complex {
+            bail!("STFT: onesided transforms are for real signals only");
+        }
+        let window = match window {
+            Some(w) => Some(w.to_array_view::<T>()?.into_dimensionality::<Ix1>()?),
+            None => None,
+        };
+        let (batch, len) = (signal.shape()[0], signal.shape()[1]);
+        if len < frame_length {
+            bail!("STFT: signal of {} items shorter than a frame of {}", len, frame_length);
+        }
+        let frames = (len - frame_length) / step + 1;
+        let bins = bins(frame_length, self.onesided);
+        let twiddles = twiddles::<T>(frame_length);
+        let mut output = Array4::<T>::zeros((batch, frames, bins, 2));
+        let mut line = vec![(T::zero(), T::zero()); frame_length];
+        let mut spectrum = vec![(T::zero(), T::zero()); bins];
+        for b in 0..batch {
+            for f in 0..frames {
+                for (j, x) in line.iter_mut().enumerate() {
+                    let t = f * step + j;
+                    let w = window.as_ref().map(|w| w[j]).unwrap_or(T::one());
+                    let im = if complex { signal[(b, t, 1)] } else { T::zero() };
+                    *x = (signal[(b, t, 0)] * w, im * w);
+                }
+                dft_line(&twiddles, false, &line, &mut spectrum);
+                for (bin, &(re, im)) in spectrum.iter().enumerate() {
+                    output[(b, f, bin, 0)] = re;
+                    output[(b, f, bin, 1)] = im;
+                }
+            }
+        }
+        Ok(output.into())
+    }
+}
+
+impl Op for Stft {
+    fn name(&self) -> Cow<str> {
+        "Stft".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("onesided: {}", self.onesided)))
+    }
+}
+
+impl StatelessOp for Stft {
+    fn eval(&self, inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let frame_length = self.frame_length(&inputs)?;
+        let step = length_input("STFT", &inputs[1])?;
+        let window = if self.have_window { Some(&*inputs[2]) } else { None };
+        let signal = &inputs[0];
+        let output = match signal.datum_type() {
+            DatumType::F16 => {
+                let window = window.map(|w| w.cast_to::<f32>()).transpose()?;
+                self.eval_t::<f32>(
+                    &*signal.cast_to::<f32>()?,
+                    step,
+                    window.as_ref().map(|w| &**w),
+                    frame_length,
+                )?
+                .cast_to_dt(DatumType::F16)?
+                .into_owned()
+            }
+            DatumType::F32 => self.eval_t::<f32>(signal, step, window, frame_length)?,
+            DatumType::F64 => self.eval_t::<f64>(signal, step, window, frame_length)?,
+            dt => bail!("STFT of {:?} is not supported", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Stft {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(
+            &inputs,
+            2 + self.have_window as usize + self.have_frame_length as usize,
+        )?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.equals(&inputs[0].rank, 3)?;
+        s.equals(&inputs[1].rank, 0)?;
+        s.equals(&outputs[0].rank, 4)?;
+        s.equals(&inputs[0].shape[0], &outputs[0].shape[0])?;
+        s.equals(&outputs[0].shape[3], 2.to_dim())?;
+        if self.have_window {
+            s.equals(&inputs[2].datum_type, &inputs[0].datum_type)?;
+            s.equals(&inputs[2].rank, 1)?;
+        }
+        if self.have_window {
+            s.given(&inputs[2].shape[0], move |s, fl| {
+                self.frames_rules(s, inputs, outputs, fl.to_integer()? as usize)
+            })
+        } else {
+            s.given(&inputs[2].value, move |s, fl| {
+                self.frames_rules(s, inputs, outputs, length_input("STFT", &fl)?)
+            })
+        }
+    }
+}
+
+impl Stft {
+    fn frames_rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+        frame_length: usize,
+    ) -> InferenceResult {
+        s.equals(&outputs[0].shape[2], bins(frame_length, self.onesided).to_dim())?;
+        s.given_2(&inputs[0].shape[1], &inputs[1].value, move |s, len, step| {
+            let step = length_input("STFT", &step)?;
+            if let Ok(len) = len.to_integer() {
+                if len as usize >= frame_length {
+                    let frames = (len as usize - frame_length) / step + 1;
+                    s.equals(&outputs[0].shape[1], frames.to_dim())?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::signal::{GenerateWindow, WindowKind};
+
+    fn assert_close(found: ArrayViewD<f64>, expected: ArrayViewD<f64>) {
+        assert_eq!(found.shape(), expected.shape());
+        for (f, e) in found.iter().zip(expected.iter()) {
+            assert!((f - e).abs() < 1e-9, "{:?} for {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn onesided_and_inverse() {
+        // [batch, length, 1], transformed on axis 1
+        let signal = Array::from_shape_fn((2, 5, 1), |(b, t, _)| (b * 7 + t * t) as f64 % 3.0);
+        let signal: Arc<Tensor> = signal.into_arc_tensor();
+        let full = Dft::new(1, false, false).eval(tvec!(signal.clone())).unwrap().remove(0);
+        assert_eq!(full.shape(), &[2, 5, 2]);
+        let half = Dft::new(1, false, true).eval(tvec!(signal.clone())).unwrap().remove(0);
+        assert_eq!(half.shape(), &[2, 3, 2]);
+        let full_view = full.to_array_view::<f64>().unwrap();
+        assert_close(
+            half.to_array_view::<f64>().unwrap(),
+            full_view.slice(s![.., ..3, ..]).into_dyn(),
+        );
+        // bins past the half are the conjugates of the first ones
+        for b in 0..2 {
+            for k in 1..5 {
+                assert!((full_view[[b, k, 0]] - full_view[[b, 5 - k, 0]]).abs() < 1e-9);
+                assert!((full_view[[b, k, 1]] + full_view[[b, 5 - k, 1]]).abs() < 1e-9);
+            }
+        }
+        let back = Dft::new(-2, true, false).eval(tvec!(full)).unwrap().remove(0);
+        let back = back.to_array_view::<f64>().unwrap();
+        assert_close(
+            back.slice(s![.., .., 0..1]).into_dyn(),
+            signal.to_array_view::<f64>().unwrap(),
+        );
+        assert!(back.slice(s![.., .., 1]).iter().all(|im| im.abs() < 1e-9));
+    }
+
+    #[test]
+    fn onesided_even_length_with_padding() {
+        let signal = rctensor2(&[[1.0f64], [2.0], [3.0]]);
+        let padded =
+            Dft::new(0, false, true).eval(tvec!(signal, rctensor0(4i64))).unwrap().remove(0);
+        // [1, 2, 3, 0]: bins 0, 1 and the Nyquist one
+        let expected = arr2(&[[6.0, 0.0], [-2.0, -2.0], [2.0, 0.0]]).into_dyn();
+        assert_close(padded.to_array_view::<f64>().unwrap(), expected.view());
+    }
+
+    #[test]
+    fn windowed_stft_spectrogram() {
+        let (len, frame_length, step) = (20, 8, 3);
+        let signal = Array::from_shape_fn((1, len, 1), |(_, t, _)| {
+            (0.7 * t as f64).sin() + 0.25 * (2.1 * t as f64).cos()
+        });
+        let window = GenerateWindow::new(WindowKind::Hann, true, DatumType::F64)
+            .eval(tvec!(rctensor0(frame_length as i64)))
+            .unwrap()
+            .remove(0);
+        let stft = Stft::new(true, true, false)
+            .eval(tvec!(signal.clone().into_arc_tensor(), rctensor0(step as i64), window))
+            .unwrap()
+            .remove(0);
+        let frames = (len - frame_length) / step + 1;
+        let bins = frame_length / 2 + 1;
+        assert_eq!(stft.shape(), &[1, frames, bins, 2]);
+        let stft = stft.to_array_view::<f64>().unwrap();
+        for f in 0..frames {
+            for k in 0..bins {
+                let (mut re, mut im) = (0.0, 0.0);
+                for j in 0..frame_length {
+                    let w = 0.5 - 0.5 * (2.0 * PI * j as f64 / frame_length as f64).cos();
+                    let x = signal[(0, f * step + j, 0)] * w;
+                    let angle = 2.0 * PI * (k * j) as f64 / frame_length as f64;
+                    re += x * angle.cos();
+                    im -= x * angle.sin();
+                }
+                let power = re * re + im * im;
+                let found = stft[[0, f, k, 0]].powi(2) + stft[[0, f, k, 1]].powi(2);
+                assert!((power - found).abs() < 1e-9, "frame {} bin {}", f, k);
+            }
+        }
+    }
+
+    #[test]
+    fn stft_frame_length_mismatch() {
+        let signal = Array3::<f32>::zeros((1, 10, 1)).into_arc_tensor();
+        let window = rctensor1(&[1.0f32; 4]);
+        let op = Stft::new(true, true, true);
+        assert!(op
+            .eval(tvec!(signal.clone(), rctensor0(2i64), window.clone(), rctensor0(4i64)))
+            .is_ok());
+        assert!(op.eval(tvec!(signal, rctensor0(2i64), window, rctensor0(5i64))).is_err());
+    }
+}
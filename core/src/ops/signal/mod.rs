@@ -0,0 +1,11 @@
+//! Signal processing: window functions and discrete Fourier transforms, for
+//! audio frontends to compute their spectrograms in the graph.
+//!
+//! Complex tensors have a trailing axis of two items, the real and the
+//! imaginary parts. Real signals can come with a trailing axis of one.
+
+mod dft;
+mod window;
+
+pub use self::dft::{Dft, Stft};
+pub use self::window::{GenerateWindow, WindowKind};
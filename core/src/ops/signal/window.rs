@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+
+use crate::internal::*;
+use ndarray::*;
+
+/// Window functions, as ONNX defines them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowKind {
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowKind {
+    /// Value at `n` of the window spanning `period` items.
+    fn value(self, n: usize, period: usize) -> f64 {
+        let x = 2.0 * PI * n as f64 / period as f64;
+        match self {
+            WindowKind::Hann => 0.5 - 0.5 * x.cos(),
+            WindowKind::Hamming => {
+                let alpha = 25.0 / 46.0;
+                alpha - (1.0 - alpha) * x.cos()
+            }
+            WindowKind::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+/// A window of the length given by its scalar input.
+///
+/// Symmetric windows end on the value they start with. Periodic ones, the
+/// default of spectral analysis, are the first items of the symmetric window
+/// one item longer, so that overlapping frames add up evenly.
+#[derive(Debug, Clone, new)]
+pub struct GenerateWindow {
+    pub kind: WindowKind,
+    pub periodic: bool,
+    pub datum_type: DatumType,
+}
+
+impl GenerateWindow {
+    fn eval_t<T: Datum + num_traits::Float>(&self, size: usize) -> TractResult<Tensor> {
+        let period = if self.periodic { size } else { size.saturating_sub(1) };
+        let window = Array1::from_shape_fn(size, |n| {
+            // a symmetric window of one item has no period
+            let value = if period == 0 { 1.0 } else { self.kind.value(n, period) };
+            T::from(value).unwrap()
+        });
+        Ok(window.into())
+    }
+}
+
+impl Op for GenerateWindow {
+    fn name(&self) -> Cow<str> {
+        format!("{:?}Window", self.kind).into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("periodic: {}, datum_type: {:?}", self.periodic, self.datum_type)))
+    }
+}
+
+impl StatelessOp for GenerateWindow {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let size = args_1!(inputs);
+        let size = *size.cast_to::<i64>()?.to_scalar::<i64>()?;
+        if size < 0 {
+            bail!("Can not generate a window of {} items", size);
+        }
+        let output = match self.datum_type {
+            DatumType::F16 => {
+                self.eval_t::<f32>(size as usize)?.cast_to_dt(DatumType::F16)?.into_owned()
+            }
+            DatumType::F32 => self.eval_t::<f32>(size as usize)?,
+            DatumType::F64 => self.eval_t::<f64>(size as usize)?,
+            dt => bail!("Windows of type {:?} are not supported", dt),
+        };
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for GenerateWindow {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].rank, 0)?;
+        s.equals(&outputs[0].datum_type, self.datum_type)?;
+        s.equals(&outputs[0].rank, 1)?;
+        s.given(&inputs[0].value, move |s, size| {
+            let size = *size.cast_to::<i64>()?.to_scalar::<i64>()?;
+            if size >= 0 {
+                s.equals(&outputs[0].shape[0], (size as usize).to_dim())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn window(kind: WindowKind, periodic: bool, size: i64) -> Vec<f64> {
+        let op = GenerateWindow::new(kind, periodic, DatumType::F64);
+        let output = op.eval(tvec!(rctensor0(size))).unwrap().remove(0);
+        output.as_slice::<f64>().unwrap().to_vec()
+    }
+
+    fn assert_close(found: &[f64], expected: &[f64]) {
+        assert_eq!(found.len(), expected.len());
+        for (f, e) in found.iter().zip(expected.iter()) {
+            assert!((f - e).abs() < 1e-9, "{:?} for {:?}", found, expected);
+        }
+    }
+
+    #[test]
+    fn symmetric_and_periodic() {
+        assert_close(&window(WindowKind::Hann, false, 5), &[0.0, 0.5, 1.0, 0.5, 0.0]);
+        assert_close(&window(WindowKind::Hann, true, 4), &[0.0, 0.5, 1.0, 0.5]);
+        let alpha = 25.0 / 46.0;
+        let beta = 1.0 - alpha;
+        assert_close(
+            &window(WindowKind::Hamming, false, 3),
+            &[alpha - beta, alpha + beta, alpha - beta],
+        );
+        assert_close(&window(WindowKind::Blackman, false, 3), &[0.0, 1.0, 0.0]);
+        assert_close(&window(WindowKind::Blackman, true, 4), &[0.0, 0.34, 1.0, 0.34]);
+    }
+
+    #[test]
+    fn degenerate_sizes() {
+        assert_close(&window(WindowKind::Hann, false, 1), &[1.0]);
+        assert!(window(WindowKind::Hamming, true, 0).is_empty());
+        let op = GenerateWindow::new(WindowKind::Hann, true, DatumType::F32);
+        assert!(op.eval(tvec!(rctensor0(-1i64))).is_err());
+    }
+}
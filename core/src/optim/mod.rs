@@ -1,19 +1,60 @@
-use crate::model::TypedModel;
+use crate::model::{TypedModel, TypedModelPatch, TypedNode};
 use crate::TractResult;
 use std::fmt::Debug;
 
+mod nhwc_convs;
 mod prop_const;
 mod push_split_down;
 
+pub use self::nhwc_convs::NhwcConvs;
 use self::prop_const::PropConst;
 use self::push_split_down::PushSplitDown;
 
+/// A rewrite an optimization pass applied to a node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FusionEvent {
+    /// Name of the rewritten node.
+    pub node: String,
+    /// Name of its operator, before the rewrite.
+    pub op: String,
+    /// What was done, as labelled by the operator.
+    pub transformation: String,
+}
+
+impl FusionEvent {
+    fn of(node: &TypedNode, patch: &TypedModelPatch) -> Option<FusionEvent> {
+        patch.label().map(|label| FusionEvent {
+            node: node.name.clone(),
+            op: node.op().name().into_owned(),
+            transformation: label.to_string(),
+        })
+    }
+}
+
 pub trait DeclutterPass: Debug + Send + Sync {
     fn pass(&self, model: &mut TypedModel) -> TractResult<bool>;
+
+    /// Same as `pass`, pushing the labelled rewrites to `events`.
+    fn pass_reporting(
+        &self,
+        model: &mut TypedModel,
+        _events: &mut Vec<FusionEvent>,
+    ) -> TractResult<bool> {
+        self.pass(model)
+    }
 }
 
 pub trait CodegenPass: Debug + Send + Sync {
     fn pass(&self, model: &mut TypedModel) -> TractResult<bool>;
+
+    /// Same as `pass`, pushing the labelled rewrites to `events`.
+    fn pass_reporting(
+        &self,
+        model: &mut TypedModel,
+        _events: &mut Vec<FusionEvent>,
+    ) -> TractResult<bool> {
+        self.pass(model)
+    }
 }
 
 pub fn declutter() -> Vec<Box<DeclutterPass>> {
@@ -29,6 +70,14 @@ pub struct NormalizeOps;
 
 impl DeclutterPass for NormalizeOps {
     fn pass(&self, model: &mut TypedModel) -> TractResult<bool> {
+        self.pass_reporting(model, &mut vec![])
+    }
+
+    fn pass_reporting(
+        &self,
+        model: &mut TypedModel,
+        events: &mut Vec<FusionEvent>,
+    ) -> TractResult<bool> {
         let mut done_something = false;
         loop {
             let mut done_something_this_time = false;
@@ -43,6 +92,7 @@ impl DeclutterPass for NormalizeOps {
                 if let Some(red) = reduced {
                     {
                         let node = &model.nodes()[id];
+                        events.extend(FusionEvent::of(node, &red));
                         debug!("Apply a model patch for {:?}: {}", self, node);
                     }
                     red.apply(model)?;
@@ -66,6 +116,14 @@ pub struct CodegenOps;
 
 impl CodegenPass for CodegenOps {
     fn pass(&self, model: &mut TypedModel) -> TractResult<bool> {
+        self.pass_reporting(model, &mut vec![])
+    }
+
+    fn pass_reporting(
+        &self,
+        model: &mut TypedModel,
+        events: &mut Vec<FusionEvent>,
+    ) -> TractResult<bool> {
         let mut done_something = false;
         loop {
             let mut done_something_this_time = false;
@@ -80,6 +138,7 @@ impl CodegenPass for CodegenOps {
                 if let Some(red) = reduced {
                     {
                         let node = &model.nodes()[id];
+                        events.extend(FusionEvent::of(node, &red));
                         debug!("Apply a model patch for {:?} {}", self, node);
                     }
                     red.apply(model)?;
@@ -0,0 +1,24 @@
+use crate::internal::*;
+use crate::ops::cnn::ConvUnary;
+
+/// Moves the NCHW convolutions to NHWC, see `TypedModel::into_nhwc_convs`.
+#[derive(Debug)]
+pub struct NhwcConvs;
+
+impl super::DeclutterPass for NhwcConvs {
+    fn pass(&self, model: &mut TypedModel) -> TractResult<bool> {
+        let mut done_something = false;
+        for id in model.eval_order()? {
+            let patch = match model.node(id).op_as::<ConvUnary>() {
+                Some(conv) => conv.nhwc_patch(model, model.node(id))?,
+                None => None,
+            };
+            if let Some(patch) = patch {
+                debug!("Convolving {} in NHWC", model.node(id));
+                patch.apply(model)?;
+                done_something = true;
+            }
+        }
+        Ok(done_something)
+    }
+}
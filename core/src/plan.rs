@@ -3,7 +3,8 @@ use std::marker::PhantomData;
 
 use crate::internal::*;
 use crate::model::order::eval_order_for_nodes;
-use crate::model::{Model, OutletId, TensorInfo};
+use crate::model::{Model, Node, OutletId, TensorInfo};
+use crate::ops::cnn::Arena;
 
 #[derive(Debug, Default)]
 pub struct SessionState {
@@ -11,12 +12,35 @@ pub struct SessionState {
     pub tensors: HashMap<String, Tensor>,
 }
 
+/// Callbacks around the evaluation of each node by a `SimpleState`, for
+/// tracing, metrics or any custom instrumentation. See
+/// `SimpleState::set_hook`.
+///
+/// Tensors are lent for the duration of the call only: a hook keeping one
+/// must clone it, as holding on to it would stop ops from reusing their input
+/// buffers.
+pub trait NodeHook: Send {
+    /// Called with the inputs of a node, before it is evaluated.
+    fn before_eval(&mut self, _node: usize, _name: &str, _op: &str, _inputs: &[&Tensor]) {}
+
+    /// Called with the outputs of a node, after it is evaluated successfully.
+    fn after_eval(&mut self, _node: usize, _name: &str, _op: &str, _outputs: &[&Tensor]) {}
+}
+
 #[derive(Debug, Clone)]
 pub struct SimplePlan<TI: TensorInfo, M: Borrow<Model<TI>>> {
     pub model: M,
     pub outputs: Vec<OutletId>,
     pub order: Vec<usize>,
     pub flush_lists: Vec<TVec<usize>>,
+    /// Cap on the bytes held by values during a run, see `with_memory_budget`.
+    pub memory_budget: Option<usize>,
+    /// Check every value against the facts of its outlet while running, see
+    /// `with_fact_checks`.
+    pub check_facts: bool,
+    /// Scratch bytes of each node evaluated with an arena, see
+    /// `with_scratch_arena`. Empty without an arena.
+    pub scratch_bytes: Vec<usize>,
     _casper: PhantomData<TI>,
 }
 
@@ -55,29 +79,166 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>> SimplePlan<TI, M> {
             order,
             flush_lists,
             outputs: outputs.to_vec(),
+            memory_budget: None,
+            check_facts: cfg!(debug_assertions),
+            scratch_bytes: vec![],
             _casper: PhantomData,
         })
     }
 
+    /// Try to keep the bytes held by values under `bytes` during runs, by
+    /// dropping values that are needed later and computing them again when
+    /// they are, cheapest recomputations first.
+    ///
+    /// Only outputs of stateless ops are dropped and recomputed: running a
+    /// stateful op twice would advance its state. Model inputs are kept for
+    /// the whole run, as recomputations start from them. The budget is not a
+    /// hard limit: a node still runs if its inputs and outputs alone exceed
+    /// it.
+    pub fn with_memory_budget(mut self, bytes: usize) -> SimplePlan<TI, M> {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Check the inputs and outputs of every node against the facts
+    /// inference gave them while running, failing on the first mismatch with
+    /// the node name. On by default in debug builds only.
+    pub fn with_fact_checks(mut self, check: bool) -> SimplePlan<TI, M> {
+        self.check_facts = check;
+        self
+    }
+
+    /// Take the scratch buffers of the ops that can use one, the im2col
+    /// convolutions, from an arena each state allocates once, instead of
+    /// allocating them for each evaluation.
+    ///
+    /// The arena fits the largest scratch of the nodes with a concrete input
+    /// shape, and is reset after each of them. Other nodes, and all the
+    /// values, are allocated as usual: values may outlive the run.
+    pub fn with_scratch_arena(mut self) -> TractResult<SimplePlan<TI, M>> {
+        let model = self.model.borrow();
+        let mut scratch_bytes = vec![0; model.nodes().len()];
+        for &node in &self.order {
+            let op = match model.node(node).op().as_stateless() {
+                Some(op) => op,
+                None => continue,
+            };
+            let shapes = model
+                .node_input_facts(node)?
+                .iter()
+                .map(|fact| fact.to_tensor_fact().shape.as_concrete_finite())
+                .collect::<TractResult<Option<Vec<_>>>>()?;
+            if let Some(shapes) = shapes {
+                let shapes: Vec<&[usize]> = shapes.iter().map(|s| &**s).collect();
+                scratch_bytes[node] = op.scratch_bytes(&shapes)?;
+            }
+        }
+        self.scratch_bytes = scratch_bytes;
+        Ok(self)
+    }
+
     pub fn run(&self, inputs: TVec<Tensor>) -> TractResult<TVec<Arc<Tensor>>> {
         let mut state = SimpleState::new(self)?;
         state.run(inputs)
     }
 
+    /// Run the plan once on zeroed inputs shaped after the model input
+    /// facts, discarding the outputs.
+    ///
+    /// Ops defer some setup to their first evaluation: kernel packing,
+    /// scratch panel allocation... Warming up a plan before timing it keeps
+    /// this one-time cost out of the measures. Input facts must have a known
+    /// type and finite shape: use `warmup_with` for other models, or to warm
+    /// up for different input shapes.
+    pub fn warmup(&self) -> TractResult<()> {
+        let model = self.model();
+        let inputs = model
+            .input_outlets()?
+            .iter()
+            .map(|&input| -> TractResult<Tensor> {
+                let fact = model.outlet_fact(input)?.to_tensor_fact();
+                match (fact.datum_type.concretize(), fact.shape.as_concrete_finite()?) {
+                    (Some(dt), Some(shape)) => Ok(dispatch_datum!(zeroed(dt)(&shape))),
+                    _ => bail!(
+                        "Can not warm up for input {} of unknown type or shape ({:?})",
+                        model.outlet_name(input),
+                        fact
+                    ),
+                }
+            })
+            .collect::<TractResult<TVec<_>>>()?;
+        self.warmup_with(inputs)
+    }
+
+    /// Run the plan once on `inputs`, representative of the ones to come,
+    /// discarding the outputs. See `warmup`.
+    pub fn warmup_with(&self, inputs: TVec<Tensor>) -> TractResult<()> {
+        self.run(inputs)?;
+        Ok(())
+    }
+
+    /// Run the plan, binding inputs and outputs by name.
+    ///
+    /// See `SimpleState::run_named`.
+    pub fn run_named(
+        &self,
+        inputs: impl IntoIterator<Item = (impl AsRef<str>, Tensor)>,
+    ) -> TractResult<HashMap<String, Arc<Tensor>>> {
+        let mut state = SimpleState::new(self)?;
+        state.run_named(inputs)
+    }
+
+    /// Names of the outputs computed by the plan, in order.
+    pub fn output_names(&self) -> Vec<String> {
+        let model = self.model();
+        let names = model.output_names();
+        self.outputs
+            .iter()
+            .map(|&o| match model.outputs.iter().position(|&mo| mo == o) {
+                Some(ix) => names[ix].clone(),
+                None => model.outlet_name(o),
+            })
+            .collect()
+    }
+
     pub fn model(&self) -> &Model<TI> {
         self.model.borrow()
     }
 }
 
-#[derive(Debug)]
 pub struct SimpleState<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> {
     plans: Vec<P>,
     pub states: Vec<Option<Box<OpState>>>,
     pub session_state: SessionState,
     pub values: Vec<Option<TVec<Arc<Tensor>>>>,
+    /// Most bytes held by values at once during the last run.
+    pub peak_bytes: usize,
+    /// See `SimplePlan::with_scratch_arena`.
+    scratch: Option<Arena>,
+    /// See `set_hook`.
+    hook: Option<Box<NodeHook>>,
     _phantom: PhantomData<(M, TI)>,
 }
 
+impl<TI, M, P> std::fmt::Debug for SimpleState<TI, M, P>
+where
+    TI: TensorInfo,
+    M: Borrow<Model<TI>> + std::fmt::Debug,
+    P: Borrow<SimplePlan<TI, M>> + std::fmt::Debug,
+{
+    // the hook is left out, hooks are not Debug
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SimpleState")
+            .field("plans", &self.plans)
+            .field("states", &self.states)
+            .field("session_state", &self.session_state)
+            .field("values", &self.values)
+            .field("peak_bytes", &self.peak_bytes)
+            .field("scratch", &self.scratch)
+            .finish()
+    }
+}
+
 impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>> + Clone> Clone
     for SimpleState<TI, M, P>
 {
@@ -94,6 +255,9 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>> + Clone>
             states,
             session_state: SessionState::default(),
             values: self.values.clone(),
+            peak_bytes: self.peak_bytes,
+            scratch: self.scratch.as_ref().map(|a| Arena::new(a.capacity())),
+            hook: None,
             _phantom: PhantomData,
         }
     }
@@ -110,7 +274,31 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
         let model = plans[0].borrow().model();
         let states =
             model.nodes().iter().map(|n| n.op().state(&mut session)).collect::<TractResult<_>>()?;
-        Ok(SimpleState { plans, states, session_state: session, values, _phantom: PhantomData })
+        let scratch = plans.iter().flat_map(|p| p.borrow().scratch_bytes.iter()).max().cloned();
+        let scratch = scratch.filter(|&bytes| bytes > 0).map(Arena::new);
+        Ok(SimpleState {
+            plans,
+            states,
+            session_state: session,
+            values,
+            peak_bytes: 0,
+            scratch,
+            hook: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Call `hook` around the evaluation of each node by `run` and the
+    /// methods built on it, and by `compute_one`, replacing the previous
+    /// hook. Model inputs are not evaluated, and the recomputations of a
+    /// memory budget are not reported. Clones of the state do not share it.
+    pub fn set_hook(&mut self, hook: impl NodeHook + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    /// Remove the hook of the state, returning it.
+    pub fn take_hook(&mut self) -> Option<Box<NodeHook>> {
+        self.hook.take()
     }
 
     /// Reset wires state.
@@ -136,6 +324,80 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
         self.run_plan(inputs, 0)
     }
 
+    /// Run the model, binding inputs and outputs by name.
+    ///
+    /// Inputs can come in any order, but each model input must be given
+    /// exactly once. Outputs are returned by name.
+    pub fn run_named(
+        &mut self,
+        inputs: impl IntoIterator<Item = (impl AsRef<str>, Tensor)>,
+    ) -> TractResult<HashMap<String, Arc<Tensor>>> {
+        let names: Vec<String> =
+            self.model().input_names().into_iter().map(|s| s.to_string()).collect();
+        let mut slots: Vec<Option<Tensor>> = vec![None; names.len()];
+        for (name, t) in inputs {
+            let name = name.as_ref();
+            let ix = names
+                .iter()
+                .position(|n| n == name)
+                .ok_or_else(|| format!("Unknown input {:?}, model expects {:?}", name, names))?;
+            if slots[ix].is_some() {
+                bail!("Input {:?} given more than once", name);
+            }
+            slots[ix] = Some(t);
+        }
+        let missing: Vec<&String> =
+            names.iter().zip(slots.iter()).filter(|(_, t)| t.is_none()).map(|(n, _)| n).collect();
+        if !missing.is_empty() {
+            bail!("Missing inputs {:?}, model expects {:?}", missing, names);
+        }
+        let outputs = self.run(slots.into_iter().map(|t| t.unwrap()).collect())?;
+        Ok(self.plan().output_names().into_iter().zip(outputs).collect())
+    }
+
+    /// Run the model, writing its outputs into the `outputs` buffers
+    /// instead of returning them.
+    ///
+    /// Each buffer must have the datum type and shape of the output it
+    /// receives: the same buffers can be passed run after run, they are
+    /// written in place and never reallocated. Buffers are checked against
+    /// the output facts before running, and against the computed outputs
+    /// before any of them is written: on a mismatch, all of them are left
+    /// untouched.
+    pub fn run_into(&mut self, inputs: TVec<Tensor>, outputs: &mut [Tensor]) -> TractResult<()> {
+        let plan = self.plan();
+        if outputs.len() != plan.outputs.len() {
+            bail!("Expected {} output buffers, got {}", plan.outputs.len(), outputs.len());
+        }
+        for (ix, (outlet, buffer)) in plan.outputs.iter().zip(outputs.iter()).enumerate() {
+            let fact = plan.model().outlet_fact(*outlet)?.to_tensor_fact();
+            let dt = fact.datum_type.concretize();
+            let shape = fact.shape.as_concrete_finite()?;
+            if dt.map(|dt| dt != buffer.datum_type()).unwrap_or(false)
+                || shape.map(|s| &*s != buffer.shape()).unwrap_or(false)
+            {
+                bail!("Output buffer {} is {:?}, expected {:?}", ix, buffer, fact);
+            }
+        }
+        let values = self.run(inputs)?;
+        for (ix, (value, buffer)) in values.iter().zip(outputs.iter()).enumerate() {
+            if value.datum_type() != buffer.datum_type() || value.shape() != buffer.shape() {
+                bail!(
+                    "Output buffer {} is {:?} {:?}, output is {:?} {:?}",
+                    ix,
+                    buffer.datum_type(),
+                    buffer.shape(),
+                    value.datum_type(),
+                    value.shape()
+                );
+            }
+        }
+        for (value, buffer) in values.iter().zip(outputs.iter_mut()) {
+            dispatch_datum!(copy_into(value.datum_type())(value, buffer))?;
+        }
+        Ok(())
+    }
+
     pub fn run_plan(
         &mut self,
         inputs: TVec<Tensor>,
@@ -149,14 +411,43 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
                 ref mut session_state,
                 ref mut states,
                 ref mut values,
+                ref mut peak_bytes,
+                ref mut scratch,
+                ref mut hook,
                 ..
             } = self;
             let plan = plans[plan].borrow();
             let model = plan.model().borrow();
+            let mut live: usize = values.iter().map(values_bytes).sum();
+            *peak_bytes = live;
+            let mut needed_until = vec![0; values.len()];
+            for (step, flush) in plan.flush_lists.iter().enumerate() {
+                for &node in flush {
+                    needed_until[node] = step;
+                }
+            }
             for (step, n) in plan.order.iter().enumerate() {
                 let node = model.node(*n);
                 trace!("Running step {}, node {}", step, node);
                 if !model.inputs.iter().any(|outlet| outlet.node == *n) {
+                    if let Some(budget) = plan.memory_budget {
+                        let budgeted = Budgeted {
+                            model,
+                            steps: plan.order.len(),
+                            needed_until: &needed_until,
+                        };
+                        budgeted.restore_inputs(step, node.id, values, &mut live)?;
+                        *peak_bytes = (*peak_bytes).max(live);
+                        let room = budgeted.output_bytes(node.id)?;
+                        budgeted.evict(
+                            step,
+                            node.id,
+                            budget.saturating_sub(room),
+                            states,
+                            values,
+                            &mut live,
+                        );
+                    }
                     let mut inputs: TVec<Arc<Tensor>> = tvec![];
                     for i in &node.inputs {
                         trace!("  use input {:?}", i);
@@ -167,74 +458,47 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
                         inputs.push(prec[i.slot].clone().into())
                     }
 
-                    if cfg!(debug_assertions) {
-                        let facts = model.node_input_facts(node.id)?;
-                        if facts.len() != inputs.len() {
-                            bail!(
-                                "Evaluating {}: expected {} inputs, got {}",
-                                node,
-                                facts.len(),
-                                inputs.len()
-                            );
-                        }
-                        for (ix, (v, f)) in inputs.iter().zip(facts.iter()).enumerate() {
-                            if f.to_tensor_fact().is_concrete()
-                                && f.to_tensor_fact().stream_info()?.is_some()
-                            {
-                                continue;
-                            }
-                            if let Err(e) = f.to_tensor_fact().unify(&v.clone().into()) {
-                                bail!(
-                                    "Evaluating {}: input {:?}, expected {:?}, got {:?} ({})",
-                                    node,
-                                    ix,
-                                    f,
-                                    v,
-                                    e
-                                );
-                            }
-                        }
+                    if plan.check_facts {
+                        check_facts(node, "input", &inputs, &model.node_input_facts(node.id)?)?;
                     }
 
-                    let vs = match states[node.id] {
-                        Some(ref mut state) => state.eval(session_state, node.op(), inputs),
-                        None => node.op().as_stateless().unwrap().eval(inputs),
+                    if let Some(hook) = hook.as_mut() {
+                        run_hook(&mut **hook, false, node, &inputs);
                     }
-                    .map_err(|e| format!("Evaluating {}: {}", node, e))?;
 
-                    if cfg!(debug_assertions) {
-                        let facts = model.node_output_facts(node.id)?;
-                        if facts.len() != vs.len() {
-                            bail!(
-                                "Evaluating {}: expected {} outputs, got {}",
-                                node,
-                                facts.len(),
-                                vs.len()
-                            );
-                        }
-                        for (ix, (v, f)) in vs.iter().zip(facts.iter()).enumerate() {
-                            if f.to_tensor_fact().is_concrete()
-                                && f.to_tensor_fact().stream_info()?.is_some()
-                            {
-                                continue;
-                            }
-                            if let Err(e) = f.to_tensor_fact().unify(&v.clone().into()) {
-                                bail!(
-                                    "Evaluating {}: output {:?}, expected {:?}, got {:?} ({})",
-                                    node,
-                                    ix,
-                                    f,
-                                    v,
-                                    e
-                                );
-                            }
+                    let arena = scratch
+                        .as_mut()
+                        .filter(|_| plan.scratch_bytes.get(node.id).cloned().unwrap_or(0) > 0);
+                    let vs = match (states[node.id].as_mut(), arena) {
+                        (Some(state), _) => state.eval(session_state, node.op(), inputs),
+                        (None, Some(arena)) => {
+                            arena.reset();
+                            node.op().as_stateless().unwrap().eval_with_scratch(inputs, arena)
                         }
+                        (None, None) => node.op().as_stateless().unwrap().eval(inputs),
+                    }
+                    .map_err(|e| format!("Evaluating {}: {}", node, e))?;
+
+                    if let Some(hook) = hook.as_mut() {
+                        run_hook(&mut **hook, true, node, &vs);
+                    }
+
+                    if plan.check_facts {
+                        check_facts(node, "output", &vs, &model.node_output_facts(node.id)?)?;
                     }
 
+                    live += vs.iter().map(|t| tensor_bytes(t)).sum::<usize>();
+                    *peak_bytes = (*peak_bytes).max(live);
                     values[node.id] = Some(vs);
                 }
                 for flush in &plan.flush_lists[step] {
+                    if plan.memory_budget.is_some()
+                        && model.inputs.iter().any(|outlet| outlet.node == *flush)
+                    {
+                        continue;
+                    }
                     trace!("  flushing node {} {}", flush, node);
+                    live -= values_bytes(&values[*flush]);
                     values[*flush] = None;
                 }
             }
@@ -294,7 +558,8 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
     }
 
     pub fn compute_one(&mut self, node: usize) -> TractResult<()> {
-        let SimpleState { ref plans, ref mut session_state, ref mut values, .. } = self;
+        let SimpleState { ref plans, ref mut session_state, ref mut values, ref mut hook, .. } =
+            self;
         let plan = plans[0].borrow();
         let nodes = plan.model().nodes();
         let node = &nodes[node];
@@ -306,11 +571,17 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
                 .ok_or_else(|| format!("Computing {}, precursor {} not done.", node, prec_node))?;
             inputs.push(prec[i.slot].clone().into())
         }
+        if let Some(hook) = hook.as_mut() {
+            run_hook(&mut **hook, false, node, &inputs);
+        }
         let vs = match self.states[node.id] {
             Some(ref mut state) => state.eval(session_state, node.op(), inputs),
             None => node.op().as_stateless().unwrap().eval(inputs),
         }
         .map_err(|e| format!("Evaluating {}: {}", node, e))?;
+        if let Some(hook) = hook.as_mut() {
+            run_hook(&mut **hook, true, node, &vs);
+        }
         values[node.id] = Some(vs);
         Ok(())
     }
@@ -361,6 +632,11 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
             .collect())
     }
 
+    /// The scratch arena of the state, if its plan has one.
+    pub fn scratch_arena(&self) -> Option<&Arena> {
+        self.scratch.as_ref()
+    }
+
     pub fn plan(&self) -> &SimplePlan<TI, M> {
         &self.plans[0].borrow()
     }
@@ -369,3 +645,527 @@ impl<TI: TensorInfo, M: Borrow<Model<TI>>, P: Borrow<SimplePlan<TI, M>>> SimpleS
         self.plan().model()
     }
 }
+
+/// Lend the inputs or outputs of `node` to `hook`.
+fn run_hook<TI: TensorInfo>(
+    hook: &mut NodeHook,
+    after: bool,
+    node: &Node<TI>,
+    tensors: &[Arc<Tensor>],
+) {
+    let tensors: TVec<&Tensor> = tensors.iter().map(|t| &**t).collect();
+    let op = node.op().name();
+    if after {
+        hook.after_eval(node.id, &node.name, &op, &tensors)
+    } else {
+        hook.before_eval(node.id, &node.name, &op, &tensors)
+    }
+}
+
+fn tensor_bytes(t: &Tensor) -> usize {
+    t.shape().iter().product::<usize>() * t.datum_type().size_of()
+}
+
+fn zeroed<T: Datum>(shape: &[usize]) -> Tensor {
+    ndarray::ArrayD::<T>::default(shape).into()
+}
+
+fn values_bytes(values: &Option<TVec<Arc<Tensor>>>) -> usize {
+    values.iter().flat_map(|vs| vs.iter()).map(|t| tensor_bytes(t)).sum()
+}
+
+/// Copy `src` into `dst`, of the same type and shape, in place.
+fn copy_into<T: Datum>(src: &Tensor, dst: &mut Tensor) -> TractResult<()> {
+    dst.as_slice_mut::<T>()?.clone_from_slice(src.as_slice::<T>()?);
+    Ok(())
+}
+
+/// Fails, naming `node`, if `values` do not match the `facts` of its inputs
+/// or outputs (`what`).
+fn check_facts<TI: TensorInfo>(
+    node: &Node<TI>,
+    what: &str,
+    values: &[Arc<Tensor>],
+    facts: &[&TI],
+) -> TractResult<()> {
+    if facts.len() != values.len() {
+        bail!("Evaluating {}: expected {} {}s, got {}", node, facts.len(), what, values.len());
+    }
+    for (ix, (v, f)) in values.iter().zip(facts.iter()).enumerate() {
+        let f = f.to_tensor_fact();
+        if f.is_concrete() && f.stream_info()?.is_some() {
+            continue;
+        }
+        if let Err(e) = f.unify(&v.clone().into()) {
+            bail!("Evaluating {}: {} {:?}, expected {:?}, got {:?} ({})", node, what, ix, f, v, e);
+        }
+    }
+    Ok(())
+}
+
+/// Drops and recomputes values of a plan run under a memory budget.
+struct Budgeted<'a, TI: TensorInfo> {
+    model: &'a Model<TI>,
+    /// Number of steps in the plan.
+    steps: usize,
+    /// Last step using the value of each node.
+    needed_until: &'a [usize],
+}
+
+impl<'a, TI: TensorInfo> Budgeted<'a, TI> {
+    fn is_input(&self, node: usize) -> bool {
+        self.model.inputs.iter().any(|outlet| outlet.node == node)
+    }
+
+    /// Bytes of the outputs of `node`, as far as its facts tell.
+    fn output_bytes(&self, node: usize) -> TractResult<usize> {
+        let mut bytes = 0;
+        for fact in self.model.node_output_facts(node)? {
+            let fact = fact.to_tensor_fact();
+            if let (Some(dt), Some(shape)) =
+                (fact.datum_type.concretize(), fact.shape.as_concrete_finite()?)
+            {
+                bytes += shape.iter().product::<usize>() * dt.size_of();
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Number of nodes to run to get the value of `node` back at any step
+    /// up to `until`, or None if it can not be recomputed.
+    fn recompute_cost(
+        &self,
+        node: usize,
+        until: usize,
+        states: &[Option<Box<OpState>>],
+        values: &[Option<TVec<Arc<Tensor>>>],
+    ) -> Option<usize> {
+        if self.is_input(node) || (values[node].is_some() && self.needed_until[node] >= until) {
+            return Some(0);
+        }
+        let op = self.model.node(node).op();
+        if states[node].is_some() || op.as_stateless().is_none() {
+            return None;
+        }
+        let mut cost = 1;
+        for input in &self.model.node(node).inputs {
+            cost += self.recompute_cost(input.node, until, states, values)?;
+        }
+        Some(cost)
+    }
+
+    /// Drop values, other than the inputs of `node`, until at most `target`
+    /// bytes are held.
+    fn evict(
+        &self,
+        step: usize,
+        node: usize,
+        target: usize,
+        states: &[Option<Box<OpState>>],
+        values: &mut [Option<TVec<Arc<Tensor>>>],
+        live: &mut usize,
+    ) {
+        let inputs: TVec<usize> = self.model.node(node).inputs.iter().map(|i| i.node).collect();
+        while *live > target {
+            let victim = (0..values.len())
+                .filter(|&n| {
+                    values[n].is_some()
+                        && !self.is_input(n)
+                        && !inputs.contains(&n)
+                        && self.needed_until[n] > step
+                        && self.needed_until[n] < self.steps
+                })
+                .filter(|&n| {
+                    states[n].is_none() && self.model.node(n).op().as_stateless().is_some()
+                })
+                .filter_map(|n| {
+                    let until = self.needed_until[n];
+                    let mut cost = 1;
+                    for input in &self.model.node(n).inputs {
+                        cost += self.recompute_cost(input.node, until, states, values)?;
+                    }
+                    Some((n, cost))
+                })
+                .min_by_key(|&(n, cost)| {
+                    (
+                        cost,
+                        std::cmp::Reverse(values_bytes(&values[n])),
+                        std::cmp::Reverse(self.needed_until[n]),
+                    )
+                });
+            if let Some((victim, _)) = victim {
+                trace!("  evicting node {}", victim);
+                *live -= values_bytes(&values[victim]);
+                values[victim] = None;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Compute again the evicted inputs of `node`.
+    fn restore_inputs(
+        &self,
+        step: usize,
+        node: usize,
+        values: &mut [Option<TVec<Arc<Tensor>>>],
+        live: &mut usize,
+    ) -> TractResult<()> {
+        let mut restored = false;
+        for input in &self.model.node(node).inputs {
+            if values[input.node].is_none() {
+                self.restore(input.node, values, live)?;
+                restored = true;
+            }
+        }
+        if restored {
+            // drop what was only recomputed on the way
+            for n in 0..values.len() {
+                if values[n].is_some() && !self.is_input(n) && self.needed_until[n] < step {
+                    *live -= values_bytes(&values[n]);
+                    values[n] = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn restore(
+        &self,
+        node: usize,
+        values: &mut [Option<TVec<Arc<Tensor>>>],
+        live: &mut usize,
+    ) -> TractResult<()> {
+        let node = self.model.node(node);
+        trace!("  recomputing node {}", node);
+        let mut inputs: TVec<Arc<Tensor>> = tvec!();
+        for input in &node.inputs {
+            if values[input.node].is_none() {
+                self.restore(input.node, values, live)?;
+            }
+            inputs.push(values[input.node].as_ref().unwrap()[input.slot].clone());
+        }
+        let stateless = node
+            .op()
+            .as_stateless()
+            .ok_or_else(|| format!("Can not recompute {}, it is stateful", node))?;
+        let vs = stateless.eval(inputs).map_err(|e| format!("Evaluating {}: {}", node, e))?;
+        *live += vs.iter().map(|t| tensor_bytes(t)).sum::<usize>();
+        values[node.id] = Some(vs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, ConvUnary};
+
+    fn sub_model() -> InferenceModel {
+        let mut model = InferenceModel::default();
+        model.add_source_default("a").unwrap();
+        model.add_source_default("b").unwrap();
+        let sub = model.add_node_default("sub", crate::ops::math::Sub::default()).unwrap();
+        model.add_edge(OutletId::new(0, 0), InletId::new(sub, 0)).unwrap();
+        model.add_edge(OutletId::new(1, 0), InletId::new(sub, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(sub, 0)]).unwrap();
+        model.set_output_labels(vec!["diff"]).unwrap();
+        model
+    }
+
+    #[test]
+    fn run_named_in_any_order() {
+        let model = sub_model();
+        let plan = SimplePlan::new(&model).unwrap();
+        let outputs = plan
+            .run_named(tvec!(("b", tensor1(&[1.0f32, 2.0])), ("a", tensor1(&[5.0f32, 7.0]))))
+            .unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(*outputs["diff"], tensor1(&[4.0f32, 5.0]));
+    }
+
+    #[test]
+    fn run_named_missing_input() {
+        let model = sub_model();
+        let plan = SimplePlan::new(&model).unwrap();
+        let err = plan.run_named(tvec!(("a", tensor1(&[5.0f32])))).unwrap_err();
+        assert!(err.to_string().contains(r#"Missing inputs ["b"], model expects ["a", "b"]"#));
+        assert!(plan.run_named(tvec!(("c", tensor1(&[5.0f32])))).is_err());
+    }
+
+    /// Records the node events, in order.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl NodeHook for Recorder {
+        fn before_eval(&mut self, _node: usize, name: &str, op: &str, inputs: &[&Tensor]) {
+            self.0.lock().unwrap().push(format!("before {} {} {}", name, op, inputs.len()));
+        }
+
+        fn after_eval(&mut self, _node: usize, name: &str, _op: &str, outputs: &[&Tensor]) {
+            self.0.lock().unwrap().push(format!("after {} {:?}", name, outputs[0]));
+        }
+    }
+
+    #[test]
+    fn hooks_follow_eval_order() {
+        let mut model = sub_model();
+        let mul = model.add_node_default("mul", crate::ops::math::Mul::default()).unwrap();
+        let sub = model.node_by_name("sub").unwrap().id;
+        model.add_edge(OutletId::new(sub, 0), InletId::new(mul, 0)).unwrap();
+        model.add_edge(OutletId::new(0, 0), InletId::new(mul, 1)).unwrap();
+        model.set_output_outlets(&[OutletId::new(mul, 0)]).unwrap();
+        let plan = SimplePlan::new(&model).unwrap();
+        let mut state = SimpleState::new(&plan).unwrap();
+        let recorder = Recorder::default();
+        state.set_hook(recorder.clone());
+        let outputs = state.run(tvec!(tensor1(&[5.0f32]), tensor1(&[1.0f32]))).unwrap();
+        assert_eq!(*outputs[0], tensor1(&[20.0f32]));
+        let expected: Vec<String> = plan
+            .order
+            .iter()
+            .map(|&n| model.node(n))
+            .filter(|node| !node.inputs.is_empty())
+            .flat_map(|node| {
+                let output = if node.name == "sub" { 4.0f32 } else { 20.0 };
+                vec![
+                    format!("before {} {} 2", node.name, node.op().name()),
+                    format!("after {} {:?}", node.name, tensor1(&[output])),
+                ]
+            })
+            .collect();
+        assert_eq!(*recorder.0.lock().unwrap(), expected);
+        assert!(expected[0].starts_with("before sub"));
+        assert!(state.take_hook().is_some());
+        state.run(tvec!(tensor1(&[5.0f32]), tensor1(&[1.0f32]))).unwrap();
+        assert_eq!(recorder.0.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn run_into_reused_buffer() {
+        let mut model = sub_model();
+        for input in 0..2 {
+            let fact = TensorFact::dt_shape(f32::datum_type(), tvec!(2usize));
+            model.set_input_fact(input, fact).unwrap();
+        }
+        let model = model.into_typed().unwrap();
+        let plan = SimplePlan::new(&model).unwrap();
+        let mut state = SimpleState::new(&plan).unwrap();
+        // checked against the facts, before running
+        let mut wrong = [tensor1(&[0.0f32, 0.0, 0.0])];
+        let err = state
+            .run_into(tvec!(tensor1(&[1.0f32, 2.0]), tensor1(&[1.0f32, 2.0])), &mut wrong)
+            .unwrap_err();
+        assert!(err.to_string().contains("Output buffer 0"), "{}", err);
+        let mut outputs = [tensor1(&[0.0f32, 0.0])];
+        let buffer = outputs[0].as_ptr::<f32>().unwrap();
+        for i in 0..100 {
+            let a = tensor1(&[i as f32, 2.0 * i as f32]);
+            state.run_into(tvec!(a, tensor1(&[1.0f32, 2.0])), &mut outputs).unwrap();
+            assert_eq!(outputs[0].as_ptr::<f32>().unwrap(), buffer);
+            assert_eq!(outputs[0], tensor1(&[i as f32 - 1.0, 2.0 * i as f32 - 2.0]));
+        }
+    }
+
+    #[test]
+    fn run_into_mismatched_buffer() {
+        let model = sub_model();
+        let plan = SimplePlan::new(&model).unwrap();
+        let mut state = SimpleState::new(&plan).unwrap();
+        let inputs = || tvec!(tensor1(&[5.0f32, 7.0]), tensor1(&[1.0f32, 2.0]));
+        let mut outputs = [tensor1(&[9.0f32, 9.0, 9.0])];
+        assert!(state.run_into(inputs(), &mut outputs).is_err());
+        assert_eq!(outputs[0], tensor1(&[9.0f32, 9.0, 9.0]));
+        let mut outputs = [tensor1(&[9i32, 9])];
+        assert!(state.run_into(inputs(), &mut outputs).is_err());
+        assert_eq!(outputs[0], tensor1(&[9i32, 9]));
+        assert!(state.run_into(inputs(), &mut []).is_err());
+    }
+
+    /// Produces a large tensor, recording where its data lives.
+    /// Claims to keep its input shape, but drops the last item.
+    #[derive(Debug, Clone)]
+    struct DropsLast;
+
+    impl Op for DropsLast {
+        fn name(&self) -> Cow<str> {
+            "DropsLast".into()
+        }
+    }
+
+    impl StatelessOp for DropsLast {
+        fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+            let input = args_1!(inputs);
+            let input = input.as_slice::<f32>()?;
+            Ok(tvec!(tensor1(&input[..input.len() - 1]).into_arc_tensor()))
+        }
+    }
+
+    impl InferenceRulesOp for DropsLast {
+        fn rules<'r, 'p: 'r, 's: 'r>(
+            &'s self,
+            s: &mut Solver<'r>,
+            inputs: &'p [TensorProxy],
+            outputs: &'p [TensorProxy],
+        ) -> InferenceResult {
+            s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+            s.equals(&inputs[0].shape, &outputs[0].shape)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn wrong_output_shape_fails_fast() {
+        let mut model = InferenceModel::default();
+        let x_ = x_source(&mut model);
+        let wire = relu(&mut model, "before", x_);
+        let buggy = model.add_node_default("buggy", DropsLast).unwrap();
+        model.add_edge(wire, InletId::new(buggy, 0)).unwrap();
+        let wire = relu(&mut model, "after", OutletId::new(buggy, 0));
+        model.set_output_outlets(&[wire]).unwrap();
+        let model = model.into_typed().unwrap();
+
+        let plan = SimplePlan::new(&model).unwrap().with_fact_checks(true);
+        let err = plan.run(tvec!(x())).unwrap_err().to_string();
+        assert!(err.contains("buggy") && err.contains("output"), "{}", err);
+        let plan = SimplePlan::new(&model).unwrap().with_fact_checks(false);
+        assert_eq!(plan.run(tvec!(x())).unwrap()[0].shape(), &[3]);
+    }
+
+    /// x: [4] f32, concatenated 16 times into 256 bytes values.
+    fn spread(model: &mut InferenceModel, name: &str, x: OutletId) -> OutletId {
+        let concat = model.add_node_default(name, crate::ops::array::Concat::new(0)).unwrap();
+        for i in 0..16 {
+            model.add_edge(x, InletId::new(concat, i)).unwrap();
+        }
+        OutletId::new(concat, 0)
+    }
+
+    fn relu(model: &mut InferenceModel, name: &str, input: OutletId) -> OutletId {
+        let relu = model.add_node_default(name, crate::ops::nn::Relu::default()).unwrap();
+        model.add_edge(input, InletId::new(relu, 0)).unwrap();
+        OutletId::new(relu, 0)
+    }
+
+    fn add(model: &mut InferenceModel, name: &str, a: OutletId, b: OutletId) -> OutletId {
+        let add = model.add_node_default(name, crate::ops::math::Add::default()).unwrap();
+        model.add_edge(a, InletId::new(add, 0)).unwrap();
+        model.add_edge(b, InletId::new(add, 1)).unwrap();
+        OutletId::new(add, 0)
+    }
+
+    fn x_source(model: &mut InferenceModel) -> OutletId {
+        let fact = TensorFact::dt_shape(f32::datum_type(), tvec!(4usize));
+        OutletId::new(model.add_source("x", fact).unwrap(), 0)
+    }
+
+    fn x() -> Tensor {
+        tensor1(&[1.0f32, -2.0, 3.0, -4.0])
+    }
+
+    #[test]
+    fn linear_model_under_budget() {
+        let mut model = InferenceModel::default();
+        let x_ = x_source(&mut model);
+        let mut wire = spread(&mut model, "spread", x_);
+        for i in 0..4 {
+            wire = relu(&mut model, &format!("relu-{}", i), wire);
+        }
+        model.set_output_outlets(&[wire]).unwrap();
+        let model = model.into_typed().unwrap();
+
+        let plan = SimplePlan::new(&model).unwrap().with_memory_budget(600);
+        let mut state = SimpleState::new(&plan).unwrap();
+        let output = state.run(tvec!(x())).unwrap();
+        assert!(state.peak_bytes <= 600, "peak at {} bytes", state.peak_bytes);
+        assert_eq!(SimplePlan::new(&model).unwrap().run(tvec!(x())).unwrap(), output);
+    }
+
+    #[test]
+    fn skip_connections_are_recomputed_under_budget() {
+        let mut model = InferenceModel::default();
+        let x_ = x_source(&mut model);
+        let p = spread(&mut model, "p", x_);
+        let a = spread(&mut model, "a", x_);
+        let b = relu(&mut model, "b", a);
+        let c = relu(&mut model, "c", b);
+        let s1 = add(&mut model, "s1", c, a);
+        let s2 = add(&mut model, "s2", p, s1);
+        model.set_output_outlets(&[s2]).unwrap();
+        let model = model.into_typed().unwrap();
+
+        let plan = SimplePlan::new(&model).unwrap();
+        let mut state = SimpleState::new(&plan).unwrap();
+        let expected = state.run(tvec!(x())).unwrap();
+        assert!(state.peak_bytes > 1000, "peak at {} bytes", state.peak_bytes);
+
+        let plan = SimplePlan::new(&model).unwrap().with_memory_budget(800);
+        let mut state = SimpleState::new(&plan).unwrap();
+        let output = state.run(tvec!(x())).unwrap();
+        assert!(state.peak_bytes <= 800, "peak at {} bytes", state.peak_bytes);
+        assert_eq!(output, expected);
+    }
+
+    fn conv_model(input: &Tensor, kernel: Tensor) -> (TypedModel, ConvUnary) {
+        let facts = conv_facts(input.clone(), kernel);
+        let conv = Conv::default().to_unary(&facts).unwrap().unwrap();
+        let fact = |shape: &[TDim]| TypedTensorInfo {
+            datum_type: f32::datum_type(),
+            shape: shape.iter().cloned().collect(),
+            konst: None,
+        };
+        let mut model = TypedModel::default();
+        model.add_source("input", fact(&*conv.full_input_shape)).unwrap();
+        model.chain("conv", conv.clone(), tvec!(fact(&*conv.output_shape()))).unwrap();
+        (model, conv)
+    }
+
+    #[test]
+    fn warmup_packs_kernels_before_the_first_run() {
+        let input = rctensor4(&[[[[0.0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]]]);
+        let (model, conv) = conv_model(&input, tensor4(&[[[[1.0f32]]], [[[-2.0f32]]]]));
+        let plan = SimplePlan::new(&model).unwrap();
+        // the op in the model shares its kernel cache with conv
+        assert!(!conv.kernel_cache.is_packed());
+        plan.warmup().unwrap();
+        assert!(conv.kernel_cache.is_packed());
+        let expected = conv.eval(tvec!(input.clone())).unwrap();
+        assert_eq!(plan.run(tvec!(input.into_tensor())).unwrap(), expected);
+    }
+
+    #[test]
+    fn scratch_arena_is_allocated_once() {
+        let input = ndarray::Array4::from_shape_fn((1, 3, 6, 6), |(_, c, y, x)| {
+            ((c * 36 + y * 6 + x) % 7) as f32 - 3.0
+        });
+        let kernel = ndarray::Array4::from_shape_fn((4, 3, 3, 3), |(o, c, y, x)| {
+            ((o * 27 + c * 9 + y * 3 + x) % 5) as f32 / 2.0
+        });
+        let input = input.into_tensor();
+        let (model, conv) = conv_model(&input, kernel.into_tensor());
+        let expected = SimplePlan::new(&model).unwrap().run(tvec!(input.clone())).unwrap();
+        let plan = SimplePlan::new(&model).unwrap();
+        assert!(SimpleState::new(&plan).unwrap().scratch_arena().is_none());
+        let plan = plan.with_scratch_arena().unwrap();
+        let scratch = conv.scratch_layout(input.shape()).unwrap().size();
+        assert_eq!(plan.scratch_bytes.iter().max(), Some(&scratch));
+        let mut state = SimpleState::new(&plan).unwrap();
+        for _ in 0..3 {
+            assert_eq!(state.run(tvec!(input.clone())).unwrap(), expected);
+            let arena = state.scratch_arena().unwrap();
+            // the arena never grows, and served the conv scratch
+            assert_eq!(arena.capacity(), scratch);
+            assert!(arena.used() > 0);
+        }
+    }
+
+    #[test]
+    fn warmup_needs_concrete_input_facts() {
+        let model = sub_model();
+        let plan = SimplePlan::new(&model).unwrap();
+        let err = plan.warmup().unwrap_err();
+        assert!(err.to_string().contains("Can not warm up for input a"), "{}", err);
+        plan.warmup_with(tvec!(tensor1(&[0.0f32; 3]), tensor1(&[0.0f32; 3]))).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Feed a temporal convolution one video frame at a time.
+//!
+//! A conv over (N, C, T, H, W) clips only reads the last frames of the
+//! clip to produce its last output: `FrameStream` keeps these in a ring
+//! buffer, the delay line a pulsed conv would keep, and runs the conv on
+//! them as each (N, C, H, W) frame comes in.
+
+use crate::internal::*;
+use crate::ops::cnn::{ConvUnary, KernelFormat};
+use crate::ops::nn::DataFormat;
+use ndarray::*;
+
+fn window_t<T: Datum + Copy>(frames: &[Arc<Tensor>], oldest: usize) -> TractResult<Tensor> {
+    let views = frames[oldest..]
+        .iter()
+        .chain(frames[..oldest].iter())
+        .map(|t| Ok(t.to_array_view::<T>()?.insert_axis(Axis(2))))
+        .collect::<TractResult<Vec<_>>>()?;
+    Ok(ndarray::stack(Axis(2), &*views)?.into())
+}
+
+/// Runs a temporal conv on a stream of frames, one output frame per input
+/// frame once enough of them have been seen.
+///
+/// The conv reads NCHW clips with three spatial axes, time first. It must
+/// not pad nor stride over time: the output for a frame then only depends
+/// on the `window()` frames ending with it.
+#[derive(Debug, Clone)]
+pub struct FrameStream {
+    conv: ConvUnary,
+    window: usize,
+    frames: Vec<Arc<Tensor>>,
+    /// Slot of the oldest frame, once the ring is full.
+    oldest: usize,
+}
+
+impl FrameStream {
+    pub fn new(conv: ConvUnary) -> TractResult<FrameStream> {
+        if conv.data_format != DataFormat::NCHW || conv.full_input_shape.len() != 5 {
+            bail!(
+                "Frame streams need a NCHW conv over (N, C, T, H, W) clips, got {:?} of rank {}",
+                conv.data_format,
+                conv.full_input_shape.len()
+            );
+        }
+        if conv.summary.is_some() || conv.token_output {
+            bail!("Frame streams support neither channel summaries nor tokens");
+        }
+        if !conv.independent_shape.is_empty() {
+            bail!("Frame streams need a conv without independent axes");
+        }
+        if conv.golden.is_some() {
+            bail!("Frame streams can not check a golden output of the whole clip");
+        }
+        if conv.strides[0] != 1 || !conv.padding.valid_dim(0) {
+            bail!("Frame streams need a conv neither striding nor padding over time");
+        }
+        let kernel_len = match conv.kernel_fmt {
+            KernelFormat::OIHW => conv.kernel.shape()[2],
+            KernelFormat::HWIO => conv.kernel.shape()[0],
+        };
+        let window = (kernel_len - 1) * conv.dilations[0] + 1;
+        let mut conv = conv;
+        conv.full_input_shape[2] = window.to_dim();
+        conv.full_output_shape[2] = 1.to_dim();
+        Ok(FrameStream { conv, window, frames: Vec::with_capacity(window), oldest: 0 })
+    }
+
+    /// Frames the conv reads for each output: its temporal kernel length,
+    /// dilated.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Frames pushed before the first output comes out.
+    pub fn warmup(&self) -> usize {
+        self.window - 1
+    }
+
+    /// Forget the frames seen so far, to start a new stream.
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.oldest = 0;
+    }
+
+    /// Push a (N, C, H, W) frame. Returns the (N, C, H, W) output frame
+    /// ending with it, or `None` during the warmup.
+    pub fn push(&mut self, frame: Arc<Tensor>) -> TractResult<Option<Arc<Tensor>>> {
+        let expected = self.frames.first().map(|f| f.shape()).unwrap_or(frame.shape());
+        if frame.shape().len() != 4 || frame.shape() != expected {
+            bail!("Frame of shape {:?}, expected a (N, C, H, W) {:?}", frame.shape(), expected);
+        }
+        if self.frames.len() < self.window {
+            self.frames.push(frame);
+            if self.frames.len() < self.window {
+                return Ok(None);
+            }
+        } else {
+            self.frames[self.oldest] = frame;
+            self.oldest = (self.oldest + 1) % self.window;
+        }
+        let dt = self.frames[0].datum_type();
+        let window = dispatch_copy!(self::window_t(dt)(&self.frames, self.oldest))?;
+        let output = self.conv.eval(tvec!(window.into_arc_tensor()))?.remove(0).into_tensor();
+        let mut shape = output.shape().to_vec();
+        shape.remove(2);
+        Ok(Some(unsafe { output.into_shape(&shape)? }.into_arc_tensor()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ops::cnn::conv::conv_facts;
+    use crate::ops::cnn::{Conv, PaddingSpec};
+
+    fn check(frames: usize, kernel_len: usize, dilation: usize) {
+        let clip = Array5::from_shape_fn((1, 2, frames, 5, 4), |(_, c, t, y, x)| {
+            ((c * 31 + t * 7 + y * 4 + x) % 11) as f32 - 5.0
+        })
+        .into_arc_tensor();
+        let kernel = Array5::from_shape_fn((3, 2, kernel_len, 3, 3), |(o, c, t, y, x)| {
+            ((o * 13 + c * 7 + t * 5 + y * 3 + x) % 7) as f32 / 4.0 - 0.75
+        });
+        let padding = PaddingSpec::Explicit(tvec!(0, 1, 1), tvec!(0, 1, 1));
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            Some(tvec!(dilation, 1, 1)),
+            None,
+            padding,
+            None,
+            1,
+        );
+        let facts = conv_facts(clip.clone(), kernel);
+        let mut op = conv.to_unary(&facts).unwrap().unwrap();
+        op.bias = Some(tensor1(&[0.5f32, -1.0, 2.0]));
+        let expected = op.eval(tvec!(clip.clone())).unwrap().remove(0);
+        let expected = expected.to_array_view::<f32>().unwrap();
+
+        let mut stream = FrameStream::new(op).unwrap();
+        assert_eq!(stream.window(), (kernel_len - 1) * dilation + 1);
+        for pass in 0..2 {
+            for t in 0..frames {
+                let frame = clip.to_array_view::<f32>().unwrap().index_axis(Axis(2), t).to_owned();
+                let found = stream.push(frame.into_arc_tensor()).unwrap();
+                if t < stream.warmup() {
+                    assert!(found.is_none(), "pass {} frame {}", pass, t);
+                } else {
+                    let reference =
+                        expected.index_axis(Axis(2), t - stream.warmup()).to_owned().into_tensor();
+                    let found = found.unwrap();
+                    assert!(found.close_enough(&reference, true), "pass {} frame {}", pass, t);
+                }
+            }
+            stream.reset();
+        }
+    }
+
+    #[test]
+    fn streamed_frames_match_clip() {
+        check(7, 3, 1);
+    }
+
+    #[test]
+    fn dilated_window() {
+        check(9, 2, 3);
+    }
+
+    #[test]
+    fn rejects_temporal_padding() {
+        let clip = Tensor::from(ArrayD::<f32>::zeros(&[1, 1, 4, 3, 3][..]));
+        let kernel = ArrayD::<f32>::zeros(&[1, 1, 3, 1, 1][..]);
+        let facts = conv_facts(clip, kernel);
+        let conv = Conv::new(
+            DataFormat::NCHW,
+            KernelFormat::OIHW,
+            None,
+            None,
+            PaddingSpec::SameUpper,
+            None,
+            1,
+        );
+        let op = conv.to_unary(&facts).unwrap().unwrap();
+        assert!(FrameStream::new(op).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use std::fmt;
 use std::convert::TryFrom;
 
 pub mod delay;
+pub mod frames;
 
 #[derive(Clone, PartialEq)]
 pub struct PulsedTensorFact {
@@ -116,6 +117,7 @@ impl PulsedModel {
         // maintaining order of i/o interface
         target.inputs = source.input_outlets()?.iter().map(|i| mapping[&i]).collect();
         target.outputs = source.output_outlets()?.iter().map(|o| mapping[&o]).collect();
+        target.output_labels = source.output_labels.clone();
         Ok((target, mapping))
     }
 
@@ -275,6 +277,33 @@ mod tests {
             proptest_regular_against_pulse(model, pulse as _, input.into_dyn(), 0)?;
         }
 
+        #[test]
+        fn proptest_reflect_pad(pulse in 1i32..4, input_len in 0i32..10, begin in 0i32..3, end in 0i32..3) {
+            use crate::ops::array::{ Pad, PadMode };
+            let input_len = input_len + begin.max(end) + 1;
+            let mut model = Model::default();
+            let _ = model
+                .add_source("a", TensorFact::dt_shape(f32::datum_type(), shapefact!(S)))
+                .unwrap();
+            model.chain_default("pad", Pad::new(vec![(begin as _, end as _)], PadMode::Reflect)).unwrap();
+
+            let input = Array1::range(1.0f32, input_len as f32 + 1.0, 1.0);
+            proptest_regular_against_pulse(model, pulse as _, input.into_dyn(), 0)?;
+        }
+
+        #[test]
+        fn proptest_edge_pad(pulse in 1i32..4, input_len in 1i32..10, begin in 0i32..3, end in 0i32..3) {
+            use crate::ops::array::{ Pad, PadMode };
+            let mut model = Model::default();
+            let _ = model
+                .add_source("a", TensorFact::dt_shape(f32::datum_type(), shapefact!(S)))
+                .unwrap();
+            model.chain_default("pad", Pad::new(vec![(begin as _, end as _)], PadMode::Edge)).unwrap();
+
+            let input = Array1::range(1.0f32, input_len as f32 + 1.0, 1.0);
+            proptest_regular_against_pulse(model, pulse as _, input.into_dyn(), 0)?;
+        }
+
     }
 
     #[test]
@@ -294,6 +323,38 @@ mod tests {
         proptest_regular_against_pulse(model, 4, input.into_dyn(), 2).unwrap();
     }
 
+    fn mirror_padded_conv(mode: crate::ops::array::PadMode) -> InferenceModel {
+        use crate::ops::array::Pad;
+        use crate::ops::cnn::*;
+
+        let mut model = Model::default();
+        let ker = model.add_const("kernel", tensor3(&[[[0.5f32, 1.0, -0.1]]])).unwrap();
+        let _ = model
+            .add_source("a", TensorFact::dt_shape(f32::datum_type(), shapefact!(1, 1, S))) // NCT
+            .unwrap();
+        model.chain_default("pad", Pad::new(vec![(0, 0), (0, 0), (2, 1)], mode)).unwrap();
+        let conv = model.chain_default("conv", Conv::default()).unwrap();
+        model.add_edge(OutletId::new(ker, 0), InletId::new(conv, 1)).unwrap();
+        model
+    }
+
+    #[test]
+    fn test_reflect_padded_conv() {
+        let model = mirror_padded_conv(crate::ops::array::PadMode::Reflect);
+        let input = arr3(&[[[1.0f32, 3.0, 0.0, 0.0, -1.0, 2.0, 0.0, 5.0]]]);
+        proptest_regular_against_pulse(model, 4, input.into_dyn(), 2).unwrap();
+    }
+
+    #[test]
+    fn test_edge_padded_conv() {
+        // the edge pad is absorbed by the conv, which pads the stream ahead
+        let model = mirror_padded_conv(crate::ops::array::PadMode::Edge);
+        let normalized = model.clone().into_normalized().unwrap();
+        assert!(normalized.nodes().iter().all(|n| n.op().name() != "Pad"));
+        let input = arr3(&[[[1.0f32, 3.0, 0.0, 0.0, -1.0, 2.0, 0.0, 5.0]]]);
+        proptest_regular_against_pulse(model, 3, input.into_dyn(), 2).unwrap();
+    }
+
     #[test]
     fn test_pad_after_1() {
         use crate::ops::array::{Pad, PadMode};
@@ -329,5 +390,4 @@ mod tests {
         let input = arr1(&[1.0, 2.0]);
         proptest_regular_against_pulse(model, 2, input.into_dyn(), 0).unwrap();
     }
-
 }
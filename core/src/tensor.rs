@@ -14,6 +14,42 @@ use std::sync::Arc;
 
 pub mod litteral;
 
+/// Items shown at each end of an axis by the summarized `Display` preview.
+const PREVIEW_EDGE_ITEMS: usize = 3;
+
+/// Tensors of more items than this are summarized by `Display`.
+const PREVIEW_THRESHOLD: usize = 1000;
+
+/// Append the values of `view` to `s`, nested as its axes. If `summarize`,
+/// long axes are cut to their first and last few items.
+fn preview_axes<D: Datum>(view: ArrayViewD<D>, summarize: bool, s: &mut String) {
+    if view.ndim() == 0 {
+        s.push_str(&view.iter().next().unwrap().to_string());
+        return;
+    }
+    let len = view.shape()[0];
+    let shown: Vec<Option<usize>> = if summarize && len > 2 * PREVIEW_EDGE_ITEMS {
+        (0..PREVIEW_EDGE_ITEMS)
+            .map(Some)
+            .chain(Some(None))
+            .chain((len - PREVIEW_EDGE_ITEMS..len).map(Some))
+            .collect()
+    } else {
+        (0..len).map(Some).collect()
+    };
+    s.push('[');
+    for (i, ix) in shown.into_iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        match ix {
+            Some(ix) => preview_axes(view.index_axis(Axis(0), ix), summarize, s),
+            None => s.push_str("..."),
+        }
+    }
+    s.push(']');
+}
+
 /// Tensor is a concrete tensor in tract.
 pub struct Tensor {
     null: bool,
@@ -141,6 +177,21 @@ impl Tensor {
         dispatch_datum!(Self::dump_t(self.dt)(self, force_full))
     }
 
+    fn preview_t<D: Datum>(&self) -> TractResult<String> {
+        let data = self.to_array_view::<D>()?;
+        let summarize = data.len() > PREVIEW_THRESHOLD;
+        let mut s = String::new();
+        preview_axes(data, summarize, &mut s);
+        Ok(s)
+    }
+
+    /// The values of the tensor nested as its axes, like NumPy prints them:
+    /// in full up to a thousand items, else the first and last three along
+    /// each axis.
+    pub fn preview(&self) -> TractResult<String> {
+        dispatch_datum!(Self::preview_t(self.dt)(self))
+    }
+
     /// Compare two tensors, allowing for rounding errors.
     pub fn close_enough(&self, other: &Self, approx: bool) -> bool {
         if self.is_null() != other.is_null() {
@@ -341,11 +392,23 @@ impl Tensor {
             (I32, I64) => self.cast::<i32, i64>()?,
             (I64, I32) => self.cast::<i64, i32>()?,
 
+            (Bool, U8) => self.cast::<bool, u8>()?,
+            (U8, Bool) => self.cast::<u8, bool>()?,
+
             (Bool, F32) => self.cast::<bool, f32>()?,
             (I8, F32) => self.cast::<i8, f32>()?,
             (I16, F32) => self.cast::<i16, f32>()?,
             (I32, F32) => self.cast::<i32, f32>()?,
             (I64, F32) => self.cast::<i64, f32>()?,
+            (U8, F32) => self.cast::<u8, f32>()?,
+            (U16, F32) => self.cast::<u16, f32>()?,
+
+            (U8, F64) => self.cast::<u8, f64>()?,
+            (U16, F64) => self.cast::<u16, f64>()?,
+            (I8, F64) => self.cast::<i8, f64>()?,
+            (I16, F64) => self.cast::<i16, f64>()?,
+            (I32, F64) => self.cast::<i32, f64>()?,
+            (I64, F64) => self.cast::<i64, f64>()?,
 
             (F32, String) => self.cast::<f32, std::string::String>()?,
             (String, F32) => self.cast::<std::string::String, f32>()?,
@@ -382,6 +445,19 @@ impl fmt::Debug for Tensor {
     }
 }
 
+/// Datum type, shape and a preview of the values, see `Tensor::preview`.
+impl fmt::Display for Tensor {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let shape = if self.shape.is_empty() {
+            "scalar".to_string()
+        } else {
+            self.shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("x")
+        };
+        let preview = self.preview().unwrap_or_else(|e| format!("Error : {:?}", e));
+        write!(formatter, "{:?} {} {}", self.dt, shape, preview)
+    }
+}
+
 #[cfg(feature = "serialize")]
 impl Serialize for Tensor {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -427,6 +503,11 @@ fn vec_to_u8<T: Datum>(mut data: Vec<T>) -> Vec<u8> {
 }
 
 unsafe fn vec_to_datum<T: Datum>(mut data: Vec<u8>) -> Vec<T> {
+    // a cloned empty buffer is not allocated, its dangling pointer is only
+    // aligned for u8
+    if data.capacity() == 0 {
+        return vec![];
+    }
     assert!(data.as_ptr() as usize % T::datum_type().alignment() == 0);
     let v = Vec::from_raw_parts(
         data.as_mut_ptr() as *mut T,
@@ -501,3 +582,34 @@ impl IntoArcTensor for Arc<Tensor> {
         self
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_small() {
+        let t = Tensor::from(arr2(&[[1i32, 2, 3], [4, 5, 6]]));
+        assert_eq!(t.to_string(), "I32 2x3 [[1, 2, 3], [4, 5, 6]]");
+        assert_eq!(Tensor::from(arr0(2.5f32)).to_string(), "F32 scalar 2.5");
+        assert_eq!(Arc::new(t).to_string(), "I32 2x3 [[1, 2, 3], [4, 5, 6]]");
+    }
+
+    #[test]
+    fn display_empty() {
+        let t = Tensor::from(Array2::<f32>::zeros((0, 3)));
+        assert_eq!(t.to_string(), "F32 0x3 []");
+        let t = Tensor::from(Array2::<f32>::zeros((2, 0)));
+        assert_eq!(t.to_string(), "F32 2x0 [[], []]");
+    }
+
+    #[test]
+    fn display_large_is_summarized() {
+        let t = Tensor::from(Array::from_shape_fn((100, 200, 300), |(i, j, k)| (i + j + k) as i32));
+        let s = t.to_string();
+        assert!(s.starts_with("I32 100x200x300 [[[0, 1, 2, ..., 297, 298, 299], "), "{}", &s[..80]);
+        assert!(s.ends_with("[298, 299, 300, ..., 595, 596, 597]]]"), "{}", s);
+        // 7 x 7 rows of 7 items out of six millions
+        assert!(s.len() < 4000, "{}", s.len());
+    }
+}
@@ -1,6 +1,6 @@
 use std::{env, fs};
-mod armv7neon;
-mod armvfpv2;
+pub(crate) mod armv7neon;
+pub(crate) mod armvfpv2;
 
 use crate::frame::PackedConv;
 use crate::frame::PackedMatMul;
@@ -13,7 +13,7 @@ fn has_neon_cpuinfo() -> std::io::Result<bool> {
     Ok(neon)
 }
 
-fn has_neon() -> bool {
+pub(crate) fn has_neon() -> bool {
     if let Ok(v) = env::var("TRACT_CPU_ARM32_NEON") {
         return v == "true";
     }
@@ -1,4 +1,4 @@
-mod arm64simd;
+pub(crate) mod arm64simd;
 
 use crate::frame::PackedConv;
 use crate::frame::PackedMatMul;
@@ -1,6 +1,6 @@
 use num_traits::Zero;
 use std::fmt::Debug;
-use std::ops::{Add, Mul};
+use std::ops::{Add, Mul, Range};
 
 use std::marker::PhantomData;
 
@@ -11,8 +11,64 @@ pub trait MatMul<T: Copy + Add + Mul + Zero + Debug>: Send + Sync + Debug + obje
     fn packed_a_alignment(&self) -> usize;
     fn pack_a(&self, pa: *mut T, a: *const T, rsa: isize, csa: isize);
     fn b_pack(&self) -> PackB<T>;
+    /// Packer laying out A from its transpose, the way `pack_a` does from A.
+    fn a_pack(&self) -> PackB<T>;
+
+    /// Would the kernel waste less work computing C^T = B^T.A^T ?
+    ///
+    /// The transposed product is meant to run on a multiplier built for
+    /// (n, k, m), with C strides swapped.
+    fn prefer_transposed(&self) -> bool;
 
     fn mat_mul_prepacked(&self, pa: *const T, pb: *const T, c: *mut T, rsc: isize, csc: isize);
+    /// Same as `mat_mul_prepacked`, but adds the product to the values
+    /// already in C instead of overwriting them.
+    fn mat_mul_prepacked_acc(&self, pa: *const T, pb: *const T, c: *mut T, rsc: isize, csc: isize);
+
+    /// Length of the scratch tile the product goes through for partial
+    /// tiles, and for all of them when accumulating.
+    fn c_panel_len(&self) -> usize;
+    /// `mat_mul_prepacked` (or its accumulating version) using `c_panel`, of
+    /// `c_panel_len()` items, instead of allocating its scratch tile.
+    fn mat_mul_prepacked_in(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        acc: bool,
+        c_panel: &mut [T],
+    );
+
+    /// Number of row and column panels the product is tiled in.
+    fn panels(&self) -> (usize, usize);
+    /// `mat_mul_prepacked_in` restricted to the tiles in row panels `rows`
+    /// and column panels `cols`, leaving the rest of C untouched.
+    ///
+    /// Disjoint ranges write disjoint parts of C, so they can be computed
+    /// concurrently, each with its own `c_panel`.
+    fn mat_mul_prepacked_tiles(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        acc: bool,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        c_panel: &mut [T],
+    );
+    /// `mat_mul_prepacked_in`, writing the `m` rows of C at the `rows`
+    /// pointers, each of them to `n` contiguous items.
+    fn mat_mul_prepacked_rows(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        rows: &[*mut T],
+        c_panel: &mut [T],
+    );
 
     fn m(&self) -> usize;
     fn n(&self) -> usize;
@@ -87,6 +143,52 @@ where
             }
         }
     }
+
+    /// Kernels overwrite their tile: partial tiles, and all of them when
+    /// accumulating, go through the `tmpc` scratch tile.
+    fn run(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        acc: bool,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        tmpc: &mut [T],
+    ) {
+        assert!(pa as usize % K::alignment_bytes_a() == 0);
+        assert!(pb as usize % K::alignment_bytes_b() == 0);
+        let mr = K::mr();
+        let nr = K::nr();
+        assert!(tmpc.len() >= mr * nr);
+        let m = self.m;
+        let k = self.k;
+        let n = self.n;
+        unsafe {
+            for ia in rows {
+                let rows = (m - ia * mr).min(mr);
+                for ib in cols.clone() {
+                    let cols = (n - ib * nr).min(nr);
+                    let pa = pa.offset((ia * k * mr) as isize);
+                    let pb = pb.offset((ib * k * nr) as isize);
+                    let c = c.offset((mr * ia) as isize * rsc + (nr * ib) as isize * csc);
+                    if !acc && rows == mr && cols == nr {
+                        K::kernel(k, pa, pb, c, rsc as usize, csc as usize);
+                        continue;
+                    }
+                    K::kernel(k, pa, pb, tmpc.as_mut_ptr(), nr, 1);
+                    for y in 0..rows {
+                        for x in 0..cols {
+                            let c = c.offset(y as isize * rsc + x as isize * csc);
+                            *c = if acc { *c + tmpc[y * nr + x] } else { tmpc[y * nr + x] };
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl<K, T> MatMul<T> for PackedMatMul<K, T>
@@ -132,77 +234,104 @@ where
         PackB::new(self.k, self.n, K::nr(), K::alignment_bytes_b())
     }
 
+    fn a_pack(&self) -> PackB<T> {
+        PackB::new(self.k, self.m, K::mr(), K::alignment_bytes_a())
+    }
+
+    fn prefer_transposed(&self) -> bool {
+        let padded = |x: usize, r: usize| (x + r - 1) / r * r;
+        let (mr, nr) = (K::mr(), K::nr());
+        padded(self.n, mr) * padded(self.m, nr) < padded(self.m, mr) * padded(self.n, nr)
+    }
+
     fn mat_mul_prepacked(&self, pa: *const T, pb: *const T, c: *mut T, rsc: isize, csc: isize) {
+        self.mat_mul_prepacked_in(
+            pa,
+            pb,
+            c,
+            rsc,
+            csc,
+            false,
+            &mut vec![T::zero(); self.c_panel_len()],
+        )
+    }
+
+    fn mat_mul_prepacked_acc(&self, pa: *const T, pb: *const T, c: *mut T, rsc: isize, csc: isize) {
+        self.mat_mul_prepacked_in(
+            pa,
+            pb,
+            c,
+            rsc,
+            csc,
+            true,
+            &mut vec![T::zero(); self.c_panel_len()],
+        )
+    }
+
+    fn c_panel_len(&self) -> usize {
+        K::mr() * K::nr()
+    }
+
+    fn mat_mul_prepacked_in(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        acc: bool,
+        c_panel: &mut [T],
+    ) {
+        let (rows, cols) = self.panels();
+        self.run(pa, pb, c, rsc, csc, acc, 0..rows, 0..cols, c_panel)
+    }
+
+    fn panels(&self) -> (usize, usize) {
+        ((self.m + K::mr() - 1) / K::mr(), (self.n + K::nr() - 1) / K::nr())
+    }
+
+    fn mat_mul_prepacked_tiles(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        c: *mut T,
+        rsc: isize,
+        csc: isize,
+        acc: bool,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        c_panel: &mut [T],
+    ) {
+        self.run(pa, pb, c, rsc, csc, acc, rows, cols, c_panel)
+    }
+
+    fn mat_mul_prepacked_rows(
+        &self,
+        pa: *const T,
+        pb: *const T,
+        rows: &[*mut T],
+        c_panel: &mut [T],
+    ) {
         assert!(pa as usize % K::alignment_bytes_a() == 0);
         assert!(pb as usize % K::alignment_bytes_b() == 0);
+        assert_eq!(rows.len(), self.m);
         let mr = K::mr();
         let nr = K::nr();
-        let m = self.m;
-        let k = self.k;
-        let n = self.n;
-        let mut tmpc = vec![T::zero(); mr * nr];
+        assert!(c_panel.len() >= mr * nr);
+        let (m, k, n) = (self.m, self.k, self.n);
+        let (row_panels, col_panels) = self.panels();
         unsafe {
-            for ia in 0..m / mr {
-                for ib in 0..n / nr {
-                    K::kernel(
-                        k,
-                        pa.offset((ia * k * mr) as isize),
-                        pb.offset((ib * k * nr) as isize),
-                        c.offset((mr * ia) as isize * rsc + (nr * ib) as isize * csc),
-                        rsc as usize,
-                        csc as usize,
-                    );
-                }
-                if n % nr != 0 {
-                    K::kernel(
-                        k,
-                        pa.offset((ia * k * mr) as isize),
-                        pb.offset((n / nr * k * nr) as isize),
-                        tmpc.as_mut_ptr(),
-                        nr,
-                        1,
-                    );
-                    for y in 0..mr {
-                        for x in 0..(n % nr) {
-                            *c.offset(
-                                (mr * ia + y) as isize * rsc + (x + n / nr * nr) as isize * csc,
-                            ) = tmpc[y * nr + x];
-                        }
-                    }
-                }
-            }
-            if m % mr != 0 {
-                for ib in 0..n / nr {
-                    K::kernel(
-                        k,
-                        pa.offset((m / mr * mr * k) as isize),
-                        pb.offset((ib * nr * k) as isize),
-                        tmpc.as_mut_ptr(),
-                        nr,
-                        1,
-                    );
-                    for y in 0..(m % mr) {
-                        for x in 0..nr {
-                            *c.offset(
-                                (y + m / mr * mr) as isize * rsc + (x + ib * nr) as isize * csc,
-                            ) = tmpc[y * nr + x];
-                        }
-                    }
-                }
-                if n % nr != 0 {
-                    K::kernel(
-                        k,
-                        pa.offset((m / mr * mr * k) as isize),
-                        pb.offset((n / nr * nr * k) as isize),
-                        tmpc.as_mut_ptr(),
-                        nr,
-                        1,
-                    );
-                    for y in 0..(m % mr) {
-                        for x in 0..(n % nr) {
-                            *c.offset(
-                                (y + m / mr * mr) as isize * rsc + (x + n / nr * nr) as isize * csc,
-                            ) = tmpc[y * nr + x];
+            for ia in 0..row_panels {
+                let height = (m - ia * mr).min(mr);
+                for ib in 0..col_panels {
+                    let width = (n - ib * nr).min(nr);
+                    let pa = pa.offset((ia * k * mr) as isize);
+                    let pb = pb.offset((ib * k * nr) as isize);
+                    K::kernel(k, pa, pb, c_panel.as_mut_ptr(), nr, 1);
+                    for y in 0..height {
+                        let row = rows[ia * mr + y].offset((ib * nr) as isize);
+                        for x in 0..width {
+                            *row.offset(x as isize) = c_panel[y * nr + x];
                         }
                     }
                 }
@@ -385,4 +514,167 @@ pub mod test {
         Ok(())
     }
 
+    /// Computes C += A.B, C starting with non-zero values.
+    pub fn test_mat_mul_acc_f32<MM: MatMul<f32>>(
+        mm: MM,
+        m: usize,
+        k: usize,
+        n: usize,
+        a: &[f32],
+        b: &[f32],
+    ) -> Result<(), proptest::test_runner::TestCaseError> {
+        unsafe {
+            let mut packed_a: Vec<f32> =
+                align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.pack_a(packed_a.as_mut_ptr(), a.as_ptr(), k as isize, 1);
+
+            let mut packed_b: Vec<f32> =
+                align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+            mm.b_pack().pack(packed_b.as_mut_ptr(), b.as_ptr(), n as isize, 1);
+
+            let mut found: Vec<f32> = (0..m * n).map(|i| (i % 7) as f32).collect();
+            let mut expect = found.clone();
+            mm.mat_mul_prepacked_acc(
+                packed_a.as_ptr(),
+                packed_b.as_ptr(),
+                found.as_mut_ptr(),
+                n as isize,
+                1,
+            );
+            for x in 0..n {
+                for y in 0..m {
+                    for i in 0..k {
+                        expect[x + y * n] += a[i + k * y] * b[x + i * n]
+                    }
+                }
+            }
+            prop_assert_eq!(found, expect);
+        }
+        Ok(())
+    }
+
+    /// Computes C = A.B as C^T = B^T.A^T, `mm` being built for (n, k, m).
+    pub fn test_mat_mul_transposed_f32<MM: MatMul<f32>>(
+        mm: MM,
+        m: usize,
+        k: usize,
+        n: usize,
+        a: &[f32],
+        b: &[f32],
+    ) -> Result<(), proptest::test_runner::TestCaseError> {
+        unsafe {
+            let mut packed_bt: Vec<f32> =
+                align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.a_pack().pack(packed_bt.as_mut_ptr(), b.as_ptr(), n as isize, 1);
+
+            let mut packed_at: Vec<f32> =
+                align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+            mm.b_pack().pack(packed_at.as_mut_ptr(), a.as_ptr(), 1, k as isize);
+
+            let mut found = vec![9999.0f32; m * n];
+            mm.mat_mul_prepacked(
+                packed_bt.as_ptr(),
+                packed_at.as_ptr(),
+                found.as_mut_ptr(),
+                1,
+                n as isize,
+            );
+            let mut expect = vec![0.0f32; m * n];
+            for x in 0..n {
+                for y in 0..m {
+                    for i in 0..k {
+                        expect[x + y * n] += a[i + k * y] * b[x + i * n]
+                    }
+                }
+            }
+            prop_assert_eq!(found, expect);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn a_pack_matches_pack_a() {
+        use crate::generic::SMatMul4x4;
+        let (m, k) = (7, 3);
+        let a: Vec<f32> = (0..m * k).map(|i| i as f32).collect();
+        let mm = PackedMatMul::<SMatMul4x4, f32>::new(m, k, 5);
+        unsafe {
+            let mut packed: Vec<f32> =
+                align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.pack_a(packed.as_mut_ptr(), a.as_ptr(), k as isize, 1);
+            let mut from_t: Vec<f32> =
+                align::uninitialized(mm.a_pack().len(), mm.a_pack().alignment());
+            mm.a_pack().pack(from_t.as_mut_ptr(), a.as_ptr(), 1, k as isize);
+            for p in 0..m / 4 * 4 * k {
+                assert_eq!(packed[p], from_t[p]);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_the_product() {
+        use crate::generic::SMatMul4x4;
+        let (m, k, n) = (9, 5, 14);
+        let a: Vec<f32> = (0..m * k).map(|i| (i % 5) as f32 - 2.0).collect();
+        let b: Vec<f32> = (0..k * n).map(|i| (i % 7) as f32 - 3.0).collect();
+        let mm = PackedMatMul::<SMatMul4x4, f32>::new(m, k, n);
+        assert_eq!(mm.panels(), (3, 4));
+        unsafe {
+            let mut pa: Vec<f32> = align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.pack_a(pa.as_mut_ptr(), a.as_ptr(), k as isize, 1);
+            let mut pb: Vec<f32> = align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+            mm.b_pack().pack(pb.as_mut_ptr(), b.as_ptr(), n as isize, 1);
+            let mut expected = vec![0.0f32; m * n];
+            mm.mat_mul_prepacked(pa.as_ptr(), pb.as_ptr(), expected.as_mut_ptr(), n as isize, 1);
+            let mut found = vec![9999.0f32; m * n];
+            let mut c_panel = vec![0.0f32; mm.c_panel_len()];
+            for (rows, cols) in &[(0..2, 0..1), (0..2, 1..4), (2..3, 0..4)] {
+                mm.mat_mul_prepacked_tiles(
+                    pa.as_ptr(),
+                    pb.as_ptr(),
+                    found.as_mut_ptr(),
+                    n as isize,
+                    1,
+                    false,
+                    rows.clone(),
+                    cols.clone(),
+                    &mut c_panel,
+                );
+            }
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn rows_land_at_their_pointers() {
+        use crate::generic::SMatMul4x4;
+        let (m, k, n) = (6, 3, 7);
+        let a: Vec<f32> = (0..m * k).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..k * n).map(|i| (i % 4) as f32).collect();
+        let mm = PackedMatMul::<SMatMul4x4, f32>::new(m, k, n);
+        unsafe {
+            let mut pa: Vec<f32> = align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.pack_a(pa.as_mut_ptr(), a.as_ptr(), k as isize, 1);
+            let mut pb: Vec<f32> = align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+            mm.b_pack().pack(pb.as_mut_ptr(), b.as_ptr(), n as isize, 1);
+            let mut expected = vec![0.0f32; m * n];
+            mm.mat_mul_prepacked(pa.as_ptr(), pb.as_ptr(), expected.as_mut_ptr(), n as isize, 1);
+            let mut planes = vec![vec![9999.0f32; n]; m];
+            let rows: Vec<*mut f32> = planes.iter_mut().map(|p| p.as_mut_ptr()).collect();
+            mm.mat_mul_prepacked_rows(pa.as_ptr(), pb.as_ptr(), &rows, &mut vec![0.0; 16]);
+            for (y, plane) in planes.iter().enumerate() {
+                assert_eq!(&**plane, &expected[y * n..][..n]);
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_m_prefers_transposed() {
+        use crate::generic::SMatMul4x4;
+        assert!(!PackedMatMul::<SMatMul4x4, f32>::new(8, 9, 64).prefer_transposed());
+        assert!(!PackedMatMul::<SMatMul4x4, f32>::new(2, 9, 64).prefer_transposed());
+        use crate::generic::DMatMul4x2;
+        assert!(PackedMatMul::<DMatMul4x2, f64>::new(2, 9, 64).prefer_transposed());
+        assert!(!PackedMatMul::<DMatMul4x2, f64>::new(8, 9, 64).prefer_transposed());
+    }
 }
@@ -20,6 +20,11 @@ impl<T: Copy + Add + Mul + Zero + Debug> PackB<T> {
         self.alignment
     }
 
+    /// Width of the panels the columns are packed by.
+    pub fn panel_width(&self) -> usize {
+        self.nr
+    }
+
     pub fn len(&self) -> usize {
         (self.n + self.nr - 1) / self.nr * self.nr * self.k
     }
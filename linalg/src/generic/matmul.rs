@@ -99,7 +99,7 @@ impl frame::matmul::PackedMatMulKer<f64> for DMatMul4x2 {
             let mut ab = [[0.0f64; 2]; 4];
             for i in 0..k {
                 let a = std::slice::from_raw_parts(a.offset(4 * i as isize), 4);
-                let b = std::slice::from_raw_parts(b.offset(4 * i as isize), 4);
+                let b = std::slice::from_raw_parts(b.offset(2 * i as isize), 2);
                 ab[0][0] += a[0] * b[0];
                 ab[0][1] += a[0] * b[1];
                 ab[1][0] += a[1] * b[0];
@@ -135,5 +135,46 @@ mod test {
             let mm = PackedMatMul::<SMatMul4x4, f32>::new(m, k, n);
             test_mat_mul_prep_f32(mm, m, k, n, a, b)?
         }
+
+        #[test]
+        fn mat_mul_acc((m, k, n, ref a, ref b) in strat_mat_mul()) {
+            let mm = PackedMatMul::<SMatMul4x4, f32>::new(m, k, n);
+            test_mat_mul_acc_f32(mm, m, k, n, a, b)?
+        }
+
+        #[test]
+        fn mat_mul_transposed((m, k, n, ref a, ref b) in strat_mat_mul()) {
+            let mm = PackedMatMul::<SMatMul4x4, f32>::new(n, k, m);
+            test_mat_mul_transposed_f32(mm, m, k, n, a, b)?
+        }
+    }
+
+    #[test]
+    fn f64_mat_mul_prepacked() {
+        use crate::frame::MatMul;
+        let (m, k, n) = (5, 3, 3);
+        let a: Vec<f64> = (0..m * k).map(|i| i as f64).collect();
+        let b: Vec<f64> = (0..k * n).map(|i| i as f64 - 4.0).collect();
+        let mm = PackedMatMul::<DMatMul4x2, f64>::new(m, k, n);
+        let mut found = vec![9999.0f64; m * n];
+        unsafe {
+            let mut packed_a: Vec<f64> =
+                crate::align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+            mm.pack_a(packed_a.as_mut_ptr(), a.as_ptr(), k as isize, 1);
+            let mut packed_b: Vec<f64> =
+                crate::align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+            mm.b_pack().pack(packed_b.as_mut_ptr(), b.as_ptr(), n as isize, 1);
+            mm.mat_mul_prepacked(
+                packed_a.as_ptr(),
+                packed_b.as_ptr(),
+                found.as_mut_ptr(),
+                n as isize,
+                1,
+            );
+        }
+        let expected: Vec<f64> = (0..m * n)
+            .map(|ix| (0..k).map(|i| a[i + k * (ix / n)] * b[ix % n + i * n]).sum())
+            .collect();
+        assert_eq!(found, expected);
     }
 }
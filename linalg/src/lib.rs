@@ -10,6 +10,9 @@ extern crate proptest;
 pub mod align;
 pub mod f16;
 pub mod frame;
+pub mod lu;
+pub mod quant;
+pub mod tune;
 mod generic;
 
 #[cfg(target_arch = "x86_64")]
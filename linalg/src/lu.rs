@@ -0,0 +1,122 @@
+//! LU decomposition of small dense square matrices, and the determinant it
+//! gives.
+//!
+//! Matrices are `n * n` row-major slices, decomposed in place.
+
+use num_traits::Float;
+
+/// Decomposes `a` in place as `P.A = L.U`, with partial pivoting: `U` is in
+/// the upper triangle and diagonal, `L` (with an implicit unit diagonal)
+/// in the lower triangle.
+///
+/// Returns whether `P` swapped an odd number of rows, or None if `a` is
+/// singular, in which case `a` is left partially decomposed.
+pub fn lu_in_place<T: Float>(n: usize, a: &mut [T]) -> Option<bool> {
+    assert_eq!(a.len(), n * n);
+    let mut odd = false;
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&x, &y| {
+                let (x, y) = (a[x * n + col].abs(), a[y * n + col].abs());
+                x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        if a[pivot * n + col] == T::zero() {
+            return None;
+        }
+        if pivot != col {
+            for x in 0..n {
+                a.swap(pivot * n + x, col * n + x);
+            }
+            odd = !odd;
+        }
+        let diag = a[col * n + col];
+        for row in col + 1..n {
+            let factor = a[row * n + col] / diag;
+            a[row * n + col] = factor;
+            for x in col + 1..n {
+                a[row * n + x] = a[row * n + x] - factor * a[col * n + x];
+            }
+        }
+    }
+    Some(odd)
+}
+
+/// Determinant of `a`, clobbered by its decomposition. Zero for singular
+/// matrices, one for the empty matrix.
+pub fn det<T: Float>(n: usize, a: &mut [T]) -> T {
+    match lu_in_place(n, a) {
+        None => T::zero(),
+        Some(odd) => {
+            let det = (0..n).fold(T::one(), |det, ix| det * a[ix * n + ix]);
+            if odd {
+                -det
+            } else {
+                det
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::*;
+
+    /// Laplace expansion along the first row.
+    fn reference(n: usize, a: &[f64]) -> f64 {
+        if n == 0 {
+            return 1.0;
+        }
+        (0..n)
+            .map(|col| {
+                let minor: Vec<f64> =
+                    (n..n * n).filter(|ix| ix % n != col).map(|ix| a[ix]).collect();
+                let sign = if col % 2 == 0 { 1.0 } else { -1.0 };
+                sign * a[col] * reference(n - 1, &minor)
+            })
+            .sum()
+    }
+
+    fn strat_matrix() -> BoxedStrategy<(usize, Vec<f64>)> {
+        (0usize..6)
+            .prop_flat_map(|n| {
+                (Just(n), proptest::collection::vec((-10..10).prop_map(|a| a as f64 / 4.0), n * n))
+            })
+            .boxed()
+    }
+
+    proptest! {
+        #[test]
+        fn det_prop((n, a) in strat_matrix()) {
+            let expected = reference(n, &a);
+            // Hadamard bound on the determinant, to scale the rounding errors
+            let scale = a
+                .chunks(n.max(1))
+                .map(|row| row.iter().map(|x| x * x).sum::<f64>().sqrt())
+                .product::<f64>()
+                .max(1.0);
+            let found = det(n, &mut a.clone());
+            prop_assert!((found - expected).abs() <= 1e-12 * scale, "{} for {}", found, expected);
+            let found = det(n, &mut a.iter().map(|&x| x as f32).collect::<Vec<_>>());
+            prop_assert!((found as f64 - expected).abs() <= 1e-5 * scale,
+                "{} for {}", found, expected);
+        }
+    }
+
+    #[test]
+    fn swapped_rows() {
+        assert_eq!(det(2, &mut [0.0f32, 1.0, 1.0, 0.0]), -1.0);
+        assert_eq!(det(3, &mut [0.0f32, 0.0, 2.0, 0.0, 3.0, 0.0, 4.0, 0.0, 0.0]), -24.0);
+    }
+
+    #[test]
+    fn singular() {
+        let mut a = [1.0f32, 2.0, 3.0, 2.0, 4.0, 6.0, 0.0, 1.0, 5.0];
+        assert_eq!(lu_in_place(3, &mut a), None);
+        assert_eq!(det(3, &mut [1.0f32, 2.0, 3.0, 2.0, 4.0, 6.0, 0.0, 1.0, 5.0]), 0.0);
+        assert_eq!(det(2, &mut [0.0f64; 4]), 0.0);
+        assert_eq!(det::<f32>(0, &mut []), 1.0);
+    }
+}
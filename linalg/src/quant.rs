@@ -0,0 +1,427 @@
+//! Reference kernels for integer (quantized) networks.
+//!
+//! Operands are `MatRef`s, slices read through a row and a column stride,
+//! so transposed operands need no copy.
+
+use num_traits::Zero;
+
+/// What integer arithmetic does with results out of the range of its type.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Overflow {
+    /// Keep the low bits, in two's complement, like ONNX integer ops.
+    Wrap,
+    /// Clamp to the range of the type, like some quantized runtimes.
+    Saturate,
+}
+
+/// Integer arithmetic with an explicit `Overflow` behaviour.
+pub trait OverflowArith: Copy {
+    fn add_with(self, other: Self, overflow: Overflow) -> Self;
+    fn sub_with(self, other: Self, overflow: Overflow) -> Self;
+    fn mul_with(self, other: Self, overflow: Overflow) -> Self;
+}
+
+macro_rules! impl_overflow_arith {
+    ($($t:ty),*) => {
+        $(impl OverflowArith for $t {
+            fn add_with(self, other: $t, overflow: Overflow) -> $t {
+                match overflow {
+                    Overflow::Wrap => self.wrapping_add(other),
+                    Overflow::Saturate => self.saturating_add(other),
+                }
+            }
+
+            fn sub_with(self, other: $t, overflow: Overflow) -> $t {
+                match overflow {
+                    Overflow::Wrap => self.wrapping_sub(other),
+                    Overflow::Saturate => self.saturating_sub(other),
+                }
+            }
+
+            fn mul_with(self, other: $t, overflow: Overflow) -> $t {
+                match overflow {
+                    Overflow::Wrap => self.wrapping_mul(other),
+                    Overflow::Saturate => self.saturating_mul(other),
+                }
+            }
+        })*
+    };
+}
+
+impl_overflow_arith!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// A `rows * cols` matrix read in place from a slice, its item (row, col)
+/// being at `row * row_stride + col * col_stride`: row-major, column-major,
+/// or a transposition of them.
+#[derive(Debug, Copy, Clone)]
+pub struct MatRef<'a, T> {
+    data: &'a [T],
+    rows: usize,
+    cols: usize,
+    row_stride: usize,
+    col_stride: usize,
+}
+
+impl<'a, T> MatRef<'a, T> {
+    /// Panics if an item of the matrix falls out of `data`.
+    pub fn new(
+        data: &'a [T],
+        (rows, cols): (usize, usize),
+        (row_stride, col_stride): (usize, usize),
+    ) -> MatRef<'a, T> {
+        if rows > 0 && cols > 0 {
+            let last = (rows - 1) * row_stride + (cols - 1) * col_stride;
+            assert!(last < data.len(), "{}x{} matrix out of a slice of {}", rows, cols, data.len());
+        }
+        MatRef { data, rows, cols, row_stride, col_stride }
+    }
+
+    /// A row-major matrix.
+    pub fn row_major(data: &'a [T], rows: usize, cols: usize) -> MatRef<'a, T> {
+        MatRef::new(data, (rows, cols), (cols, 1))
+    }
+}
+
+/// C(m,n) = A(m,k) . B(k,n), each operand described by a pointer and a row
+/// and column stride.
+///
+/// # Safety
+///
+/// Every item of the three matrices, at `row * rs + col * cs` from its
+/// pointer, must be in bounds, and C must not overlap A or B.
+unsafe fn mat_mul_acc<A, B, C>(
+    m: usize,
+    k: usize,
+    n: usize,
+    a: *const A,
+    rsa: isize,
+    csa: isize,
+    b: *const B,
+    rsb: isize,
+    csb: isize,
+    c: *mut C,
+    rsc: isize,
+    csc: isize,
+    overflow: Overflow,
+) where
+    A: Copy + Into<C>,
+    B: Copy + Into<C>,
+    C: Zero + OverflowArith,
+{
+    for row in 0..m {
+        for col in 0..n {
+            let mut sum = C::zero();
+            for i in 0..k {
+                let a: C = (*a.offset(row as isize * rsa + i as isize * csa)).into();
+                let b: C = (*b.offset(i as isize * rsb + col as isize * csb)).into();
+                sum = sum.add_with(a.mul_with(b, overflow), overflow);
+            }
+            *c.offset(row as isize * rsc + col as isize * csc) = sum;
+        }
+    }
+}
+
+/// C = A.B, C being row-major. Panics if the shapes do not match.
+fn mat_mul_checked<A, B, C>(a: MatRef<A>, b: MatRef<B>, c: &mut [C], overflow: Overflow)
+where
+    A: Copy + Into<C>,
+    B: Copy + Into<C>,
+    C: Zero + OverflowArith,
+{
+    let (m, k, n) = (a.rows, a.cols, b.cols);
+    assert_eq!(b.rows, k, "a is {}x{}, b {}x{}", m, k, b.rows, n);
+    assert_eq!(c.len(), m * n, "c has {} items for a {}x{} product", c.len(), m, n);
+    // MatRef::new checked A and B items are in their slices, C is exactly m * n
+    unsafe {
+        mat_mul_acc(
+            m,
+            k,
+            n,
+            a.data.as_ptr(),
+            a.row_stride as isize,
+            a.col_stride as isize,
+            b.data.as_ptr(),
+            b.row_stride as isize,
+            b.col_stride as isize,
+            c.as_mut_ptr(),
+            n as isize,
+            1,
+            overflow,
+        )
+    }
+}
+
+/// C(m,n) = A(m,k) . B(k,n), with i8 weights as A, i16 activations as B, and
+/// i32 accumulation in the row-major C. Panics if the shapes do not match.
+///
+/// Exact as long as k * 2^7 * 2^15 fits in i32, so k up to 2^9. Beyond,
+/// sums out of the i32 range wrap or saturate according to `overflow`: the
+/// products themselves always fit.
+pub fn mat_mul_i16_i8_i32(a: MatRef<i8>, b: MatRef<i16>, c: &mut [i32], overflow: Overflow) {
+    mat_mul_checked(a, b, c, overflow)
+}
+
+/// Same as `mat_mul_i16_i8_i32`, accumulating in i64 to rule out overflow.
+pub fn mat_mul_i16_i8_i64(a: MatRef<i8>, b: MatRef<i16>, c: &mut [i64]) {
+    mat_mul_checked(a, b, c, Overflow::Wrap)
+}
+
+/// C(m,n) = A(m,k) . B(k,n), with i8 operands and i32 accumulation in the
+/// row-major C, for int8 dense layers. Panics if the shapes do not match.
+///
+/// Exact as long as k * 2^14 fits in i32, so k up to 2^17. Beyond, sums out
+/// of the i32 range wrap or saturate according to `overflow`.
+pub fn mat_mul_i8_i32(a: MatRef<i8>, b: MatRef<i8>, c: &mut [i32], overflow: Overflow) {
+    mat_mul_checked(a, b, c, overflow)
+}
+
+/// Scale an accumulator back to i16, rounding to nearest with `rounding` and
+/// saturating.
+pub fn requantize_i64_to_i16(v: i64, multiplier: f32, rounding: Rounding) -> i16 {
+    let v = rounding.round(v as f64 * multiplier as f64);
+    v.max(std::i16::MIN as f64).min(std::i16::MAX as f64) as i16
+}
+
+/// Tie breaking rule of the integer requantizations.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rounding {
+    /// Halves go away from zero, like gemmlowp and TFLite.
+    HalfAwayFromZero,
+    /// Halves go to the even neighbour, like ONNX QuantizeLinear.
+    HalfToEven,
+}
+
+impl Rounding {
+    /// Round `x` to the nearest integer, breaking ties by `self`.
+    pub fn round(self, x: f64) -> f64 {
+        let away = x.round();
+        match self {
+            Rounding::HalfToEven if (away - x).abs() == 0.5 => 2.0 * (x / 2.0).round(),
+            _ => away,
+        }
+    }
+}
+
+/// Splits a positive real multiplier into a Q31 fixed-point multiplier in
+/// [2^30, 2^31) and a right shift, so that real ~= multiplier * 2^(-31-shift).
+///
+/// The shift is negative for multipliers of 1 or more.
+pub fn quantize_multiplier(real: f64) -> (i32, i32) {
+    assert!(real > 0.0 && real.is_finite(), "multiplier must be positive, got {}", real);
+    let mut exp = real.log2().floor() as i32 + 1;
+    let mut mantissa = real / 2f64.powi(exp);
+    // log2 may land one off near powers of two
+    if mantissa >= 1.0 {
+        mantissa /= 2.0;
+        exp += 1;
+    } else if mantissa < 0.5 {
+        mantissa *= 2.0;
+        exp -= 1;
+    }
+    let mut fixed = (mantissa * (1i64 << 31) as f64).round() as i64;
+    if fixed == 1 << 31 {
+        fixed /= 2;
+        exp += 1;
+    }
+    (fixed as i32, -exp)
+}
+
+/// Scale an accumulator by a fixed-point multiplier: acc * multiplier *
+/// 2^(-31-shift), rounded once with `rounding` and saturated to i64.
+///
+/// Integer arithmetic only, for targets without a FPU: the product is
+/// computed exactly in i128, so the result does not suffer from the double
+/// rounding of the gemmlowp doubling-high-mul then rounding-shift sequence.
+pub fn scale_by_fixed_point(acc: i64, multiplier: i32, shift: i32, rounding: Rounding) -> i64 {
+    let total_shift = 31 + shift;
+    assert!(total_shift > 0 && total_shift < 127, "shift out of range: {}", shift);
+    let product = acc as i128 * multiplier as i128;
+    let floor = product >> total_shift;
+    let rest = product - (floor << total_shift);
+    let half = 1i128 << (total_shift - 1);
+    let rounded = if rest > half {
+        floor + 1
+    } else if rest < half {
+        floor
+    } else {
+        match rounding {
+            Rounding::HalfAwayFromZero if product >= 0 => floor + 1,
+            Rounding::HalfAwayFromZero => floor,
+            Rounding::HalfToEven => floor + (floor & 1),
+        }
+    };
+    rounded.max(std::i64::MIN as i128).min(std::i64::MAX as i128) as i64
+}
+
+/// Scale an i32 accumulator to i8: acc * multiplier * 2^(-31-shift), rounded
+/// once with `rounding`, offset by `zero_point` and saturated to [-128, 127].
+///
+/// `multiplier` and `shift` are usually the output of `quantize_multiplier`.
+pub fn requantize_i32_to_i8(
+    acc: i32,
+    multiplier: i32,
+    shift: i32,
+    zero_point: i8,
+    rounding: Rounding,
+) -> i8 {
+    let rounded = scale_by_fixed_point(acc as i64, multiplier, shift, rounding);
+    (rounded + zero_point as i64).max(std::i8::MIN as i64).min(std::i8::MAX as i64) as i8
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mat_mul_i16_i8() {
+        // a: 2x3 row major, b: 3x2 col major
+        let a = MatRef::row_major(&[1i8, -2, 3, -4, 5, -6], 2, 3);
+        let b = MatRef::new(&[1000i16, 2000, 3000, -1000, -2000, -3000], (3, 2), (1, 3));
+        let mut c32 = [0i32; 4];
+        let mut c64 = [0i64; 4];
+        mat_mul_i16_i8_i32(a, b, &mut c32, Overflow::Wrap);
+        mat_mul_i16_i8_i64(a, b, &mut c64);
+        assert_eq!(c32, [6000, -6000, -12000, 12000]);
+        assert_eq!(c64, [6000, -6000, -12000, 12000]);
+    }
+
+    #[test]
+    fn mat_mul_i16_i8_i64_no_overflow() {
+        let a = [std::i8::MIN; 1024];
+        let b = [std::i16::MIN; 1024];
+        let mut c = [0i64];
+        mat_mul_i16_i8_i64(MatRef::row_major(&a, 1, 1024), MatRef::row_major(&b, 1024, 1), &mut c);
+        assert_eq!(c[0], 1024 * 128 * 32768);
+    }
+
+    #[test]
+    fn mat_mul_i16_i8_i32_overflow() {
+        let a = [std::i8::MIN; 1024];
+        let b = [std::i16::MIN; 1024];
+        let exact = 1024i64 * 128 * 32768;
+        for &(overflow, expected) in
+            &[(Overflow::Wrap, exact as i32), (Overflow::Saturate, std::i32::MAX)]
+        {
+            let mut c = [0i32];
+            let (a, b) = (MatRef::row_major(&a, 1, 1024), MatRef::row_major(&b, 1024, 1));
+            mat_mul_i16_i8_i32(a, b, &mut c, overflow);
+            assert_eq!(c[0], expected, "{:?}", overflow);
+        }
+    }
+
+    #[test]
+    fn mat_mul_i8() {
+        // a: 2x3 row major, b: 3x2 col major
+        let a = MatRef::row_major(&[1i8, -2, 3, -4, 5, -6], 2, 3);
+        let b = MatRef::new(&[100i8, 20, 3, -100, -20, -3], (3, 2), (1, 3));
+        let mut c = [0i32; 4];
+        mat_mul_i8_i32(a, b, &mut c, Overflow::Wrap);
+        assert_eq!(c, [69, -69, -318, 318]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mat_ref_out_of_slice() {
+        // the last item of a 3x2 column major matrix is at 2 + 1 * 3
+        MatRef::new(&[0i8; 5], (3, 2), (1, 3));
+    }
+
+    #[test]
+    fn overflow_arith() {
+        use self::Overflow::*;
+        assert_eq!(100i8.add_with(100, Wrap), -56);
+        assert_eq!(100i8.add_with(100, Saturate), 127);
+        assert_eq!((-100i8).sub_with(100, Wrap), 56);
+        assert_eq!((-100i8).sub_with(100, Saturate), -128);
+        assert_eq!(300i16.mul_with(300, Wrap), 24464);
+        assert_eq!(300i16.mul_with(-300, Saturate), std::i16::MIN);
+        assert_eq!(200u8.add_with(100, Saturate), 255);
+        assert_eq!(10u8.sub_with(20, Wrap), 246);
+    }
+
+    #[test]
+    fn requantize() {
+        use self::Rounding::*;
+        assert_eq!(requantize_i64_to_i16(1000, 0.5, HalfAwayFromZero), 500);
+        assert_eq!(requantize_i64_to_i16(-3, 0.5, HalfAwayFromZero), -2);
+        assert_eq!(requantize_i64_to_i16(1 << 40, 1.0, HalfAwayFromZero), std::i16::MAX);
+        assert_eq!(requantize_i64_to_i16(-(1 << 40), 1.0, HalfToEven), std::i16::MIN);
+    }
+
+    #[test]
+    fn requantize_i16_ties() {
+        use self::Rounding::*;
+        // (acc, away from zero, to even), scaled by 0.5
+        let table =
+            [(1, 1, 0), (3, 2, 2), (5, 3, 2), (-1, -1, 0), (-3, -2, -2), (-5, -3, -2), (4, 2, 2)];
+        for &(acc, away, even) in table.iter() {
+            assert_eq!(requantize_i64_to_i16(acc, 0.5, HalfAwayFromZero), away, "{} away", acc);
+            assert_eq!(requantize_i64_to_i16(acc, 0.5, HalfToEven), even, "{} even", acc);
+        }
+        assert_eq!(Rounding::HalfToEven.round(2.4), 2.0);
+        assert_eq!(Rounding::HalfToEven.round(-2.6), -3.0);
+    }
+
+    #[test]
+    fn quantize_multipliers() {
+        assert_eq!(quantize_multiplier(0.5), (1 << 30, 0));
+        assert_eq!(quantize_multiplier(0.25), (1 << 30, 1));
+        assert_eq!(quantize_multiplier(1.0), (1 << 30, -1));
+        assert_eq!(quantize_multiplier(0.75), (3 << 29, 0));
+        assert_eq!(quantize_multiplier(3.0), (3 << 29, -2));
+        let (m, s) = quantize_multiplier(0.0123);
+        assert!(m >= 1 << 30);
+        assert!((m as f64 * 2f64.powi(-31 - s) - 0.0123).abs() < 1e-11);
+    }
+
+    #[test]
+    fn fixed_point_scaling() {
+        use self::Rounding::*;
+        let (half, half_shift) = quantize_multiplier(0.5);
+        for &(acc, away, even) in &[(5i64, 3, 2), (-5, -3, -2), (7, 4, 4), (4, 2, 2)] {
+            assert_eq!(scale_by_fixed_point(acc, half, half_shift, HalfAwayFromZero), away);
+            assert_eq!(scale_by_fixed_point(acc, half, half_shift, HalfToEven), even);
+        }
+        // past the i64 range of the product
+        let (m, s) = quantize_multiplier(0.75);
+        assert_eq!(scale_by_fixed_point(1 << 60, m, s, HalfToEven), 3 << 58);
+        let (m, s) = quantize_multiplier(4.0);
+        assert_eq!(scale_by_fixed_point(std::i64::MIN, m, s, HalfToEven), std::i64::MIN);
+    }
+
+    #[test]
+    fn requantize_i8() {
+        use self::Rounding::*;
+        let half = quantize_multiplier(0.5);
+        let quarter = quantize_multiplier(0.25);
+        let two = quantize_multiplier(2.0);
+        // (acc, (multiplier, shift), zero point, away from zero, to even)
+        let table = [
+            (0, half, 0, 0, 0),
+            (10, half, 0, 5, 5),
+            (5, half, 0, 3, 2),
+            (7, half, 0, 4, 4),
+            (-5, half, 0, -3, -2),
+            (-7, half, 0, -4, -4),
+            (-3, half, 0, -2, -2),
+            (6, quarter, 0, 2, 2),
+            (-6, quarter, 0, -2, -2),
+            (10, quarter, 0, 3, 2),
+            (-10, quarter, 0, -3, -2),
+            (5, half, 10, 13, 12),
+            (-5, half, -10, -13, -12),
+            (100, two, 0, 127, 127),
+            (-100, two, 0, -128, -128),
+            (255, half, 0, 127, 127),
+            (-257, half, 0, -128, -128),
+            (250, half, -100, 25, 25),
+            (-250, half, 100, -25, -25),
+            (std::i32::MAX, half, 0, 127, 127),
+            (std::i32::MIN, half, 0, -128, -128),
+        ];
+        for &(acc, (m, s), zp, away, even) in table.iter() {
+            assert_eq!(requantize_i32_to_i8(acc, m, s, zp, HalfAwayFromZero), away, "{} away", acc);
+            assert_eq!(requantize_i32_to_i8(acc, m, s, zp, HalfToEven), even, "{} even", acc);
+        }
+    }
+}
@@ -0,0 +1,276 @@
+//! Picks the f32 matrix multiplier for a product shape by timing the
+//! kernels this CPU supports, and keeps the choices in a file so later runs
+//! skip the benchmark.
+//!
+//! The file starts with a stamp naming the crate version and the candidate
+//! kernels: a file written by a build with other kernels is ignored, then
+//! overwritten on the next save.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use crate::align;
+use crate::frame::matmul::PackedMatMulKer;
+use crate::frame::{MatMul, PackedMatMul};
+
+/// Bumped when the file layout changes.
+const FORMAT: usize = 1;
+
+/// Timed runs per candidate, the fastest one counts.
+const RUNS: usize = 5;
+
+#[derive(Clone)]
+struct Candidate {
+    name: String,
+    smm: fn(usize, usize, usize) -> Box<MatMul<f32>>,
+}
+
+fn candidate<K: PackedMatMulKer<f32> + 'static>() -> Candidate {
+    Candidate {
+        name: format!("{}-{}x{}", K::name(), K::mr(), K::nr()),
+        smm: |m, k, n| Box::new(PackedMatMul::<K, f32>::new(m, k, n)),
+    }
+}
+
+fn candidates() -> Vec<Candidate> {
+    let mut candidates = vec![candidate::<crate::generic::SMatMul4x4>()];
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("fma") {
+            candidates.push(candidate::<crate::x86_64_fma::matmul::KerFma16x6>());
+        }
+    }
+    #[cfg(target_arch = "arm")]
+    {
+        candidates.push(candidate::<crate::arm32::armvfpv2::SMatMul4x4>());
+        if crate::arm32::has_neon() {
+            candidates.push(candidate::<crate::arm32::armv7neon::SMatMul8x4>());
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    candidates.push(candidate::<crate::arm64::arm64simd::SMatMul8x8>());
+    candidates
+}
+
+fn cpu() -> String {
+    fs::read_to_string("/proc/cpuinfo")
+        .ok()
+        .and_then(|info| {
+            info.lines()
+                .find(|l| l.starts_with("model name"))
+                .and_then(|l| l.splitn(2, ':').nth(1))
+                .map(|name| name.trim().to_string())
+        })
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+/// Times `candidate` on a product of this shape.
+fn bench(candidate: &Candidate, m: usize, k: usize, n: usize) -> Duration {
+    let mm = (candidate.smm)(m, k, n);
+    unsafe {
+        let mut pa: Vec<f32> = align::uninitialized(mm.packed_a_len(), mm.packed_a_alignment());
+        let mut pb: Vec<f32> = align::uninitialized(mm.b_pack().len(), mm.b_pack().alignment());
+        pa.iter_mut().chain(pb.iter_mut()).enumerate().for_each(|(i, x)| *x = (i % 7) as f32);
+        let mut c = vec![0.0f32; m * n];
+        let mut c_panel = vec![0.0f32; mm.c_panel_len()];
+        (0..RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                mm.mat_mul_prepacked_in(
+                    pa.as_ptr(),
+                    pb.as_ptr(),
+                    c.as_mut_ptr(),
+                    n as isize,
+                    1,
+                    false,
+                    &mut c_panel,
+                );
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    }
+}
+
+/// Kernel choices per (cpu, m, k, n), backed by a file.
+#[derive(Clone)]
+pub struct Autotuner {
+    path: PathBuf,
+    cpu: String,
+    candidates: Vec<Candidate>,
+    choices: HashMap<(String, usize, usize, usize), String>,
+}
+
+impl std::fmt::Debug for Autotuner {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "Autotuner {:?} ({} choices)", self.path, self.choices.len())
+    }
+}
+
+impl Autotuner {
+    /// Loads the choices cached at `path`. A missing, unreadable or stale
+    /// file gives an empty tuner.
+    pub fn open(path: impl AsRef<Path>) -> Autotuner {
+        let mut tuner = Autotuner {
+            path: path.as_ref().to_path_buf(),
+            cpu: cpu(),
+            candidates: candidates(),
+            choices: HashMap::new(),
+        };
+        if let Ok(content) = fs::read_to_string(&tuner.path) {
+            tuner.load(&content);
+        }
+        tuner
+    }
+
+    fn stamp(&self) -> String {
+        let names: Vec<&str> = self.candidates.iter().map(|c| &*c.name).collect();
+        format!("tract-linalg-tune {} {} {}", FORMAT, env!("CARGO_PKG_VERSION"), names.join(","))
+    }
+
+    fn load(&mut self, content: &str) {
+        let mut lines = content.lines();
+        if lines.next() != Some(&*self.stamp()) {
+            log::info!("Ignoring stale kernel choices in {:?}", self.path);
+            return;
+        }
+        for line in lines {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if let [cpu, m, k, n, kernel] = &*fields {
+                if !self.candidates.iter().any(|c| c.name == *kernel) {
+                    continue;
+                }
+                if let (Ok(m), Ok(k), Ok(n)) = (m.parse(), k.parse(), n.parse()) {
+                    self.choices.insert((cpu.to_string(), m, k, n), kernel.to_string());
+                }
+            }
+        }
+    }
+
+    /// Kernel chosen for this shape on this CPU, if any.
+    pub fn cached(&self, m: usize, k: usize, n: usize) -> Option<&str> {
+        self.choices.get(&(self.cpu.clone(), m, k, n)).map(|s| &**s)
+    }
+
+    /// Name of the kernel to use for this shape, benchmarking the candidates
+    /// the first time the shape is seen.
+    pub fn choose(&mut self, m: usize, k: usize, n: usize) -> &str {
+        let key = (self.cpu.clone(), m, k, n);
+        if !self.choices.contains_key(&key) {
+            let best = self
+                .candidates
+                .iter()
+                .min_by_key(|c| bench(c, m, k, n))
+                .map(|c| c.name.clone())
+                .unwrap();
+            log::info!("Tuned {}x{}x{}: {}", m, k, n, best);
+            self.choices.insert(key.clone(), best);
+        }
+        &self.choices[&key]
+    }
+
+    /// A multiplier for this shape, using the kernel `choose` picks.
+    pub fn smm(&mut self, m: usize, k: usize, n: usize) -> Box<MatMul<f32>> {
+        let name = self.choose(m, k, n).to_string();
+        let candidate = self.candidates.iter().find(|c| c.name == name).unwrap();
+        (candidate.smm)(m, k, n)
+    }
+
+    /// Writes the choices, for every CPU seen in the file, to the file.
+    pub fn save(&self) -> io::Result<()> {
+        let mut content = self.stamp();
+        content.push('\n');
+        let mut choices: Vec<_> = self.choices.iter().collect();
+        choices.sort();
+        for ((cpu, m, k, n), kernel) in choices {
+            writeln!(content, "{}\t{}\t{}\t{}\t{}", cpu, m, k, n, kernel).unwrap();
+        }
+        fs::write(&self.path, content)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TUNER: Mutex<Option<Autotuner>> = Mutex::new(None);
+}
+
+/// Route the f32 products built by `smm` through an autotuner caching its
+/// choices at `path`.
+pub fn enable(path: impl AsRef<Path>) {
+    *TUNER.lock().unwrap() = Some(Autotuner::open(path));
+}
+
+/// A f32 multiplier for this shape: the tuned kernel if `enable` was called,
+/// the default one otherwise.
+///
+/// Newly tuned shapes are saved right away, a failure to do so is only
+/// logged.
+pub fn smm(m: usize, k: usize, n: usize) -> Box<MatMul<f32>> {
+    let mut tuner = TUNER.lock().unwrap();
+    if let Some(tuner) = tuner.as_mut() {
+        let known = tuner.cached(m, k, n).is_some();
+        let mm = tuner.smm(m, k, n);
+        if !known {
+            if let Err(e) = tuner.save() {
+                log::warn!("Could not save kernel choices to {:?}: {}", tuner.path, e);
+            }
+        }
+        mm
+    } else {
+        (crate::ops().smm)(m, k, n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn cache_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("tract-linalg-tune-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn choice_is_consistent() {
+        let mut tuner = Autotuner::open(cache_path("consistent"));
+        let first = tuner.choose(16, 32, 40).to_string();
+        assert!(tuner.candidates.iter().any(|c| c.name == first));
+        assert_eq!(tuner.cached(16, 32, 40), Some(&*first));
+        assert_eq!(tuner.choose(16, 32, 40), first);
+        let mm = tuner.smm(16, 32, 40);
+        assert_eq!((mm.m(), mm.k(), mm.n()), (16, 32, 40));
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let path = cache_path("round-trip");
+        let mut tuner = Autotuner::open(&path);
+        tuner.choose(8, 8, 8);
+        tuner.choose(4, 64, 100);
+        tuner.choices.insert(("other cpu".to_string(), 1, 2, 3), "generic-4x4".to_string());
+        tuner.save().unwrap();
+        let reloaded = Autotuner::open(&path);
+        assert_eq!(reloaded.choices, tuner.choices);
+        assert_eq!(reloaded.cached(8, 8, 8), tuner.cached(8, 8, 8));
+        assert_eq!(reloaded.cached(1, 2, 3), None);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn stale_cache_is_ignored() {
+        let path = cache_path("stale");
+        let mut tuner = Autotuner::open(&path);
+        tuner.choose(8, 8, 8);
+        tuner.save().unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        let stale = content.replacen(&format!(" {} ", FORMAT), &format!(" {} ", FORMAT + 1), 1);
+        fs::write(&path, stale).unwrap();
+        assert_eq!(Autotuner::open(&path).cached(8, 8, 8), None);
+        fs::remove_file(path).unwrap();
+    }
+}
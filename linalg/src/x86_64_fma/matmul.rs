@@ -75,5 +75,23 @@ mod test {
             let mm = PackedMatMul::<KerFma16x6, f32>::new(m, k, n);
             test_mat_mul_prep_f32(mm, m, k, n, a, b)?
         }
+
+        #[test]
+        fn mat_mul_acc((m, k, n, ref a, ref b) in strat_mat_mul()) {
+            if !is_x86_feature_detected!("fma") {
+                return Ok(())
+            }
+            let mm = PackedMatMul::<KerFma16x6, f32>::new(m, k, n);
+            test_mat_mul_acc_f32(mm, m, k, n, a, b)?
+        }
+
+        #[test]
+        fn mat_mul_transposed((m, k, n, ref a, ref b) in strat_mat_mul()) {
+            if !is_x86_feature_detected!("fma") {
+                return Ok(())
+            }
+            let mm = PackedMatMul::<KerFma16x6, f32>::new(n, k, m);
+            test_mat_mul_transposed_f32(mm, m, k, n, a, b)?
+        }
     }
 }
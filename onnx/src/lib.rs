@@ -37,5 +37,5 @@ pub fn for_reader<R: std::io::Read>(mut r: R) -> TractResult<InferenceModel> {
 pub fn onnx() -> Onnx {
     let mut ops = tract_core::framework::OpRegister::default();
     ops::register_all_ops(&mut ops);
-    Onnx { op_register: ops }
+    Onnx { op_register: ops, downcast_f64: false }
 }
@@ -3,6 +3,7 @@ use std::convert::TryInto;
 use std::collections::HashMap;
 
 use tract_core::internal::*;
+use tract_core::ops::unimpl::UnimplementedOp;
 
 use crate::pb;
 
@@ -10,20 +11,141 @@ pub type OnnxOpRegister = OpRegister<pb::NodeProto>;
 
 pub struct Onnx {
     pub op_register: OnnxOpRegister,
+    /// See `Onnx::with_f64_downcast`.
+    pub downcast_f64: bool,
 }
 
-impl Framework<pb::NodeProto, pb::ModelProto> for Onnx {
-    fn op_builder_for_name(&self, name: &str) -> Option<&OpBuilder<pb::NodeProto>> {
-        self.op_register.get(name)
+/// Whether `domain` names the default ONNX operator set.
+fn is_default_domain(domain: &str) -> bool {
+    domain == "" || domain == "ai.onnx"
+}
+
+/// Version of each operator set the model imports, by domain. The default
+/// domain is keyed as "".
+pub fn opset_versions(proto: &pb::ModelProto) -> TractResult<HashMap<String, i64>> {
+    let mut versions = HashMap::new();
+    for import in proto.get_opset_import() {
+        let domain = if is_default_domain(import.get_domain()) { "" } else { import.get_domain() };
+        if let Some(previous) = versions.insert(domain.to_string(), import.get_version()) {
+            if previous != import.get_version() {
+                bail!(
+                    "Model imports opset versions {} and {} of domain {:?}",
+                    previous,
+                    import.get_version(),
+                    import.get_domain()
+                );
+            }
+        }
     }
+    Ok(versions)
+}
 
-    fn proto_model_for_read(&self, r: &mut std::io::Read) -> TractResult<pb::ModelProto> {
-        Ok(::protobuf::parse_from_reader(r).map_err(|e| format!("{:?}", e))?)
+/// Turn a f64 tensor into a f32 one, in place. Other tensors are left
+/// untouched.
+fn downcast_f64_tensor(tensor: &mut pb::TensorProto) {
+    if tensor.get_data_type() != pb::TensorProto_DataType::DOUBLE {
+        return;
     }
+    let values: Vec<f32> = if tensor.has_raw_data() {
+        // raw data is little endian
+        let raw = tensor.take_raw_data();
+        raw.chunks(8)
+            .map(|b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32)
+            .collect()
+    } else {
+        tensor.take_double_data().into_iter().map(|x| x as f32).collect()
+    };
+    tensor.set_data_type(pb::TensorProto_DataType::FLOAT);
+    tensor.set_float_data(values);
+}
 
-    fn model_for_proto_model(&self, proto: &pb::ModelProto) -> TractResult<InferenceModel> {
+fn downcast_f64_value_infos(infos: &mut [pb::ValueInfoProto]) {
+    for info in infos {
+        if info.get_field_type().has_tensor_type() {
+            let tensor_type = info.mut_field_type().mut_tensor_type();
+            if tensor_type.get_elem_type() == pb::TensorProto_DataType::DOUBLE {
+                tensor_type.set_elem_type(pb::TensorProto_DataType::FLOAT);
+            }
+        }
+    }
+}
+
+/// Make f32 all the f64 of `graph` and its subgraphs: initializers, declared
+/// types, constant values and the types ops are asked to produce (`to` of
+/// Cast, `dtype` of the random generators...).
+fn downcast_f64_graph(graph: &mut pb::GraphProto) {
+    for init in graph.mut_initializer().iter_mut() {
+        downcast_f64_tensor(init);
+    }
+    downcast_f64_value_infos(graph.mut_input());
+    downcast_f64_value_infos(graph.mut_output());
+    downcast_f64_value_infos(graph.mut_value_info());
+    for node in graph.mut_node().iter_mut() {
+        for attr in node.mut_attribute().iter_mut() {
+            if attr.has_t() {
+                downcast_f64_tensor(attr.mut_t());
+            }
+            for tensor in attr.mut_tensors().iter_mut() {
+                downcast_f64_tensor(tensor);
+            }
+            if attr.has_g() {
+                downcast_f64_graph(attr.mut_g());
+            }
+            for subgraph in attr.mut_graphs().iter_mut() {
+                downcast_f64_graph(subgraph);
+            }
+            let names_type = attr.get_name() == "to" || attr.get_name() == "dtype";
+            if names_type && attr.get_i() == pb::TensorProto_DataType::DOUBLE as i64 {
+                attr.set_i(pb::TensorProto_DataType::FLOAT as i64);
+            }
+        }
+    }
+}
+
+impl Onnx {
+    /// Load f64 tensors and types as f32, for the models storing their
+    /// weights as double to run on the f32 kernels.
+    ///
+    /// This is lossy, hence opt-in. Integer tensors are left as they are.
+    pub fn with_f64_downcast(self, downcast_f64: bool) -> Onnx {
+        Onnx { downcast_f64, ..self }
+    }
+
+    /// Build the op for `node` as defined in version `opset` of the default
+    /// operator set, or in the latest version if `opset` is None.
+    pub fn build_op_for_opset(
+        &self,
+        node: &pb::NodeProto,
+        opset: Option<i64>,
+    ) -> TractResult<Box<Op>> {
+        let builder = match opset {
+            Some(version) => self.op_register.get_for_version(node.get_op_type(), version),
+            None => self.op_register.get(node.get_op_type()),
+        };
+        match builder {
+            Some(builder) => builder(node),
+            None => Ok(Box::new(UnimplementedOp::new(node.get_op_type(), format!("{:?}", node)))),
+        }
+    }
+
+    /// Build the model of a graph, its ops defined as in the `opsets`
+    /// versions. Subgraphs, like loop bodies, are built with this too: they
+    /// can not refer to the tensors of the graph holding them.
+    pub fn model_for_graph(
+        &self,
+        graph: &pb::GraphProto,
+        opsets: &HashMap<String, i64>,
+    ) -> TractResult<InferenceModel> {
+        let downcast;
+        let graph = if self.downcast_f64 {
+            let mut graph = graph.clone();
+            downcast_f64_graph(&mut graph);
+            downcast = graph;
+            &downcast
+        } else {
+            graph
+        };
         let mut model = Model::default();
-        let graph = proto.get_graph();
         let mut initializers: HashMap<&str, Tensor> = graph
             .get_initializer()
             .iter()
@@ -51,14 +173,38 @@ impl Framework<pb::NodeProto, pb::ModelProto> for Onnx {
             trace!("Creating node {}", name);
             let facts = (0..pbnode.get_output().len()).map(|_| TensorFact::default()).collect();
             trace!("  outputs {:?}", pbnode.get_output());
-            let id = model.add_node(name, self.build_op(pbnode.get_op_type(), pbnode)?, facts)?;
+            // the register holds the default domain ops: nodes from other
+            // domains get their latest version
+            let opset =
+                if is_default_domain(pbnode.get_domain()) { opsets.get("").cloned() } else { None };
+            let id = model.add_node(&*name, self.build_op_for_opset(pbnode, opset)?, facts)?;
             for (ix, output) in pbnode.get_output().iter().enumerate() {
                 outlets_by_name.insert(output.to_owned(), OutletId::new(id, ix));
             }
-            for (ix, input) in pbnode.get_input().iter().enumerate() {
-                model.add_edge(outlets_by_name[&*input], InletId::new(id, ix))?;
+            // optional inputs left out are named "": the others are packed
+            let inputs = pbnode.get_input().iter().filter(|input| !input.is_empty());
+            for (ix, input) in inputs.enumerate() {
+                let outlet = outlets_by_name
+                    .get(&*input)
+                    .ok_or_else(|| format!("Node {} reads unknown tensor {}", name, input))?;
+                model.add_edge(*outlet, InletId::new(id, ix))?;
             }
         }
+        // shapes of intermediate tensors, seeding inference
+        for info in graph.get_value_info() {
+            let outlet = match outlets_by_name.get(info.get_name()) {
+                Some(outlet) => *outlet,
+                None => {
+                    debug!("Ignoring value_info for unknown tensor {}", info.get_name());
+                    continue;
+                }
+            };
+            let hint: TensorFact = info.get_field_type().get_tensor_type().try_into()?;
+            let fact = model.outlet_fact(outlet)?.unify(&hint).map_err(|e| {
+                format!("value_info of {} contradicts its declaration: {}", info.get_name(), e)
+            })?;
+            model.set_outlet_fact(outlet, fact)?;
+        }
         let mut outputs = vec![];
         for output in graph.get_output().iter() {
             let fact = output.get_field_type().get_tensor_type().try_into()?;
@@ -66,6 +212,334 @@ impl Framework<pb::NodeProto, pb::ModelProto> for Onnx {
             model.set_outlet_fact(outlets_by_name[output.get_name()], fact)?;
         }
         model.set_output_outlets(&outputs)?;
+        model.set_output_labels(graph.get_output().iter().map(|o| o.get_name()))?;
         Ok(model)
     }
 }
+
+impl Framework<pb::NodeProto, pb::ModelProto> for Onnx {
+    fn op_builder_for_name(&self, name: &str) -> Option<&OpBuilder<pb::NodeProto>> {
+        self.op_register.get(name)
+    }
+
+    fn proto_model_for_read(&self, r: &mut std::io::Read) -> TractResult<pb::ModelProto> {
+        Ok(::protobuf::parse_from_reader(r).map_err(|e| format!("{:?}", e))?)
+    }
+
+    fn model_for_proto_model(&self, proto: &pb::ModelProto) -> TractResult<InferenceModel> {
+        self.model_for_graph(proto.get_graph(), &opset_versions(proto)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pb::*;
+    use tract_core::ops::cnn::ConvUnary;
+
+    fn value_info(name: &str, dims: &[Result<i64, &str>]) -> ValueInfoProto {
+        let mut info = ValueInfoProto::new();
+        info.set_name(name.to_string());
+        let tensor_type = info.mut_field_type().mut_tensor_type();
+        tensor_type.set_elem_type(TensorProto_DataType::FLOAT);
+        for dim in dims {
+            let mut d = TensorShapeProto_Dimension::new();
+            match dim {
+                Ok(v) => d.set_dim_value(*v),
+                Err(p) => d.set_dim_param(p.to_string()),
+            }
+            tensor_type.mut_shape().mut_dim().push(d);
+        }
+        info
+    }
+
+    #[test]
+    fn io_metadata_matches_proto() {
+        let mut proto = ModelProto::new();
+        let graph = proto.mut_graph();
+        graph.mut_input().push(value_info("x", &[Err("batch"), Ok(3), Ok(224)]));
+        graph.mut_output().push(value_info("y", &[Err("batch"), Ok(3), Ok(224)]));
+        let mut relu = NodeProto::new();
+        relu.set_op_type("Relu".to_string());
+        relu.mut_input().push("x".to_string());
+        relu.mut_output().push("y".to_string());
+        graph.mut_node().push(relu);
+        let model = crate::onnx().model_for_proto_model(&proto).unwrap();
+
+        let inputs = model.input_metadata().unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].name, "x");
+        assert_eq!(inputs[0].datum_type, Some(DatumType::F32));
+        // named ONNX dims are left for the caller to resolve
+        assert_eq!(
+            inputs[0].shape,
+            Some(tvec!(IoDim::Unknown, IoDim::Fixed(3), IoDim::Fixed(224)))
+        );
+        let outputs = model.output_metadata().unwrap();
+        assert_eq!(outputs[0].name, "y");
+        assert_eq!(outputs[0].shape, inputs[0].shape);
+    }
+
+    fn relu(input: &str, output: &str) -> NodeProto {
+        let mut relu = NodeProto::new();
+        relu.set_op_type("Relu".to_string());
+        relu.mut_input().push(input.to_string());
+        relu.mut_output().push(output.to_string());
+        relu
+    }
+
+    /// x -> Relu -> h -> Relu -> y, with a value_info for h.
+    fn hinted(x: &[Result<i64, &str>], h: &[Result<i64, &str>]) -> InferenceModel {
+        let mut proto = ModelProto::new();
+        let graph = proto.mut_graph();
+        graph.mut_input().push(value_info("x", x));
+        graph.mut_output().push(value_info("y", &[Err("n"), Err("c")]));
+        graph.mut_value_info().push(value_info("h", h));
+        graph.mut_node().push(relu("x", "h"));
+        graph.mut_node().push(relu("h", "y"));
+        crate::onnx().model_for_proto_model(&proto).unwrap()
+    }
+
+    #[test]
+    fn value_info_pins_axes() {
+        let mut model = hinted(&[Err("n"), Err("c")], &[Err("n"), Ok(3)]);
+        model.analyse(false).unwrap();
+        for name in &["x", "h", "y"] {
+            let outlet = model.node_by_name(name).unwrap().id;
+            let fact = model.outlet_fact(OutletId::new(outlet, 0)).unwrap();
+            assert_eq!(fact.shape.dims().nth(1).unwrap(), DimFact::from(3.to_dim()));
+        }
+    }
+
+    #[test]
+    fn value_info_conflicts_with_inference() {
+        let mut model = hinted(&[Ok(2), Ok(4)], &[Ok(2), Ok(3)]);
+        let e = model.analyse(false).unwrap_err().to_string();
+        assert!(e.contains("Relu infers 2x4xF32 for output #0"), "{}", e);
+        assert!(e.contains("conflicting with its declared fact 2x3xF32"), "{}", e);
+    }
+
+    fn node(op_type: &str, inputs: &[&str], ints: &[(&str, &[i64])]) -> NodeProto {
+        let mut node = NodeProto::new();
+        node.set_op_type(op_type.to_string());
+        node.set_input(inputs.iter().map(|s| s.to_string()).collect());
+        node.mut_output().push("y".to_string());
+        for (name, values) in ints {
+            let mut attr = AttributeProto::new();
+            attr.set_name(name.to_string());
+            attr.set_field_type(AttributeProto_AttributeType::INTS);
+            attr.set_ints(values.to_vec());
+            node.mut_attribute().push(attr);
+        }
+        node
+    }
+
+    /// Run `node` on a [1, 2, 3] input x, in a model importing `opsets`.
+    /// `consts` are int64 vectors fed to the node after x.
+    fn run_at(
+        opsets: &[(&str, i64)],
+        node: NodeProto,
+        consts: &[(&str, &[i64])],
+    ) -> TractResult<Arc<Tensor>> {
+        let mut proto = ModelProto::new();
+        for (domain, version) in opsets {
+            let mut import = OperatorSetIdProto::new();
+            import.set_domain(domain.to_string());
+            import.set_version(*version);
+            proto.mut_opset_import().push(import);
+        }
+        let graph = proto.mut_graph();
+        graph.mut_input().push(value_info("x", &[Ok(1), Ok(2), Ok(3)]));
+        for (name, values) in consts {
+            graph.mut_input().push(value_info(name, &[Ok(values.len() as i64)]));
+            let mut init = TensorProto::new();
+            init.set_name(name.to_string());
+            init.set_data_type(TensorProto_DataType::INT64);
+            init.set_dims(vec![values.len() as i64]);
+            init.set_int64_data(values.to_vec());
+            graph.mut_initializer().push(init);
+        }
+        graph.mut_output().push(value_info("y", &[]));
+        graph.mut_node().push(node);
+        let model = crate::onnx().model_for_proto_model(&proto)?.into_typed()?.declutter()?;
+        let x = ndarray::Array::from_shape_fn((1, 2, 3), |(_, i, j)| (i * 3 + j) as f32);
+        Ok(SimplePlan::new(&model)?.run(tvec!(x.into()))?.remove(0))
+    }
+
+    fn softmax(xs: &[f32]) -> Vec<f32> {
+        let sum: f32 = xs.iter().map(|x| x.exp()).sum();
+        xs.iter().map(|x| x.exp() / sum).collect()
+    }
+
+    #[test]
+    fn softmax_axis_follows_opset() {
+        let xs = [0f32, 1.0, 2.0, 3.0, 4.0, 5.0];
+        // up to opset 12, the input is flattened from axis 1 on
+        let found = run_at(&[("", 11)], node("Softmax", &["x"], &[]), &[]).unwrap();
+        let expected =
+            Tensor::from(ndarray::Array::from_shape_vec((1, 2, 3), softmax(&xs)).unwrap());
+        assert!(found.close_enough(&expected, true));
+        // from opset 13, only the last axis is normalized
+        let found = run_at(&[("", 13)], node("Softmax", &["x"], &[]), &[]).unwrap();
+        let rows = [softmax(&xs[..3]), softmax(&xs[3..])].concat();
+        let expected = Tensor::from(ndarray::Array::from_shape_vec((1, 2, 3), rows).unwrap());
+        assert!(found.close_enough(&expected, true));
+    }
+
+    #[test]
+    fn squeeze_axes_follow_opset() {
+        let expected = Tensor::from(ndarray::arr2(&[[0f32, 1.0, 2.0], [3.0, 4.0, 5.0]]));
+        let attr = node("Squeeze", &["x"], &[("axes", &[0])]);
+        assert_eq!(*run_at(&[("", 11)], attr, &[]).unwrap(), expected);
+        let input = node("Squeeze", &["x", "axes"], &[]);
+        assert_eq!(*run_at(&[("", 13)], input, &[("axes", &[-3])]).unwrap(), expected);
+    }
+
+    #[test]
+    fn slice_bounds_follow_opset() {
+        let expected = Tensor::from(ndarray::arr3(&[[[1f32, 2.0], [4.0, 5.0]]]));
+        let attrs = &[("starts", &[1][..]), ("ends", &[3][..]), ("axes", &[2][..])];
+        let attr = node("Slice", &["x"], attrs);
+        assert_eq!(*run_at(&[("", 9)], attr, &[]).unwrap(), expected);
+        let input = node("Slice", &["x", "starts", "ends", "axes"], &[]);
+        assert_eq!(*run_at(&[("", 10)], input, attrs).unwrap(), expected);
+    }
+
+    #[test]
+    fn opset_of_default_domain() {
+        let xs = [0f32, 1.0, 2.0, 3.0, 4.0, 5.0];
+        let rows = [softmax(&xs[..3]), softmax(&xs[3..])].concat();
+        let expected = Tensor::from(ndarray::Array::from_shape_vec((1, 2, 3), rows).unwrap());
+        let opsets = [("ai.onnx.ml", 2), ("ai.onnx", 13)];
+        let found = run_at(&opsets, node("Softmax", &["x"], &[]), &[]).unwrap();
+        assert!(found.close_enough(&expected, true));
+        let e = run_at(&[("", 11), ("ai.onnx", 13)], node("Softmax", &["x"], &[]), &[]);
+        assert!(e.unwrap_err().to_string().contains("opset versions 11 and 13"));
+    }
+
+    /// y = Reshape(MatMul(x, w) + c, shape), in f64 but for the int64 shape:
+    /// w is stored raw, c is a Constant node.
+    fn f64_model() -> ModelProto {
+        let mut proto = ModelProto::new();
+        let graph = proto.mut_graph();
+        let typed = |name: &str, dt: TensorProto_DataType, dims: &[Result<i64, &str>]| {
+            let mut info = value_info(name, dims);
+            info.mut_field_type().mut_tensor_type().set_elem_type(dt);
+            info
+        };
+        graph.mut_input().push(typed("x", TensorProto_DataType::DOUBLE, &[Ok(1), Ok(3)]));
+        graph.mut_input().push(typed("w", TensorProto_DataType::DOUBLE, &[Ok(3), Ok(4)]));
+        graph.mut_input().push(typed("shape", TensorProto_DataType::INT64, &[Ok(2)]));
+        graph.mut_output().push(typed("y", TensorProto_DataType::DOUBLE, &[Ok(2), Ok(2)]));
+        let w: Vec<f64> = (0..12).map(|i| 1.0 / (i + 3) as f64).collect();
+        let mut init = TensorProto::new();
+        init.set_name("w".to_string());
+        init.set_data_type(TensorProto_DataType::DOUBLE);
+        init.set_dims(vec![3, 4]);
+        init.set_raw_data(w.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect());
+        graph.mut_initializer().push(init);
+        let mut shape = TensorProto::new();
+        shape.set_name("shape".to_string());
+        shape.set_data_type(TensorProto_DataType::INT64);
+        shape.set_dims(vec![2]);
+        shape.set_int64_data(vec![2, 2]);
+        graph.mut_initializer().push(shape);
+        let mut c = node("Constant", &[], &[]);
+        c.set_output(vec!["c".to_string()].into());
+        let mut value = AttributeProto::new();
+        value.set_name("value".to_string());
+        value.set_field_type(AttributeProto_AttributeType::TENSOR);
+        value.mut_t().set_data_type(TensorProto_DataType::DOUBLE);
+        value.mut_t().set_dims(vec![4]);
+        value.mut_t().set_double_data(vec![0.1, -0.2, 1e-9, 3.0]);
+        c.mut_attribute().push(value);
+        graph.mut_node().push(c);
+        let mut matmul = node("MatMul", &["x", "w"], &[]);
+        matmul.set_output(vec!["xw".to_string()].into());
+        graph.mut_node().push(matmul);
+        let mut add = node("Add", &["xw", "c"], &[]);
+        add.set_output(vec!["sum".to_string()].into());
+        graph.mut_node().push(add);
+        graph.mut_node().push(node("Reshape", &["sum", "shape"], &[]));
+        proto
+    }
+
+    #[test]
+    fn f64_downcast() {
+        let proto = f64_model();
+        let run = |model: InferenceModel, x: Tensor| {
+            let model = model.into_typed().unwrap().declutter().unwrap();
+            SimplePlan::new(&model).unwrap().run(tvec!(x)).unwrap().remove(0)
+        };
+        let x = ndarray::arr2(&[[0.5f64, -1.0, 2.0]]);
+        let reference = run(crate::onnx().model_for_proto_model(&proto).unwrap(), x.clone().into());
+        assert_eq!(reference.datum_type(), DatumType::F64);
+
+        let onnx = crate::onnx().with_f64_downcast(true);
+        let model = onnx.model_for_proto_model(&proto).unwrap();
+        assert_eq!(model.input_metadata().unwrap()[0].datum_type, Some(DatumType::F32));
+        let shape = model.node_by_name("shape").unwrap().id;
+        let shape = model.outlet_fact(OutletId::new(shape, 0)).unwrap();
+        assert_eq!(shape.value.concretize().unwrap(), rctensor1(&[2i64, 2]));
+        let found = run(model, x.mapv(|x| x as f32).into());
+        assert_eq!(found.datum_type(), DatumType::F32);
+        assert_eq!(found.shape(), &[2, 2]);
+        let reference = reference.cast_to::<f32>().unwrap();
+        assert!(found.close_enough(&reference, true));
+    }
+    /// y = Relu(Conv(x, w, b)), grouped and padded.
+    fn conv_model() -> ModelProto {
+        let mut proto = ModelProto::new();
+        let graph = proto.mut_graph();
+        graph.mut_input().push(value_info("x", &[Ok(1), Ok(4), Ok(6), Ok(5)]));
+        graph.mut_input().push(value_info("w", &[Ok(6), Ok(2), Ok(3), Ok(3)]));
+        graph.mut_input().push(value_info("b", &[Ok(6)]));
+        graph.mut_output().push(value_info("y", &[Ok(1), Ok(6), Ok(6), Ok(5)]));
+        let mut w = TensorProto::new();
+        w.set_name("w".to_string());
+        w.set_data_type(TensorProto_DataType::FLOAT);
+        w.set_dims(vec![6, 2, 3, 3]);
+        w.set_float_data((0..108).map(|i| ((i * 7) % 11) as f32 * 0.25 - 1.0).collect());
+        graph.mut_initializer().push(w);
+        let mut b = TensorProto::new();
+        b.set_name("b".to_string());
+        b.set_data_type(TensorProto_DataType::FLOAT);
+        b.set_dims(vec![6]);
+        b.set_float_data(vec![0.5, -1.0, 0.0, 2.0, -0.25, 1.5]);
+        graph.mut_initializer().push(b);
+        let mut conv = node("Conv", &["x", "w", "b"], &[("pads", &[1, 1, 1, 1])]);
+        conv.set_output(vec!["h".to_string()].into());
+        let mut group = AttributeProto::new();
+        group.set_name("group".to_string());
+        group.set_field_type(AttributeProto_AttributeType::INT);
+        group.set_i(2);
+        conv.mut_attribute().push(group);
+        graph.mut_node().push(conv);
+        graph.mut_node().push(relu("h", "y"));
+        proto
+    }
+
+    #[test]
+    fn saved_conv_runs_the_same() {
+        let model = crate::onnx().model_for_proto_model(&conv_model()).unwrap();
+        let model = model.into_typed().unwrap().declutter().unwrap();
+        let conv = model.nodes().iter().find_map(|n| n.op_as::<ConvUnary>()).unwrap();
+        assert_eq!(conv.group, 2);
+        assert!(conv.bias.is_some());
+        let mut buf = vec![];
+        model.save(&mut buf).unwrap();
+        let loaded = TypedModel::load(&mut &*buf).unwrap();
+        assert_eq!(loaded.input_metadata().unwrap(), model.input_metadata().unwrap());
+        assert_eq!(loaded.output_names(), vec!["y"]);
+        let x = ndarray::Array::from_shape_fn((1, 4, 6, 5), |(_, c, y, x)| {
+            ((c * 13 + y * 5 + x * 3) % 17) as f32 * 0.5 - 4.0
+        });
+        let run = |model: TypedModel| {
+            let plan = SimplePlan::new(model.into_optimized().unwrap()).unwrap();
+            plan.run(tvec!(x.clone().into())).unwrap().remove(0)
+        };
+        let expected = run(model);
+        assert!(expected.to_array_view::<f32>().unwrap().iter().any(|&y| y > 0.0));
+        assert_eq!(run(loaded), expected);
+    }
+}
@@ -1,4 +1,5 @@
 mod slice;
+mod squeeze;
 
 use std::convert::TryInto;
 
@@ -11,23 +12,47 @@ use crate::pb::NodeProto;
 use num_traits::AsPrimitive;
 
 pub fn register_all_ops(reg: &mut OnnxOpRegister) {
+    reg.insert("Compress", compress);
     reg.insert("Concat", concat);
+    reg.insert("ConcatFromSequence", concat_from_sequence);
     reg.insert("ConstantLike", constant_like);
     reg.insert("ConstantOfShape", constant_of_shape);
     reg.insert("Expand", |_| Ok(Box::new(tractops::array::MultiBroadcastTo::default())));
     reg.insert("EyeLike", eye_like);
     reg.insert("Flatten", flatten);
     reg.insert("Gather", gather);
+    reg.insert("GatherElements", gather_elements);
+    reg.insert("GatherND", gather_nd);
+    reg.insert("NonZero", |_| Ok(Box::new(tractops::array::NonZero)));
     reg.insert("Pad", pad);
     reg.insert("Reshape", |_| Ok(Box::new(tractops::array::Reshape::default())));
+    reg.insert("ReverseSequence", reverse_sequence);
+    reg.insert("SequenceAt", |_| Ok(Box::new(tractops::array::SequenceAt::default())));
+    reg.insert("SequenceConstruct", |_| {
+        Ok(Box::new(tractops::array::SequenceConstruct::default()))
+    });
+    reg.insert("SequenceErase", |_| Ok(Box::new(tractops::array::SequenceErase::default())));
+    reg.insert("SequenceInsert", |_| Ok(Box::new(tractops::array::SequenceInsert::default())));
+    reg.insert("SequenceLength", |_| Ok(Box::new(tractops::array::SequenceLength::default())));
     reg.insert("Shape", |_| Ok(Box::new(tractops::array::Shape::new(DatumType::I64))));
     reg.insert("Size", |_| Ok(Box::new(tractops::array::Size::new(DatumType::I64))));
     reg.insert("Transpose", transpose);
     reg.insert("Tile", |_| Ok(Box::new(tractops::array::Tile::default())));
+    reg.insert("Trilu", trilu);
     reg.insert("Slice", slice);
+    reg.insert_since("Slice", 10, |_| Ok(Box::new(slice::Slice10::default())));
     reg.insert("Split", split);
+    reg.insert("SplitToSequence", split_to_sequence);
     reg.insert("Squeeze", squeeze);
+    reg.insert_since("Squeeze", 13, squeeze_13);
+    reg.insert("Unique", unique);
     reg.insert("Unsqueeze", unsqueeze);
+    reg.insert_since("Unsqueeze", 13, |_| Ok(Box::new(squeeze::Unsqueeze13::default())));
+}
+
+pub fn compress(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?;
+    Ok(Box::new(tractops::array::Compress::new(axis)))
 }
 
 pub fn concat(node: &NodeProto) -> TractResult<Box<Op>> {
@@ -35,6 +60,12 @@ pub fn concat(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::array::Concat::new(axis)))
 }
 
+pub fn concat_from_sequence(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr("axis")?;
+    let new_axis = node.get_attr_opt("new_axis")?.unwrap_or(0i64) != 0;
+    Ok(Box::new(tractops::array::ConcatFromSequence::new(axis, new_axis)))
+}
+
 pub fn make_const<T>(shape: &[usize], v: f32) -> TractResult<Arc<Tensor>>
 where
     T: Copy + Datum,
@@ -97,6 +128,16 @@ pub fn gather(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::array::Gather::new(axis)))
 }
 
+pub fn gather_elements(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(0);
+    Ok(Box::new(tractops::array::GatherElements::new(axis)))
+}
+
+pub fn gather_nd(node: &NodeProto) -> TractResult<Box<Op>> {
+    let batch_dims = node.get_attr_opt("batch_dims")?.unwrap_or(0);
+    Ok(Box::new(tractops::array::GatherNd::new(batch_dims)))
+}
+
 pub fn pad(node: &NodeProto) -> TractResult<Box<Op>> {
     let value = node.get_attr_opt("value")?;
     let mode = match node.get_attr_opt("mode")? {
@@ -117,6 +158,12 @@ pub fn pad(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::array::Pad::new(pads, mode)))
 }
 
+pub fn reverse_sequence(node: &NodeProto) -> TractResult<Box<Op>> {
+    let batch_axis = node.get_attr_opt("batch_axis")?.unwrap_or(1);
+    let time_axis = node.get_attr_opt("time_axis")?.unwrap_or(0);
+    Ok(Box::new(tractops::array::ReverseSequence::new(batch_axis, time_axis)))
+}
+
 pub fn slice(node: &NodeProto) -> TractResult<Box<Op>> {
     let axes = node.get_attr_opt_vec("axes")?;
     let begin = node.get_attr_vec("starts")?;
@@ -130,16 +177,41 @@ pub fn split(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::array::Split::new(axis, node.get_output().len(), split)))
 }
 
+pub fn split_to_sequence(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(0);
+    let keepdims = node.get_attr_opt("keepdims")?.unwrap_or(1i64) != 0;
+    Ok(Box::new(tractops::array::SplitToSequence::new(axis, keepdims)))
+}
+
 pub fn squeeze(node: &NodeProto) -> TractResult<Box<Op>> {
     let axes = node.get_attr_opt_vec("axes")?;
     Ok(Box::new(tractops::array::Squeeze::new(axes)))
 }
 
+pub fn squeeze_13(node: &NodeProto) -> TractResult<Box<Op>> {
+    if node.get_input().len() == 2 {
+        Ok(Box::new(squeeze::Squeeze13::default()))
+    } else {
+        Ok(Box::new(tractops::array::Squeeze::new(None)))
+    }
+}
+
 pub fn transpose(node: &NodeProto) -> TractResult<Box<Op>> {
     let perm = node.get_attr_opt_vec("perm")?;
     Ok(Box::new(tractops::array::PermuteAxes::new(perm)))
 }
 
+pub fn trilu(node: &NodeProto) -> TractResult<Box<Op>> {
+    let upper = node.get_attr_opt("upper")?.unwrap_or(1i64) != 0;
+    Ok(Box::new(tractops::array::Trilu::new(upper)))
+}
+
+pub fn unique(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?;
+    let sorted = node.get_attr_opt("sorted")?.unwrap_or(1i64) != 0;
+    Ok(Box::new(tractops::array::Unique::new(axis, sorted, node.get_output().len())))
+}
+
 pub fn unsqueeze(node: &NodeProto) -> TractResult<Box<Op>> {
     let axes = node.get_attr_vec("axes")?;
     Ok(Box::new(tractops::array::AddDims::new(axes)))
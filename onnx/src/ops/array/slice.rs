@@ -20,6 +20,34 @@ impl Slice {
         }
         Ok(Tensor::from(input.to_owned()).into())
     }
+
+    /// The output dimension on `axis`, `d` being the input one.
+    fn output_dim(&self, axis: usize, d: TDim) -> TDim {
+        let spec = if let Some(axes) = self.axes.as_ref() {
+            if let Some(ix) = axes.iter().position(|&a| a == axis) {
+                Some((self.starts[ix], self.ends[ix]))
+            } else {
+                None
+            }
+        } else {
+            Some((self.starts[axis], self.ends[axis]))
+        };
+        if let Some((mut b, mut e)) = spec {
+            if let Ok(d) = d.to_integer() {
+                if b as i32 > d {
+                    b = d as isize;
+                }
+                if e as i32 > d {
+                    e = d as isize;
+                }
+            }
+            let b = if b < 0 { d + TDim::from(b) } else { TDim::from(b) };
+            let e = if e < 0 { d + TDim::from(e) } else { TDim::from(e) };
+            e - b
+        } else {
+            d
+        }
+    }
 }
 
 impl Op for Slice {
@@ -53,33 +81,92 @@ impl InferenceRulesOp for Slice {
         s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
         s.given(&inputs[0].shape, move |s, shape| {
             (0..shape.len()).try_for_each(move |axis| {
-                let d = shape[axis];
-                let spec = if let Some(axes) = self.axes.as_ref() {
-                    if let Some(ix) = axes.iter().position(|&a| a == axis) {
-                        Some((self.starts[ix], self.ends[ix]))
-                    } else {
-                        None
-                    }
-                } else {
-                    Some((self.starts[axis].into(), self.ends[axis].into()))
-                };
-                if let Some((mut b, mut e)) = spec {
-                    if let Ok(d) = d.to_integer() {
-                        if b as i32 > d {
-                            b = (d as isize).into();
-                        }
-                        if e as i32 > d {
-                            e = (d as isize).into();
-                        }
-                    }
-                    let b = if b < 0 { d.bex() + TDim::from(b) } else { TDim::from(b).bex() };
-                    let e = if e < 0 { d.bex() + TDim::from(e) } else { TDim::from(e).bex() };
-                    s.equals(&outputs[0].shape[axis], e - b)
-                } else {
-                    s.equals(&outputs[0].shape[axis], shape[axis])
-                }
+                s.equals(&outputs[0].shape[axis], self.output_dim(axis, shape[axis]))
             })
         })?;
         Ok(())
     }
 }
+
+/// Slice from opset 10 on: starts, ends, and optionally axes and steps are
+/// inputs. Only unit steps are supported.
+#[derive(Debug, Clone, Default)]
+pub struct Slice10;
+
+impl Slice10 {
+    /// The attribute-based Slice equivalent to the inputs following the
+    /// data, the data being of rank `rank`.
+    fn slice(params: &[Arc<Tensor>], rank: usize) -> TractResult<Slice> {
+        let ints = |t: &Tensor| -> TractResult<Vec<isize>> {
+            Ok(t.cast_to::<i64>()?.as_slice::<i64>()?.iter().map(|&i| i as isize).collect())
+        };
+        let axes = match params.get(2) {
+            Some(axes) => Some(
+                ints(axes)?
+                    .into_iter()
+                    .map(|a| normalize_axis("onnx.Slice10", a as i64, rank))
+                    .collect::<TractResult<Vec<usize>>>()?,
+            ),
+            None => None,
+        };
+        if let Some(steps) = params.get(3) {
+            if ints(steps)?.iter().any(|&step| step != 1) {
+                bail!("onnx.Slice10: only unit steps are supported");
+            }
+        }
+        Ok(Slice::new(axes, ints(&params[0])?, ints(&params[1])?))
+    }
+}
+
+impl Op for Slice10 {
+    fn name(&self) -> Cow<str> {
+        "onnx.Slice10".into()
+    }
+
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let inputs = model.node_input_facts(node.id)?;
+        let params: Option<Vec<Arc<Tensor>>> =
+            inputs[1..].iter().map(|i| i.konst.clone()).collect();
+        if let Some(params) = params {
+            let slice = Self::slice(&params, inputs[0].shape.rank())?;
+            return Ok(Some(TypedModelPatch::single_unary_op(model, node, slice)?));
+        }
+        Ok(None)
+    }
+}
+
+impl StatelessOp for Slice10 {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = inputs.remove(0);
+        let slice = Self::slice(&inputs, input.shape().len())?;
+        Ok(tvec!(dispatch_datum!(Slice::eval_t(input.datum_type())(&slice, input))?))
+    }
+}
+
+impl InferenceRulesOp for Slice10 {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() < 3 || inputs.len() > 5 {
+            bail!("Wrong input number. Rules expect 3 to 5, node has {}.", inputs.len())
+        }
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].rank, &outputs[0].rank)?;
+        s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
+        s.given_all(inputs[1..].iter().map(|i| &i.value), move |s, params| {
+            s.given(&inputs[0].shape, move |s, shape| {
+                let slice = Self::slice(&params, shape.len())?;
+                (0..shape.len()).try_for_each(|axis| {
+                    s.equals(&outputs[0].shape[axis], slice.output_dim(axis, shape[axis]))
+                })
+            })
+        })
+    }
+}
@@ -0,0 +1,127 @@
+use tract_core::internal::*;
+use tract_core::ops::array::{AddDims, RmDims, Squeeze};
+
+/// The axes given as an input, normalized against `rank`, in increasing
+/// order.
+fn axes(op: &str, axes: &Tensor, rank: usize) -> TractResult<Vec<usize>> {
+    let axes = axes.cast_to::<i64>()?;
+    let mut axes = axes
+        .to_array_view::<i64>()?
+        .iter()
+        .map(|&a| normalize_axis(op, a, rank))
+        .collect::<TractResult<Vec<usize>>>()?;
+    axes.sort();
+    if axes.windows(2).any(|w| w[0] == w[1]) {
+        bail!("{}: repeated axis in {:?}", op, axes);
+    }
+    Ok(axes)
+}
+
+/// Squeeze from opset 13 on, the axes being its second input.
+#[derive(Debug, Clone, Default)]
+pub struct Squeeze13;
+
+impl Op for Squeeze13 {
+    fn name(&self) -> Cow<str> {
+        "onnx.Squeeze13".into()
+    }
+
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let mut inputs = model.node_input_facts(node.id)?;
+        let (data, axes) = args_2!(inputs);
+        if let Some(ref axes) = axes.konst {
+            let axes = self::axes("onnx.Squeeze13", axes, data.shape.rank())?;
+            return Ok(Some(TypedModelPatch::single_unary_op(model, node, RmDims::new(axes))?));
+        }
+        Ok(None)
+    }
+}
+
+impl StatelessOp for Squeeze13 {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (data, axes) = args_2!(inputs);
+        let axes = self::axes("onnx.Squeeze13", &axes, data.shape().len())?;
+        Squeeze::new(Some(axes)).eval(tvec!(data))
+    }
+}
+
+impl InferenceRulesOp for Squeeze13 {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, &inputs[0].datum_type)?;
+        s.given_2(&inputs[0].shape, &inputs[1].value, move |s, mut shape, axes| {
+            let axes = self::axes("onnx.Squeeze13", &axes, shape.len())?;
+            for &axis in axes.iter().rev() {
+                if shape.remove(axis) != 1.to_dim() {
+                    bail!("onnx.Squeeze13: axis {} is not of dimension one", axis);
+                }
+            }
+            s.equals(&outputs[0].shape, ShapeFact::from(shape))
+        })
+    }
+}
+
+/// Unsqueeze from opset 13 on, the axes being its second input.
+#[derive(Debug, Clone, Default)]
+pub struct Unsqueeze13;
+
+impl Op for Unsqueeze13 {
+    fn name(&self) -> Cow<str> {
+        "onnx.Unsqueeze13".into()
+    }
+
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let mut inputs = model.node_input_facts(node.id)?;
+        let (data, axes) = args_2!(inputs);
+        if let Some(ref axes) = axes.konst {
+            let rank = data.shape.rank() + axes.shape().iter().product::<usize>();
+            let axes = self::axes("onnx.Unsqueeze13", axes, rank)?;
+            return Ok(Some(TypedModelPatch::single_unary_op(model, node, AddDims::new(axes))?));
+        }
+        Ok(None)
+    }
+}
+
+impl StatelessOp for Unsqueeze13 {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let (data, axes) = args_2!(inputs);
+        let rank = data.shape().len() + axes.shape().iter().product::<usize>();
+        let axes = self::axes("onnx.Unsqueeze13", &axes, rank)?;
+        AddDims::new(axes).eval(tvec!(data))
+    }
+}
+
+impl InferenceRulesOp for Unsqueeze13 {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 2)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&outputs[0].datum_type, &inputs[0].datum_type)?;
+        s.given_2(&inputs[0].shape, &inputs[1].value, move |s, mut shape, axes| {
+            let rank = shape.len() + axes.shape().iter().product::<usize>();
+            let axes = self::axes("onnx.Unsqueeze13", &axes, rank)?;
+            for &axis in &axes {
+                shape.insert(axis, 1.to_dim());
+            }
+            s.equals(&outputs[0].shape, ShapeFact::from(shape))
+        })
+    }
+}
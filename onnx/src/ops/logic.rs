@@ -6,8 +6,16 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("And", |_| Ok(Box::new(tractops::logic::And::default())));
     reg.insert("Or", |_| Ok(Box::new(tractops::logic::Or::default())));
     reg.insert("Xor", |_| Ok(Box::new(tractops::logic::Xor::default())));
+    reg.insert("BitwiseNot", |_| Ok(Box::new(tractops::logic::Not::default())));
+    reg.insert("BitwiseAnd", |_| Ok(Box::new(tractops::logic::And::default())));
+    reg.insert("BitwiseOr", |_| Ok(Box::new(tractops::logic::Or::default())));
+    reg.insert("BitwiseXor", |_| Ok(Box::new(tractops::logic::Xor::default())));
 
     reg.insert("Equal", |_| Ok(Box::new(tractops::logic::Equals::default())));
     reg.insert("Greater", |_| Ok(Box::new(tractops::logic::Greater::default())));
     reg.insert("Less", |_| Ok(Box::new(tractops::logic::Lesser::default())));
+    reg.insert("GreaterOrEqual", |_| Ok(Box::new(tractops::logic::GreaterEqual::default())));
+    reg.insert("LessOrEqual", |_| Ok(Box::new(tractops::logic::LesserEqual::default())));
+
+    reg.insert("Where", |_| Ok(Box::new(tractops::logic::Iff::default())));
 }
@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+
+use crate::model::OnnxOpRegister;
+use crate::pb::*;
+use tract_core::internal::*;
+
+pub fn register_all_ops(reg: &mut OnnxOpRegister) {
+    reg.insert("Loop", loop_);
+}
+
+/// The body gets the latest version of each op: builders do not know the
+/// opset of the graph.
+pub fn loop_(node: &NodeProto) -> TractResult<Box<Op>> {
+    let body: &GraphProto = node.get_attr("body")?;
+    let body = crate::onnx().model_for_graph(body, &HashMap::new())?;
+    let inputs = node.get_input();
+    let has_trip_count = inputs.get(0).map(|i| !i.is_empty()).unwrap_or(false);
+    let has_condition = inputs.get(1).map(|i| !i.is_empty()).unwrap_or(false);
+    Ok(Box::new(tract_core::ops::loops::Loop::new(body, has_trip_count, has_condition)))
+}
@@ -9,6 +9,7 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("Sub", |_| Ok(Box::new(tractops::math::Sub::default())));
     reg.insert("Mul", |_| Ok(Box::new(tractops::math::Mul::default())));
     reg.insert("Div", |_| Ok(Box::new(tractops::math::Div::default())));
+    reg.insert("Mod", rem);
 
     reg.insert("Sum", |_| Ok(Box::new(tractops::math::AddN::default())));
     reg.insert("Max", |_| Ok(Box::new(tractops::math::MaxN::default())));
@@ -18,6 +19,7 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("Abs", |_| Ok(Box::new(tractops::math::Abs::default())));
     reg.insert("Ceil", |_| Ok(Box::new(tractops::math::Ceil::default())));
     reg.insert("Floor", |_| Ok(Box::new(tractops::math::Floor::default())));
+    reg.insert("Round", |_| Ok(Box::new(tractops::math::Round::default())));
     reg.insert("Clip", clip);
 
     reg.insert("Cos", |_| Ok(Box::new(tractops::math::Cos::default())));
@@ -40,14 +42,49 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("Rsqrt", |_| Ok(Box::new(tractops::math::Rsqrt::default())));
 
     reg.insert("IsNaN", |_| Ok(Box::new(tractops::math::IsNan::default())));
+    reg.insert("IsInf", is_inf);
     reg.insert("Neg", |_| Ok(Box::new(tractops::math::Neg::default())));
     reg.insert("Sign", |_| Ok(Box::new(tractops::math::Sign::default())));
     reg.insert("Reciprocal", |_| Ok(Box::new(tractops::math::Recip::default())));
 
     reg.insert("Pow", |_| Ok(Box::new(tractops::math::Pow::default())));
+    reg.insert("BitShift", bit_shift);
 
+    reg.insert("Det", |_| Ok(Box::new(tractops::math::Det::default())));
     reg.insert("MatMul", |_| Ok(Box::new(tractops::math::MatMul::default())));
     reg.insert("Gemm", gemm);
+    reg.insert("Einsum", einsum);
+}
+
+pub fn einsum(node: &NodeProto) -> TractResult<Box<Op>> {
+    let equation: &str = node.get_attr("equation")?;
+    Ok(Box::new(tractops::math::Einsum::new(tractops::math::EinsumExpr::parse(equation)?)))
+}
+
+pub fn rem(node: &NodeProto) -> TractResult<Box<Op>> {
+    if node.get_attr_opt("fmod")?.unwrap_or(0i64) == 1 {
+        Ok(Box::new(tractops::math::Rem::default()))
+    } else {
+        Ok(Box::new(tractops::math::FloorMod::default()))
+    }
+}
+
+pub fn is_inf(node: &NodeProto) -> TractResult<Box<Op>> {
+    let detect_positive = node.get_attr_opt("detect_positive")?.unwrap_or(true);
+    let detect_negative = node.get_attr_opt("detect_negative")?.unwrap_or(true);
+    Ok(Box::new(tractops::math::IsInf::new(detect_positive, detect_negative)))
+}
+
+pub fn bit_shift(node: &NodeProto) -> TractResult<Box<Op>> {
+    let direction: &str = node.get_attr("direction")?;
+    node.check_value(
+        "direction",
+        match direction {
+            "LEFT" => Ok(Box::new(tractops::math::ShiftLeft::default()) as Box<Op>),
+            "RIGHT" => Ok(Box::new(tractops::math::ShiftRight::default()) as Box<Op>),
+            _ => Err(direction),
+        },
+    )
 }
 
 pub fn clip(node: &NodeProto) -> TractResult<Box<Op>> {
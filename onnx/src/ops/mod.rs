@@ -1,24 +1,30 @@
-use std::convert::TryInto;
 use crate::model::OnnxOpRegister;
 use crate::pb;
 use crate::pb::NodeProto;
+use std::convert::TryInto;
 use tract_core::internal::*;
 
 mod array;
 mod logic;
+mod loops;
 mod math;
 mod nn;
+mod random;
 pub mod rec;
+mod signal;
 
 pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("Cast", cast);
     reg.insert("Constant", konst);
     reg.insert("Identity", |_| Ok(Box::new(::tract_core::ops::identity::Identity::default())));
     logic::register_all_ops(reg);
+    loops::register_all_ops(reg);
     math::register_all_ops(reg);
     nn::register_all_ops(reg);
     array::register_all_ops(reg);
+    random::register_all_ops(reg);
     rec::register_all_ops(reg);
+    signal::register_all_ops(reg);
 }
 
 fn konst(node: &NodeProto) -> TractResult<Box<Op>> {
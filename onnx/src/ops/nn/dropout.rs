@@ -2,24 +2,43 @@ use ndarray::*;
 use tract_core::internal::*;
 use tract_core::ops::identity::Identity;
 
+/// Dropout at inference: the input passes through, and the optional mask
+/// output is all true.
 #[derive(Debug, Clone, new, Default)]
-pub struct Dropout;
+pub struct Dropout {
+    /// The node has a second, mask, output.
+    output_mask: bool,
+}
 
 impl Op for Dropout {
     fn name(&self) -> Cow<str> {
         "onnx.Dropout".into()
     }
 
+    /// Replace by an identity, and the mask by a constant if it is read.
     fn declutter(
         &self,
         model: &TypedModel,
         node: &TypedNode,
     ) -> TractResult<Option<TypedModelPatch>> {
-        if node.outputs.len() == 1 || node.outputs[1].successors.len() == 0 {
-            Ok(Some(TypedModelPatch::single_unary_op(model, node, Identity)?))
+        let mask = if self.output_mask && node.outputs[1].successors.len() > 0 {
+            let shape = node.outputs[1].fact.shape.as_finite().map(|s| s.to_vec());
+            match shape {
+                Some(shape) => Some(ArrayD::from_elem(shape, true)),
+                None => return Ok(None),
+            }
         } else {
-            Ok(None)
+            None
+        };
+        let mut patch = TypedModelPatch::default();
+        patch.tap_model(model, node.inputs[0])?;
+        let id = patch.chain(&*node.name, Identity, tvec!(node.outputs[0].fact.clone()))?;
+        patch.shunt_outside(OutletId::new(node.id, 0), OutletId::new(id, 0))?;
+        if let Some(mask) = mask {
+            let id = patch.add_const(format!("{}.mask", node.name), mask)?;
+            patch.shunt_outside(OutletId::new(node.id, 1), OutletId::new(id, 0))?;
         }
+        Ok(Some(patch))
     }
 }
 
@@ -27,8 +46,12 @@ impl StatelessOp for Dropout {
     /// Evaluates the operation given the input tensors.
     fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
         let input = args_1!(inputs);
-        let mask = ArrayD::from_elem(input.shape(), true);
-        Ok(tvec!(input, mask.into_arc_tensor()))
+        if self.output_mask {
+            let mask = ArrayD::from_elem(input.shape(), true);
+            Ok(tvec!(input, mask.into_arc_tensor()))
+        } else {
+            Ok(tvec!(input))
+        }
     }
 }
 
@@ -40,15 +63,63 @@ impl InferenceRulesOp for Dropout {
         outputs: &'p [TensorProxy],
     ) -> InferenceResult {
         check_input_arity(&inputs, 1)?;
-        if outputs.len() > 2 || outputs.len() == 0 {
-            bail!("Dropout shoud have 1 or 2 outputs, found {}", outputs.len());
-        }
+        check_output_arity(&outputs, 1 + self.output_mask as usize)?;
         s.equals(&inputs[0].datum_type, &outputs[0].datum_type)?;
         s.equals(&inputs[0].shape, &outputs[0].shape)?;
-        if outputs.len() == 2 {
+        if self.output_mask {
             s.equals(&outputs[1].datum_type, bool::datum_type())?;
             s.equals(&inputs[0].shape, &outputs[1].shape)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tract_core::ops::logic::Iff;
+
+    /// x -> Dropout, its mask selecting between its output and -x if
+    /// `select`, its output only otherwise.
+    fn model(select: bool) -> TypedModel {
+        let mut model = InferenceModel::default();
+        let fact = TensorFact::dt_shape(f32::datum_type(), tvec!(2, 3));
+        let x = model.add_source("x", fact).unwrap();
+        let dropout =
+            model.add_node("dropout", Dropout::new(true), tvec!(TensorFact::default(); 2)).unwrap();
+        model.add_edge(OutletId::new(x, 0), InletId::new(dropout, 0)).unwrap();
+        if select {
+            let neg = model.add_node_default("neg", tract_core::ops::math::Neg::default()).unwrap();
+            model.add_edge(OutletId::new(x, 0), InletId::new(neg, 0)).unwrap();
+            let iff = model.add_node_default("where", Iff::default()).unwrap();
+            model.add_edge(OutletId::new(dropout, 1), InletId::new(iff, 0)).unwrap();
+            model.add_edge(OutletId::new(dropout, 0), InletId::new(iff, 1)).unwrap();
+            model.add_edge(OutletId::new(neg, 0), InletId::new(iff, 2)).unwrap();
+            model.set_output_outlets(&[OutletId::new(iff, 0)]).unwrap();
+        } else {
+            model.set_output_outlets(&[OutletId::new(dropout, 0)]).unwrap();
+        }
+        model.into_typed().unwrap()
+    }
+
+    fn check(model: TypedModel) {
+        let x = Array2::from_shape_fn((2, 3), |(y, x)| (y * 3 + x) as f32 - 2.0);
+        let expected = x.clone().into_arc_tensor();
+        let output = SimplePlan::new(&model).unwrap().run(tvec!(x.clone().into())).unwrap();
+        assert_eq!(output[0], expected);
+        let model = model.declutter().unwrap();
+        assert!(model.nodes().iter().all(|n| !n.op_is::<Dropout>()));
+        let output = SimplePlan::new(&model).unwrap().run(tvec!(x.into())).unwrap();
+        assert_eq!(output[0], expected);
+    }
+
+    #[test]
+    fn mask_feeding_where() {
+        check(model(true));
+    }
+
+    #[test]
+    fn dead_mask() {
+        check(model(false));
+    }
+}
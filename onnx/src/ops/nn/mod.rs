@@ -26,17 +26,22 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("AveragePool", average_pool);
     reg.insert("BatchNormalization", batch_normalization);
     reg.insert("Conv", conv);
-    reg.insert("Dropout", |_| Ok(Box::new(dropout::Dropout)));
+    reg.insert("DepthToSpace", depth_to_space);
+    reg.insert("Dropout", |node| Ok(Box::new(dropout::Dropout::new(node.get_output().len() == 2))));
     reg.insert("Elu", elu);
     reg.insert("GlobalAveragePool", |_| Ok(Box::new(tractops::nn::GlobalAvgPool::default())));
     reg.insert("GlobalLpPool", global_lp_pool);
     reg.insert("GlobalMaxPool", |_| Ok(Box::new(tractops::nn::GlobalMaxPool::default())));
+    reg.insert("GridSample", grid_sample);
     reg.insert("Hardmax", layer_hard_max);
+    reg.insert_since("Hardmax", 13, hard_max);
     reg.insert("HardSigmoid", hard_sigmoid);
     reg.insert("LeakyRelu", leaky_relu);
     reg.insert("LogSoftmax", layer_log_soft_max);
+    reg.insert_since("LogSoftmax", 13, log_soft_max);
     reg.insert("LRN", lrn);
     reg.insert("MaxPool", max_pool);
+    reg.insert("MeanVarianceNormalization", mean_variance_normalization);
     reg.insert("ParametricSoftplus", parametric_softplus);
     reg.insert("PRelu", |_| Ok(Box::new(Prelu::default())));
     reg.insert("ReduceL1", reduce!(L1));
@@ -56,20 +61,31 @@ pub fn register_all_ops(reg: &mut OnnxOpRegister) {
     reg.insert("Selu", selu);
     reg.insert("Sigmoid", |_| Ok(Box::new(tractops::nn::Sigmoid::default())));
     reg.insert("Softmax", layer_soft_max);
+    reg.insert_since("Softmax", 13, soft_max);
     reg.insert("Softplus", |_| Ok(Box::new(tractops::nn::Softplus::default())));
     reg.insert("Softsign", |_| Ok(Box::new(tractops::nn::Softsign::default())));
 }
 
+/// ONNX padding: `pads` (begins then ends) or `auto_pad`, the two being
+/// exclusive. SAME_UPPER puts the odd extra padding at the end, SAME_LOWER at
+/// the beginning.
 fn pad(node: &NodeProto) -> TractResult<PaddingSpec> {
+    let auto_pad: Option<&str> = node.get_attr_opt("auto_pad")?;
     if let Some(pads) = node.get_attr_opt_tvec("pads")? {
+        if auto_pad.map(|s| s != "NOTSET").unwrap_or(false) {
+            return node
+                .bail_attr("pads", "can not be combined with an auto_pad other than NOTSET");
+        }
         let len = pads.len();
+        if len % 2 != 0 {
+            return node.bail_attr("pads", "expected as many begin and end values");
+        }
         return Ok(PaddingSpec::Explicit(
             pads.iter().cloned().take(len / 2).collect(),
             pads.iter().cloned().skip(len / 2).collect(),
         ));
     }
-    Ok(node
-        .get_attr_opt("auto_pad")?
+    Ok(auto_pad
         .and_try(|s| {
             node.check_value(
                 "auto_pad",
@@ -121,6 +137,21 @@ pub fn conv(node: &NodeProto) -> TractResult<Box<Op>> {
     )))
 }
 
+pub fn depth_to_space(node: &NodeProto) -> TractResult<Box<Op>> {
+    use tractops::nn::DepthToSpaceMode;
+    let block_size = node.get_attr("blocksize")?;
+    let mode: &str = node.get_attr_opt("mode")?.unwrap_or("DCR");
+    let mode = node.check_value(
+        "mode",
+        match mode {
+            "DCR" => Ok(DepthToSpaceMode::DCR),
+            "CRD" => Ok(DepthToSpaceMode::CRD),
+            _ => Err(mode),
+        },
+    )?;
+    Ok(Box::new(tractops::nn::DepthToSpace::new(block_size, mode, DataFormat::NCHW)))
+}
+
 pub fn average_pool(node: &NodeProto) -> TractResult<Box<Op>> {
     let kernel_shape = node.get_attr_tvec("kernel_shape")?;
     let pad = pad(node)?;
@@ -142,6 +173,32 @@ pub fn global_lp_pool(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::nn::GlobalLpPool::new(p)))
 }
 
+pub fn grid_sample(node: &NodeProto) -> TractResult<Box<Op>> {
+    use tractops::nn::{GridSampleMode, GridSamplePadding};
+    let mode: &str = node.get_attr_opt("mode")?.unwrap_or("bilinear");
+    let mode = node.check_value(
+        "mode",
+        match mode {
+            // renamed in opset 20
+            "bilinear" | "linear" => Ok(GridSampleMode::Bilinear),
+            "nearest" => Ok(GridSampleMode::Nearest),
+            _ => Err(mode),
+        },
+    )?;
+    let padding_mode: &str = node.get_attr_opt("padding_mode")?.unwrap_or("zeros");
+    let padding_mode = node.check_value(
+        "padding_mode",
+        match padding_mode {
+            "zeros" => Ok(GridSamplePadding::Zeros),
+            "border" => Ok(GridSamplePadding::Border),
+            "reflection" => Ok(GridSamplePadding::Reflection),
+            _ => Err(padding_mode),
+        },
+    )?;
+    let align_corners = node.get_attr_opt("align_corners")?.unwrap_or(false);
+    Ok(Box::new(tractops::nn::GridSample::new(mode, padding_mode, align_corners)))
+}
+
 pub fn hard_sigmoid(node: &NodeProto) -> TractResult<Box<Op>> {
     let alpha = node.get_attr_opt("alpha")?.unwrap_or(0.2);
     let beta = node.get_attr_opt("beta")?.unwrap_or(0.5);
@@ -163,6 +220,24 @@ pub fn layer_soft_max(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::nn::LayerSoftmax::new(axis)))
 }
 
+// From opset 13, Hardmax, LogSoftmax and Softmax normalize over their axis
+// only, the last one by default.
+
+pub fn hard_max(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(-1);
+    Ok(Box::new(tractops::nn::LayerHardmax::single_axis(axis)))
+}
+
+pub fn log_soft_max(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(-1);
+    Ok(Box::new(tractops::nn::LayerLogSoftmax::single_axis(axis)))
+}
+
+pub fn soft_max(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(-1);
+    Ok(Box::new(tractops::nn::LayerSoftmax::single_axis(axis)))
+}
+
 pub fn leaky_relu(node: &NodeProto) -> TractResult<Box<Op>> {
     let alpha = node.get_attr_opt("alpha")?.unwrap_or(0.01);
     Ok(Box::new(tractops::nn::LeakyRelu::new(alpha)))
@@ -186,6 +261,11 @@ pub fn max_pool(node: &NodeProto) -> TractResult<Box<Op>> {
     )))
 }
 
+pub fn mean_variance_normalization(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axes = node.get_attr_opt_tvec("axes")?.unwrap_or(tvec!(0, 2, 3));
+    Ok(Box::new(tractops::nn::MeanVarianceNorm::new(axes, 1e-9)))
+}
+
 pub fn parametric_softplus(node: &NodeProto) -> TractResult<Box<Op>> {
     let alpha = node.get_attr("alpha")?;
     let beta = node.get_attr("beta")?;
@@ -207,19 +287,10 @@ pub fn scaled_tanh(node: &NodeProto) -> TractResult<Box<Op>> {
     Ok(Box::new(tractops::nn::ScaledTanh::new(alpha, beta)))
 }
 
-element_map_with_params!(Shrink, [f16, f32, f64], { bias: f32, lambd: f32 },
-    fn eval_one<T>(s: &Shrink, x:T) -> T
-    where T: Datum+::num_traits::Float, f32: ::num_traits::AsPrimitive<T>
-    {
-        use num_traits::AsPrimitive;
-        if x < -s.lambd.as_() { x + s.bias.as_() } else if x > s.lambd.as_() { x - s.bias.as_() } else { T::zero() }
-    }
-);
-
 pub fn shrink(node: &NodeProto) -> TractResult<Box<Op>> {
     let bias = node.get_attr_opt("bias")?.unwrap_or(0.0);
     let lambd = node.get_attr_opt("lambd")?.unwrap_or(0.5);
-    Ok(Box::new(Shrink::new(bias, lambd)))
+    Ok(Box::new(tractops::nn::Shrink::new(bias, lambd)))
 }
 
 pub fn selu(node: &NodeProto) -> TractResult<Box<Op>> {
@@ -0,0 +1,43 @@
+use std::convert::TryInto;
+
+use crate::model::OnnxOpRegister;
+use crate::pb;
+use crate::pb::NodeProto;
+use tract_core::internal::*;
+use tract_core::ops::random::{Distribution, Random};
+
+pub fn register_all_ops(reg: &mut OnnxOpRegister) {
+    reg.insert("RandomNormal", |node| random(node, normal(node)?, true));
+    reg.insert("RandomNormalLike", |node| random(node, normal(node)?, false));
+    reg.insert("RandomUniform", |node| random(node, uniform(node)?, true));
+    reg.insert("RandomUniformLike", |node| random(node, uniform(node)?, false));
+}
+
+fn normal(node: &NodeProto) -> TractResult<Distribution> {
+    let mean = node.get_attr_opt("mean")?.unwrap_or(0.0);
+    let scale = node.get_attr_opt("scale")?.unwrap_or(1.0);
+    Ok(Distribution::Normal { mean, scale })
+}
+
+fn uniform(node: &NodeProto) -> TractResult<Distribution> {
+    let low = node.get_attr_opt("low")?.unwrap_or(0.0);
+    let high = node.get_attr_opt("high")?.unwrap_or(1.0);
+    Ok(Distribution::Uniform { low, high })
+}
+
+fn random(node: &NodeProto, distribution: Distribution, with_shape: bool) -> TractResult<Box<Op>> {
+    use protobuf::ProtobufEnum;
+    let dt = match node.get_attr_opt("dtype")? {
+        Some(dt) => Some(
+            pb::TensorProto_DataType::from_i32(dt)
+                .ok_or_else(|| {
+                    format!("Can not convert integer {} into a TensorProto_DataType", dt)
+                })?
+                .try_into()?,
+        ),
+        None => None,
+    };
+    let shape = if with_shape { Some(node.get_attr_tvec("shape")?) } else { None };
+    let seed = node.get_attr_opt::<f32>("seed")?.map(|s| s.to_bits() as u64);
+    Ok(Box::new(Random::new(distribution, dt, shape, seed)))
+}
@@ -0,0 +1,39 @@
+use std::convert::TryInto;
+
+use crate::model::OnnxOpRegister;
+use crate::pb;
+use crate::pb::NodeProto;
+use tract_core::internal::*;
+use tract_core::ops::signal::{Dft, GenerateWindow, Stft, WindowKind};
+
+pub fn register_all_ops(reg: &mut OnnxOpRegister) {
+    reg.insert("BlackmanWindow", |node| window(node, WindowKind::Blackman));
+    reg.insert("DFT", dft);
+    reg.insert("HammingWindow", |node| window(node, WindowKind::Hamming));
+    reg.insert("HannWindow", |node| window(node, WindowKind::Hann));
+    reg.insert("STFT", stft);
+}
+
+fn window(node: &NodeProto, kind: WindowKind) -> TractResult<Box<Op>> {
+    use protobuf::ProtobufEnum;
+    let dt = node.get_attr_opt("output_datatype")?.unwrap_or(1);
+    let dt = pb::TensorProto_DataType::from_i32(dt)
+        .ok_or_else(|| format!("Can not convert integer {} into a TensorProto_DataType", dt))?
+        .try_into()?;
+    let periodic = node.get_attr_opt("periodic")?.unwrap_or(1i64) == 1;
+    Ok(Box::new(GenerateWindow::new(kind, periodic, dt)))
+}
+
+fn dft(node: &NodeProto) -> TractResult<Box<Op>> {
+    let axis = node.get_attr_opt("axis")?.unwrap_or(1);
+    let inverse = node.get_attr_opt("inverse")?.unwrap_or(0i64) == 1;
+    let onesided = node.get_attr_opt("onesided")?.unwrap_or(0i64) == 1;
+    Ok(Box::new(Dft::new(axis, inverse, onesided)))
+}
+
+fn stft(node: &NodeProto) -> TractResult<Box<Op>> {
+    let onesided = node.get_attr_opt("onesided")?.unwrap_or(1i64) == 1;
+    // optional inputs left out are dropped, the others packed
+    let present = |ix: usize| node.get_input().get(ix).map(|i| !i.is_empty()).unwrap_or(false);
+    Ok(Box::new(Stft::new(onesided, present(2), present(3))))
+}
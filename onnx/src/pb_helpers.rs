@@ -103,6 +103,13 @@ impl<'a> AttrScalarType<'a> for Tensor {
     }
 }
 
+impl<'a> AttrScalarType<'a> for &'a GraphProto {
+    fn get_attr_opt_scalar(node: &'a NodeProto, name: &str) -> TractResult<Option<Self>> {
+        node.get_attr_opt_with_type(name, AttributeProto_AttributeType::GRAPH)?
+            .and_ok(AttributeProto::get_g)
+    }
+}
+
 impl<'a> AttrScalarType<'a> for &'a [u8] {
     fn get_attr_opt_scalar(node: &'a NodeProto, name: &str) -> TractResult<Option<Self>> {
         node.get_attr_opt_with_type(name, AttributeProto_AttributeType::STRING)?
@@ -1,7 +1,7 @@
 use crate::pb::*;
+use std::convert::{TryFrom, TryInto};
 use tract_core::internal::*;
 use tract_core::*;
-use std::convert::{ TryFrom, TryInto };
 
 impl TryFrom<TensorProto_DataType> for DatumType {
     type Error = TractError;
@@ -55,6 +55,7 @@ pub mod conform;
 
 pub mod model;
 pub mod ops;
+pub mod saved_model;
 pub mod tensor;
 pub mod tfpb;
 
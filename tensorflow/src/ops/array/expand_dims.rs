@@ -29,12 +29,16 @@ impl Op for ExpandDims {
         node: &TypedNode,
     ) -> TractResult<Option<TypedModelPatch>> {
         let mut inputs = model.node_input_facts(node.id)?;
-        let (_, dims) = args_2!(inputs);
+        let (data, dims) = args_2!(inputs);
         if let Some(ref dims) = dims.konst {
+            let rank = data.shape.rank();
             let dims = dims.cast_to::<i64>()?;
-            let op = ::tract_core::ops::array::AddDims::new(
-                dims.to_array_view::<i64>()?.iter().map(|&i| i as usize).collect(),
-            );
+            let dims = dims
+                .to_array_view::<i64>()?
+                .iter()
+                .map(|&d| normalize_insertion_axis("tf.ExpandDims", d, rank))
+                .collect::<TractResult<Vec<usize>>>()?;
+            let op = ::tract_core::ops::array::AddDims::new(dims);
             return Ok(Some(TypedModelPatch::single_unary_op(model, node, op)?));
         }
         Ok(None)
@@ -47,7 +51,7 @@ impl StatelessOp for ExpandDims {
         let dims = dims.to_array_view::<i32>()?;
         let mut shape: TVec<usize> = data.shape().into();
         for d in dims.iter() {
-            let d = if *d >= 0 { *d } else { *d + 1 + data.shape().len() as i32 } as usize;
+            let d = normalize_insertion_axis("tf.ExpandDims", *d as i64, data.shape().len())?;
             shape.insert(d, 1);
         }
         dispatch_copy!(Self::eval_t(data.datum_type())(self, data, &*shape))
@@ -71,11 +75,8 @@ impl InferenceRulesOp for ExpandDims {
         s.equals(&data.datum_type, &output.datum_type)?;
         s.equals(data.rank.bex() + 1, &output.rank)?;
         s.given_2(&dims.value, &data.rank, move |s, index, rank| {
-            let mut index = *(index.to_scalar::<i32>()?);
-            if index < 0 {
-                index += rank
-            }
-            let index = index as usize;
+            let index = *(index.to_scalar::<i32>()?) as i64;
+            let index = normalize_insertion_axis("tf.ExpandDims", index, rank as usize)?;
 
             for i in 0..index {
                 s.equals(&output.shape[i], &data.shape[i])?;
@@ -4,8 +4,22 @@ use tract_core::ops as tractops;
 use crate::model::TfOpRegister;
 
 pub fn register_all_ops(reg: &mut TfOpRegister) {
+    reg.insert("BitwiseAnd", |_| Ok(Box::new(tractops::logic::And::default())));
+    reg.insert("BitwiseOr", |_| Ok(Box::new(tractops::logic::Or::default())));
+    reg.insert("BitwiseXor", |_| Ok(Box::new(tractops::logic::Xor::default())));
+    reg.insert("Equal", with_T!(tractops::logic::Equals::Bin));
+    reg.insert("Greater", with_T!(tractops::logic::Greater::Bin));
+    reg.insert("GreaterEqual", with_T!(tractops::logic::GreaterEqual::Bin));
+    reg.insert("Invert", |_| Ok(Box::new(tractops::logic::Not::default())));
+    reg.insert("LeftShift", with_T!(tractops::math::ShiftLeft::Bin));
     reg.insert("Less", with_T!(tractops::logic::Lesser::Bin));
+    reg.insert("LessEqual", with_T!(tractops::logic::LesserEqual::Bin));
+    reg.insert("LogicalAnd", |_| Ok(Box::new(tractops::logic::And::default())));
+    reg.insert("LogicalNot", |_| Ok(Box::new(tractops::logic::Not::default())));
+    reg.insert("LogicalOr", |_| Ok(Box::new(tractops::logic::Or::default())));
     reg.insert("Merge", merge);
+    reg.insert("RightShift", with_T!(tractops::math::ShiftRight::Bin));
+    reg.insert("SelectV2", |_| Ok(Box::new(tractops::logic::Iff::default())));
     reg.insert("Switch", |_| Ok(Box::new(Switch)));
 }
 
@@ -13,7 +13,9 @@ pub fn register_all_ops(reg: &mut TfOpRegister) {
     reg.insert("BiasAdd", with_T!(tractops::math::Add::Bin));
     reg.insert("Ceil", with_T!(tractops::math::Ceil));
     reg.insert("Div", with_T!(tractops::math::Div::Bin));
-    reg.insert("FloorMod", with_T!(tractops::math::Rem::Bin));
+    reg.insert("Floor", with_T!(tractops::math::Floor));
+    reg.insert("FloorDiv", with_T!(tractops::math::FloorDiv::Bin));
+    reg.insert("FloorMod", with_T!(tractops::math::FloorMod::Bin));
     reg.insert("MatMul", mat_mul);
     reg.insert("Max", max::max);
     reg.insert("Maximum", with_T!(tractops::math::Max::Bin));
@@ -24,6 +26,7 @@ pub fn register_all_ops(reg: &mut TfOpRegister) {
     reg.insert("Pow", with_T!(tractops::math::Pow::Bin));
     reg.insert("Neg", with_T!(tractops::math::Neg));
     reg.insert("RealDiv", with_T!(tractops::math::Div::Bin));
+    reg.insert("Round", with_T!(tractops::math::Round));
     reg.insert("Rsqrt", with_T!(tractops::math::Rsqrt));
     reg.insert("Sub", with_T!(tractops::math::Sub::Bin));
     reg.insert("Tanh", with_T!(tractops::math::Tanh));
@@ -5,13 +5,14 @@ pub fn conv2d(pb: &crate::tfpb::node_def::NodeDef) -> TractResult<Box<Op>> {
     let data_format = super::data_format(pb)?;
     let padding = super::padding(pb)?;
     let strides = super::strides(pb)?;
+    let hw_axes = data_format.shape(&strides).hw_axes();
     Ok(Box::new(Conv::new(
         data_format,
         KernelFormat::HWIO,
         None,
         None,
         padding,
-        Some(strides[1..3].into()),
+        Some(strides[hw_axes].into()),
         1,
     )))
 }
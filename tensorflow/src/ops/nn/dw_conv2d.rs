@@ -7,9 +7,7 @@ pub fn depthwise_conv2d(pb: &crate::tfpb::node_def::NodeDef) -> TractResult<Box<
     let padding = super::padding(pb)?;
     let strides = super::strides(pb)?.into();
     let dilations: TVec<usize> = pb.get_attr_list_int("dilations")?.into();
-    if dilations.len() != 4 || dilations[0] != 1 && dilations[3] != 1 {
-        Err(format!("dilations must be of the form [1, h, v, 1], found {:?}", dilations))?
-    };
+    super::check_spatial_only("dilations", &dilations, data_format)?;
     Ok(Box::new(DepthwiseConv2d::new(data_format, padding, strides, dilations)))
 }
 
@@ -50,16 +48,19 @@ impl Op for DepthwiseConv2d {
         let output_dims = self.padding.compute(
             shape.hw_dims(),
             &ker[0..2],
-            &self.dilations[1..3],
-            &self.strides[1..3],
+            &self.dilations[shape.hw_axes()],
+            &self.strides[shape.hw_axes()],
         );
         let n_output_points: TDim = output_dims.iter().map(|d| d.output).product::<TDim>();
         let kernel_surface = ker[0] * ker[1];
         let out_channels = ker[2] * ker[3];
-        Ok(tvec!((
-            Cost::FMA(f32::datum_type()),
-            shape.n() * out_channels * n_output_points * kernel_surface
-        )))
+        Ok(tvec!(
+            (
+                Cost::FMA(f32::datum_type()),
+                shape.n() * out_channels * n_output_points * kernel_surface
+            ),
+            (Cost::Params(inputs[1].datum_type), ker.iter().product::<usize>().to_dim())
+        ))
     }
 
     fn declutter(
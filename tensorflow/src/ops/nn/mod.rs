@@ -1,6 +1,6 @@
 use tract_core::internal::*;
 use tract_core::ops::cnn::PaddingSpec;
-use tract_core::ops::nn::{DataFormat, LayerSoftmax};
+use tract_core::ops::nn::{DataFormat, DepthToSpace, DepthToSpaceMode, LayerSoftmax, Lrn};
 
 use crate::model::TfOpRegister;
 use crate::tfpb::node_def::NodeDef;
@@ -14,8 +14,10 @@ pub mod s2b;
 pub fn register_all_ops(reg: &mut TfOpRegister) {
     reg.insert("AvgPool", pools::avgpool);
     reg.insert("Conv2D", conv2d::conv2d);
+    reg.insert("DepthToSpace", depth_to_space);
     reg.insert("DepthwiseConv2dNative", dw_conv2d::depthwise_conv2d);
     reg.insert("FusedBatchNorm", fused_batch_norm::fused_batch_norm);
+    reg.insert("LRN", lrn);
     reg.insert("MaxPool", pools::maxpool);
     reg.insert("Relu", with_T!(::tract_core::ops::nn::Relu));
     reg.insert("Relu6", |_| Ok(Box::new(Relu6::default())));
@@ -25,16 +27,48 @@ pub fn register_all_ops(reg: &mut TfOpRegister) {
     reg.insert("BatchToSpaceND", s2b::batch_to_space_nd);
 }
 
+/// TensorFlow's LRN sums over `depth_radius` channels on each side, and does
+/// not divide alpha by the window size.
+pub fn lrn(pb: &NodeDef) -> TractResult<Box<Op>> {
+    let radius: usize = pb.get_attr_opt_int("depth_radius")?.unwrap_or(5);
+    let bias: f32 = pb.get_attr_opt_float("bias")?.unwrap_or(1.0);
+    let alpha: f32 = pb.get_attr_opt_float("alpha")?.unwrap_or(1.0);
+    let beta: f32 = pb.get_attr_opt_float("beta")?.unwrap_or(0.5);
+    let size = 2 * radius + 1;
+    let op = Lrn::new(alpha * size as f32, beta, bias, size);
+    Ok(Box::new(op.with_data_format(DataFormat::NHWC)))
+}
+
+pub fn depth_to_space(pb: &NodeDef) -> TractResult<Box<Op>> {
+    let block_size: usize = pb.get_attr_int("block_size")?;
+    let op = DepthToSpace::new(block_size, DepthToSpaceMode::DCR, data_format(pb)?);
+    Ok(Box::new(op))
+}
+
 element_map!(Relu6, [f32, i32], |x| x.max(0 as _).min(6 as _));
 
+/// Strides over the whole input, one per axis of the data format: the
+/// spatial ones may differ, the batch and channel ones must be 1.
 pub fn strides(pb: &NodeDef) -> TractResult<Vec<usize>> {
     let strides: Vec<usize> = pb.get_attr_list_int("strides")?;
-    if strides.len() != 4 || strides[0] != 1 && strides[3] != 1 {
-        Err(format!("strides must be of the form [1, h, v, 1], found {:?}", strides))?
-    };
+    check_spatial_only("strides", &strides, data_format(pb)?)?;
     Ok(strides)
 }
 
+/// Check a per-axis attribute only departs from 1 on the spatial axes.
+pub fn check_spatial_only(
+    name: &str,
+    values: &[usize],
+    data_format: DataFormat,
+) -> TractResult<()> {
+    let shape = data_format.shape(values);
+    if shape.rank() != 4 || values[shape.n_axis()] != 1 || values[shape.c_axis()] != 1 {
+        let form = if data_format == DataFormat::NHWC { "[1, h, w, 1]" } else { "[1, 1, h, w]" };
+        bail!("{} must be of the form {}, found {:?}", name, form, values)
+    }
+    Ok(())
+}
+
 pub fn data_format(pb: &NodeDef) -> TractResult<DataFormat> {
     let df = if pb.get_attr_opt_raw_str("data_format")?.unwrap_or(b"NHWC") == b"NHWC" {
         DataFormat::NHWC
@@ -30,9 +30,11 @@ impl Op for SpaceToBatchUnary {
     ) -> TractResult<Option<TypedModelPatch>> {
         if let Some(conv_node) = model.single_succ(node.id)? {
             if let Some(b2s_node) = model.single_succ(conv_node.id)? {
-                if let (Some(conv_op), Some(_)) =
-                    (conv_node.op_as::<ConvUnary>(), b2s_node.op_as::<BatchToSpaceUnary>())
-                {
+                // a golden output is checked in the batched layout
+                let conv_op = conv_node
+                    .op_as::<ConvUnary>()
+                    .filter(|c| c.golden.is_none() && c.independent_shape.is_empty());
+                if let (Some(conv_op), Some(_)) = (conv_op, b2s_node.op_as::<BatchToSpaceUnary>()) {
                     let op = ConvUnary {
                         data_format: conv_op.data_format,
                         kernel_fmt: conv_op.kernel_fmt,
@@ -44,6 +46,15 @@ impl Op for SpaceToBatchUnary {
                         full_input_shape: model.outlet_fact(node.inputs[0])?.shape.iter().collect(),
                         full_output_shape: b2s_node.outputs[0].fact.shape.iter().collect(),
                         group: conv_op.group,
+                        summary: None,
+                        pad_mode: conv_op.pad_mode,
+                        options: conv_op.options.clone(),
+                        f64_output: conv_op.f64_output,
+                        token_output: conv_op.token_output,
+                        independent_shape: tvec!(),
+                        golden: None,
+                        kernel_cache: Default::default(),
+                        timer: conv_op.timer.clone(),
                     };
                     let mut patch = TypedModelPatch::default();
                     patch.tap_model(&model, node.inputs[0])?;
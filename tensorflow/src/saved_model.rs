@@ -0,0 +1,638 @@
+//! Loading of SavedModel directories.
+//!
+//! The directory holds `saved_model.pb`, a SavedModel protobuf wrapping the
+//! graphs and their signatures, and a `variables/` checkpoint with the
+//! variable values. The variables are read from the checkpoint and turned
+//! into constants, so the loaded model is self-contained, like a frozen
+//! graph.
+//!
+//! Only the few fields of the SavedModel messages tract needs are decoded,
+//! the rest is skipped.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs;
+use std::path::Path;
+
+use protobuf::wire_format::WireType;
+use protobuf::{CodedInputStream, Message, ProtobufEnum};
+
+use crate::model::Tensorflow;
+use crate::tfpb::graph::GraphDef;
+use crate::tfpb::node_def::NodeDef;
+use crate::tfpb::tensor::TensorProto;
+use crate::tfpb::tensor_shape::TensorShapeProto;
+use crate::tfpb::types::DataType;
+use tract_core::internal::*;
+
+/// Tag of the meta graph meant for inference.
+const SERVE_TAG: &str = "serve";
+
+/// Signature picked when none is asked for and there are several.
+const DEFAULT_SIGNATURE: &str = "serving_default";
+
+/// Magic number ending a checkpoint index table.
+const TABLE_MAGIC: u64 = 0xdb4775248b80fb57;
+
+fn pb_error(e: protobuf::ProtobufError) -> TractError {
+    format!("{:?}", e).into()
+}
+
+/// Calls `f` with each field number, wire type and the stream positioned on
+/// the field value, which `f` must consume or skip.
+fn for_each_field(
+    bytes: &[u8],
+    mut f: impl FnMut(u32, WireType, &mut CodedInputStream) -> TractResult<bool>,
+) -> TractResult<()> {
+    let mut is = CodedInputStream::from_bytes(bytes);
+    while !is.eof().map_err(pb_error)? {
+        let (field, wire_type) = is.read_tag_unpack().map_err(pb_error)?;
+        if !f(field, wire_type, &mut is)? {
+            is.skip_field(wire_type).map_err(pb_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_bytes(is: &mut CodedInputStream) -> TractResult<Vec<u8>> {
+    is.read_bytes().map_err(pb_error)
+}
+
+fn read_string(is: &mut CodedInputStream) -> TractResult<String> {
+    is.read_string().map_err(pb_error)
+}
+
+/// Entries of a `map<string, Message>` field.
+fn map_entry(bytes: &[u8]) -> TractResult<(String, Vec<u8>)> {
+    let (mut key, mut value) = (String::new(), vec![]);
+    for_each_field(bytes, |field, _, is| {
+        match field {
+            1 => key = read_string(is)?,
+            2 => value = read_bytes(is)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    })?;
+    Ok((key, value))
+}
+
+/// Inputs and outputs of a model entry point, as tensor names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Signature {
+    pub inputs: BTreeMap<String, String>,
+    pub outputs: BTreeMap<String, String>,
+}
+
+impl Signature {
+    fn parse(bytes: &[u8]) -> TractResult<Signature> {
+        fn tensor_name(bytes: &[u8]) -> TractResult<String> {
+            let mut name = String::new();
+            for_each_field(bytes, |field, _, is| {
+                if field == 1 {
+                    name = read_string(is)?;
+                    return Ok(true);
+                }
+                Ok(false)
+            })?;
+            Ok(name)
+        }
+        let mut signature = Signature::default();
+        for_each_field(bytes, |field, _, is| {
+            let map = match field {
+                1 => &mut signature.inputs,
+                2 => &mut signature.outputs,
+                _ => return Ok(false),
+            };
+            let (key, info) = map_entry(&read_bytes(is)?)?;
+            map.insert(key, tensor_name(&info)?);
+            Ok(true)
+        })?;
+        Ok(signature)
+    }
+}
+
+/// A graph of a SavedModel, with its tags and signatures.
+#[derive(Clone, Debug, Default)]
+pub struct MetaGraph {
+    pub tags: Vec<String>,
+    pub graph: GraphDef,
+    pub signatures: BTreeMap<String, Signature>,
+}
+
+impl MetaGraph {
+    fn parse(bytes: &[u8]) -> TractResult<MetaGraph> {
+        let mut meta = MetaGraph::default();
+        for_each_field(bytes, |field, _, is| {
+            match field {
+                1 => for_each_field(&read_bytes(is)?, |field, _, is| {
+                    if field == 4 {
+                        meta.tags.push(read_string(is)?);
+                        return Ok(true);
+                    }
+                    Ok(false)
+                })?,
+                2 => meta.graph = GraphDef::parse_from_bytes(&read_bytes(is)?).map_err(pb_error)?,
+                5 => {
+                    let (key, signature) = map_entry(&read_bytes(is)?)?;
+                    meta.signatures.insert(key, Signature::parse(&signature)?);
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+        Ok(meta)
+    }
+
+    /// The signature called `name`, or the default one if `name` is `None`:
+    /// the only one, or the one called `serving_default`.
+    pub fn signature(&self, name: Option<&str>) -> TractResult<&Signature> {
+        let name = match name {
+            Some(name) => name,
+            None if self.signatures.len() == 1 => self.signatures.keys().next().unwrap(),
+            None => DEFAULT_SIGNATURE,
+        };
+        self.signatures.get(name).ok_or_else(|| {
+            format!(
+                "No signature {:?} in SavedModel, found {:?}",
+                name,
+                self.signatures.keys().collect::<Vec<_>>()
+            )
+            .into()
+        })
+    }
+}
+
+/// Reads the `saved_model.pb` of a SavedModel directory.
+pub fn meta_graphs_for_path(dir: impl AsRef<Path>) -> TractResult<Vec<MetaGraph>> {
+    let path = dir.as_ref().join("saved_model.pb");
+    let bytes = fs::read(&path).map_err(|e| format!("Could not open {:?}: {}", path, e))?;
+    let mut metas = vec![];
+    for_each_field(&bytes, |field, _, is| {
+        if field == 2 {
+            metas.push(MetaGraph::parse(&read_bytes(is)?)?);
+            return Ok(true);
+        }
+        Ok(false)
+    })?;
+    Ok(metas)
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> TractResult<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos).ok_or("Truncated varint in checkpoint index")?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Invalid varint in checkpoint index")
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> TractResult<u32> {
+    let b = bytes.get(pos..pos + 4).ok_or("Truncated checkpoint index")?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The block of the index table `handle` points to.
+fn block<'a>(table: &'a [u8], handle: &[u8]) -> TractResult<&'a [u8]> {
+    let mut pos = 0;
+    let offset = read_varint(handle, &mut pos)? as usize;
+    let len = read_varint(handle, &mut pos)? as usize;
+    // the block is followed by its compression type and checksum
+    match table.get(offset + len) {
+        Some(0) => (),
+        Some(_) => bail!("Compressed checkpoint index blocks are not supported"),
+        None => bail!("Truncated checkpoint index"),
+    }
+    Ok(&table[offset..offset + len])
+}
+
+/// Key and value pairs of an index table block.
+fn block_entries(block: &[u8]) -> TractResult<Vec<(Vec<u8>, &[u8])>> {
+    let restarts = read_u32(block, block.len().checked_sub(4).ok_or("Empty block")?)? as usize;
+    let end = block.len() - 4 * (restarts + 1);
+    let mut entries: Vec<(Vec<u8>, &[u8])> = vec![];
+    let mut pos = 0;
+    while pos < end {
+        let shared = read_varint(block, &mut pos)? as usize;
+        let unshared = read_varint(block, &mut pos)? as usize;
+        let value_len = read_varint(block, &mut pos)? as usize;
+        let mut key = entries.last().map(|e| e.0[..shared].to_vec()).unwrap_or_default();
+        key.extend_from_slice(&block[pos..pos + unshared]);
+        pos += unshared;
+        entries.push((key, &block[pos..pos + value_len]));
+        pos += value_len;
+    }
+    Ok(entries)
+}
+
+/// Where a variable is stored in the checkpoint data files.
+#[derive(Debug, Default)]
+struct BundleEntry {
+    dtype: i32,
+    shape: TensorShapeProto,
+    shard_id: i32,
+    offset: u64,
+    size: u64,
+    sliced: bool,
+}
+
+impl BundleEntry {
+    fn parse(bytes: &[u8]) -> TractResult<BundleEntry> {
+        let mut entry = BundleEntry::default();
+        for_each_field(bytes, |field, _, is| {
+            match field {
+                1 => entry.dtype = is.read_int32().map_err(pb_error)?,
+                2 => {
+                    entry.shape =
+                        TensorShapeProto::parse_from_bytes(&read_bytes(is)?).map_err(pb_error)?
+                }
+                3 => entry.shard_id = is.read_int32().map_err(pb_error)?,
+                4 => entry.offset = is.read_int64().map_err(pb_error)? as u64,
+                5 => entry.size = is.read_int64().map_err(pb_error)? as u64,
+                7 => {
+                    entry.sliced = true;
+                    return Ok(false);
+                }
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+        Ok(entry)
+    }
+}
+
+/// Reads the variables of a checkpoint, by checkpoint key. `prefix` is the
+/// checkpoint path without the `.index` and `.data-*` suffixes.
+pub fn checkpoint_tensors(prefix: impl AsRef<Path>) -> TractResult<HashMap<String, TensorProto>> {
+    let prefix = prefix.as_ref().to_string_lossy().into_owned();
+    let index_path = format!("{}.index", prefix);
+    let table =
+        fs::read(&index_path).map_err(|e| format!("Could not open {}: {}", index_path, e))?;
+    if table.len() < 48 {
+        bail!("Truncated checkpoint index {}", index_path);
+    }
+    let footer = &table[table.len() - 48..];
+    let magic = read_u32(footer, 40)? as u64 | (read_u32(footer, 44)? as u64) << 32;
+    if magic != TABLE_MAGIC {
+        bail!("{} is not a checkpoint index", index_path);
+    }
+    let mut pos = 0;
+    // skip the metaindex handle
+    read_varint(footer, &mut pos)?;
+    read_varint(footer, &mut pos)?;
+    let index = block(&table, &footer[pos..])?;
+    let mut tensors = HashMap::new();
+    let mut shards: HashMap<i32, Vec<u8>> = HashMap::new();
+    let mut num_shards = 1;
+    for (_, handle) in block_entries(index)? {
+        for (key, value) in block_entries(block(&table, handle)?)? {
+            if key.is_empty() {
+                // the bundle header
+                for_each_field(value, |field, _, is| {
+                    if field == 1 {
+                        num_shards = is.read_int32().map_err(pb_error)?;
+                        return Ok(true);
+                    }
+                    Ok(false)
+                })?;
+                continue;
+            }
+            let key = String::from_utf8(key).map_err(|_| "Checkpoint key is not UTF-8")?;
+            let entry = BundleEntry::parse(value)?;
+            if entry.sliced {
+                bail!("Sliced checkpoint variable {} is not supported", key);
+            }
+            if !shards.contains_key(&entry.shard_id) {
+                let path = format!("{}.data-{:05}-of-{:05}", prefix, entry.shard_id, num_shards);
+                let data =
+                    fs::read(&path).map_err(|e| format!("Could not open {}: {}", path, e))?;
+                shards.insert(entry.shard_id, data);
+            }
+            let data = &shards[&entry.shard_id];
+            let content = data
+                .get(entry.offset as usize..(entry.offset + entry.size) as usize)
+                .ok_or_else(|| format!("Checkpoint variable {} is out of its data file", key))?;
+            let mut tensor = TensorProto::new();
+            tensor.set_dtype(
+                DataType::from_i32(entry.dtype)
+                    .ok_or_else(|| format!("Unknown type for checkpoint variable {}", key))?,
+            );
+            tensor.set_tensor_shape(entry.shape);
+            tensor.set_tensor_content(content.to_vec());
+            tensors.insert(key, tensor);
+        }
+    }
+    Ok(tensors)
+}
+
+/// Checkpoint key restored into each variable handle: the restore ops assign
+/// the outputs of a `RestoreV2` to the handles, the keys are a constant input
+/// of the `RestoreV2`. Handles not restored this way use their shared name.
+fn checkpoint_keys(graph: &GraphDef) -> TractResult<HashMap<String, String>> {
+    let nodes: HashMap<&str, &NodeDef> =
+        graph.get_node().iter().map(|n| (n.get_name(), n)).collect();
+    let mut keys = HashMap::new();
+    for node in graph.get_node() {
+        if node.get_op() == "VarHandleOp" {
+            let shared_name = node.get_attr_opt_str("shared_name")?.unwrap_or_default();
+            let key =
+                if shared_name.is_empty() { node.get_name().to_string() } else { shared_name };
+            keys.entry(node.get_name().to_string()).or_insert(key);
+        }
+        if node.get_op() != "AssignVariableOp" || node.get_input().len() < 2 {
+            continue;
+        }
+        let (handle, _) = Tensorflow::parse_input(&node.get_input()[0])?;
+        let (mut value, mut slot) = Tensorflow::parse_input(&node.get_input()[1])?;
+        while let Some(n) = nodes.get(value).filter(|n| n.get_op() == "Identity") {
+            let input = Tensorflow::parse_input(&n.get_input()[0])?;
+            value = input.0;
+            slot = input.1;
+        }
+        let restore = match nodes.get(value).filter(|n| n.get_op() == "RestoreV2") {
+            Some(restore) => restore,
+            None => continue,
+        };
+        let (names, _) = Tensorflow::parse_input(&restore.get_input()[1])?;
+        let names = nodes
+            .get(names)
+            .and_then(|n| n.get_attr().get("value"))
+            .map(|v| v.get_tensor().get_string_val());
+        if let Some(name) = names.and_then(|names| names.get(slot)) {
+            let key = String::from_utf8(name.clone()).map_err(|_| "Checkpoint key is not UTF-8")?;
+            keys.insert(handle.to_string(), key);
+        }
+    }
+    Ok(keys)
+}
+
+/// Only keep the nodes `outputs` depend on, replacing the variable reads by
+/// constants holding the values in `tensors`.
+fn freeze<'a>(
+    graph: &'a GraphDef,
+    outputs: &[&'a str],
+    tensors: &HashMap<String, TensorProto>,
+) -> TractResult<GraphDef> {
+    let keys = checkpoint_keys(graph)?;
+    let nodes: HashMap<&str, &NodeDef> =
+        graph.get_node().iter().map(|n| (n.get_name(), n)).collect();
+    let mut frozen: HashMap<&str, NodeDef> = HashMap::new();
+    let mut todo: Vec<&str> = outputs.to_vec();
+    while let Some(name) = todo.pop() {
+        if frozen.contains_key(name) {
+            continue;
+        }
+        let node = nodes.get(name).ok_or_else(|| format!("No node {} in the graph", name))?;
+        if node.get_op() == "ReadVariableOp" {
+            let (handle, _) = Tensorflow::parse_input(&node.get_input()[0])?;
+            let key = keys
+                .get(handle)
+                .ok_or_else(|| format!("{} reads {}, not a variable", name, handle))?;
+            let value = tensors
+                .get(key)
+                .ok_or_else(|| format!("No value for variable {} in checkpoint", key))?;
+            let dtype: DataType = node.get_attr_datum_type("dtype")?.try_into()?;
+            let konst = NodeDef::new()
+                .name(name)
+                .op("Const")
+                .attr("dtype", dtype)
+                .attr("value", value.clone());
+            frozen.insert(name, konst);
+            continue;
+        }
+        for input in node.get_input() {
+            todo.push(Tensorflow::parse_input(input)?.0);
+        }
+        frozen.insert(name, (*node).clone());
+    }
+    let mut pruned = GraphDef::new();
+    for node in graph.get_node() {
+        if let Some(node) = frozen.remove(node.get_name()) {
+            pruned.mut_node().push(node);
+        }
+    }
+    Ok(pruned)
+}
+
+impl Tensorflow {
+    /// Loads the `serve` graph of a SavedModel directory, its variables
+    /// turned into constants.
+    ///
+    /// `signature` names the entry point to load, setting the model inputs
+    /// and outputs. `None` picks the only signature, or `serving_default` if
+    /// there are several.
+    pub fn saved_model_for_path(
+        &self,
+        dir: impl AsRef<Path>,
+        signature: Option<&str>,
+    ) -> TractResult<InferenceModel> {
+        let metas = meta_graphs_for_path(dir.as_ref())?;
+        let meta = metas
+            .iter()
+            .find(|m| m.tags.iter().any(|t| t == SERVE_TAG))
+            .ok_or_else(|| format!("No {:?} graph in SavedModel {:?}", SERVE_TAG, dir.as_ref()))?;
+        let signature = meta.signature(signature)?;
+        let inputs = signature
+            .inputs
+            .values()
+            .map(|t| Self::parse_input(t))
+            .collect::<TractResult<Vec<_>>>()?;
+        let outputs = signature
+            .outputs
+            .values()
+            .map(|t| Self::parse_input(t))
+            .collect::<TractResult<Vec<_>>>()?;
+        let has_variables = meta.graph.get_node().iter().any(|n| n.get_op() == "VarHandleOp");
+        let tensors = if has_variables {
+            checkpoint_tensors(dir.as_ref().join("variables").join("variables"))?
+        } else {
+            HashMap::new()
+        };
+        let graph =
+            freeze(&meta.graph, &*outputs.iter().map(|o| o.0).collect::<Vec<_>>(), &tensors)?;
+        let mut model = self.model_for_proto_model(&graph)?;
+        let outlets = |names: &[(&str, usize)]| {
+            names
+                .iter()
+                .map(|&(name, slot)| Ok(OutletId::new(model.node_by_name(name)?.id, slot)))
+                .collect::<TractResult<Vec<_>>>()
+        };
+        let (inputs, outputs) = (outlets(&inputs)?, outlets(&outputs)?);
+        model.set_input_outlets(&inputs)?;
+        model.set_output_outlets(&outputs)?;
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tfpb::*;
+    use protobuf::{CodedOutputStream, Message, ProtobufResult};
+
+    fn message(write: impl FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>) -> Vec<u8> {
+        let mut bytes = vec![];
+        {
+            let mut os = CodedOutputStream::vec(&mut bytes);
+            write(&mut os).unwrap();
+            os.flush().unwrap();
+        }
+        bytes
+    }
+
+    fn entry(key: &str, value: &[u8]) -> Vec<u8> {
+        message(|os| {
+            os.write_string(1, key)?;
+            os.write_bytes(2, value)
+        })
+    }
+
+    fn signature(inputs: &[(&str, &str)], outputs: &[(&str, &str)]) -> Vec<u8> {
+        message(|os| {
+            for (field, tensors) in &[(1, inputs), (2, outputs)] {
+                for (key, name) in tensors.iter() {
+                    let info = message(|os| os.write_string(1, name));
+                    os.write_bytes(*field, &entry(key, &info))?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn varint(bytes: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            bytes.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+
+    /// Appends an uncompressed table block, returning its handle.
+    fn table_block(table: &mut Vec<u8>, entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let offset = table.len();
+        for (key, value) in entries {
+            varint(table, 0);
+            varint(table, key.len());
+            varint(table, value.len());
+            table.extend_from_slice(key.as_bytes());
+            table.extend_from_slice(value);
+        }
+        table.extend_from_slice(&0u32.to_le_bytes());
+        table.extend_from_slice(&1u32.to_le_bytes());
+        let mut handle = vec![];
+        varint(&mut handle, offset);
+        varint(&mut handle, table.len() - offset);
+        table.extend_from_slice(&[0; 5]);
+        handle
+    }
+
+    fn write_checkpoint(prefix: &Path, key: &str, shape: Vec<usize>, values: &[f32]) {
+        let data: Vec<u8> =
+            values.iter().flat_map(|v| v.to_bits().to_le_bytes().to_vec()).collect();
+        fs::write(format!("{}.data-00000-of-00001", prefix.display()), &data).unwrap();
+        let shape = tensor_f32(shape, vec![]).take_tensor_shape().write_to_bytes().unwrap();
+        let header = message(|os| os.write_int32(1, 1));
+        let variable = message(|os| {
+            os.write_enum(1, DataType::DT_FLOAT.value())?;
+            os.write_bytes(2, &shape)?;
+            os.write_int64(5, data.len() as i64)
+        });
+        let mut table = vec![];
+        let data_block = table_block(&mut table, &[("", header), (key, variable)]);
+        let meta_block = table_block(&mut table, &[]);
+        let index_block = table_block(&mut table, &[(key, data_block)]);
+        let mut footer = [meta_block, index_block].concat();
+        footer.resize(40, 0);
+        footer.extend_from_slice(&(TABLE_MAGIC as u32).to_le_bytes());
+        footer.extend_from_slice(&((TABLE_MAGIC >> 32) as u32).to_le_bytes());
+        table.extend(footer);
+        fs::write(format!("{}.index", prefix.display()), &table).unwrap();
+    }
+
+    /// y = x + w, w being a variable, or a constant if `frozen`.
+    fn model_graph(frozen: bool) -> graph::GraphDef {
+        let w = if frozen {
+            node()
+                .name("w/read")
+                .op("Const")
+                .attr("dtype", DataType::DT_FLOAT)
+                .attr("value", tensor_f32(vec![3], vec![1.0, -2.0, 0.5]))
+        } else {
+            node().name("w/read").op("ReadVariableOp").input("w").attr("dtype", DataType::DT_FLOAT)
+        };
+        let graph = graph()
+            .node(node().name("x").op("Placeholder").attr("dtype", DataType::DT_FLOAT))
+            .node(w)
+            .node(
+                node().name("y").op("Add").input("x").input("w/read").attr("T", DataType::DT_FLOAT),
+            );
+        if frozen {
+            graph
+        } else {
+            graph.node(
+                node()
+                    .name("w")
+                    .op("VarHandleOp")
+                    .attr("dtype", DataType::DT_FLOAT)
+                    .attr("shared_name", "weights"),
+            )
+        }
+    }
+
+    fn saved_model(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "tract-tf-saved-model-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("variables")).unwrap();
+        write_checkpoint(
+            &dir.join("variables").join("variables"),
+            "weights",
+            vec![3],
+            &[1.0, -2.0, 0.5],
+        );
+        let graph = model_graph(false).write_to_bytes().unwrap();
+        let meta = message(|os| {
+            os.write_bytes(1, &message(|os| os.write_string(4, "serve")))?;
+            os.write_bytes(2, &graph)?;
+            let default = signature(&[("x", "x:0")], &[("y", "y:0")]);
+            os.write_bytes(5, &entry("serving_default", &default))?;
+            let weights = signature(&[], &[("w", "w/read:0")]);
+            os.write_bytes(5, &entry("weights", &weights))
+        });
+        fs::write(dir.join("saved_model.pb"), message(|os| os.write_bytes(2, &meta))).unwrap();
+        dir
+    }
+
+    #[test]
+    fn saved_model_matches_frozen_graph() {
+        let dir = saved_model("frozen");
+        let tf = crate::tensorflow();
+        let model = tf.saved_model_for_path(&dir, None).unwrap();
+        let frozen = tf.model_for_proto_model(&model_graph(true)).unwrap();
+        let input = ndarray::arr1(&[3.0f32, 4.0, 5.0]);
+        let found = SimplePlan::new(&model).unwrap().run(tvec!(input.clone().into())).unwrap();
+        let expected = SimplePlan::new(&frozen).unwrap().run(tvec!(input.into())).unwrap();
+        assert_eq!(found, expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn signature_selection() {
+        let dir = saved_model("signatures");
+        let tf = crate::tensorflow();
+        let model = tf.saved_model_for_path(&dir, Some("weights")).unwrap();
+        assert!(model.input_outlets().unwrap().is_empty());
+        let found = SimplePlan::new(&model).unwrap().run(tvec!()).unwrap();
+        assert_eq!(found[0], rctensor1(&[1.0f32, -2.0, 0.5]));
+        assert!(tf.saved_model_for_path(&dir, Some("missing")).is_err());
+        let meta = &meta_graphs_for_path(&dir).unwrap()[0];
+        assert_eq!(meta.signature(None).unwrap().outputs["y"], "y:0");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
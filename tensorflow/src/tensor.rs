@@ -47,6 +47,7 @@ impl TryFrom<DatumType> for DataType  {
             DatumType::F64 => Ok(DataType::DT_DOUBLE),
             DatumType::String => Ok(DataType::DT_STRING),
             DatumType::TDim => bail!("Dimension is not translatable in protobuf"),
+            DatumType::SequenceItem => bail!("Sequences are not translatable in protobuf"),
         }
     }
 }
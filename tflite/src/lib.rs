@@ -0,0 +1,33 @@
+//! # Tract TFLite module
+//!
+//! Loads quantized int8 TensorFlow Lite models: the flatbuffer is decoded
+//! here, without the TFLite runtime, and its operators are mapped to tract
+//! ones.
+//!
+//! Covered builtin operators are CONV_2D, DEPTHWISE_CONV_2D,
+//! FULLY_CONNECTED, RESHAPE, QUANTIZE and DEQUANTIZE, with their fused
+//! RELU, RELU6 and RELU_N1_TO_1 activations. Others load as unimplemented
+//! operators.
+
+#[macro_use]
+extern crate derive_new;
+#[macro_use]
+extern crate error_chain;
+#[allow(unused_imports)]
+#[macro_use]
+extern crate log;
+extern crate ndarray;
+extern crate tract_core;
+
+pub mod model;
+pub mod ops;
+pub mod schema;
+
+pub use model::Tflite;
+pub use tract_core::flat;
+
+pub fn tflite() -> Tflite {
+    let mut ops = tract_core::framework::OpRegister::default();
+    ops::register_all_ops(&mut ops);
+    Tflite { op_register: ops }
+}
@@ -0,0 +1,418 @@
+use tract_core::internal::*;
+
+use crate::flat::Scalar;
+use crate::schema::*;
+
+pub type TfliteOpRegister = OpRegister<OpNode>;
+
+/// An operator and the tensors it reads and writes, as op builders get it.
+///
+/// Optional inputs left out are not in `inputs`.
+#[derive(Clone, Debug)]
+pub struct OpNode {
+    pub name: String,
+    pub op: String,
+    pub options: BuiltinOptions,
+    pub inputs: Vec<TensorProto>,
+    pub outputs: Vec<TensorProto>,
+}
+
+pub struct Tflite {
+    pub op_register: TfliteOpRegister,
+}
+
+/// Value of a constant tensor, from its little-endian buffer.
+fn tensor_value<T: Scalar + Datum>(proto: &TensorProto, data: &[u8]) -> TractResult<Tensor> {
+    let shape: Vec<usize> = proto.shape.iter().map(|&d| d as usize).collect();
+    let len = shape.iter().product::<usize>();
+    if data.len() != len * T::SIZE {
+        bail!("Tensor {} of shape {:?} holds {} bytes", proto.name, shape, data.len());
+    }
+    let values: Vec<T> = data.chunks(T::SIZE).map(T::from_le).collect();
+    Ok(ndarray::ArrayD::from_shape_vec(shape, values)?.into())
+}
+
+fn tensor(graph: &SubGraphProto, ix: i32) -> TractResult<&TensorProto> {
+    graph.tensors.get(ix as usize).ok_or_else(|| format!("No tensor {} in graph", ix).into())
+}
+
+impl Tflite {
+    /// The outlet holding tensor `ix`, a constant node being added for it on
+    /// its first use.
+    fn outlet(
+        model: &mut InferenceModel,
+        proto: &ModelProto,
+        graph: &SubGraphProto,
+        outlets: &mut HashMap<i32, OutletId>,
+        ix: i32,
+    ) -> TractResult<OutletId> {
+        if let Some(outlet) = outlets.get(&ix) {
+            return Ok(*outlet);
+        }
+        let t = tensor(graph, ix)?;
+        let data = match proto.buffers.get(t.buffer as usize) {
+            Some(data) if !data.is_empty() => data,
+            _ => bail!("Tensor {} is read before it is computed", t.name),
+        };
+        let value = match t.datum_type {
+            DatumType::U8 => tensor_value::<u8>(t, data)?,
+            DatumType::I8 => tensor_value::<i8>(t, data)?,
+            DatumType::I16 => tensor_value::<i16>(t, data)?,
+            DatumType::I32 => tensor_value::<i32>(t, data)?,
+            DatumType::I64 => tensor_value::<i64>(t, data)?,
+            DatumType::F32 => tensor_value::<f32>(t, data)?,
+            dt => bail!("Constant tensor {} of unsupported type {:?}", t.name, dt),
+        };
+        let id = model.add_const(&*t.name, value)?;
+        let outlet = OutletId::new(id, 0);
+        outlets.insert(ix, outlet);
+        Ok(outlet)
+    }
+}
+
+impl Framework<OpNode, ModelProto> for Tflite {
+    fn op_builder_for_name(&self, name: &str) -> Option<&OpBuilder<OpNode>> {
+        self.op_register.get(name)
+    }
+
+    fn proto_model_for_read(&self, r: &mut std::io::Read) -> TractResult<ModelProto> {
+        let mut buf = vec![];
+        r.read_to_end(&mut buf)?;
+        ModelProto::decode(&buf)
+    }
+
+    fn model_for_proto_model(&self, proto: &ModelProto) -> TractResult<InferenceModel> {
+        // the first subgraph is the model, others are the bodies of control
+        // flow operators
+        let graph = proto.subgraphs.get(0).ok_or("TFLite model without subgraph")?;
+        let mut model = InferenceModel::default();
+        let mut outlets = HashMap::new();
+        for &input in &graph.inputs {
+            let t = tensor(graph, input)?;
+            let id =
+                model.add_source(&*t.name, TensorFact::dt_shape(t.datum_type, t.shape_fact()))?;
+            outlets.insert(input, OutletId::new(id, 0));
+        }
+        for (ix, op) in graph.operators.iter().enumerate() {
+            let op_name = proto
+                .operator_codes
+                .get(op.opcode_index as usize)
+                .ok_or_else(|| format!("No operator code {}", op.opcode_index))?;
+            let inputs: Vec<i32> = op.inputs.iter().cloned().filter(|&i| i >= 0).collect();
+            let node = OpNode {
+                name: match op.outputs.get(0) {
+                    Some(&o) => tensor(graph, o)?.name.clone(),
+                    None => format!("{}.{}", op_name, ix),
+                },
+                op: op_name.clone(),
+                options: op.options.clone(),
+                inputs: inputs
+                    .iter()
+                    .map(|&i| Ok(tensor(graph, i)?.clone()))
+                    .collect::<TractResult<_>>()?,
+                outputs: op
+                    .outputs
+                    .iter()
+                    .map(|&o| Ok(tensor(graph, o)?.clone()))
+                    .collect::<TractResult<_>>()?,
+            };
+            let facts: TVec<TensorFact> = node
+                .outputs
+                .iter()
+                .map(|t| TensorFact::dt_shape(t.datum_type, t.shape_fact()))
+                .collect();
+            let built = self
+                .build_op(op_name, &node)
+                .map_err(|e| format!("While building node {}, {}", node.name, e.description()))?;
+            let id = model.add_node(node.name.clone(), built, facts.clone())?;
+            for (slot, &input) in inputs.iter().enumerate() {
+                let outlet = Self::outlet(&mut model, proto, graph, &mut outlets, input)?;
+                model.add_edge(outlet, InletId::new(id, slot))?;
+            }
+            for (slot, &output) in op.outputs.iter().enumerate() {
+                outlets.insert(output, OutletId::new(id, slot));
+            }
+            if let Some(activation) = crate::ops::fused_activation(&node)? {
+                let act = model.add_node(
+                    format!("{}.activation", node.name),
+                    activation,
+                    tvec!(facts[0].clone()),
+                )?;
+                model.add_edge(OutletId::new(id, 0), InletId::new(act, 0))?;
+                outlets.insert(op.outputs[0], OutletId::new(act, 0));
+            }
+        }
+        let outputs = graph
+            .outputs
+            .iter()
+            .map(|&o| Self::outlet(&mut model, proto, graph, &mut outlets, o))
+            .collect::<TractResult<Vec<_>>>()?;
+        model.set_output_outlets(&outputs)?;
+        Ok(model)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flat::builder::*;
+    use ndarray::ArrayD;
+
+    const INPUT: (f32, i64) = (0.05, -3);
+    const OUTPUT: (f32, i64) = (0.1, 2);
+
+    fn quantization(scale: Vec<f32>, zero_point: Vec<i64>) -> Value {
+        Value::Table(vec![None, None, Some(Value::F32s(scale)), Some(Value::I64s(zero_point))])
+    }
+
+    fn tensor(name: &str, shape: &[i32], dt: u8, buffer: u32, q: Option<Value>) -> Value {
+        Value::Table(vec![
+            Some(Value::I32s(shape.to_vec())),
+            Some(Value::U8(dt)),
+            Some(Value::U32(buffer)),
+            Some(Value::Str(name.to_string())),
+            q,
+        ])
+    }
+
+    fn operator(
+        opcode: u32,
+        inputs: &[i32],
+        outputs: &[i32],
+        options: Option<(u8, Value)>,
+    ) -> Value {
+        let (options_type, options) = match options {
+            Some((t, o)) => (Some(Value::U8(t)), Some(o)),
+            None => (None, None),
+        };
+        Value::Table(vec![
+            Some(Value::U32(opcode)),
+            Some(Value::I32s(inputs.to_vec())),
+            Some(Value::I32s(outputs.to_vec())),
+            options_type,
+            options,
+        ])
+    }
+
+    /// QUANTIZE, CONV_2D (or DEPTHWISE_CONV_2D) with a fused RELU, then
+    /// DEQUANTIZE, on a 1x4x4x2 input, same padding.
+    fn conv_model(depthwise: bool, kernel: &[i8], scales: &[f32], bias: &[i32]) -> Vec<u8> {
+        let channels = scales.len() as i32;
+        let (code, options_type, kernel_shape) =
+            if depthwise { (4, 2, [1, 3, 3, channels]) } else { (3, 1, [channels, 3, 3, 2]) };
+        let mut options = vec![Some(Value::U8(0)), Some(Value::I32(1)), Some(Value::I32(1))];
+        if depthwise {
+            options.push(Some(Value::I32(channels / 2)));
+        }
+        options.push(Some(Value::U8(1)));
+        let codes =
+            [114, code, 6].iter().map(|&c| Value::Table(vec![Some(Value::U8(c))])).collect();
+        let bias_bytes = bias.iter().flat_map(|b| b.to_le_bytes().to_vec()).collect();
+        let tensors = vec![
+            tensor("input", &[1, 4, 4, 2], 0, 0, None),
+            tensor(
+                "q_input",
+                &[1, 4, 4, 2],
+                9,
+                0,
+                Some(quantization(vec![INPUT.0], vec![INPUT.1])),
+            ),
+            tensor(
+                "kernel",
+                &kernel_shape,
+                9,
+                1,
+                Some(quantization(scales.to_vec(), vec![0; scales.len()])),
+            ),
+            tensor("bias", &[channels], 2, 2, None),
+            tensor(
+                "conv",
+                &[1, 4, 4, channels],
+                9,
+                0,
+                Some(quantization(vec![OUTPUT.0], vec![OUTPUT.1])),
+            ),
+            tensor("output", &[1, 4, 4, channels], 0, 0, None),
+        ];
+        let operators = vec![
+            operator(0, &[0], &[1], None),
+            operator(1, &[1, 2, 3], &[4], Some((options_type, Value::Table(options)))),
+            operator(2, &[4], &[5], None),
+        ];
+        let subgraph = Value::Table(vec![
+            Some(Value::Tables(tensors)),
+            Some(Value::I32s(vec![0])),
+            Some(Value::I32s(vec![5])),
+            Some(Value::Tables(operators)),
+        ]);
+        let buffers = vec![
+            Value::Table(vec![]),
+            Value::Table(vec![Some(Value::Bytes(kernel.iter().map(|&w| w as u8).collect()))]),
+            Value::Table(vec![Some(Value::Bytes(bias_bytes))]),
+        ];
+        finish(&Value::Table(vec![
+            Some(Value::U32(3)),
+            Some(Value::Tables(codes)),
+            Some(Value::Tables(vec![subgraph])),
+            None,
+            Some(Value::Tables(buffers)),
+        ]))
+    }
+
+    /// The quantized convolution, computed in f64 from its definition.
+    fn reference(
+        depthwise: bool,
+        input: &ArrayD<f32>,
+        kernel: &[i8],
+        scales: &[f32],
+        bias: &[i32],
+    ) -> ArrayD<f32> {
+        let q = input.mapv(|x| ((x / INPUT.0).round() + INPUT.1 as f32).max(-128.0).min(127.0));
+        let channels = scales.len();
+        ArrayD::from_shape_fn(vec![1, 4, 4, channels], |coords| {
+            let (y, x, o) = (coords[1], coords[2], coords[3]);
+            let mut acc = bias[o] as f64;
+            for ky in 0..3 {
+                for kx in 0..3 {
+                    let (iy, ix) = (y as isize + ky as isize - 1, x as isize + kx as isize - 1);
+                    if iy < 0 || ix < 0 || iy >= 4 || ix >= 4 {
+                        continue;
+                    }
+                    for c in 0..2 {
+                        let w = if depthwise {
+                            if c != o / (channels / 2) {
+                                continue;
+                            }
+                            kernel[(ky * 3 + kx) * channels + o]
+                        } else {
+                            kernel[((o * 3 + ky) * 3 + kx) * 2 + c]
+                        };
+                        let x = q[[0, iy as usize, ix as usize, c]] as f64 - INPUT.1 as f64;
+                        acc += x * w as f64;
+                    }
+                }
+            }
+            let real = acc * INPUT.0 as f64 * scales[o] as f64;
+            let out = ((real / OUTPUT.0 as f64).round() + OUTPUT.1 as f64)
+                .max(OUTPUT.1 as f64)
+                .min(127.0);
+            ((out - OUTPUT.1 as f64) * OUTPUT.0 as f64) as f32
+        })
+    }
+
+    fn check(depthwise: bool, kernel: &[i8], scales: &[f32], bias: &[i32]) {
+        let buf = conv_model(depthwise, kernel, scales, bias);
+        let model = crate::tflite().model_for_read(&mut &*buf).unwrap();
+        let input = ArrayD::from_shape_fn(vec![1, 4, 4, 2], |c| {
+            ((c[1] * 8 + c[2] * 2 + c[3]) * 7 % 19) as f32 * 0.04 - 0.36
+        });
+        let expected = reference(depthwise, &input, kernel, scales, bias);
+        let compare = |found: Arc<Tensor>| {
+            let found = found.to_array_view::<f32>().unwrap();
+            assert_eq!(found.shape(), expected.shape());
+            // within one unit of the output quantization
+            for (f, e) in found.iter().zip(expected.iter()) {
+                assert!((f - e).abs() <= OUTPUT.0 * 1.001, "{} for {}", f, e);
+            }
+        };
+        let plan = SimplePlan::new(&model).unwrap();
+        compare(plan.run(tvec!(input.clone().into())).unwrap().remove(0));
+        let optimized = model.into_optimized().unwrap();
+        let plan = SimplePlan::new(&optimized).unwrap();
+        compare(plan.run(tvec!(input.into())).unwrap().remove(0));
+    }
+
+    /// QUANTIZE, FULLY_CONNECTED with one weight scale per output, RESHAPE
+    /// of its 2x3 output to 3x2, then DEQUANTIZE.
+    fn fully_connected_model(weights: &[i8], scales: &[f32], bias: &[i32]) -> Vec<u8> {
+        let codes =
+            [114, 9, 22, 6].iter().map(|&c| Value::Table(vec![Some(Value::U8(c))])).collect();
+        let bias_bytes = bias.iter().flat_map(|b| b.to_le_bytes().to_vec()).collect();
+        let shape_bytes = [3i32, 2].iter().flat_map(|d| d.to_le_bytes().to_vec()).collect();
+        let tensors = vec![
+            tensor("input", &[2, 6], 0, 0, None),
+            tensor("q_input", &[2, 6], 9, 0, Some(quantization(vec![INPUT.0], vec![INPUT.1]))),
+            tensor("weights", &[3, 6], 9, 1, Some(quantization(scales.to_vec(), vec![0; 3]))),
+            tensor("bias", &[3], 2, 2, None),
+            tensor("fc", &[2, 3], 9, 0, Some(quantization(vec![OUTPUT.0], vec![OUTPUT.1]))),
+            tensor("shape", &[2], 2, 3, None),
+            tensor("reshaped", &[3, 2], 9, 0, Some(quantization(vec![OUTPUT.0], vec![OUTPUT.1]))),
+            tensor("output", &[3, 2], 0, 0, None),
+        ];
+        let fc_options = Value::Table(vec![Some(Value::U8(0))]);
+        let reshape_options = Value::Table(vec![Some(Value::I32s(vec![3, 2]))]);
+        let operators = vec![
+            operator(0, &[0], &[1], None),
+            operator(1, &[1, 2, 3], &[4], Some((8, fc_options))),
+            operator(2, &[4, 5], &[6], Some((17, reshape_options))),
+            operator(3, &[6], &[7], None),
+        ];
+        let subgraph = Value::Table(vec![
+            Some(Value::Tables(tensors)),
+            Some(Value::I32s(vec![0])),
+            Some(Value::I32s(vec![7])),
+            Some(Value::Tables(operators)),
+        ]);
+        let buffers = vec![
+            Value::Table(vec![]),
+            Value::Table(vec![Some(Value::Bytes(weights.iter().map(|&w| w as u8).collect()))]),
+            Value::Table(vec![Some(Value::Bytes(bias_bytes))]),
+            Value::Table(vec![Some(Value::Bytes(shape_bytes))]),
+        ];
+        finish(&Value::Table(vec![
+            Some(Value::U32(3)),
+            Some(Value::Tables(codes)),
+            Some(Value::Tables(vec![subgraph])),
+            None,
+            Some(Value::Tables(buffers)),
+        ]))
+    }
+
+    #[test]
+    fn fully_connected_per_axis() {
+        let weights: Vec<i8> = (0..18).map(|i| ((i * 7) % 11) as i8 - 3).collect();
+        let scales = [0.01, 0.04, 0.02];
+        let bias = [15, -30, 0];
+        let buf = fully_connected_model(&weights, &scales, &bias);
+        let model = crate::tflite().model_for_read(&mut &*buf).unwrap();
+        let input = ArrayD::from_shape_fn(vec![2, 6], |c| (c[0] * 6 + c[1]) as f32 * 0.5 - 2.9);
+        let q = input.mapv(|x| ((x / INPUT.0).round() + INPUT.1 as f32).max(-128.0).min(127.0));
+        let fc = ArrayD::from_shape_fn(vec![2, 3], |c| {
+            let (row, o) = (c[0], c[1]);
+            let acc = (0..6).fold(bias[o] as f64, |acc, k| {
+                acc + (q[[row, k]] as f64 - INPUT.1 as f64) * weights[o * 6 + k] as f64
+            });
+            let real = acc * INPUT.0 as f64 * scales[o] as f64;
+            let out = ((real / OUTPUT.0 as f64).round() + OUTPUT.1 as f64).max(-128.0).min(127.0);
+            ((out - OUTPUT.1 as f64) * OUTPUT.0 as f64) as f32
+        });
+        let expected = fc.into_shape(vec![3, 2]).unwrap();
+        // the weight scales are far apart: a single one for all the outputs
+        // would be off by several steps
+        let compare = |found: Arc<Tensor>| {
+            let found = found.to_array_view::<f32>().unwrap();
+            assert_eq!(found.shape(), expected.shape());
+            for (f, e) in found.iter().zip(expected.iter()) {
+                assert!((f - e).abs() <= OUTPUT.0 * 1.001, "{} for {}", f, e);
+            }
+        };
+        let plan = SimplePlan::new(&model).unwrap();
+        compare(plan.run(tvec!(input.clone().into())).unwrap().remove(0));
+        let optimized = model.into_optimized().unwrap();
+        let plan = SimplePlan::new(&optimized).unwrap();
+        compare(plan.run(tvec!(input.into())).unwrap().remove(0));
+    }
+
+    #[test]
+    fn conv2d() {
+        let kernel: Vec<i8> = (0..54).map(|i| ((i * 5) % 11) as i8 - 5).collect();
+        check(false, &kernel, &[0.01, 0.02, 0.015], &[10, -20, 5]);
+    }
+
+    #[test]
+    fn depthwise_conv2d() {
+        // a depth multiplier of 2: 4 output channels
+        let kernel: Vec<i8> = (0..36).map(|i| ((i * 7) % 13) as i8 - 6).collect();
+        check(true, &kernel, &[0.01, 0.02, 0.015, 0.03], &[10, -20, 5, 0]);
+    }
+}
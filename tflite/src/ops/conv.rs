@@ -0,0 +1,206 @@
+use std::borrow::Borrow;
+
+use ndarray::*;
+use tract_core::internal::*;
+use tract_core::ops::cnn::{Conv, KernelFormat, PaddingSpec, QConvI16};
+use tract_core::ops::nn::DataFormat;
+
+use crate::model::OpNode;
+use crate::schema::{BuiltinOptions, Padding};
+
+pub fn conv2d(node: &OpNode) -> TractResult<Box<Op>> {
+    match node.options {
+        BuiltinOptions::Conv2D { padding, strides, dilations, .. } => {
+            QConv2D::build(node, padding, strides, dilations, 1, false)
+        }
+        ref other => bail!("CONV_2D with {:?}", other),
+    }
+}
+
+pub fn depthwise_conv2d(node: &OpNode) -> TractResult<Box<Op>> {
+    match node.options {
+        BuiltinOptions::DepthwiseConv2D { padding, strides, dilations, .. } => {
+            let channels = node.inputs[0].shape.get(3).cloned().unwrap_or(-1);
+            if channels < 1 {
+                bail!("DEPTHWISE_CONV_2D needs a known number of input channels");
+            }
+            QConv2D::build(node, padding, strides, dilations, channels as usize, true)
+        }
+        ref other => bail!("DEPTHWISE_CONV_2D with {:?}", other),
+    }
+}
+
+/// A quantized int8 TFLite convolution, NHWC, its kernel and bias constant.
+///
+/// Kernels are OHWI, or 1HW(I*M) for depthwise ones, int8 and symmetric,
+/// with one scale per output channel. Biases are i32, in units of the input
+/// scale times the kernel one.
+///
+/// It declutters into a `QConvI16` on i8 activations, requantizing in fixed
+/// point like TFLite does.
+#[derive(Debug, Clone, new)]
+pub struct QConv2D {
+    conv: Conv,
+    depthwise: bool,
+    input_scale: f32,
+    input_zero_point: i16,
+    kernel_scales: TVec<f32>,
+    kernel_zero_point: i8,
+    output_scale: f32,
+    output_zero_point: i32,
+}
+
+impl QConv2D {
+    fn build(
+        node: &OpNode,
+        padding: Padding,
+        strides: (usize, usize),
+        dilations: (usize, usize),
+        group: usize,
+        depthwise: bool,
+    ) -> TractResult<Box<Op>> {
+        super::check_i8(&node.inputs[0])?;
+        super::check_i8(&node.outputs[0])?;
+        let (input_scale, input_zero_point) = node.inputs[0].quantization()?;
+        let (kernel_scales, kernel_zero_point) = super::kernel_quantization(&node.inputs[1])?;
+        let (output_scale, output_zero_point) = node.outputs[0].quantization()?;
+        let padding = match padding {
+            Padding::Same => PaddingSpec::SameUpper,
+            Padding::Valid => PaddingSpec::Valid,
+        };
+        let conv = Conv::new(
+            DataFormat::NHWC,
+            KernelFormat::HWIO,
+            Some(tvec!(dilations.0, dilations.1)),
+            None,
+            padding,
+            Some(tvec!(strides.0, strides.1)),
+            group,
+        );
+        Ok(Box::new(QConv2D::new(
+            conv,
+            depthwise,
+            input_scale,
+            input_zero_point as i16,
+            kernel_scales,
+            kernel_zero_point,
+            output_scale,
+            output_zero_point as i32,
+        )))
+    }
+
+    /// The kernel as HWIO, of `channels` input channels.
+    ///
+    /// Grouped HWIO kernels store all the input channels and the output
+    /// channels of one group: a depthwise kernel is HWIM, M its depth
+    /// multiplier.
+    fn hwio_kernel(&self, kernel: &Tensor, channels: usize) -> TractResult<ArrayD<i8>> {
+        let kernel = kernel.to_array_view::<i8>()?;
+        if kernel.ndim() != 4 {
+            bail!("Expected a rank 4 kernel, got {:?}", kernel.shape());
+        }
+        let axes = if self.depthwise { [1, 2, 0, 3] } else { [1, 2, 3, 0] };
+        let hwio = kernel.permuted_axes(&axes[..]);
+        let mut shape = hwio.shape().to_vec();
+        if self.depthwise {
+            if shape[3] % channels != 0 {
+                bail!("Depthwise kernel of {} channels for {} input channels", shape[3], channels);
+            }
+            shape[2] = channels;
+            shape[3] /= channels;
+        }
+        Ok(ArrayD::from_shape_vec(shape, hwio.iter().cloned().collect())?)
+    }
+
+    fn to_qconv(&self, inputs: &[impl Borrow<TypedTensorInfo>]) -> TractResult<Option<QConvI16>> {
+        let (kernel, bias) = match (&inputs[1].borrow().konst, inputs.get(2)) {
+            (Some(kernel), None) => (kernel, None),
+            (Some(kernel), Some(bias)) => match &bias.borrow().konst {
+                Some(bias) => (kernel, Some(bias.as_ref().clone())),
+                None => return Ok(None),
+            },
+            (None, _) => return Ok(None),
+        };
+        let channels = inputs[0].borrow().shape.dim(3).to_integer()? as usize;
+        let kernel = self.hwio_kernel(kernel, channels)?;
+        let facts = [
+            inputs[0].borrow().clone(),
+            TypedTensorInfo::from(kernel.mapv(|w| w as f32).into_arc_tensor()),
+        ];
+        let mut unary = match self.conv.to_unary(&facts)? {
+            Some(unary) => unary,
+            None => return Ok(None),
+        };
+        let k = kernel.len() / kernel.shape()[3] / if self.depthwise { channels } else { 1 };
+        unary.kernel = kernel.into();
+        // see mat_mul_i16_i8_i32
+        let accumulator = if k <= 512 { DatumType::I32 } else { DatumType::I64 };
+        let op = QConvI16::new(
+            unary,
+            bias,
+            self.input_scale,
+            self.kernel_scales.clone(),
+            self.output_scale,
+            accumulator,
+        )
+        .with_zero_points(self.input_zero_point, self.kernel_zero_point)?
+        .with_datum_types(DatumType::I8, DatumType::I8, self.output_zero_point)?
+        .with_fixed_point()?;
+        Ok(Some(op))
+    }
+}
+
+impl Op for QConv2D {
+    fn name(&self) -> Cow<str> {
+        "tflite.QConv2D".into()
+    }
+
+    fn declutter(
+        &self,
+        model: &TypedModel,
+        node: &TypedNode,
+    ) -> TractResult<Option<TypedModelPatch>> {
+        let inputs = model.node_input_facts(node.id)?;
+        if let Some(op) = self.to_qconv(&*inputs)? {
+            let patch = TypedModelPatch::single_unary_op(model, node, op)?;
+            return Ok(Some(patch.with_label("constant kernel and bias made unary")));
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl StatelessOp for QConv2D {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let inputs_info: TVec<TypedTensorInfo> =
+            inputs.iter().map(|t| TypedTensorInfo::from(&**t)).collect();
+        let op = self.to_qconv(&*inputs_info)?.unwrap();
+        op.eval(tvec!(inputs.remove(0)))
+    }
+}
+
+impl InferenceRulesOp for QConv2D {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        if inputs.len() < 2 || inputs.len() > 3 {
+            bail!("Wrong number of inputs. Expected 2 or 3, got {}", inputs.len());
+        }
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::I8)?;
+        s.equals(&inputs[1].datum_type, DatumType::I8)?;
+        s.equals(&outputs[0].datum_type, DatumType::I8)?;
+        if let Some(bias) = inputs.get(2) {
+            s.equals(&bias.datum_type, DatumType::I32)?;
+            s.equals(&bias.rank, 1)?;
+        }
+        s.equals(&inputs[0].rank, 4)?;
+        s.equals(&inputs[1].rank, 4)?;
+        s.equals(&outputs[0].rank, 4)?;
+        s.equals(&inputs[0].shape[0], &outputs[0].shape[0])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use tract_core::internal::*;
+use tract_core::ops::math::{QGemm, Requantize};
+
+use crate::model::{OpNode, TfliteOpRegister};
+use crate::schema::{Activation, BuiltinOptions, TensorProto};
+
+pub mod conv;
+pub mod quant;
+
+pub fn register_all_ops(reg: &mut TfliteOpRegister) {
+    reg.insert("CONV_2D", conv::conv2d);
+    reg.insert("DEPTHWISE_CONV_2D", conv::depthwise_conv2d);
+    reg.insert("DEQUANTIZE", quant::dequantize);
+    reg.insert("FULLY_CONNECTED", fully_connected);
+    reg.insert("QUANTIZE", quant::quantize);
+    reg.insert("RESHAPE", reshape);
+}
+
+/// Checks the tensor holds int8 values: uint8 models, from the older
+/// quantization scheme, are not supported.
+fn check_i8(tensor: &TensorProto) -> TractResult<()> {
+    if tensor.datum_type != DatumType::I8 {
+        bail!("Expected an I8 tensor, {} is {:?}", tensor.name, tensor.datum_type);
+    }
+    Ok(())
+}
+
+/// Scales of a symmetric int8 kernel, one for the whole kernel or one per
+/// output channel, and its zero point.
+fn kernel_quantization(kernel: &TensorProto) -> TractResult<(TVec<f32>, i8)> {
+    check_i8(kernel)?;
+    let q = kernel
+        .quantization
+        .as_ref()
+        .ok_or_else(|| format!("Kernel {} is not quantized", kernel.name))?;
+    let zero_point = q.zero_point.get(0).cloned().unwrap_or(0);
+    if q.zero_point.iter().any(|&zp| zp != zero_point) {
+        bail!("Kernel {} has different zero points per channel", kernel.name);
+    }
+    Ok((q.scale.iter().cloned().collect(), zero_point as i8))
+}
+
+/// A clamp for the activation fused in a quantized operator, to apply on
+/// its first output.
+pub fn fused_activation(node: &OpNode) -> TractResult<Option<Box<Op>>> {
+    let activation = node.options.activation();
+    if activation == Activation::None {
+        return Ok(None);
+    }
+    let output = &node.outputs[0];
+    check_i8(output)?;
+    let (scale, zero_point) = output.quantization()?;
+    let q = |x: f32| (zero_point as f32 + (x / scale).round()).max(-128.0).min(127.0) as i8;
+    let (min, max) = match activation {
+        Activation::Relu => (q(0.0), std::i8::MAX),
+        Activation::Relu6 => (q(0.0), q(6.0)),
+        Activation::ReluN1To1 => (q(-1.0), q(1.0)),
+        other => bail!("Fused activation {:?} not supported", other),
+    };
+    Ok(Some(Box::new(quant::QClamp::new(min, max))))
+}
+
+fn fully_connected(node: &OpNode) -> TractResult<Box<Op>> {
+    let (input, weights, output) = (&node.inputs[0], &node.inputs[1], &node.outputs[0]);
+    check_i8(input)?;
+    check_i8(output)?;
+    if input.shape.len() != 2 {
+        bail!("FULLY_CONNECTED on a rank {} input not supported, expected 2", input.shape.len());
+    }
+    if let BuiltinOptions::FullyConnected { keep_num_dims: true, .. } = node.options {
+        bail!("FULLY_CONNECTED keeping its input dimensions not supported");
+    }
+    let (input_scale, input_zero_point) = input.quantization()?;
+    let (output_scale, output_zero_point) = output.quantization()?;
+    let (weight_scales, weight_zero_point) = kernel_quantization(weights)?;
+    // weights are [output, input]: one scale per output column
+    let scales = weight_scales.iter().map(|s| input_scale * s / output_scale).collect();
+    Ok(Box::new(
+        QGemm::new(false, true, node.inputs.len() > 2)
+            .with_zero_points(input_zero_point as i8, weight_zero_point)
+            .with_requantize(Requantize::new(scales, output_zero_point as i8)),
+    ))
+}
+
+fn reshape(node: &OpNode) -> TractResult<Box<Op>> {
+    if node.inputs.len() != 2 {
+        bail!("RESHAPE without a shape input not supported");
+    }
+    Ok(Box::new(tract_core::ops::array::Reshape::new()))
+}
@@ -0,0 +1,147 @@
+use tract_core::internal::*;
+
+use crate::model::OpNode;
+
+pub fn quantize(node: &OpNode) -> TractResult<Box<Op>> {
+    if node.inputs[0].datum_type != DatumType::F32 {
+        bail!("QUANTIZE of {:?} not supported, only F32", node.inputs[0].datum_type);
+    }
+    super::check_i8(&node.outputs[0])?;
+    let (scale, zero_point) = node.outputs[0].quantization()?;
+    Ok(Box::new(Quantize::new(scale, zero_point as i8)))
+}
+
+pub fn dequantize(node: &OpNode) -> TractResult<Box<Op>> {
+    super::check_i8(&node.inputs[0])?;
+    let (scale, zero_point) = node.inputs[0].quantization()?;
+    Ok(Box::new(Dequantize::new(scale, zero_point as i8)))
+}
+
+/// Quantizes f32 to i8, rounding half away from zero and saturating.
+#[derive(Clone, Debug, new)]
+pub struct Quantize {
+    scale: f32,
+    zero_point: i8,
+}
+
+impl Op for Quantize {
+    fn name(&self) -> Cow<str> {
+        "tflite.Quantize".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("scale: {}, zero_point: {}", self.scale, self.zero_point)))
+    }
+}
+
+impl StatelessOp for Quantize {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let zero_point = self.zero_point as f32;
+        let output = input
+            .to_array_view::<f32>()?
+            .mapv(|x| ((x / self.scale).round() + zero_point).max(-128.0).min(127.0) as i8);
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Quantize {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::F32)?;
+        s.equals(&outputs[0].datum_type, DatumType::I8)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+/// Dequantizes i8 to f32.
+#[derive(Clone, Debug, new)]
+pub struct Dequantize {
+    scale: f32,
+    zero_point: i8,
+}
+
+impl Op for Dequantize {
+    fn name(&self) -> Cow<str> {
+        "tflite.Dequantize".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("scale: {}, zero_point: {}", self.scale, self.zero_point)))
+    }
+}
+
+impl StatelessOp for Dequantize {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let zero_point = self.zero_point as f32;
+        let output = input.to_array_view::<i8>()?.mapv(|x| (x as f32 - zero_point) * self.scale);
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for Dequantize {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::I8)?;
+        s.equals(&outputs[0].datum_type, DatumType::F32)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
+
+/// Clamps i8 values, for the activations TFLite fuses in quantized
+/// operators, their bounds quantized with the output.
+#[derive(Clone, Debug, new)]
+pub struct QClamp {
+    min: i8,
+    max: i8,
+}
+
+impl Op for QClamp {
+    fn name(&self) -> Cow<str> {
+        "tflite.QClamp".into()
+    }
+
+    fn info(&self) -> TractResult<Option<String>> {
+        Ok(Some(format!("min: {}, max: {}", self.min, self.max)))
+    }
+}
+
+impl StatelessOp for QClamp {
+    fn eval(&self, mut inputs: TVec<Arc<Tensor>>) -> TractResult<TVec<Arc<Tensor>>> {
+        let input = args_1!(inputs);
+        let mut output = input.into_tensor().into_array::<i8>()?;
+        output.mapv_inplace(|x| x.max(self.min).min(self.max));
+        Ok(tvec!(output.into_arc_tensor()))
+    }
+}
+
+impl InferenceRulesOp for QClamp {
+    fn rules<'r, 'p: 'r, 's: 'r>(
+        &'s self,
+        s: &mut Solver<'r>,
+        inputs: &'p [TensorProxy],
+        outputs: &'p [TensorProxy],
+    ) -> InferenceResult {
+        check_input_arity(&inputs, 1)?;
+        check_output_arity(&outputs, 1)?;
+        s.equals(&inputs[0].datum_type, DatumType::I8)?;
+        s.equals(&outputs[0].datum_type, DatumType::I8)?;
+        s.equals(&inputs[0].shape, &outputs[0].shape)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+//! The parts of the TFLite schema (`schema.fbs`, version 3) tract reads,
+//! decoded from the flatbuffer into plain structs.
+
+use tract_core::internal::*;
+
+use crate::flat::Table;
+
+/// A TFLite model: its operator codes, subgraphs and constant buffers.
+#[derive(Clone, Debug)]
+pub struct ModelProto {
+    pub version: u32,
+    /// Name of the operator of each code: the builtin name, like
+    /// "CONV_2D", or the custom code.
+    pub operator_codes: Vec<String>,
+    pub subgraphs: Vec<SubGraphProto>,
+    pub buffers: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubGraphProto {
+    pub name: String,
+    pub tensors: Vec<TensorProto>,
+    pub inputs: Vec<i32>,
+    pub outputs: Vec<i32>,
+    pub operators: Vec<OperatorProto>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TensorProto {
+    pub name: String,
+    pub shape: Vec<i32>,
+    pub datum_type: DatumType,
+    /// Index of the buffer holding the value of a constant. Buffer 0 is the
+    /// empty one, referred to by variable tensors.
+    pub buffer: u32,
+    pub quantization: Option<QuantizationProto>,
+}
+
+/// Affine quantization: real = scale * (quantized - zero_point), one scale
+/// and zero point for the whole tensor or one per index of
+/// `quantized_dimension`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantizationProto {
+    pub scale: Vec<f32>,
+    pub zero_point: Vec<i64>,
+    pub quantized_dimension: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct OperatorProto {
+    pub opcode_index: u32,
+    /// Indices of the tensors, -1 for an optional input left out.
+    pub inputs: Vec<i32>,
+    pub outputs: Vec<i32>,
+    pub options: BuiltinOptions,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Padding {
+    Same,
+    Valid,
+}
+
+/// Activation applied to the output of an operator.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Activation {
+    None,
+    Relu,
+    ReluN1To1,
+    Relu6,
+    Tanh,
+    SignBit,
+}
+
+/// Options of the builtin operators tract reads. Others are kept as the
+/// type of their union member.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuiltinOptions {
+    None,
+    Conv2D {
+        padding: Padding,
+        strides: (usize, usize),
+        activation: Activation,
+        dilations: (usize, usize),
+    },
+    DepthwiseConv2D {
+        padding: Padding,
+        strides: (usize, usize),
+        depth_multiplier: usize,
+        activation: Activation,
+        dilations: (usize, usize),
+    },
+    FullyConnected {
+        activation: Activation,
+        keep_num_dims: bool,
+    },
+    Reshape {
+        new_shape: Vec<i32>,
+    },
+    Other(u8),
+}
+
+impl BuiltinOptions {
+    /// The activation fused in the operator.
+    pub fn activation(&self) -> Activation {
+        match self {
+            BuiltinOptions::Conv2D { activation, .. }
+            | BuiltinOptions::DepthwiseConv2D { activation, .. }
+            | BuiltinOptions::FullyConnected { activation, .. } => *activation,
+            _ => Activation::None,
+        }
+    }
+}
+
+/// Names of the builtin operators, by code.
+const BUILTIN_OPERATORS: &[&str] = &[
+    "ADD",
+    "AVERAGE_POOL_2D",
+    "CONCATENATION",
+    "CONV_2D",
+    "DEPTHWISE_CONV_2D",
+    "DEPTH_TO_SPACE",
+    "DEQUANTIZE",
+    "EMBEDDING_LOOKUP",
+    "FLOOR",
+    "FULLY_CONNECTED",
+    "HASHTABLE_LOOKUP",
+    "L2_NORMALIZATION",
+    "L2_POOL_2D",
+    "LOCAL_RESPONSE_NORMALIZATION",
+    "LOGISTIC",
+    "LSH_PROJECTION",
+    "LSTM",
+    "MAX_POOL_2D",
+    "MUL",
+    "RELU",
+    "RELU_N1_TO_1",
+    "RELU6",
+    "RESHAPE",
+    "RESIZE_BILINEAR",
+    "RNN",
+    "SOFTMAX",
+    "SPACE_TO_DEPTH",
+    "SVDF",
+    "TANH",
+];
+
+/// Code of the QUANTIZE builtin, past the contiguous ones above.
+const QUANTIZE: i32 = 114;
+
+fn builtin_name(code: i32) -> String {
+    match code {
+        QUANTIZE => "QUANTIZE".to_string(),
+        code if code >= 0 && (code as usize) < BUILTIN_OPERATORS.len() => {
+            BUILTIN_OPERATORS[code as usize].to_string()
+        }
+        code => format!("BUILTIN_{}", code),
+    }
+}
+
+fn datum_type(code: i8) -> TractResult<DatumType> {
+    Ok(match code {
+        0 => DatumType::F32,
+        1 => DatumType::F16,
+        2 => DatumType::I32,
+        3 => DatumType::U8,
+        4 => DatumType::I64,
+        5 => DatumType::String,
+        6 => DatumType::Bool,
+        7 => DatumType::I16,
+        9 => DatumType::I8,
+        10 => DatumType::F64,
+        code => bail!("Unsupported TFLite tensor type {}", code),
+    })
+}
+
+fn padding(code: i8) -> TractResult<Padding> {
+    match code {
+        0 => Ok(Padding::Same),
+        1 => Ok(Padding::Valid),
+        code => bail!("Unknown TFLite padding {}", code),
+    }
+}
+
+fn activation(code: i8) -> TractResult<Activation> {
+    Ok(match code {
+        0 => Activation::None,
+        1 => Activation::Relu,
+        2 => Activation::ReluN1To1,
+        3 => Activation::Relu6,
+        4 => Activation::Tanh,
+        5 => Activation::SignBit,
+        code => bail!("Unknown TFLite activation {}", code),
+    })
+}
+
+/// Strides, dilations... as (height, width), from their fields.
+fn hw(table: &Table, h: usize, w: usize, default: i32) -> TractResult<(usize, usize)> {
+    let (h, w) = (table.scalar(h, default)?, table.scalar(w, default)?);
+    if h < 1 || w < 1 {
+        bail!("Expected positive TFLite strides and dilations, got {}x{}", h, w);
+    }
+    Ok((h as usize, w as usize))
+}
+
+impl ModelProto {
+    pub fn decode(buf: &[u8]) -> TractResult<ModelProto> {
+        let model = Table::root(buf)?;
+        let version = model.scalar::<u32>(0, 0)?;
+        if version != 3 {
+            bail!("Unsupported TFLite schema version {}, expected 3", version);
+        }
+        let operator_codes = model
+            .tables(1)?
+            .iter()
+            .map(|code| {
+                // the byte code saturates at 127, the int one came later
+                let builtin = (code.scalar::<i8>(0, 0)? as i32).max(code.scalar::<i32>(3, 0)?);
+                match code.string(1)? {
+                    Some(custom) if builtin == 32 => Ok(custom.to_string()),
+                    _ => Ok(builtin_name(builtin)),
+                }
+            })
+            .collect::<TractResult<_>>()?;
+        let subgraphs =
+            model.tables(2)?.iter().map(SubGraphProto::decode).collect::<TractResult<_>>()?;
+        let buffers = model
+            .tables(4)?
+            .iter()
+            .map(|b| Ok(b.bytes(0)?.to_vec()))
+            .collect::<TractResult<_>>()?;
+        Ok(ModelProto { version, operator_codes, subgraphs, buffers })
+    }
+}
+
+impl SubGraphProto {
+    fn decode(table: &Table) -> TractResult<SubGraphProto> {
+        Ok(SubGraphProto {
+            tensors: table
+                .tables(0)?
+                .iter()
+                .map(TensorProto::decode)
+                .collect::<TractResult<_>>()?,
+            inputs: table.vector(1)?,
+            outputs: table.vector(2)?,
+            operators: table
+                .tables(3)?
+                .iter()
+                .map(OperatorProto::decode)
+                .collect::<TractResult<_>>()?,
+            name: table.string(4)?.unwrap_or("").to_string(),
+        })
+    }
+}
+
+impl TensorProto {
+    fn decode(table: &Table) -> TractResult<TensorProto> {
+        let quantization = match table.table(4)? {
+            Some(q) => {
+                let scale: Vec<f32> = q.vector(2)?;
+                let zero_point: Vec<i64> = q.vector(3)?;
+                if scale.is_empty() {
+                    None
+                } else {
+                    let quantized_dimension = q.scalar::<i32>(6, 0)?.max(0) as usize;
+                    Some(QuantizationProto { scale, zero_point, quantized_dimension })
+                }
+            }
+            None => None,
+        };
+        Ok(TensorProto {
+            shape: table.vector(0)?,
+            datum_type: datum_type(table.scalar(1, 0)?)?,
+            buffer: table.scalar(2, 0)?,
+            name: table.string(3)?.unwrap_or("").to_string(),
+            quantization,
+        })
+    }
+
+    /// Shape of the tensor, dynamic dimensions (-1) included.
+    pub fn shape_fact(&self) -> ShapeFact {
+        let dims = self.shape.iter().map(|&d| {
+            if d < 0 {
+                GenericFact::Any
+            } else {
+                GenericFact::Only((d as usize).to_dim())
+            }
+        });
+        ShapeFact::closed(dims.collect())
+    }
+
+    /// The single (scale, zero point) of a tensor quantized as a whole.
+    pub fn quantization(&self) -> TractResult<(f32, i64)> {
+        match &self.quantization {
+            Some(q) if q.scale.len() == 1 => {
+                Ok((q.scale[0], q.zero_point.get(0).cloned().unwrap_or(0)))
+            }
+            Some(q) => bail!("Tensor {} quantized per axis, {} scales", self.name, q.scale.len()),
+            None => bail!("Tensor {} is not quantized", self.name),
+        }
+    }
+}
+
+impl OperatorProto {
+    fn decode(table: &Table) -> TractResult<OperatorProto> {
+        let options_type = table.scalar::<u8>(3, 0)?;
+        let options = match table.table(4)? {
+            None => BuiltinOptions::None,
+            Some(o) => match options_type {
+                1 => BuiltinOptions::Conv2D {
+                    padding: padding(o.scalar(0, 0)?)?,
+                    strides: hw(&o, 2, 1, 1)?,
+                    activation: activation(o.scalar(3, 0)?)?,
+                    dilations: hw(&o, 5, 4, 1)?,
+                },
+                2 => BuiltinOptions::DepthwiseConv2D {
+                    padding: padding(o.scalar(0, 0)?)?,
+                    strides: hw(&o, 2, 1, 1)?,
+                    depth_multiplier: o.scalar::<i32>(3, 1)?.max(1) as usize,
+                    activation: activation(o.scalar(4, 0)?)?,
+                    dilations: hw(&o, 6, 5, 1)?,
+                },
+                8 => BuiltinOptions::FullyConnected {
+                    activation: activation(o.scalar(0, 0)?)?,
+                    keep_num_dims: o.scalar::<u8>(2, 0)? != 0,
+                },
+                17 => BuiltinOptions::Reshape { new_shape: o.vector(0)? },
+                other => BuiltinOptions::Other(other),
+            },
+        };
+        Ok(OperatorProto {
+            opcode_index: table.scalar(0, 0)?,
+            inputs: table.vector(1)?,
+            outputs: table.vector(2)?,
+            options,
+        })
+    }
+}